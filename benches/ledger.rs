@@ -0,0 +1,44 @@
+//! Hot path benchmarks of [`Accounts`] operations on a 1M account ledger.
+//!
+//! Run with `cargo bench --bench ledger`; use `-- --save-baseline <name>` and
+//! `-- --baseline <name>` to compare two revisions.
+
+use crabbux::{accounts::Accounts, tx::Units};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+
+const ACCOUNTS: usize = 1_000_000;
+
+fn ledger(names: &[String]) -> Accounts {
+    let mut ledger = Accounts::with_capacity(names.len());
+    for name in names {
+        ledger.deposit(name, 1_000_000_000).unwrap();
+    }
+    ledger
+}
+
+fn operations(c: &mut Criterion) {
+    let names: Vec<String> = (0..ACCOUNTS).map(|i| format!("ACCOUNT{}", i)).collect();
+    let mut ledger = ledger(&names);
+    let mut i = 0;
+    let mut next = move || {
+        i = (i + 7919) % ACCOUNTS;
+        i
+    };
+
+    c.bench_function("deposit", |b| {
+        b.iter(|| ledger.deposit(black_box(&names[next()]), 1))
+    });
+    c.bench_function("withdraw", |b| {
+        b.iter(|| ledger.withdraw(black_box(&names[next()]), 1))
+    });
+    c.bench_function("send", |b| {
+        b.iter(|| ledger.send(black_box(&names[next()]), black_box(&names[next()]), 1))
+    });
+    c.bench_function("withdraw_underfunded", |b| {
+        b.iter(|| ledger.withdraw(black_box(&names[next()]), Units::MAX))
+    });
+}
+
+criterion_group!(benches, operations);
+criterion_main!(benches);
@@ -0,0 +1,84 @@
+//! Compares transfer throughput of [`SharedAccounts`] (one lock) and [`ShardedAccounts`],
+//! whose shards are whole ledgers too, so both keep the same stats and run the same checks.
+//! A single shard shows what the sharding itself costs: most sends cross shards, and a send
+//! between shards checks both sides before committing them.
+//!
+//! Run with `cargo bench --bench sharded`. The shards only pay off with several cores;
+//! on a single core both variants perform about the same.
+
+use crabbux::{accounts::Accounts, sharded::ShardedAccounts, shared::SharedAccounts};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const ACCOUNTS: usize = 10_000;
+const THREADS: usize = 8;
+const SENDS_PER_THREAD: usize = 200_000;
+
+fn run(send: impl Fn(&str, &str) + Send + Sync + 'static, names: Arc<Vec<String>>) -> Duration {
+    let send = Arc::new(send);
+    let start = Instant::now();
+    let workers: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let (send, names) = (send.clone(), names.clone());
+            thread::spawn(move || {
+                for i in 0..SENDS_PER_THREAD {
+                    let sender = &names[(i * 31 + t) % names.len()];
+                    let recipient = &names[(i * 17 + t + 1) % names.len()];
+                    send(sender, recipient);
+                }
+            })
+        })
+        .collect();
+    workers.into_iter().for_each(|w| w.join().unwrap());
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let sends = (THREADS * SENDS_PER_THREAD) as f64;
+    println!(
+        "{:<16} {:>8.0?} {:>12.0} sends/s",
+        name,
+        elapsed,
+        sends / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    let names: Arc<Vec<String>> =
+        Arc::new((0..ACCOUNTS).map(|i| format!("ACCOUNT{}", i)).collect());
+    println!(
+        "{} threads, {} sends each, {} accounts",
+        THREADS, SENDS_PER_THREAD, ACCOUNTS
+    );
+
+    let shared = SharedAccounts::new(Accounts::new());
+    names
+        .iter()
+        .for_each(|a| drop(shared.deposit(a, 1_000_000)));
+    report(
+        "single lock",
+        run(
+            move |sender, recipient| drop(shared.send(sender, recipient, 1)),
+            names.clone(),
+        ),
+    );
+
+    for shards in [1, 16, 64] {
+        let sharded = ShardedAccounts::new(shards);
+        names
+            .iter()
+            .for_each(|a| drop(sharded.deposit(a, 1_000_000)));
+        let name = match shards {
+            1 => "1 shard".to_string(),
+            shards => format!("{} shards", shards),
+        };
+        report(
+            &name,
+            run(
+                move |sender, recipient| drop(sharded.send(sender, recipient, 1)),
+                names.clone(),
+            ),
+        );
+    }
+}
@@ -0,0 +1,9 @@
+//! `cargo +nightly fuzz run apply_sequence`
+#![no_main]
+
+use crabbux::fuzz::{apply_sequence, Op};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|ops: Vec<Op>| {
+    apply_sequence(&ops);
+});
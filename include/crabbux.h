@@ -0,0 +1,61 @@
+/* C interface of the crabbux ledger, implemented in src/ffi.rs.
+ *
+ * Link against the crabbux cdylib (libcrabbux.so, libcrabbux.dylib or crabbux.dll).
+ * Functions returning int return CRABBUX_OK or one of the error codes below.
+ * A ledger must not be used from two threads at the same time. */
+#ifndef CRABBUX_H
+#define CRABBUX_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define CRABBUX_OK 0
+/* A pointer argument was null or an account name wasn't valid UTF-8 */
+#define CRABBUX_INVALID_ARGUMENT 1
+#define CRABBUX_NOT_FOUND 2
+#define CRABBUX_UNDERFUNDED 3
+#define CRABBUX_OVERFUNDED 4
+#define CRABBUX_STORAGE_ERROR 5
+#define CRABBUX_ACCOUNT_EXISTS 6
+/* The account is an escrow account */
+#define CRABBUX_LOCKED 7
+/* The transfer is waiting for approval */
+#define CRABBUX_APPROVAL_REQUIRED 8
+#define CRABBUX_UNAUTHORIZED 9
+/* The transfer would exceed a spending limit */
+#define CRABBUX_LIMIT_EXCEEDED 10
+/* The sender may not send to the recipient */
+#define CRABBUX_BLOCKED 11
+/* The send looks suspicious and was blocked */
+#define CRABBUX_SUSPICIOUS 12
+/* The send looks suspicious and must be confirmed */
+#define CRABBUX_CONFIRMATION_REQUIRED 13
+/* The account is archived */
+#define CRABBUX_ARCHIVED 14
+/* The ledger is read-only */
+#define CRABBUX_PERMISSION_DENIED 15
+/* Persisted data doesn't match its checksum */
+#define CRABBUX_CORRUPT_DATA 16
+
+typedef struct crabbux_ledger crabbux_ledger;
+
+/* Creates an empty ledger, to be released with crabbux_ledger_free */
+crabbux_ledger *crabbux_ledger_new(void);
+/* Releases a ledger; NULL is ignored */
+void crabbux_ledger_free(crabbux_ledger *ledger);
+
+int crabbux_deposit(crabbux_ledger *ledger, const char *account, uint64_t amount);
+int crabbux_withdraw(crabbux_ledger *ledger, const char *account, uint64_t amount);
+int crabbux_send(crabbux_ledger *ledger, const char *sender, const char *recipient,
+                 uint64_t amount);
+/* Writes the balance of account to *balance */
+int crabbux_balance(const crabbux_ledger *ledger, const char *account, uint64_t *balance);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
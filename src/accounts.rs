@@ -1,237 +1,3933 @@
-use crate::{errors::ApplicationError, tx::Tx};
-use std::collections::HashMap;
+use crate::{
+    anomaly::{Action, AnomalyDetector, AnomalyPolicy},
+    clock::{Clock, SystemClock, Timestamp},
+    dispute::{self, Dispute},
+    dryrun::{self, BatchSimulation, DryRun, Op, SimulationResult},
+    errors::ApplicationError,
+    escrow::{self, Escrow},
+    events::{EventBus, LedgerEvent, OwnershipChange, Threshold},
+    fees::FeeSchedules,
+    history::TxLog,
+    i18n::{tr, Key},
+    interest::InterestSchedules,
+    limits::{Allowance, SpendingLimit},
+    metadata::LedgerMetadata,
+    multisig::{MultisigPolicy, PendingTransfer},
+    overlay::Overlay,
+    promo::PromoCredits,
+    rounding::Rounding,
+    stats::LedgerStats,
+    storage::{self, LogEntry},
+    tx::{Tx, Units},
+};
+use hashbrown::HashMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::Infallible;
 use std::fmt;
+use std::io;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use tracing::{instrument, Level};
 
 impl fmt::Display for ApplicationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            ApplicationError::NotFound(account) => tr(Key::NotFound, &[account]),
+            ApplicationError::UnderFunded(account, amount) => {
+                tr(Key::UnderFunded, &[account, amount])
+            }
+            ApplicationError::OverFunded(account, amount) => {
+                tr(Key::OverFunded, &[account, amount])
+            }
+            ApplicationError::Storage(message) => tr(Key::Storage, &[message]),
+            ApplicationError::AlreadyExists(account) => tr(Key::AlreadyExists, &[account]),
+            ApplicationError::Locked(account) => tr(Key::Locked, &[account]),
+            ApplicationError::ApprovalRequired(id) => tr(Key::ApprovalRequired, &[id]),
+            ApplicationError::Unauthorized(signer) => tr(Key::Unauthorized, &[signer]),
+            ApplicationError::LimitExceeded(recipient, remaining) => {
+                tr(Key::LimitExceeded, &[recipient, remaining])
+            }
+            ApplicationError::Blocked(recipient) => tr(Key::Blocked, &[recipient]),
+            ApplicationError::Suspicious(anomaly) => tr(Key::Suspicious, &[anomaly]),
+            ApplicationError::ConfirmationRequired(anomaly) => {
+                tr(Key::ConfirmationRequired, &[anomaly])
+            }
+            ApplicationError::Archived(account) => tr(Key::Archived, &[account]),
+            ApplicationError::PermissionDenied(operation) => {
+                tr(Key::PermissionDenied, &[operation])
+            }
+            ApplicationError::CorruptData(file) => tr(Key::CorruptData, &[file]),
+        };
+        f.write_str(&message)
+    }
+}
+
+impl std::error::Error for ApplicationError {}
+
+/// A read-only ledger, e.g. a past state from [`Accounts::state_at`].
+///
+/// It dereferences to [`Accounts`] for queries, but can't be changed.
+#[derive(Debug)]
+pub struct LedgerView(Accounts);
+
+impl Deref for LedgerView {
+    type Target = Accounts;
+
+    fn deref(&self) -> &Accounts {
+        &self.0
+    }
+}
+
+/// What [`Accounts::merge`] does with an account present in both ledgers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Adds the other ledger's balance to this one's
+    Sum,
+    /// Fails the merge with [`ApplicationError::AlreadyExists`]
+    Error,
+    /// Keeps this ledger's balance
+    PreferLeft,
+}
+
+/// Balance limits of an account, see [`Accounts::set_thresholds`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Thresholds {
+    /// Balances below this are too low
+    pub low: Option<Units>,
+    /// Balances above this are too high
+    pub high: Option<Units>,
+}
+
+/// Credit extended to an account, see [`Accounts::set_credit_limit`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CreditLine {
+    /// How far the account may be overdrawn
+    pub limit: Units,
+    /// How far it is overdrawn at the moment
+    pub drawn: Units,
+}
+
+/// An internal inconsistency found by [`Accounts::check_invariants`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvariantViolation {
+    /// The balances minus the drawn credit don't add up to the deposits minus the withdrawals
+    Supply {
+        balances: u128,
+        supply: u128,
+    },
+    Conservation(ConservationViolation),
+}
+
+impl fmt::Display for InvariantViolation {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            ApplicationError::NotFound(account) => write!(f, "Account {} not found", account),
-            ApplicationError::UnderFunded(account, amount) => write!(
-                f,
-                "Account {} is underfunded; required amount is {}",
-                account, amount
-            ),
-            ApplicationError::OverFunded(account, amount) => write!(
+            InvariantViolation::Supply { balances, supply } => write!(
                 f,
-                "Account {} is overfunded; maximum allowed amount is {}",
-                account, amount
+                "balances add up to {} but the supply is {}",
+                balances, supply
             ),
+            InvariantViolation::Conservation(violation) => violation.fmt(f),
+        }
+    }
+}
+
+/// Money appeared or vanished other than through a deposit, withdrawal, mint or burn, found by
+/// [`Accounts::check_conservation`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConservationViolation {
+    /// [`Accounts::supply`]
+    pub supply: u128,
+    /// Everything minted and deposited minus everything burned and withdrawn, and sent but not
+    /// received yet, as the [`Accounts::stats`] count it
+    pub issued: u128,
+}
+
+impl fmt::Display for ConservationViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "the supply is {} but {} was issued",
+            self.supply, self.issued
+        )
+    }
+}
+
+/// A type for managing accounts and their current currency balance.
+///
+/// Each account name is allocated once and shared with every [`Tx`] created for it.
+///
+/// Balances are never negative. An account with a [`CreditLine`] can be overdrawn instead: the
+/// part of a withdrawal beyond its balance is drawn from the credit line, and deposits pay back
+/// drawn credit before adding to the balance.
+#[derive(Debug)]
+pub struct Accounts {
+    accounts: Overlay<Arc<str>, Units>,
+    events: EventBus,
+    /// Everything deposited minus everything withdrawn, which the balances must add up to.
+    /// Updated with wrapping arithmetic, so a corrupted supply is reported by
+    /// [`Accounts::check_invariants`] rather than panicking in the middle of an operation.
+    supply: u128,
+    stats: LedgerStats,
+    thresholds: Overlay<String, Thresholds>,
+    credit_lines: Overlay<String, CreditLine>,
+    /// Every escrow ever opened, including settled ones, by id
+    escrows: Arc<BTreeMap<u64, Escrow>>,
+    /// Every dispute ever opened, including resolved ones, by the position of the disputed entry
+    disputes: Arc<BTreeMap<usize, Dispute>>,
+    multisig: Overlay<String, MultisigPolicy>,
+    /// Transfers waiting for approval by id, see [`Accounts::approve`]
+    pending: Arc<BTreeMap<u64, PendingTransfer>>,
+    next_pending_id: u64,
+    /// The allowances of each sender by recipient, see [`Accounts::set_spending_limit`]
+    spending_limits: Overlay<String, HashMap<String, Allowance>>,
+    /// Tells which window of the spending limits a transfer falls into
+    clock: Arc<dyn Clock>,
+    metadata: Arc<LedgerMetadata>,
+    anomalies: AnomalyDetector,
+    /// Balances of the accounts taken out of use, see [`Accounts::archive`]
+    archived: Overlay<Arc<str>, Units>,
+    /// Who operations are performed as, see [`Accounts::set_principal`]
+    principal: Option<String>,
+    /// Who may mint and burn, see [`Accounts::set_admins`]
+    admins: Arc<BTreeSet<String>>,
+    /// See [`Accounts::set_read_only`]
+    read_only: bool,
+    /// How derived amounts are rounded, see [`Accounts::set_rounding`]
+    rounding: Rounding,
+    /// What sends are charged, see [`Accounts::set_fees`]
+    fees: FeeSchedules,
+    /// The interest each kind of account earns, see [`Accounts::set_interest`]
+    interest: InterestSchedules,
+    /// The kind of each account that has one, see [`Accounts::set_account_kind`]
+    account_kinds: Arc<BTreeMap<String, String>>,
+    /// See [`Accounts::grant_promo`]
+    promos: Overlay<String, PromoCredits>,
+    /// The allowances of each payer by payee, see [`Accounts::grant_mandate`]
+    mandates: Overlay<String, HashMap<String, Allowance>>,
+    /// The transfer [`Accounts::apply`] is replaying, see [`ReplayedSend`]
+    replayed_send: Option<ReplayedSend>,
+}
+
+/// A transfer whose withdrawal side [`Accounts::apply`] replayed last, received by the deposit
+/// replayed right after it. Once it is, the transfer is counted against the allowances of its
+/// sender, and remembered for its anomaly policy, as soon as [`Accounts::backdate`] knows when
+/// it happened.
+#[derive(Debug, Clone)]
+struct ReplayedSend {
+    sender: Arc<str>,
+    recipient: Arc<str>,
+    amount: Units,
+    /// Whether the withdrawal side was a [`Tx::Collect`] under a mandate
+    collected: bool,
+    /// Whether the deposit side was replayed too
+    received: bool,
+}
+
+/// An operation applied to a fork of the ledger but not yet to the ledger, see
+/// [`Accounts::stage`]
+pub(crate) struct Staged {
+    fork: Accounts,
+    published: Vec<LedgerEvent>,
+}
+
+impl Default for Accounts {
+    fn default() -> Self {
+        Accounts::new()
+    }
+}
+
+impl Accounts {
+    /// Returns an empty instance of the [`Accounts`] type
+    pub fn new() -> Self {
+        Accounts {
+            accounts: Default::default(),
+            events: Default::default(),
+            supply: 0,
+            stats: Default::default(),
+            thresholds: Default::default(),
+            credit_lines: Default::default(),
+            escrows: Default::default(),
+            disputes: Default::default(),
+            multisig: Default::default(),
+            pending: Default::default(),
+            next_pending_id: 1,
+            spending_limits: Default::default(),
+            clock: Arc::new(SystemClock),
+            metadata: Default::default(),
+            anomalies: Default::default(),
+            archived: Default::default(),
+            principal: None,
+            admins: Default::default(),
+            read_only: false,
+            rounding: Rounding::default(),
+            fees: FeeSchedules::default(),
+            interest: InterestSchedules::default(),
+            account_kinds: Default::default(),
+            promos: Default::default(),
+            mandates: Default::default(),
+            replayed_send: None,
+        }
+    }
+
+    /// Returns an empty instance with room for at least `capacity` accounts before reallocating
+    pub fn with_capacity(capacity: usize) -> Self {
+        Accounts {
+            accounts: Overlay::with_capacity(capacity),
+            ..Accounts::new()
+        }
+    }
+
+    /// Makes room for at least `additional` more accounts, e.g. before importing a large account set
+    pub fn reserve(&mut self, additional: usize) {
+        self.accounts.reserve(additional);
+    }
+
+    /// The number of accounts that fit without reallocating
+    pub fn capacity(&self) -> usize {
+        self.accounts.capacity()
+    }
+
+    /// Returns the current balance of the `signer` account.
+    /// # Errors
+    /// The account doesn't exist or is archived
+    pub fn balance_of(&self, signer: &str) -> Result<&Units, ApplicationError> {
+        self.accounts
+            .get(signer)
+            .ok_or_else(|| self.missing(signer))
+    }
+
+    /// The error for operating on `signer`, which isn't among the accounts in use
+    fn missing(&self, signer: &str) -> ApplicationError {
+        if self.archived.contains_key(signer) {
+            ApplicationError::Archived(signer.to_string())
+        } else {
+            ApplicationError::NotFound(signer.to_string())
+        }
+    }
+
+    /// The number of accounts, not counting archived ones
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    /// Returns `true` if there are no accounts
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    /// Iterates over all accounts but the archived ones and their balances in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Units)> {
+        self.accounts
+            .iter()
+            .map(|(account, balance)| (&**account, balance))
+    }
+
+    /// The balance of `signer` minus the credit it has drawn, negative if it is overdrawn
+    /// # Errors
+    /// The account doesn't exist
+    pub fn signed_balance_of(&self, signer: &str) -> Result<i128, ApplicationError> {
+        let drawn = self.credit_lines.get(signer).map_or(0, |line| line.drawn);
+        Ok(*self.balance_of(signer)? as i128 - drawn as i128)
+    }
+
+    /// Lets `signer`, which needn't exist yet, be overdrawn by up to `limit`.
+    ///
+    /// Lowering the limit below the credit drawn already only blocks further overdrafts.
+    /// Credit limits aren't part of the tx log, so they must be set before replaying it;
+    /// without them, replaying an overdraft fails with [`ApplicationError::UnderFunded`].
+    pub fn set_credit_limit(&mut self, signer: &str, limit: Units) {
+        self.credit_lines.get_or_insert_default(signer).limit = limit;
+    }
+
+    /// The credit line of `signer`, if it has one
+    pub fn credit_line(&self, signer: &str) -> Option<&CreditLine> {
+        self.credit_lines.get(signer)
+    }
+
+    /// The promotional credit of `signer`, if it has any, see [`Accounts::grant_promo`]
+    pub fn promo_credits(&self, signer: &str) -> Option<&PromoCredits> {
+        self.promos
+            .get(signer)
+            .filter(|credits| !credits.is_empty())
+    }
+
+    /// The total of all balances minus the drawn credit, i.e. everything deposited and minted
+    /// minus everything withdrawn and burned, modulo 2^128 while more credit is drawn than there
+    /// are balances
+    pub fn supply(&self) -> u128 {
+        self.supply
+    }
+
+    /// Per-account and overall totals of everything committed to this ledger
+    pub fn stats(&self) -> &LedgerStats {
+        &self.stats
+    }
+
+    /// Validates the internal consistency of the ledger and returns every violation found.
+    ///
+    /// This walks all accounts, so it is meant for tests, debug assertions and the `check`
+    /// command rather than for every operation.
+    pub fn check_invariants(&self) -> Result<(), Vec<InvariantViolation>> {
+        let mut violations = vec![];
+        let drawn = self
+            .credit_lines
+            .values()
+            .fold(0u128, |sum, line| sum.wrapping_add(line.drawn as u128));
+        let balances = self
+            .accounts
+            .values()
+            .chain(self.archived.values())
+            .fold(0u128, |sum, &b| sum.wrapping_add(b as u128))
+            .wrapping_sub(drawn);
+        if balances != self.supply {
+            violations.push(InvariantViolation::Supply {
+                balances,
+                supply: self.supply,
+            });
+        }
+        if let Err(violation) = self.check_conservation() {
+            violations.push(InvariantViolation::Conservation(violation));
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// Checks that the supply only changed by what was minted, burned, deposited and withdrawn,
+    /// i.e. that no operation, like a transfer, created or destroyed money along the way.
+    ///
+    /// Both sides are kept up to date as transactions commit, so this is cheap enough to run
+    /// after every transaction, which debug builds do, logging violations. Whether the supply
+    /// matches the balances is up to [`Accounts::check_invariants`].
+    pub fn check_conservation(&self) -> Result<(), ConservationViolation> {
+        let totals = self.stats.totals();
+        let issued = totals
+            .deposited
+            .wrapping_add(totals.minted)
+            .wrapping_sub(totals.withdrawn)
+            .wrapping_sub(totals.burned)
+            .wrapping_sub(totals.sent.wrapping_sub(totals.received));
+        match issued == self.supply {
+            true => Ok(()),
+            false => Err(ConservationViolation {
+                supply: self.supply,
+                issued,
+            }),
+        }
+    }
+
+    /// Materializes the state after the first `tx_index` entries of a log, i.e. just before
+    /// entry `tx_index + 1` in the 1-based numbering used by [`crate::storage::replay`] errors.
+    /// The entries are replayed into this ledger, which is empty but follows the rules the log
+    /// was written under, like its credit limits.
+    /// # Errors
+    /// Reading or applying an entry failed, or the log has fewer than `tx_index` entries
+    pub fn state_at(
+        mut self,
+        entries: impl IntoIterator<Item = io::Result<LogEntry>>,
+        tx_index: usize,
+    ) -> io::Result<LedgerView> {
+        let applied = storage::replay(&mut self, entries.into_iter().take(tx_index))?;
+        if applied < tx_index {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("the log has only {} entries", applied),
+            ));
+        }
+        Ok(LedgerView(self))
+    }
+
+    /// A copy of the ledger to try operations on, e.g. for a dry run, see [`crate::dryrun`]:
+    /// the same balances, rules, metadata and principal, but without the listeners, so nothing
+    /// done to the fork is published. The state of the accounts is shared with this ledger
+    /// until either changes it, see [`crate::overlay`], so forking costs as much as what the
+    /// fork changes rather than as much as the ledger.
+    pub fn fork(&self) -> Accounts {
+        Accounts {
+            accounts: self.accounts.clone(),
+            events: EventBus::new(),
+            supply: self.supply,
+            stats: self.stats.clone(),
+            thresholds: self.thresholds.clone(),
+            credit_lines: self.credit_lines.clone(),
+            escrows: self.escrows.clone(),
+            disputes: self.disputes.clone(),
+            multisig: self.multisig.clone(),
+            pending: self.pending.clone(),
+            next_pending_id: self.next_pending_id,
+            spending_limits: self.spending_limits.clone(),
+            clock: self.clock.clone(),
+            metadata: self.metadata.clone(),
+            anomalies: self.anomalies.clone(),
+            archived: self.archived.clone(),
+            principal: self.principal.clone(),
+            admins: self.admins.clone(),
+            read_only: self.read_only,
+            rounding: self.rounding.clone(),
+            fees: self.fees.clone(),
+            interest: self.interest.clone(),
+            account_kinds: self.account_kinds.clone(),
+            promos: self.promos.clone(),
+            mandates: self.mandates.clone(),
+            replayed_send: self.replayed_send.clone(),
+        }
+    }
+
+    /// Projects what `op` would do without changing the ledger: the balances of the accounts
+    /// involved afterwards, what it would commit and trigger, or the error it would fail with.
+    /// The operation goes through every check it would for real, see [`crate::dryrun::run`].
+    pub fn simulate(&self, op: &Op) -> SimulationResult {
+        let balances = |ledger: &Accounts| -> BTreeMap<String, Units> {
+            op.accounts()
+                .into_iter()
+                .filter_map(|account| {
+                    let balance = *ledger.balance_of(account).ok()?;
+                    Some((account.to_string(), balance))
+                })
+                .collect()
+        };
+        match dryrun::run(self, |fork| op.apply(fork).map(|_| balances(fork))) {
+            Ok((balances, dry_run)) => SimulationResult {
+                balances,
+                dry_run,
+                error: None,
+            },
+            Err(error) => SimulationResult {
+                balances: balances(self),
+                dry_run: DryRun::default(),
+                error: Some(error),
+            },
+        }
+    }
+
+    /// Projects what applying `ops` in order would do without changing the ledger, see
+    /// [`Accounts::simulate`]. The operations are simulated up to the first one that would fail,
+    /// so the end state is where the batch would stop.
+    pub fn simulate_batch(&self, ops: &[Op]) -> BatchSimulation {
+        let mut failure = None;
+        let Ok((balances, dry_run)) = dryrun::run(self, |fork| {
+            failure = ops
+                .iter()
+                .enumerate()
+                .find_map(|(i, op)| op.apply(fork).err().map(|error| (i, error)));
+            Ok::<_, Infallible>(
+                fork.iter()
+                    .map(|(account, balance)| (account.to_string(), *balance))
+                    .collect(),
+            )
+        });
+        BatchSimulation {
+            balances,
+            dry_run,
+            failure,
+        }
+    }
+
+    /// Sets the balance limits of `account`, which needn't exist yet. Whenever a transaction
+    /// moves its balance from within a limit to beyond it, a [`LedgerEvent::ThresholdCrossed`]
+    /// is published after the [`LedgerEvent::TxCommitted`].
+    pub fn set_thresholds(&mut self, account: &str, thresholds: Thresholds) {
+        self.thresholds.insert(account.to_string(), thresholds);
+    }
+
+    /// The balance limits of `account`, if any were set
+    pub fn thresholds(&self, account: &str) -> Option<&Thresholds> {
+        self.thresholds.get(account)
+    }
+
+    /// Lets `sender` send at most `limit.max` to `recipient` per `limit.period`; neither needs
+    /// to exist yet. Setting a limit again keeps what was sent in the current window.
+    ///
+    /// Limits are only enforced by [`Accounts::send`] and [`Accounts::approve`], and like
+    /// credit limits they aren't part of the tx log, so a replayed ledger starts with the full
+    /// allowance.
+    pub fn set_spending_limit(&mut self, sender: &str, recipient: &str, limit: SpendingLimit) {
+        self.spending_limits
+            .get_or_insert_default(sender)
+            .entry_ref(recipient)
+            .and_modify(|allowance| allowance.limit = limit)
+            .or_insert_with(|| Allowance::new(limit));
+    }
+
+    /// The spending limit from `sender` to `recipient`, if there is one
+    pub fn spending_limit(&self, sender: &str, recipient: &str) -> Option<&SpendingLimit> {
+        self.allowance(sender, recipient)
+            .map(|allowance| &allowance.limit)
+    }
+
+    /// What `sender` may still send to `recipient` before reaching the spending limit, or
+    /// `None` without a limit
+    pub fn remaining_allowance(&self, sender: &str, recipient: &str) -> Option<Units> {
+        self.allowance(sender, recipient)
+            .map(|allowance| allowance.remaining(self.clock.now()))
+    }
+
+    /// Uses `clock` instead of the system clock for the windows of spending limits
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    fn allowance(&self, sender: &str, recipient: &str) -> Option<&Allowance> {
+        self.spending_limits.get(sender)?.get(recipient)
+    }
+
+    /// The settings of the accounts, e.g. their recipient lists
+    pub fn metadata(&self) -> &LedgerMetadata {
+        &self.metadata
+    }
+
+    /// Changes the settings of the accounts. [`Accounts::send`] and [`Accounts::approve`]
+    /// only let a sender send to the recipients its
+    /// [`crate::metadata::AccountMetadata::may_send_to`] allows.
+    pub fn metadata_mut(&mut self) -> &mut LedgerMetadata {
+        Arc::make_mut(&mut self.metadata)
+    }
+
+    /// Registers a listener for the [`LedgerEvent`]s of this ledger
+    pub fn subscribe(&mut self, listener: impl Fn(&LedgerEvent) + Send + Sync + 'static) {
+        self.events.subscribe(listener);
+    }
+
+    /// Either deposits the `amount` provided into the `signer` account or adds the amount to the existing account.
+    /// # Errors
+    /// Attempted overflow, or `signer` is an escrow account
+    #[instrument(skip(self), ret(level = Level::DEBUG), err(Display, level = Level::INFO))]
+    pub fn deposit(&mut self, signer: &str, amount: Units) -> Result<Tx, ApplicationError> {
+        self.check_writable("deposit")?;
+        self.check_unlocked("deposit", &[signer])?;
+        self.commit_deposit("deposit", signer, amount)
+    }
+
+    /// Withdraws the `amount` from the `signer` account.
+    /// # Errors
+    /// Attempted overflow, `signer` is an escrow account, or the [`Accounts::principal`] doesn't
+    /// own it
+    #[instrument(skip(self), ret(level = Level::DEBUG), err(Display, level = Level::INFO))]
+    pub fn withdraw(&mut self, signer: &str, amount: Units) -> Result<Tx, ApplicationError> {
+        self.check_writable("withdraw")?;
+        self.check_unlocked("withdraw", &[signer])?;
+        self.check_owner("withdraw", signer)?;
+        self.commit_withdraw("withdraw", signer, amount)
+    }
+
+    /// Issues `amount` of new money into the `signer` account. Unlike a [`Accounts::deposit`]
+    /// of money a customer brings, minting is how the money in the system grows, so only admins
+    /// may do it, and [`Accounts::stats`] count it apart from deposits.
+    /// # Errors
+    /// Attempted overflow, `signer` is an escrow account, or the [`Accounts::principal`] isn't
+    /// an admin
+    #[instrument(skip(self), ret(level = Level::DEBUG), err(Display, level = Level::INFO))]
+    pub fn mint(&mut self, signer: &str, amount: Units) -> Result<Tx, ApplicationError> {
+        self.check_writable("mint")?;
+        self.check_unlocked("mint", &[signer])?;
+        self.check_admin("mint")?;
+        self.commit_mint("mint", signer, amount)
+    }
+
+    /// Takes `amount` out of circulation from the `signer` account, the counterpart of
+    /// [`Accounts::mint`]
+    /// # Errors
+    /// `signer` can't afford `amount` or is an escrow account, or the [`Accounts::principal`]
+    /// isn't an admin
+    #[instrument(skip(self), ret(level = Level::DEBUG), err(Display, level = Level::INFO))]
+    pub fn burn(&mut self, signer: &str, amount: Units) -> Result<Tx, ApplicationError> {
+        self.check_writable("burn")?;
+        self.check_unlocked("burn", &[signer])?;
+        self.check_admin("burn")?;
+        self.commit_burn("burn", signer, amount)
+    }
+
+    /// Grants `signer`, which needn't exist yet, `amount` of promotional credit it can spend
+    /// until `expires`. The credit is kept apart from its balance, and is spent before it by
+    /// [`Accounts::pay`].
+    /// # Errors
+    /// `signer` is an escrow, dispute or archived account, or the [`Accounts::principal`]
+    /// isn't an admin
+    #[instrument(skip(self), ret(level = Level::DEBUG), err(Display, level = Level::INFO))]
+    pub fn grant_promo(
+        &mut self,
+        signer: &str,
+        amount: Units,
+        expires: Timestamp,
+    ) -> Result<Tx, ApplicationError> {
+        self.check_writable("grant_promo")?;
+        self.check_unlocked("grant_promo", &[signer])?;
+        self.check_admin("grant_promo")?;
+        Ok(self.commit_promo_grant(signer, amount, expires))
+    }
+
+    /// Authorizes `payee` to collect up to `limit.max` from `payer` per `limit.period` with
+    /// [`Accounts::collect`], replacing the mandate it had; neither needs to exist yet. The
+    /// mandate and every collection are recorded in the log, so a replayed ledger knows what
+    /// is left of it, unless the log has no timestamps to tell the windows apart; then the
+    /// replayed ledger starts with the full allowance.
+    /// # Errors
+    /// `payer` is an escrow, dispute or archived account, or the [`Accounts::principal`] may
+    /// not operate it
+    #[instrument(skip(self), ret(level = Level::DEBUG), err(Display, level = Level::INFO))]
+    pub fn grant_mandate(
+        &mut self,
+        payer: &str,
+        payee: &str,
+        limit: SpendingLimit,
+    ) -> Result<Tx, ApplicationError> {
+        self.check_writable("grant_mandate")?;
+        self.check_unlocked("grant_mandate", &[payer])?;
+        self.check_owner("grant_mandate", payer)?;
+        Ok(self.commit_mandate_grant(payer, payee, limit))
+    }
+
+    /// Revokes the mandate of `payee` on `payer`, if it has one, and returns the committed
+    /// transaction
+    /// # Errors
+    /// The [`Accounts::principal`] may not operate `payer`
+    #[instrument(skip(self), ret(level = Level::DEBUG), err(Display, level = Level::INFO))]
+    pub fn revoke_mandate(
+        &mut self,
+        payer: &str,
+        payee: &str,
+    ) -> Result<Option<Tx>, ApplicationError> {
+        self.check_writable("revoke_mandate")?;
+        self.check_owner("revoke_mandate", payer)?;
+        if self.mandate(payer, payee).is_none() {
+            return Ok(None);
+        }
+        Ok(Some(self.commit_mandate_revoke(payer, payee)))
+    }
+
+    /// Transfers `amount` from `payer` to `payee` on the initiative of `payee`, within the
+    /// mandate `payer` granted it. The payer authorized the collection up front, so it needs
+    /// neither approval nor confirmation, and the payer's recipient lists don't apply.
+    ///
+    /// The payer is charged the fee of the `collect` schedule of [`Accounts::fees`]. Returns the
+    /// committed transactions, the transfer and the fee, if any.
+    /// # Errors
+    /// [`ApplicationError::Unauthorized`] if `payee` has no mandate on `payer` or the
+    /// [`Accounts::principal`] may not operate `payee`, [`ApplicationError::LimitExceeded`]
+    /// if `amount` is more than is left of the mandate in its current window,
+    /// [`ApplicationError::UnderFunded`] if `payer` can't cover the amount and the fee together;
+    /// otherwise see [`Accounts::send`]
+    #[instrument(skip(self), ret(level = Level::DEBUG), err(Display, level = Level::INFO))]
+    pub fn collect(
+        &mut self,
+        payee: &str,
+        payer: &str,
+        amount: Units,
+    ) -> Result<Vec<Tx>, ApplicationError> {
+        self.check_writable("collect")?;
+        self.check_unlocked("collect", &[payer, payee])?;
+        self.check_owner("collect", payee)?;
+        let now = self.clock.now();
+        let remaining = self
+            .mandates
+            .get(payer)
+            .and_then(|mandates| mandates.get(payee))
+            .map(|allowance| allowance.remaining(now));
+        let e = match remaining {
+            None => ApplicationError::Unauthorized(payee.to_string()),
+            Some(remaining) if amount > remaining => {
+                ApplicationError::LimitExceeded(payee.to_string(), remaining)
+            }
+            Some(_) => {
+                self.check_affordable("collect", payer, amount, 0)?;
+                let txs = self.commit_charged("collect", payer, payee, amount, true, false)?;
+                if let Some(allowance) = self
+                    .mandates
+                    .get_mut(payer)
+                    .and_then(|mandates| mandates.get_mut(payee))
+                {
+                    allowance.spend(now, amount);
+                }
+                return Ok(txs);
+            }
+        };
+        self.publish_failed("collect", &e);
+        Err(e)
+    }
+
+    /// The mandate of `payee` on `payer`, if there is one
+    pub fn mandate(&self, payer: &str, payee: &str) -> Option<&SpendingLimit> {
+        let allowance = self.mandates.get(payer)?.get(payee)?;
+        Some(&allowance.limit)
+    }
+
+    /// What `payee` may still collect from `payer` in the current window, or `None` without a
+    /// mandate
+    pub fn remaining_mandate(&self, payer: &str, payee: &str) -> Option<Units> {
+        let allowance = self.mandates.get(payer)?.get(payee)?;
+        Some(allowance.remaining(self.clock.now()))
+    }
+
+    /// Every mandate as payer, payee and limit, ordered by payer and payee
+    pub fn mandates(&self) -> Vec<(&str, &str, SpendingLimit)> {
+        let mut mandates: Vec<_> = self
+            .mandates
+            .iter()
+            .flat_map(|(payer, mandates)| {
+                mandates.iter().map(move |(payee, allowance)| {
+                    (payer.as_str(), payee.as_str(), allowance.limit)
+                })
+            })
+            .collect();
+        mandates.sort_by_key(|&(payer, payee, _)| (payer, payee));
+        mandates
+    }
+
+    /// Writes off the promotional credit of every account that expired by now, and returns
+    /// the committed transactions in account order
+    /// # Errors
+    /// The ledger is read-only
+    pub fn expire_promos(&mut self) -> Result<Vec<Tx>, ApplicationError> {
+        self.check_writable("expire_promos")?;
+        let now = self.clock.now();
+        let mut expired: Vec<_> = self
+            .promos
+            .iter()
+            .flat_map(|(account, credits)| {
+                credits
+                    .expired(now)
+                    .into_iter()
+                    .map(move |(expires, amount)| (account.clone(), expires, amount))
+            })
+            .collect();
+        expired.sort();
+        let mut txs = vec![];
+        for (account, expires, amount) in expired {
+            txs.push(self.commit_promo_expire(&account, amount, expires)?);
+        }
+        Ok(txs)
+    }
+
+    /// Withdraws the amount from the sender account and deposits it in the recipient account.
+    ///
+    /// If the sender has a [`MultisigPolicy`] covering `amount`, nothing is moved yet: the
+    /// transfer waits for [`Accounts::approve`] and this fails with
+    /// [`ApplicationError::ApprovalRequired`], giving its id.
+    ///
+    /// Sends the [`AnomalyPolicy`] flags are published as [`LedgerEvent::AnomalyDetected`], and
+    /// depending on its [`Action`] fail.
+    ///
+    /// Neither promotional credit nor a fee is involved, see [`Accounts::pay`] for that.
+    /// # Errors
+    /// The account doesn't exist, either of them is an escrow account, the
+    /// [`Accounts::principal`] doesn't own the sender, the sender may not send to the recipient, the transfer exceeds a spending limit, looks suspicious, or needs approval
+    #[instrument(skip(self), ret(level = Level::DEBUG), err(Display, level = Level::INFO))]
+    pub fn send(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx), ApplicationError> {
+        self.checked_send(sender, recipient, amount, false)
+    }
+
+    /// Like [`Accounts::send`], but goes through even if the [`AnomalyPolicy`] asks to confirm
+    /// it, e.g. after [`ApplicationError::ConfirmationRequired`] and asking the user
+    /// # Errors
+    /// See [`Accounts::send`]
+    #[instrument(skip(self), ret(level = Level::DEBUG), err(Display, level = Level::INFO))]
+    pub fn send_confirmed(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx), ApplicationError> {
+        self.checked_send(sender, recipient, amount, true)
+    }
+
+    /// Like [`Accounts::send`], but spends the promotional credit of `sender` first, see
+    /// [`Accounts::grant_promo`], then charges it the fee of `amount` by the `send` schedule of
+    /// [`Accounts::fees`], paid to the fee account. Returns the committed transactions: the
+    /// credit spent, the transfer and the fee, if any.
+    /// # Errors
+    /// See [`Accounts::send`]; [`ApplicationError::UnderFunded`] if `sender` can't cover the
+    /// amount and the fee together, in which case nothing is spent or sent
+    pub fn pay(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<Vec<Tx>, ApplicationError> {
+        self.checked_pay(sender, recipient, amount, false)
+    }
+
+    /// Like [`Accounts::pay`], but goes through even if the [`AnomalyPolicy`] asks to confirm
+    /// it, see [`Accounts::send_confirmed`]
+    /// # Errors
+    /// See [`Accounts::pay`]
+    pub fn pay_confirmed(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<Vec<Tx>, ApplicationError> {
+        self.checked_pay(sender, recipient, amount, true)
+    }
+
+    fn checked_pay(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+        confirmed: bool,
+    ) -> Result<Vec<Tx>, ApplicationError> {
+        let now = self.clock.now();
+        let promo = self.promos.get(sender).map_or(0, |c| c.available(now));
+        self.check_affordable("send", sender, amount, promo)?;
+        self.check_send(sender, recipient, amount, confirmed)?;
+        let txs = self.commit_charged("send", sender, recipient, amount, false, promo > 0)?;
+        self.anomalies.record(now, sender, recipient);
+        Ok(txs)
+    }
+
+    /// Fails with [`ApplicationError::UnderFunded`] if `sender` can't cover `amount` and the fee
+    /// of `operation` on it together, spending up to `promo` of promotional credit on `amount`
+    fn check_affordable(
+        &self,
+        operation: &'static str,
+        sender: &str,
+        amount: Units,
+        promo: Units,
+    ) -> Result<(), ApplicationError> {
+        let Ok(balance) = self.balance_of(sender) else {
+            return Ok(());
+        };
+        let credit = self
+            .credit_lines
+            .get(sender)
+            .map_or(0, |line| line.limit.saturating_sub(line.drawn));
+        let total = amount.saturating_add(self.fee(operation, amount));
+        let spendable = balance
+            .saturating_add(credit)
+            .saturating_add(promo.min(amount));
+        if spendable < total {
+            let e = ApplicationError::UnderFunded(sender.to_string(), total);
+            self.publish_failed(operation, &e);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// The fee [`Accounts::fees`] charge for `operation` on `amount`, nothing without a fee
+    /// account to pay it to
+    fn fee(&self, operation: &str, amount: Units) -> Units {
+        match self.fees.account() {
+            Some(_) => self.fees.fee(operation, amount, self.rounding.policy),
+            None => 0,
+        }
+    }
+
+    /// Commits the transfer of `amount` from `sender` to `recipient` that `operation` makes,
+    /// see [`Accounts::commit_send_as`], charging `sender` the fee of the `collect` schedule if
+    /// `collected` and of the `send` schedule otherwise. With `promo`, the promotional credit
+    /// of `sender` is spent on `amount` first. Returns the committed transactions, the credit
+    /// spent, the transfer and the fee, which are committed together or not at all.
+    fn commit_charged(
+        &mut self,
+        operation: &'static str,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+        collected: bool,
+        promo: bool,
+    ) -> Result<Vec<Tx>, ApplicationError> {
+        let now = self.clock.now();
+        let spending = match self.promos.get(sender).filter(|_| promo) {
+            Some(credits) => credits.spending(amount, now),
+            None => vec![],
+        };
+        let fee = self.fee(if collected { "collect" } else { "send" }, amount);
+        let fee_account = self.fees.account().filter(|_| fee > 0).map(str::to_string);
+        self.atomically(|ledger| {
+            let mut txs = vec![];
+            for (expires, spent) in spending {
+                txs.push(ledger.commit_promo_spend(sender, spent, expires)?);
+            }
+            let (withdrawal, deposit) =
+                ledger.commit_send_as(operation, sender, recipient, amount, collected)?;
+            txs.extend([withdrawal, deposit]);
+            if let Some(fee_account) = fee_account {
+                let (withdrawal, deposit) = ledger.commit_send("fee", sender, &fee_account, fee)?;
+                txs.extend([withdrawal, deposit]);
+            }
+            Ok(txs)
+        })
+    }
+
+    fn checked_send(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+        confirmed: bool,
+    ) -> Result<(Tx, Tx), ApplicationError> {
+        self.check_send(sender, recipient, amount, confirmed)?;
+        let txs = self.commit_send("send", sender, recipient, amount)?;
+        self.record_send(sender, recipient);
+        Ok(txs)
+    }
+
+    /// Remembers a committed send from `sender` to `recipient` for the [`AnomalyPolicy`]
+    pub(crate) fn record_send(&mut self, sender: &str, recipient: &str) {
+        self.anomalies.record(self.clock.now(), sender, recipient);
+    }
+
+    /// Everything [`Accounts::send`] checks before committing, failing with
+    /// [`ApplicationError::ApprovalRequired`] once the transfer waits for approval
+    pub(crate) fn check_send(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+        confirmed: bool,
+    ) -> Result<(), ApplicationError> {
+        self.check_writable("send")?;
+        self.check_unlocked("send", &[sender, recipient])?;
+        self.check_owner("send", sender)?;
+        self.check_recipient("send", sender, recipient)?;
+        self.check_spending_limit("send", sender, recipient, amount)?;
+        self.check_anomalies(sender, recipient, amount, confirmed)?;
+        if self
+            .multisig
+            .get(sender)
+            .is_some_and(|policy| policy.applies_to(amount))
+        {
+            let id = self.next_pending_id;
+            self.next_pending_id += 1;
+            Arc::make_mut(&mut self.pending).insert(
+                id,
+                PendingTransfer {
+                    id,
+                    sender: sender.to_string(),
+                    recipient: recipient.to_string(),
+                    amount,
+                    approvals: vec![],
+                },
+            );
+            let e = ApplicationError::ApprovalRequired(id);
+            self.publish_failed("send", &e);
+            return Err(e);
         }
+        Ok(())
+    }
+
+    /// Pays `total` out of `payer` to the accounts of `weights` in proportion to their weight,
+    /// each share rounded by the [`Accounts::rounding`] policy, e.g. a dividend by balance.
+    /// What rounding leaves of the total goes to the remainder account, which covers it if
+    /// negative; without one `payer` keeps or covers it. Returns the committed transactions.
+    ///
+    /// The transfers are paid like [`Accounts::pay`] pays, as one unit, so either all of them
+    /// go through or none does.
+    /// # Errors
+    /// Any of the transfers would fail, see [`Accounts::pay`]
+    pub fn pay_out(
+        &mut self,
+        payer: &str,
+        total: Units,
+        weights: &[(&str, Units)],
+    ) -> Result<Vec<Tx>, ApplicationError> {
+        let split = self.rounding.split(total, weights);
+        let send = |sender: &str, recipient: &str, amount: Units| Op::Send {
+            sender: sender.to_string(),
+            recipient: recipient.to_string(),
+            amount,
+        };
+        let mut ops = vec![];
+        let remainder = Units::try_from(split.remainder.unsigned_abs()).unwrap_or(Units::MAX);
+        let remainder_account = self.rounding.remainder_account.as_deref();
+        if let Some(account) = remainder_account.filter(|_| split.remainder < 0) {
+            ops.push(send(account, payer, remainder));
+        }
+        for (account, share) in &split.shares {
+            ops.push(send(payer, account, *share));
+        }
+        if let Some(account) = remainder_account.filter(|_| split.remainder > 0) {
+            ops.push(send(payer, account, remainder));
+        }
+        ops.retain(|op| !matches!(op, Op::Send { amount: 0, .. }));
+        self.apply_atomically(&ops)
+    }
+
+    /// Sends along every `(sender, recipient, amount)` leg of `legs` in order as one unit and
+    /// returns the committed transactions, e.g. `A` to `B`, `B` to `C` and `C` to `D` to pass
+    /// money down a chain, or `A` to `B` and `A` to `C` to split one payment between several
+    /// recipients. A leg can send on what an earlier one received.
+    ///
+    /// The legs are paid like [`Accounts::pay`] pays, as one unit, so either all of them go
+    /// through or none does.
+    /// # Errors
+    /// Any of the legs would fail, see [`Accounts::pay`]
+    pub fn send_multi(
+        &mut self,
+        legs: &[(&str, &str, Units)],
+    ) -> Result<Vec<Tx>, ApplicationError> {
+        let ops: Vec<Op> = legs
+            .iter()
+            .map(|&(sender, recipient, amount)| Op::Send {
+                sender: sender.to_string(),
+                recipient: recipient.to_string(),
+                amount,
+            })
+            .collect();
+        self.apply_atomically(&ops)
+    }
+
+    /// Applies `ops` in order as one unit, see [`Accounts::atomically`]
+    fn apply_atomically(&mut self, ops: &[Op]) -> Result<Vec<Tx>, ApplicationError> {
+        self.atomically(|ledger| {
+            let mut txs = vec![];
+            for op in ops {
+                txs.extend(op.apply(ledger)?);
+            }
+            Ok(txs)
+        })
+    }
+
+    /// Does what `operation` does to the ledger if it succeeds, and nothing if it fails.
+    ///
+    /// The operation is applied to a [`Accounts::fork`] that takes the place of the ledger once
+    /// it succeeded, and what it published is published then. If it fails, whatever it
+    /// committed before is rolled back by dropping the fork, and only its failure is published.
+    fn atomically<T>(
+        &mut self,
+        operation: impl FnOnce(&mut Accounts) -> Result<T, ApplicationError>,
+    ) -> Result<T, ApplicationError> {
+        let (result, staged) = self.stage(operation);
+        self.settle(staged, result.is_ok());
+        result
+    }
+
+    /// Applies `operation` to a [`Accounts::fork`] and keeps what it published, leaving it to
+    /// [`Accounts::settle`] whether the ledger takes it over, e.g. once it's persisted
+    pub(crate) fn stage<T>(
+        &self,
+        operation: impl FnOnce(&mut Accounts) -> Result<T, ApplicationError>,
+    ) -> (Result<T, ApplicationError>, Staged) {
+        let mut fork = self.fork();
+        let published: Arc<Mutex<Vec<LedgerEvent>>> = Default::default();
+        let collected = published.clone();
+        fork.subscribe(move |event| collected.lock().unwrap().push(event.clone()));
+        let result = operation(&mut fork);
+        let published = std::mem::take(&mut *published.lock().unwrap());
+        (result, Staged { fork, published })
+    }
+
+    /// Takes over the `staged` fork in place of the ledger and publishes what it published if
+    /// `keep` is set, and otherwise drops it, publishing only its failures
+    pub(crate) fn settle(&mut self, staged: Staged, keep: bool) {
+        let Staged {
+            mut fork,
+            published,
+        } = staged;
+        if keep {
+            fork.events = std::mem::take(&mut self.events);
+            *self = fork;
+            self.compact();
+        }
+        let published = published
+            .iter()
+            .filter(|event| keep || matches!(event, LedgerEvent::TxFailed { .. }));
+        published.for_each(|event| self.events.publish(event));
+    }
+
+    /// Takes back what a fork changed once it took the place of the ledger, which otherwise
+    /// stays on top of the state the fork shared and is copied again by every later fork
+    fn compact(&mut self) {
+        self.accounts.compact();
+        self.stats.compact();
+        self.thresholds.compact();
+        self.credit_lines.compact();
+        self.multisig.compact();
+        self.spending_limits.compact();
+        self.archived.compact();
+        self.anomalies.compact();
+        self.promos.compact();
+        self.mandates.compact();
+    }
+
+    /// Rounds derived amounts like shares of [`Accounts::pay_out`] by `rounding`
+    pub fn set_rounding(&mut self, rounding: Rounding) {
+        self.rounding = rounding;
+    }
+
+    /// How derived amounts are rounded, floor without a remainder account unless
+    /// [`Accounts::set_rounding`] says otherwise
+    pub fn rounding(&self) -> &Rounding {
+        &self.rounding
+    }
+
+    /// Charges transfers by `fees`: those made with [`Accounts::pay`], and so with
+    /// [`Accounts::send_multi`] and [`Accounts::pay_out`], approved with [`Accounts::approve`]
+    /// and collected with [`Accounts::collect`].
+    ///
+    /// Fees aren't part of the tx log, but the transfers charging them are, so replaying it
+    /// doesn't need them.
+    pub fn set_fees(&mut self, fees: FeeSchedules) {
+        self.fees = fees;
+    }
+
+    /// What transfers are charged, see [`Accounts::set_fees`], nothing unless
+    /// [`Accounts::set_fees`] says otherwise
+    pub fn fees(&self) -> &FeeSchedules {
+        &self.fees
+    }
+
+    /// Pays interest by `schedules` on the accounts of the kinds they cover, see
+    /// [`Accounts::accrue_interest`]
+    pub fn set_interest(&mut self, schedules: InterestSchedules) {
+        self.interest = schedules;
+    }
+
+    /// The interest each kind of account earns, none unless [`Accounts::set_interest`] says
+    /// otherwise
+    pub fn interest(&self) -> &InterestSchedules {
+        &self.interest
+    }
+
+    /// Makes `account`, which needn't exist yet, one of kind `kind`, e.g. `savings`
+    pub fn set_account_kind(&mut self, account: &str, kind: &str) {
+        Arc::make_mut(&mut self.account_kinds).insert(account.to_string(), kind.to_string());
+    }
+
+    /// The kind of `account`, if it was given one, see [`Accounts::set_account_kind`]
+    pub fn account_kind(&self, account: &str) -> Option<&str> {
+        self.account_kinds.get(account).map(String::as_str)
+    }
+
+    /// Mints the interest every account earned on its balance over `days` days, by the
+    /// [`crate::interest::RateSchedule`] of its kind, see [`Accounts::set_interest`], rounded
+    /// by [`Accounts::rounding`]. Accounts without a kind, or of a kind without a schedule,
+    /// earn nothing. The interest of all accounts is minted, in name order, or none of it.
+    /// # Errors
+    /// The [`Accounts::principal`] isn't an admin, or a balance would overflow
+    #[instrument(skip(self), ret(level = Level::DEBUG), err(Display, level = Level::INFO))]
+    pub fn accrue_interest(&mut self, days: u32) -> Result<Vec<Tx>, ApplicationError> {
+        self.check_writable("accrue_interest")?;
+        self.check_admin("accrue_interest")?;
+        let policy = self.rounding.policy;
+        let earned: Vec<(String, Units)> = self
+            .account_kinds
+            .iter()
+            .filter_map(|(account, kind)| {
+                let schedule = self.interest.get(kind)?;
+                let balance = *self.accounts.get(account.as_str())?;
+                let interest = schedule.interest_rounded(balance, days, policy);
+                (interest > 0).then(|| (account.clone(), interest))
+            })
+            .collect();
+        self.atomically(|ledger| {
+            earned
+                .iter()
+                .map(|(account, interest)| {
+                    ledger.commit_mint("accrue_interest", account, *interest)
+                })
+                .collect()
+        })
+    }
+
+    /// Flags suspicious sends like [`Accounts::send`] by `policy`
+    pub fn set_anomaly_policy(&mut self, policy: AnomalyPolicy) {
+        self.anomalies.policy = policy;
+    }
+
+    pub fn anomaly_policy(&self) -> &AnomalyPolicy {
+        &self.anomalies.policy
+    }
+
+    /// Requires approval by `policy` for the large transfers of `account`, which needn't exist yet.
+    ///
+    /// Transfers waiting for approval are kept in memory only; the tx log just records the
+    /// transfers once they are approved.
+    pub fn set_multisig(&mut self, account: &str, policy: MultisigPolicy) {
+        self.multisig.insert(account.to_string(), policy);
+    }
+
+    /// The approval policy of `account`, if it has one
+    pub fn multisig(&self, account: &str) -> Option<&MultisigPolicy> {
+        self.multisig.get(account)
+    }
+
+    /// The transfers waiting for approval, oldest first
+    pub fn pending_transfers(&self) -> impl Iterator<Item = &PendingTransfer> {
+        self.pending.values()
+    }
+
+    /// Records the approval of pending transfer `id` by the [`Accounts::principal`], and carries
+    /// it out once enough of the sender's signers approved, charging the sender the fee of the
+    /// `send` schedule of [`Accounts::fees`]. Returns the committed transactions then, the
+    /// transfer and the fee, if any. Approving twice counts once.
+    /// # Errors
+    /// There is no pending transfer `id`, there is no principal or it isn't one of the sender's
+    /// signers, or the approved transfer failed, e.g. because it exceeds a spending limit by
+    /// now, in which case it stays pending
+    #[instrument(skip(self), err(Display, level = Level::INFO))]
+    pub fn approve(&mut self, id: u64) -> Result<Option<Vec<Tx>>, ApplicationError> {
+        self.check_writable("approve")?;
+        let Some(signer) = self.principal.clone() else {
+            let e = ApplicationError::Unauthorized("anonymous".to_string());
+            self.publish_failed("approve", &e);
+            return Err(e);
+        };
+        let Some(pending) = Arc::make_mut(&mut self.pending).get_mut(&id) else {
+            let e = ApplicationError::NotFound(format!("transfer {}", id));
+            self.publish_failed("approve", &e);
+            return Err(e);
+        };
+        let required = match self.multisig.get(&pending.sender) {
+            Some(policy) if !policy.is_signer(&signer) => {
+                let e = ApplicationError::Unauthorized(signer);
+                self.publish_failed("approve", &e);
+                return Err(e);
+            }
+            Some(policy) => policy.required,
+            // The policy was lifted in the meantime
+            None => 0,
+        };
+        if !pending.approvals.contains(&signer) {
+            pending.approvals.push(signer);
+        }
+        if pending.approvals.len() < required {
+            return Ok(None);
+        }
+        let pending = pending.clone();
+        self.check_recipient("approve", &pending.sender, &pending.recipient)?;
+        self.check_spending_limit(
+            "approve",
+            &pending.sender,
+            &pending.recipient,
+            pending.amount,
+        )?;
+        let txs = self.commit_charged(
+            "approve",
+            &pending.sender,
+            &pending.recipient,
+            pending.amount,
+            false,
+            false,
+        )?;
+        Arc::make_mut(&mut self.pending).remove(&id);
+        Ok(Some(txs))
+    }
+
+    /// Applies a committed transaction again, e.g. while replaying a log. Unlike [`Accounts::deposit`]
+    /// and [`Accounts::withdraw`], this doesn't enforce rules that only apply to new operations,
+    /// like escrow locks.
+    /// # Errors
+    /// The transaction doesn't fit the current balances
+    pub fn apply(&mut self, tx: &Tx) -> Result<Tx, ApplicationError> {
+        if !matches!(tx, Tx::Deposit { .. }) {
+            self.replayed_send = None;
+        }
+        match tx {
+            Tx::Deposit { account, amount } => self.apply_deposit(account, *amount),
+            Tx::Withdraw {
+                account,
+                amount,
+                recipient: None,
+            } => self.apply_withdrawal(account, *amount),
+            Tx::Withdraw {
+                account,
+                amount,
+                recipient: Some(recipient),
+            } => self.apply_sent(account, recipient, *amount, false),
+            Tx::Mint { account, amount } => self.commit_mint("mint", account, *amount),
+            Tx::Burn { account, amount } => self.commit_burn("burn", account, *amount),
+            Tx::PromoGrant {
+                account,
+                amount,
+                expires,
+            } => Ok(self.commit_promo_grant(account, *amount, *expires)),
+            Tx::PromoSpend {
+                account,
+                amount,
+                expires,
+            } => self.commit_promo_spend(account, *amount, *expires),
+            Tx::PromoExpire {
+                account,
+                amount,
+                expires,
+            } => self.commit_promo_expire(account, *amount, *expires),
+            Tx::MandateGrant {
+                account,
+                payee,
+                max,
+                period,
+            } => {
+                let limit = SpendingLimit {
+                    max: *max,
+                    period: *period,
+                };
+                Ok(self.commit_mandate_grant(account, payee, limit))
+            }
+            Tx::MandateRevoke { account, payee } => Ok(self.commit_mandate_revoke(account, payee)),
+            Tx::Collect {
+                account,
+                payee,
+                amount,
+            } => self.apply_sent(account, payee, *amount, true),
+        }
+    }
+
+    /// [`Accounts::apply`] for a deposit that isn't a [`Tx`] yet, e.g. read from a WAL
+    pub fn apply_deposit(&mut self, signer: &str, amount: Units) -> Result<Tx, ApplicationError> {
+        let received = self
+            .replayed_send
+            .take()
+            .filter(|send| !send.received && &*send.recipient == signer && send.amount == amount);
+        match received {
+            Some(send) => self.apply_received(send),
+            None => self.commit_deposit("deposit", signer, amount),
+        }
+    }
+
+    /// [`Accounts::apply`] for a withdrawal that isn't a [`Tx`] yet, e.g. read from a WAL
+    pub fn apply_withdrawal(
+        &mut self,
+        signer: &str,
+        amount: Units,
+    ) -> Result<Tx, ApplicationError> {
+        self.replayed_send = None;
+        self.commit_withdraw("withdraw", signer, amount)
+    }
+
+    /// [`Accounts::apply`] for the withdrawal side of a transfer to `recipient`, a collection
+    /// by it if `collected`. The stats count it as sent, and as received once the deposit side
+    /// is replayed, see [`Accounts::apply_received`].
+    fn apply_sent(
+        &mut self,
+        sender: &str,
+        recipient: &Arc<str>,
+        amount: Units,
+        collected: bool,
+    ) -> Result<Tx, ApplicationError> {
+        let result = self
+            .debit(sender, amount)
+            .map(|withdrawal| match collected {
+                true => self::collected(withdrawal, recipient),
+                false => sent(withdrawal, recipient),
+            });
+        match &result {
+            Ok(tx) => {
+                self.stats
+                    .record_sent(tx.account_name(), amount, self.clock.now());
+                self.publish_committed(tx);
+                self.replayed_send = Some(ReplayedSend {
+                    sender: tx.account_name().clone(),
+                    recipient: recipient.clone(),
+                    amount,
+                    collected,
+                    received: false,
+                });
+            }
+            Err(e) => self.publish_failed(if collected { "collect" } else { "send" }, e),
+        }
+        result
+    }
+
+    /// [`Accounts::apply`] for the deposit side of the transfer `send`
+    fn apply_received(&mut self, send: ReplayedSend) -> Result<Tx, ApplicationError> {
+        match self.credit(&send.recipient, send.amount) {
+            Ok((tx, created)) => {
+                if created {
+                    self.publish_created(&send.recipient);
+                }
+                self.stats
+                    .record_received(tx.account_name(), send.amount, self.clock.now());
+                self.publish_committed(&tx);
+                self.replayed_send = Some(ReplayedSend {
+                    received: true,
+                    ..send
+                });
+                Ok(tx)
+            }
+            Err(e) => {
+                self.publish_failed("send", &e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Dates the transaction applied last `at`, when it was first committed, for
+    /// [`Accounts::stats`]
+    pub(crate) fn backdate(&mut self, at: Timestamp) {
+        self.stats.backdate(at);
+        let Some(send) = self.replayed_send.take_if(|send| send.received) else {
+            return;
+        };
+        let (sender, recipient) = (&*send.sender, &*send.recipient);
+        if let Some(allowance) = self
+            .spending_limits
+            .get_mut(sender)
+            .and_then(|allowances| allowances.get_mut(recipient))
+        {
+            allowance.spend(at, send.amount);
+        }
+        if let Some(allowance) = self
+            .mandates
+            .get_mut(sender)
+            .and_then(|mandates| mandates.get_mut(recipient))
+            .filter(|_| send.collected)
+        {
+            allowance.spend(at, send.amount);
+        }
+        // Fees, escrows and collections aren't sends the anomaly policy sees either
+        let fee = self.fees.account() == Some(recipient);
+        if !send.collected && !fee && !escrow::is_escrow(sender) && !escrow::is_escrow(recipient) {
+            self.anomalies.record(at, sender, recipient);
+        }
+    }
+
+    /// Moves `amount` from `payer` into a new escrow for `payee`, where it stays locked until
+    /// [`Accounts::release_escrow`] or [`Accounts::refund_escrow`]
+    /// # Errors
+    /// `payer` can't afford `amount`, the [`Accounts::principal`] doesn't own it, or either
+    /// account is an escrow account itself
+    #[instrument(skip(self), err(Display, level = Level::INFO))]
+    pub fn hold_in_escrow(
+        &mut self,
+        payer: &str,
+        payee: &str,
+        amount: Units,
+    ) -> Result<(Escrow, (Tx, Tx)), ApplicationError> {
+        self.check_writable("escrow")?;
+        self.check_unlocked("escrow", &[payer, payee])?;
+        self.check_owner("escrow", payer)?;
+        let id = self.escrows.last_key_value().map_or(1, |(id, _)| id + 1);
+        let account = escrow::account_name(id, payer, payee);
+        let txs = self.commit_send("escrow", payer, &account, amount)?;
+        Ok((self.escrows[&id].clone(), txs))
+    }
+
+    /// Pays the funds of escrow `id` out to its payee, on behalf of the payer or an admin
+    /// # Errors
+    /// There is no escrow `id` holding funds, the [`Accounts::principal`] doesn't act for the
+    /// payer, see [`Accounts::acts_for`], and isn't an admin, or the payee's balance would
+    /// overflow
+    #[instrument(skip(self), err(Display, level = Level::INFO))]
+    pub fn release_escrow(&mut self, id: u64) -> Result<(Tx, Tx), ApplicationError> {
+        let (escrow, amount) = self.open_escrow("release", id)?;
+        self.check_party("release", &escrow.payer)?;
+        self.commit_send("release", &escrow.account, &escrow.payee, amount)
+    }
+
+    /// Returns the funds of escrow `id` to its payer, on behalf of the payee or an admin
+    /// # Errors
+    /// There is no escrow `id` holding funds, the [`Accounts::principal`] doesn't act for the
+    /// payee, see [`Accounts::acts_for`], and isn't an admin, or the payer's balance would
+    /// overflow
+    #[instrument(skip(self), err(Display, level = Level::INFO))]
+    pub fn refund_escrow(&mut self, id: u64) -> Result<(Tx, Tx), ApplicationError> {
+        let (escrow, amount) = self.open_escrow("refund", id)?;
+        self.check_party("refund", &escrow.payee)?;
+        self.commit_send("refund", &escrow.account, &escrow.payer, amount)
+    }
+
+    /// The escrows still holding funds, by id, with their amounts
+    pub fn escrows(&self) -> impl Iterator<Item = (&Escrow, Units)> {
+        self.escrows.values().filter_map(|escrow| {
+            let amount = self.accounts.get(&escrow.account).copied().unwrap_or(0);
+            (amount > 0).then_some((escrow, amount))
+        })
+    }
+
+    pub(crate) fn open_escrow(
+        &self,
+        operation: &'static str,
+        id: u64,
+    ) -> Result<(Escrow, Units), ApplicationError> {
+        self.check_writable(operation)?;
+        let open = self.escrows().find(|(escrow, _)| escrow.id == id);
+        let (escrow, amount) = open.ok_or_else(|| {
+            let e = ApplicationError::NotFound(format!("escrow {}", id));
+            self.publish_failed(operation, &e);
+            e
+        })?;
+        Ok((escrow.clone(), amount))
+    }
+
+    /// Disputes the deposit at `position` of `log`, the ledger's tx log, by freezing its amount
+    /// in a dispute account until [`Accounts::uphold_dispute`] or [`Accounts::deny_dispute`].
+    /// Disputes are opened for the account that received the deposit, or by an admin.
+    /// # Errors
+    /// The entry at `position` isn't a deposit, it was disputed before, the
+    /// [`Accounts::principal`] doesn't act for its account, see [`Accounts::acts_for`], and isn't
+    /// an admin, or the account can't afford the amount any more
+    #[instrument(skip(self, log), err(Display, level = Level::INFO))]
+    pub fn open_dispute(
+        &mut self,
+        log: &TxLog,
+        position: usize,
+    ) -> Result<(Dispute, (Tx, Tx)), ApplicationError> {
+        self.check_writable("dispute")?;
+        let Some(Tx::Deposit { account, amount }) = log.entries().get(position).map(|e| &e.tx)
+        else {
+            let e = ApplicationError::NotFound(format!("deposit #{}", position));
+            self.publish_failed("dispute", &e);
+            return Err(e);
+        };
+        self.check_party("dispute", account)?;
+        self.check_unlocked("dispute", &[account])?;
+        if self.disputes.contains_key(&position) {
+            let e = ApplicationError::AlreadyExists(dispute::account_name(position, account));
+            self.publish_failed("dispute", &e);
+            return Err(e);
+        }
+        let holding = dispute::account_name(position, account);
+        let txs = self.commit_send("dispute", account, &holding, *amount)?;
+        Ok((self.disputes[&position].clone(), txs))
+    }
+
+    /// Resolves the dispute of the entry at `position` in favor of the disputing party by
+    /// withdrawing the frozen amount, which reverses the deposit
+    /// # Errors
+    /// There is no open dispute of that entry, or the [`Accounts::principal`] isn't an admin
+    #[instrument(skip(self), err(Display, level = Level::INFO))]
+    pub fn uphold_dispute(&mut self, position: usize) -> Result<Tx, ApplicationError> {
+        self.check_admin("uphold")?;
+        let (dispute, amount) = self.open_dispute_of("uphold", position)?;
+        self.commit_withdraw("uphold", &dispute.holding, amount)
+    }
+
+    /// Rejects the dispute of the entry at `position` by returning the frozen amount to its account
+    /// # Errors
+    /// There is no open dispute of that entry, the [`Accounts::principal`] isn't an admin, or the
+    /// account's balance would overflow
+    #[instrument(skip(self), err(Display, level = Level::INFO))]
+    pub fn deny_dispute(&mut self, position: usize) -> Result<(Tx, Tx), ApplicationError> {
+        self.check_admin("deny")?;
+        let (dispute, amount) = self.open_dispute_of("deny", position)?;
+        self.commit_send("deny", &dispute.holding, &dispute.account, amount)
+    }
+
+    /// The disputes whose amounts are still frozen, by position, with their amounts
+    pub fn disputes(&self) -> impl Iterator<Item = (&Dispute, Units)> {
+        self.disputes.values().filter_map(|dispute| {
+            let amount = self.accounts.get(&dispute.holding).copied().unwrap_or(0);
+            (amount > 0).then_some((dispute, amount))
+        })
+    }
+
+    fn open_dispute_of(
+        &self,
+        operation: &'static str,
+        position: usize,
+    ) -> Result<(Dispute, Units), ApplicationError> {
+        self.check_writable(operation)?;
+        let open = self
+            .disputes()
+            .find(|(dispute, _)| dispute.position == position);
+        let (dispute, amount) = open.ok_or_else(|| {
+            let e = ApplicationError::NotFound(format!("dispute #{}", position));
+            self.publish_failed(operation, &e);
+            e
+        })?;
+        Ok((dispute.clone(), amount))
+    }
+
+    /// Makes all further operations act on behalf of `principal`, e.g. the user logged in:
+    /// operating an account with owners, like withdrawing from it or changing its owners, fails
+    /// with [`ApplicationError::Unauthorized`] unless `principal` is one of them. Without a
+    /// principal, the default, everything is allowed.
+    ///
+    /// Replaying committed transactions with [`Accounts::apply`] isn't restricted.
+    pub fn set_principal(&mut self, principal: Option<String>) {
+        self.principal = principal;
+    }
+
+    /// Makes the ledger read-only for viewers: every operation changing it, like a deposit or
+    /// adding an owner, fails with [`ApplicationError::PermissionDenied`], while queries work
+    /// as usual. Replaying committed transactions with [`Accounts::apply`] still works, so a
+    /// read-only ledger can be loaded from its log.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Returns `true` if the ledger is read-only, see [`Accounts::set_read_only`]
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Who operations are performed as, see [`Accounts::set_principal`]
+    pub fn principal(&self) -> Option<&str> {
+        self.principal.as_deref()
+    }
+
+    /// Lets only `admins` mint and burn while there is a [`Accounts::principal`], see
+    /// [`Accounts::mint`]. Without a principal, anyone may.
+    pub fn set_admins(&mut self, admins: impl IntoIterator<Item = String>) {
+        self.admins = Arc::new(admins.into_iter().collect());
+    }
+
+    /// Returns `true` if `user` may mint and burn, see [`Accounts::set_admins`]
+    pub fn is_admin(&self, user: &str) -> bool {
+        self.admins.contains(user)
+    }
+
+    /// Returns `true` if `user` stands for `account` as a party to it, e.g. as the payer of an
+    /// escrow: as one of its owners, or as the account itself if it has none. Unlike operating
+    /// an account without owners, which anybody may do, this never holds for somebody else.
+    pub fn acts_for(&self, user: &str, account: &str) -> bool {
+        match self.metadata.get(account) {
+            Some(metadata) if !metadata.owners.is_empty() => metadata.owners.contains(user),
+            _ => user == account,
+        }
+    }
+
+    /// The owners of `account`, who may all operate it, in name order
+    pub fn owners(&self, account: &str) -> impl Iterator<Item = &str> {
+        self.metadata
+            .get(account)
+            .into_iter()
+            .flat_map(|metadata| metadata.owners.iter().map(String::as_str))
+    }
+
+    /// Makes `owner` an owner of `account`, publishing [`LedgerEvent::OwnershipChanged`] for the
+    /// audit trail. The owners are kept in the [`Accounts::metadata`].
+    ///
+    /// The first owner of an account can only be the principal itself, if there is one.
+    /// # Errors
+    /// The [`Accounts::principal`] doesn't own `account`, or isn't `owner` for an account
+    /// without owners
+    #[instrument(skip(self), err(Display, level = Level::INFO))]
+    pub fn add_owner(&mut self, account: &str, owner: &str) -> Result<(), ApplicationError> {
+        self.check_writable("add_owner")?;
+        match &self.principal {
+            Some(principal) if self.owners(account).next().is_none() && principal != owner => {
+                let e = ApplicationError::Unauthorized(principal.clone());
+                self.publish_failed("add_owner", &e);
+                return Err(e);
+            }
+            _ => self.check_owner("add_owner", account)?,
+        }
+        let metadata = Arc::make_mut(&mut self.metadata);
+        if metadata.entry(account).owners.insert(owner.to_string()) {
+            self.publish_ownership(account, OwnershipChange::Added(owner.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Removes `owner` from the owners of `account`, publishing [`LedgerEvent::OwnershipChanged`].
+    /// Removing the last owner makes the account operable by anybody again.
+    /// # Errors
+    /// The [`Accounts::principal`] doesn't own `account`
+    #[instrument(skip(self), err(Display, level = Level::INFO))]
+    pub fn remove_owner(&mut self, account: &str, owner: &str) -> Result<(), ApplicationError> {
+        self.check_writable("remove_owner")?;
+        self.check_owner("remove_owner", account)?;
+        if Arc::make_mut(&mut self.metadata)
+            .entry(account)
+            .owners
+            .remove(owner)
+        {
+            self.publish_ownership(account, OwnershipChange::Removed(owner.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Moves `account` out of the accounts in use into a separate, rarely touched store, e.g.
+    /// once it has been inactive for long. Archived accounts aren't listed, and operating on them
+    /// fails with [`ApplicationError::Archived`] until [`Accounts::restore`]. Their balances
+    /// still count towards the [`Accounts::supply`].
+    ///
+    /// The account is marked as archived in the [`Accounts::metadata`], so
+    /// [`Accounts::set_metadata`] archives it again after replaying the log.
+    /// # Errors
+    /// The account doesn't exist, is archived already, is an escrow or dispute account, or the
+    /// [`Accounts::principal`] doesn't own it
+    #[instrument(skip(self), err(Display, level = Level::INFO))]
+    pub fn archive(&mut self, account: &str) -> Result<(), ApplicationError> {
+        self.check_writable("archive")?;
+        self.check_unlocked("archive", &[account])?;
+        self.check_owner("archive", account)?;
+        let Some((name, balance)) = self.accounts.remove_entry(account) else {
+            let e = self.missing(account);
+            self.publish_failed("archive", &e);
+            return Err(e);
+        };
+        self.archived.insert(name, balance);
+        Arc::make_mut(&mut self.metadata).entry(account).archived = true;
+        Ok(())
+    }
+
+    /// Brings an archived `account` back into use with its balance
+    /// # Errors
+    /// The account isn't archived, or the [`Accounts::principal`] doesn't own it
+    #[instrument(skip(self), err(Display, level = Level::INFO))]
+    pub fn restore(&mut self, account: &str) -> Result<(), ApplicationError> {
+        self.check_writable("restore")?;
+        self.check_owner("restore", account)?;
+        let Some((name, balance)) = self.archived.remove_entry(account) else {
+            let e = ApplicationError::NotFound(account.to_string());
+            self.publish_failed("restore", &e);
+            return Err(e);
+        };
+        self.accounts.insert(name, balance);
+        Arc::make_mut(&mut self.metadata).entry(account).archived = false;
+        Ok(())
+    }
+
+    /// Iterates over the archived accounts and their balances in no particular order
+    pub fn archived(&self) -> impl Iterator<Item = (&str, &Units)> {
+        self.archived
+            .iter()
+            .map(|(account, balance)| (&**account, balance))
+    }
+
+    /// Replaces the settings of the accounts, e.g. with those loaded next to the log after
+    /// replaying it, and archives or restores accounts as they are marked. Marked accounts that
+    /// don't exist are ignored.
+    pub fn set_metadata(&mut self, metadata: LedgerMetadata) {
+        let marked = |account: &str| metadata.get(account).is_some_and(|m| m.archived);
+        let archive: Vec<_> = self
+            .accounts
+            .keys()
+            .filter(|a| marked(a))
+            .cloned()
+            .collect();
+        let restore: Vec<_> = self
+            .archived
+            .keys()
+            .filter(|a| !marked(a))
+            .cloned()
+            .collect();
+        for account in archive {
+            let balance = self
+                .accounts
+                .remove(&account)
+                .expect("the account is in use");
+            self.archived.insert(account, balance);
+        }
+        for account in restore {
+            let balance = self
+                .archived
+                .remove(&account)
+                .expect("the account is archived");
+            self.accounts.insert(account, balance);
+        }
+        self.metadata = Arc::new(metadata);
+    }
+
+    /// Publishes the anomaly sending `amount` from `sender` to `recipient` is, if any, and fails
+    /// if the [`AnomalyPolicy`] says so
+    fn check_anomalies(
+        &self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+        confirmed: bool,
+    ) -> Result<(), ApplicationError> {
+        let balance = self.accounts.get(sender).copied().unwrap_or(0);
+        let Some(anomaly) =
+            self.anomalies
+                .check(self.clock.now(), sender, recipient, amount, balance)
+        else {
+            return Ok(());
+        };
+        let action = self.anomalies.policy.action;
+        self.events.publish(&LedgerEvent::AnomalyDetected {
+            anomaly: anomaly.clone(),
+            action,
+        });
+        let e = match action {
+            Action::Warn => return Ok(()),
+            Action::Confirm if confirmed => return Ok(()),
+            Action::Confirm => ApplicationError::ConfirmationRequired(anomaly.to_string()),
+            Action::Block => ApplicationError::Suspicious(anomaly.to_string()),
+        };
+        self.publish_failed("send", &e);
+        Err(e)
+    }
+
+    /// Fails with [`ApplicationError::Blocked`] if the recipient lists of `sender` don't allow
+    /// sending to `recipient`
+    fn check_recipient(
+        &self,
+        operation: &'static str,
+        sender: &str,
+        recipient: &str,
+    ) -> Result<(), ApplicationError> {
+        match self.metadata.get(sender) {
+            Some(metadata) if !metadata.may_send_to(recipient) => {
+                let e = ApplicationError::Blocked(recipient.to_string());
+                self.publish_failed(operation, &e);
+                Err(e)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails with [`ApplicationError::LimitExceeded`] if sending `amount` would exceed the
+    /// spending limit from `sender` to `recipient`
+    fn check_spending_limit(
+        &self,
+        operation: &'static str,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(), ApplicationError> {
+        match self.remaining_allowance(sender, recipient) {
+            Some(remaining) if amount > remaining => {
+                let e = ApplicationError::LimitExceeded(recipient.to_string(), remaining);
+                self.publish_failed(operation, &e);
+                Err(e)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails with [`ApplicationError::PermissionDenied`] if the ledger is read-only
+    fn check_writable(&self, operation: &'static str) -> Result<(), ApplicationError> {
+        if !self.read_only {
+            return Ok(());
+        }
+        let e = ApplicationError::PermissionDenied(operation.to_string());
+        self.publish_failed(operation, &e);
+        Err(e)
+    }
+
+    /// Fails with [`ApplicationError::Unauthorized`] if the principal may not operate `account`
+    fn check_owner(&self, operation: &'static str, account: &str) -> Result<(), ApplicationError> {
+        let Some(principal) = &self.principal else {
+            return Ok(());
+        };
+        match self.metadata.get(account) {
+            Some(metadata) if !metadata.may_operate(principal) => {
+                let e = ApplicationError::Unauthorized(principal.clone());
+                self.publish_failed(operation, &e);
+                Err(e)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails with [`ApplicationError::Unauthorized`] if there is a principal that neither
+    /// [`Accounts::acts_for`] `account` nor is an admin
+    pub(crate) fn check_party(
+        &self,
+        operation: &'static str,
+        account: &str,
+    ) -> Result<(), ApplicationError> {
+        match &self.principal {
+            Some(principal) if !self.acts_for(principal, account) && !self.is_admin(principal) => {
+                let e = ApplicationError::Unauthorized(principal.clone());
+                self.publish_failed(operation, &e);
+                Err(e)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails with [`ApplicationError::Unauthorized`] if the principal isn't an admin
+    fn check_admin(&self, operation: &'static str) -> Result<(), ApplicationError> {
+        match &self.principal {
+            Some(principal) if !self.is_admin(principal) => {
+                let e = ApplicationError::Unauthorized(principal.clone());
+                self.publish_failed(operation, &e);
+                Err(e)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails with [`ApplicationError::Locked`] if any of `accounts` is an escrow or dispute
+    /// account, or with [`ApplicationError::Archived`] if any is archived
+    fn check_unlocked(
+        &self,
+        operation: &'static str,
+        accounts: &[&str],
+    ) -> Result<(), ApplicationError> {
+        let locked = |account: &&&str| escrow::is_escrow(account) || dispute::is_dispute(account);
+        let e = if let Some(account) = accounts.iter().find(locked) {
+            ApplicationError::Locked(account.to_string())
+        } else if let Some(account) = accounts.iter().find(|a| self.archived.contains_key(**a)) {
+            ApplicationError::Archived(account.to_string())
+        } else {
+            return Ok(());
+        };
+        self.publish_failed(operation, &e);
+        Err(e)
+    }
+
+    fn commit_deposit(
+        &mut self,
+        operation: &'static str,
+        signer: &str,
+        amount: Units,
+    ) -> Result<Tx, ApplicationError> {
+        match self.credit(signer, amount) {
+            Ok((tx, created)) => {
+                if created {
+                    self.publish_created(signer);
+                }
+                self.stats
+                    .record_deposit(tx.account_name(), amount, self.clock.now());
+                self.publish_committed(&tx);
+                Ok(tx)
+            }
+            Err(e) => {
+                self.publish_failed(operation, &e);
+                Err(e)
+            }
+        }
+    }
+
+    fn commit_withdraw(
+        &mut self,
+        operation: &'static str,
+        signer: &str,
+        amount: Units,
+    ) -> Result<Tx, ApplicationError> {
+        let result = self.debit(signer, amount);
+        match &result {
+            Ok(tx) => {
+                self.stats
+                    .record_withdrawal(tx.account_name(), amount, self.clock.now());
+                self.publish_committed(tx)
+            }
+            Err(e) => self.publish_failed(operation, e),
+        }
+        result
+    }
+
+    fn commit_mint(
+        &mut self,
+        operation: &'static str,
+        signer: &str,
+        amount: Units,
+    ) -> Result<Tx, ApplicationError> {
+        match self.credit(signer, amount) {
+            Ok((deposit, created)) => {
+                if created {
+                    self.publish_created(signer);
+                }
+                let account = deposit.account_name().clone();
+                self.stats.record_mint(&account, amount, self.clock.now());
+                let tx = Tx::Mint { account, amount };
+                self.publish_committed(&tx);
+                Ok(tx)
+            }
+            Err(e) => {
+                self.publish_failed(operation, &e);
+                Err(e)
+            }
+        }
+    }
+
+    fn commit_promo_grant(&mut self, signer: &str, amount: Units, expires: Timestamp) -> Tx {
+        self.promos
+            .get_or_insert_default(signer)
+            .grant(amount, expires);
+        let tx = Tx::PromoGrant {
+            account: signer.into(),
+            amount,
+            expires,
+        };
+        self.publish_committed(&tx);
+        tx
+    }
+
+    fn commit_mandate_grant(&mut self, payer: &str, payee: &str, limit: SpendingLimit) -> Tx {
+        self.mandates
+            .get_or_insert_default(payer)
+            .insert(payee.to_string(), Allowance::new(limit));
+        let tx = Tx::MandateGrant {
+            account: payer.into(),
+            payee: payee.into(),
+            max: limit.max,
+            period: limit.period,
+        };
+        self.publish_committed(&tx);
+        tx
+    }
+
+    fn commit_mandate_revoke(&mut self, payer: &str, payee: &str) -> Tx {
+        if let Some(mandates) = self.mandates.get_mut(payer) {
+            mandates.remove(payee);
+            if mandates.is_empty() {
+                self.mandates.remove(payer);
+            }
+        }
+        let tx = Tx::MandateRevoke {
+            account: payer.into(),
+            payee: payee.into(),
+        };
+        self.publish_committed(&tx);
+        tx
+    }
+
+    /// Turns `amount` of the promotional credit of `signer` expiring at `expires` into
+    /// balance, which issues it like [`Accounts::mint`] does
+    fn commit_promo_spend(
+        &mut self,
+        signer: &str,
+        amount: Units,
+        expires: Timestamp,
+    ) -> Result<Tx, ApplicationError> {
+        if let Err(e) = self.take_promo(signer, amount, expires) {
+            self.publish_failed("spend_promo", &e);
+            return Err(e);
+        }
+        match self.credit(signer, amount) {
+            Ok((deposit, created)) => {
+                if created {
+                    self.publish_created(signer);
+                }
+                let account = deposit.account_name().clone();
+                self.stats.record_mint(&account, amount, self.clock.now());
+                let tx = Tx::PromoSpend {
+                    account,
+                    amount,
+                    expires,
+                };
+                self.publish_committed(&tx);
+                Ok(tx)
+            }
+            Err(e) => {
+                self.promos
+                    .get_or_insert_default(signer)
+                    .grant(amount, expires);
+                self.publish_failed("spend_promo", &e);
+                Err(e)
+            }
+        }
+    }
+
+    fn commit_promo_expire(
+        &mut self,
+        signer: &str,
+        amount: Units,
+        expires: Timestamp,
+    ) -> Result<Tx, ApplicationError> {
+        if let Err(e) = self.take_promo(signer, amount, expires) {
+            self.publish_failed("expire_promos", &e);
+            return Err(e);
+        }
+        let tx = Tx::PromoExpire {
+            account: signer.into(),
+            amount,
+            expires,
+        };
+        self.publish_committed(&tx);
+        Ok(tx)
+    }
+
+    /// Takes `amount` off the promotional credit of `signer` expiring at `expires`
+    fn take_promo(
+        &mut self,
+        signer: &str,
+        amount: Units,
+        expires: Timestamp,
+    ) -> Result<(), ApplicationError> {
+        let credits = self.promos.get_mut(signer);
+        match credits.is_some_and(|credits| credits.take(amount, expires)) {
+            true => Ok(()),
+            false => Err(ApplicationError::UnderFunded(signer.to_string(), amount)),
+        }
+    }
+
+    fn commit_burn(
+        &mut self,
+        operation: &'static str,
+        signer: &str,
+        amount: Units,
+    ) -> Result<Tx, ApplicationError> {
+        match self.debit(signer, amount) {
+            Ok(withdrawal) => {
+                let account = withdrawal.account_name().clone();
+                self.stats.record_burn(&account, amount, self.clock.now());
+                let tx = Tx::Burn { account, amount };
+                self.publish_committed(&tx);
+                Ok(tx)
+            }
+            Err(e) => {
+                self.publish_failed(operation, &e);
+                Err(e)
+            }
+        }
+    }
+
+    pub(crate) fn commit_send(
+        &mut self,
+        operation: &'static str,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx), ApplicationError> {
+        self.commit_send_as(operation, sender, recipient, amount, false)
+    }
+
+    /// [`Accounts::commit_send`], with the withdrawal a [`Tx::Collect`] by `recipient` if
+    /// `collected`
+    fn commit_send_as(
+        &mut self,
+        operation: &'static str,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+        collected: bool,
+    ) -> Result<(Tx, Tx), ApplicationError> {
+        // Events are only published once both sides went through
+        match self.transfer(sender, recipient, amount) {
+            Ok((withdrawal_tx, deposit_tx, created)) => {
+                let withdrawal_tx = match collected {
+                    true => self::collected(withdrawal_tx, recipient),
+                    false => sent(withdrawal_tx, deposit_tx.account_name()),
+                };
+                if created {
+                    self.publish_created(recipient);
+                }
+                self.stats.record_transfer(
+                    withdrawal_tx.account_name(),
+                    deposit_tx.account_name(),
+                    amount,
+                    self.clock.now(),
+                );
+                self.spend_allowance(sender, recipient, amount);
+                if sender == recipient {
+                    // The balance didn't change, so no threshold was crossed either
+                    self.events
+                        .publish(&LedgerEvent::TxCommitted(withdrawal_tx.clone()));
+                    self.events
+                        .publish(&LedgerEvent::TxCommitted(deposit_tx.clone()));
+                } else {
+                    self.publish_committed(&withdrawal_tx);
+                    self.publish_committed(&deposit_tx);
+                }
+                Ok((withdrawal_tx, deposit_tx))
+            }
+            Err(e) => {
+                self.publish_failed(operation, &e);
+                Err(e)
+            }
+        }
+    }
+
+    /// The sending side of a transfer to `recipient` kept by another ledger, e.g. another shard
+    /// of [`crate::sharded::ShardedAccounts`]: what [`Accounts::commit_send`] does to the sender.
+    /// [`Accounts::receive`] is the receiving side.
+    pub(crate) fn send_out(
+        &mut self,
+        operation: &'static str,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<Tx, ApplicationError> {
+        match self.debit(sender, amount) {
+            Ok(tx) => {
+                let tx = sent(tx, &recipient.into());
+                self.stats
+                    .record_sent(tx.account_name(), amount, self.clock.now());
+                self.spend_allowance(sender, recipient, amount);
+                self.publish_committed(&tx);
+                Ok(tx)
+            }
+            Err(e) => {
+                self.publish_failed(operation, &e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Fails like crediting `amount` to `recipient` in [`Accounts::receive`] would, without
+    /// crediting it, for the receiving side of a transfer to check before the sending side
+    /// commits
+    pub(crate) fn check_receive(
+        &self,
+        operation: &'static str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(), ApplicationError> {
+        let e = match self.accounts.get(recipient) {
+            Some(balance) => {
+                let line = self.credit_lines.get(recipient);
+                let repaid = line.map_or(0, |line| line.drawn.min(amount));
+                match balance.checked_add(amount - repaid) {
+                    Some(_) => return Ok(()),
+                    None => ApplicationError::OverFunded(recipient.to_string(), amount),
+                }
+            }
+            None if self.archived.contains_key(recipient) => {
+                ApplicationError::Archived(recipient.to_string())
+            }
+            None => return Ok(()),
+        };
+        self.publish_failed(operation, &e);
+        Err(e)
+    }
+
+    /// The receiving side of [`Accounts::send_out`]
+    pub(crate) fn receive(
+        &mut self,
+        operation: &'static str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<Tx, ApplicationError> {
+        match self.credit(recipient, amount) {
+            Ok((tx, created)) => {
+                if created {
+                    self.publish_created(recipient);
+                }
+                self.stats
+                    .record_received(tx.account_name(), amount, self.clock.now());
+                self.publish_committed(&tx);
+                Ok(tx)
+            }
+            Err(e) => {
+                self.publish_failed(operation, &e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Counts `amount` sent against the spending limit from `sender` to `recipient`, if any
+    fn spend_allowance(&mut self, sender: &str, recipient: &str, amount: Units) {
+        if let Some(allowance) = self
+            .spending_limits
+            .get_mut(sender)
+            .and_then(|allowances| allowances.get_mut(recipient))
+        {
+            allowance.spend(self.clock.now(), amount);
+        }
+    }
+
+    /// Creates all `accounts` with their opening balances and returns the matching deposits,
+    /// so replaying the transactions rebuilds the imported state.
+    ///
+    /// Either all accounts are created or none.
+    /// # Errors
+    /// An account exists already or appears more than once in `accounts`
+    #[instrument(skip_all, err(Display, level = Level::INFO))]
+    pub fn import_accounts(
+        &mut self,
+        accounts: impl IntoIterator<Item = (String, Units)>,
+    ) -> Result<Vec<Tx>, ApplicationError> {
+        self.check_writable("import")?;
+        let accounts = accounts.into_iter();
+        let mut imported: HashMap<Arc<str>, Units> = HashMap::with_capacity(accounts.size_hint().0);
+        let mut txs = Vec::with_capacity(imported.capacity());
+        for (account, amount) in accounts {
+            self.check_unlocked("import", &[&account])?;
+            if self.accounts.contains_key(account.as_str())
+                || imported.contains_key(account.as_str())
+            {
+                let e = ApplicationError::AlreadyExists(account);
+                self.publish_failed("import", &e);
+                return Err(e);
+            }
+            let account: Arc<str> = account.into();
+            imported.insert(account.clone(), amount);
+            txs.push(Tx::Deposit { account, amount });
+        }
+
+        let imported_supply: u128 = imported.values().map(|&b| b as u128).sum();
+        self.supply = self.supply.wrapping_add(imported_supply);
+        self.accounts.extend(imported);
+        // All recorded before anything is published, for the supply to be conserved by then
+        for tx in &txs {
+            self.stats
+                .record_deposit(tx.account_name(), tx.amount(), self.clock.now());
+        }
+        for tx in &txs {
+            self.publish_created(tx.account());
+            self.publish_committed(tx);
+        }
+        Ok(txs)
+    }
+
+    /// Combines the accounts of `other` into this ledger and returns the deposits doing so,
+    /// so replaying this ledger's history followed by them rebuilds the merged state.
+    ///
+    /// Accounts only in `other` are created with their balance; `policy` decides what happens
+    /// to accounts present in both. Either everything is merged or nothing.
+    /// # Errors
+    /// An account is in both ledgers under [`MergePolicy::Error`], or summing balances overflows
+    #[instrument(skip_all, err(Display, level = Level::INFO))]
+    pub fn merge(
+        &mut self,
+        other: &Accounts,
+        policy: MergePolicy,
+    ) -> Result<Vec<Tx>, ApplicationError> {
+        self.check_writable("merge")?;
+        // Sorted, so merging the same ledgers always emits the same transactions
+        let mut incoming: Vec<(&str, Units)> = other.iter().map(|(k, v)| (k, *v)).collect();
+        incoming.sort_unstable();
+        let mut deposits = Vec::with_capacity(incoming.len());
+        for (account, amount) in incoming {
+            self.check_unlocked("merge", &[account])?;
+            let Some(balance) = self.accounts.get(account) else {
+                deposits.push((account, amount));
+                continue;
+            };
+            let conflict = match policy {
+                MergePolicy::Sum => balance
+                    .checked_add(amount)
+                    .map(|_| ())
+                    .ok_or_else(|| ApplicationError::OverFunded(account.to_string(), amount)),
+                MergePolicy::Error => Err(ApplicationError::AlreadyExists(account.to_string())),
+                MergePolicy::PreferLeft => continue,
+            };
+            if let Err(e) = conflict {
+                self.publish_failed("merge", &e);
+                return Err(e);
+            }
+            deposits.push((account, amount));
+        }
+
+        let mut txs = Vec::with_capacity(deposits.len());
+        for (account, amount) in deposits {
+            let (tx, created) = self
+                .credit(account, amount)
+                .expect("overflows were ruled out above");
+            if created {
+                self.publish_created(account);
+            }
+            self.stats
+                .record_deposit(tx.account_name(), amount, self.clock.now());
+            self.publish_committed(&tx);
+            txs.push(tx);
+        }
+        Ok(txs)
+    }
+
+    /// Adds `amount` to the `signer` account, creating it if needed, and reports whether it was created.
+    fn credit(&mut self, signer: &str, amount: Units) -> Result<(Tx, bool), ApplicationError> {
+        // Existing accounts are looked up by `&str`; only a new account needs an owned key,
+        // which `HashMap::entry` would require for every call
+        let (account, created) = match self.accounts.get_key_value_mut(signer) {
+            Some((account, balance)) => {
+                let line = self.credit_lines.get_mut(signer);
+                let repaid = line.as_ref().map_or(0, |line| line.drawn.min(amount));
+                *balance = balance
+                    .checked_add(amount - repaid)
+                    .ok_or_else(|| ApplicationError::OverFunded(signer.to_string(), amount))?;
+                if let Some(line) = line {
+                    line.drawn -= repaid;
+                }
+                (account.clone(), false)
+            }
+            None if self.archived.contains_key(signer) => {
+                return Err(ApplicationError::Archived(signer.to_string()))
+            }
+            None => {
+                let account: Arc<str> = signer.into();
+                self.accounts.insert(account.clone(), amount);
+                if let Some(escrow) = Escrow::parse(&account) {
+                    Arc::make_mut(&mut self.escrows).insert(escrow.id, escrow);
+                } else if let Some(dispute) = Dispute::parse(&account) {
+                    Arc::make_mut(&mut self.disputes).insert(dispute.position, dispute);
+                }
+                (account, true)
+            }
+        };
+        self.supply = self.supply.wrapping_add(amount as u128);
+        Ok((Tx::Deposit { account, amount }, created))
+    }
+
+    fn debit(&mut self, signer: &str, amount: Units) -> Result<Tx, ApplicationError> {
+        let Some((account, balance)) = self.accounts.get_key_value_mut(signer) else {
+            return Err(self.missing(signer));
+        };
+        match balance.checked_sub(amount) {
+            Some(rest) => *balance = rest,
+            None => {
+                let overdraft = amount - *balance;
+                let line = self
+                    .credit_lines
+                    .get_mut(signer)
+                    .filter(|line| line.limit.saturating_sub(line.drawn) >= overdraft)
+                    .ok_or_else(|| ApplicationError::UnderFunded(signer.to_string(), amount))?;
+                line.drawn += overdraft;
+                *balance = 0;
+            }
+        }
+        self.supply = self.supply.wrapping_sub(amount as u128);
+        Ok(Tx::Withdraw {
+            account: account.clone(),
+            amount,
+            recipient: None,
+        })
+    }
+
+    /// Moves `amount` from `sender` to `recipient` and reports whether the recipient was created.
+    fn transfer(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx, bool), ApplicationError> {
+        #[cfg(feature = "self-check")]
+        let before = self.pair_balances(sender, recipient);
+        let result = self.transfer_unchecked(sender, recipient, amount);
+        #[cfg(feature = "self-check")]
+        {
+            let (sender_after, recipient_after) = self.pair_balances(sender, recipient);
+            let (sender_before, recipient_before) = before;
+            assert_eq!(
+                sender_before + recipient_before,
+                sender_after + recipient_after,
+                "sending {} from {} to {} changed their total",
+                amount,
+                sender,
+                recipient
+            );
+            if result.is_err() || sender == recipient {
+                assert_eq!(
+                    before,
+                    (sender_after, recipient_after),
+                    "sending {} from {} to {} wasn't rolled back",
+                    amount,
+                    sender,
+                    recipient
+                );
+            } else {
+                assert_eq!(
+                    sender_before - amount as i128,
+                    sender_after,
+                    "sending {} from {} to {} took something else",
+                    amount,
+                    sender,
+                    recipient
+                );
+            }
+        }
+        result
+    }
+
+    /// The signed balances of `sender` and `recipient`, 0 for accounts that don't exist, for
+    /// the self-check of [`Accounts::transfer`]
+    #[cfg(feature = "self-check")]
+    fn pair_balances(&self, sender: &str, recipient: &str) -> (i128, i128) {
+        let balance = |account| self.signed_balance_of(account).unwrap_or(0);
+        (balance(sender), balance(recipient))
+    }
+
+    /// [`Accounts::transfer`] without the self-check
+    fn transfer_unchecked(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx, bool), ApplicationError> {
+        let withdrawal_tx = self.debit(sender, amount)?;
+        match self.credit(recipient, amount) {
+            Ok((deposit_tx, created)) => Ok((withdrawal_tx, deposit_tx, created)),
+            Err(e) => {
+                // If the deposit fails due to OverFunded error, restore the sender's balance.
+                // Crediting pays back drawn credit first, which exactly undoes the debit, and
+                // the debit just succeeded, so adding the amount back can't overflow
+                self.credit(sender, amount)
+                    .expect("undoing a debit can't overflow");
+                Err(e)
+            }
+        }
+    }
+
+    /// Publishes `tx`, which must be the last change to its account, any threshold it crossed
+    /// and any savings goal it reached
+    fn publish_committed(&self, tx: &Tx) {
+        #[cfg(debug_assertions)]
+        if let Err(violation) = self.check_conservation() {
+            tracing::error!(?tx, %violation, "money isn't conserved");
+        }
+        self.events.publish(&LedgerEvent::TxCommitted(tx.clone()));
+        let after = self.signed_balance_of(tx.account()).unwrap_or(0);
+        let before = after - tx.balance_change();
+        let goals = self.metadata.get(tx.account()).map(|m| &m.goals);
+        for (name, goal) in goals.into_iter().flatten() {
+            if goal.reached_by(before, after) {
+                self.events.publish(&LedgerEvent::GoalReached {
+                    account: tx.account().to_string(),
+                    goal: name.clone(),
+                    target: goal.target,
+                });
+            }
+        }
+        let Some(thresholds) = self.thresholds.get(tx.account()) else {
+            return;
+        };
+        let crossed = match (thresholds.low, thresholds.high) {
+            (Some(low), _) if before >= low as i128 && after < low as i128 => {
+                Some(Threshold::Low(low))
+            }
+            (_, Some(high)) if before <= high as i128 && after > high as i128 => {
+                Some(Threshold::High(high))
+            }
+            _ => None,
+        };
+        if let Some(threshold) = crossed {
+            self.events.publish(&LedgerEvent::ThresholdCrossed {
+                account: tx.account().to_string(),
+                threshold,
+                balance: after,
+            });
+        }
+    }
+
+    fn publish_created(&self, account: &str) {
+        self.events.publish(&LedgerEvent::AccountCreated {
+            account: account.to_string(),
+        });
+    }
+
+    fn publish_ownership(&self, account: &str, change: OwnershipChange) {
+        self.events.publish(&LedgerEvent::OwnershipChanged {
+            account: account.to_string(),
+            change,
+            by: self.principal.clone(),
+        });
+    }
+
+    fn publish_failed(&self, operation: &'static str, error: &ApplicationError) {
+        self.events.publish(&LedgerEvent::TxFailed {
+            operation,
+            error: error.clone(),
+        });
+    }
+}
+
+/// `withdrawal` as the withdrawal side of a transfer to `recipient`
+fn sent(withdrawal: Tx, recipient: &Arc<str>) -> Tx {
+    match withdrawal {
+        Tx::Withdraw {
+            account, amount, ..
+        } => Tx::Withdraw {
+            account,
+            amount,
+            recipient: Some(recipient.clone()),
+        },
+        tx => tx,
+    }
+}
+
+/// `withdrawal` as the withdrawal side of a collection by `payee`
+fn collected(withdrawal: Tx, payee: &str) -> Tx {
+    match withdrawal {
+        Tx::Withdraw {
+            account, amount, ..
+        } => Tx::Collect {
+            account,
+            payee: payee.into(),
+            amount,
+        },
+        tx => tx,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Accounts;
+    use super::*;
+    use crate::clock::{ManualClock, Timestamp};
+    use crate::fees::FeeSchedules;
+    use crate::rounding::RoundingPolicy;
+    use std::time::Duration;
+
+    #[test]
+    fn test_withdraw_underfunded() {
+        //arrange
+        let mut ledger = Accounts::new();
+        let signer = "test_account";
+        ledger.accounts.insert(signer.into(), 50); // Insert a test account with balance 50
+
+        //act
+        match ledger.withdraw(signer, 100) {
+            Ok(_) => panic!("Expected UnderFunded error, but got Ok(_)"),
+            Err(e) => match e {
+                ApplicationError::UnderFunded(account, amount) => {
+                    assert_eq!(account, signer);
+                    assert_eq!(amount, 100);
+                }
+                _ => panic!("Expected UnderFunded error, but got a different error"),
+            },
+        }
+    }
+    #[test]
+    fn test_accounts_deposit_overfunded() {
+        //arrange
+        let mut ledger = Accounts::new();
+        let signer = "test_account";
+        ledger.accounts.insert(signer.into(), 50); // Insert a test account with balance 50
+
+        //act
+        match ledger.deposit(signer, Units::MAX) {
+            Ok(_) => panic!("Expected OverFunded error, but got Ok(_)"),
+            Err(e) => match e {
+                ApplicationError::OverFunded(account, amount) => {
+                    assert_eq!(account, signer);
+                    assert_eq!(amount, Units::MAX);
+                }
+                _ => panic!("Expected UnderFunded error, but got a different error"),
+            },
+        }
+    }
+
+    #[test]
+    fn test_accounts_deposit_works() {
+        //arrange
+        let mut ledger = Accounts::new();
+        let signer = "test_account";
+        ledger.accounts.insert(signer.into(), 0);
+
+        //act
+        match ledger.deposit(signer, 100) {
+            Ok(_) => assert_eq!(*ledger.accounts.get("test_account").unwrap(), 100),
+            Err(e) => panic!("Expected deposit to work but got error{:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_accounts_withdraw_works() {
+        //arrange
+        let mut ledger = Accounts::new();
+        let signer = "test_account";
+        ledger.accounts.insert(signer.into(), 100);
+
+        //act
+        match ledger.withdraw(signer, 100) {
+            Ok(_) => assert_eq!(*ledger.accounts.get("test_account").unwrap(), 0),
+            Err(e) => panic!("Expected deposit to work but got error{:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_accounts_send_works() {
+        let mut ledger = Accounts::new();
+        let sender = "test_account";
+        let receiver = "test_account2";
+        ledger.accounts.insert(sender.into(), 100);
+        ledger.accounts.insert(receiver.into(), 0);
+
+        //act
+        match ledger.send(sender, receiver, 100) {
+            Ok(_) => assert_eq!(*ledger.accounts.get("test_account2").unwrap(), 100),
+            Err(e) => panic!("Expected deposit to work but got error{:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_accounts_send_underfunded_fails_and_rolls_back() {
+        let mut ledger = Accounts::new();
+        let sender = "test_account";
+        let receiver = "test_account2";
+        ledger.accounts.insert(sender.into(), 10);
+        ledger.accounts.insert(receiver.into(), 0);
+
+        //act
+        match ledger.send(sender, receiver, 100) {
+            Ok(tx) => panic!("Expected send to fail but but succeeded. Tx:{:?}", tx),
+            Err(e) => match e {
+                ApplicationError::UnderFunded(sender, 100) => {
+                    assert_eq!(*ledger.accounts.get(sender.as_str()).unwrap(), 10)
+                }
+                _ => panic!("Expected UnderFunded error, but got a different error"),
+            },
+        };
+    }
+
+    #[test]
+    fn test_accounts_send_overfunded_fails_and_rolls_back() {
+        let mut ledger = Accounts::new();
+        let sender = "test_account";
+        let receiver = "test_account2";
+        ledger.accounts.insert(sender.into(), Units::MAX);
+        ledger.accounts.insert(receiver.into(), 10);
+
+        //act
+        match ledger.send(sender, receiver, Units::MAX) {
+            Ok(tx) => panic!("Expected send to fail but but succeeded. Tx:{:?}", tx),
+            Err(e) => match e {
+                ApplicationError::OverFunded(sender, Units::MAX) => {
+                    assert_eq!(*ledger.accounts.get(sender.as_str()).unwrap(), 10)
+                }
+                _ => panic!("Expected OverFunded error, but got a different error"),
+            },
+        };
+    }
+
+    #[test]
+    fn test_accounts_send_publishes_events_only_on_success() {
+        let mut ledger = Accounts::new();
+        let events = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let seen = events.clone();
+        ledger.subscribe(move |event| seen.lock().unwrap().push(event.clone()));
+        ledger.accounts.insert("test_account".into(), 100);
+        ledger.accounts.insert("test_account2".into(), Units::MAX);
+
+        //act
+        assert!(ledger.send("test_account", "test_account2", 10).is_err());
+        assert!(ledger.send("test_account", "test_account3", 10).is_ok());
+
+        let events = events.lock().unwrap();
+        assert_eq!(
+            *events,
+            vec![
+                LedgerEvent::TxFailed {
+                    operation: "send",
+                    error: ApplicationError::OverFunded("test_account2".to_string(), 10)
+                },
+                LedgerEvent::AccountCreated {
+                    account: "test_account3".to_string()
+                },
+                LedgerEvent::TxCommitted(Tx::Withdraw {
+                    account: "test_account".into(),
+                    amount: 10,
+                    recipient: Some("test_account3".into())
+                }),
+                LedgerEvent::TxCommitted(Tx::Deposit {
+                    account: "test_account3".into(),
+                    amount: 10
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_accounts_stats_count_committed_txs() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("ALICE", 100).unwrap();
+        ledger.send("ALICE", "BOB", 40).unwrap();
+        ledger.withdraw("BOB", 5).unwrap();
+
+        //act
+        assert!(ledger.withdraw("BOB", 500).is_err());
+        assert!(ledger.send("BOB", "ALICE", 500).is_err());
+
+        let stats = ledger.stats();
+        let alice = stats.account("ALICE").unwrap();
+        assert_eq!((alice.deposited, alice.sent, alice.txs), (100, 40, 2));
+        let bob = stats.account("BOB").unwrap();
+        assert_eq!((bob.received, bob.withdrawn, bob.txs), (40, 5, 2));
+        assert_eq!(stats.totals().txs, 4);
+    }
+
+    #[test]
+    fn test_accounts_thresholds_publish_crossings() {
+        let mut ledger = Accounts::new();
+        let crossings = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let seen = crossings.clone();
+        ledger.subscribe(move |event| {
+            if let LedgerEvent::ThresholdCrossed {
+                threshold, balance, ..
+            } = event
+            {
+                seen.lock().unwrap().push((*threshold, *balance));
+            }
+        });
+        ledger.set_thresholds(
+            "ALICE",
+            Thresholds {
+                low: Some(50),
+                high: Some(1000),
+            },
+        );
+
+        //act
+        ledger.deposit("ALICE", 100).unwrap();
+        ledger.withdraw("ALICE", 60).unwrap();
+        ledger.withdraw("ALICE", 10).unwrap();
+        ledger.send("ALICE", "ALICE", 30).unwrap();
+        ledger.deposit("ALICE", 2000).unwrap();
+
+        assert_eq!(
+            *crossings.lock().unwrap(),
+            vec![(Threshold::Low(50), 40), (Threshold::High(1000), 2030)]
+        );
+    }
+
+    #[test]
+    fn test_accounts_publish_reached_goals() {
+        let mut ledger = Accounts::new();
+        let reached = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let seen = reached.clone();
+        ledger.subscribe(move |event| {
+            if let LedgerEvent::GoalReached { account, goal, .. } = event {
+                seen.lock().unwrap().push(format!("{} {}", account, goal));
+            }
+        });
+        let goals = &mut ledger.metadata_mut().entry("ALICE:savings").goals;
+        for (name, target) in [("bike", 100), ("car", 500)] {
+            let goal = crate::goals::Goal {
+                target,
+                deadline: None,
+            };
+            goals.insert(name.to_string(), goal);
+        }
+
+        //act
+        ledger.deposit("ALICE:savings", 60).unwrap();
+        ledger.deposit("ALICE:savings", 40).unwrap();
+        ledger.deposit("ALICE:savings", 10).unwrap();
+        ledger.withdraw("ALICE:savings", 20).unwrap();
+        ledger.deposit("ALICE:savings", 410).unwrap();
+
+        assert_eq!(
+            *reached.lock().unwrap(),
+            vec![
+                "ALICE:savings bike",
+                "ALICE:savings bike",
+                "ALICE:savings car"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_accounts_collect_stays_within_the_mandate() {
+        let mut ledger = Accounts::new();
+        let clock = Arc::new(ManualClock::new(Timestamp(0)));
+        ledger.set_clock(clock.clone());
+        let deposit = ledger.deposit("ALICE", 1_000).unwrap();
+        let without_mandate = ledger.collect("GYM", "ALICE", 10);
+        let mandate = ledger
+            .grant_mandate("ALICE", "GYM", "100/day".parse().unwrap())
+            .unwrap();
+
+        //act
+        let collected = ledger.collect("GYM", "ALICE", 60).unwrap();
+        let (pulled, paid) = (&collected[0], &collected[1]);
+        let mut replayed = Accounts::new();
+        replayed.set_clock(Arc::new(ManualClock::new(Timestamp(0))));
+        for tx in [&deposit, &mandate, pulled, paid] {
+            replayed.apply(tx).unwrap();
+            replayed.backdate(Timestamp(0));
+        }
+        let beyond = ledger.collect("GYM", "ALICE", 50);
+        clock.advance(Duration::from_secs(86_400));
+        ledger.collect("GYM", "ALICE", 100).unwrap();
+        let revoked = ledger.revoke_mandate("ALICE", "GYM").unwrap();
+        let revoked_again = ledger.revoke_mandate("ALICE", "GYM").unwrap();
+        let after_revoking = ledger.collect("GYM", "ALICE", 1);
+
+        assert_eq!(
+            without_mandate,
+            Err(ApplicationError::Unauthorized("GYM".to_string()))
+        );
+        assert_eq!(
+            beyond,
+            Err(ApplicationError::LimitExceeded("GYM".to_string(), 40))
+        );
+        assert_eq!(*ledger.balance_of("GYM").unwrap(), 160);
+        assert_eq!(pulled.kind(), "collect");
+        assert_eq!(pulled.payee(), Some("GYM"));
+        assert_eq!(replayed.remaining_mandate("ALICE", "GYM"), Some(40));
+        assert_eq!(*replayed.balance_of("ALICE").unwrap(), 940);
+        assert!(revoked.is_some() && revoked_again.is_none());
+        assert_eq!(
+            after_revoking,
+            Err(ApplicationError::Unauthorized("GYM".to_string()))
+        );
+        assert!(ledger.mandates().is_empty());
+    }
+
+    #[test]
+    fn test_accounts_credit_line_allows_overdrafts() {
+        let mut ledger = Accounts::new();
+        ledger.set_credit_limit("ALICE", 100);
+        ledger.deposit("ALICE", 30).unwrap();
+        ledger.deposit("BOB", Units::MAX).unwrap();
+
+        //act
+        ledger.withdraw("ALICE", 80).unwrap();
+        let overdrawn = ledger.signed_balance_of("ALICE").unwrap();
+        let beyond_limit = ledger.withdraw("ALICE", 51);
+        let rolled_back = ledger.send("ALICE", "BOB", 10);
+        ledger.deposit("ALICE", 70).unwrap();
+
+        assert_eq!(overdrawn, -50);
+        assert_eq!(
+            beyond_limit,
+            Err(ApplicationError::UnderFunded("ALICE".to_string(), 51))
+        );
+        assert!(rolled_back.is_err());
+        assert_eq!(ledger.signed_balance_of("ALICE"), Ok(20));
+        assert_eq!(ledger.balance_of("ALICE"), Ok(&20));
+        assert_eq!(ledger.credit_line("ALICE").unwrap().drawn, 0);
+        assert_eq!(ledger.check_invariants(), Ok(()));
+        assert!(ledger.withdraw("BOB", 1).is_ok());
+    }
+
+    #[test]
+    fn test_accounts_escrow_locks_funds_until_settled() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("ALICE", 100).unwrap();
+
+        //act
+        let (escrow, _) = ledger.hold_in_escrow("ALICE", "BOB", 40).unwrap();
+        let (refunded, _) = ledger.hold_in_escrow("ALICE", "CAROL", 10).unwrap();
+        let locked = ledger.withdraw(&escrow.account, 40);
+        ledger.release_escrow(escrow.id).unwrap();
+        ledger.refund_escrow(refunded.id).unwrap();
+
+        assert_eq!(
+            locked,
+            Err(ApplicationError::Locked(escrow.account.to_string()))
+        );
+        assert_eq!(ledger.balance_of("ALICE"), Ok(&60));
+        assert_eq!(ledger.balance_of("BOB"), Ok(&40));
+        assert_eq!(ledger.escrows().count(), 0);
+        assert!(ledger.release_escrow(escrow.id).is_err());
+        assert!(ledger.send("ALICE", &escrow.account, 1).is_err());
+    }
+
+    #[test]
+    fn test_accounts_escrow_is_released_by_the_payer_and_refunded_by_the_payee() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("ALICE", 100).unwrap();
+        ledger.set_admins(["ROOT".to_string()]);
+        let (released, _) = ledger.hold_in_escrow("ALICE", "BOB", 40).unwrap();
+        let (refunded, _) = ledger.hold_in_escrow("ALICE", "BOB", 10).unwrap();
+        let (settled, _) = ledger.hold_in_escrow("ALICE", "BOB", 5).unwrap();
+        let mut as_user = |user: &str, settle: fn(&mut Accounts, u64) -> _, id| {
+            ledger.set_principal(Some(user.to_string()));
+            settle(&mut ledger, id)
+        };
+
+        //act
+        let released_by_payee = as_user("BOB", Accounts::release_escrow, released.id);
+        let refunded_by_payer = as_user("ALICE", Accounts::refund_escrow, refunded.id);
+        let released_by_stranger = as_user("EVE", Accounts::release_escrow, released.id);
+        as_user("ALICE", Accounts::release_escrow, released.id).unwrap();
+        as_user("BOB", Accounts::refund_escrow, refunded.id).unwrap();
+        as_user("ROOT", Accounts::refund_escrow, settled.id).unwrap();
+
+        let unauthorized = |user: &str| Err(ApplicationError::Unauthorized(user.to_string()));
+        assert_eq!(released_by_payee, unauthorized("BOB"));
+        assert_eq!(refunded_by_payer, unauthorized("ALICE"));
+        assert_eq!(released_by_stranger, unauthorized("EVE"));
+        assert_eq!(ledger.balance_of("ALICE"), Ok(&60));
+        assert_eq!(ledger.balance_of("BOB"), Ok(&40));
+        assert_eq!(ledger.escrows().count(), 0);
     }
-}
 
-impl std::error::Error for ApplicationError {}
+    #[test]
+    fn test_accounts_multisig_holds_large_sends_for_approval() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("ALICE", 5000).unwrap();
+        ledger.set_multisig("ALICE", "2 of BOB,CAROL,DAVE above 1000".parse().unwrap());
 
-/// A type for managing accounts and their current currency balance
-#[derive(Debug)]
-pub struct Accounts {
-    accounts: HashMap<String, u64>,
-}
+        let approve_as = |ledger: &mut Accounts, signer: Option<&str>| {
+            ledger.set_principal(signer.map(str::to_string));
+            ledger.approve(1)
+        };
 
-impl Accounts {
-    /// Returns an empty instance of the [`Accounts`] type
-    pub fn new() -> Self {
-        Accounts {
-            accounts: Default::default(),
+        //act
+        let small = ledger.send("ALICE", "EVE", 1000);
+        let large = ledger.send("ALICE", "EVE", 3000);
+        let anonymous = approve_as(&mut ledger, None);
+        let stranger = approve_as(&mut ledger, Some("EVE"));
+        let first = approve_as(&mut ledger, Some("BOB")).unwrap();
+        let repeated = approve_as(&mut ledger, Some("BOB")).unwrap();
+        let second = approve_as(&mut ledger, Some("CAROL")).unwrap();
+
+        assert!(small.is_ok());
+        assert_eq!(large, Err(ApplicationError::ApprovalRequired(1)));
+        assert_eq!(
+            anonymous,
+            Err(ApplicationError::Unauthorized("anonymous".to_string()))
+        );
+        assert_eq!(
+            stranger,
+            Err(ApplicationError::Unauthorized("EVE".to_string()))
+        );
+        assert_eq!((first, repeated), (None, None));
+        assert!(second.is_some());
+        assert_eq!(ledger.balance_of("ALICE"), Ok(&1000));
+        assert_eq!(ledger.balance_of("EVE"), Ok(&4000));
+        assert!(ledger.pending_transfers().next().is_none());
+        assert!(approve_as(&mut ledger, Some("DAVE")).is_err());
+    }
+
+    #[test]
+    fn test_accounts_spending_limit_caps_sends_per_period() {
+        let clock = Arc::new(ManualClock::new(Timestamp(0)));
+        let mut ledger = Accounts::new();
+        ledger.set_clock(clock.clone());
+        ledger.deposit("ALICE", 2000).unwrap();
+        ledger.set_spending_limit("ALICE", "BOB", "500/day".parse().unwrap());
+
+        //act
+        ledger.send("ALICE", "BOB", 300).unwrap();
+        let exceeded = ledger.send("ALICE", "BOB", 300);
+        let other = ledger.send("ALICE", "CAROL", 300);
+        clock.advance(Duration::from_secs(86_400));
+        let next_day = ledger.send("ALICE", "BOB", 500);
+
+        assert_eq!(
+            exceeded,
+            Err(ApplicationError::LimitExceeded("BOB".to_string(), 200))
+        );
+        assert!(other.is_ok() && next_day.is_ok());
+        assert_eq!(ledger.remaining_allowance("ALICE", "BOB"), Some(0));
+        assert_eq!(ledger.remaining_allowance("ALICE", "CAROL"), None);
+        assert_eq!(ledger.balance_of("BOB"), Ok(&800));
+    }
+
+    #[test]
+    fn test_accounts_send_respects_recipient_lists() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("ALICE", 100).unwrap();
+        ledger.metadata_mut().entry("ALICE").block("BOB");
+
+        //act
+        let blocked = ledger.send("ALICE", "BOB", 10);
+        let open = ledger.send("ALICE", "CAROL", 10);
+        ledger.metadata_mut().entry("ALICE").allow("DAVE");
+        let not_allowed = ledger.send("ALICE", "CAROL", 10);
+
+        assert_eq!(blocked, Err(ApplicationError::Blocked("BOB".to_string())));
+        assert!(open.is_ok());
+        assert_eq!(
+            not_allowed,
+            Err(ApplicationError::Blocked("CAROL".to_string()))
+        );
+        assert!(ledger.send("ALICE", "DAVE", 10).is_ok());
+        assert!(ledger.send("BOB", "ALICE", 0).is_err());
+        assert_eq!(ledger.balance_of("ALICE"), Ok(&80));
+    }
+
+    #[test]
+    fn test_accounts_anomaly_policy_confirms_or_blocks_suspicious_sends() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("ALICE", 100).unwrap();
+        let anomalies = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let seen = anomalies.clone();
+        ledger.subscribe(move |event| {
+            if let LedgerEvent::AnomalyDetected { anomaly, .. } = event {
+                seen.lock().unwrap().push(anomaly.clone());
+            }
+        });
+        ledger.set_anomaly_policy(AnomalyPolicy {
+            velocity: None,
+            drain_percent: Some(90),
+            action: Action::Confirm,
+        });
+
+        //act
+        let unconfirmed = ledger.send("ALICE", "MALLORY", 95);
+        let confirmed = ledger.send_confirmed("ALICE", "MALLORY", 95);
+        ledger.deposit("ALICE", 95).unwrap();
+        let known = ledger.send("ALICE", "MALLORY", 95);
+        ledger.set_anomaly_policy(AnomalyPolicy {
+            action: Action::Block,
+            ..*ledger.anomaly_policy()
+        });
+        let blocked = ledger.send_confirmed("ALICE", "TRENT", 5);
+
+        assert!(matches!(
+            unconfirmed,
+            Err(ApplicationError::ConfirmationRequired(_))
+        ));
+        assert!(confirmed.is_ok() && known.is_ok());
+        assert!(matches!(blocked, Err(ApplicationError::Suspicious(_))));
+        assert_eq!(ledger.balance_of("MALLORY"), Ok(&190));
+        assert_eq!(anomalies.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_accounts_anomaly_policy_knows_replayed_sends() {
+        let mut ledger = Accounts::new();
+        let mut log = vec![ledger.deposit("ALICE", 100).unwrap()];
+        let (withdrawal, deposit) = ledger.send("ALICE", "BOB", 10).unwrap();
+        log.extend([withdrawal, deposit]);
+        let (withdrawal, deposit) = ledger.send("ALICE", "BOB", 10).unwrap();
+        log.extend([withdrawal, deposit]);
+        let mut replayed = Accounts::new();
+        replayed.set_anomaly_policy(AnomalyPolicy {
+            velocity: Some("2/minute".parse().unwrap()),
+            drain_percent: Some(90),
+            action: Action::Block,
+        });
+        let now = replayed.clock.now();
+        for tx in &log {
+            replayed.apply(tx).unwrap();
+            replayed.backdate(now);
         }
+
+        //act
+        let fast = replayed.send("ALICE", "BOB", 75);
+        replayed.set_anomaly_policy(AnomalyPolicy {
+            velocity: None,
+            ..*replayed.anomaly_policy()
+        });
+        let known = replayed.send("ALICE", "BOB", 75);
+
+        assert!(matches!(fast, Err(ApplicationError::Suspicious(e)) if e.contains("3 sends")));
+        assert!(known.is_ok());
+        assert_eq!(replayed.balance_of("BOB"), Ok(&95));
     }
 
-    /// Either deposits the `amount` provided into the `signer` account or adds the amount to the existing account.
-    /// # Errors
-    /// Attempted overflow
-    pub fn deposit(&mut self, signer: &str, amount: u64) -> Result<Tx, ApplicationError> {
-        if let Some(account) = self.accounts.get_mut(signer) {
-            (*account)
-                .checked_add(amount)
-                .map(|r| *account = r)
-                .ok_or(ApplicationError::OverFunded(signer.to_string(), amount))
-                // Using map() here is an easy way to only manipulate the non-error result
-                .map(|_| Tx::Deposit {
-                    account: signer.to_string(),
-                    amount,
-                })
-        } else {
-            self.accounts.insert(signer.to_string(), amount);
-            Ok(Tx::Deposit {
-                account: signer.to_string(),
-                amount,
+    #[test]
+    fn test_accounts_dispute_freezes_until_resolved() {
+        let mut ledger = Accounts::new();
+        let mut log = TxLog::new();
+        let mut commit = |tx: Tx| {
+            log.push(LogEntry {
+                timestamp: Timestamp(0),
+                tx,
+                actor: None,
             })
-        }
+        };
+        commit(ledger.deposit("ALICE", 100).unwrap());
+        commit(ledger.deposit("ALICE", 30).unwrap());
+        commit(ledger.deposit("BOB", 10).unwrap());
+        commit(ledger.deposit("BOB", 5).unwrap());
+        commit(ledger.withdraw("BOB", 5).unwrap());
+        ledger.set_admins(["ROOT".to_string()]);
+
+        //act
+        let (dispute, _) = ledger.open_dispute(&log, 0).unwrap();
+        let frozen = ledger.withdraw("ALICE", 31);
+        let locked = ledger.withdraw(&dispute.holding, 1);
+        ledger.uphold_dispute(0).unwrap();
+        ledger.open_dispute(&log, 1).unwrap();
+        ledger.deny_dispute(1).unwrap();
+        ledger.set_principal(Some("ALICE".to_string()));
+        let not_hers = ledger.open_dispute(&log, 2);
+        let withdrawal = ledger.open_dispute(&log, 4);
+        let missing = ledger.open_dispute(&log, 9);
+        ledger.set_principal(Some("BOB".to_string()));
+        ledger.open_dispute(&log, 2).unwrap();
+        let self_denied = ledger.deny_dispute(2);
+        ledger.set_principal(Some("ROOT".to_string()));
+        ledger.deny_dispute(2).unwrap();
+
+        assert!(matches!(frozen, Err(ApplicationError::UnderFunded(_, _))));
+        assert!(matches!(locked, Err(ApplicationError::Locked(_))));
+        assert_eq!(ledger.balance_of("ALICE"), Ok(&30));
+        assert_eq!(ledger.balance_of("BOB"), Ok(&10));
+        assert_eq!(ledger.disputes().count(), 0);
+        assert!(matches!(
+            ledger.open_dispute(&log, 1),
+            Err(ApplicationError::AlreadyExists(_))
+        ));
+        assert!(ledger.uphold_dispute(0).is_err());
+        let unauthorized = |user: &str| Err(ApplicationError::Unauthorized(user.to_string()));
+        assert_eq!(not_hers.map(|_| ()), unauthorized("ALICE"));
+        assert_eq!(self_denied.map(|_| ()), unauthorized("BOB"));
+        let not_a_deposit =
+            |position| Err(ApplicationError::NotFound(format!("deposit #{}", position)));
+        assert_eq!(withdrawal.map(|_| ()), not_a_deposit(4));
+        assert_eq!(missing.map(|_| ()), not_a_deposit(9));
     }
 
-    /// Withdraws the `amount` from the `signer` account.
-    /// # Errors
-    /// Attempted overflow
-    pub fn withdraw(&mut self, signer: &str, amount: u64) -> Result<Tx, ApplicationError> {
-        if let Some(bal) = self.accounts.get_mut(signer) {
-            (*bal)
-                .checked_sub(amount)
-                .map(|r| *bal = r)
-                .ok_or(ApplicationError::UnderFunded(signer.to_string(), amount))
-                .map(|_| Tx::Withdraw {
-                    account: signer.to_string(),
-                    amount,
-                })
-        } else {
-            Err(ApplicationError::NotFound(signer.to_string()))
-        }
+    #[test]
+    fn test_accounts_archive_takes_accounts_out_of_use() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("ALICE", 100).unwrap();
+        ledger.deposit("BOB", 5).unwrap();
+
+        //act
+        ledger.archive("BOB").unwrap();
+        let deposit = ledger.deposit("BOB", 1);
+        let send = ledger.send("ALICE", "BOB", 1);
+
+        let archived = ApplicationError::Archived("BOB".to_string());
+        assert_eq!(deposit, Err(archived.clone()));
+        assert_eq!(send, Err(archived.clone()));
+        assert_eq!(ledger.balance_of("BOB"), Err(archived.clone()));
+        assert_eq!(ledger.len(), 1);
+        assert_eq!(ledger.archived().collect::<Vec<_>>(), vec![("BOB", &5)]);
+        assert_eq!(ledger.check_invariants(), Ok(()));
+        assert!(ledger.metadata().get("BOB").unwrap().archived);
+
+        let mut replayed = Accounts::new();
+        replayed.deposit("ALICE", 100).unwrap();
+        replayed.deposit("BOB", 5).unwrap();
+        replayed.set_metadata(ledger.metadata().clone());
+        assert_eq!(replayed.balance_of("BOB"), Err(archived));
+
+        ledger.restore("BOB").unwrap();
+        assert_eq!(ledger.balance_of("BOB"), Ok(&5));
+        assert_eq!(
+            ledger.restore("BOB"),
+            Err(ApplicationError::NotFound("BOB".to_string()))
+        );
     }
 
-    /// Withdraws the amount from the sender account and deposits it in the recipient account.
-    ///
-    /// # Errors
-    /// The account doesn't exist
-    pub fn send(
-        &mut self,
-        sender: &str,
-        recipient: &str,
-        amount: u64,
-    ) -> Result<(Tx, Tx), ApplicationError> {
-        let sender_previous_balance = *self
-            .accounts
-            .get(sender)
-            .ok_or(ApplicationError::NotFound(sender.to_string()))?;
-
-        match self.withdraw(sender, amount) {
-            Ok(withdrawal_tx) => match self.deposit(recipient, amount) {
-                Ok(deposit_tx) => Ok((withdrawal_tx, deposit_tx)),
-                Err(ApplicationError::OverFunded(account, amount)) => {
-                    // If the deposit fails due to OverFunded error,
-                    // restore the sender's balance and return the error
-                    *self.accounts.get_mut(sender).unwrap() = sender_previous_balance;
-                    Err(ApplicationError::OverFunded(account, amount))
-                }
-                Err(e) => Err(e),
+    #[test]
+    fn test_accounts_joint_owners_operate_the_account() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("JOINT", 100).unwrap();
+        let events = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let sink = events.clone();
+        ledger.subscribe(move |event| {
+            if let LedgerEvent::OwnershipChanged { change, by, .. } = event {
+                sink.lock().unwrap().push((change.clone(), by.clone()));
+            }
+        });
+
+        //act
+        ledger.set_principal(Some("ALICE".to_string()));
+        let hijack = ledger.add_owner("JOINT", "MALLORY");
+        ledger.add_owner("JOINT", "ALICE").unwrap();
+        ledger.add_owner("JOINT", "BOB").unwrap();
+        ledger.set_principal(Some("BOB".to_string()));
+        let by_bob = ledger.withdraw("JOINT", 10);
+        ledger.set_principal(Some("EVE".to_string()));
+        let by_eve = ledger.send("JOINT", "EVE", 10);
+
+        let eve = ApplicationError::Unauthorized("EVE".to_string());
+        assert_eq!(
+            hijack,
+            Err(ApplicationError::Unauthorized("ALICE".to_string()))
+        );
+        assert!(by_bob.is_ok());
+        assert_eq!(by_eve, Err(eve.clone()));
+        assert_eq!(ledger.remove_owner("JOINT", "BOB"), Err(eve));
+        assert_eq!(
+            ledger.owners("JOINT").collect::<Vec<_>>(),
+            vec!["ALICE", "BOB"]
+        );
+        let alice = Some("ALICE".to_string());
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                (OwnershipChange::Added("ALICE".to_string()), alice.clone()),
+                (OwnershipChange::Added("BOB".to_string()), alice),
+            ]
+        );
+        ledger.set_principal(None);
+        assert!(ledger.withdraw("JOINT", 10).is_ok());
+    }
+
+    #[test]
+    fn test_accounts_read_only_rejects_changes() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("ALICE", 100).unwrap();
+        let (_, (withdrawal, deposit)) = ledger.hold_in_escrow("ALICE", "BOB", 10).unwrap();
+
+        //act
+        ledger.set_read_only(true);
+        let send = ledger.send("ALICE", "BOB", 1);
+        let release = ledger.release_escrow(1);
+        let owner = ledger.add_owner("ALICE", "ALICE");
+
+        let denied = |operation: &str| ApplicationError::PermissionDenied(operation.to_string());
+        assert_eq!(send, Err(denied("send")));
+        assert_eq!(release.map(|_| ()), Err(denied("release")));
+        assert_eq!(owner, Err(denied("add_owner")));
+        assert_eq!(ledger.deposit("ALICE", 1), Err(denied("deposit")));
+        assert_eq!(ledger.balance_of("ALICE"), Ok(&90));
+        let mut replica = Accounts::new();
+        replica.set_read_only(true);
+        for tx in [
+            Tx::Deposit {
+                account: "ALICE".into(),
+                amount: 100,
             },
-            Err(e) => Err(e),
+            withdrawal,
+            deposit,
+        ] {
+            replica.apply(&tx).unwrap();
         }
+        assert_eq!(replica.balance_of("ALICE"), Ok(&90));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::Accounts;
-    use super::*;
+    #[test]
+    fn test_accounts_pay_out_routes_the_remainder() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("POOL", 100).unwrap();
+        ledger.set_rounding(Rounding {
+            policy: RoundingPolicy::HalfUp,
+            remainder_account: Some("ROUNDING".to_string()),
+        });
+        let weights = [("ALICE", 1), ("BOB", 1), ("CAROL", 1)];
+
+        //act
+        let txs = ledger.pay_out("POOL", 100, &weights).unwrap();
+        let remainder = *ledger.balance_of("ROUNDING").unwrap();
+        let halves = ledger.pay_out("ALICE", 5, &[("BOB", 1), ("CAROL", 1)]);
+        let too_much = ledger.pay_out("ALICE", 1_000, &weights);
+
+        assert_eq!(txs.len(), 8);
+        assert_eq!(remainder, 1);
+        assert_eq!(ledger.balance_of("POOL"), Ok(&0));
+        // 2.5 each rounds up to 3, the remainder account covering the extra unit
+        assert!(halves.is_ok());
+        assert_eq!(ledger.balance_of("ALICE"), Ok(&28));
+        assert_eq!(ledger.balance_of("BOB"), Ok(&36));
+        assert_eq!(ledger.balance_of("ROUNDING"), Ok(&0));
+        assert!(too_much.is_err());
+        assert_eq!(ledger.balance_of("ALICE"), Ok(&28));
+        assert_eq!(ledger.supply(), 100);
+    }
 
     #[test]
-    fn test_withdraw_underfunded() {
-        //arrange
+    fn test_accounts_pay_charges_the_fee() {
         let mut ledger = Accounts::new();
-        let signer = "test_account";
-        ledger.accounts.insert(signer.to_string(), 50); // Insert a test account with balance 50
+        ledger.deposit("ALICE", 1_000).unwrap();
+        let mut fees = FeeSchedules::default();
+        fees.insert("send", "1% min 2".parse().unwrap(), "FEES");
+        ledger.set_fees(fees);
 
         //act
-        match ledger.withdraw(signer, 100) {
-            Ok(_) => panic!("Expected UnderFunded error, but got Ok(_)"),
-            Err(e) => match e {
-                ApplicationError::UnderFunded(account, amount) => {
-                    assert_eq!(account, signer);
-                    assert_eq!(amount, 100);
-                }
-                _ => panic!("Expected UnderFunded error, but got a different error"),
-            },
-        }
+        let txs = ledger.pay("ALICE", "BOB", 500).unwrap();
+        let too_much = ledger.pay("ALICE", "BOB", 492);
+        let all_but_the_fee = ledger.pay("ALICE", "BOB", 485).unwrap();
+        let plain = ledger.send("BOB", "ALICE", 10).unwrap();
+
+        assert_eq!(txs.len(), 4);
+        assert_eq!(txs[2].account(), "ALICE");
+        assert_eq!(txs[3].account(), "FEES");
+        assert_eq!(
+            too_much,
+            Err(ApplicationError::UnderFunded("ALICE".to_string(), 496))
+        );
+        assert_eq!(all_but_the_fee.len(), 4);
+        assert_eq!(ledger.balance_of("FEES"), Ok(&(5 + 4)));
+        assert_eq!(ledger.balance_of("ALICE"), Ok(&(6 + 10)));
+        assert_eq!(ledger.balance_of("BOB"), Ok(&(500 + 485 - 10)));
+        assert_eq!(plain.1.amount(), 10);
+        assert_eq!(ledger.supply(), 1_000);
     }
+
     #[test]
-    fn test_accounts_deposit_overfunded() {
-        //arrange
+    fn test_accounts_fees_are_charged_on_approved_collected_and_multi_leg_transfers() {
         let mut ledger = Accounts::new();
-        let signer = "test_account";
-        ledger.accounts.insert(signer.to_string(), 50); // Insert a test account with balance 50
+        ledger.deposit("ALICE", 1_000).unwrap();
+        let mut fees = FeeSchedules::default();
+        fees.insert("send", "2".parse().unwrap(), "FEES");
+        fees.insert("collect", "1".parse().unwrap(), "FEES");
+        ledger.set_fees(fees);
+        ledger.set_multisig("ALICE", "1 of BOB above 500".parse().unwrap());
+        ledger
+            .grant_mandate("ALICE", "GYM", "100/day".parse().unwrap())
+            .unwrap();
 
         //act
-        match ledger.deposit(signer, std::u64::MAX) {
-            Ok(_) => panic!("Expected OverFunded error, but got Ok(_)"),
-            Err(e) => match e {
-                ApplicationError::OverFunded(account, amount) => {
-                    assert_eq!(account, signer);
-                    assert_eq!(amount, 18446744073709551615);
-                }
-                _ => panic!("Expected UnderFunded error, but got a different error"),
-            },
+        let legs = ledger.send_multi(&[("ALICE", "CAROL", 100), ("CAROL", "DAVE", 50)]);
+        let held = ledger.send("ALICE", "EVE", 600);
+        ledger.set_principal(Some("BOB".to_string()));
+        let approved = ledger.approve(1).unwrap().unwrap();
+        ledger.set_principal(None);
+        let collected = ledger.collect("GYM", "ALICE", 40).unwrap();
+        let plain = ledger.send("ALICE", "EVE", 10).unwrap();
+
+        assert_eq!(legs.unwrap().len(), 8);
+        assert_eq!(held, Err(ApplicationError::ApprovalRequired(1)));
+        assert_eq!(approved.len(), 4);
+        assert_eq!(collected.len(), 4);
+        assert_eq!(plain.1.amount(), 10);
+        assert_eq!(ledger.balance_of("FEES"), Ok(&(2 + 2 + 2 + 1)));
+        assert_eq!(ledger.balance_of("CAROL"), Ok(&(100 - 50 - 2)));
+        assert_eq!(
+            ledger.balance_of("ALICE"),
+            Ok(&(1_000 - 102 - 602 - 41 - 10))
+        );
+        assert_eq!(ledger.supply(), 1_000);
+    }
+
+    #[test]
+    fn test_accounts_accrue_interest_by_account_kind() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("ALICE", 20_000).unwrap();
+        ledger.deposit("BOB", 100).unwrap();
+        ledger.deposit("CAROL", 50_000).unwrap();
+        let mut schedules = InterestSchedules::default();
+        schedules.insert("savings", "1%:10000,2%".parse().unwrap());
+        ledger.set_interest(schedules);
+        for account in ["ALICE", "BOB", "DAVE"] {
+            ledger.set_account_kind(account, "savings");
         }
+        ledger.set_account_kind("CAROL", "checking");
+
+        //act
+        let yearly = ledger.accrue_interest(365).unwrap();
+        ledger.set_rounding(Rounding {
+            policy: RoundingPolicy::HalfUp,
+            remainder_account: None,
+        });
+        let daily = ledger.accrue_interest(1).unwrap();
+        ledger.set_admins(["ROOT".to_string()]);
+        ledger.set_principal(Some("BOB".to_string()));
+        let unauthorized = ledger.accrue_interest(365);
+
+        let minted = |txs: &[Tx]| {
+            txs.iter()
+                .map(|tx| (tx.account().to_string(), tx.amount()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            minted(&yearly),
+            vec![("ALICE".to_string(), 100 + 200), ("BOB".to_string(), 1)]
+        );
+        assert_eq!(minted(&daily), vec![("ALICE".to_string(), 1)]);
+        assert_eq!(
+            unauthorized,
+            Err(ApplicationError::Unauthorized("BOB".to_string()))
+        );
+        assert_eq!(ledger.balance_of("ALICE"), Ok(&20_301));
+        assert_eq!(ledger.balance_of("CAROL"), Ok(&50_000));
+        assert!(ledger.balance_of("DAVE").is_err());
+        assert_eq!(ledger.supply(), 70_100 + 302);
     }
 
     #[test]
-    fn test_accounts_deposit_works() {
-        //arrange
+    fn test_accounts_pay_commits_nothing_if_the_fee_fails() {
         let mut ledger = Accounts::new();
-        let signer = "test_account";
-        ledger.accounts.insert(signer.to_string(), 0);
+        ledger.deposit("ALICE", 1_000).unwrap();
+        ledger.deposit("FEES", Units::MAX).unwrap();
+        ledger
+            .grant_promo("ALICE", 30, Timestamp(u64::MAX))
+            .unwrap();
+        let mut fees = FeeSchedules::default();
+        fees.insert("send", "1% min 2".parse().unwrap(), "FEES");
+        ledger.set_fees(fees);
 
         //act
-        match ledger.deposit(signer, 100) {
-            Ok(_) => assert_eq!(*ledger.accounts.get("test_account").unwrap(), 100),
-            Err(e) => panic!("Expected deposit to work but got error{:?}", e),
-        }
+        let result = ledger.pay("ALICE", "BOB", 100);
+
+        assert_eq!(
+            result,
+            Err(ApplicationError::OverFunded("FEES".to_string(), 2))
+        );
+        assert_eq!(ledger.balance_of("ALICE"), Ok(&1_000));
+        assert!(ledger.balance_of("BOB").is_err());
+        assert_eq!(ledger.promo_credits("ALICE").map(|c| c.total()), Some(30));
+        assert_eq!(ledger.check_conservation(), Ok(()));
     }
 
     #[test]
-    fn test_accounts_withdraw_works() {
-        //arrange
+    fn test_accounts_pay_spends_promotional_credit_first() {
+        let clock = Arc::new(ManualClock::new(Timestamp(0)));
         let mut ledger = Accounts::new();
-        let signer = "test_account";
-        ledger.accounts.insert(signer.to_string(), 100);
+        ledger.set_clock(clock.clone());
+        let mut log = vec![ledger.deposit("ALICE", 100).unwrap()];
 
         //act
-        match ledger.withdraw(signer, 100) {
-            Ok(_) => assert_eq!(*ledger.accounts.get("test_account").unwrap(), 0),
-            Err(e) => panic!("Expected deposit to work but got error{:?}", e),
+        log.push(ledger.grant_promo("ALICE", 30, Timestamp(1_000)).unwrap());
+        log.push(ledger.grant_promo("ALICE", 50, Timestamp(2_000)).unwrap());
+        let paid = ledger.pay("ALICE", "BOB", 40).unwrap();
+        log.extend(paid.iter().cloned());
+        clock.set(Timestamp(1_500));
+        let nothing_expired = ledger.expire_promos().unwrap();
+        let too_much = ledger.pay("ALICE", "BOB", 200);
+        clock.set(Timestamp(2_000));
+        let expired = ledger.expire_promos().unwrap();
+        log.extend(expired.iter().cloned());
+        let mut replayed = Accounts::new();
+        for tx in &log {
+            replayed.apply(tx).unwrap();
         }
+
+        assert_eq!(
+            paid[..2],
+            [
+                Tx::PromoSpend {
+                    account: "ALICE".into(),
+                    amount: 30,
+                    expires: Timestamp(1_000)
+                },
+                Tx::PromoSpend {
+                    account: "ALICE".into(),
+                    amount: 10,
+                    expires: Timestamp(2_000)
+                }
+            ]
+        );
+        assert_eq!(paid.len(), 4);
+        assert!(nothing_expired.is_empty());
+        assert_eq!(
+            too_much,
+            Err(ApplicationError::UnderFunded("ALICE".to_string(), 200))
+        );
+        assert_eq!(
+            expired,
+            vec![Tx::PromoExpire {
+                account: "ALICE".into(),
+                amount: 40,
+                expires: Timestamp(2_000)
+            }]
+        );
+        assert_eq!(ledger.balance_of("ALICE"), Ok(&100));
+        assert_eq!(ledger.balance_of("BOB"), Ok(&40));
+        assert_eq!(ledger.promo_credits("ALICE"), None);
+        assert_eq!(ledger.supply(), 140);
+        assert_eq!(ledger.check_invariants(), Ok(()));
+        assert_eq!(replayed.balance_of("ALICE"), Ok(&100));
+        assert_eq!(replayed.promo_credits("ALICE"), None);
+        assert_eq!(replayed.supply(), 140);
     }
 
     #[test]
-    fn test_accounts_send_works() {
+    fn test_accounts_mint_and_burn_are_admin_only() {
         let mut ledger = Accounts::new();
-        let sender = "test_account";
-        let receiver = "test_account2";
-        ledger.accounts.insert(sender.to_string(), 100);
-        ledger.accounts.insert(receiver.to_string(), 0);
+        ledger.set_admins(["ROOT".to_string()]);
 
         //act
-        match ledger.send(sender, receiver, 100) {
-            Ok(_) => assert_eq!(*ledger.accounts.get("test_account2").unwrap(), 100),
-            Err(e) => panic!("Expected deposit to work but got error{:?}", e),
+        ledger.set_principal(Some("ALICE".to_string()));
+        let denied = ledger.mint("ALICE", 100);
+        ledger.set_principal(Some("ROOT".to_string()));
+        let minted = ledger.mint("ALICE", 100).unwrap();
+        let burned = ledger.burn("ALICE", 30).unwrap();
+        let overdrawn = ledger.burn("ALICE", 100);
+        let mut replica = Accounts::new();
+        replica.set_principal(Some("ALICE".to_string()));
+        replica.apply(&minted).unwrap();
+        replica.apply(&burned).unwrap();
+
+        assert_eq!(
+            denied,
+            Err(ApplicationError::Unauthorized("ALICE".to_string()))
+        );
+        assert_eq!(
+            minted,
+            Tx::Mint {
+                account: "ALICE".into(),
+                amount: 100
+            }
+        );
+        assert_eq!(burned.kind(), "burn");
+        assert!(overdrawn.is_err());
+        assert_eq!(ledger.balance_of("ALICE"), Ok(&70));
+        assert_eq!(ledger.supply(), 70);
+        let totals = ledger.stats().totals();
+        assert_eq!(
+            (totals.minted, totals.burned, totals.deposited),
+            (100, 30, 0)
+        );
+        assert_eq!(replica.balance_of("ALICE"), Ok(&70));
+    }
+
+    #[test]
+    fn test_accounts_txs_share_account_names() {
+        let mut ledger = Accounts::new();
+
+        //act
+        let deposit = ledger.deposit("test_account", 100).unwrap();
+        let withdrawal = ledger.withdraw("test_account", 10).unwrap();
+
+        match (deposit, withdrawal) {
+            (Tx::Deposit { account: a, .. }, Tx::Withdraw { account: b, .. }) => {
+                assert!(Arc::ptr_eq(&a, &b))
+            }
+            other => panic!("Expected a deposit and a withdrawal but got {:?}", other),
         }
     }
 
     #[test]
-    fn test_accounts_send_underfunded_fails_and_rolls_back() {
+    fn test_accounts_with_capacity_and_reserve() {
+        let mut ledger = Accounts::with_capacity(100);
+        assert!(ledger.capacity() >= 100);
+
+        //act
+        ledger.reserve(1000);
+
+        assert!(ledger.capacity() >= 1000);
+        assert!(ledger.is_empty());
+    }
+
+    #[test]
+    fn test_accounts_import_accounts_works() {
         let mut ledger = Accounts::new();
-        let sender = "test_account";
-        let receiver = "test_account2";
-        ledger.accounts.insert(sender.to_string(), 10);
-        ledger.accounts.insert(receiver.to_string(), 0);
+        ledger.deposit("test_account", 5).unwrap();
 
         //act
-        match ledger.send(sender, receiver, 100) {
-            Ok(tx) => panic!("Expected send to fail but but succeeded. Tx:{:?}", tx),
-            Err(e) => match e {
-                ApplicationError::UnderFunded(sender, 100) => {
-                    assert_eq!(*ledger.accounts.get(&sender).unwrap(), 10)
+        let txs = ledger
+            .import_accounts([("a".to_string(), 10), ("b".to_string(), 20)])
+            .unwrap();
+
+        assert_eq!(
+            txs,
+            vec![
+                Tx::Deposit {
+                    account: "a".into(),
+                    amount: 10
+                },
+                Tx::Deposit {
+                    account: "b".into(),
+                    amount: 20
                 }
-                _ => panic!("Expected UnderFunded error, but got a different error"),
-            },
-        };
+            ]
+        );
+        assert_eq!(ledger.balance_of("b"), Ok(&20));
+        assert_eq!(ledger.len(), 3);
     }
 
     #[test]
-    fn test_accounts_send_overfunded_fails_and_rolls_back() {
+    fn test_accounts_import_accounts_rejects_duplicates() {
         let mut ledger = Accounts::new();
-        let sender = "test_account";
-        let receiver = "test_account2";
-        ledger.accounts.insert(sender.to_string(), std::u64::MAX);
-        ledger.accounts.insert(receiver.to_string(), 10);
+        ledger.deposit("test_account", 5).unwrap();
 
         //act
-        match ledger.send(sender, receiver, std::u64::MAX) {
-            Ok(tx) => panic!("Expected send to fail but but succeeded. Tx:{:?}", tx),
-            Err(e) => match e {
-                ApplicationError::OverFunded(sender, 18446744073709551615) => {
-                    assert_eq!(*ledger.accounts.get(&sender).unwrap(), 10)
+        let duplicate = ledger.import_accounts([("a".to_string(), 1), ("a".to_string(), 2)]);
+        let existing =
+            ledger.import_accounts([("b".to_string(), 1), ("test_account".to_string(), 2)]);
+
+        assert_eq!(
+            duplicate,
+            Err(ApplicationError::AlreadyExists("a".to_string()))
+        );
+        assert_eq!(
+            existing,
+            Err(ApplicationError::AlreadyExists("test_account".to_string()))
+        );
+        assert_eq!(ledger.len(), 1);
+        assert_eq!(ledger.balance_of("test_account"), Ok(&5));
+    }
+
+    #[test]
+    fn test_accounts_check_invariants_works() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("test_account", 100).unwrap();
+        ledger.send("test_account", "test_account2", 30).unwrap();
+        assert!(ledger.send("test_account", "test_account2", 500).is_err());
+        ledger.withdraw("test_account2", 10).unwrap();
+        ledger
+            .import_accounts([("test_account3".to_string(), 7)])
+            .unwrap();
+        assert_eq!(ledger.check_invariants(), Ok(()));
+
+        //act
+        ledger.accounts.insert("test_account4".into(), 1);
+
+        assert_eq!(
+            ledger.check_invariants(),
+            Err(vec![InvariantViolation::Supply {
+                balances: 98,
+                supply: 97
+            }])
+        );
+    }
+
+    #[test]
+    fn test_accounts_check_conservation_works() {
+        let mut ledger = Accounts::new();
+        ledger.mint("test_account", 100).unwrap();
+        ledger.deposit("test_account2", 50).unwrap();
+        ledger.send("test_account", "test_account2", 30).unwrap();
+        ledger.burn("test_account2", 20).unwrap();
+        ledger.withdraw("test_account", 10).unwrap();
+        let conserved = ledger.check_conservation();
+
+        //act
+        ledger.supply += 5;
+
+        assert_eq!(conserved, Ok(()));
+        let violation = ConservationViolation {
+            supply: 125,
+            issued: 120,
+        };
+        assert_eq!(ledger.check_conservation(), Err(violation));
+        assert_eq!(
+            ledger.check_invariants(),
+            Err(vec![
+                InvariantViolation::Supply {
+                    balances: 120,
+                    supply: 125
+                },
+                InvariantViolation::Conservation(violation)
+            ])
+        );
+        assert_eq!(
+            violation.to_string(),
+            "the supply is 125 but 120 was issued"
+        );
+    }
+
+    #[test]
+    fn test_accounts_merge_works() {
+        let mut other = Accounts::new();
+        other.deposit("test_account", 5).unwrap();
+        other.deposit("test_account2", 7).unwrap();
+        let ledger = || {
+            let mut ledger = Accounts::new();
+            ledger.deposit("test_account", 10).unwrap();
+            ledger
+        };
+
+        //act
+        let mut summed = ledger();
+        let txs = summed.merge(&other, MergePolicy::Sum).unwrap();
+        let mut left = ledger();
+        left.merge(&other, MergePolicy::PreferLeft).unwrap();
+        let mut strict = ledger();
+        let error = strict.merge(&other, MergePolicy::Error);
+
+        assert_eq!(summed.balance_of("test_account"), Ok(&15));
+        assert_eq!(summed.balance_of("test_account2"), Ok(&7));
+        assert_eq!(
+            txs,
+            vec![
+                Tx::Deposit {
+                    account: "test_account".into(),
+                    amount: 5
+                },
+                Tx::Deposit {
+                    account: "test_account2".into(),
+                    amount: 7
                 }
-                _ => panic!("Expected OverFunded error, but got a different error"),
-            },
+            ]
+        );
+        assert_eq!(left.balance_of("test_account"), Ok(&10));
+        assert_eq!(left.balance_of("test_account2"), Ok(&7));
+        assert_eq!(
+            error,
+            Err(ApplicationError::AlreadyExists("test_account".to_string()))
+        );
+        assert!(strict.balance_of("test_account2").is_err());
+    }
+
+    #[test]
+    fn test_accounts_state_at_works() {
+        let mut ledger = Accounts::new();
+        ledger.set_credit_limit("test_account", 5);
+        let mut txs = vec![ledger.deposit("test_account", 10).unwrap()];
+        let (withdrawal, deposit) = ledger.send("test_account", "test_account2", 4).unwrap();
+        txs.extend([withdrawal, deposit]);
+        txs.push(ledger.withdraw("test_account", 9).unwrap());
+        let rules = || {
+            let mut ledger = Accounts::new();
+            ledger.set_credit_limit("test_account", 5);
+            ledger
+        };
+        let entries = || {
+            txs.iter().map(|tx| {
+                Ok(LogEntry {
+                    timestamp: Default::default(),
+                    tx: tx.clone(),
+                    actor: None,
+                })
+            })
+        };
+
+        //act
+        let before_send = rules().state_at(entries(), 1).unwrap();
+        let mid_send = rules().state_at(entries(), 2).unwrap();
+        let overdrawn = rules().state_at(entries(), 4).unwrap();
+
+        assert_eq!(before_send.balance_of("test_account"), Ok(&10));
+        assert!(before_send.balance_of("test_account2").is_err());
+        assert_eq!(mid_send.balance_of("test_account"), Ok(&6));
+        assert_eq!(rules().state_at(entries(), 3).unwrap().len(), 2);
+        assert_eq!(overdrawn.signed_balance_of("test_account"), Ok(-3));
+        assert!(Accounts::new().state_at(entries(), 4).is_err());
+        assert!(rules().state_at(entries(), 5).is_err());
+    }
+
+    #[test]
+    fn test_accounts_simulate_works() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("test_account", 10).unwrap();
+        let send = |amount| Op::Send {
+            sender: "test_account".to_string(),
+            recipient: "test_account2".to_string(),
+            amount,
+        };
+
+        //act
+        let simulated = ledger.simulate(&send(4));
+        let failing = ledger.simulate(&send(11));
+
+        assert_eq!(simulated.error, None);
+        assert_eq!(
+            simulated.balances,
+            BTreeMap::from([
+                ("test_account".to_string(), 6),
+                ("test_account2".to_string(), 4)
+            ])
+        );
+        assert_eq!(simulated.dry_run.txs.len(), 2);
+        assert_eq!(
+            failing.error,
+            Some(ApplicationError::UnderFunded(
+                "test_account".to_string(),
+                11
+            ))
+        );
+        assert_eq!(
+            failing.balances,
+            BTreeMap::from([("test_account".to_string(), 10)])
+        );
+        assert!(failing.dry_run.is_empty());
+        assert_eq!(ledger.balance_of("test_account"), Ok(&10));
+        assert!(ledger.balance_of("test_account2").is_err());
+    }
+
+    #[test]
+    fn test_accounts_simulate_batch_stops_at_the_first_failure() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("payroll", 100).unwrap();
+        let pay = |employee: &str, amount| Op::Send {
+            sender: "payroll".to_string(),
+            recipient: employee.to_string(),
+            amount,
         };
+
+        //act
+        let run = ledger.simulate_batch(&[pay("test_account", 60), pay("test_account2", 30)]);
+        let overdrawn = ledger.simulate_batch(&[
+            pay("test_account", 60),
+            pay("test_account2", 50),
+            pay("test_account3", 10),
+        ]);
+
+        assert!(run.succeeds());
+        assert_eq!(run.dry_run.txs.len(), 4);
+        assert_eq!(
+            run.balances,
+            BTreeMap::from([
+                ("payroll".to_string(), 10),
+                ("test_account".to_string(), 60),
+                ("test_account2".to_string(), 30)
+            ])
+        );
+        assert_eq!(
+            overdrawn.failure,
+            Some((1, ApplicationError::UnderFunded("payroll".to_string(), 50)))
+        );
+        assert_eq!(overdrawn.balances.get("payroll"), Some(&40));
+        assert!(!overdrawn.balances.contains_key("test_account3"));
+        assert_eq!(ledger.len(), 1);
+    }
+
+    #[test]
+    fn test_accounts_send_multi_is_all_or_nothing() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("A", 100).unwrap();
+
+        //act
+        let chain = ledger.send_multi(&[("A", "B", 60), ("B", "C", 60), ("C", "D", 60)]);
+        let split = ledger.send_multi(&[("A", "B", 10), ("A", "C", 20)]);
+        let overdrawn = ledger.send_multi(&[("A", "B", 5), ("A", "C", 6)]);
+
+        assert_eq!(chain.unwrap().len(), 6);
+        assert_eq!(split.unwrap().len(), 4);
+        assert_eq!(
+            overdrawn,
+            Err(ApplicationError::UnderFunded("A".to_string(), 6))
+        );
+        assert_eq!(ledger.balance_of("A"), Ok(&10));
+        assert_eq!(ledger.balance_of("B"), Ok(&10));
+        assert_eq!(ledger.balance_of("C"), Ok(&20));
+        assert_eq!(ledger.balance_of("D"), Ok(&60));
+    }
+
+    #[test]
+    fn test_accounts_send_multi_rolls_back_when_the_second_leg_fails() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("A", 100).unwrap();
+        ledger.set_spending_limit("A", "B", "100/day".parse().unwrap());
+        let published = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let listener = published.clone();
+        ledger.subscribe(move |event| listener.lock().unwrap().push(event.clone()));
+
+        //act
+        let result = ledger.send_multi(&[("A", "B", 60), ("B", "C", 70)]);
+
+        assert_eq!(
+            result,
+            Err(ApplicationError::UnderFunded("B".to_string(), 70))
+        );
+        assert_eq!(ledger.balance_of("A"), Ok(&100));
+        assert!(ledger.balance_of("B").is_err());
+        assert_eq!(ledger.stats().totals().sent, 0);
+        assert_eq!(ledger.remaining_allowance("A", "B"), Some(100));
+        assert!(matches!(
+            published.lock().unwrap().as_slice(),
+            [LedgerEvent::TxFailed { .. }]
+        ));
+        assert_eq!(ledger.check_conservation(), Ok(()));
     }
 }
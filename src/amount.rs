@@ -0,0 +1,331 @@
+//! Parsing and formatting amounts for people, following the decimal and grouping
+//! conventions of a [`Locale`].
+//!
+//! Amounts are integers in the smallest currency unit; `decimals` says how many of their
+//! digits are shown after the decimal separator. A ledger's currency has as many decimal
+//! places as `decimals = <n>` in the config file says, see [`decimals_of`]; they apply to
+//! amounts people enter and read, while those in the config file, like credit limits and
+//! interest tiers, stay in the smallest unit.
+//!
+//! People may write the ledger's currency, `currency = <code>` in the config file, after or
+//! before the number, like `12.34 USD`, see [`Amount`]. The CLI, the statement importers and
+//! the JSON-RPC parameters all convert through here, so none of them scales amounts by hand.
+
+use crate::config::Config;
+use crate::i18n::{self, Locale};
+use crate::tx::Units;
+use serde::de::{self, Deserializer, Visitor};
+use std::fmt;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::RwLock;
+
+/// The most decimal places a currency may have
+pub const MAX_DECIMALS: u32 = 18;
+
+static DECIMALS: AtomicU32 = AtomicU32::new(0);
+
+static CURRENCY: RwLock<Option<String>> = RwLock::new(None);
+
+/// The decimal places of the ledger's currency, `decimals = <n>` in the config file: 0 for
+/// points, 2 for dollars, 8 for sats-like units. `None` if it isn't set.
+/// # Errors
+/// The setting isn't a number up to [`MAX_DECIMALS`]
+pub fn decimals_of(config: &Config) -> Result<Option<u32>, String> {
+    let Some(decimals) = config.get("decimals") else {
+        return Ok(None);
+    };
+    match decimals.parse() {
+        Ok(decimals) if decimals <= MAX_DECIMALS => Ok(Some(decimals)),
+        _ => Err(format!(
+            "decimals: expected 0 to {}, got {:?}",
+            MAX_DECIMALS, decimals
+        )),
+    }
+}
+
+/// Selects how many decimal places amounts are entered and shown with from now on
+pub fn set_decimals(decimals: u32) {
+    DECIMALS.store(decimals, Ordering::Relaxed);
+}
+
+/// The currently selected decimal places, 0 unless [`set_decimals`] says otherwise
+pub fn decimals() -> u32 {
+    DECIMALS.load(Ordering::Relaxed)
+}
+
+/// Selects the code of the ledger's currency, which amounts may be written with from now on
+pub fn set_currency(currency: Option<&str>) {
+    *CURRENCY.write().unwrap_or_else(|e| e.into_inner()) = currency.map(str::to_uppercase);
+}
+
+/// The currently selected currency code, `None` unless [`set_currency`] says otherwise
+pub fn currency() -> Option<String> {
+    CURRENCY.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// The separators used for writing numbers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberFormat {
+    pub decimal: char,
+    pub group: char,
+}
+
+impl NumberFormat {
+    /// `1,234.56`
+    pub const POINT: NumberFormat = NumberFormat {
+        decimal: '.',
+        group: ',',
+    };
+    /// `1.234,56`
+    pub const COMMA: NumberFormat = NumberFormat {
+        decimal: ',',
+        group: '.',
+    };
+
+    /// The conventions of `locale`
+    pub fn of(locale: Locale) -> Self {
+        match locale {
+            Locale::En => NumberFormat::POINT,
+            Locale::Es | Locale::De => NumberFormat::COMMA,
+        }
+    }
+
+    /// The conventions of the current [`i18n::locale`]
+    pub fn current() -> Self {
+        NumberFormat::of(i18n::locale())
+    }
+}
+
+/// Parses `s` into the smallest unit given `decimals` digits after the decimal separator,
+/// e.g. `-1,234.56` into -123456 for 2 decimals with [`NumberFormat::POINT`].
+/// Group separators, spaces and apostrophes are ignored.
+pub fn parse(s: &str, decimals: u32, format: NumberFormat) -> Result<i128, String> {
+    let cleaned: String = s
+        .trim()
+        .chars()
+        .filter(|&c| c != format.group && !matches!(c, '\'' | ' ' | '\u{a0}'))
+        .collect();
+    let (negative, digits) = match cleaned.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, cleaned.strip_prefix('+').unwrap_or(&cleaned)),
+    };
+    let (whole, fraction) = digits.split_once(format.decimal).unwrap_or((digits, ""));
+    let valid = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+    if whole.is_empty() && fraction.is_empty() || !valid(whole) || !valid(fraction) {
+        return Err(format!("invalid amount {}", s));
+    }
+    if fraction.len() > decimals as usize {
+        return Err(format!("amount {} has more than {} decimals", s, decimals));
+    }
+    let padded = format!("{}{:0<width$}", whole, fraction, width = decimals as usize);
+    let amount: i128 = padded
+        .parse()
+        .map_err(|_| format!("amount {} is too large", s))?;
+    Ok(if negative { -amount } else { amount })
+}
+
+/// Parses a non-negative amount as entered by a user, see [`parse`]
+pub fn parse_unsigned(s: &str, decimals: u32, format: NumberFormat) -> Result<Units, String> {
+    let amount = parse(s, decimals, format)?;
+    Units::try_from(amount).map_err(|_| format!("invalid amount {}", s))
+}
+
+/// Parses an amount entered by a user with the current [`decimals`] and
+/// [`NumberFormat::current`], written with the [`currency`] or without a currency, see
+/// [`Amount::parse`]
+pub fn parse_entered(s: &str) -> Result<Units, String> {
+    let amount = Amount::parse(s, decimals(), NumberFormat::current())?;
+    Units::try_from(amount.minor_in(currency().as_deref())?)
+        .map_err(|_| format!("invalid amount {}", s))
+}
+
+/// Formats `amount` with the current [`decimals`] and [`NumberFormat::current`]
+pub fn format_current(amount: i128) -> String {
+    format(amount, decimals(), NumberFormat::current())
+}
+
+/// Formats `amount` with `decimals` digits after the decimal separator and grouped thousands
+pub fn format(amount: i128, decimals: u32, format: NumberFormat) -> String {
+    join(amount, decimals, format.decimal, Some(format.group))
+}
+
+/// Formats `amount` with `decimals` digits after a `.` and no grouping, the way files meant
+/// for other programs write numbers
+pub fn format_plain(amount: i128, decimals: u32) -> String {
+    join(amount, decimals, '.', None)
+}
+
+fn join(amount: i128, decimals: u32, decimal: char, group: Option<char>) -> String {
+    let digits = amount.unsigned_abs().to_string();
+    let digits = format!("{:0>width$}", digits, width = decimals as usize + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals as usize);
+
+    let mut grouped = String::new();
+    for (i, c) in whole.chars().enumerate() {
+        if let Some(group) = group.filter(|_| i > 0 && (whole.len() - i) % 3 == 0) {
+            grouped.push(group);
+        }
+        grouped.push(c);
+    }
+    let sign = if amount < 0 { "-" } else { "" };
+    if fraction.is_empty() {
+        format!("{}{}", sign, grouped)
+    } else {
+        format!("{}{}{}{}", sign, grouped, decimal, fraction)
+    }
+}
+
+/// An amount as people write it, like `12.34 USD`: in the smallest unit, with the currency
+/// code it was written with, if any
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Amount {
+    pub minor: i128,
+    pub currency: Option<String>,
+}
+
+impl Amount {
+    /// Parses a number, see [`parse`], optionally followed or preceded by a currency code of
+    /// ASCII letters and digits after a space, like `12.34 USD` or `USD 12.34`. The code is
+    /// kept in upper case.
+    pub fn parse(s: &str, decimals: u32, format: NumberFormat) -> Result<Self, String> {
+        let is_code = |word: &str| {
+            word.starts_with(|c: char| c.is_ascii_alphabetic())
+                && word.chars().all(|c| c.is_ascii_alphanumeric())
+        };
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let (number, currency) = match words.as_slice() {
+            [number, code] if is_code(code) => (*number, Some(code.to_uppercase())),
+            [code, number] if is_code(code) => (*number, Some(code.to_uppercase())),
+            _ => (s, None),
+        };
+        Ok(Amount {
+            minor: parse(number, decimals, format)?,
+            currency,
+        })
+    }
+
+    /// The amount in the smallest unit of `currency`, or of any currency if that's `None`
+    /// # Errors
+    /// The amount was written with a different currency
+    pub fn minor_in(&self, currency: Option<&str>) -> Result<i128, String> {
+        match (&self.currency, currency) {
+            (Some(written), Some(currency)) if !written.eq_ignore_ascii_case(currency) => Err(
+                format!("expected an amount in {}, got {}", currency, written),
+            ),
+            _ => Ok(self.minor),
+        }
+    }
+
+    /// Formats the amount, see [`format()`], followed by its currency code if it has one
+    pub fn display(&self, decimals: u32, number_format: NumberFormat) -> String {
+        let number = format(self.minor, decimals, number_format);
+        match &self.currency {
+            Some(currency) => format!("{} {}", number, currency),
+            None => number,
+        }
+    }
+}
+
+impl fmt::Display for Amount {
+    /// With the current [`decimals`] and [`NumberFormat::current`]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.display(decimals(), NumberFormat::current()))
+    }
+}
+
+/// Deserializes an amount in the smallest unit from either a number or a string like
+/// `"12.34 USD"`, written with the current [`decimals`] and a `.` before the decimals like
+/// JSON numbers, and with the [`currency`] or without a currency. For `deserialize_with`.
+///
+/// Numbers beyond `u64` need writing as a string, as serde can't tell them from floats here.
+pub fn deserialize_units<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Units, D::Error> {
+    deserializer.deserialize_any(UnitsVisitor)
+}
+
+struct UnitsVisitor;
+
+impl Visitor<'_> for UnitsVisitor {
+    type Value = Units;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an amount in the smallest unit, or written out like \"12.34 USD\"")
+    }
+
+    fn visit_u64<E: de::Error>(self, units: u64) -> Result<Units, E> {
+        Ok(Units::from(units))
+    }
+
+    fn visit_i64<E: de::Error>(self, units: i64) -> Result<Units, E> {
+        u64::try_from(units)
+            .map_err(|_| E::custom(format!("invalid amount {}", units)))
+            .and_then(|units| self.visit_u64(units))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Units, E> {
+        Amount::parse(s, decimals(), NumberFormat::POINT)
+            .and_then(|amount| amount.minor_in(currency().as_deref()))
+            .and_then(|minor| Units::try_from(minor).map_err(|_| format!("invalid amount {}", s)))
+            .map_err(E::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_follows_locale() {
+        assert_eq!(parse("1.234,56", 2, NumberFormat::COMMA), Ok(123456));
+        assert_eq!(parse("1,234.56", 2, NumberFormat::POINT), Ok(123456));
+        assert_eq!(parse("1.000", 0, NumberFormat::COMMA), Ok(1000));
+        assert!(parse("1.5", 0, NumberFormat::POINT).is_err());
+        assert!(parse_unsigned("-1", 0, NumberFormat::POINT).is_err());
+    }
+
+    #[test]
+    fn test_format_follows_locale() {
+        assert_eq!(format(123456, 2, NumberFormat::COMMA), "1.234,56");
+        assert_eq!(format(-1234567, 0, NumberFormat::POINT), "-1,234,567");
+        assert_eq!(format(5, 2, NumberFormat::POINT), "0.05");
+        assert_eq!(format(100, 0, NumberFormat::COMMA), "100");
+        assert_eq!(format_plain(-1234567, 2), "-12345.67");
+        assert_eq!(format_plain(1000, 0), "1000");
+    }
+
+    #[test]
+    fn test_amount_parses_currency_codes() {
+        //act
+        let after = Amount::parse("12.34 usd", 2, NumberFormat::POINT).unwrap();
+        let before = Amount::parse("EUR 1.234,5", 2, NumberFormat::COMMA).unwrap();
+        let bare = Amount::parse("-7", 2, NumberFormat::POINT).unwrap();
+
+        assert_eq!(
+            (after.minor, after.currency.as_deref()),
+            (1234, Some("USD"))
+        );
+        assert_eq!(
+            (before.minor, before.currency.as_deref()),
+            (123450, Some("EUR"))
+        );
+        assert_eq!((bare.minor, bare.currency.as_deref()), (-700, None));
+        assert_eq!(after.minor_in(Some("USD")), Ok(1234));
+        assert_eq!(after.minor_in(None), Ok(1234));
+        assert_eq!(bare.minor_in(Some("USD")), Ok(-700));
+        assert!(after.minor_in(Some("EUR")).is_err());
+        assert_eq!(after.display(2, NumberFormat::COMMA), "12,34 USD");
+        assert_eq!(bare.display(0, NumberFormat::POINT), "-700");
+        assert!(Amount::parse("12.34 US$", 2, NumberFormat::POINT).is_err());
+        assert!(Amount::parse("USD", 2, NumberFormat::POINT).is_err());
+        assert!(Amount::parse("1 2 USD", 2, NumberFormat::POINT).is_err());
+    }
+
+    #[test]
+    fn test_decimals_of_reads_the_config() {
+        let config = |text: &str| Config::parse(text).unwrap();
+
+        assert_eq!(decimals_of(&config("decimals = 8")), Ok(Some(8)));
+        assert_eq!(decimals_of(&config("")), Ok(None));
+        assert!(decimals_of(&config("decimals = 19")).is_err());
+        assert!(decimals_of(&config("decimals = two")).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+//! Heuristics flagging suspicious sends, see [`crate::accounts::Accounts::set_anomaly_policy`].
+//!
+//! Two patterns are recognized: an account sending more often than usual, and an account
+//! sending most of its balance to a recipient it never sent to before.
+
+use crate::clock::Timestamp;
+use crate::overlay::Overlay;
+use crate::ratelimit::RateLimit;
+use crate::tx::Units;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// A suspicious send
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Anomaly {
+    /// `sender` made `sends` sends, this one included, within `window`
+    Velocity {
+        sender: String,
+        sends: usize,
+        window: Duration,
+    },
+    /// `sender` sends `amount` of its `balance` to `recipient`, which it never sent to before
+    DrainToNewRecipient {
+        sender: String,
+        recipient: String,
+        amount: Units,
+        balance: Units,
+    },
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Anomaly::Velocity {
+                sender,
+                sends,
+                window,
+            } => write!(
+                f,
+                "{} made {} sends within {}s",
+                sender,
+                sends,
+                window.as_secs()
+            ),
+            Anomaly::DrainToNewRecipient {
+                sender,
+                recipient,
+                amount,
+                balance,
+            } => write!(
+                f,
+                "{} sends {} of its {} to the new recipient {}",
+                sender, amount, balance, recipient
+            ),
+        }
+    }
+}
+
+/// What happens to a send flagged as an [`Anomaly`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Action {
+    /// It goes through, but a [`crate::events::LedgerEvent::AnomalyDetected`] is published
+    #[default]
+    Warn,
+    /// It fails with [`crate::errors::ApplicationError::ConfirmationRequired`] unless it is
+    /// made with [`crate::accounts::Accounts::send_confirmed`]
+    Confirm,
+    /// It fails with [`crate::errors::ApplicationError::Suspicious`]
+    Block,
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "warn" => Ok(Action::Warn),
+            "confirm" => Ok(Action::Confirm),
+            "block" => Ok(Action::Block),
+            _ => Err(format!("expected warn, confirm or block, got {:?}", s)),
+        }
+    }
+}
+
+/// Which patterns count as anomalies, and what to do about them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AnomalyPolicy {
+    /// More sends by one account than this are an [`Anomaly::Velocity`]
+    pub velocity: Option<RateLimit>,
+    /// Sending at least this percentage of the balance to a new recipient is an
+    /// [`Anomaly::DrainToNewRecipient`]
+    pub drain_percent: Option<u32>,
+    pub action: Action,
+}
+
+/// Remembers the recent sends of every account to apply an [`AnomalyPolicy`].
+///
+/// Replaying a log remembers its sends too, except in logs from before the withdrawal side of a
+/// transfer named its recipient, see [`crate::tx::Tx::Withdraw`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AnomalyDetector {
+    pub(crate) policy: AnomalyPolicy,
+    /// When each account sent within the velocity window, oldest first
+    recent: Overlay<String, VecDeque<Timestamp>>,
+    recipients: Overlay<String, HashSet<String>>,
+}
+
+impl AnomalyDetector {
+    /// The anomaly sending `amount` of `balance` from `sender` to `recipient` at `now` is, if any
+    pub(crate) fn check(
+        &self,
+        now: Timestamp,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+        balance: Units,
+    ) -> Option<Anomaly> {
+        if let Some(percent) = self.policy.drain_percent {
+            let known = self
+                .recipients
+                .get(sender)
+                .is_some_and(|recipients| recipients.contains(recipient));
+            if !known && balance > 0 && amount as u128 * 100 >= balance as u128 * percent as u128 {
+                return Some(Anomaly::DrainToNewRecipient {
+                    sender: sender.to_string(),
+                    recipient: recipient.to_string(),
+                    amount,
+                    balance,
+                });
+            }
+        }
+        let limit = self.policy.velocity?;
+        let since = Timestamp(now.0.saturating_sub(limit.per.as_millis() as u64));
+        let sends = self.recent.get(sender).map_or(0, |times| {
+            times.iter().filter(|&&time| time > since).count()
+        }) + 1;
+        (sends > limit.requests as usize).then(|| Anomaly::Velocity {
+            sender: sender.to_string(),
+            sends,
+            window: limit.per,
+        })
+    }
+
+    /// See [`Overlay::compact`]
+    pub(crate) fn compact(&mut self) {
+        self.recent.compact();
+        self.recipients.compact();
+    }
+
+    /// Remembers a committed send
+    pub(crate) fn record(&mut self, now: Timestamp, sender: &str, recipient: &str) {
+        if self.policy.drain_percent.is_some() {
+            self.recipients
+                .get_or_insert_default(sender)
+                .insert(recipient.to_string());
+        }
+        if let Some(limit) = self.policy.velocity {
+            let since = Timestamp(now.0.saturating_sub(limit.per.as_millis() as u64));
+            let times = self.recent.get_or_insert_default(sender);
+            while times.front().is_some_and(|&time| time <= since) {
+                times.pop_front();
+            }
+            times.push_back(now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anomaly_detector_flags_velocity_and_draining() {
+        let mut detector = AnomalyDetector {
+            policy: AnomalyPolicy {
+                velocity: Some("2/minute".parse().unwrap()),
+                drain_percent: Some(90),
+                action: Action::Block,
+            },
+            ..Default::default()
+        };
+
+        //act
+        let draining = detector.check(Timestamp(0), "ALICE", "MALLORY", 95, 100);
+        detector.record(Timestamp(100_000), "ALICE", "BOB");
+        detector.record(Timestamp(101_000), "ALICE", "BOB");
+        let fast = detector.check(Timestamp(102_000), "ALICE", "BOB", 1, 100);
+        let later = detector.check(Timestamp(161_000), "ALICE", "BOB", 1, 100);
+
+        assert!(matches!(
+            draining,
+            Some(Anomaly::DrainToNewRecipient { amount: 95, .. })
+        ));
+        assert!(matches!(fast, Some(Anomaly::Velocity { sends: 3, .. })));
+        assert_eq!(later, None);
+        assert_eq!(
+            detector.check(Timestamp(161_000), "ALICE", "BOB", 95, 100),
+            None
+        );
+        assert_eq!("confirm".parse(), Ok(Action::Confirm));
+    }
+}
@@ -0,0 +1,185 @@
+//! API keys authenticating the requests [`crate::server::HttpServer`] serves.
+//!
+//! Every key either only reads or has full access, and may be scoped to some accounts.
+
+use crate::config::Config;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// What a key may do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Only requests that don't change the ledger
+    Read,
+    Full,
+}
+
+impl FromStr for Access {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "read" => Ok(Access::Read),
+            "full" => Ok(Access::Full),
+            _ => Err(format!("expected read or full, got {:?}", s)),
+        }
+    }
+}
+
+/// The permissions of a key, parsed from `<secret> <read|full> [<account>,...]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKey {
+    pub name: String,
+    pub access: Access,
+    /// The accounts the key may act on, all of them if `None`
+    pub accounts: Option<BTreeSet<String>>,
+}
+
+/// What a request does, to check it against an [`ApiKey`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation {
+    pub writes: bool,
+    /// The accounts it acts on, or `None` if it concerns every account like listing all balances
+    pub accounts: Option<Vec<String>>,
+}
+
+/// Why a request was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    /// The request carries no key, or one that isn't configured
+    Unauthenticated,
+    /// The key named in the error may not make the request
+    Forbidden(String),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthError::Unauthenticated => f.write_str("missing or unknown API key"),
+            AuthError::Forbidden(name) => write!(f, "API key {} may not make this request", name),
+        }
+    }
+}
+
+/// The configured API keys, by secret. Without any, every request is allowed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApiKeys {
+    keys: HashMap<String, ApiKey>,
+}
+
+impl ApiKeys {
+    /// Reads every `api_key.<name> = <secret> <read|full> [<account>,...]` setting, e.g.
+    /// `api_key.dashboard = 5f3a... read ALICE,BOB`
+    /// # Errors
+    /// A setting doesn't have that form, or two keys share a secret; the error names the key
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let mut keys = ApiKeys::default();
+        for (name, value) in config.with_prefix("api_key.") {
+            let invalid = |e: String| format!("api_key.{}: {}", name, e);
+            let mut words = value.split_whitespace();
+            let (Some(secret), Some(access)) = (words.next(), words.next()) else {
+                return Err(invalid(
+                    "expected `<secret> <read|full> [<account>,...]`".to_string(),
+                ));
+            };
+            let accounts = words
+                .next()
+                .map(|accounts| accounts.split(',').map(str::to_string).collect());
+            if words.next().is_some() {
+                return Err(invalid("accounts are separated by commas only".to_string()));
+            }
+            let key = ApiKey {
+                name: name.to_string(),
+                access: access.parse().map_err(invalid)?,
+                accounts,
+            };
+            if keys.insert(secret, key).is_some() {
+                return Err(invalid("the secret is used by another key".to_string()));
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Adds the key with `secret`, returning the key it replaces
+    pub fn insert(&mut self, secret: &str, key: ApiKey) -> Option<ApiKey> {
+        self.keys.insert(secret.to_string(), key)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Checks that the key with `secret` may make every one of `operations`.
+    ///
+    /// Returns the key, or `None` if no keys are configured.
+    pub fn authorize(
+        &self,
+        secret: Option<&str>,
+        operations: &[Operation],
+    ) -> Result<Option<&ApiKey>, AuthError> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        let key = secret
+            .and_then(|secret| self.keys.get(secret))
+            .ok_or(AuthError::Unauthenticated)?;
+        let allowed = |operation: &Operation| {
+            let writable = !operation.writes || key.access == Access::Full;
+            let in_scope = match (&key.accounts, &operation.accounts) {
+                (None, _) => true,
+                (Some(scope), Some(accounts)) => {
+                    accounts.iter().all(|account| scope.contains(account))
+                }
+                (Some(_), None) => false,
+            };
+            writable && in_scope
+        };
+        if operations.iter().all(allowed) {
+            Ok(Some(key))
+        } else {
+            Err(AuthError::Forbidden(key.name.clone()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_keys_authorize_by_access_and_scope() {
+        let config = Config::parse(
+            "api_key.admin = s3cret full\napi_key.alice = alice-key read ALICE,JOINT",
+        )
+        .unwrap();
+        let keys = ApiKeys::from_config(&config).unwrap();
+        let op = |writes, accounts: Option<&[&str]>| Operation {
+            writes,
+            accounts: accounts.map(|accounts| accounts.iter().map(|a| a.to_string()).collect()),
+        };
+
+        //act
+        let admin = keys.authorize(Some("s3cret"), &[op(true, None)]);
+        let reads = keys.authorize(Some("alice-key"), &[op(false, Some(&["ALICE", "JOINT"]))]);
+        let writes = keys.authorize(Some("alice-key"), &[op(true, Some(&["ALICE"]))]);
+        let elsewhere = keys.authorize(Some("alice-key"), &[op(false, Some(&["BOB"]))]);
+        let everything = keys.authorize(Some("alice-key"), &[op(false, None)]);
+
+        assert_eq!(admin.unwrap().unwrap().name, "admin");
+        assert_eq!(reads.unwrap().unwrap().access, Access::Read);
+        for denied in [writes, elsewhere, everything] {
+            assert_eq!(denied, Err(AuthError::Forbidden("alice".to_string())));
+        }
+        assert_eq!(
+            keys.authorize(Some("guess"), &[]),
+            Err(AuthError::Unauthenticated)
+        );
+        assert_eq!(keys.authorize(None, &[]), Err(AuthError::Unauthenticated));
+        assert_eq!(
+            ApiKeys::default().authorize(None, &[op(true, None)]),
+            Ok(None)
+        );
+        assert!(ApiKeys::from_config(&Config::parse("api_key.x = s3cret write").unwrap()).is_err());
+    }
+}
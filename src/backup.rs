@@ -0,0 +1,639 @@
+//! Backups of a persisted ledger: a tar archive, optionally gzip compressed, holding the tx log
+//! or WAL, its `<log>.meta` sidecar and a [`Snapshot`] of the state the log leads to, described
+//! by a [`Manifest`].
+//!
+//! Logs are only ever appended to, so a backup can be taken while the ledger is in use: it
+//! covers the complete entries at the time the log is read, and an entry still being written
+//! is left out.
+
+use crate::{
+    accounts::Accounts,
+    checksum::{self, sha256_hex},
+    clock::{Clock, SystemClock, Timestamp},
+    metadata, migrations,
+    shutdown::Shutdown,
+    snapshot::{self, Snapshot},
+    storage::{self, LogEntry, LogReader},
+    wal,
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{error, info};
+
+/// The version of the archive layout written by [`create`]
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The name of the manifest inside an archive; it is always the first entry
+pub const MANIFEST: &str = "manifest.json";
+
+/// The name of the snapshot inside an archive
+pub const SNAPSHOT: &str = "snapshot.json";
+
+/// What a backup holds
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// See [`FORMAT_VERSION`]
+    pub version: u32,
+    pub created: Timestamp,
+    /// The file name of the backed up log inside the archive
+    pub log: String,
+    /// Whether the log is a binary WAL rather than a JSON lines tx log
+    pub wal: bool,
+    /// How many log entries the backup covers
+    pub entries: usize,
+    /// The hex encoded SHA-256 of every other file in the archive, by name
+    pub files: BTreeMap<String, String>,
+}
+
+/// Backs up the ledger persisted to `log`, a WAL if `wal` is set, into a new archive in `dir`
+/// named `<log file name>-<YYYYMMDDTHHMMSSZ>.tar`, or `.tar.gz` if `compress` is set. The
+/// snapshot in it comes from replaying the log into `ledger`, which is empty but follows the
+/// rules of the log like its credit limits.
+/// Returns the path of the archive and its manifest.
+/// # Errors
+/// The log can't be read or replayed, or the archive can't be written
+pub fn create(
+    log: &Path,
+    wal: bool,
+    mut ledger: Accounts,
+    dir: &Path,
+    compress: bool,
+    now: Timestamp,
+) -> io::Result<(PathBuf, Manifest)> {
+    let name = log
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| invalid(format!("{} isn't a log file", log.display())))?
+        .to_string();
+    let bytes = fs::read(log)?;
+    let (bytes, entries) = complete_entries(&bytes, wal).map_err(|e| match e.kind() {
+        io::ErrorKind::InvalidData if !checksum::is_corrupt(&e) => checksum::corrupt(log, e),
+        _ => e,
+    })?;
+
+    let applied = storage::replay(&mut ledger, entries.into_iter().map(Ok))?;
+    let snapshot = migrations::to_json(&Snapshot::of(&ledger, applied), migrations::SNAPSHOT)?;
+    let mut files = vec![
+        (name.clone(), bytes.to_vec()),
+        (SNAPSHOT.to_string(), snapshot),
+    ];
+    match checksum::read(metadata::path_for(log)) {
+        Ok(meta) => files.push((format!("{}.meta", name), meta)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    let manifest = Manifest {
+        version: FORMAT_VERSION,
+        created: now,
+        log: name.clone(),
+        wal,
+        entries: applied,
+        files: files
+            .iter()
+            .map(|(name, contents)| (name.clone(), sha256_hex(contents)))
+            .collect(),
+    };
+
+    fs::create_dir_all(dir)?;
+    let extension = if compress { "tar.gz" } else { "tar" };
+    let path = dir.join(format!("{}-{}.{}", name, now.compact(), extension));
+    let tmp = path.with_extension("tmp");
+    let file = File::create(&tmp)?;
+    let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+    let file = if compress {
+        let gz = write_archive(
+            GzEncoder::new(file, Compression::default()),
+            &manifest_json,
+            &files,
+            now,
+        )?;
+        gz.finish()?
+    } else {
+        write_archive(file, &manifest_json, &files, now)?
+    };
+    file.sync_all()?;
+    // Only complete archives get their final name
+    fs::rename(tmp, &path)?;
+    Ok((path, manifest))
+}
+
+/// An archive read back by [`read`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup {
+    pub manifest: Manifest,
+    /// The contents of every file but the manifest, by name
+    pub files: BTreeMap<String, Vec<u8>>,
+}
+
+/// Reads the archive at `path`, compressed or not, and checks it against its manifest.
+/// # Errors
+/// The archive can't be read, has an unknown [`Manifest::version`], or a file is missing, not
+/// listed in the manifest, or doesn't match its checksum, which is [`checksum::corrupt`]
+pub fn read(path: &Path) -> io::Result<Backup> {
+    let bytes = fs::read(path)?;
+    let mut tar = vec![];
+    let bytes = if bytes.starts_with(&[0x1f, 0x8b]) {
+        GzDecoder::new(&bytes[..]).read_to_end(&mut tar)?;
+        &tar[..]
+    } else {
+        &bytes[..]
+    };
+    let mut archive = tar::Archive::new(bytes);
+    let mut manifest = None;
+    let mut files = BTreeMap::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.display().to_string();
+        let mut contents = vec![];
+        entry.read_to_end(&mut contents)?;
+        if name == MANIFEST {
+            manifest = Some(serde_json::from_slice::<Manifest>(&contents)?);
+        } else {
+            files.insert(name, contents);
+        }
+    }
+    let manifest = manifest.ok_or_else(|| invalid(format!("no {}", MANIFEST)))?;
+    if manifest.version > FORMAT_VERSION {
+        return Err(invalid(format!(
+            "format version {} is newer than this crabbux supports ({})",
+            manifest.version, FORMAT_VERSION
+        )));
+    }
+    for (name, hash) in &manifest.files {
+        let contents = files
+            .get(name)
+            .ok_or_else(|| invalid(format!("{} is missing", name)))?;
+        if &sha256_hex(contents) != hash {
+            let what = format!("{} doesn't match its checksum", name);
+            return Err(checksum::corrupt(path, what));
+        }
+    }
+    if let Some(name) = files
+        .keys()
+        .find(|name| !manifest.files.contains_key(*name))
+    {
+        return Err(invalid(format!("{} isn't listed in the manifest", name)));
+    }
+    if !files.contains_key(&manifest.log) {
+        return Err(invalid(format!("the log {} is missing", manifest.log)));
+    }
+    Ok(Backup { manifest, files })
+}
+
+impl Backup {
+    /// The backed up log
+    pub fn log(&self) -> &[u8] {
+        &self.files[&self.manifest.log]
+    }
+
+    /// Replays the log into `ledger`, see [`create`], and checks that it reproduces the
+    /// snapshot and covers every entry the manifest claims.
+    /// # Errors
+    /// The log or snapshot can't be read, or they diverge
+    pub fn verify(&self, ledger: Accounts) -> io::Result<()> {
+        let snapshot: Snapshot = migrations::from_json(
+            self.files
+                .get(SNAPSHOT)
+                .ok_or_else(|| invalid(format!("{} is missing", SNAPSHOT)))?,
+            migrations::SNAPSHOT,
+        )?;
+        let (complete, entries) = complete_entries(self.log(), self.manifest.wal)?;
+        if complete.len() != self.log().len() || entries.len() != self.manifest.entries {
+            return Err(invalid(format!(
+                "the log has {} complete entries, the manifest claims {}",
+                entries.len(),
+                self.manifest.entries
+            )));
+        }
+        match snapshot::verify(&snapshot, ledger, entries.into_iter().map(Ok))? {
+            None => Ok(()),
+            Some(divergence) => Err(invalid(format!(
+                "the log doesn't reproduce the snapshot: {}",
+                divergence
+            ))),
+        }
+    }
+
+    /// Replaces the ledger persisted to `log` with the backup: the log, its `<log>.meta`
+    /// sidecar, which is removed if the backup has none, and the final snapshot next to it,
+    /// see [`snapshot::path_for`], both with their checksums. Every file is replaced atomically.
+    /// # Errors
+    /// The backup log is a WAL and `log` isn't meant to be, or the other way around, or a
+    /// file can't be written
+    pub fn restore(&self, log: &Path, wal: bool) -> io::Result<()> {
+        if wal != self.manifest.wal {
+            return Err(invalid(format!(
+                "the backup holds a {}, not a {}",
+                kind(self.manifest.wal),
+                kind(wal)
+            )));
+        }
+        let meta = metadata::path_for(log);
+        match self.files.get(&format!("{}.meta", self.manifest.log)) {
+            Some(contents) => checksum::write(&meta, contents)?,
+            None => {
+                for path in [checksum::path_for(&meta), meta] {
+                    match fs::remove_file(path) {
+                        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        if let Some(contents) = self.files.get(SNAPSHOT) {
+            checksum::write(snapshot::path_for(log), contents)?;
+        }
+        replace(log, self.log())
+    }
+}
+
+/// Which backups to keep: the newest of each of the last `daily` days and of the last `weekly`
+/// weeks that have any, and always the newest one.
+///
+/// Parsed from e.g. `7 daily, 4 weekly`; either part may be left out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Retention {
+    pub daily: usize,
+    pub weekly: usize,
+}
+
+impl FromStr for Retention {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let invalid = || format!("expected e.g. `7 daily, 4 weekly`, got {:?}", s);
+        let mut retention = Retention::default();
+        for part in s.split(',') {
+            let (count, period) = part.trim().split_once(' ').ok_or_else(invalid)?;
+            let count = count.parse().map_err(|_| invalid())?;
+            match period.trim() {
+                "daily" => retention.daily = count,
+                "weekly" => retention.weekly = count,
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(retention)
+    }
+}
+
+impl Retention {
+    /// Of the backups taken at `times`, the positions of those to keep
+    pub fn keep(&self, times: &[Timestamp]) -> BTreeSet<usize> {
+        let mut newest: Vec<usize> = (0..times.len()).collect();
+        newest.sort_by_key(|&i| std::cmp::Reverse(times[i]));
+        let mut keep: BTreeSet<usize> = newest.first().copied().into_iter().collect();
+        let day = |i: usize| times[i].date().unix_days();
+        // Unix day 0 was a Thursday, so weeks start on Mondays
+        let week = |i: usize| (day(i) + 3).div_euclid(7);
+        let mut keep_newest_per = |period: &dyn Fn(usize) -> i64, count: usize| {
+            let mut seen = BTreeSet::new();
+            for &i in &newest {
+                if seen.len() == count {
+                    break;
+                }
+                if seen.insert(period(i)) {
+                    keep.insert(i);
+                }
+            }
+        };
+        keep_newest_per(&day, self.daily);
+        keep_newest_per(&week, self.weekly);
+        keep
+    }
+}
+
+/// The backups of the log named `log` in `dir` and when they were taken, by their file names,
+/// oldest first
+pub fn list(dir: &Path, log: &str) -> io::Result<Vec<(PathBuf, Timestamp)>> {
+    let mut backups = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let taken = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(log)?.strip_prefix('-'))
+            .and_then(|name| {
+                name.strip_suffix(".tar.gz")
+                    .or_else(|| name.strip_suffix(".tar"))
+            })
+            .and_then(Timestamp::parse_compact);
+        if let Some(taken) = taken {
+            backups.push((path, taken));
+        }
+    }
+    backups.sort_by_key(|(_, taken)| *taken);
+    Ok(backups)
+}
+
+/// The newest backup of the log named `log` in `dir` that is intact and reproduces its
+/// snapshot replayed into a `ledger()`, see [`Backup::verify`], to restore a damaged ledger
+/// from
+pub fn last_good(dir: &Path, log: &str, ledger: impl Fn() -> Accounts) -> Option<PathBuf> {
+    let backups = list(dir, log).ok()?;
+    backups
+        .into_iter()
+        .rev()
+        .map(|(path, _)| path)
+        .find(|path| {
+            read(path)
+                .and_then(|backup| backup.verify(ledger()))
+                .is_ok()
+        })
+}
+
+/// Deletes the backups of the log named `log` in `dir` that `retention` doesn't keep, and
+/// returns their paths. Other files are left alone.
+pub fn prune(dir: &Path, log: &str, retention: &Retention) -> io::Result<Vec<PathBuf>> {
+    let backups = list(dir, log)?;
+    let times: Vec<Timestamp> = backups.iter().map(|(_, taken)| *taken).collect();
+    let keep = retention.keep(&times);
+    let mut pruned = vec![];
+    for (i, (path, _)) in backups.into_iter().enumerate() {
+        if !keep.contains(&i) {
+            fs::remove_file(&path)?;
+            pruned.push(path);
+        }
+    }
+    Ok(pruned)
+}
+
+/// Parses an interval like `30m`, `12h` or `1d`
+pub fn parse_interval(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("expected `<n><m|h|d>`, e.g. `12h`, got {:?}", s);
+    let (count, unit) = s.trim().split_at(s.trim().len().saturating_sub(1));
+    let unit = match unit {
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        _ => return Err(invalid()),
+    };
+    match count.parse::<u64>() {
+        Ok(count) if count > 0 => Ok(Duration::from_secs(count * unit)),
+        _ => Err(invalid()),
+    }
+}
+
+/// How [`spawn_scheduler`] backs up
+#[derive(Debug)]
+pub struct Schedule {
+    pub log: PathBuf,
+    pub wal: bool,
+    /// The empty ledger every backup forks to replay the log into, see [`create`]
+    pub ledger: Accounts,
+    pub dir: PathBuf,
+    pub compress: bool,
+    pub every: Duration,
+    pub retention: Option<Retention>,
+}
+
+/// Backs up and prunes as `schedule` says on a thread of its own, until `shutdown` is requested
+pub fn spawn_scheduler(schedule: Schedule, shutdown: Arc<Shutdown>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        while !shutdown.wait_timeout(schedule.every) {
+            match create(
+                &schedule.log,
+                schedule.wal,
+                schedule.ledger.fork(),
+                &schedule.dir,
+                schedule.compress,
+                SystemClock.now(),
+            ) {
+                Ok((path, manifest)) => {
+                    info!(path = %path.display(), entries = manifest.entries, "backed up")
+                }
+                Err(e) => error!(error = %e, "backup failed"),
+            }
+            let Some(retention) = &schedule.retention else {
+                continue;
+            };
+            let name = schedule
+                .log
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy();
+            match prune(&schedule.dir, &name, retention) {
+                Ok(pruned) => pruned
+                    .iter()
+                    .for_each(|path| info!(path = %path.display(), "pruned backup")),
+                Err(e) => error!(error = %e, "pruning backups failed"),
+            }
+        }
+    })
+}
+
+fn kind(wal: bool) -> &'static str {
+    if wal {
+        "WAL"
+    } else {
+        "tx log"
+    }
+}
+
+/// Writes `contents` to `path` through a temporary file
+fn replace(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".restoring");
+    let file = File::create(&tmp)?;
+    (&file).write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(tmp, path)
+}
+
+/// The complete entries at the start of the log `bytes`, and those bytes
+fn complete_entries(bytes: &[u8], wal: bool) -> io::Result<(&[u8], Vec<LogEntry>)> {
+    if wal {
+        let format = wal::Format::of(bytes)?;
+        let start = format.magic().len();
+        let len = start + format.complete_len(&bytes[start..])?;
+        let mut rest = &bytes[start..len];
+        let mut decoded = vec![];
+        while !rest.is_empty() {
+            let (entry, entry_len) = format.decode(rest)?;
+            decoded.push(entry.to_entry());
+            rest = &rest[entry_len..];
+        }
+        return Ok((&bytes[..len], decoded));
+    }
+    let len = bytes.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    let entries = LogReader::new(&bytes[..len]).collect::<io::Result<_>>()?;
+    Ok((&bytes[..len], entries))
+}
+
+/// Writes the manifest and `files` as a tar archive to `writer` and returns it
+fn write_archive<W: Write>(
+    writer: W,
+    manifest: &[u8],
+    files: &[(String, Vec<u8>)],
+    modified: Timestamp,
+) -> io::Result<W> {
+    let mut archive = tar::Builder::new(writer);
+    append(&mut archive, MANIFEST, manifest, modified)?;
+    for (name, contents) in files {
+        append(&mut archive, name, contents, modified)?;
+    }
+    archive.into_inner()
+}
+
+fn append<W: Write>(
+    archive: &mut tar::Builder<W>,
+    name: &str,
+    contents: &[u8],
+    modified: Timestamp,
+) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o600);
+    header.set_mtime(modified.0 / 1000);
+    archive.append_data(&mut header, name, contents)
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::FileStore;
+    use std::env;
+
+    #[test]
+    fn test_create_backs_up_complete_entries() {
+        let dir = env::temp_dir().join(format!("crabbux-backup-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("ledger.jsonl");
+        let mut ledger = Accounts::new();
+        let store = FileStore::open(&log).unwrap();
+        store
+            .write(&[ledger.deposit("ALICE", 10).unwrap()])
+            .unwrap();
+        // An entry that is still being appended
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&log)
+            .unwrap()
+            .write_all(br#"{"timestamp":1,"tx":"#)
+            .unwrap();
+        let now = Timestamp::start_of(crate::date::Date::new(2024, 3, 1).unwrap());
+
+        //act
+        let (path, manifest) = create(
+            &log,
+            false,
+            Accounts::new(),
+            &dir.join("backups"),
+            false,
+            now,
+        )
+        .unwrap();
+        let mut names = vec![];
+        let mut archive = tar::Archive::new(File::open(&path).unwrap());
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let mut contents = vec![];
+            entry.read_to_end(&mut contents).unwrap();
+            let name = entry.path().unwrap().display().to_string();
+            if let Some(hash) = manifest.files.get(&name) {
+                assert_eq!(&sha256_hex(&contents), hash);
+            }
+            names.push(name);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            path.file_name().unwrap(),
+            "ledger.jsonl-20240301T000000Z.tar"
+        );
+        assert_eq!((manifest.version, manifest.entries), (FORMAT_VERSION, 1));
+        assert_eq!(names, vec![MANIFEST, "ledger.jsonl", SNAPSHOT]);
+    }
+
+    #[test]
+    fn test_retention_keeps_newest_per_day_and_week() {
+        let retention: Retention = "2 daily, 2 weekly".parse().unwrap();
+        // Mondays 2024-01-01 and 2024-01-08, then every day up to Thursday 2024-01-11
+        let times: Vec<Timestamp> = [1, 1, 8, 9, 10, 11, 11]
+            .into_iter()
+            .enumerate()
+            .map(|(i, day)| {
+                let date = crate::date::Date::new(2024, 1, day).unwrap();
+                Timestamp(Timestamp::start_of(date).0 + i as u64)
+            })
+            .collect();
+
+        //act
+        let keep = retention.keep(&times);
+
+        // The newest of the 11th and the 10th, and of the weeks of the 8th and the 1st
+        assert_eq!(keep, BTreeSet::from([1, 4, 6]));
+        assert_eq!(Retention::default().keep(&times), BTreeSet::from([6]));
+        assert_eq!(
+            "4 weekly".parse(),
+            Ok(Retention {
+                daily: 0,
+                weekly: 4
+            })
+        );
+        assert!("7 days".parse::<Retention>().is_err());
+        assert_eq!(parse_interval("12h"), Ok(Duration::from_secs(43_200)));
+        assert!(parse_interval("0d").is_err());
+    }
+
+    #[test]
+    fn test_read_verifies_and_restores_backups() {
+        let dir = env::temp_dir().join(format!("crabbux-restore-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("ledger.wal");
+        let rules = || {
+            let mut ledger = Accounts::new();
+            ledger.set_credit_limit("ALICE", 10);
+            ledger
+        };
+        let mut ledger = rules();
+        let writer = crate::wal::WalWriter::open(&log).unwrap();
+        writer
+            .write(&[
+                ledger.deposit("ALICE", 10).unwrap(),
+                ledger.withdraw("ALICE", 15).unwrap(),
+            ])
+            .unwrap();
+        let without_credit = create(&log, true, Accounts::new(), &dir, true, Timestamp(0));
+        let (path, _) = create(&log, true, rules(), &dir, true, Timestamp(0)).unwrap();
+        writer.write(&[ledger.deposit("BOB", 5).unwrap()]).unwrap();
+        let mut tampered = read(&path).unwrap();
+        tampered.files.insert(SNAPSHOT.to_string(), b"{}".to_vec());
+
+        //act
+        let backup = read(&path).unwrap();
+        backup.verify(rules()).unwrap();
+        let unverified = backup.verify(Accounts::new());
+        let last_good = last_good(&dir, "ledger.wal", rules);
+        let wrong_kind = backup.restore(&dir.join("ledger.jsonl"), false);
+        backup.restore(&log, true).unwrap();
+        let mut restored = rules();
+        let applied = crate::wal::MmapWal::open(&log)
+            .unwrap()
+            .replay(&mut restored)
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(backup.manifest.wal);
+        assert!(wrong_kind.is_err());
+        assert!(tampered.verify(rules()).is_err());
+        assert!(without_credit.is_err() && unverified.is_err());
+        assert_eq!(last_good, Some(path));
+        assert_eq!(applied, 2);
+        assert_eq!(restored.signed_balance_of("ALICE"), Ok(-5));
+        assert!(restored.balance_of("BOB").is_err());
+    }
+}
@@ -0,0 +1,117 @@
+//! Checksums of the files a ledger is persisted to, so a damaged file is refused with
+//! [`ApplicationError::CorruptData`] instead of loaded.
+//!
+//! Snapshots and `<log>.meta` files are written with a `<file>.sha256` sidecar in the format
+//! `sha256sum` reads, see [`write()`] and [`read`]. WAL entries carry a CRC32 each instead, see
+//! [`crate::wal`].
+
+use crate::{errors::ApplicationError, storage};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt::{Display, Write as _};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where the checksum of `file` is kept: `<file>.sha256`
+pub fn path_for(file: impl AsRef<Path>) -> PathBuf {
+    let mut path = OsString::from(file.as_ref());
+    path.push(".sha256");
+    path.into()
+}
+
+/// The hex encoded SHA-256 of `bytes`
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .fold(String::new(), |mut hex, b| {
+            let _ = write!(hex, "{:02x}", b);
+            hex
+        })
+}
+
+/// Writes `contents` to `path` and its checksum next to it, each replaced atomically and
+/// synced to disk
+pub fn write(path: impl AsRef<Path>, contents: &[u8]) -> io::Result<()> {
+    let path = path.as_ref();
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let sum = format!("{}  {}\n", sha256_hex(contents), name);
+    storage::replace_file(path, contents)?;
+    storage::replace_file(path_for(path), sum.as_bytes())
+}
+
+/// Reads `path` and checks it against its checksum. Files without one, written by versions
+/// before checksums, are read as they are.
+/// # Errors
+/// Reading failed, or the contents don't match the checksum, see [`corrupt`]
+pub fn read(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+    let path = path.as_ref();
+    let contents = fs::read(path)?;
+    let sum = match fs::read_to_string(path_for(path)) {
+        Ok(sum) => sum,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(contents),
+        Err(e) => return Err(e),
+    };
+    let expected = sum.split_whitespace().next().unwrap_or_default();
+    if expected != sha256_hex(&contents) {
+        return Err(corrupt(path, "it doesn't match its checksum"));
+    }
+    Ok(contents)
+}
+
+/// An [`io::ErrorKind::InvalidData`] error carrying [`ApplicationError::CorruptData`] about
+/// `path`, see [`is_corrupt`]
+pub fn corrupt(path: impl AsRef<Path>, what: impl Display) -> io::Error {
+    let message = format!("{}: {}", path.as_ref().display(), what);
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        ApplicationError::CorruptData(message),
+    )
+}
+
+/// Whether `error` is, or is an [`io::Error`] carrying, [`ApplicationError::CorruptData`]
+pub fn is_corrupt(error: &(dyn Error + 'static)) -> bool {
+    let error = match error.downcast_ref::<io::Error>() {
+        Some(e) => match e.get_ref() {
+            Some(inner) => inner,
+            None => return false,
+        },
+        None => error,
+    };
+    matches!(
+        error.downcast_ref::<ApplicationError>(),
+        Some(ApplicationError::CorruptData(_))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_read_refuses_files_not_matching_their_checksum() {
+        let path = env::temp_dir().join(format!("crabbux-{}-checksum.json", std::process::id()));
+        write(&path, b"{\"ALICE\": 100}").unwrap();
+        let sum = fs::read_to_string(path_for(&path)).unwrap();
+
+        //act
+        let intact = read(&path).unwrap();
+        fs::write(&path, b"{\"ALICE\": 900}").unwrap();
+        let damaged = read(&path).unwrap_err();
+        fs::remove_file(path_for(&path)).unwrap();
+        let legacy = read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(intact, b"{\"ALICE\": 100}");
+        assert!(sum.ends_with(&format!(
+            "  {}\n",
+            path.file_name().unwrap().to_string_lossy()
+        )));
+        assert!(is_corrupt(&damaged));
+        assert!(damaged.to_string().contains("doesn't match its checksum"));
+        assert_eq!(legacy, b"{\"ALICE\": 900}");
+        assert!(!is_corrupt(&io::Error::other("disk full")));
+    }
+}
@@ -0,0 +1,344 @@
+use crate::{
+    clock::Timestamp,
+    goals::Goal,
+    rpc::RpcError,
+    tx::{Tx, Units},
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::TcpStream;
+use tungstenite::{stream::MaybeTlsStream, Message, WebSocket};
+
+/// An error talking to a remote crabbux server
+#[derive(Debug)]
+pub enum ClientError {
+    /// The server couldn't be reached or responded with an HTTP error
+    Transport(String),
+    /// The server processed the request and returned an error
+    Rpc(RpcError),
+    /// The server responded with something that isn't a valid JSON-RPC response
+    InvalidResponse(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::Transport(e) => write!(f, "Couldn't reach server: {}", e),
+            ClientError::Rpc(e) => write!(f, "{}", e.message),
+            ClientError::InvalidResponse(e) => write!(f, "Invalid server response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    result: Option<Value>,
+    error: Option<RpcError>,
+}
+
+/// A ledger living in a crabbux server (see [`crate::server::HttpServer`]), operated via its JSON-RPC endpoint.
+pub struct RemoteLedger {
+    endpoint: String,
+    next_id: u64,
+}
+
+impl RemoteLedger {
+    /// Creates a client for the server at `url`, e.g. `http://127.0.0.1:8080`
+    pub fn new(url: &str) -> Self {
+        RemoteLedger {
+            endpoint: format!("{}/rpc", url.trim_end_matches('/')),
+            next_id: 0,
+        }
+    }
+
+    /// Calls `method` on the server and decodes its result.
+    pub fn call<T: DeserializeOwned>(
+        &mut self,
+        method: &str,
+        params: Value,
+    ) -> Result<T, ClientError> {
+        self.next_id += 1;
+        let request =
+            json!({"jsonrpc": "2.0", "method": method, "params": params, "id": self.next_id});
+
+        let body = ureq::post(&self.endpoint)
+            .header("Content-Type", "application/json")
+            .send(request.to_string())
+            .and_then(|mut response| response.body_mut().read_to_string())
+            .map_err(|e| ClientError::Transport(e.to_string()))?;
+
+        let response: RpcResponse =
+            serde_json::from_str(&body).map_err(|e| ClientError::InvalidResponse(e.to_string()))?;
+        match (response.result, response.error) {
+            (_, Some(error)) => Err(ClientError::Rpc(error)),
+            (Some(result), None) => serde_json::from_value(result)
+                .map_err(|e| ClientError::InvalidResponse(e.to_string())),
+            (None, None) => Err(ClientError::InvalidResponse("empty response".to_string())),
+        }
+    }
+
+    /// Deposits `amount` into the `signer` account on the server
+    pub fn deposit(&mut self, signer: &str, amount: Units) -> Result<Tx, ClientError> {
+        let txs: Vec<Tx> = self.call("deposit", json!({"account": signer, "amount": amount}))?;
+        single(txs)
+    }
+
+    /// Withdraws `amount` from the `signer` account on the server
+    pub fn withdraw(&mut self, signer: &str, amount: Units) -> Result<Tx, ClientError> {
+        let txs: Vec<Tx> = self.call("withdraw", json!({"account": signer, "amount": amount}))?;
+        single(txs)
+    }
+
+    /// Transfers `amount` from `sender` to `recipient` on the server, which may spend
+    /// promotional credit on it and charge a fee, see [`crate::accounts::Accounts::pay`]
+    pub fn send(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx), ClientError> {
+        let txs = self.call(
+            "send",
+            json!({"sender": sender, "recipient": recipient, "amount": amount}),
+        )?;
+        transfer(txs)
+    }
+
+    /// Transfers `amount` even if the server asks to confirm it, see
+    /// [`crate::accounts::Accounts::send_confirmed`]
+    pub fn send_confirmed(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx), ClientError> {
+        let txs = self.call(
+            "send",
+            json!({"sender": sender, "recipient": recipient, "amount": amount, "confirmed": true}),
+        )?;
+        transfer(txs)
+    }
+
+    /// Pays `amount` from `sender` to `recipient` on the server, returning the promotional
+    /// credit it spent, the transfer and the fee, see [`crate::accounts::Accounts::pay`]
+    pub fn pay(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<Vec<Tx>, ClientError> {
+        self.call(
+            "send",
+            json!({"sender": sender, "recipient": recipient, "amount": amount}),
+        )
+    }
+
+    /// Pays even if the server asks to confirm it, see
+    /// [`crate::accounts::Accounts::pay_confirmed`]
+    pub fn pay_confirmed(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<Vec<Tx>, ClientError> {
+        self.call(
+            "send",
+            json!({"sender": sender, "recipient": recipient, "amount": amount, "confirmed": true}),
+        )
+    }
+
+    /// Issues `amount` of new money into the `signer` account on the server
+    pub fn mint(&mut self, signer: &str, amount: Units) -> Result<Tx, ClientError> {
+        let txs: Vec<Tx> = self.call("mint", json!({"account": signer, "amount": amount}))?;
+        single(txs)
+    }
+
+    /// Takes `amount` out of circulation from the `signer` account on the server
+    pub fn burn(&mut self, signer: &str, amount: Units) -> Result<Tx, ClientError> {
+        let txs: Vec<Tx> = self.call("burn", json!({"account": signer, "amount": amount}))?;
+        single(txs)
+    }
+
+    /// Grants `signer` promotional credit on the server until `expires`
+    pub fn grant_promo(
+        &mut self,
+        signer: &str,
+        amount: Units,
+        expires: Timestamp,
+    ) -> Result<Tx, ClientError> {
+        let txs: Vec<Tx> = self.call(
+            "grant_promo",
+            json!({"account": signer, "amount": amount, "expires": expires}),
+        )?;
+        single(txs)
+    }
+
+    /// Approves the pending transfer `id` as the server ledger's principal and returns its
+    /// transactions if that was the last approval needed
+    pub fn approve(&mut self, id: u64) -> Result<Vec<Tx>, ClientError> {
+        self.call("approve", json!({ "id": id }))
+    }
+
+    /// Fetches the balance of the `signer` account
+    pub fn balance_of(&mut self, signer: &str) -> Result<Units, ClientError> {
+        self.call("balance", json!({ "account": signer }))
+    }
+
+    /// Fetches all accounts and their balances
+    pub fn accounts(&mut self) -> Result<BTreeMap<String, Units>, ClientError> {
+        self.call("accounts", Value::Null)
+    }
+
+    /// Fetches the savings goals of every account that has some, by account and goal name
+    pub fn goals(&mut self) -> Result<BTreeMap<String, BTreeMap<String, Goal>>, ClientError> {
+        self.call("goals", Value::Null)
+    }
+
+    /// Subscribes to the transactions the server commits from now on, only those affecting
+    /// `account` if given, through its `/ws/txs` WebSocket
+    pub fn subscribe(&self, account: Option<&str>) -> Result<TxStream, ClientError> {
+        let base = self.endpoint.trim_end_matches("/rpc");
+        let mut url = match base.strip_prefix("http") {
+            Some(rest) => format!("ws{}/ws/txs", rest),
+            None => format!("{}/ws/txs", base),
+        };
+        if let Some(account) = account {
+            url = format!("{}?account={}", url, account);
+        }
+        let (socket, _) =
+            tungstenite::connect(url).map_err(|e| ClientError::Transport(e.to_string()))?;
+        Ok(TxStream {
+            socket,
+            closed: false,
+        })
+    }
+}
+
+/// Transactions pushed by a server as they're committed, see [`RemoteLedger::subscribe`].
+/// Ends when the server closes the connection, after yielding the error if it broke.
+pub struct TxStream {
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    closed: bool,
+}
+
+impl Iterator for TxStream {
+    type Item = Result<Tx, ClientError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.closed {
+            match self.socket.read() {
+                Ok(Message::Text(json)) => {
+                    return Some(
+                        serde_json::from_str(&json)
+                            .map_err(|e| ClientError::InvalidResponse(e.to_string())),
+                    )
+                }
+                Ok(Message::Close(_)) | Err(tungstenite::Error::ConnectionClosed) => {
+                    self.closed = true
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    self.closed = true;
+                    return Some(Err(ClientError::Transport(e.to_string())));
+                }
+            }
+        }
+        None
+    }
+}
+
+fn single(mut txs: Vec<Tx>) -> Result<Tx, ClientError> {
+    match (txs.pop(), txs.is_empty()) {
+        (Some(tx), true) => Ok(tx),
+        _ => Err(ClientError::InvalidResponse(
+            "expected a single transaction".to_string(),
+        )),
+    }
+}
+
+/// The withdrawal and deposit of a transfer, after the promotional credit it spent and before
+/// its fee
+fn transfer(txs: Vec<Tx>) -> Result<(Tx, Tx), ClientError> {
+    let mut txs = txs
+        .into_iter()
+        .filter(|tx| !matches!(tx, Tx::PromoSpend { .. }));
+    match (txs.next(), txs.next()) {
+        (Some(withdrawal), Some(deposit)) => Ok((withdrawal, deposit)),
+        _ => Err(ClientError::InvalidResponse(
+            "expected a transfer".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{accounts::Accounts, rpc, rpc::RpcServer, server::HttpServer};
+    use std::thread;
+
+    fn remote() -> RemoteLedger {
+        let server = HttpServer::bind("127.0.0.1:0", RpcServer::new(Accounts::new())).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+        RemoteLedger::new(&format!("http://{}/", addr))
+    }
+
+    #[test]
+    fn test_remote_ledger_operations_work() {
+        let mut ledger = remote();
+
+        assert!(ledger.deposit("ALICE", 100).is_ok());
+        assert!(ledger.withdraw("ALICE", 10).is_ok());
+        let (withdrawal, deposit) = ledger.send("ALICE", "BOB", 40).unwrap();
+
+        assert_eq!(withdrawal.account(), "ALICE");
+        assert_eq!(deposit.account(), "BOB");
+        assert_eq!(ledger.balance_of("ALICE").unwrap(), 50);
+        assert_eq!(
+            ledger.accounts().unwrap(),
+            BTreeMap::from([("ALICE".to_string(), 50), ("BOB".to_string(), 40)])
+        );
+    }
+
+    #[test]
+    fn test_remote_ledger_subscribe_streams_committed_txs() {
+        let mut ledger = remote();
+        let mut txs = ledger.subscribe(Some("BOB")).unwrap();
+
+        //act
+        ledger.deposit("ALICE", 100).unwrap();
+        ledger.send("ALICE", "BOB", 40).unwrap();
+
+        assert_eq!(
+            txs.next().unwrap().unwrap(),
+            Tx::Deposit {
+                account: "BOB".into(),
+                amount: 40
+            }
+        );
+    }
+
+    #[test]
+    fn test_remote_ledger_returns_server_errors() {
+        let mut ledger = remote();
+
+        match ledger.withdraw("ALICE", 10) {
+            Err(ClientError::Rpc(e)) => assert_eq!(e.code, rpc::ACCOUNT_NOT_FOUND),
+            other => panic!("Expected an RPC error but got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_remote_ledger_unreachable_server() {
+        let mut ledger = RemoteLedger::new("http://127.0.0.1:1");
+
+        assert!(matches!(
+            ledger.balance_of("ALICE"),
+            Err(ClientError::Transport(_))
+        ));
+    }
+}
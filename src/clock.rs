@@ -0,0 +1,173 @@
+//! The source of the current time for everything time-dependent, so tests can control it.
+
+use crate::date::Date;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MILLIS_PER_DAY: u64 = 86_400_000;
+
+/// A point in time as milliseconds since the Unix epoch, UTC
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Timestamp(pub u64);
+
+impl Timestamp {
+    /// Midnight UTC at the start of `date`; dates before the epoch are clamped to it
+    pub fn start_of(date: Date) -> Self {
+        Timestamp(date.unix_days().max(0) as u64 * MILLIS_PER_DAY)
+    }
+
+    /// The last millisecond of `date` in UTC
+    pub fn end_of(date: Date) -> Self {
+        Timestamp(Timestamp::start_of(date).0 + MILLIS_PER_DAY - 1)
+    }
+
+    /// The UTC date this timestamp falls on
+    pub fn date(&self) -> Date {
+        Date::from_unix_days((self.0 / MILLIS_PER_DAY) as i64)
+    }
+
+    /// `YYYYMMDDTHHMMSSZ`, e.g. for file names
+    pub fn compact(&self) -> String {
+        let seconds = self.0 % MILLIS_PER_DAY / 1000;
+        format!(
+            "{}T{:02}{:02}{:02}Z",
+            self.date().compact(),
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    }
+
+    /// Parses [`Timestamp::compact`]
+    pub fn parse_compact(s: &str) -> Option<Self> {
+        let (date, time) = s.strip_suffix('Z')?.split_once('T')?;
+        if date.len() != 8 || time.len() != 6 || !s.is_ascii() {
+            return None;
+        }
+        let date = Date::new(
+            date[..4].parse().ok()?,
+            date[4..6].parse().ok()?,
+            date[6..].parse().ok()?,
+        )?;
+        let (hours, minutes, seconds): (u64, u64, u64) = (
+            time[..2].parse().ok()?,
+            time[2..4].parse().ok()?,
+            time[4..].parse().ok()?,
+        );
+        if hours > 23 || minutes > 59 || seconds > 59 {
+            return None;
+        }
+        Some(Timestamp(
+            Timestamp::start_of(date).0 + (hours * 3600 + minutes * 60 + seconds) * 1000,
+        ))
+    }
+
+    /// This timestamp moved forward by `duration`
+    pub fn add(&self, duration: Duration) -> Self {
+        Timestamp(self.0.saturating_add(duration.as_millis() as u64))
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let millis = self.0 % MILLIS_PER_DAY;
+        write!(
+            f,
+            "{}T{:02}:{:02}:{:02}.{:03}Z",
+            self.date().iso(),
+            millis / 3_600_000,
+            millis / 60_000 % 60,
+            millis / 1000 % 60,
+            millis % 1000
+        )
+    }
+}
+
+/// Tells the current time
+pub trait Clock: Send + Sync + fmt::Debug {
+    fn now(&self) -> Timestamp;
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> Timestamp {
+        (**self).now()
+    }
+}
+
+/// The operating system's wall clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Timestamp {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Timestamp(since_epoch.as_millis() as u64)
+    }
+}
+
+/// A clock that only moves when told to, for deterministic tests
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: AtomicU64,
+}
+
+impl ManualClock {
+    /// A clock standing still at `now`
+    pub fn new(now: Timestamp) -> Self {
+        ManualClock {
+            now: AtomicU64::new(now.0),
+        }
+    }
+
+    /// Jumps to `now`, which may be in the past
+    pub fn set(&self, now: Timestamp) {
+        self.now.store(now.0, Ordering::SeqCst);
+    }
+
+    /// Moves the clock forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        self.now
+            .fetch_add(duration.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Timestamp {
+        Timestamp(self.now.load(Ordering::SeqCst))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock_works() {
+        let clock = ManualClock::new(Timestamp::start_of(Date::new(2024, 1, 31).unwrap()));
+
+        //act
+        clock.advance(Duration::from_secs(86_400 + 3_661));
+
+        assert_eq!(clock.now().date(), Date::new(2024, 2, 1).unwrap());
+        assert_eq!(clock.now().to_string(), "2024-02-01T01:01:01.000Z");
+        assert_eq!(clock.now().compact(), "20240201T010101Z");
+        assert_eq!(
+            Timestamp::parse_compact("20240201T010101Z"),
+            Some(clock.now())
+        );
+        assert_eq!(Timestamp::parse_compact("20240201T250101Z"), None);
+        assert_eq!(
+            Timestamp::end_of(clock.now().date()).to_string(),
+            "2024-02-01T23:59:59.999Z"
+        );
+    }
+}
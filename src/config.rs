@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Settings from the config file: one `key = value` per line, `#` starts a comment.
+///
+/// Command line flags take precedence over the config file, which takes precedence over
+/// the environment.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    values: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// Parses the contents of a config file
+    /// # Errors
+    /// A line is neither empty, a comment nor `key = value`; the error names the line
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut values = BTreeMap::new();
+        for (i, line) in input.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `key = value`", i + 1))?;
+            values.insert(key.trim().to_string(), value.trim().to_string());
+        }
+        Ok(Config { values })
+    }
+
+    /// Reads the config file at `path`; a missing file is an empty config
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(input) => {
+                Config::parse(&input).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// `$CRABBUX_CONFIG`, or `crabbux/config` in `$XDG_CONFIG_HOME` (defaulting to `~/.config`)
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = env::var_os("CRABBUX_CONFIG") {
+            return Some(path.into());
+        }
+        let base = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(base.join("crabbux").join("config"))
+    }
+
+    /// The value of `key`, if set
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+
+    /// Sets `key` to `value`, replacing what it was set to
+    pub fn set(&mut self, key: &str, value: impl Into<String>) {
+        self.values.insert(key.to_string(), value.into());
+    }
+
+    /// Every `<prefix><name> = value` setting as `(name, value)`, ordered by name
+    pub fn with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.values
+            .range(prefix.to_string()..)
+            .map_while(move |(key, value)| Some((key.strip_prefix(prefix)?, value.as_str())))
+    }
+}
+
+/// The settings in the format [`Config::parse`] reads, one per line ordered by key. Comments
+/// aren't kept.
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (key, value) in &self.values {
+            writeln!(f, "{} = {}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_parse_works() {
+        let config = Config::parse("# crabbux\nlocale = de_DE # German\n\n").unwrap();
+
+        assert_eq!(config.get("locale"), Some("de_DE"));
+        assert_eq!(config.get("other"), None);
+        let config =
+            Config::parse("low_balance.ALICE = 10\nlow_balance.BOB = 5\nlocale = de").unwrap();
+        let low: Vec<_> = config.with_prefix("low_balance.").collect();
+        assert_eq!(low, vec![("ALICE", "10"), ("BOB", "5")]);
+        assert_eq!(
+            Config::parse("locale"),
+            Err("line 1: expected `key = value`".to_string())
+        );
+    }
+
+    #[test]
+    fn test_config_to_string_round_trips() {
+        let mut config = Config::parse("locale = de # German").unwrap();
+
+        //act
+        config.set("currency", "EUR");
+        config.set("locale", "es");
+        let written = config.to_string();
+
+        assert_eq!(written, "currency = EUR\nlocale = es\n");
+        assert_eq!(Config::parse(&written), Ok(config));
+    }
+}
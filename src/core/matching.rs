@@ -45,19 +45,31 @@ impl MatchingEngine {
         // Orders are matched to the opposite side
         let receipt = match &partial.side {
             Side::Buy => {
-                // Implement this side of the matching!
-                todo!();
+                // Fetch all asks at or below the bid price, cheapest first
+                let orderbook_entry = self.asks.range_mut(0..=partial.price);
+
+                let receipt = MatchingEngine::match_order(&partial, orderbook_entry, ordinal)?;
+                let matched_amount: u64 = receipt.matches.iter().map(|m| m.amount).sum();
+
+                // The order wasn't fully matched
+                if matched_amount < original_amount {
+                    partial.remaining = original_amount - matched_amount;
+                    let price = partial.price;
+                    let bids = self.bids.entry(price).or_insert(vec![].into());
+                    bids.push(partial);
+                }
+                receipt
             }
             Side::Sell => {
-                // Fetch all orders in the expected price range from this side of the orderbook
-                let orderbook_entry = self.bids.range_mut(partial.price..=u64::MAX);
+                // Fetch all orders in the expected price range from this side of the orderbook, highest bid first
+                let orderbook_entry = self.bids.range_mut(partial.price..=u64::MAX).rev();
 
                 let receipt = MatchingEngine::match_order(&partial, orderbook_entry, ordinal)?;
                 let matched_amount: u64 = receipt.matches.iter().map(|m| m.amount).sum();
 
                 // The order wasn't fully matched
                 if matched_amount < original_amount {
-                    partial.amount = original_amount - matched_amount;
+                    partial.remaining = original_amount - matched_amount;
                     let price = partial.price;
                     let asks = self.asks.entry(price).or_insert(vec![].into());
                     asks.push(partial);
@@ -96,12 +108,32 @@ impl MatchingEngine {
             // The iterator contains all orderbook_entry of a price point
             match orderbook_entry.next() {
                 Some((price, orderbook_entry)) => {
+                    // Own orders are skipped and put back once this price point is done
+                    let mut own_orders = vec![];
+
                     // 1 remove the Order with the lowest sequence nr from the orderbook entry
-                    // 2 check if it's your own order
-                    // 3 subtract the amount from your current order and decide
-                    //   a. is there anything left from the match? split the Order into two and put one back into the orderbook entry
-                    //   b. if nothing is left, add the full order to your matches and continue from 1
-                    todo!()
+                    while let Some(mut position) = orderbook_entry.pop() {
+                        // 2 check if it's your own order
+                        if position.signer == order.signer {
+                            own_orders.push(position);
+                            continue;
+                        }
+
+                        // 3 subtract the amount from your current order and decide
+                        let take = remaining_amount.min(position.remaining);
+                        remaining_amount -= take;
+                        matches.push(PartialOrder::take_from(&mut position, take, *price));
+
+                        //   a. is there anything left from the match? put the rest back into the orderbook entry
+                        if position.remaining > 0 {
+                            orderbook_entry.push(position);
+                        }
+                        //   b. if nothing is left, continue from 1
+                        if remaining_amount == 0 {
+                            break;
+                        }
+                    }
+                    orderbook_entry.extend(own_orders);
                 }
                 // Nothing left to match with
                 None => break 'outer,
@@ -120,8 +152,43 @@ mod tests {
 
     #[test]
     fn test_MatchingEngine_process_partially_match_order() {
-        // Immplement me
-        todo!();
+        let mut matching_engine = MatchingEngine::new();
+
+        let alice_receipt = matching_engine
+            .process(Order {
+                price: 10,
+                amount: 1,
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+            })
+            .unwrap();
+        assert_eq!(alice_receipt.matches, vec![]);
+        assert_eq!(alice_receipt.ordinal, 1);
+
+        let bob_receipt = matching_engine
+            .process(Order {
+                price: 10,
+                amount: 2,
+                side: Side::Buy,
+                signer: "BOB".to_string(),
+            })
+            .unwrap();
+
+        assert_eq!(
+            bob_receipt.matches,
+            vec![PartialOrder {
+                price: 10,
+                amount: 1,
+                remaining: 0,
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+                ordinal: 1
+            }]
+        );
+        // The unmatched remainder of the order stays in the book
+        assert!(matching_engine.asks.is_empty());
+        assert_eq!(matching_engine.bids.len(), 1);
+        assert_eq!(matching_engine.bids[&10].peek().unwrap().remaining, 1);
     }
 
     #[test]
@@ -1,4 +1,4 @@
-use std::cmp::Reverse;
+use std::cmp::{Ordering, Reverse};
 
 /// Simplified side of a position as well as order.
 #[derive(Clone, PartialOrd, PartialEq, Eq, Debug, Ord)]
@@ -23,7 +23,6 @@ pub struct Order {
 }
 
 impl Order {
-
     /// Convert an [`Order`] into a [`PartialOrder`] with the added parameters
     pub fn into_partial_order(self, ordinal: u64, remaining: u64) -> PartialOrder {
         let Order {
@@ -44,7 +43,7 @@ impl Order {
 }
 
 /// A position represents an unfilled order that is kept in the system for later filling.
-#[derive(Clone, PartialEq, Debug, Eq, Ord)]
+#[derive(Clone, PartialEq, Debug, Eq)]
 pub struct PartialOrder {
     /// Price per unit
     pub price: u64,
@@ -60,10 +59,16 @@ pub struct PartialOrder {
     pub ordinal: u64,
 }
 
-impl PartialOrd for PartialOrder {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+impl Ord for PartialOrder {
+    fn cmp(&self, other: &Self) -> Ordering {
         // this reverses the comparison to create a min heap
-        Reverse(self.ordinal).partial_cmp(&Reverse(other.ordinal))
+        Reverse(self.ordinal).cmp(&Reverse(other.ordinal))
+    }
+}
+
+impl PartialOrd for PartialOrder {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
@@ -78,7 +83,6 @@ pub struct Receipt {
 }
 
 impl PartialOrder {
-    
     /// Splits one [`PartialOrder`] into two by taking a defined `take` amount
     pub fn take_from(pos: &mut PartialOrder, take: u64, price: u64) -> PartialOrder {
         pos.remaining -= take;
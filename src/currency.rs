@@ -0,0 +1,298 @@
+//! Currency codes and their precision: the ISO 4217 currencies, and custom codes like `CRAB`
+//! registered with `currency.<code> = <decimals>` in the config file.
+//!
+//! A ledger's `currency = <code>` should be one of them, see [`CurrencyRegistry::validate`];
+//! its amounts are still entered and shown with the ledger's `decimals`, see
+//! [`crate::amount`], so a ledger keeps reading its log the same way whatever its currency.
+
+use crate::amount::MAX_DECIMALS;
+use crate::config::Config;
+use std::collections::BTreeMap;
+
+/// The active ISO 4217 currencies and funds by code, with the decimal places of their minor
+/// unit. Precious metals and other codes without a minor unit are left out.
+pub const ISO_4217: &[(&str, u32)] = &[
+    ("AED", 2),
+    ("AFN", 2),
+    ("ALL", 2),
+    ("AMD", 2),
+    ("ANG", 2),
+    ("AOA", 2),
+    ("ARS", 2),
+    ("AUD", 2),
+    ("AWG", 2),
+    ("AZN", 2),
+    ("BAM", 2),
+    ("BBD", 2),
+    ("BDT", 2),
+    ("BGN", 2),
+    ("BHD", 3),
+    ("BIF", 0),
+    ("BMD", 2),
+    ("BND", 2),
+    ("BOB", 2),
+    ("BOV", 2),
+    ("BRL", 2),
+    ("BSD", 2),
+    ("BTN", 2),
+    ("BWP", 2),
+    ("BYN", 2),
+    ("BZD", 2),
+    ("CAD", 2),
+    ("CDF", 2),
+    ("CHE", 2),
+    ("CHF", 2),
+    ("CHW", 2),
+    ("CLF", 4),
+    ("CLP", 0),
+    ("CNY", 2),
+    ("COP", 2),
+    ("COU", 2),
+    ("CRC", 2),
+    ("CUP", 2),
+    ("CVE", 2),
+    ("CZK", 2),
+    ("DJF", 0),
+    ("DKK", 2),
+    ("DOP", 2),
+    ("DZD", 2),
+    ("EGP", 2),
+    ("ERN", 2),
+    ("ETB", 2),
+    ("EUR", 2),
+    ("FJD", 2),
+    ("FKP", 2),
+    ("GBP", 2),
+    ("GEL", 2),
+    ("GHS", 2),
+    ("GIP", 2),
+    ("GMD", 2),
+    ("GNF", 0),
+    ("GTQ", 2),
+    ("GYD", 2),
+    ("HKD", 2),
+    ("HNL", 2),
+    ("HTG", 2),
+    ("HUF", 2),
+    ("IDR", 2),
+    ("ILS", 2),
+    ("INR", 2),
+    ("IQD", 3),
+    ("IRR", 2),
+    ("ISK", 0),
+    ("JMD", 2),
+    ("JOD", 3),
+    ("JPY", 0),
+    ("KES", 2),
+    ("KGS", 2),
+    ("KHR", 2),
+    ("KMF", 0),
+    ("KPW", 2),
+    ("KRW", 0),
+    ("KWD", 3),
+    ("KYD", 2),
+    ("KZT", 2),
+    ("LAK", 2),
+    ("LBP", 2),
+    ("LKR", 2),
+    ("LRD", 2),
+    ("LSL", 2),
+    ("LYD", 3),
+    ("MAD", 2),
+    ("MDL", 2),
+    ("MGA", 2),
+    ("MKD", 2),
+    ("MMK", 2),
+    ("MNT", 2),
+    ("MOP", 2),
+    ("MRU", 2),
+    ("MUR", 2),
+    ("MVR", 2),
+    ("MWK", 2),
+    ("MXN", 2),
+    ("MXV", 2),
+    ("MYR", 2),
+    ("MZN", 2),
+    ("NAD", 2),
+    ("NGN", 2),
+    ("NIO", 2),
+    ("NOK", 2),
+    ("NPR", 2),
+    ("NZD", 2),
+    ("OMR", 3),
+    ("PAB", 2),
+    ("PEN", 2),
+    ("PGK", 2),
+    ("PHP", 2),
+    ("PKR", 2),
+    ("PLN", 2),
+    ("PYG", 0),
+    ("QAR", 2),
+    ("RON", 2),
+    ("RSD", 2),
+    ("RUB", 2),
+    ("RWF", 0),
+    ("SAR", 2),
+    ("SBD", 2),
+    ("SCR", 2),
+    ("SDG", 2),
+    ("SEK", 2),
+    ("SGD", 2),
+    ("SHP", 2),
+    ("SLE", 2),
+    ("SOS", 2),
+    ("SRD", 2),
+    ("SSP", 2),
+    ("STN", 2),
+    ("SVC", 2),
+    ("SYP", 2),
+    ("SZL", 2),
+    ("THB", 2),
+    ("TJS", 2),
+    ("TMT", 2),
+    ("TND", 3),
+    ("TOP", 2),
+    ("TRY", 2),
+    ("TTD", 2),
+    ("TWD", 2),
+    ("TZS", 2),
+    ("UAH", 2),
+    ("UGX", 0),
+    ("USD", 2),
+    ("USN", 2),
+    ("UYI", 0),
+    ("UYU", 2),
+    ("UYW", 4),
+    ("UZS", 2),
+    ("VED", 2),
+    ("VES", 2),
+    ("VND", 0),
+    ("VUV", 0),
+    ("WST", 2),
+    ("XAF", 0),
+    ("XCD", 2),
+    ("XCG", 2),
+    ("XOF", 0),
+    ("XPF", 0),
+    ("YER", 2),
+    ("ZAR", 2),
+    ("ZMW", 2),
+    ("ZWG", 2),
+];
+
+/// The decimal places of the ISO 4217 currency `code`, in upper case, or `None` if it isn't one
+pub fn iso_decimals(code: &str) -> Option<u32> {
+    ISO_4217
+        .binary_search_by_key(&code, |&(iso, _)| iso)
+        .ok()
+        .map(|index| ISO_4217[index].1)
+}
+
+/// The currencies a ledger knows: the ISO 4217 ones and those registered on top
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CurrencyRegistry {
+    custom: BTreeMap<String, u32>,
+}
+
+impl CurrencyRegistry {
+    /// Registers every `currency.<code> = <decimals>` setting
+    /// # Errors
+    /// A registration is invalid, see [`CurrencyRegistry::register`]; the error names the
+    /// setting
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let mut registry = CurrencyRegistry::default();
+        for (code, decimals) in config.with_prefix("currency.") {
+            let decimals = decimals
+                .parse()
+                .map_err(|_| format!("currency.{}: invalid decimals {:?}", code, decimals))?;
+            registry
+                .register(code, decimals)
+                .map_err(|e| format!("currency.{}: {}", code, e))?;
+        }
+        Ok(registry)
+    }
+
+    /// Adds the custom currency `code` with `decimals` decimal places, replacing its
+    /// registration. The code is kept in upper case.
+    /// # Errors
+    /// The code isn't 3 to 12 ASCII letters and digits starting with a letter, is an ISO 4217
+    /// code, or `decimals` is more than [`MAX_DECIMALS`]
+    pub fn register(&mut self, code: &str, decimals: u32) -> Result<(), String> {
+        let code = code.to_uppercase();
+        let valid = (3..=12).contains(&code.len())
+            && code.starts_with(|c: char| c.is_ascii_alphabetic())
+            && code.chars().all(|c| c.is_ascii_alphanumeric());
+        if !valid {
+            return Err(format!("invalid currency code {:?}", code));
+        }
+        if iso_decimals(&code).is_some() {
+            return Err(format!("{} is an ISO 4217 currency", code));
+        }
+        if decimals > MAX_DECIMALS {
+            return Err(format!(
+                "expected 0 to {} decimals, got {}",
+                MAX_DECIMALS, decimals
+            ));
+        }
+        self.custom.insert(code, decimals);
+        Ok(())
+    }
+
+    /// The decimal places of `code`, whatever its case, or `None` if it's unknown
+    pub fn decimals_of(&self, code: &str) -> Option<u32> {
+        let code = code.to_uppercase();
+        iso_decimals(&code).or_else(|| self.custom.get(&code).copied())
+    }
+
+    /// The decimal places of `code`, see [`CurrencyRegistry::decimals_of`]
+    /// # Errors
+    /// `code` is neither an ISO 4217 currency nor registered
+    pub fn validate(&self, code: &str) -> Result<u32, String> {
+        self.decimals_of(code).ok_or_else(|| {
+            format!(
+                "unknown currency {}, register it with currency.{} = <decimals>",
+                code,
+                code.to_uppercase()
+            )
+        })
+    }
+
+    /// The registered custom currencies by code, with their decimal places
+    pub fn custom(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.custom
+            .iter()
+            .map(|(code, decimals)| (code.as_str(), *decimals))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_currency_registry_knows_iso_and_custom_codes() {
+        let config = Config::parse("currency = CRAB\ncurrency.crab = 4").unwrap();
+
+        //act
+        let mut registry = CurrencyRegistry::from_config(&config).unwrap();
+        let shadowing = registry.register("EUR", 3);
+        let invalid = registry.register("1X", 2);
+        let too_precise = registry.register("SATS", 19);
+
+        assert!(ISO_4217.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(registry.validate("usd"), Ok(2));
+        assert_eq!(registry.validate("JPY"), Ok(0));
+        assert_eq!(registry.validate("KWD"), Ok(3));
+        assert_eq!(registry.validate("CRAB"), Ok(4));
+        assert!(registry
+            .validate("XYZ")
+            .unwrap_err()
+            .contains("currency.XYZ"));
+        assert!(shadowing.is_err() && invalid.is_err() && too_precise.is_err());
+        assert_eq!(registry.custom().collect::<Vec<_>>(), vec![("CRAB", 4)]);
+        let invalid = Config::parse("currency.CRAB = lots").unwrap();
+        assert!(CurrencyRegistry::from_config(&invalid)
+            .unwrap_err()
+            .starts_with("currency.CRAB"));
+    }
+}
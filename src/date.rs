@@ -0,0 +1,104 @@
+use crate::clock::{Clock, SystemClock};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// A calendar date in the proleptic Gregorian calendar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    /// The given date, if it exists
+    pub fn new(year: i32, month: u32, day: u32) -> Option<Self> {
+        let date = Date { year, month, day };
+        // Out of range days and months roll over, so they don't survive the round trip
+        ((1..=12).contains(&month) && Date::from_unix_days(date.unix_days()) == date)
+            .then_some(date)
+    }
+
+    /// Parses `YYYY-MM-DD`
+    pub fn parse_iso(s: &str) -> Option<Self> {
+        let mut parts = s.splitn(3, '-');
+        let year = parts.next()?.parse().ok()?;
+        let month = parts.next()?.parse().ok()?;
+        let day = parts.next()?.parse().ok()?;
+        Date::new(year, month, day)
+    }
+
+    /// The date `days` days after 1970-01-01
+    pub fn from_unix_days(days: i64) -> Self {
+        // Howard Hinnant's `civil_from_days`
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = (yoe + era * 400 + i64::from(month <= 2)) as i32;
+        Date { year, month, day }
+    }
+
+    /// The number of days since 1970-01-01, the inverse of [`Date::from_unix_days`]
+    pub fn unix_days(&self) -> i64 {
+        // Howard Hinnant's `days_from_civil`
+        let y = i64::from(self.year) - i64::from(self.month <= 2);
+        let era = y.div_euclid(400);
+        let yoe = y.rem_euclid(400);
+        let m = i64::from(self.month);
+        let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + i64::from(self.day) - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146_097 + doe - 719_468
+    }
+
+    /// `YYYY-MM-DD`
+    pub fn iso(&self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+
+    /// `YYYYMMDD`
+    pub fn compact(&self) -> String {
+        format!("{:04}{:02}{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Stored as `YYYY-MM-DD`
+impl Serialize for Date {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.iso())
+    }
+}
+
+impl<'de> Deserialize<'de> for Date {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let date = String::deserialize(deserializer)?;
+        Date::parse_iso(&date).ok_or_else(|| de::Error::custom(format!("invalid date {}", date)))
+    }
+}
+
+/// The current date in UTC according to the [`SystemClock`]
+pub fn today() -> Date {
+    SystemClock.now().date()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_date_from_unix_days_works() {
+        assert_eq!(Date::from_unix_days(0).iso(), "1970-01-01");
+        assert_eq!(Date::from_unix_days(11_016).iso(), "2000-02-29");
+        assert_eq!(Date::from_unix_days(20_740).compact(), "20261014");
+        assert_eq!(Date::from_unix_days(-1).iso(), "1969-12-31");
+        assert_eq!(Date::parse_iso("2024-02-29"), Date::new(2024, 2, 29));
+        assert_eq!(Date::parse_iso("2023-02-29"), None);
+        assert_eq!(Date::new(2024, 13, 1), None);
+        for days in [-1, 0, 59, 11_016, 20_740] {
+            assert_eq!(Date::from_unix_days(days).unix_days(), days);
+        }
+    }
+}
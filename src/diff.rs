@@ -0,0 +1,93 @@
+use crate::{accounts::Accounts, snapshot::Snapshot, tx::Units};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// How the balances of one ledger differ from another's, each list in account order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LedgerDiff {
+    /// Accounts only in the right ledger, with their balance
+    pub added: Vec<(String, Units)>,
+    /// Accounts only in the left ledger, with their balance
+    pub removed: Vec<(String, Units)>,
+    /// Accounts in both with a different balance, as `(account, left, right)`
+    pub changed: Vec<(String, Units, Units)>,
+}
+
+impl LedgerDiff {
+    /// Compares two sets of balances
+    pub fn between(left: &BTreeMap<String, Units>, right: &BTreeMap<String, Units>) -> Self {
+        let mut diff = LedgerDiff::default();
+        for (account, &balance) in left {
+            match right.get(account) {
+                None => diff.removed.push((account.clone(), balance)),
+                Some(&other) if other != balance => {
+                    diff.changed.push((account.clone(), balance, other))
+                }
+                Some(_) => {}
+            }
+        }
+        for (account, &balance) in right {
+            if !left.contains_key(account) {
+                diff.added.push((account.clone(), balance));
+            }
+        }
+        diff
+    }
+
+    /// Compares two ledgers
+    pub fn of(left: &Accounts, right: &Accounts) -> Self {
+        LedgerDiff::between(
+            &Snapshot::of(left, 0).balances,
+            &Snapshot::of(right, 0).balances,
+        )
+    }
+
+    /// Returns `true` if both sides have the same balances
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// One line per difference: `+ account balance`, `- account balance` and
+/// `~ account left -> right (delta)`
+impl fmt::Display for LedgerDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (account, balance) in &self.added {
+            writeln!(f, "+ {} {}", account, balance)?;
+        }
+        for (account, balance) in &self.removed {
+            writeln!(f, "- {} {}", account, balance)?;
+        }
+        for (account, left, right) in &self.changed {
+            let delta = *right as i128 - *left as i128;
+            writeln!(f, "~ {} {} -> {} ({:+})", account, left, right, delta)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ledger_diff_works() {
+        let mut left = Accounts::new();
+        left.deposit("ALICE", 10).unwrap();
+        left.deposit("BOB", 5).unwrap();
+        left.deposit("CAROL", 1).unwrap();
+        let mut right = Accounts::new();
+        right.deposit("ALICE", 4).unwrap();
+        right.deposit("CAROL", 1).unwrap();
+        right.deposit("DAVE", 2).unwrap();
+
+        //act
+        let diff = LedgerDiff::of(&left, &right);
+
+        assert_eq!(
+            diff.to_string(),
+            "+ DAVE 2\n- BOB 5\n~ ALICE 10 -> 4 (-6)\n"
+        );
+        assert!(LedgerDiff::of(&left, &left).is_empty());
+    }
+}
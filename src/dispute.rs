@@ -0,0 +1,86 @@
+//! Disputed deposits: the disputed amount is frozen until the dispute is upheld, which reverses
+//! the deposit, or denied, which unfreezes it, see [`crate::accounts::Accounts::open_dispute`].
+//! Disputes are opened for the account that received the deposit and resolved by an admin.
+//!
+//! Like escrows, the frozen funds are kept in an account of their own whose name records the
+//! dispute, `dispute:<position>:<account>`, where `position` is the disputed entry's position
+//! in the tx log. Every step is an ordinary transaction, so the log tells the state of each
+//! dispute, see [`crate::history::TxLog::disputes`].
+
+use serde::Serialize;
+use std::fmt;
+use std::sync::Arc;
+
+/// The prefix of every dispute account; normal operations can't touch accounts starting with it
+pub const DISPUTE_PREFIX: &str = "dispute:";
+
+/// Funds of `account` frozen while the deposit at `position` is disputed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dispute {
+    pub position: usize,
+    pub account: String,
+    /// The account holding the frozen funds
+    pub holding: Arc<str>,
+}
+
+impl Dispute {
+    /// The dispute recorded by the name of `holding`, if it is a dispute account
+    pub fn parse(holding: &Arc<str>) -> Option<Self> {
+        let (position, account) = holding.strip_prefix(DISPUTE_PREFIX)?.split_once(':')?;
+        Some(Dispute {
+            position: position.parse().ok()?,
+            account: account.to_string(),
+            holding: holding.clone(),
+        })
+    }
+}
+
+/// Where a dispute stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeState {
+    /// The disputed amount is frozen
+    Open,
+    /// The deposit was reversed
+    Upheld,
+    /// The disputed amount was unfrozen
+    Denied,
+}
+
+impl fmt::Display for DisputeState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            DisputeState::Open => "disputed",
+            DisputeState::Upheld => "reversed",
+            DisputeState::Denied => "dispute denied",
+        })
+    }
+}
+
+/// The name of the account holding the funds frozen by disputing the entry at `position`
+pub fn account_name(position: usize, account: &str) -> String {
+    format!("{}{}:{}", DISPUTE_PREFIX, position, account)
+}
+
+/// Returns `true` if `account` belongs to a dispute
+pub fn is_dispute(account: &str) -> bool {
+    account.starts_with(DISPUTE_PREFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dispute_account_name_round_trips() {
+        let name: Arc<str> = account_name(12, "A:B").into();
+
+        //act
+        let dispute = Dispute::parse(&name).unwrap();
+
+        assert_eq!(&*name, "dispute:12:A:B");
+        assert_eq!((dispute.position, &*dispute.account), (12, "A:B"));
+        assert_eq!(Dispute::parse(&"dispute:x:A".into()), None);
+        assert_eq!(Dispute::parse(&"escrow:1:A:B".into()), None);
+    }
+}
@@ -0,0 +1,275 @@
+//! Trying mutating operations without committing them: the transactions they would commit, how
+//! the balances would change and which thresholds or anomalies they would trigger.
+//!
+//! [`run`] applies an operation to an [`Accounts::fork`], leaving the ledger itself untouched.
+//! A ledger that is thrown away afterwards anyway, like one loaded from a log for a single
+//! command, can be watched by a [`Recorder`] directly instead. [`Accounts::simulate`] projects
+//! a single [`Op`], e.g. for a UI to show what a transfer leaves before confirming it, and
+//! [`Accounts::simulate_batch`] a whole sequence of them.
+
+use crate::{
+    accounts::Accounts,
+    diff::LedgerDiff,
+    errors::ApplicationError,
+    events::{LedgerEvent, OwnershipChange, Threshold},
+    metadata::LedgerMetadata,
+    snapshot::Snapshot,
+    tx::{Tx, Units},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// A ledger operation as issued by a user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Op {
+    Deposit {
+        account: String,
+        amount: Units,
+    },
+    Withdraw {
+        account: String,
+        amount: Units,
+    },
+    Send {
+        sender: String,
+        recipient: String,
+        amount: Units,
+    },
+}
+
+impl Op {
+    /// Performs the operation on `ledger` like [`Accounts::deposit`], [`Accounts::withdraw`] and
+    /// [`Accounts::pay`] do, and returns the committed transactions
+    pub fn apply(&self, ledger: &mut Accounts) -> Result<Vec<Tx>, ApplicationError> {
+        match self {
+            Op::Deposit { account, amount } => ledger.deposit(account, *amount).map(|tx| vec![tx]),
+            Op::Withdraw { account, amount } => {
+                ledger.withdraw(account, *amount).map(|tx| vec![tx])
+            }
+            Op::Send {
+                sender,
+                recipient,
+                amount,
+            } => ledger.pay(sender, recipient, *amount),
+        }
+    }
+
+    /// The accounts the operation involves
+    pub fn accounts(&self) -> Vec<&str> {
+        match self {
+            Op::Deposit { account, .. } | Op::Withdraw { account, .. } => vec![account],
+            Op::Send {
+                sender, recipient, ..
+            } => vec![sender, recipient],
+        }
+    }
+}
+
+/// What [`Accounts::simulate`] projects an [`Op`] to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulationResult {
+    /// The balances of the accounts the operation involves afterwards, the current ones if it
+    /// would fail. Accounts that wouldn't exist are left out.
+    pub balances: BTreeMap<String, Units>,
+    /// What it would commit and trigger, nothing if it would fail
+    pub dry_run: DryRun,
+    /// Why it would fail, if it would
+    pub error: Option<ApplicationError>,
+}
+
+/// What an operation would do to a ledger, see [`run`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DryRun {
+    /// The transactions it would commit, in order
+    pub txs: Vec<Tx>,
+    /// How the balances would change
+    pub balances: LedgerDiff,
+    /// Accounts whose metadata would change, in name order
+    pub metadata_changed: Vec<String>,
+    /// What else would be published, like crossed thresholds, detected anomalies and changed
+    /// owners
+    pub events: Vec<LedgerEvent>,
+}
+
+impl DryRun {
+    /// Returns `true` if the operation wouldn't change anything
+    pub fn is_empty(&self) -> bool {
+        self.txs.is_empty() && self.balances.is_empty() && self.metadata_changed.is_empty()
+    }
+}
+
+/// The transactions, the balances as [`LedgerDiff`] shows them, then a `* account` line per
+/// changed metadata and a `! ...` line per other event
+impl fmt::Display for DryRun {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "would commit {} transactions", self.txs.len())?;
+        for tx in &self.txs {
+            writeln!(f, "  {} {} {}", tx.kind(), tx.account(), tx.amount())?;
+        }
+        write!(f, "{}", self.balances)?;
+        for account in &self.metadata_changed {
+            writeln!(f, "* {}", account)?;
+        }
+        for event in &self.events {
+            match event {
+                LedgerEvent::ThresholdCrossed {
+                    account,
+                    threshold: Threshold::Low(limit),
+                    balance,
+                } => writeln!(f, "! {} drops below {} to {}", account, limit, balance)?,
+                LedgerEvent::ThresholdCrossed {
+                    account,
+                    threshold: Threshold::High(limit),
+                    balance,
+                } => writeln!(f, "! {} rises above {} to {}", account, limit, balance)?,
+                LedgerEvent::AnomalyDetected { anomaly, .. } => writeln!(f, "! {}", anomaly)?,
+                LedgerEvent::GoalReached {
+                    account,
+                    goal,
+                    target,
+                } => writeln!(f, "! {} reaches its goal {} of {}", account, goal, target)?,
+                LedgerEvent::OwnershipChanged {
+                    account,
+                    change: OwnershipChange::Added(owner),
+                    ..
+                } => writeln!(f, "! {} becomes an owner of {}", owner, account)?,
+                LedgerEvent::OwnershipChanged {
+                    account,
+                    change: OwnershipChange::Removed(owner),
+                    ..
+                } => writeln!(f, "! {} stops being an owner of {}", owner, account)?,
+                LedgerEvent::AccountCreated { .. }
+                | LedgerEvent::TxCommitted(_)
+                | LedgerEvent::TxFailed { .. } => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What [`Accounts::simulate_batch`] projects a sequence of [`Op`]s to do, e.g. a payroll run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchSimulation {
+    /// The balances of all accounts after the operations before the first failing one
+    pub balances: BTreeMap<String, Units>,
+    /// What those operations would commit and trigger together
+    pub dry_run: DryRun,
+    /// The position in the batch of the first operation that would fail, and why
+    pub failure: Option<(usize, ApplicationError)>,
+}
+
+impl BatchSimulation {
+    /// Returns `true` if every operation would go through
+    pub fn succeeds(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+/// Watches a ledger from [`Recorder::start`] on, for [`Recorder::finish`] to tell what was done
+/// to it.
+///
+/// Its listener stays registered, so it's meant for ledgers that are thrown away afterwards.
+#[derive(Debug)]
+pub struct Recorder {
+    balances: BTreeMap<String, Units>,
+    metadata: LedgerMetadata,
+    events: Arc<Mutex<Vec<LedgerEvent>>>,
+}
+
+impl Recorder {
+    /// Remembers the state of `ledger` and starts collecting its events
+    pub fn start(ledger: &mut Accounts) -> Self {
+        let events: Arc<Mutex<Vec<LedgerEvent>>> = Default::default();
+        let collected = events.clone();
+        ledger.subscribe(move |event| collected.lock().unwrap().push(event.clone()));
+        Recorder {
+            balances: Snapshot::of(ledger, 0).balances,
+            metadata: ledger.metadata().clone(),
+            events,
+        }
+    }
+
+    /// What was done to `ledger` since [`Recorder::start`]
+    pub fn finish(self, ledger: &Accounts) -> DryRun {
+        let mut report = DryRun {
+            balances: LedgerDiff::between(&self.balances, &Snapshot::of(ledger, 0).balances),
+            metadata_changed: self.metadata.changed(ledger.metadata()),
+            ..Default::default()
+        };
+        for event in self.events.lock().unwrap().drain(..) {
+            match event {
+                LedgerEvent::TxCommitted(tx) => report.txs.push(tx),
+                // Failures are returned to whoever made the operation
+                LedgerEvent::AccountCreated { .. } | LedgerEvent::TxFailed { .. } => {}
+                event => report.events.push(event),
+            }
+        }
+        report
+    }
+}
+
+/// Applies `operation` to a fork of `ledger`, and returns what it returned along with what it
+/// did to the fork
+/// # Errors
+/// Whatever `operation` fails with
+pub fn run<T, E>(
+    ledger: &Accounts,
+    operation: impl FnOnce(&mut Accounts) -> Result<T, E>,
+) -> Result<(T, DryRun), E> {
+    let mut fork = ledger.fork();
+    let recorder = Recorder::start(&mut fork);
+    let result = operation(&mut fork)?;
+    Ok((result, recorder.finish(&fork)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accounts::Thresholds;
+
+    #[test]
+    fn test_run_reports_what_an_operation_would_do() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("ALICE", 100).unwrap();
+        ledger.set_thresholds(
+            "ALICE",
+            Thresholds {
+                low: Some(50),
+                high: None,
+            },
+        );
+        let published: Arc<Mutex<Vec<LedgerEvent>>> = Default::default();
+        let listener = published.clone();
+        ledger.subscribe(move |event| listener.lock().unwrap().push(event.clone()));
+
+        //act
+        let (txs, report) = run(&ledger, |fork| fork.send("ALICE", "BOB", 60)).unwrap();
+        let failed = run(&ledger, |fork| fork.send("ALICE", "BOB", 500));
+        let (_, blocked) = run(&ledger, |fork| {
+            fork.metadata_mut().entry("ALICE").block("EVE");
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+
+        assert_eq!(report.txs, vec![txs.0, txs.1]);
+        assert_eq!(report.balances.added, vec![("BOB".to_string(), 60)]);
+        assert_eq!(
+            report.balances.changed,
+            vec![("ALICE".to_string(), 100, 40)]
+        );
+        assert!(matches!(
+            report.events.as_slice(),
+            [LedgerEvent::ThresholdCrossed { balance: 40, .. }]
+        ));
+        assert!(report.to_string().contains("! ALICE drops below 50 to 40"));
+        assert!(failed.is_err());
+        assert_eq!(blocked.metadata_changed, vec!["ALICE"]);
+        assert!(!blocked.is_empty());
+        assert_eq!(*ledger.balance_of("ALICE").unwrap(), 100);
+        assert!(ledger.metadata().get("ALICE").is_none());
+        assert!(published.lock().unwrap().is_empty());
+    }
+}
@@ -1,7 +1,57 @@
+use crate::tx::Units;
+
 /// An application-specific error type
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ApplicationError {
     NotFound(String),
-    UnderFunded(String, u64),
-    OverFunded(String, u64),
+    UnderFunded(String, Units),
+    OverFunded(String, Units),
+    /// Persisting a change failed; the change was compensated in memory
+    Storage(String),
+    /// The account to create exists already
+    AlreadyExists(String),
+    /// The account is an escrow or dispute account, which only their own operations may touch
+    Locked(String),
+    /// The transfer needs approval and is pending with this id, see [`crate::accounts::Accounts::approve`]
+    ApprovalRequired(u64),
+    /// The signer may not do this
+    Unauthorized(String),
+    /// Sending to the account would exceed a spending limit, or collecting by it its mandate,
+    /// which has the given allowance left
+    LimitExceeded(String, Units),
+    /// The sender's allowlist or blocklist doesn't let it send to the account
+    Blocked(String),
+    /// The send looks like the described anomaly and was blocked
+    Suspicious(String),
+    /// The send looks like the described anomaly and must be confirmed, see [`crate::accounts::Accounts::send_confirmed`]
+    ConfirmationRequired(String),
+    /// The account is archived, see [`crate::accounts::Accounts::archive`]
+    Archived(String),
+    /// The ledger is read-only and the operation would change it, see [`crate::accounts::Accounts::set_read_only`]
+    PermissionDenied(String),
+    /// A persisted file doesn't match its checksum; names the file and what's wrong
+    CorruptData(String),
+}
+
+impl ApplicationError {
+    /// A short, lowercase name of the error variant
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ApplicationError::NotFound(_) => "not_found",
+            ApplicationError::UnderFunded(_, _) => "underfunded",
+            ApplicationError::OverFunded(_, _) => "overfunded",
+            ApplicationError::Storage(_) => "storage",
+            ApplicationError::AlreadyExists(_) => "already_exists",
+            ApplicationError::Locked(_) => "locked",
+            ApplicationError::ApprovalRequired(_) => "approval_required",
+            ApplicationError::Unauthorized(_) => "unauthorized",
+            ApplicationError::LimitExceeded(_, _) => "limit_exceeded",
+            ApplicationError::Blocked(_) => "blocked",
+            ApplicationError::Suspicious(_) => "suspicious",
+            ApplicationError::ConfirmationRequired(_) => "confirmation_required",
+            ApplicationError::Archived(_) => "archived",
+            ApplicationError::PermissionDenied(_) => "permission_denied",
+            ApplicationError::CorruptData(_) => "corrupt_data",
+        }
+    }
 }
@@ -0,0 +1,69 @@
+//! Escrows: funds locked to a payer and a payee until they are released or refunded, see
+//! [`crate::accounts::Accounts::hold_in_escrow`]. Only the payer releases the funds to the
+//! payee, and only the payee refunds them to the payer, unless an admin steps in.
+//!
+//! The funds of an escrow are kept in an account of its own, whose name records the escrow,
+//! so every step is an ordinary transfer in the tx log and replaying it rebuilds the escrows:
+//! `escrow:<id>:<payer>:<payee>`, with `%` and `:` in the payer escaped as `%25` and `%3A`.
+
+use std::sync::Arc;
+
+/// The prefix of every escrow account; normal operations can't touch accounts starting with it
+pub const ESCROW_PREFIX: &str = "escrow:";
+
+/// Funds held for `payee` on behalf of `payer`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Escrow {
+    pub id: u64,
+    pub payer: String,
+    pub payee: String,
+    /// The account holding the funds
+    pub account: Arc<str>,
+}
+
+impl Escrow {
+    /// The escrow recorded by the name of `account`, if it is an escrow account
+    pub fn parse(account: &Arc<str>) -> Option<Self> {
+        let rest = account.strip_prefix(ESCROW_PREFIX)?;
+        let (id, rest) = rest.split_once(':')?;
+        let (payer, payee) = rest.split_once(':')?;
+        Some(Escrow {
+            id: id.parse().ok()?,
+            payer: payer.replace("%3A", ":").replace("%25", "%"),
+            payee: payee.to_string(),
+            account: account.clone(),
+        })
+    }
+}
+
+/// The name of the account holding escrow `id`
+pub fn account_name(id: u64, payer: &str, payee: &str) -> String {
+    let payer = payer.replace('%', "%25").replace(':', "%3A");
+    format!("{}{}:{}:{}", ESCROW_PREFIX, id, payer, payee)
+}
+
+/// Returns `true` if `account` belongs to an escrow
+pub fn is_escrow(account: &str) -> bool {
+    account.starts_with(ESCROW_PREFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escrow_account_name_round_trips() {
+        let name: Arc<str> = account_name(7, "A:%B", "C:D").into();
+
+        //act
+        let escrow = Escrow::parse(&name).unwrap();
+
+        assert_eq!(&*name, "escrow:7:A%3A%25B:C:D");
+        assert_eq!(
+            (escrow.id, &*escrow.payer, &*escrow.payee),
+            (7, "A:%B", "C:D")
+        );
+        assert_eq!(Escrow::parse(&"escrow:x:A:B".into()), None);
+        assert_eq!(Escrow::parse(&"ALICE".into()), None);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{
+    anomaly::{Action, Anomaly},
+    errors::ApplicationError,
+    tx::{Tx, Units},
+};
+use std::fmt;
+
+/// Something that happened to the ledger
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LedgerEvent {
+    /// An account received its first deposit
+    AccountCreated { account: String },
+    /// A transaction was applied to the ledger
+    TxCommitted(Tx),
+    /// A committed transaction moved the balance of `account` across one of its
+    /// [`crate::accounts::Thresholds`]
+    ThresholdCrossed {
+        account: String,
+        threshold: Threshold,
+        /// The new balance, see [`crate::accounts::Accounts::signed_balance_of`]
+        balance: i128,
+    },
+    /// A committed transaction made the balance of `account` reach the target of its savings
+    /// goal called `goal`, see [`crate::goals`]
+    GoalReached {
+        account: String,
+        goal: String,
+        target: Units,
+    },
+    /// A send was flagged by the [`crate::anomaly::AnomalyPolicy`], before `action` was taken
+    AnomalyDetected { anomaly: Anomaly, action: Action },
+    /// `by`, the [`crate::accounts::Accounts::principal`] if there was one, changed the owners
+    /// of `account`
+    OwnershipChanged {
+        account: String,
+        change: OwnershipChange,
+        by: Option<String>,
+    },
+    /// An operation was rejected and nothing was changed
+    TxFailed {
+        operation: &'static str,
+        error: ApplicationError,
+    },
+}
+
+/// A change to the owners of an account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OwnershipChange {
+    Added(String),
+    Removed(String),
+}
+
+/// A balance limit from [`crate::accounts::Thresholds`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Threshold {
+    /// The balance dropped below this
+    Low(Units),
+    /// The balance rose above this
+    High(Units),
+}
+
+type Listener = Box<dyn Fn(&LedgerEvent) + Send + Sync>;
+
+/// Calls every registered listener for each published [`LedgerEvent`], in registration order.
+///
+/// Listeners run synchronously while the ledger is being modified, so they should be quick
+/// and must not call back into the ledger.
+#[derive(Default)]
+pub struct EventBus {
+    listeners: Vec<Listener>,
+}
+
+impl EventBus {
+    /// Creates a bus without listeners
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Registers `listener` to be called for every event from now on
+    pub fn subscribe(&mut self, listener: impl Fn(&LedgerEvent) + Send + Sync + 'static) {
+        self.listeners.push(Box::new(listener));
+    }
+
+    /// Passes `event` to all listeners
+    pub fn publish(&self, event: &LedgerEvent) {
+        self.listeners.iter().for_each(|listener| listener(event));
+    }
+}
+
+impl fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EventBus")
+            .field("listeners", &self.listeners.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_event_bus_publishes_to_all_listeners() {
+        let mut bus = EventBus::new();
+        let seen = Arc::new(Mutex::new(vec![]));
+        for i in 0..2 {
+            let seen = seen.clone();
+            bus.subscribe(move |event| seen.lock().unwrap().push((i, event.clone())));
+        }
+        let event = LedgerEvent::AccountCreated {
+            account: "ALICE".to_string(),
+        };
+
+        bus.publish(&event);
+
+        assert_eq!(*seen.lock().unwrap(), vec![(0, event.clone()), (1, event)]);
+    }
+}
@@ -0,0 +1,9 @@
+//! Writers turning the transaction history into formats understood by other tools.
+//!
+//! Transactions don't carry timestamps, so every writer dates all entries with the
+//! `date` it is given, usually the export date from [`crate::date::today`].
+
+pub mod beancount;
+pub mod html;
+pub mod journal;
+pub mod state;
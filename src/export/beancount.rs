@@ -0,0 +1,150 @@
+use crate::amount;
+use crate::date::Date;
+use crate::tx::Tx;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// The account balancing money entering and leaving the ledger
+pub const EXTERNAL_ACCOUNT: &str = "Equity:External";
+
+/// Writes `txs` as a beancount file.
+///
+/// Every crabbux account is opened as `Assets:Crabbux:<name>` and every transaction
+/// becomes an entry balanced against [`EXTERNAL_ACCOUNT`]. With `balance_assertions`, the
+/// final balance of every account is asserted on the day after `date`, since beancount
+/// checks assertions at the start of their day. Amounts have the `decimals` decimal places of
+/// `commodity`.
+pub fn write(
+    out: &mut impl Write,
+    txs: &[Tx],
+    date: Date,
+    commodity: &str,
+    decimals: u32,
+    balance_assertions: bool,
+) -> io::Result<()> {
+    let mut balances: BTreeMap<&str, i128> = BTreeMap::new();
+    for tx in txs {
+        let balance = balances.entry(tx.account()).or_default();
+        *balance += tx.balance_change();
+    }
+
+    writeln!(out, "; exported from crabbux")?;
+    writeln!(out, "{} open {}", date.iso(), EXTERNAL_ACCOUNT)?;
+    for account in balances.keys() {
+        writeln!(
+            out,
+            "{} open {} {}",
+            date.iso(),
+            account_name(account),
+            commodity
+        )?;
+    }
+    let format = |amount: i128| amount::format_plain(amount, decimals);
+    for tx in txs {
+        let amount = tx.amount() as i128;
+        let (narration, amount) = match tx {
+            Tx::Deposit { .. } => ("Deposit", amount),
+            Tx::Withdraw { .. } => ("Withdrawal", -amount),
+            Tx::Collect { .. } => ("Direct debit", -amount),
+            Tx::Mint { .. } => ("Mint", amount),
+            Tx::Burn { .. } => ("Burn", -amount),
+            Tx::PromoSpend { .. } => ("Promotional credit", amount),
+            // Promotional credit is only money once spent
+            Tx::PromoGrant { .. } | Tx::PromoExpire { .. } => continue,
+            // Mandates move no money
+            Tx::MandateGrant { .. } | Tx::MandateRevoke { .. } => continue,
+        };
+        writeln!(out)?;
+        writeln!(out, "{} * \"{}\"", date.iso(), narration)?;
+        writeln!(
+            out,
+            "  {}  {} {}",
+            account_name(tx.account()),
+            format(amount),
+            commodity
+        )?;
+        writeln!(
+            out,
+            "  {}  {} {}",
+            EXTERNAL_ACCOUNT,
+            format(-amount),
+            commodity
+        )?;
+    }
+    if balance_assertions {
+        let next_day = Date::from_unix_days(date.unix_days() + 1);
+        writeln!(out)?;
+        for (account, balance) in &balances {
+            writeln!(
+                out,
+                "{} balance {}  {} {}",
+                next_day.iso(),
+                account_name(account),
+                format(*balance),
+                commodity
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// `Assets:Crabbux:<name>` with `name` changed to what beancount accepts in an account
+/// component: starting with a capital letter or digit, then only letters, digits and dashes
+fn account_name(name: &str) -> String {
+    let mut component: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    match component.chars().next() {
+        Some(c) if c.is_ascii_lowercase() => {
+            component.replace_range(..1, &c.to_ascii_uppercase().to_string())
+        }
+        Some(c) if c.is_ascii_alphanumeric() => {}
+        _ => component.insert(0, 'X'),
+    }
+    format!("Assets:Crabbux:{}", component)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_beancount_works() {
+        let txs = [
+            Tx::Deposit {
+                account: "ALICE".into(),
+                amount: 100,
+            },
+            Tx::Withdraw {
+                account: "ALICE".into(),
+                amount: 30,
+                recipient: None,
+            },
+        ];
+        let mut out = vec![];
+
+        //act
+        write(&mut out, &txs, Date::from_unix_days(0), "CBX", 0, true).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "; exported from crabbux\n\
+             1970-01-01 open Equity:External\n\
+             1970-01-01 open Assets:Crabbux:ALICE CBX\n\
+             \n\
+             1970-01-01 * \"Deposit\"\n  Assets:Crabbux:ALICE  100 CBX\n  Equity:External  -100 CBX\n\
+             \n\
+             1970-01-01 * \"Withdrawal\"\n  Assets:Crabbux:ALICE  -30 CBX\n  Equity:External  30 CBX\n\
+             \n\
+             1970-01-02 balance Assets:Crabbux:ALICE  70 CBX\n"
+        );
+    }
+
+    #[test]
+    fn test_account_name_sanitizes() {
+        assert_eq!(account_name("test_account"), "Assets:Crabbux:Test-account");
+        assert_eq!(account_name("_x"), "Assets:Crabbux:X-x");
+        assert_eq!(account_name("7up"), "Assets:Crabbux:7up");
+    }
+}
@@ -0,0 +1,177 @@
+use crate::amount::{self, NumberFormat};
+use crate::date::Date;
+use crate::tx::Tx;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::ops::Range;
+
+/// Writes a self-contained HTML statement of `account` covering the transactions in `period`,
+/// given as indices into the whole history `txs`.
+///
+/// The statement shows the opening balance (from the transactions before `period`), a table of
+/// the account's transactions with their running balance, the closing balance and an inline SVG
+/// balance chart. Amounts are shown with `decimals` decimal places; the chart data, also
+/// embedded as JSON in `<script id="balance-data">`, is in the smallest unit.
+pub fn write_statement(
+    out: &mut impl Write,
+    txs: &[Tx],
+    account: &str,
+    period: Range<usize>,
+    generated: Date,
+    decimals: u32,
+) -> io::Result<()> {
+    let format = |amount: i128| amount::format(amount, decimals, NumberFormat::POINT);
+    let period = period.start.min(txs.len())..period.end.min(txs.len());
+    let opening: i128 = txs[..period.start]
+        .iter()
+        .filter(|tx| tx.account() == account)
+        .map(Tx::balance_change)
+        .sum();
+
+    let mut rows = String::new();
+    let mut balances = vec![(period.start, opening)];
+    let mut balance = opening;
+    for (index, tx) in txs[period.clone()].iter().enumerate() {
+        if tx.account() != account {
+            continue;
+        }
+        let amount = format(tx.amount() as i128);
+        let (kind, credit, debit) = match tx {
+            Tx::Deposit { .. } => ("Deposit", amount, String::new()),
+            Tx::Withdraw { .. } => ("Withdrawal", String::new(), amount),
+            Tx::Collect { .. } => ("Direct debit", String::new(), amount),
+            Tx::Mint { .. } => ("Mint", amount, String::new()),
+            Tx::Burn { .. } => ("Burn", String::new(), amount),
+            Tx::PromoSpend { .. } => ("Promotional credit", amount, String::new()),
+            // Promotional credit is only part of the balance once spent
+            Tx::PromoGrant { .. } | Tx::PromoExpire { .. } => continue,
+            // Mandates move no money
+            Tx::MandateGrant { .. } | Tx::MandateRevoke { .. } => continue,
+        };
+        balance += tx.balance_change();
+        let index = period.start + index;
+        balances.push((index + 1, balance));
+        let _ = writeln!(
+            rows,
+            "<tr><td>{}</td><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>",
+            index + 1,
+            kind,
+            credit,
+            debit,
+            format(balance)
+        );
+    }
+
+    let data = balances
+        .iter()
+        .map(|(tx, balance)| format!("{{\"tx\":{},\"balance\":{}}}", tx, balance))
+        .collect::<Vec<_>>()
+        .join(",");
+    let account = escape(account);
+    write!(
+        out,
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Statement {account}</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; }}
+table {{ border-collapse: collapse; }}
+th, td {{ border-bottom: 1px solid #ddd; padding: 0.3em 0.8em; text-align: left; }}
+.num {{ text-align: right; font-variant-numeric: tabular-nums; }}
+svg {{ border: 1px solid #ddd; margin: 1em 0; }}
+</style>
+</head>
+<body>
+<h1>Statement {account}</h1>
+<p>Transactions {from} to {to}, generated {generated}</p>
+<p>Opening balance: <strong>{opening}</strong></p>
+{chart}
+<table>
+<thead><tr><th>Tx</th><th>Type</th><th class="num">Credit</th><th class="num">Debit</th><th class="num">Balance</th></tr></thead>
+<tbody>
+{rows}</tbody>
+</table>
+<p>Closing balance: <strong>{closing}</strong></p>
+<script type="application/json" id="balance-data">[{data}]</script>
+</body>
+</html>
+"#,
+        from = period.start + 1,
+        to = period.end,
+        generated = generated.iso(),
+        opening = format(opening),
+        chart = chart(&balances),
+        closing = format(balance),
+    )
+}
+
+/// An SVG line chart of the balance after each transaction
+fn chart(balances: &[(usize, i128)]) -> String {
+    const WIDTH: f64 = 600.0;
+    const HEIGHT: f64 = 200.0;
+    let max = balances.iter().map(|(_, b)| *b).max().unwrap_or(0).max(1) as f64;
+    let step = WIDTH / (balances.len().max(2) - 1) as f64;
+    let points = balances
+        .iter()
+        .enumerate()
+        .map(|(i, (_, balance))| {
+            format!(
+                "{:.1},{:.1}",
+                i as f64 * step,
+                HEIGHT - *balance as f64 / max * HEIGHT
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "<svg width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\"><polyline fill=\"none\" stroke=\"#2a6\" stroke-width=\"2\" points=\"{p}\"/></svg>",
+        w = WIDTH,
+        h = HEIGHT,
+        p = points
+    )
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_statement_works() {
+        let txs = [
+            Tx::Deposit {
+                account: "<ALICE>".into(),
+                amount: 100,
+            },
+            Tx::Deposit {
+                account: "BOB".into(),
+                amount: 5,
+            },
+            Tx::Withdraw {
+                account: "<ALICE>".into(),
+                amount: 30,
+                recipient: None,
+            },
+        ];
+        let mut out = vec![];
+
+        //act
+        write_statement(&mut out, &txs, "<ALICE>", 1..3, Date::from_unix_days(0), 0).unwrap();
+
+        let html = String::from_utf8(out).unwrap();
+        assert!(html.contains("<h1>Statement &lt;ALICE&gt;</h1>"));
+        assert!(html.contains("Opening balance: <strong>100</strong>"));
+        assert!(html.contains("<tr><td>3</td><td>Withdrawal</td><td class=\"num\"></td><td class=\"num\">30</td><td class=\"num\">70</td></tr>"));
+        assert!(html.contains("Closing balance: <strong>70</strong>"));
+        assert!(html.contains(r#"[{"tx":1,"balance":100},{"tx":3,"balance":70}]"#));
+        assert!(!html.contains("BOB"));
+    }
+}
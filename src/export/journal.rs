@@ -0,0 +1,81 @@
+use crate::amount;
+use crate::date::Date;
+use crate::tx::Tx;
+use std::io::{self, Write};
+
+/// The account balancing money entering and leaving the ledger
+pub const EXTERNAL_ACCOUNT: &str = "Equity:External";
+
+/// Writes `txs` as a plain-text journal readable by ledger-cli and hledger.
+///
+/// Every crabbux account becomes `Assets:Crabbux:<name>` and every transaction an entry
+/// balanced against [`EXTERNAL_ACCOUNT`]; a `send` shows up as its withdrawal and deposit.
+/// Amounts are written in `commodity`, e.g. `CBX`, with its `decimals` decimal places.
+pub fn write(
+    out: &mut impl Write,
+    txs: &[Tx],
+    date: Date,
+    commodity: &str,
+    decimals: u32,
+) -> io::Result<()> {
+    writeln!(out, "; exported from crabbux")?;
+    for tx in txs {
+        let amount = tx.amount() as i128;
+        let (description, amount) = match tx {
+            Tx::Deposit { .. } => ("Deposit", amount),
+            Tx::Withdraw { .. } => ("Withdrawal", -amount),
+            Tx::Collect { .. } => ("Direct debit", -amount),
+            Tx::Mint { .. } => ("Mint", amount),
+            Tx::Burn { .. } => ("Burn", -amount),
+            Tx::PromoSpend { .. } => ("Promotional credit", amount),
+            // Promotional credit is only money once spent
+            Tx::PromoGrant { .. } | Tx::PromoExpire { .. } => continue,
+            // Mandates move no money
+            Tx::MandateGrant { .. } | Tx::MandateRevoke { .. } => continue,
+        };
+        writeln!(out)?;
+        writeln!(out, "{} {}", date.iso(), description)?;
+        writeln!(
+            out,
+            "    Assets:Crabbux:{}  {} {}",
+            tx.account(),
+            amount::format_plain(amount, decimals),
+            commodity
+        )?;
+        writeln!(out, "    {}", EXTERNAL_ACCOUNT)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_journal_works() {
+        let txs = [
+            Tx::Deposit {
+                account: "ALICE".into(),
+                amount: 100,
+            },
+            Tx::Withdraw {
+                account: "ALICE".into(),
+                amount: 30,
+                recipient: None,
+            },
+        ];
+        let mut out = vec![];
+
+        //act
+        write(&mut out, &txs, Date::from_unix_days(0), "CBX", 2).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "; exported from crabbux\n\
+             \n\
+             1970-01-01 Deposit\n    Assets:Crabbux:ALICE  1.00 CBX\n    Equity:External\n\
+             \n\
+             1970-01-01 Withdrawal\n    Assets:Crabbux:ALICE  -0.30 CBX\n    Equity:External\n"
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! The whole ledger as one JSON document: its balances, account metadata and full tx log, to
+//! inspect, diff or import into another instance.
+
+use crate::{
+    accounts::Accounts,
+    clock::Timestamp,
+    metadata::LedgerMetadata,
+    migrations,
+    snapshot::Snapshot,
+    storage::{self, LogEntry},
+    tx::Units,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// A ledger exported by [`write()`], in the version of [`migrations::STATE`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedgerState {
+    pub exported: Timestamp,
+    /// See [`crate::snapshot::state_hash`]
+    pub hash: String,
+    /// The balances the log leads to
+    pub balances: BTreeMap<String, Units>,
+    pub metadata: LedgerMetadata,
+    pub log: Vec<LogEntry>,
+}
+
+impl LedgerState {
+    /// Replays `log` into `ledger`, which is empty but follows the rules of the log like its
+    /// credit limits, to capture the state it leads to, along with `metadata`
+    /// # Errors
+    /// An entry of `log` can't be applied, see [`storage::replay`]
+    pub fn of(
+        mut ledger: Accounts,
+        log: Vec<LogEntry>,
+        metadata: LedgerMetadata,
+        exported: Timestamp,
+    ) -> io::Result<Self> {
+        storage::replay(&mut ledger, log.iter().cloned().map(Ok))?;
+        let snapshot = Snapshot::of(&ledger, log.len());
+        Ok(LedgerState {
+            exported,
+            hash: snapshot.hash,
+            balances: snapshot.balances,
+            metadata,
+            log,
+        })
+    }
+}
+
+/// Writes `state` as pretty-printed JSON, see [`migrations::to_json`]
+pub fn write(out: &mut impl Write, state: &LedgerState) -> io::Result<()> {
+    out.write_all(&migrations::to_json(state, migrations::STATE)?)?;
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tx::Tx;
+    use serde_json::Value;
+
+    #[test]
+    fn test_write_exports_balances_metadata_and_log() {
+        let entry = |timestamp, tx| LogEntry {
+            timestamp: Timestamp(timestamp),
+            tx,
+            actor: None,
+        };
+        let log = vec![
+            entry(
+                1,
+                Tx::Deposit {
+                    account: "ALICE".into(),
+                    amount: 100,
+                },
+            ),
+            entry(
+                2,
+                Tx::Withdraw {
+                    account: "ALICE".into(),
+                    amount: 40,
+                    recipient: None,
+                },
+            ),
+        ];
+        let mut metadata = LedgerMetadata::default();
+        metadata.entry("ALICE").block("BOB");
+
+        //act
+        let state = LedgerState::of(Accounts::new(), log.clone(), metadata, Timestamp(3)).unwrap();
+        let mut out = vec![];
+        write(&mut out, &state).unwrap();
+        let json: Value = serde_json::from_slice(&out).unwrap();
+        let overdrawn = LedgerState::of(
+            Accounts::new(),
+            log[1..].to_vec(),
+            LedgerMetadata::default(),
+            Timestamp(3),
+        );
+
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["balances"]["ALICE"], 60);
+        assert_eq!(json["metadata"]["accounts"]["ALICE"]["blocklist"][0], "BOB");
+        assert_eq!(json["log"][1]["tx"]["Withdraw"]["amount"], 40);
+        assert_eq!(state.log, log);
+        assert!(overdrawn.is_err());
+    }
+}
@@ -0,0 +1,334 @@
+//! Fees charged on transactions: flat, a percentage of the amount, or either by amount band,
+//! within a minimum and a maximum and waived for small amounts.
+//!
+//! Schedules are configured per operation with `fee.<operation> = <schedule>` in the config
+//! file, e.g. `fee.send = 0.5% min 1 max 50 free under 100`, and the fees go to the account
+//! named by `fee_account = <account>`, see [`FeeSchedules::from_config`] and
+//! [`FeeSchedule`] for the format.
+
+use crate::config::Config;
+use crate::interest::parse_rate;
+use crate::rounding::RoundingPolicy;
+use crate::tx::Units;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+const BASIS_POINTS: u128 = 10_000;
+
+/// What a [`FeeTier`] charges
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charge {
+    /// The same fee whatever the amount
+    Flat(Units),
+    /// A share of the amount in basis points, i.e. 50 is 0.5%
+    Percent(u32),
+}
+
+/// One band of a [`FeeSchedule`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeTier {
+    pub charge: Charge,
+    /// The amount up to which this charge applies, `None` for the last tier
+    pub up_to: Option<Units>,
+}
+
+/// The fee of a transaction by its amount. Unlike interest tiers, the tier an amount falls in
+/// applies to all of it, so a fee can drop when crossing into a cheaper tier.
+///
+/// Parsed from the charge, `<n>` or `<rate>%`, or comma separated `<charge>:<up to>` tiers
+/// ending with an unbounded `<charge>`, followed by any of `min <n>`, `max <n>` and
+/// `free under <n>`, like `1%:1000,0.5% max 50`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeeSchedule {
+    tiers: Vec<FeeTier>,
+    /// The lowest fee charged
+    pub min: Option<Units>,
+    /// The highest fee charged
+    pub max: Option<Units>,
+    /// Amounts below this are charged nothing
+    pub free_under: Option<Units>,
+}
+
+impl FeeSchedule {
+    /// Creates a schedule from tiers in ascending order, without a minimum, maximum or waiver
+    /// # Errors
+    /// There are no tiers, the bounds don't strictly increase, or only the last tier is unbounded
+    pub fn new(tiers: Vec<FeeTier>) -> Result<Self, String> {
+        let Some((last, bounded)) = tiers.split_last() else {
+            return Err("a fee schedule needs at least one tier".to_string());
+        };
+        if last.up_to.is_some() {
+            return Err("the last tier must be unbounded".to_string());
+        }
+        let mut previous = None;
+        for tier in bounded {
+            match tier.up_to {
+                Some(up_to) if previous.is_none_or(|p| up_to > p) => previous = Some(up_to),
+                Some(_) => return Err("tier bounds must increase".to_string()),
+                None => return Err("only the last tier may be unbounded".to_string()),
+            }
+        }
+        Ok(FeeSchedule {
+            tiers,
+            min: None,
+            max: None,
+            free_under: None,
+        })
+    }
+
+    /// A single charge for every amount
+    pub fn single(charge: Charge) -> Self {
+        FeeSchedule {
+            tiers: vec![FeeTier {
+                charge,
+                up_to: None,
+            }],
+            min: None,
+            max: None,
+            free_under: None,
+        }
+    }
+
+    /// The tiers in ascending order
+    pub fn tiers(&self) -> &[FeeTier] {
+        &self.tiers
+    }
+
+    /// The fee of a transaction of `amount`, percentages rounded by `policy`, e.g. the ledger's
+    /// [`crate::accounts::Accounts::rounding`]
+    pub fn fee(&self, amount: Units, policy: RoundingPolicy) -> Units {
+        if self
+            .free_under
+            .is_some_and(|free_under| amount < free_under)
+        {
+            return 0;
+        }
+        let tier = self
+            .tiers
+            .iter()
+            .find(|tier| tier.up_to.is_none_or(|up_to| amount <= up_to))
+            .expect("the last tier is unbounded");
+        let fee = match tier.charge {
+            Charge::Flat(fee) => fee,
+            Charge::Percent(rate) => {
+                let fee = policy.divide((amount as u128) * (rate as u128), BASIS_POINTS);
+                fee.min(Units::MAX as u128) as Units
+            }
+        };
+        fee.max(self.min.unwrap_or(0))
+            .min(self.max.unwrap_or(Units::MAX))
+    }
+}
+
+impl FromStr for FeeSchedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut words = s.split_whitespace();
+        let tiers = words
+            .next()
+            .ok_or("a fee schedule needs a charge")?
+            .split(',')
+            .map(|tier| {
+                let (charge, up_to) = match tier.split_once(':') {
+                    Some((charge, up_to)) => (charge, Some(up_to)),
+                    None => (tier, None),
+                };
+                Ok(FeeTier {
+                    charge: parse_charge(charge)?,
+                    up_to: up_to.map(|up_to| parse_units(up_to, "bound")).transpose()?,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        let mut schedule = FeeSchedule::new(tiers)?;
+        while let Some(word) = words.next() {
+            let setting = match word {
+                "min" => &mut schedule.min,
+                "max" => &mut schedule.max,
+                "free" if words.next() == Some("under") => &mut schedule.free_under,
+                _ => return Err(format!("expected min, max or free under, got {:?}", word)),
+            };
+            let value = words
+                .next()
+                .ok_or_else(|| format!("{} needs an amount", word))?;
+            *setting = Some(parse_units(value, word)?);
+        }
+        if schedule.min > schedule.max && schedule.max.is_some() {
+            return Err("the minimum fee is above the maximum".to_string());
+        }
+        Ok(schedule)
+    }
+}
+
+impl fmt::Display for FeeSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, tier) in self.tiers.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            match tier.charge {
+                Charge::Flat(fee) => write!(f, "{}", fee)?,
+                Charge::Percent(rate) => write!(f, "{}.{:02}%", rate / 100, rate % 100)?,
+            }
+            if let Some(up_to) = tier.up_to {
+                write!(f, ":{}", up_to)?;
+            }
+        }
+        if let Some(min) = self.min {
+            write!(f, " min {}", min)?;
+        }
+        if let Some(max) = self.max {
+            write!(f, " max {}", max)?;
+        }
+        if let Some(free_under) = self.free_under {
+            write!(f, " free under {}", free_under)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a flat fee like `2` or a percentage like `0.5%`
+fn parse_charge(charge: &str) -> Result<Charge, String> {
+    match charge.ends_with('%') {
+        true => parse_rate(charge)
+            .map(Charge::Percent)
+            .ok_or_else(|| format!("invalid rate {:?}", charge)),
+        false => parse_units(charge, "fee").map(Charge::Flat),
+    }
+}
+
+fn parse_units(value: &str, what: &str) -> Result<Units, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid {} {:?}", what, value))
+}
+
+/// The [`FeeSchedule`] of each operation, and the account the fees go to
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeeSchedules {
+    by_operation: BTreeMap<String, FeeSchedule>,
+    account: Option<String>,
+}
+
+impl FeeSchedules {
+    /// Reads every `fee.<operation> = <schedule>` setting and `fee_account = <account>`
+    /// # Errors
+    /// A schedule is invalid, or there are schedules but no fee account; the error names the
+    /// setting
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let by_operation: BTreeMap<_, _> = config
+            .with_prefix("fee.")
+            .map(|(operation, schedule)| {
+                let schedule = schedule
+                    .parse()
+                    .map_err(|e| format!("fee.{}: {}", operation, e))?;
+                Ok((operation.to_string(), schedule))
+            })
+            .collect::<Result<_, String>>()?;
+        let account = config.get("fee_account").map(str::to_string);
+        if account.is_none() && !by_operation.is_empty() {
+            return Err("fee_account: fees need an account to go to".to_string());
+        }
+        Ok(FeeSchedules {
+            by_operation,
+            account,
+        })
+    }
+
+    /// Charges `operation` by `schedule`, the fees going to `account`
+    pub fn insert(&mut self, operation: &str, schedule: FeeSchedule, account: &str) {
+        self.by_operation.insert(operation.to_string(), schedule);
+        self.account = Some(account.to_string());
+    }
+
+    /// The schedule of `operation`, if one is configured
+    pub fn get(&self, operation: &str) -> Option<&FeeSchedule> {
+        self.by_operation.get(operation)
+    }
+
+    /// The account the fees go to
+    pub fn account(&self) -> Option<&str> {
+        self.account.as_deref()
+    }
+
+    /// The fee of `operation` on `amount` by its schedule, 0 if it has none
+    pub fn fee(&self, operation: &str, amount: Units, policy: RoundingPolicy) -> Units {
+        self.get(operation)
+            .map_or(0, |schedule| schedule.fee(amount, policy))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fee_schedule_charges_within_bounds() {
+        let schedule: FeeSchedule = "0.5% min 1 max 50 free under 100".parse().unwrap();
+        let fee = |amount| schedule.fee(amount, RoundingPolicy::Floor);
+
+        //act
+        let fees = [fee(99), fee(100), fee(1_000), fee(5_000), fee(20_000)];
+        let half_up = schedule.fee(1_100, RoundingPolicy::HalfUp);
+
+        assert_eq!(fees, [0, 1, 5, 25, 50]);
+        assert_eq!(half_up, 6);
+        assert_eq!(schedule.to_string(), "0.50% min 1 max 50 free under 100");
+        let flat = FeeSchedule::single(Charge::Flat(2));
+        assert_eq!(flat.fee(0, RoundingPolicy::Floor), 2);
+    }
+
+    #[test]
+    fn test_fee_schedule_applies_the_tier_of_the_amount() {
+        let schedule: FeeSchedule = "5:100,1%:1000,0.5%".parse().unwrap();
+
+        //act
+        let fees = [100, 101, 1_000, 1_001, 10_000]
+            .map(|amount| schedule.fee(amount, RoundingPolicy::Floor));
+
+        assert_eq!(fees, [5, 1, 10, 5, 50]);
+        assert_eq!(schedule.to_string(), "5:100,1.00%:1000,0.50%");
+        assert_eq!(
+            schedule.tiers()[0],
+            FeeTier {
+                charge: Charge::Flat(5),
+                up_to: Some(100)
+            }
+        );
+    }
+
+    #[test]
+    fn test_fee_schedule_rejects_invalid_schedules() {
+        assert!("".parse::<FeeSchedule>().is_err());
+        assert!("1%:1000".parse::<FeeSchedule>().is_err());
+        assert!("1%:1000,2%:500,3%".parse::<FeeSchedule>().is_err());
+        assert!("1.234%".parse::<FeeSchedule>().is_err());
+        assert!("1% min".parse::<FeeSchedule>().is_err());
+        assert!("1% free 100".parse::<FeeSchedule>().is_err());
+        assert!("1% min 10 max 5".parse::<FeeSchedule>().is_err());
+        assert!("1% cap 5".parse::<FeeSchedule>().is_err());
+    }
+
+    #[test]
+    fn test_fee_schedules_from_config_works() {
+        let config = Config::parse("fee.send = 0.5% min 1\nfee_account = fees").unwrap();
+
+        //act
+        let schedules = FeeSchedules::from_config(&config).unwrap();
+        let without_account = Config::parse("fee.send = 1").unwrap();
+        let invalid = Config::parse("fee.send = lots\nfee_account = fees").unwrap();
+
+        assert_eq!(schedules.fee("send", 1_000, RoundingPolicy::Floor), 5);
+        assert_eq!(schedules.fee("withdraw", 1_000, RoundingPolicy::Floor), 0);
+        assert_eq!(schedules.account(), Some("fees"));
+        assert!(FeeSchedules::from_config(&without_account).is_err());
+        assert!(FeeSchedules::from_config(&invalid)
+            .unwrap_err()
+            .starts_with("fee.send"));
+        assert_eq!(
+            FeeSchedules::from_config(&Config::default()),
+            Ok(FeeSchedules::default())
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! A C ABI for embedding the ledger in C, C++, Go and other languages, declared in `include/crabbux.h`.
+//!
+//! Every fallible function returns one of the `CRABBUX_*` codes, with [`CRABBUX_OK`] meaning
+//! success; results are written through out pointers. A ledger is only valid between
+//! [`crabbux_ledger_new`] and [`crabbux_ledger_free`] and must not be used from two threads
+//! at the same time.
+
+use crate::{
+    accounts::Accounts,
+    errors::ApplicationError,
+    tx::{self, Units},
+};
+use std::ffi::{c_char, c_int, CStr};
+
+pub const CRABBUX_OK: c_int = 0;
+/// A pointer argument was null or an account name wasn't valid UTF-8
+pub const CRABBUX_INVALID_ARGUMENT: c_int = 1;
+pub const CRABBUX_NOT_FOUND: c_int = 2;
+pub const CRABBUX_UNDERFUNDED: c_int = 3;
+pub const CRABBUX_OVERFUNDED: c_int = 4;
+pub const CRABBUX_STORAGE_ERROR: c_int = 5;
+pub const CRABBUX_ACCOUNT_EXISTS: c_int = 6;
+/// The account is an escrow account
+pub const CRABBUX_LOCKED: c_int = 7;
+/// The transfer is waiting for approval
+pub const CRABBUX_APPROVAL_REQUIRED: c_int = 8;
+pub const CRABBUX_UNAUTHORIZED: c_int = 9;
+/// The transfer would exceed a spending limit
+pub const CRABBUX_LIMIT_EXCEEDED: c_int = 10;
+/// The sender may not send to the recipient
+pub const CRABBUX_BLOCKED: c_int = 11;
+/// The send looks suspicious and was blocked
+pub const CRABBUX_SUSPICIOUS: c_int = 12;
+/// The send looks suspicious and must be confirmed
+pub const CRABBUX_CONFIRMATION_REQUIRED: c_int = 13;
+/// The account is archived
+pub const CRABBUX_ARCHIVED: c_int = 14;
+/// The ledger is read-only
+pub const CRABBUX_PERMISSION_DENIED: c_int = 15;
+/// Persisted data doesn't match its checksum
+pub const CRABBUX_CORRUPT_DATA: c_int = 16;
+
+/// Creates an empty ledger, to be released with [`crabbux_ledger_free`]
+#[no_mangle]
+pub extern "C" fn crabbux_ledger_new() -> *mut Accounts {
+    Box::into_raw(Box::new(Accounts::new()))
+}
+
+/// Releases a ledger. Null is ignored.
+/// # Safety
+/// `ledger` is null or was returned by [`crabbux_ledger_new`] and isn't used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn crabbux_ledger_free(ledger: *mut Accounts) {
+    if !ledger.is_null() {
+        drop(Box::from_raw(ledger));
+    }
+}
+
+/// Deposits `amount` into `account`, creating it if needed
+/// # Safety
+/// `ledger` is a live ledger and `account` a NUL terminated string, or either is null
+#[no_mangle]
+pub unsafe extern "C" fn crabbux_deposit(
+    ledger: *mut Accounts,
+    account: *const c_char,
+    amount: u64,
+) -> c_int {
+    let (Some(ledger), Some(account)) = (ledger.as_mut(), to_str(account)) else {
+        return CRABBUX_INVALID_ARGUMENT;
+    };
+    to_code(ledger.deposit(account, amount as Units).map(drop))
+}
+
+/// Withdraws `amount` from `account`
+/// # Safety
+/// See [`crabbux_deposit`]
+#[no_mangle]
+pub unsafe extern "C" fn crabbux_withdraw(
+    ledger: *mut Accounts,
+    account: *const c_char,
+    amount: u64,
+) -> c_int {
+    let (Some(ledger), Some(account)) = (ledger.as_mut(), to_str(account)) else {
+        return CRABBUX_INVALID_ARGUMENT;
+    };
+    to_code(ledger.withdraw(account, amount as Units).map(drop))
+}
+
+/// Transfers `amount` from `sender` to `recipient`
+/// # Safety
+/// See [`crabbux_deposit`], for both account names
+#[no_mangle]
+pub unsafe extern "C" fn crabbux_send(
+    ledger: *mut Accounts,
+    sender: *const c_char,
+    recipient: *const c_char,
+    amount: u64,
+) -> c_int {
+    let (Some(ledger), Some(sender), Some(recipient)) =
+        (ledger.as_mut(), to_str(sender), to_str(recipient))
+    else {
+        return CRABBUX_INVALID_ARGUMENT;
+    };
+    to_code(ledger.send(sender, recipient, amount as Units).map(drop))
+}
+
+/// Writes the balance of `account` to `balance`
+/// # Safety
+/// See [`crabbux_deposit`]; `balance` is null or valid for writing a `uint64_t`. With the `u128`
+/// feature, a balance that doesn't fit one is [`CRABBUX_OVERFUNDED`].
+#[no_mangle]
+pub unsafe extern "C" fn crabbux_balance(
+    ledger: *const Accounts,
+    account: *const c_char,
+    balance: *mut u64,
+) -> c_int {
+    let (Some(ledger), Some(account)) = (ledger.as_ref(), to_str(account)) else {
+        return CRABBUX_INVALID_ARGUMENT;
+    };
+    if balance.is_null() {
+        return CRABBUX_INVALID_ARGUMENT;
+    }
+    match ledger.balance_of(account) {
+        Ok(b) => match tx::to_u64(*b) {
+            Some(b) => {
+                *balance = b;
+                CRABBUX_OK
+            }
+            None => CRABBUX_OVERFUNDED,
+        },
+        Err(e) => to_code(Err(e)),
+    }
+}
+
+/// # Safety
+/// `s` is null or a NUL terminated string
+unsafe fn to_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+fn to_code(result: Result<(), ApplicationError>) -> c_int {
+    match result {
+        Ok(()) => CRABBUX_OK,
+        Err(ApplicationError::NotFound(_)) => CRABBUX_NOT_FOUND,
+        Err(ApplicationError::UnderFunded(_, _)) => CRABBUX_UNDERFUNDED,
+        Err(ApplicationError::OverFunded(_, _)) => CRABBUX_OVERFUNDED,
+        Err(ApplicationError::Storage(_)) => CRABBUX_STORAGE_ERROR,
+        Err(ApplicationError::AlreadyExists(_)) => CRABBUX_ACCOUNT_EXISTS,
+        Err(ApplicationError::Locked(_)) => CRABBUX_LOCKED,
+        Err(ApplicationError::ApprovalRequired(_)) => CRABBUX_APPROVAL_REQUIRED,
+        Err(ApplicationError::Unauthorized(_)) => CRABBUX_UNAUTHORIZED,
+        Err(ApplicationError::LimitExceeded(_, _)) => CRABBUX_LIMIT_EXCEEDED,
+        Err(ApplicationError::Blocked(_)) => CRABBUX_BLOCKED,
+        Err(ApplicationError::Suspicious(_)) => CRABBUX_SUSPICIOUS,
+        Err(ApplicationError::ConfirmationRequired(_)) => CRABBUX_CONFIRMATION_REQUIRED,
+        Err(ApplicationError::Archived(_)) => CRABBUX_ARCHIVED,
+        Err(ApplicationError::PermissionDenied(_)) => CRABBUX_PERMISSION_DENIED,
+        Err(ApplicationError::CorruptData(_)) => CRABBUX_CORRUPT_DATA,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn test_ffi_works() {
+        let ledger = crabbux_ledger_new();
+        let (alice, bob) = (c"ALICE".as_ptr(), c"BOB".as_ptr());
+        let mut balance = 0;
+
+        //act
+        unsafe {
+            assert_eq!(crabbux_deposit(ledger, alice, 10), CRABBUX_OK);
+            assert_eq!(crabbux_send(ledger, alice, bob, 4), CRABBUX_OK);
+            assert_eq!(crabbux_withdraw(ledger, bob, 5), CRABBUX_UNDERFUNDED);
+            assert_eq!(crabbux_balance(ledger, bob, &mut balance), CRABBUX_OK);
+            assert_eq!(
+                crabbux_balance(ledger, c"CAROL".as_ptr(), &mut balance),
+                CRABBUX_NOT_FOUND
+            );
+            assert_eq!(
+                crabbux_deposit(ptr::null_mut(), alice, 1),
+                CRABBUX_INVALID_ARGUMENT
+            );
+            assert_eq!(
+                crabbux_deposit(ledger, ptr::null(), 1),
+                CRABBUX_INVALID_ARGUMENT
+            );
+            crabbux_ledger_free(ledger);
+        }
+
+        assert_eq!(balance, 4);
+    }
+}
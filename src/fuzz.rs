@@ -0,0 +1,217 @@
+//! Fuzzing entry points for the ledger invariants, e.g. for `cargo fuzz` (see `fuzz/` in the repository).
+//!
+//! With the `arbitrary` feature, [`Op`] and [`Tx`] implement `arbitrary::Arbitrary`, so a fuzzer
+//! can generate operation sequences straight from its input bytes.
+
+use crate::{
+    accounts::Accounts,
+    clock::Timestamp,
+    dispute,
+    errors::ApplicationError,
+    escrow,
+    snapshot::state_hash,
+    storage::{self, LogEntry},
+    tx::{Tx, Units},
+};
+use std::collections::BTreeMap;
+
+pub use crate::dryrun::Op;
+
+/// Applies `ops` to an empty ledger and returns it, panicking as soon as an invariant is broken:
+/// - every operation succeeds or fails exactly like a straightforward reference model
+/// - failed operations leave all balances untouched
+/// - [`Accounts::check_invariants`] holds and the sum of all balances equals deposits minus withdrawals
+/// - every committed [`Tx`] survives the JSON log and WAL encodings
+/// - replaying the committed transactions rebuilds the same state
+///
+/// Any input is valid, so this can serve as a fuzz target as is.
+pub fn apply_sequence(ops: &[Op]) -> Accounts {
+    let mut ledger = Accounts::new();
+    let mut model: BTreeMap<&str, Units> = BTreeMap::new();
+    let mut committed = vec![];
+    for (i, op) in ops.iter().enumerate() {
+        let result = op.apply(&mut ledger);
+        let expected = match op {
+            Op::Deposit { account, amount } => model_credit(&mut model, account, *amount),
+            Op::Withdraw { account, amount } => model_debit(&mut model, account, *amount),
+            Op::Send {
+                sender,
+                recipient,
+                amount,
+            } => model_transfer(&mut model, sender, recipient, *amount),
+        };
+        assert_eq!(
+            result.as_ref().err(),
+            expected.as_ref().err(),
+            "op {} ({:?}) disagrees with the model",
+            i,
+            op
+        );
+        if let Ok(txs) = result {
+            committed.extend(txs);
+        }
+    }
+
+    if let Err(violations) = ledger.check_invariants() {
+        panic!("invariants are violated: {:?}", violations);
+    }
+    let balances: BTreeMap<&str, Units> = ledger.iter().map(|(k, v)| (k, *v)).collect();
+    assert_eq!(balances, model, "balances disagree with the model");
+    let supply = ledger.supply();
+    let (deposited, withdrawn) =
+        committed
+            .iter()
+            .fold((0u128, 0u128), |(d, w), tx| match tx.is_credit() {
+                true => (d.wrapping_add(tx.amount() as u128), w),
+                false => (d, w.wrapping_add(tx.amount() as u128)),
+            });
+    assert_eq!(
+        supply,
+        deposited.wrapping_sub(withdrawn),
+        "money was created or destroyed"
+    );
+
+    for tx in &committed {
+        assert_round_trip(tx);
+    }
+    let mut replayed = Accounts::new();
+    let entries = committed.iter().map(|tx| {
+        Ok(LogEntry {
+            timestamp: Timestamp::default(),
+            tx: tx.clone(),
+            actor: None,
+        })
+    });
+    storage::replay(&mut replayed, entries).expect("committed transactions replay");
+    assert_eq!(
+        state_hash(&replayed),
+        state_hash(&ledger),
+        "replay diverged"
+    );
+    ledger
+}
+
+/// Panics unless `tx` encodes and decodes to itself in the JSON log and the WAL formats
+pub fn assert_round_trip(tx: &Tx) {
+    let json = serde_json::to_string(tx).unwrap();
+    assert_eq!(&serde_json::from_str::<Tx>(&json).unwrap(), tx, "JSON");
+    #[cfg(feature = "native")]
+    {
+        use crate::wal;
+        let mut buffer = vec![];
+        // Names too long for the WAL are rejected when writing, not silently mangled
+        if wal::encode(tx, Timestamp(1), &mut buffer).is_ok() {
+            let (entry, len) = wal::decode(&buffer).unwrap();
+            assert_eq!((&entry.to_tx(), len), (tx, buffer.len()), "WAL");
+        }
+    }
+}
+
+fn model_credit<'a>(
+    model: &mut BTreeMap<&'a str, Units>,
+    account: &'a str,
+    amount: Units,
+) -> Result<(), ApplicationError> {
+    check_unlocked(account)?;
+    let balance = model.get(account).copied().unwrap_or(0);
+    let balance = balance
+        .checked_add(amount)
+        .ok_or_else(|| ApplicationError::OverFunded(account.to_string(), amount))?;
+    model.insert(account, balance);
+    Ok(())
+}
+
+fn model_debit(
+    model: &mut BTreeMap<&str, Units>,
+    account: &str,
+    amount: Units,
+) -> Result<(), ApplicationError> {
+    check_unlocked(account)?;
+    let balance = model
+        .get_mut(account)
+        .ok_or_else(|| ApplicationError::NotFound(account.to_string()))?;
+    if *balance < amount {
+        return Err(ApplicationError::UnderFunded(account.to_string(), amount));
+    }
+    *balance -= amount;
+    Ok(())
+}
+
+fn model_transfer<'a>(
+    model: &mut BTreeMap<&'a str, Units>,
+    sender: &'a str,
+    recipient: &'a str,
+    amount: Units,
+) -> Result<(), ApplicationError> {
+    check_unlocked(sender)?;
+    check_unlocked(recipient)?;
+    let before = model.clone();
+    model_debit(model, sender, amount)?;
+    model_credit(model, recipient, amount).inspect_err(|_| *model = before)
+}
+
+fn check_unlocked(account: &str) -> Result<(), ApplicationError> {
+    if escrow::is_escrow(account) || dispute::is_dispute(account) {
+        return Err(ApplicationError::Locked(account.to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deposit(account: &str, amount: Units) -> Op {
+        Op::Deposit {
+            account: account.to_string(),
+            amount,
+        }
+    }
+
+    #[test]
+    fn test_apply_sequence_works() {
+        let ops = vec![
+            deposit("ALICE", 100),
+            Op::Withdraw {
+                account: "BOB".to_string(),
+                amount: 1,
+            },
+            Op::Send {
+                sender: "ALICE".to_string(),
+                recipient: "BOB".to_string(),
+                amount: 150,
+            },
+            deposit("BOB", Units::MAX),
+            Op::Send {
+                sender: "BOB".to_string(),
+                recipient: "ALICE".to_string(),
+                amount: Units::MAX,
+            },
+            Op::Send {
+                sender: "ALICE".to_string(),
+                recipient: "ALICE".to_string(),
+                amount: 100,
+            },
+        ];
+
+        //act
+        let ledger = apply_sequence(&ops);
+
+        assert_eq!(ledger.balance_of("ALICE"), Ok(&100));
+        assert_eq!(ledger.balance_of("BOB"), Ok(&Units::MAX));
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_apply_sequence_accepts_arbitrary_input() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        for seed in 0..64u8 {
+            let bytes: Vec<u8> = (0..512u32)
+                .map(|i| (i as u8).wrapping_mul(seed) ^ seed)
+                .collect();
+            let ops = Vec::<Op>::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+            apply_sequence(&ops);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Savings goals: a target balance an account, or a sub-account like `alice:holiday`, saves
+//! towards, optionally by a deadline.
+//!
+//! Goals are settings of their account kept in its [`crate::metadata::AccountMetadata`], and
+//! are managed with `crabbux goals`. The interactive `print` shows the progress of each, and
+//! every commit that makes the balance reach a target from below it publishes
+//! [`crate::events::LedgerEvent::GoalReached`].
+
+use crate::date::Date;
+use crate::tx::Units;
+use serde::{Deserialize, Serialize};
+
+/// What an account saves towards
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Goal {
+    pub target: Units,
+    /// The last day to reach the target on, if there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<Date>,
+}
+
+impl Goal {
+    /// How far a `balance`, see [`crate::accounts::Accounts::signed_balance_of`], got towards
+    /// the goal
+    pub fn progress(&self, balance: i128) -> Progress {
+        Progress {
+            saved: balance.clamp(0, self.target as i128) as Units,
+            target: self.target,
+        }
+    }
+
+    /// Returns `true` if a commit taking the balance from `before` to `after` reached the target
+    pub fn reached_by(&self, before: i128, after: i128) -> bool {
+        let target = self.target as i128;
+        before < target && after >= target
+    }
+
+    /// Returns `true` if the deadline passed on `today` without the target being reached
+    pub fn missed(&self, balance: i128, today: Date) -> bool {
+        self.deadline.is_some_and(|deadline| today > deadline) && !self.progress(balance).done()
+    }
+}
+
+/// How much of a [`Goal`] was saved, never more than its target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub saved: Units,
+    pub target: Units,
+}
+
+impl Progress {
+    /// The share saved in whole percent, rounded down, 100 for a target of 0
+    pub fn percent(&self) -> u32 {
+        match self.target {
+            0 => 100,
+            target => ((self.saved as u128) * 100 / (target as u128)) as u32,
+        }
+    }
+
+    /// Returns `true` if the target was reached
+    pub fn done(&self) -> bool {
+        self.saved >= self.target
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_goal_progress_works() {
+        let goal = Goal {
+            target: 300,
+            deadline: Date::new(2027, 1, 31),
+        };
+
+        //act
+        let overdrawn = goal.progress(-50);
+        let halfway = goal.progress(150);
+        let beyond = goal.progress(1_000);
+
+        assert_eq!((overdrawn.saved, overdrawn.percent()), (0, 0));
+        assert_eq!((halfway.saved, halfway.percent()), (150, 50));
+        assert_eq!((beyond.saved, beyond.percent()), (300, 100));
+        assert!(beyond.done() && !halfway.done());
+        assert!(goal.reached_by(299, 300));
+        assert!(!goal.reached_by(300, 400));
+        assert!(!goal.reached_by(100, 299));
+        assert!(goal.missed(150, Date::new(2027, 2, 1).unwrap()));
+        assert!(!goal.missed(150, Date::new(2027, 1, 31).unwrap()));
+        assert!(!goal.missed(300, Date::new(2027, 2, 1).unwrap()));
+    }
+}
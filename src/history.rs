@@ -0,0 +1,374 @@
+use crate::{
+    accounts::{Accounts, LedgerView},
+    clock::Timestamp,
+    dispute::{Dispute, DisputeState},
+    storage::LogEntry,
+    tx::{Tx, Units},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::sync::Arc;
+
+/// The most entries [`TxLog::after`] returns at once
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+/// The timestamped transaction history of a ledger, in commit order, for queries about the past.
+///
+/// Load it from a persisted log with [`TxLog::load`], e.g. from a [`crate::storage::LogReader`].
+/// The entries of every account are indexed as they are added, so per-account queries only
+/// visit that account's entries.
+#[derive(Debug, Clone, Default)]
+pub struct TxLog {
+    entries: Vec<LogEntry>,
+    /// Positions of each account's entries in `entries`, ascending
+    by_account: HashMap<Arc<str>, Vec<usize>>,
+}
+
+impl TxLog {
+    /// Creates an empty history
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Reads every entry of a persisted log
+    pub fn load(entries: impl IntoIterator<Item = io::Result<LogEntry>>) -> io::Result<Self> {
+        let mut log = TxLog::new();
+        for entry in entries {
+            log.push(entry?);
+        }
+        Ok(log)
+    }
+
+    /// Records a committed transaction
+    pub fn push(&mut self, entry: LogEntry) {
+        let position = self.entries.len();
+        self.by_account
+            .entry(entry.tx.account_name().clone())
+            .or_default()
+            .push(position);
+        self.entries.push(entry);
+    }
+
+    /// The entries affecting `account`, oldest first, with their positions in the log
+    pub fn account_entries<'a>(
+        &'a self,
+        account: &str,
+    ) -> impl DoubleEndedIterator<Item = (usize, &'a LogEntry)> + 'a {
+        self.by_account
+            .get(account)
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .map(|&position| (position, &self.entries[position]))
+    }
+
+    /// The entries moving between `min_amount` and `max_amount` (both inclusive), oldest first,
+    /// with their positions in the log
+    pub fn find_txs(
+        &self,
+        min_amount: Units,
+        max_amount: Units,
+    ) -> impl DoubleEndedIterator<Item = (usize, &LogEntry)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(move |(_, entry)| (min_amount..=max_amount).contains(&entry.tx.amount()))
+    }
+
+    /// The entries stored between `from` and `to` (both inclusive), in commit order, with their
+    /// positions in the log
+    pub fn between(
+        &self,
+        from: Timestamp,
+        to: Timestamp,
+    ) -> impl DoubleEndedIterator<Item = (usize, &LogEntry)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(move |(_, entry)| (from..=to).contains(&entry.timestamp))
+    }
+
+    /// All entries, oldest first
+    pub fn entries(&self) -> &[LogEntry] {
+        &self.entries
+    }
+
+    /// The number of entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if nothing was committed yet
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Up to `limit` entries starting at entry `offset` (0-based)
+    pub fn page(&self, offset: usize, limit: usize) -> &[LogEntry] {
+        let start = offset.min(self.entries.len());
+        let end = start.saturating_add(limit).min(self.entries.len());
+        &self.entries[start..end]
+    }
+
+    /// Up to `limit` (at most [`MAX_PAGE_SIZE`]) entries following `cursor`, or from the start
+    /// without one. Pass the returned [`Page::next_cursor`] to get the next page.
+    ///
+    /// The log is append-only, so a cursor stays valid forever and walking the log with it
+    /// never skips or repeats entries, even while transactions are being committed.
+    pub fn after(&self, cursor: Option<Cursor>, limit: usize) -> Page<'_> {
+        let offset = cursor.map_or(0, |c| c.0);
+        let entries = self.page(offset, limit.min(MAX_PAGE_SIZE));
+        let next = offset.saturating_add(entries.len());
+        Page {
+            entries,
+            next_cursor: (next < self.entries.len()).then_some(Cursor(next)),
+        }
+    }
+
+    /// Every account with the time of its last entry, in no particular order
+    pub fn last_activity(&self) -> impl Iterator<Item = (&str, Timestamp)> {
+        self.by_account.iter().filter_map(|(account, positions)| {
+            let last = positions.last()?;
+            Some((&**account, self.entries[*last].timestamp))
+        })
+    }
+
+    /// The state of every dispute, by the position of the disputed entry.
+    ///
+    /// A dispute opens with the deposit into its dispute account. Emptying that account is
+    /// a denial if the funds go straight back to the disputed account, otherwise an upheld dispute.
+    pub fn disputes(&self) -> BTreeMap<usize, DisputeState> {
+        let mut disputes = BTreeMap::new();
+        for (position, entry) in self.entries.iter().enumerate() {
+            let Some(dispute) = Dispute::parse(entry.tx.account_name()) else {
+                continue;
+            };
+            let state = match (&entry.tx, self.entries.get(position + 1).map(|e| &e.tx)) {
+                (Tx::Deposit { .. }, _) => DisputeState::Open,
+                (
+                    Tx::Withdraw { amount, .. },
+                    Some(Tx::Deposit {
+                        account,
+                        amount: returned,
+                    }),
+                ) if **account == *dispute.account && returned == amount => DisputeState::Denied,
+                (Tx::Withdraw { .. }, _) => DisputeState::Upheld,
+                // Dispute accounts are locked against minting, burning, promotional credit and
+                // mandates, and have none to collect under
+                (
+                    Tx::Mint { .. }
+                    | Tx::Burn { .. }
+                    | Tx::PromoGrant { .. }
+                    | Tx::PromoSpend { .. }
+                    | Tx::PromoExpire { .. }
+                    | Tx::MandateGrant { .. }
+                    | Tx::MandateRevoke { .. }
+                    | Tx::Collect { .. },
+                    _,
+                ) => continue,
+            };
+            disputes.insert(dispute.position, state);
+        }
+        disputes
+    }
+
+    /// The state after the first `tx_index` entries, replayed into `ledger`, see
+    /// [`Accounts::state_at`]
+    pub fn state_at(&self, ledger: Accounts, tx_index: usize) -> io::Result<LedgerView> {
+        ledger.state_at(self.entries.iter().cloned().map(Ok), tx_index)
+    }
+
+    /// The balance `account` had at `at`, counting every entry stored at or before it,
+    /// or `None` if the account didn't exist yet
+    pub fn balance_at(&self, account: &str, at: Timestamp) -> Option<Units> {
+        self.account_entries(account)
+            .filter(|(_, entry)| entry.timestamp <= at)
+            .fold(None, |balance, (_, entry)| {
+                let balance = balance.unwrap_or(0);
+                let change = entry.tx.balance_change();
+                let amount = Units::try_from(change.unsigned_abs()).unwrap_or(Units::MAX);
+                Some(match change >= 0 {
+                    true => balance.saturating_add(amount),
+                    false => balance.saturating_sub(amount),
+                })
+            })
+    }
+}
+
+/// Where the next [`Page`] of a [`TxLog`] starts. Clients should treat it as opaque.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Cursor(pub usize);
+
+/// A slice of the log returned by [`TxLog::after`]
+#[derive(Debug, Serialize)]
+pub struct Page<'a> {
+    pub entries: &'a [LogEntry],
+    /// `None` on the last page
+    pub next_cursor: Option<Cursor>,
+}
+
+impl FromIterator<LogEntry> for TxLog {
+    fn from_iter<I: IntoIterator<Item = LogEntry>>(entries: I) -> Self {
+        let mut log = TxLog::new();
+        entries.into_iter().for_each(|entry| log.push(entry));
+        log
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(millis: u64, tx: Tx) -> LogEntry {
+        LogEntry {
+            timestamp: Timestamp(millis),
+            tx,
+            actor: None,
+        }
+    }
+
+    #[test]
+    fn test_tx_log_balance_at_works() {
+        let mut log = TxLog::new();
+        log.push(entry(
+            10,
+            Tx::Deposit {
+                account: "ALICE".into(),
+                amount: 100,
+            },
+        ));
+        log.push(entry(
+            20,
+            Tx::Withdraw {
+                account: "ALICE".into(),
+                amount: 30,
+                recipient: None,
+            },
+        ));
+        log.push(entry(
+            20,
+            Tx::Deposit {
+                account: "BOB".into(),
+                amount: 30,
+            },
+        ));
+
+        assert_eq!(log.balance_at("ALICE", Timestamp(9)), None);
+        assert_eq!(log.balance_at("ALICE", Timestamp(10)), Some(100));
+        assert_eq!(log.balance_at("ALICE", Timestamp(19)), Some(100));
+        assert_eq!(log.balance_at("ALICE", Timestamp(20)), Some(70));
+        assert_eq!(log.balance_at("BOB", Timestamp(25)), Some(30));
+        let positions: Vec<usize> = log.account_entries("ALICE").map(|(i, _)| i).collect();
+        assert_eq!(positions, vec![0, 1]);
+        assert_eq!(log.account_entries("CAROL").count(), 0);
+    }
+
+    #[test]
+    fn test_tx_log_find_txs_works() {
+        let log: TxLog = [5, 1000, 20, 5000]
+            .into_iter()
+            .map(|amount| {
+                entry(
+                    0,
+                    Tx::Withdraw {
+                        account: "ALICE".into(),
+                        amount,
+                        recipient: None,
+                    },
+                )
+            })
+            .collect();
+
+        //act
+        let large: Vec<usize> = log.find_txs(1000, Units::MAX).map(|(i, _)| i).collect();
+        let exact: Vec<usize> = log.find_txs(20, 20).map(|(i, _)| i).collect();
+
+        assert_eq!(large, vec![1, 3]);
+        assert_eq!(exact, vec![2]);
+        assert_eq!(log.find_txs(10, 5).count(), 0);
+    }
+
+    #[test]
+    fn test_tx_log_between_works() {
+        let log: TxLog = [10, 20, 20, 30]
+            .into_iter()
+            .map(|millis| {
+                entry(
+                    millis,
+                    Tx::Deposit {
+                        account: "ALICE".into(),
+                        amount: 1,
+                    },
+                )
+            })
+            .collect();
+
+        //act
+        let middle: Vec<usize> = log
+            .between(Timestamp(15), Timestamp(20))
+            .map(|(i, _)| i)
+            .collect();
+
+        assert_eq!(middle, vec![1, 2]);
+        assert_eq!(log.between(Timestamp(0), Timestamp(u64::MAX)).count(), 4);
+        assert_eq!(log.between(Timestamp(31), Timestamp(40)).count(), 0);
+    }
+
+    #[test]
+    fn test_tx_log_disputes_tracks_resolutions() {
+        let mut ledger = Accounts::new();
+        let mut txs = vec![];
+        for amount in [100, 20, 5] {
+            txs.push(ledger.deposit("ALICE", amount).unwrap());
+        }
+        let deposits: TxLog = txs.iter().map(|tx| entry(0, tx.clone())).collect();
+        for position in 0..deposits.len() {
+            let (_, (withdrawal, deposit)) = ledger.open_dispute(&deposits, position).unwrap();
+            txs.extend([withdrawal, deposit]);
+        }
+        txs.push(ledger.uphold_dispute(0).unwrap());
+        let (withdrawal, deposit) = ledger.deny_dispute(1).unwrap();
+        txs.extend([withdrawal, deposit]);
+        let log: TxLog = txs.into_iter().map(|tx| entry(0, tx)).collect();
+
+        //act
+        let disputes = log.disputes();
+
+        assert_eq!(
+            disputes.into_iter().collect::<Vec<_>>(),
+            vec![
+                (0, DisputeState::Upheld),
+                (1, DisputeState::Denied),
+                (2, DisputeState::Open)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tx_log_pagination_works() {
+        let log: TxLog = (0..5)
+            .map(|i| {
+                entry(
+                    i,
+                    Tx::Deposit {
+                        account: "ALICE".into(),
+                        amount: i as Units,
+                    },
+                )
+            })
+            .collect();
+
+        //act
+        let first = log.after(None, 2);
+        let second = log.after(first.next_cursor, 2);
+        let last = log.after(second.next_cursor, 2);
+
+        assert_eq!(first.entries, log.page(0, 2));
+        assert_eq!(second.entries, &log.entries()[2..4]);
+        assert_eq!(last.entries.len(), 1);
+        assert_eq!(last.next_cursor, None);
+        assert!(log.page(10, 2).is_empty());
+        assert_eq!(log.after(Some(Cursor(5)), 2).entries.len(), 0);
+    }
+}
@@ -0,0 +1,669 @@
+//! The message catalog for everything shown to CLI users, including [`crate::errors::ApplicationError`]s.
+//!
+//! Messages are looked up by [`Key`] in the process wide [`locale`], which defaults to English.
+//! Placeholders `{0}`, `{1}`, ... are filled in by [`tr`].
+
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// A supported language
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum Locale {
+    #[default]
+    En,
+    Es,
+    De,
+}
+
+impl Locale {
+    /// Parses a language tag like `de`, `es-ES` or a POSIX locale like `de_DE.UTF-8`
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let language = tag.split(['_', '-', '.', '@']).next()?.to_ascii_lowercase();
+        match language.as_str() {
+            "en" | "c" | "posix" => Some(Locale::En),
+            "es" => Some(Locale::Es),
+            "de" => Some(Locale::De),
+            _ => None,
+        }
+    }
+
+    /// The locale configured by `LC_ALL`, `LC_MESSAGES` or `LANG`, in that order of precedence
+    pub fn from_env() -> Option<Self> {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Locale::from_tag(&value))
+    }
+
+    /// The catalog entry for `key`
+    pub fn text(self, key: Key) -> &'static str {
+        use Key::*;
+        match self {
+            Locale::En => match key {
+                Choose => "Please choose [{0}] and  hit return:",
+                Account => "Account:",
+                Amount => "Amount",
+                Sender => "Sender:",
+                Receiver => "Receiver",
+                Transfer => "Transfer:",
+                Expires => "Expires after (YYYY-MM-DD):",
+                Ledger => "ledger:",
+                Balance => "{0}: {1}",
+                NotSupported => "command not supported",
+                EncounteredError => "encountered error: {0}",
+                NotFound => "Account {0} not found",
+                UnderFunded => "Account {0} is underfunded; required amount is {1}",
+                OverFunded => "Account {0} is overfunded; maximum allowed amount is {1}",
+                Storage => "Couldn't persist change: {0}",
+                AlreadyExists => "Account {0} already exists",
+                Locked => "Account {0} is locked by an escrow or dispute",
+                ApprovalRequired => "Transfer #{0} is waiting for approval by the account's signers",
+                Unauthorized => "{0} may not do this",
+                LimitExceeded => "Spending limit for {0} reached; remaining allowance is {1}",
+                Blocked => "The sender's recipient list doesn't allow sending to {0}",
+                Suspicious => "Blocked a suspicious send: {0}",
+                ConfirmationRequired => "This send looks suspicious and needs confirmation: {0}",
+                Archived => "Account {0} is archived; restore it first",
+                PermissionDenied => "Permission denied: this session is read-only and may not {0}",
+                CorruptData => "Corrupt data: {0}",
+                Confirm => "Send anyway? [y/N]",
+                ConfirmRestore => "Replace {0} with the backup of {1} entries from {2}? [y/N]",
+                ConfirmReplaceState => "Replace {0} with the imported ledger of {1} entries? [y/N]",
+                AnomalyWarning => "Warning: suspicious send: {0}",
+                UsingLedger => "Using ledger {0}",
+                LoggedIn => "Logged in as {0}",
+                LoggedOut => "Not logged in",
+                SavedIn => "Interrupted; the ledger is saved in {0}",
+                SavedTo => "Interrupted; saved {0} transactions to {1}",
+                LastGoodBackup => "The last good backup is {0}; restore it with `crabbux restore {0} {1}`",
+                NoGoodBackup => "There is no intact backup in {0}",
+                LowBalance => "Warning: the balance of {0} fell below {1} to {2}",
+                HighBalance => "Warning: the balance of {0} rose above {1} to {2}",
+                GoalReached => "{0} reached its goal {1} of {2}",
+                GoalProgress => "{0}: {1} of {2} ({3}%)",
+                GoalProgressBy => "{0}: {1} of {2} ({3}%) by {4}",
+                SetupWelcome => {
+                    "There is no ledger yet, so let's set one up. Press return to take the suggestion in brackets."
+                }
+                SetupDirectory => "Data directory [{0}]:",
+                SetupFormat => "Persistence format, jsonl or wal [jsonl]:",
+                SetupCurrency => "Default currency [{0}]:",
+                SetupAccount => "Initial account as <name> <balance>, or return to finish:",
+                SetupDone => "Saved the settings to {0}; the ledger is kept in {1}",
+                More => "-- return shows more, q stops --",
+                GoalMissed => "{0}: {1} of {2} ({3}%), missed {4}",
+                CommandFailed => "{0} failed: {1}",
+                ConfigUnreadable => "couldn't read config: {0}",
+                LogUnreadable => "couldn't load transaction log: {0}",
+                LedgerUnreadable => "couldn't load ledger {0}: {1}",
+                PromptHistoryUnreadable => "couldn't read the prompt history: {0}",
+                LoggingFailed => "couldn't set up logging: {0}",
+                SetupFailed => "couldn't set up crabbux: {0}",
+                DryRunNeedsLocal => "--dry-run needs a local ledger",
+                NoDryRun => "{0} has no --dry-run",
+                TlsUnreadable => "couldn't read TLS certificate or key: {0}",
+                TlsIncomplete => "TLS needs both a certificate and a key",
+                Ok => "ok",
+                Diverged => "diverged: {0}",
+                Violated => "violated: {0}",
+                UseNeedsLedger => "use needs a --ledger to start with",
+                UseInDryRun => "use isn't available in a dry run",
+                InvalidDate => "invalid date {0}",
+                NoSuchCommand => "there is no command {0} in the history",
+                AliasUsage => "usage: alias <name> = <command>; ...",
+                AliasHidesCommand => "alias {0} would hide the command {0}",
+                NeedsLog => "{0} needs a --tx-log or --wal to persist to",
+                NoLog => "no --tx-log or --wal given",
+                Imported => "imported {0} entries, skipped {1} duplicates",
+                WouldFail => "operation {0} of {1} would fail: {2}",
+                UnknownFormat => "unknown format {0}",
+                Matched => "matched {0} entries",
+                OnlyOnStatement => "only on the statement:",
+                OnlyInLedger => "only in the ledger:",
+                Entries => "{0} entries, {1}",
+                Pruned => "pruned {0}",
+                Restored => "restored {0} entries into {1}",
+                ImportedInto => "imported into {0}",
+                ImportedRates => "imported {0} rates",
+                EscrowOpened => "escrow #{0}",
+                NoOwners => "no owners",
+                Owners => "owners: {0}",
+                ArchivedAccounts => "archived {0} accounts",
+                NoRestrictions => "no restrictions",
+                Allowed => "allowed: {0}",
+                BlockedRecipients => "blocked: {0}",
+                NoGoals => "no goals",
+                NoGoal => "{0} has no goal {1}",
+                NoMandate => "{0} has no mandate on {1}",
+                NoStandingOrder => "there is no standing order #{0}",
+                StandingOrderAdded => "standing order #{0}, next {1}",
+                NextRun => "next {0}",
+                OrderPaid => "#{0} paid, due {1}",
+                OrderFailed => "#{0} failed: {1}",
+                ValuingNeedsCurrency => "valuing needs the ledger's currency, currency = <code> in the config",
+                RemoteHistoryNeedsFollow => "a --remote history needs --follow",
+                ByActor => " by {0}",
+                StatsFunds => "  funds: {0}",
+                StatsAccounts => "  accounts: {0}",
+                StatsTxs => "  transactions: {0}",
+                StatsMinted => "  minted: {0}, burned: {1}",
+                StatsBusiest => "  busiest: {0}, {1} transactions",
+                StatsNoBusiest => "  busiest: -",
+                StatsLastActivity => "  last activity: {0}",
+                SetupCancelled => "setup cancelled",
+                SetupExpectedFormat => "expected jsonl or wal, got {0}",
+                SetupExpectedCurrency => "expected letters or digits, got {0}",
+                SetupExpectedAccount => "expected <name> <balance>",
+            },
+            Locale::Es => match key {
+                Choose => "Elija [{0}] y pulse Intro:",
+                Account => "Cuenta:",
+                Amount => "Importe",
+                Sender => "Remitente:",
+                Receiver => "Destinatario",
+                Transfer => "Transferencia:",
+                Expires => "Caduca después del (AAAA-MM-DD):",
+                Ledger => "libro:",
+                Balance => "{0}: {1}",
+                NotSupported => "comando no soportado",
+                EncounteredError => "se produjo un error: {0}",
+                NotFound => "No se encontró la cuenta {0}",
+                UnderFunded => {
+                    "La cuenta {0} no tiene fondos suficientes; el importe requerido es {1}"
+                }
+                OverFunded => "La cuenta {0} excede el máximo; el importe máximo permitido es {1}",
+                Storage => "No se pudo guardar el cambio: {0}",
+                AlreadyExists => "La cuenta {0} ya existe",
+                Locked => "La cuenta {0} está bloqueada por un depósito de garantía o una disputa",
+                ApprovalRequired => {
+                    "La transferencia #{0} está pendiente de aprobación por los firmantes de la cuenta"
+                }
+                Unauthorized => "{0} no tiene permiso para hacer esto",
+                LimitExceeded => {
+                    "Se alcanzó el límite de gasto para {0}; el importe disponible es {1}"
+                }
+                Blocked => "La lista de destinatarios del remitente no permite enviar a {0}",
+                Suspicious => "Se bloqueó un envío sospechoso: {0}",
+                ConfirmationRequired => "Este envío parece sospechoso y requiere confirmación: {0}",
+                Archived => "La cuenta {0} está archivada; restáurela primero",
+                PermissionDenied => "Permiso denegado: esta sesión es de solo lectura y no puede {0}",
+                CorruptData => "Datos dañados: {0}",
+                Confirm => "¿Enviar de todos modos? [s/N]",
+                ConfirmRestore => "¿Reemplazar {0} por la copia de seguridad de {1} entradas del {2}? [s/N]",
+                ConfirmReplaceState => "¿Reemplazar {0} por el libro mayor importado de {1} entradas? [s/N]",
+                AnomalyWarning => "Aviso: envío sospechoso: {0}",
+                UsingLedger => "Usando el libro {0}",
+                LoggedIn => "Sesión iniciada como {0}",
+                LoggedOut => "No hay sesión iniciada",
+                SavedIn => "Interrumpido; el libro mayor está guardado en {0}",
+                SavedTo => "Interrumpido; se guardaron {0} transacciones en {1}",
+                LastGoodBackup => {
+                    "La última copia de seguridad válida es {0}; restáurela con `crabbux restore {0} {1}`"
+                }
+                NoGoodBackup => "No hay ninguna copia de seguridad intacta en {0}",
+                LowBalance => "Aviso: el saldo de {0} bajó de {1} a {2}",
+                HighBalance => "Aviso: el saldo de {0} superó {1} y es {2}",
+                GoalReached => "{0} alcanzó su meta {1} de {2}",
+                GoalProgress => "{0}: {1} de {2} ({3}%)",
+                GoalProgressBy => "{0}: {1} de {2} ({3}%) antes del {4}",
+                SetupWelcome => {
+                    "Aún no hay ningún libro mayor, así que vamos a crear uno. Pulse Intro para aceptar la sugerencia entre corchetes."
+                }
+                SetupDirectory => "Directorio de datos [{0}]:",
+                SetupFormat => "Formato de almacenamiento, jsonl o wal [jsonl]:",
+                SetupCurrency => "Moneda predeterminada [{0}]:",
+                SetupAccount => "Cuenta inicial como <nombre> <saldo>, o Intro para terminar:",
+                SetupDone => "Configuración guardada en {0}; el libro mayor se guarda en {1}",
+                More => "-- Intro muestra más, q termina --",
+                GoalMissed => "{0}: {1} de {2} ({3}%), vencido el {4}",
+                CommandFailed => "{0} falló: {1}",
+                ConfigUnreadable => "no se pudo leer la configuración: {0}",
+                LogUnreadable => "no se pudo cargar el registro de transacciones: {0}",
+                LedgerUnreadable => "no se pudo cargar el libro {0}: {1}",
+                PromptHistoryUnreadable => "no se pudo leer el historial de comandos: {0}",
+                LoggingFailed => "no se pudo configurar el registro: {0}",
+                SetupFailed => "no se pudo configurar crabbux: {0}",
+                DryRunNeedsLocal => "--dry-run necesita un libro local",
+                NoDryRun => "{0} no tiene --dry-run",
+                TlsUnreadable => "no se pudo leer el certificado o la clave TLS: {0}",
+                TlsIncomplete => "TLS necesita un certificado y una clave",
+                Ok => "ok",
+                Diverged => "divergencia: {0}",
+                Violated => "incumplido: {0}",
+                UseNeedsLedger => "use necesita un --ledger para empezar",
+                UseInDryRun => "use no está disponible en una simulación",
+                InvalidDate => "fecha no válida {0}",
+                NoSuchCommand => "no hay ningún comando {0} en el historial",
+                AliasUsage => "uso: alias <nombre> = <comando>; ...",
+                AliasHidesCommand => "el alias {0} ocultaría el comando {0}",
+                NeedsLog => "{0} necesita un --tx-log o --wal donde guardar",
+                NoLog => "no se indicó --tx-log ni --wal",
+                Imported => "se importaron {0} entradas y se omitieron {1} duplicados",
+                WouldFail => "la operación {0} de {1} fallaría: {2}",
+                UnknownFormat => "formato desconocido {0}",
+                Matched => "{0} entradas coinciden",
+                OnlyOnStatement => "solo en el extracto:",
+                OnlyInLedger => "solo en el libro:",
+                Entries => "{0} entradas, {1}",
+                Pruned => "se eliminó {0}",
+                Restored => "se restauraron {0} entradas en {1}",
+                ImportedInto => "importado en {0}",
+                ImportedRates => "se importaron {0} tipos de cambio",
+                EscrowOpened => "depósito de garantía #{0}",
+                NoOwners => "sin titulares",
+                Owners => "titulares: {0}",
+                ArchivedAccounts => "se archivaron {0} cuentas",
+                NoRestrictions => "sin restricciones",
+                Allowed => "permitidos: {0}",
+                BlockedRecipients => "bloqueados: {0}",
+                NoGoals => "sin objetivos",
+                NoGoal => "{0} no tiene el objetivo {1}",
+                NoMandate => "{0} no tiene ningún mandato sobre {1}",
+                NoStandingOrder => "no existe la orden permanente #{0}",
+                StandingOrderAdded => "orden permanente #{0}, próxima el {1}",
+                NextRun => "próxima el {0}",
+                OrderPaid => "#{0} pagada, vencía el {1}",
+                OrderFailed => "#{0} falló: {1}",
+                ValuingNeedsCurrency => "la valoración necesita la moneda del libro, currency = <código> en la configuración",
+                RemoteHistoryNeedsFollow => "un historial --remote necesita --follow",
+                ByActor => " por {0}",
+                StatsFunds => "  fondos: {0}",
+                StatsAccounts => "  cuentas: {0}",
+                StatsTxs => "  transacciones: {0}",
+                StatsMinted => "  emitido: {0}, retirado: {1}",
+                StatsBusiest => "  más activa: {0}, {1} transacciones",
+                StatsNoBusiest => "  más activa: -",
+                StatsLastActivity => "  última actividad: {0}",
+                SetupCancelled => "configuración cancelada",
+                SetupExpectedFormat => "se esperaba jsonl o wal, se recibió {0}",
+                SetupExpectedCurrency => "se esperaban letras o dígitos, se recibió {0}",
+                SetupExpectedAccount => "se esperaba <nombre> <saldo>",
+            },
+            Locale::De => match key {
+                Choose => "Bitte [{0}] wählen und Enter drücken:",
+                Account => "Konto:",
+                Amount => "Betrag",
+                Sender => "Absender:",
+                Receiver => "Empfänger",
+                Transfer => "Überweisung:",
+                Expires => "Läuft ab nach dem (JJJJ-MM-TT):",
+                Ledger => "Kontobuch:",
+                Balance => "{0}: {1}",
+                NotSupported => "Befehl nicht unterstützt",
+                EncounteredError => "Fehler aufgetreten: {0}",
+                NotFound => "Konto {0} nicht gefunden",
+                UnderFunded => "Konto {0} ist nicht gedeckt; benötigter Betrag ist {1}",
+                OverFunded => {
+                    "Konto {0} würde das Maximum überschreiten; maximal erlaubter Betrag ist {1}"
+                }
+                Storage => "Änderung konnte nicht gespeichert werden: {0}",
+                AlreadyExists => "Konto {0} existiert bereits",
+                Locked => "Konto {0} ist durch eine Treuhand oder einen Streitfall gesperrt",
+                ApprovalRequired => "Überweisung #{0} wartet auf die Freigabe der Zeichnungsberechtigten",
+                Unauthorized => "{0} ist dazu nicht berechtigt",
+                LimitExceeded => "Ausgabenlimit für {0} erreicht; verbleibender Betrag ist {1}",
+                Blocked => "Die Empfängerliste des Absenders erlaubt keine Zahlungen an {0}",
+                Suspicious => "Verdächtige Überweisung blockiert: {0}",
+                ConfirmationRequired => "Diese Überweisung wirkt verdächtig und muss bestätigt werden: {0}",
+                Archived => "Konto {0} ist archiviert; bitte zuerst wiederherstellen",
+                PermissionDenied => "Zugriff verweigert: diese Sitzung ist schreibgeschützt und darf nicht {0}",
+                CorruptData => "Beschädigte Daten: {0}",
+                Confirm => "Trotzdem senden? [j/N]",
+                ConfirmRestore => "{0} durch die Sicherung mit {1} Einträgen vom {2} ersetzen? [j/N]",
+                ConfirmReplaceState => "{0} durch das importierte Hauptbuch mit {1} Einträgen ersetzen? [j/N]",
+                AnomalyWarning => "Warnung: verdächtige Überweisung: {0}",
+                UsingLedger => "Kontobuch {0} wird verwendet",
+                LoggedIn => "Angemeldet als {0}",
+                LoggedOut => "Nicht angemeldet",
+                SavedIn => "Abgebrochen; das Hauptbuch ist in {0} gespeichert",
+                SavedTo => "Abgebrochen; {0} Transaktionen in {1} gespeichert",
+                LastGoodBackup => {
+                    "Die letzte intakte Sicherung ist {0}; wiederherstellen mit `crabbux restore {0} {1}`"
+                }
+                NoGoodBackup => "In {0} gibt es keine intakte Sicherung",
+                LowBalance => "Warnung: der Kontostand von {0} fiel unter {1} auf {2}",
+                HighBalance => "Warnung: der Kontostand von {0} stieg über {1} auf {2}",
+                GoalReached => "{0} hat sein Sparziel {1} von {2} erreicht",
+                GoalProgress => "{0}: {1} von {2} ({3}%)",
+                GoalProgressBy => "{0}: {1} von {2} ({3}%) bis {4}",
+                SetupWelcome => {
+                    "Es gibt noch kein Hauptbuch, richten wir also eines ein. Enter übernimmt den Vorschlag in Klammern."
+                }
+                SetupDirectory => "Datenverzeichnis [{0}]:",
+                SetupFormat => "Speicherformat, jsonl oder wal [jsonl]:",
+                SetupCurrency => "Standardwährung [{0}]:",
+                SetupAccount => "Anfangskonto als <Name> <Saldo>, oder Enter zum Beenden:",
+                SetupDone => "Einstellungen in {0} gespeichert; das Hauptbuch liegt in {1}",
+                More => "-- Enter zeigt mehr, q beendet --",
+                GoalMissed => "{0}: {1} von {2} ({3}%), verpasst am {4}",
+                CommandFailed => "{0} fehlgeschlagen: {1}",
+                ConfigUnreadable => "Konfiguration konnte nicht gelesen werden: {0}",
+                LogUnreadable => "Transaktionsprotokoll konnte nicht geladen werden: {0}",
+                LedgerUnreadable => "Hauptbuch {0} konnte nicht geladen werden: {1}",
+                PromptHistoryUnreadable => "Befehlsverlauf konnte nicht gelesen werden: {0}",
+                LoggingFailed => "Logging konnte nicht eingerichtet werden: {0}",
+                SetupFailed => "crabbux konnte nicht eingerichtet werden: {0}",
+                DryRunNeedsLocal => "--dry-run braucht ein lokales Hauptbuch",
+                NoDryRun => "{0} hat kein --dry-run",
+                TlsUnreadable => "TLS-Zertifikat oder -Schlüssel konnte nicht gelesen werden: {0}",
+                TlsIncomplete => "TLS braucht ein Zertifikat und einen Schlüssel",
+                Ok => "ok",
+                Diverged => "abweichend: {0}",
+                Violated => "verletzt: {0}",
+                UseNeedsLedger => "use braucht zum Start ein --ledger",
+                UseInDryRun => "use ist in einem Probelauf nicht verfügbar",
+                InvalidDate => "ungültiges Datum {0}",
+                NoSuchCommand => "es gibt keinen Befehl {0} im Verlauf",
+                AliasUsage => "Verwendung: alias <Name> = <Befehl>; ...",
+                AliasHidesCommand => "Alias {0} würde den Befehl {0} verdecken",
+                NeedsLog => "{0} braucht ein --tx-log oder --wal zum Speichern",
+                NoLog => "kein --tx-log oder --wal angegeben",
+                Imported => "{0} Einträge importiert, {1} Duplikate übersprungen",
+                WouldFail => "Vorgang {0} von {1} würde fehlschlagen: {2}",
+                UnknownFormat => "unbekanntes Format {0}",
+                Matched => "{0} Einträge zugeordnet",
+                OnlyOnStatement => "nur auf dem Kontoauszug:",
+                OnlyInLedger => "nur im Hauptbuch:",
+                Entries => "{0} Einträge, {1}",
+                Pruned => "{0} entfernt",
+                Restored => "{0} Einträge in {1} wiederhergestellt",
+                ImportedInto => "in {0} importiert",
+                ImportedRates => "{0} Kurse importiert",
+                EscrowOpened => "Treuhand #{0}",
+                NoOwners => "keine Inhaber",
+                Owners => "Inhaber: {0}",
+                ArchivedAccounts => "{0} Konten archiviert",
+                NoRestrictions => "keine Einschränkungen",
+                Allowed => "erlaubt: {0}",
+                BlockedRecipients => "gesperrt: {0}",
+                NoGoals => "keine Sparziele",
+                NoGoal => "{0} hat kein Sparziel {1}",
+                NoMandate => "{0} hat kein Mandat für {1}",
+                NoStandingOrder => "es gibt keinen Dauerauftrag #{0}",
+                StandingOrderAdded => "Dauerauftrag #{0}, nächste Ausführung {1}",
+                NextRun => "nächste Ausführung {0}",
+                OrderPaid => "#{0} bezahlt, fällig am {1}",
+                OrderFailed => "#{0} fehlgeschlagen: {1}",
+                ValuingNeedsCurrency => "die Bewertung braucht die Währung des Hauptbuchs, currency = <Code> in der Konfiguration",
+                RemoteHistoryNeedsFollow => "ein --remote-Verlauf braucht --follow",
+                ByActor => " von {0}",
+                StatsFunds => "  Guthaben: {0}",
+                StatsAccounts => "  Konten: {0}",
+                StatsTxs => "  Transaktionen: {0}",
+                StatsMinted => "  geschöpft: {0}, vernichtet: {1}",
+                StatsBusiest => "  aktivstes Konto: {0}, {1} Transaktionen",
+                StatsNoBusiest => "  aktivstes Konto: -",
+                StatsLastActivity => "  letzte Aktivität: {0}",
+                SetupCancelled => "Einrichtung abgebrochen",
+                SetupExpectedFormat => "jsonl oder wal erwartet, {0} erhalten",
+                SetupExpectedCurrency => "Buchstaben oder Ziffern erwartet, {0} erhalten",
+                SetupExpectedAccount => "<Name> <Saldo> erwartet",
+            },
+        }
+    }
+}
+
+/// Identifies a message of the catalog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// The command menu, `{0}` is the list of commands
+    Choose,
+    Account,
+    Amount,
+    Sender,
+    Receiver,
+    /// The heading of the balances list
+    Ledger,
+    /// `{0}` is the account, `{1}` its balance
+    Balance,
+    NotSupported,
+    /// `{0}` is the error message
+    EncounteredError,
+    NotFound,
+    UnderFunded,
+    OverFunded,
+    Storage,
+    AlreadyExists,
+    Locked,
+    ApprovalRequired,
+    Unauthorized,
+    /// `{0}` is the recipient, `{1}` the remaining allowance
+    LimitExceeded,
+    /// `{0}` is the recipient
+    Blocked,
+    /// `{0}` describes the anomaly
+    Suspicious,
+    /// `{0}` describes the anomaly
+    ConfirmationRequired,
+    Archived,
+    /// `{0}` is the operation
+    PermissionDenied,
+    /// `{0}` names the file and what's wrong with it
+    CorruptData,
+    /// Asks whether to send despite an anomaly
+    Confirm,
+    /// Asks whether to restore a backup, `{0}` is the log it replaces, `{1}` the number of
+    /// entries in the backup and `{2}` when it was taken
+    ConfirmRestore,
+    /// Asks whether to replace the ledger with an imported state, `{0}` is the log it replaces
+    /// and `{1}` the number of entries of the import
+    ConfirmReplaceState,
+    /// `{0}` describes the anomaly
+    AnomalyWarning,
+    /// The prompt for a pending transfer id
+    Transfer,
+    /// The prompt for the last day promotional credit can be spent
+    Expires,
+    /// Confirms `use <name>`, `{0}` is the ledger name
+    UsingLedger,
+    /// Confirms `login <user>`, `{0}` is the user
+    LoggedIn,
+    /// Confirms `logout`, also answers `whoami` when nobody is logged in
+    LoggedOut,
+    /// Printed when interrupted, `{0}` are the logs the session persisted to
+    SavedIn,
+    /// Printed when interrupted without a log, `{0}` is the number of transactions and `{1}`
+    /// the file they were saved to
+    SavedTo,
+    /// Printed when loading found damaged data, `{0}` is the backup to restore and `{1}` the
+    /// flag naming the log
+    LastGoodBackup,
+    /// Printed when loading found damaged data, `{0}` is the backup directory
+    NoGoodBackup,
+    /// `{0}` is the account, `{1}` the threshold and `{2}` the new balance
+    LowBalance,
+    /// `{0}` is the account, `{1}` the threshold and `{2}` the new balance
+    HighBalance,
+    /// `{0}` is the account, `{1}` the goal and `{2}` its target
+    GoalReached,
+    /// A goal in `print` under its account, or listed by `goals`, `{0}` is the goal, `{1}`
+    /// the amount saved, `{2}` the target and `{3}` the percentage
+    GoalProgress,
+    /// Like `GoalProgress`, `{4}` is the deadline
+    GoalProgressBy,
+    /// Starts the first-run setup
+    SetupWelcome,
+    /// `{0}` is the suggested directory
+    SetupDirectory,
+    SetupFormat,
+    /// `{0}` is the suggested currency
+    SetupCurrency,
+    SetupAccount,
+    /// `{0}` is the config file written and `{1}` the log
+    SetupDone,
+    /// Asks to show the next page of a long listing
+    More,
+    /// Like `GoalProgressBy` for a deadline that passed
+    GoalMissed,
+    /// `{0}` is the command and `{1}` the error
+    CommandFailed,
+    /// `{0}` is the error
+    ConfigUnreadable,
+    /// `{0}` is the error
+    LogUnreadable,
+    /// `{0}` is the ledger name and `{1}` the error
+    LedgerUnreadable,
+    /// `{0}` is the error
+    PromptHistoryUnreadable,
+    /// `{0}` is the error
+    LoggingFailed,
+    /// `{0}` is the error
+    SetupFailed,
+    DryRunNeedsLocal,
+    /// `{0}` is the command
+    NoDryRun,
+    /// `{0}` is the error
+    TlsUnreadable,
+    TlsIncomplete,
+    /// A check passed
+    Ok,
+    /// `{0}` describes where the log diverged from the snapshot
+    Diverged,
+    /// `{0}` is the violated invariant
+    Violated,
+    /// Why `use` failed without `--ledger`
+    UseNeedsLedger,
+    UseInDryRun,
+    /// `{0}` is what was entered
+    InvalidDate,
+    /// `{0}` is the number entered after `!`
+    NoSuchCommand,
+    AliasUsage,
+    /// `{0}` is the alias
+    AliasHidesCommand,
+    /// `{0}` is the command
+    NeedsLog,
+    NoLog,
+    /// `{0}` is the number of imported entries and `{1}` of skipped duplicates
+    Imported,
+    /// `{0}` is the position of the operation, `{1}` the number of operations and `{2}` the error
+    WouldFail,
+    /// `{0}` is the format given
+    UnknownFormat,
+    /// `{0}` is the number of statement entries found in the ledger
+    Matched,
+    OnlyOnStatement,
+    OnlyInLedger,
+    /// `{0}` is the number of entries in a backup or snapshot and `{1}` where it is
+    Entries,
+    /// `{0}` is the backup deleted
+    Pruned,
+    /// `{0}` is the number of entries and `{1}` the log
+    Restored,
+    /// `{0}` is the log
+    ImportedInto,
+    /// `{0}` is the number of rates
+    ImportedRates,
+    /// `{0}` is the id of the new escrow
+    EscrowOpened,
+    NoOwners,
+    /// `{0}` are the owners
+    Owners,
+    /// `{0}` is the number of accounts
+    ArchivedAccounts,
+    /// The recipient list of an account that may send to anyone
+    NoRestrictions,
+    /// `{0}` are the recipients
+    Allowed,
+    /// `{0}` are the recipients
+    BlockedRecipients,
+    NoGoals,
+    /// `{0}` is the account and `{1}` the goal
+    NoGoal,
+    /// `{0}` is the payee and `{1}` the payer
+    NoMandate,
+    /// `{0}` is the id
+    NoStandingOrder,
+    /// `{0}` is the id and `{1}` the next due day
+    StandingOrderAdded,
+    /// `{0}` is the next due day of a standing order
+    NextRun,
+    /// `{0}` is the standing order and `{1}` the day it was due
+    OrderPaid,
+    /// `{0}` is the standing order and `{1}` the error
+    OrderFailed,
+    ValuingNeedsCurrency,
+    RemoteHistoryNeedsFollow,
+    /// Appended to a history line, `{0}` is who committed it
+    ByActor,
+    /// A line of `stats`, `{0}` is the amount
+    StatsFunds,
+    /// A line of `stats`, `{0}` is the number of accounts
+    StatsAccounts,
+    /// A line of `stats`, `{0}` is the number of transactions
+    StatsTxs,
+    /// A line of `stats`, `{0}` is the amount minted and `{1}` burned
+    StatsMinted,
+    /// A line of `stats`, `{0}` is the account and `{1}` its number of transactions
+    StatsBusiest,
+    /// A line of `stats` for a ledger without transactions
+    StatsNoBusiest,
+    /// A line of `stats`, `{0}` is when or `-`
+    StatsLastActivity,
+    SetupCancelled,
+    /// `{0}` is what was entered
+    SetupExpectedFormat,
+    /// `{0}` is what was entered
+    SetupExpectedCurrency,
+    SetupExpectedAccount,
+}
+
+static LOCALE: AtomicU8 = AtomicU8::new(Locale::En as u8);
+
+/// Selects the locale for all messages from now on
+pub fn set_locale(locale: Locale) {
+    LOCALE.store(locale as u8, Ordering::Relaxed);
+}
+
+/// The currently selected locale
+pub fn locale() -> Locale {
+    match LOCALE.load(Ordering::Relaxed) {
+        1 => Locale::Es,
+        2 => Locale::De,
+        _ => Locale::En,
+    }
+}
+
+/// The message for `key` in the current locale with `{n}` replaced by `args[n]`
+pub fn tr(key: Key, args: &[&dyn Display]) -> String {
+    fill(locale().text(key), args)
+}
+
+fn fill(template: &str, args: &[&dyn Display]) -> String {
+    let mut message = template.to_string();
+    for (i, arg) in args.iter().enumerate() {
+        message = message.replace(&format!("{{{}}}", i), &arg.to_string());
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locale_from_tag_works() {
+        assert_eq!(Locale::from_tag("de_DE.UTF-8"), Some(Locale::De));
+        assert_eq!(Locale::from_tag("es-ES"), Some(Locale::Es));
+        assert_eq!(Locale::from_tag("C"), Some(Locale::En));
+        assert_eq!(Locale::from_tag("fr_FR"), None);
+    }
+
+    #[test]
+    fn test_fill_replaces_placeholders() {
+        assert_eq!(
+            fill(Locale::De.text(Key::UnderFunded), &[&"ALICE", &10]),
+            "Konto ALICE ist nicht gedeckt; benötigter Betrag ist 10"
+        );
+    }
+
+    #[test]
+    fn test_fill_replaces_repeated_placeholders() {
+        assert_eq!(
+            fill(Locale::Es.text(Key::AliasHidesCommand), &[&"quit"]),
+            "el alias quit ocultaría el comando quit"
+        );
+    }
+}
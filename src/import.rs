@@ -0,0 +1,196 @@
+//! Parsers for bank statements and applying their entries to the ledger, and importing
+//! exported ledgers, see [`state`].
+
+pub mod camt;
+pub mod csv;
+pub mod ofx;
+pub mod qif;
+pub mod reconcile;
+pub mod state;
+
+use crate::{
+    accounts::Accounts,
+    amount::{self, NumberFormat},
+    date::Date,
+    errors::ApplicationError,
+    tx::{Tx, Units},
+};
+use std::collections::HashSet;
+use std::fmt;
+
+/// A single line of a bank statement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatementEntry {
+    pub date: Date,
+    /// In the smallest currency unit, negative for money leaving the account
+    pub amount: i128,
+    pub payee: String,
+    /// The bank's unique id of the entry, if the format has one
+    pub id: Option<String>,
+}
+
+impl StatementEntry {
+    /// Identifies the entry across imports: its `id` if it has one, otherwise date, amount and payee
+    pub fn key(&self) -> String {
+        match &self.id {
+            Some(id) => format!("id:{}", id),
+            None => format!("{}|{}|{}", self.date.iso(), self.amount, self.payee),
+        }
+    }
+}
+
+/// A statement that couldn't be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line of the problem
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// The outcome of [`apply`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ImportSummary {
+    /// The transactions applied to the ledger
+    pub txs: Vec<Tx>,
+    /// Entries skipped because their [`StatementEntry::key`] was seen before
+    pub skipped: usize,
+}
+
+/// Applies `entries` to `account` as deposits and withdrawals, skipping entries already in
+/// `seen`, which holds `<account>/<key>` for every [`StatementEntry::key`] imported before.
+/// Keys of the applied entries are added to `seen`, so passing the same set again makes
+/// re-imports a no-op.
+///
+/// Either all new entries are applied or none.
+/// # Errors
+/// The entries would take the account below zero or overflow it
+pub fn apply(
+    ledger: &mut Accounts,
+    account: &str,
+    entries: &[StatementEntry],
+    seen: &mut HashSet<String>,
+) -> Result<ImportSummary, ApplicationError> {
+    let mut new = vec![];
+    let mut keys = HashSet::new();
+    let mut skipped = 0;
+    for entry in entries {
+        let key = format!("{}/{}", account, entry.key());
+        if seen.contains(&key) || !keys.insert(key) {
+            skipped += 1;
+        } else {
+            new.push(entry);
+        }
+    }
+
+    // Check the whole statement against the running balance before changing anything
+    let mut balance = ledger.balance_of(account).copied().unwrap_or_default() as i128;
+    for entry in &new {
+        balance += entry.amount;
+        let magnitude = entry.amount.unsigned_abs().min(Units::MAX as u128) as Units;
+        if balance < 0 {
+            return match ledger.balance_of(account) {
+                Ok(_) => Err(ApplicationError::UnderFunded(
+                    account.to_string(),
+                    magnitude,
+                )),
+                Err(e) => Err(e),
+            };
+        }
+        if balance as u128 > Units::MAX as u128 {
+            return Err(ApplicationError::OverFunded(account.to_string(), magnitude));
+        }
+    }
+
+    let mut txs = Vec::with_capacity(new.len());
+    for entry in new {
+        let amount = entry.amount.unsigned_abs() as Units;
+        let tx = if entry.amount >= 0 {
+            ledger.deposit(account, amount)?
+        } else {
+            ledger.withdraw(account, amount)?
+        };
+        txs.push(tx);
+    }
+    seen.extend(keys);
+    Ok(ImportSummary { txs, skipped })
+}
+
+/// Parses a decimal amount like `-1,234.56`, as found in statements, into the smallest unit
+/// given `decimals` digits after the decimal separator, e.g. -123456 for 2 decimals. The amount
+/// may carry the ledger's [`amount::currency`], like `-1,234.56 EUR`, see [`amount::Amount`].
+pub fn parse_amount(s: &str, decimals: u32) -> Result<i128, String> {
+    amount::Amount::parse(s, decimals, NumberFormat::POINT)?.minor_in(amount::currency().as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(amount: i128, payee: &str) -> StatementEntry {
+        StatementEntry {
+            date: Date::new(2024, 1, 15).unwrap(),
+            amount,
+            payee: payee.to_string(),
+            id: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_amount_works() {
+        assert_eq!(parse_amount("-1,234.56", 2), Ok(-123456));
+        assert_eq!(parse_amount("12.5", 2), Ok(1250));
+        assert_eq!(parse_amount("7", 2), Ok(700));
+        assert_eq!(parse_amount("-3.10 EUR", 2), Ok(-310));
+        assert!(parse_amount("1.234", 2).is_err());
+        assert!(parse_amount("abc", 2).is_err());
+    }
+
+    #[test]
+    fn test_apply_skips_duplicates() {
+        let mut ledger = Accounts::new();
+        let mut seen = HashSet::new();
+        let entries = [
+            entry(500, "Salary"),
+            entry(-200, "Rent"),
+            entry(500, "Salary"),
+        ];
+
+        //act
+        let first = apply(&mut ledger, "ALICE", &entries, &mut seen).unwrap();
+        let second = apply(&mut ledger, "ALICE", &entries, &mut seen).unwrap();
+
+        assert_eq!((first.txs.len(), first.skipped), (2, 1));
+        assert_eq!((second.txs.len(), second.skipped), (0, 3));
+        assert_eq!(ledger.balance_of("ALICE"), Ok(&300));
+    }
+
+    #[test]
+    fn test_apply_underfunded_changes_nothing() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("ALICE", 100).unwrap();
+        let mut seen = HashSet::new();
+
+        //act
+        let result = apply(
+            &mut ledger,
+            "ALICE",
+            &[entry(50, "Refund"), entry(-200, "Rent")],
+            &mut seen,
+        );
+
+        assert_eq!(
+            result,
+            Err(ApplicationError::UnderFunded("ALICE".to_string(), 200))
+        );
+        assert_eq!(ledger.balance_of("ALICE"), Ok(&100));
+        assert!(seen.is_empty());
+    }
+}
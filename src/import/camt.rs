@@ -0,0 +1,213 @@
+use super::{parse_amount, ParseError, StatementEntry};
+use crate::date::Date;
+use quick_xml::{events::Event, Reader};
+
+/// The values of an `<Ntry>` element collected while reading it
+#[derive(Default)]
+struct Entry {
+    amount: Option<String>,
+    debit: bool,
+    pending: bool,
+    date: Option<String>,
+    reference: Option<String>,
+    creditor: Option<String>,
+    debtor: Option<String>,
+    remittance: Option<String>,
+    line: usize,
+}
+
+/// Parses the entries (`<Ntry>`) of an ISO 20022 camt.053 bank statement.
+///
+/// The booking date (falling back to the value date), the amount and its credit/debit
+/// indicator are used, and the counterparty name, falling back to the unstructured remittance
+/// information, becomes the payee. The bank's `AcctSvcrRef` becomes the
+/// [`StatementEntry::id`]. Pending (`PDNG`) entries are skipped. Amounts are converted to the
+/// smallest currency unit with `decimals` decimal places.
+pub fn parse(input: &str, decimals: u32) -> Result<Vec<StatementEntry>, ParseError> {
+    let mut reader = Reader::from_str(input);
+    let line_at = |position: u64| input[..position as usize].matches('\n').count() + 1;
+    let mut path: Vec<String> = vec![];
+    let mut entry: Option<Entry> = None;
+    let mut entries = vec![];
+    loop {
+        let event = reader.read_event().map_err(|e| ParseError {
+            line: line_at(reader.error_position()),
+            message: e.to_string(),
+        })?;
+        match event {
+            Event::Start(start) => {
+                let name = start.local_name().as_ref().to_string();
+                if name == "Ntry" {
+                    entry = Some(Entry {
+                        line: line_at(reader.buffer_position()),
+                        ..Default::default()
+                    });
+                }
+                path.push(name);
+            }
+            Event::End(_) => {
+                let name = path.pop();
+                if name.as_deref() == Some("Ntry") {
+                    let done = entry.take().expect("entries end after they start");
+                    if !done.pending {
+                        entries.push(finish(done, decimals)?);
+                    }
+                }
+            }
+            Event::Text(text) => {
+                if let Some(entry) = entry.as_mut() {
+                    collect(entry, &path, &text.xml10_content());
+                }
+            }
+            Event::GeneralRef(reference) => {
+                if let Some(entry) = entry.as_mut() {
+                    let escaped = format!("&{};", reference.into_inner());
+                    let text = quick_xml::escape::unescape(&escaped)
+                        .map(|text| text.into_owned())
+                        .unwrap_or(escaped);
+                    collect(entry, &path, &text);
+                }
+            }
+            Event::Eof => return Ok(entries),
+            _ => {}
+        }
+    }
+}
+
+/// Stores `text` found at `path` (the element names from the document root) in `entry`.
+/// Text split up by entity references arrives in several parts, which are appended and
+/// only trimmed once complete.
+fn collect(entry: &mut Entry, path: &[String], text: &str) {
+    let Some(ntry) = path.iter().rposition(|name| name == "Ntry") else {
+        return;
+    };
+    let inner: Vec<&str> = path[ntry + 1..].iter().map(String::as_str).collect();
+    let append = |field: &mut Option<String>| field.get_or_insert_with(String::new).push_str(text);
+    match inner.as_slice() {
+        ["Amt"] => append(&mut entry.amount),
+        ["CdtDbtInd"] => entry.debit = text.trim() == "DBIT",
+        ["Sts"] | ["Sts", "Cd"] => entry.pending = text.trim() == "PDNG",
+        ["BookgDt", "Dt" | "DtTm"] => entry.date = Some(text.trim().to_string()),
+        ["ValDt", "Dt" | "DtTm"] if entry.date.is_none() => {
+            entry.date = Some(text.trim().to_string())
+        }
+        ["AcctSvcrRef"] => append(&mut entry.reference),
+        [.., "RltdPties", "Cdtr", "Nm"] | [.., "RltdPties", "Cdtr", "Pty", "Nm"] => {
+            append(&mut entry.creditor)
+        }
+        [.., "RltdPties", "Dbtr", "Nm"] | [.., "RltdPties", "Dbtr", "Pty", "Nm"] => {
+            append(&mut entry.debtor)
+        }
+        [.., "RmtInf", "Ustrd"] => append(&mut entry.remittance),
+        _ => {}
+    }
+}
+
+fn finish(entry: Entry, decimals: u32) -> Result<StatementEntry, ParseError> {
+    let error = |message: String| ParseError {
+        line: entry.line,
+        message,
+    };
+    let amount = entry
+        .amount
+        .as_deref()
+        .ok_or_else(|| error("entry without Amt".to_string()))?;
+    let amount = parse_amount(amount.trim(), decimals).map_err(error)?;
+    let date = entry
+        .date
+        .as_deref()
+        .ok_or_else(|| error("entry without booking or value date".to_string()))?;
+    // `DtTm` values carry a time after the date
+    let date = date
+        .get(..10)
+        .and_then(Date::parse_iso)
+        .ok_or_else(|| error(format!("invalid date {}", date)))?;
+    // The counterparty is the creditor of outgoing and the debtor of incoming payments
+    let counterparty = if entry.debit {
+        entry.creditor
+    } else {
+        entry.debtor
+    };
+    let trimmed =
+        |text: Option<String>| text.map(|t| t.trim().to_string()).filter(|t| !t.is_empty());
+    Ok(StatementEntry {
+        date,
+        amount: if entry.debit { -amount } else { amount },
+        payee: trimmed(counterparty)
+            .or(trimmed(entry.remittance))
+            .unwrap_or_default(),
+        id: trimmed(entry.reference),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATEMENT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">
+  <BkToCstmrStmt>
+    <Stmt>
+      <Ntry>
+        <Amt Ccy="EUR">1200.00</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <BookgDt><Dt>2024-03-01</Dt></BookgDt>
+        <AcctSvcrRef>REF-1</AcctSvcrRef>
+        <NtryDtls><TxDtls><RltdPties><Cdtr><Nm>Smith &amp; Sons</Nm></Cdtr></RltdPties></TxDtls></NtryDtls>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">99.5</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts>BOOK</Sts>
+        <ValDt><DtTm>2024-03-02T10:00:00</DtTm></ValDt>
+        <NtryDtls><TxDtls><RmtInf><Ustrd>Refund</Ustrd></RmtInf></TxDtls></NtryDtls>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">5.00</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <Sts>PDNG</Sts>
+        <BookgDt><Dt>2024-03-03</Dt></BookgDt>
+      </Ntry>
+    </Stmt>
+  </BkToCstmrStmt>
+</Document>"#;
+
+    #[test]
+    fn test_parse_camt053_works() {
+        //act
+        let entries = parse(STATEMENT, 2).unwrap();
+
+        assert_eq!(
+            entries,
+            vec![
+                StatementEntry {
+                    date: Date::new(2024, 3, 1).unwrap(),
+                    amount: -120000,
+                    payee: "Smith & Sons".to_string(),
+                    id: Some("REF-1".to_string()),
+                },
+                StatementEntry {
+                    date: Date::new(2024, 3, 2).unwrap(),
+                    amount: 9950,
+                    payee: "Refund".to_string(),
+                    id: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_camt053_reports_missing_amounts() {
+        let input =
+            "<Document>\n<Ntry>\n<BookgDt><Dt>2024-03-01</Dt></BookgDt>\n</Ntry>\n</Document>";
+
+        assert_eq!(
+            parse(input, 2),
+            Err(ParseError {
+                line: 2,
+                message: "entry without Amt".to_string()
+            })
+        );
+    }
+}
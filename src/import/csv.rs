@@ -0,0 +1,133 @@
+use super::{parse_amount, ParseError, StatementEntry};
+use crate::date::Date;
+
+/// Parses a CSV bank statement with a header row.
+///
+/// The columns are found by their header, ignoring case: `date` (`YYYY-MM-DD`) and `amount`
+/// are required, the payee is taken from `payee`, `description` or `name`, and the entry's
+/// [`StatementEntry::id`] from `reference` or `id`. Other columns are ignored. Fields may be
+/// quoted with `"`, doubling quotes inside them. Amounts are converted to the smallest
+/// currency unit with `decimals` decimal places.
+pub fn parse(input: &str, decimals: u32) -> Result<Vec<StatementEntry>, ParseError> {
+    let mut lines = input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let Some((header_line, header)) = lines.next() else {
+        return Ok(vec![]);
+    };
+    let header = fields(header).map_err(|message| ParseError {
+        line: header_line + 1,
+        message,
+    })?;
+    let column = |names: &[&str]| {
+        header
+            .iter()
+            .position(|field| names.contains(&field.trim().to_lowercase().as_str()))
+    };
+    let missing = |name: &str| ParseError {
+        line: header_line + 1,
+        message: format!("no {} column", name),
+    };
+    let date_column = column(&["date"]).ok_or_else(|| missing("date"))?;
+    let amount_column = column(&["amount"]).ok_or_else(|| missing("amount"))?;
+    let payee_column = column(&["payee", "description", "name"]);
+    let id_column = column(&["reference", "id"]);
+
+    let mut entries = vec![];
+    for (i, line) in lines {
+        let error = |message: String| ParseError {
+            line: i + 1,
+            message,
+        };
+        let row = fields(line).map_err(error)?;
+        let field = |column: usize| row.get(column).map_or("", |field| field.trim());
+        let date = field(date_column);
+        entries.push(StatementEntry {
+            date: Date::parse_iso(date).ok_or_else(|| error(format!("invalid date {}", date)))?,
+            amount: parse_amount(field(amount_column), decimals).map_err(error)?,
+            payee: payee_column.map_or("", field).to_string(),
+            id: id_column
+                .map(field)
+                .filter(|id| !id.is_empty())
+                .map(str::to_string),
+        });
+    }
+    Ok(entries)
+}
+
+/// The comma separated fields of `line`, unquoted
+fn fields(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quote".to_string());
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_works() {
+        let input = "Date,Description,Amount,Reference\n\
+            2024-01-15,\"Coffee, \"\"to go\"\"\",-42.10,A1\n\
+            \n\
+            2024-01-16,Salary,\"1,000.00\",\n";
+
+        //act
+        let entries = parse(input, 2).unwrap();
+
+        assert_eq!(
+            entries,
+            vec![
+                StatementEntry {
+                    date: Date::new(2024, 1, 15).unwrap(),
+                    amount: -4210,
+                    payee: "Coffee, \"to go\"".to_string(),
+                    id: Some("A1".to_string()),
+                },
+                StatementEntry {
+                    date: Date::new(2024, 1, 16).unwrap(),
+                    amount: 100000,
+                    payee: "Salary".to_string(),
+                    id: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_csv_requires_date_and_amount() {
+        assert_eq!(
+            parse("date,payee\n2024-01-15,Rent", 2),
+            Err(ParseError {
+                line: 1,
+                message: "no amount column".to_string()
+            })
+        );
+        assert_eq!(
+            parse("date,amount\n\n15/01/2024,5", 2),
+            Err(ParseError {
+                line: 3,
+                message: "invalid date 15/01/2024".to_string()
+            })
+        );
+    }
+}
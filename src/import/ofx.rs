@@ -0,0 +1,122 @@
+use super::{parse_amount, ParseError, StatementEntry};
+use crate::date::Date;
+
+/// Parses the `<STMTTRN>` entries of an OFX or QFX statement, both the SGML (1.x) and the
+/// XML (2.x) flavor.
+///
+/// Each entry's `FITID` becomes its [`StatementEntry::id`], so re-importing a statement
+/// skips everything imported before. The payee is taken from `NAME`, falling back to `MEMO`.
+/// Amounts are converted to the smallest currency unit with `decimals` decimal places.
+pub fn parse(input: &str, decimals: u32) -> Result<Vec<StatementEntry>, ParseError> {
+    let mut entries = vec![];
+    let mut rest = input;
+    while let Some(start) = find_tag(rest, "STMTTRN") {
+        let block_start = &rest[start..];
+        let end = block_start.find("</STMTTRN>").unwrap_or(block_start.len());
+        let block = &block_start[..end];
+        let line = line_of(input, input.len() - block_start.len());
+        let error = |message: String| ParseError { line, message };
+
+        let date =
+            value(block, "DTPOSTED").ok_or_else(|| error("entry without DTPOSTED".to_string()))?;
+        let amount =
+            value(block, "TRNAMT").ok_or_else(|| error("entry without TRNAMT".to_string()))?;
+        let id = value(block, "FITID").ok_or_else(|| error("entry without FITID".to_string()))?;
+        entries.push(StatementEntry {
+            date: parse_date(date).ok_or_else(|| error(format!("invalid date {}", date)))?,
+            amount: parse_amount(amount, decimals).map_err(error)?,
+            payee: value(block, "NAME")
+                .or(value(block, "MEMO"))
+                .unwrap_or_default()
+                .to_string(),
+            id: Some(id.to_string()),
+        });
+        rest = &block_start[end..];
+    }
+    Ok(entries)
+}
+
+/// The offset of the first `<tag>` in `s`
+fn find_tag(s: &str, tag: &str) -> Option<usize> {
+    s.find(&format!("<{}>", tag))
+}
+
+/// The text following `<tag>` up to the next tag or line break, as both SGML and XML OFX put it
+fn value<'a>(block: &'a str, tag: &str) -> Option<&'a str> {
+    let start = find_tag(block, tag)? + tag.len() + 2;
+    let text = &block[start..];
+    let end = text.find(['<', '\n', '\r']).unwrap_or(text.len());
+    Some(text[..end].trim()).filter(|v| !v.is_empty())
+}
+
+/// `YYYYMMDD`, optionally followed by a time and time zone which are ignored
+fn parse_date(s: &str) -> Option<Date> {
+    let digits = s.get(..8)?;
+    Date::new(
+        digits[..4].parse().ok()?,
+        digits[4..6].parse().ok()?,
+        digits[6..].parse().ok()?,
+    )
+}
+
+fn line_of(input: &str, offset: usize) -> usize {
+    input[..offset].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sgml_ofx_works() {
+        let input = "OFXHEADER:100\nDATA:OFXSGML\n\n<OFX><BANKMSGSRSV1><STMTTRNRS><STMTRS><BANKTRANLIST>\n\
+            <STMTTRN>\n<TRNTYPE>DEBIT\n<DTPOSTED>20240115120000[-5:EST]\n<TRNAMT>-42.10\n<FITID>A1\n<NAME>Coffee Shop\n</STMTTRN>\n\
+            <STMTTRN>\n<TRNTYPE>CREDIT\n<DTPOSTED>20240116\n<TRNAMT>1000.00\n<FITID>A2\n<MEMO>Salary\n</STMTTRN>\n\
+            </BANKTRANLIST></STMTRS></STMTTRNRS></BANKMSGSRSV1></OFX>\n";
+
+        //act
+        let entries = parse(input, 2).unwrap();
+
+        assert_eq!(
+            entries,
+            vec![
+                StatementEntry {
+                    date: Date::new(2024, 1, 15).unwrap(),
+                    amount: -4210,
+                    payee: "Coffee Shop".to_string(),
+                    id: Some("A1".to_string()),
+                },
+                StatementEntry {
+                    date: Date::new(2024, 1, 16).unwrap(),
+                    amount: 100000,
+                    payee: "Salary".to_string(),
+                    id: Some("A2".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_xml_ofx_works() {
+        let input = "<?xml version=\"1.0\"?>\n<OFX>\n<STMTTRN><DTPOSTED>20240201</DTPOSTED><TRNAMT>5</TRNAMT><FITID>X9</FITID><NAME>Refund</NAME></STMTTRN>\n</OFX>";
+
+        let entries = parse(input, 2).unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].amount, 500);
+        assert_eq!(entries[0].key(), "id:X9");
+    }
+
+    #[test]
+    fn test_parse_ofx_requires_fitid() {
+        let input = "<OFX>\n\n<STMTTRN>\n<DTPOSTED>20240201\n<TRNAMT>5\n</STMTTRN>";
+
+        assert_eq!(
+            parse(input, 2),
+            Err(ParseError {
+                line: 3,
+                message: "entry without FITID".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use super::{parse_amount, ParseError, StatementEntry};
+use crate::date::Date;
+
+/// Parses the entries of a QIF bank statement.
+///
+/// Only the `D` (date), `T`/`U` (amount) and `P` (payee) fields are used; an entry ends
+/// with `^`. Dates are read as US style `M/D/YY`, `M/D'YY` or `M/D/YYYY`, with two digit
+/// years in the 2000s, or as `YYYY-MM-DD`. Amounts are converted to the smallest currency
+/// unit with `decimals` decimal places.
+pub fn parse(input: &str, decimals: u32) -> Result<Vec<StatementEntry>, ParseError> {
+    let mut entries = vec![];
+    let (mut date, mut amount, mut payee) = (None, None, String::new());
+    for (i, line) in input.lines().enumerate() {
+        let error = |message: String| ParseError {
+            line: i + 1,
+            message,
+        };
+        let line = line.trim();
+        let Some(field) = line.chars().next() else {
+            continue;
+        };
+        let value = &line[field.len_utf8()..];
+        match field {
+            // Headers like `!Type:Bank`
+            '!' => {}
+            'D' => {
+                date = Some(
+                    parse_date(value).ok_or_else(|| error(format!("invalid date {}", value)))?,
+                )
+            }
+            'T' | 'U' => amount = Some(parse_amount(value, decimals).map_err(error)?),
+            'P' => payee = value.to_string(),
+            '^' => {
+                let date = date
+                    .take()
+                    .ok_or_else(|| error("entry without date".to_string()))?;
+                let amount = amount
+                    .take()
+                    .ok_or_else(|| error("entry without amount".to_string()))?;
+                entries.push(StatementEntry {
+                    date,
+                    amount,
+                    payee: std::mem::take(&mut payee),
+                    id: None,
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+fn parse_date(s: &str) -> Option<Date> {
+    let s = s.trim();
+    if s.contains('-') {
+        return Date::parse_iso(s);
+    }
+    let normalized = s.replace('\'', "/");
+    let mut parts = normalized.split('/').map(|p| p.trim().parse::<i32>());
+    let (month, day, year) = (
+        parts.next()?.ok()?,
+        parts.next()?.ok()?,
+        parts.next()?.ok()?,
+    );
+    let year = if year < 100 { year + 2000 } else { year };
+    Date::new(year, month.try_into().ok()?, day.try_into().ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_qif_works() {
+        let input = "!Type:Bank\nD1/15'24\nT-1,200.00\nPLandlord\n^\nD02/01/2024\nU2500.5\nPACME Corp\nMSalary\n^\n";
+
+        //act
+        let entries = parse(input, 2).unwrap();
+
+        assert_eq!(
+            entries,
+            vec![
+                StatementEntry {
+                    date: Date::new(2024, 1, 15).unwrap(),
+                    amount: -120000,
+                    payee: "Landlord".to_string(),
+                    id: None,
+                },
+                StatementEntry {
+                    date: Date::new(2024, 2, 1).unwrap(),
+                    amount: 250050,
+                    payee: "ACME Corp".to_string(),
+                    id: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_qif_reports_errors() {
+        assert_eq!(
+            parse("!Type:Bank\nD13/45/2024\n", 2),
+            Err(ParseError {
+                line: 2,
+                message: "invalid date 13/45/2024".to_string()
+            })
+        );
+        assert_eq!(parse("T10\n^\n", 2).unwrap_err().line, 2);
+    }
+}
@@ -0,0 +1,143 @@
+//! Matching a bank statement against the ledger, to find what either side is missing.
+
+use super::StatementEntry;
+use crate::history::TxLog;
+
+/// The outcome of [`reconcile`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Reconciliation {
+    /// Statement entries and the position in the tx log of the entry each matched
+    pub matched: Vec<(StatementEntry, usize)>,
+    /// Statement entries without a ledger entry
+    pub unmatched_statement: Vec<StatementEntry>,
+    /// Positions in the tx log of the account's entries missing from the statement
+    pub unmatched_ledger: Vec<usize>,
+}
+
+/// Matches `entries` against the entries of `account` in `log`.
+///
+/// A statement entry matches a ledger entry of the same signed amount, deposits being positive,
+/// booked at most `max_days` days apart; of several candidates the closest in time wins, then
+/// the earliest. Every entry matches at most once. Ledger entries only count as unmatched if
+/// they fall into the statement's period, widened by `max_days`, as statements rarely cover
+/// the whole history.
+///
+/// Ledger transactions carry no reference, so the statement's [`StatementEntry::id`] isn't used
+/// for matching; it's kept in the result to tell the entries apart.
+pub fn reconcile(
+    entries: &[StatementEntry],
+    log: &TxLog,
+    account: &str,
+    max_days: u32,
+) -> Reconciliation {
+    let mut result = Reconciliation::default();
+    let (Some(first), Some(last)) = (
+        entries.iter().map(|entry| entry.date).min(),
+        entries.iter().map(|entry| entry.date).max(),
+    ) else {
+        return result;
+    };
+    let max_days = i64::from(max_days);
+    let (from, to) = (first.unix_days() - max_days, last.unix_days() + max_days);
+    let mut ledger: Vec<(usize, i64, i128)> = log
+        .account_entries(account)
+        .map(|(position, entry)| {
+            let amount = entry.tx.amount() as i128;
+            let amount = match entry.tx.is_credit() {
+                true => amount,
+                false => -amount,
+            };
+            (position, entry.timestamp.date().unix_days(), amount)
+        })
+        .filter(|(_, day, _)| (from..=to).contains(day))
+        .collect();
+
+    let mut sorted: Vec<&StatementEntry> = entries.iter().collect();
+    sorted.sort_by_key(|entry| entry.date);
+    for entry in sorted {
+        let day = entry.date.unix_days();
+        let candidate = ledger
+            .iter()
+            .enumerate()
+            .filter(|(_, &(_, booked, amount))| {
+                amount == entry.amount && (booked - day).abs() <= max_days
+            })
+            .min_by_key(|(_, &(position, booked, _))| ((booked - day).abs(), position))
+            .map(|(i, _)| i);
+        match candidate {
+            Some(i) => result
+                .matched
+                .push((entry.clone(), ledger.swap_remove(i).0)),
+            None => result.unmatched_statement.push(entry.clone()),
+        }
+    }
+    result.unmatched_ledger = ledger.into_iter().map(|(position, ..)| position).collect();
+    result.unmatched_ledger.sort_unstable();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::Timestamp, date::Date, storage::LogEntry, tx::Tx};
+
+    fn entry(amount: i128, day: u32, id: &str) -> StatementEntry {
+        StatementEntry {
+            date: Date::new(2024, 1, day).unwrap(),
+            amount,
+            payee: String::new(),
+            id: Some(id.to_string()),
+        }
+    }
+
+    fn booked(tx: Tx, day: u32) -> LogEntry {
+        LogEntry {
+            timestamp: Timestamp::start_of(Date::new(2024, 1, day).unwrap()),
+            tx,
+            actor: None,
+        }
+    }
+
+    #[test]
+    fn test_reconcile_reports_both_sides() {
+        let deposit = |amount| Tx::Deposit {
+            account: "ALICE".into(),
+            amount,
+        };
+        let log: TxLog = [
+            booked(deposit(100), 1),
+            booked(deposit(500), 10),
+            booked(
+                Tx::Withdraw {
+                    account: "ALICE".into(),
+                    amount: 200,
+                    recipient: None,
+                },
+                12,
+            ),
+            booked(deposit(500), 14),
+            booked(deposit(70), 15),
+            booked(deposit(500), 15),
+        ]
+        .into_iter()
+        .collect();
+        let statement = [
+            entry(-200, 13, "rent"),
+            entry(500, 15, "salary"),
+            entry(30, 15, "refund"),
+        ];
+
+        //act
+        let result = reconcile(&statement, &log, "ALICE", 2);
+        let idle = reconcile(&[], &log, "ALICE", 2);
+
+        assert_eq!(
+            result.matched,
+            vec![(statement[0].clone(), 2), (statement[1].clone(), 5)]
+        );
+        assert_eq!(result.unmatched_statement, vec![statement[2].clone()]);
+        // The deposit on the 10th is outside the statement's period
+        assert_eq!(result.unmatched_ledger, vec![3, 4]);
+        assert_eq!(idle, Reconciliation::default());
+    }
+}
@@ -0,0 +1,266 @@
+//! Importing a ledger exported by [`crate::export::state`], replacing the ledger or merging
+//! into it.
+
+use crate::{
+    accounts::Accounts,
+    diff::LedgerDiff,
+    export::state::LedgerState,
+    metadata::LedgerMetadata,
+    migrations,
+    snapshot::Snapshot,
+    storage::{self, LogEntry},
+    tx::Units,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+/// How [`plan`] combines an exported state with the ledger
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// The ledger becomes the exported one, log and metadata
+    Replace,
+    /// The entries of the export that the log lacks are appended, and accounts without
+    /// metadata take it from the export
+    Merge,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "replace" => Ok(Mode::Replace),
+            "merge" => Ok(Mode::Merge),
+            _ => Err(format!("expected replace or merge, got {:?}", s)),
+        }
+    }
+}
+
+/// What importing a state changes, see [`plan`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateImport {
+    /// The log of the ledger afterwards
+    pub log: Vec<LogEntry>,
+    pub metadata: LedgerMetadata,
+    /// Entries the log gains
+    pub added: Vec<LogEntry>,
+    /// Entries the log loses, only when replacing
+    pub removed: Vec<LogEntry>,
+    /// Accounts whose metadata changes, in name order
+    pub metadata_changed: Vec<String>,
+    /// How the balances change
+    pub balances: LedgerDiff,
+}
+
+impl StateImport {
+    /// Returns `true` if importing changes nothing
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.metadata_changed.is_empty()
+    }
+}
+
+/// A summary line of the entries and metadata, then the balances as [`LedgerDiff`] shows them
+impl fmt::Display for StateImport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} entries added, {} removed, metadata of {} accounts changed",
+            self.added.len(),
+            self.removed.len(),
+            self.metadata_changed.len()
+        )?;
+        for account in &self.metadata_changed {
+            writeln!(f, "* {}", account)?;
+        }
+        write!(f, "{}", self.balances)
+    }
+}
+
+/// Reads a state written by [`crate::export::state::write`], checking its log against its
+/// balances by replaying it into a `ledger()`, see [`LedgerState::of`]
+/// # Errors
+/// It doesn't parse, a newer crabbux wrote it, see [`migrations::upgrade`], or its log doesn't
+/// lead to its balances
+pub fn read(json: &[u8], ledger: impl Fn() -> Accounts) -> io::Result<LedgerState> {
+    let state: LedgerState = migrations::from_json(json, migrations::STATE)?;
+    let log = state.log.clone();
+    let replayed = LedgerState::of(ledger(), log, LedgerMetadata::default(), state.exported)?;
+    if replayed.hash != state.hash || replayed.balances != state.balances {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the exported log doesn't lead to the exported balances",
+        ));
+    }
+    Ok(state)
+}
+
+/// Works out what importing `state` in `mode` does to the ledger persisted as `log` with
+/// `metadata`, without changing anything. The logs are replayed into a `ledger()` each, see
+/// [`LedgerState::of`].
+///
+/// When merging, an entry of the export counts as present if the log has an equal one that
+/// no other entry of the export matched already.
+/// # Errors
+/// The resulting log can't be replayed, e.g. because the merged entries overdraw an account
+pub fn plan(
+    log: &[LogEntry],
+    metadata: &LedgerMetadata,
+    state: &LedgerState,
+    mode: Mode,
+    ledger: impl Fn() -> Accounts,
+) -> io::Result<StateImport> {
+    let (new_log, added, removed, new_metadata) = match mode {
+        Mode::Replace => (
+            state.log.clone(),
+            missing(&state.log, log),
+            missing(log, &state.log),
+            state.metadata.clone(),
+        ),
+        Mode::Merge => {
+            let added = missing(&state.log, log);
+            let mut merged = metadata.clone();
+            for (account, settings) in state.metadata.iter() {
+                if metadata.get(account).is_none_or(|own| own.is_empty()) {
+                    *merged.entry(account) = settings.clone();
+                }
+            }
+            let new_log = log.iter().chain(&added).cloned().collect();
+            (new_log, added, vec![], merged)
+        }
+    };
+    Ok(StateImport {
+        balances: LedgerDiff::between(&balances(ledger(), log)?, &balances(ledger(), &new_log)?),
+        metadata_changed: metadata.changed(&new_metadata),
+        log: new_log,
+        metadata: new_metadata,
+        added,
+        removed,
+    })
+}
+
+/// The entries of `entries` that `log` doesn't have, counting duplicates
+fn missing(entries: &[LogEntry], log: &[LogEntry]) -> Vec<LogEntry> {
+    let mut present: HashMap<&LogEntry, usize> = HashMap::new();
+    for entry in log {
+        *present.entry(entry).or_default() += 1;
+    }
+    entries
+        .iter()
+        .filter(|entry| match present.get_mut(entry) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                false
+            }
+            _ => true,
+        })
+        .cloned()
+        .collect()
+}
+
+fn balances(mut ledger: Accounts, log: &[LogEntry]) -> io::Result<BTreeMap<String, Units>> {
+    storage::replay(&mut ledger, log.iter().cloned().map(Ok))?;
+    Ok(Snapshot::of(&ledger, log.len()).balances)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::Timestamp, export::state, tx::Tx};
+
+    fn deposit(timestamp: u64, account: &str, amount: Units) -> LogEntry {
+        LogEntry {
+            timestamp: Timestamp(timestamp),
+            tx: Tx::Deposit {
+                account: account.into(),
+                amount,
+            },
+            actor: None,
+        }
+    }
+
+    #[test]
+    fn test_plan_merges_or_replaces_an_exported_state() {
+        let mut exported_metadata = LedgerMetadata::default();
+        exported_metadata.entry("BOB").block("EVE");
+        exported_metadata.entry("ALICE").block("EVE");
+        let exported = LedgerState::of(
+            Accounts::new(),
+            vec![deposit(1, "ALICE", 100), deposit(2, "BOB", 50)],
+            exported_metadata,
+            Timestamp(3),
+        )
+        .unwrap();
+        let mut json = vec![];
+        state::write(&mut json, &exported).unwrap();
+        let log = vec![deposit(1, "ALICE", 100), deposit(5, "CAROL", 10)];
+        let mut metadata = LedgerMetadata::default();
+        metadata.entry("ALICE").allow("BOB");
+
+        //act
+        let imported = read(&json, Accounts::new).unwrap();
+        let merged = plan(&log, &metadata, &imported, Mode::Merge, Accounts::new).unwrap();
+        let replaced = plan(&log, &metadata, &imported, Mode::Replace, Accounts::new).unwrap();
+        let again = plan(
+            &merged.log,
+            &merged.metadata,
+            &imported,
+            Mode::Merge,
+            Accounts::new,
+        )
+        .unwrap();
+        let tampered = String::from_utf8(json).unwrap().replace("100", "900");
+
+        assert_eq!(imported, exported);
+        assert_eq!(merged.added, vec![deposit(2, "BOB", 50)]);
+        assert_eq!(merged.log.len(), 3);
+        assert_eq!(merged.metadata_changed, vec!["BOB"]);
+        assert_eq!(merged.metadata.get("ALICE"), metadata.get("ALICE"));
+        assert_eq!(merged.balances.added, vec![("BOB".to_string(), 50)]);
+        assert_eq!(replaced.removed, vec![deposit(5, "CAROL", 10)]);
+        assert_eq!(replaced.metadata_changed, vec!["ALICE", "BOB"]);
+        assert_eq!(replaced.balances.removed, vec![("CAROL".to_string(), 10)]);
+        assert!(again.is_empty());
+        assert!(read(tampered.as_bytes(), Accounts::new).is_err());
+        assert_eq!("replace".parse(), Ok(Mode::Replace));
+    }
+
+    #[test]
+    fn test_plan_replays_overdrafts_on_credit() {
+        let rules = || {
+            let mut ledger = Accounts::new();
+            ledger.set_credit_limit("ALICE", 50);
+            ledger
+        };
+        let mut ledger = rules();
+        let log: Vec<LogEntry> = [
+            ledger.deposit("ALICE", 10).unwrap(),
+            ledger.withdraw("ALICE", 30).unwrap(),
+        ]
+        .into_iter()
+        .map(|tx| LogEntry {
+            timestamp: Timestamp(1),
+            tx,
+            actor: None,
+        })
+        .collect();
+        let exported = LedgerState::of(rules(), log, LedgerMetadata::default(), Timestamp(2));
+        let mut json = vec![];
+        state::write(&mut json, exported.as_ref().unwrap()).unwrap();
+
+        //act
+        let imported = read(&json, rules).unwrap();
+        let without_credit = read(&json, Accounts::new);
+        let replaced = plan(
+            &[],
+            &LedgerMetadata::default(),
+            &imported,
+            Mode::Replace,
+            rules,
+        );
+
+        assert!(without_credit.is_err());
+        assert_eq!(replaced.unwrap().log.len(), 2);
+    }
+}
@@ -0,0 +1,253 @@
+//! Tiered interest rates, e.g. 1% on the first 10 000 and 2% on everything above.
+//!
+//! Schedules are configured per account kind with `interest.<kind> = <schedule>` in the config
+//! file, see [`RateSchedule`] for the format, and accounts are given a kind with
+//! `account_kind.<account> = <kind>`. [`crate::accounts::Accounts::accrue_interest`] mints what
+//! they earned, which `crabbux interest [--days <n>]` does on the persisted ledger.
+
+use crate::config::Config;
+use crate::rounding::RoundingPolicy;
+use crate::tx::Units;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+const BASIS_POINTS: u128 = 10_000;
+const DAYS_PER_YEAR: u128 = 365;
+
+/// One band of a [`RateSchedule`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tier {
+    /// The annual rate in basis points, i.e. 150 is 1.5%
+    pub rate: u32,
+    /// The balance up to which this rate applies, `None` for the last tier
+    pub up_to: Option<Units>,
+}
+
+/// Annual interest rates by balance band. Each rate only applies to the part of the balance
+/// within its band, so crossing into a higher tier never lowers the interest.
+///
+/// Parsed from comma separated `<rate>%:<up to>` tiers ending with an unbounded `<rate>%`,
+/// like `1%:10000,2%`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateSchedule {
+    tiers: Vec<Tier>,
+}
+
+impl RateSchedule {
+    /// Creates a schedule from tiers in ascending order
+    /// # Errors
+    /// There are no tiers, the bounds don't strictly increase, or only the last tier is unbounded
+    pub fn new(tiers: Vec<Tier>) -> Result<Self, String> {
+        let Some((last, bounded)) = tiers.split_last() else {
+            return Err("a rate schedule needs at least one tier".to_string());
+        };
+        if last.up_to.is_some() {
+            return Err("the last tier must be unbounded".to_string());
+        }
+        let mut previous = None;
+        for tier in bounded {
+            match tier.up_to {
+                Some(up_to) if previous.is_none_or(|p| up_to > p) => previous = Some(up_to),
+                Some(_) => return Err("tier bounds must increase".to_string()),
+                None => return Err("only the last tier may be unbounded".to_string()),
+            }
+        }
+        Ok(RateSchedule { tiers })
+    }
+
+    /// A single rate for every balance
+    pub fn flat(rate: u32) -> Self {
+        RateSchedule {
+            tiers: vec![Tier { rate, up_to: None }],
+        }
+    }
+
+    /// The tiers in ascending order
+    pub fn tiers(&self) -> &[Tier] {
+        &self.tiers
+    }
+
+    /// The interest `balance` earns in a year, rounded down to the smallest unit of the currency
+    pub fn annual_interest(&self, balance: Units) -> Units {
+        self.interest(balance, DAYS_PER_YEAR as u32)
+    }
+
+    /// The interest `balance` earns in `days` days of a 365 day year, rounded down to the
+    /// smallest unit of the currency, whatever its [`crate::amount::decimals_of`]
+    pub fn interest(&self, balance: Units, days: u32) -> Units {
+        self.interest_rounded(balance, days, RoundingPolicy::Floor)
+    }
+
+    /// Like [`RateSchedule::interest`], rounded by `policy` instead, e.g. the ledger's
+    /// [`crate::accounts::Accounts::rounding`]
+    pub fn interest_rounded(&self, balance: Units, days: u32, policy: RoundingPolicy) -> Units {
+        let mut lower: Units = 0;
+        let mut scaled = 0u128;
+        for tier in &self.tiers {
+            let upper = tier.up_to.unwrap_or(Units::MAX).min(balance);
+            let earned = (upper.saturating_sub(lower) as u128).saturating_mul(tier.rate as u128);
+            scaled = scaled.saturating_add(earned);
+            if upper == balance {
+                break;
+            }
+            lower = upper;
+        }
+        let interest = policy.divide(
+            scaled.saturating_mul(days as u128),
+            BASIS_POINTS * DAYS_PER_YEAR,
+        );
+        interest.min(Units::MAX as u128) as Units
+    }
+}
+
+impl FromStr for RateSchedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let tiers = s
+            .split(',')
+            .map(|tier| {
+                let (rate, up_to) = match tier.split_once(':') {
+                    Some((rate, up_to)) => (rate, Some(up_to.trim())),
+                    None => (tier, None),
+                };
+                let rate = parse_rate(rate.trim())
+                    .ok_or_else(|| format!("invalid rate {:?}", rate.trim()))?;
+                let up_to = up_to
+                    .map(|up_to| {
+                        up_to
+                            .parse()
+                            .map_err(|_| format!("invalid bound {:?}", up_to))
+                    })
+                    .transpose()?;
+                Ok(Tier { rate, up_to })
+            })
+            .collect::<Result<_, String>>()?;
+        RateSchedule::new(tiers)
+    }
+}
+
+impl fmt::Display for RateSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, tier) in self.tiers.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}.{:02}%", tier.rate / 100, tier.rate % 100)?;
+            if let Some(up_to) = tier.up_to {
+                write!(f, ":{}", up_to)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a percentage with up to two decimals, like `2%` or `1.25%`, into basis points
+pub(crate) fn parse_rate(rate: &str) -> Option<u32> {
+    let rate = rate.strip_suffix('%')?;
+    let (whole, fraction) = rate.split_once('.').unwrap_or((rate, ""));
+    if fraction.len() > 2 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let whole: u32 = whole.parse().ok()?;
+    let fraction: u32 = format!("{:0<2}", fraction).parse().ok()?;
+    whole.checked_mul(100)?.checked_add(fraction)
+}
+
+/// The [`RateSchedule`] of each account kind
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InterestSchedules {
+    by_kind: BTreeMap<String, RateSchedule>,
+}
+
+impl InterestSchedules {
+    /// Reads every `interest.<kind> = <schedule>` setting
+    /// # Errors
+    /// A schedule is invalid; the error names its setting
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let by_kind = config
+            .with_prefix("interest.")
+            .map(|(kind, schedule)| {
+                let schedule = schedule
+                    .parse()
+                    .map_err(|e| format!("interest.{}: {}", kind, e))?;
+                Ok((kind.to_string(), schedule))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(InterestSchedules { by_kind })
+    }
+
+    /// Sets the schedule of accounts of kind `kind`
+    pub fn insert(&mut self, kind: &str, schedule: RateSchedule) {
+        self.by_kind.insert(kind.to_string(), schedule);
+    }
+
+    /// The schedule of accounts of kind `kind`, if one is configured
+    pub fn get(&self, kind: &str) -> Option<&RateSchedule> {
+        self.by_kind.get(kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_schedule_applies_rates_per_tier() {
+        let schedule: RateSchedule = "1%:10000,2%".parse().unwrap();
+
+        //act
+        let below = schedule.annual_interest(5_000);
+        let at = schedule.annual_interest(10_000);
+        let above = schedule.annual_interest(15_000);
+
+        assert_eq!(below, 50);
+        assert_eq!(at, 100);
+        assert_eq!(above, 100 + 100);
+        assert_eq!(schedule.interest(15_000, 73), 40);
+        // 1% of 150 is 1.5
+        assert_eq!(schedule.annual_interest(150), 1);
+        assert_eq!(
+            schedule.interest_rounded(150, 365, RoundingPolicy::HalfUp),
+            2
+        );
+        assert_eq!(schedule.annual_interest(0), 0);
+        assert_eq!(RateSchedule::flat(150).annual_interest(1_000), 15);
+        assert_eq!(schedule.to_string(), "1.00%:10000,2.00%");
+    }
+
+    #[test]
+    fn test_rate_schedule_rejects_invalid_tiers() {
+        assert!("".parse::<RateSchedule>().is_err());
+        assert!("1%:10000".parse::<RateSchedule>().is_err());
+        assert!("1%:10000,2%:5000,3%".parse::<RateSchedule>().is_err());
+        assert!("1%,2%".parse::<RateSchedule>().is_err());
+        assert!("1.234%".parse::<RateSchedule>().is_err());
+        assert_eq!(
+            "1.5%".parse::<RateSchedule>().unwrap().tiers(),
+            &[Tier {
+                rate: 150,
+                up_to: None
+            }]
+        );
+    }
+
+    #[test]
+    fn test_interest_schedules_from_config_works() {
+        let config =
+            Config::parse("interest.savings = 1%:10000,2%\ninterest.checking = 0%").unwrap();
+
+        //act
+        let schedules = InterestSchedules::from_config(&config).unwrap();
+
+        assert_eq!(
+            schedules.get("savings").unwrap().annual_interest(20_000),
+            300
+        );
+        assert_eq!(schedules.get("checking"), Some(&RateSchedule::flat(0)));
+        assert_eq!(schedules.get("loan"), None);
+        let invalid = Config::parse("interest.savings = lots").unwrap();
+        assert!(InterestSchedules::from_config(&invalid).is_err());
+    }
+}
@@ -0,0 +1,68 @@
+// Widening `Units` to `u128` is a no-op with the `u128` feature
+#![cfg_attr(feature = "u128", allow(clippy::unnecessary_cast))]
+
+pub mod accounts;
+pub mod amount;
+pub mod anomaly;
+pub mod apikey;
+#[cfg(feature = "native")]
+pub mod backup;
+pub mod checksum;
+#[cfg(feature = "native")]
+pub mod client;
+pub mod clock;
+pub mod config;
+pub mod core;
+pub mod currency;
+pub mod date;
+pub mod diff;
+pub mod dispute;
+pub mod dryrun;
+pub mod errors;
+pub mod escrow;
+pub mod events;
+pub mod export;
+pub mod fees;
+pub mod ffi;
+pub mod fuzz;
+pub mod goals;
+pub mod history;
+pub mod i18n;
+pub mod import;
+pub mod interest;
+pub mod limits;
+#[cfg(feature = "native")]
+pub mod logging;
+pub mod manager;
+pub mod metadata;
+pub mod metrics;
+pub mod migrations;
+pub mod multisig;
+pub mod netting;
+pub mod overlay;
+pub mod plugins;
+pub mod promo;
+pub mod prompt;
+pub mod query;
+pub mod ratelimit;
+pub mod rates;
+pub mod rounding;
+pub mod rpc;
+#[cfg(feature = "native")]
+pub mod scripting;
+#[cfg(feature = "native")]
+pub mod server;
+pub mod sharded;
+pub mod shared;
+pub mod shutdown;
+pub mod snapshot;
+pub mod standing;
+pub mod stats;
+pub mod storage;
+pub mod tx;
+#[cfg(feature = "native")]
+pub mod wal;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "native")]
+pub mod webhooks;
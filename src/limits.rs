@@ -0,0 +1,144 @@
+//! Spending limits between two accounts, e.g. at most 500 a day from ALICE to BOB, see
+//! [`crate::accounts::Accounts::set_spending_limit`]. Direct-debit mandates limit what the
+//! payee may collect the same way, see [`crate::accounts::Accounts::grant_mandate`].
+
+use crate::clock::Timestamp;
+use crate::tx::Units;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// The length of the window a [`SpendingLimit`] applies to. Windows are aligned to the Unix
+/// epoch, so a daily limit resets at midnight UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Hour,
+    Day,
+    Week,
+}
+
+impl Period {
+    fn millis(self) -> u64 {
+        match self {
+            Period::Hour => 3_600_000,
+            Period::Day => 86_400_000,
+            Period::Week => 7 * 86_400_000,
+        }
+    }
+
+    /// The number of the window `at` falls into
+    fn window(self, at: Timestamp) -> u64 {
+        at.0 / self.millis()
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Period::Hour => "hour",
+            Period::Day => "day",
+            Period::Week => "week",
+        })
+    }
+}
+
+/// At most `max` may be sent per `period`.
+///
+/// Parsed from `<max>/<hour|day|week>`, e.g. `500/day`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpendingLimit {
+    pub max: Units,
+    pub period: Period,
+}
+
+impl FromStr for SpendingLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let invalid = || format!("expected `<max>/<hour|day|week>`, got {:?}", s);
+        let (max, period) = s.split_once('/').ok_or_else(invalid)?;
+        let period = match period.trim() {
+            "hour" => Period::Hour,
+            "day" => Period::Day,
+            "week" => Period::Week,
+            _ => return Err(invalid()),
+        };
+        Ok(SpendingLimit {
+            max: max.trim().parse().map_err(|_| invalid())?,
+            period,
+        })
+    }
+}
+
+impl fmt::Display for SpendingLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.max, self.period)
+    }
+}
+
+/// A [`SpendingLimit`] and what was sent in its current window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Allowance {
+    pub(crate) limit: SpendingLimit,
+    window: u64,
+    spent: Units,
+}
+
+impl Allowance {
+    pub(crate) fn new(limit: SpendingLimit) -> Self {
+        Allowance {
+            limit,
+            window: 0,
+            spent: 0,
+        }
+    }
+
+    /// What may still be sent in the window `now` falls into
+    pub(crate) fn remaining(&self, now: Timestamp) -> Units {
+        if self.limit.period.window(now) == self.window {
+            self.limit.max.saturating_sub(self.spent)
+        } else {
+            self.limit.max
+        }
+    }
+
+    /// Counts `amount` as sent at `now`, unless that's before the current window
+    pub(crate) fn spend(&mut self, now: Timestamp, amount: Units) {
+        let window = self.limit.period.window(now);
+        if window < self.window {
+            return;
+        }
+        if window != self.window {
+            self.window = window;
+            self.spent = 0;
+        }
+        self.spent = self.spent.saturating_add(amount);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowance_resets_every_period() {
+        let limit: SpendingLimit = "500/day".parse().unwrap();
+        let mut allowance = Allowance::new(limit);
+        let day = Period::Day.millis();
+
+        //act
+        allowance.spend(Timestamp(day), 300);
+        let same_day = allowance.remaining(Timestamp(2 * day - 1));
+        let next_day = allowance.remaining(Timestamp(2 * day));
+
+        assert_eq!(limit.period, Period::Day);
+        assert_eq!((same_day, next_day), (200, 500));
+        allowance.spend(Timestamp(2 * day), 600);
+        assert_eq!(allowance.remaining(Timestamp(2 * day)), 0);
+        assert_eq!(limit.to_string(), "500/day");
+        assert!("500/month".parse::<SpendingLimit>().is_err());
+        assert!("lots/day".parse::<SpendingLimit>().is_err());
+    }
+}
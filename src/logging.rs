@@ -0,0 +1,88 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::{subscriber, Level, Subscriber};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+
+/// Where operational logs go and what they look like
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogConfig {
+    /// The most verbose level that is still logged
+    pub level: Level,
+    /// Append to this file instead of writing to stderr
+    pub file: Option<PathBuf>,
+    /// One JSON object per line instead of human readable text
+    pub json: bool,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            level: Level::INFO,
+            file: None,
+            json: false,
+        }
+    }
+}
+
+impl LogConfig {
+    /// Builds a subscriber according to this config.
+    /// # Errors
+    /// The log file couldn't be opened
+    pub fn subscriber(&self) -> io::Result<Box<dyn Subscriber + Send + Sync>> {
+        let (writer, ansi) = match &self.file {
+            Some(path) => {
+                let file: File = OpenOptions::new().create(true).append(true).open(path)?;
+                (BoxMakeWriter::new(Mutex::new(file)), false)
+            }
+            None => (BoxMakeWriter::new(io::stderr), true),
+        };
+        let builder = tracing_subscriber::fmt()
+            .with_max_level(self.level)
+            .with_target(false)
+            .with_ansi(ansi)
+            .with_writer(writer);
+        Ok(if self.json {
+            Box::new(builder.json().finish())
+        } else {
+            Box::new(builder.finish())
+        })
+    }
+
+    /// Installs the subscriber for the whole process.
+    /// # Errors
+    /// The log file couldn't be opened or a subscriber was already installed
+    pub fn init(&self) -> io::Result<()> {
+        subscriber::set_global_default(self.subscriber()?).map_err(io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_log_config_writes_json_to_file() {
+        let path = std::env::temp_dir().join(format!("crabbux-log-{}.json", std::process::id()));
+        let config = LogConfig {
+            level: Level::WARN,
+            file: Some(path.clone()),
+            json: true,
+        };
+
+        subscriber::with_default(config.subscriber().unwrap(), || {
+            tracing::info!("filtered");
+            tracing::warn!(account = "ALICE", "kept");
+        });
+
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let line: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["fields"]["account"], "ALICE");
+    }
+}
@@ -1,76 +1,3151 @@
-use crate::{accounts::Accounts, tx::Tx};
-use std::{io, println};
-mod accounts;
-mod core;
-mod errors;
-mod tx;
+use crabbux::{
+    accounts::{Accounts, Thresholds},
+    amount::{self, NumberFormat},
+    anomaly::{Action, AnomalyPolicy},
+    apikey::ApiKeys,
+    backup, checksum,
+    client::{ClientError, RemoteLedger},
+    clock::{Clock, SystemClock, Timestamp},
+    config::Config,
+    currency::CurrencyRegistry,
+    date::{self, Date},
+    diff::LedgerDiff,
+    dispute,
+    dryrun::{self, Op, Recorder},
+    errors::ApplicationError,
+    escrow,
+    events::{LedgerEvent, OwnershipChange, Threshold},
+    export::{beancount, html, journal, state},
+    fees::FeeSchedules,
+    goals::Goal,
+    history::TxLog,
+    i18n::{self, tr, Key, Locale},
+    import::{
+        self, camt, csv, ofx, qif,
+        reconcile::{self, Reconciliation},
+        StatementEntry,
+    },
+    interest::InterestSchedules,
+    limits::SpendingLimit,
+    logging::LogConfig,
+    manager::LedgerManager,
+    metadata::{self, LedgerMetadata},
+    metrics::Metrics,
+    migrations,
+    multisig::MultisigPolicy,
+    netting::{self, Obligation},
+    plugins::{BalancePlugin, LedgerApi, PluginRegistry},
+    promo,
+    prompt::{Aliases, PromptHistory},
+    query::{AccountFilter, AccountQuery, SortKey},
+    ratelimit::{RateLimit, RateLimiter},
+    rates::{self, RateError, RateProvider, RateTable},
+    rounding::Rounding,
+    rpc::{RpcServer, CONFIRMATION_REQUIRED},
+    scripting::run_script,
+    server::HttpServer,
+    shared::SharedAccounts,
+    shutdown::Shutdown,
+    snapshot::{self, Divergence, Snapshot},
+    standing::{self, StandingOrder, StandingOrders},
+    storage::{self, FileStore, LogEntry, LogReader},
+    tx::{Tx, Units},
+    wal::{self, MmapWal, WalWriter},
+    webhooks::{self, WebhookConfig},
+};
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::Signals,
+};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashSet, VecDeque},
+    env,
+    error::Error,
+    fmt::Display,
+    fs, io,
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+    println, process,
+    rc::Rc,
+    str::FromStr,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+use tracing::{debug, error, info, info_span, warn, Level};
 
 enum InputResult {
     Quit,
     Print,
+    Metrics,
     Confirmed(Vec<Tx>),
+    /// Switch to the named ledger
+    Use(String),
     NotSupported,
 }
 
+/// The ledger the interactive mode works on
+enum Session {
+    /// A single local or remote ledger, persisted if it has a log
+    Single(Box<dyn LedgerApi>, Option<Persist>),
+    /// The named ledgers of a [`LedgerManager`], switched between with `use <name>`
+    Managed(LedgerManager),
+    /// A local ledger that every command runs on a fork of, for `--dry-run`
+    DryRun(Box<Accounts>),
+}
+
+/// What the interactive prompt keeps besides the ledger
+struct Prompt {
+    history: PromptHistory,
+    aliases: Aliases,
+    /// How many lines long listings show before waiting for return, see [`page`]
+    page_size: Option<usize>,
+}
+
+impl Session {
+    fn ledger(&mut self) -> &mut dyn LedgerApi {
+        match self {
+            Session::Single(ledger, _) => ledger.as_mut(),
+            Session::Managed(manager) => manager.ledger().expect("a ledger is always selected"),
+            Session::DryRun(ledger) => ledger.as_mut(),
+        }
+    }
+
+    fn persist(&self, txs: &[Tx]) -> io::Result<()> {
+        match self {
+            Session::Single(ledger, Some(persist)) => persist(txs, ledger.whoami()),
+            Session::Single(_, None) => Ok(()),
+            Session::Managed(manager) => manager.persist(txs),
+            Session::DryRun(_) => Ok(()),
+        }
+    }
+}
+
 fn main() {
-    // Creates the basic ledger and a tx log container
-    let mut ledger = Accounts::new();
-    let mut tx_log = vec![];
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    if let Err(e) = init_logging(&args) {
+        eprintln!("{}", tr(Key::LoggingFailed, &[&e]));
+        return;
+    }
+    let mut config = match load_config(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", tr(Key::ConfigUnreadable, &[&e]));
+            return;
+        }
+    };
+    // `--locale <tag>` wins over the config file, which wins over the environment
+    let locale = match flag_value(&args, "--locale").or(config.get("locale")) {
+        Some(tag) => Locale::from_tag(tag),
+        None => Locale::from_env(),
+    };
+    i18n::set_locale(locale.unwrap_or_default());
+    match amount::decimals_of(&config) {
+        Ok(decimals) => amount::set_decimals(decimals.unwrap_or(0)),
+        Err(e) => {
+            eprintln!("{}", tr(Key::ConfigUnreadable, &[&e]));
+            return;
+        }
+    }
+    amount::set_currency(config.get("currency"));
+    // Unknown currencies are only warned about, like those of ledgers set up before the
+    // registry
+    match CurrencyRegistry::from_config(&config) {
+        Ok(registry) => {
+            if let Some(Err(e)) = config.get("currency").map(|code| registry.validate(code)) {
+                warn!("{}", e);
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", tr(Key::ConfigUnreadable, &[&e]));
+            return;
+        }
+    }
+    // Rules like credit limits must be in place before a log is replayed
+    let rules = match LedgerRules::from_config(&config) {
+        Ok(rules) => LedgerRules {
+            principal: flag_value(&args, "--as").map(str::to_string),
+            viewer: args.iter().any(|arg| arg == "--viewer"),
+            ..rules
+        },
+        Err(e) => {
+            eprintln!("{}", tr(Key::ConfigUnreadable, &[&e]));
+            return;
+        }
+    };
+    // `--ledger <name>` stands for the named ledger's log in the ledger directory
+    let manager = LedgerManager::new(ledger_dir(&args, &config));
+    let ledger_name = flag_value(&args, "--ledger").map(str::to_string);
+    if let Some(name) = &ledger_name {
+        if flag_value(&args, "--tx-log").is_none() && flag_value(&args, "--wal").is_none() {
+            match manager.log_path(name) {
+                Ok(path) => args.extend(["--tx-log".into(), path.display().to_string()]),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            }
+        }
+    }
+    // `tx_log = <path>` or `wal = <path>` in the config, as the first-run setup writes them,
+    // stand for the flags when no other ledger is given
+    if log_path(&args).is_none() && ledger_name.is_none() && flag_value(&args, "--remote").is_none()
+    {
+        if let Some(path) = config.get("wal") {
+            args.extend(["--wal".into(), path.into()]);
+        } else if let Some(path) = config.get("tx_log") {
+            args.extend(["--tx-log".into(), path.into()]);
+        }
+    }
+    if let Some(path) = Config::default_path().filter(|path| needs_setup(&args, &config, path)) {
+        if let Err(e) = first_run_setup(&mut args, &mut config, &path, &rules) {
+            eprintln!("{}", tr(Key::SetupFailed, &[&e]));
+            return;
+        }
+    }
+    // `--dry-run` works on a fork of a local ledger, see `dryrun`, and servers and restores
+    // have nothing to report per command
+    if dry_run(&args) {
+        let command = args.first().map(String::as_str);
+        if flag_value(&args, "--remote").is_some() {
+            eprintln!("{}", tr(Key::DryRunNeedsLocal, &[]));
+            return;
+        }
+        if let Some(command @ ("rpc" | "serve" | "restore")) = command {
+            eprintln!("{}", tr(Key::NoDryRun, &[&command]));
+            return;
+        }
+    }
+    // Quitting, SIGTERM and stopping the server all end up completing this
+    let shutdown = Arc::new(Shutdown::new());
+    match args.first().map(String::as_str) {
+        // `rpc` serves JSON-RPC on stdio, `rpc --listen <addr>` on TCP, persisting to the
+        // `--tx-log`/`--wal` if given, backed up every `backup.interval`
+        Some("rpc") => {
+            let (ledger, history) = match serve_ledger(&args, &rules, &shutdown) {
+                Ok(served) => served,
+                Err(e) => {
+                    eprintln!("{}", tr(Key::LogUnreadable, &[&e]));
+                    hint_backup(&args, &config, &rules.ledger(), &*e);
+                    return;
+                }
+            };
+            if let Err(e) = schedule_backups(&args, &config, &rules, &shutdown) {
+                eprintln!("{}", tr(Key::ConfigUnreadable, &[&e]));
+                return;
+            }
+            // Serving TCP never returns, so a signal completes the shutdown right away
+            exit_on_signals(shutdown.clone(), OnSignal::Exit(Box::new(|| {})));
+            let server = RpcServer::with_history(ledger, history);
+            if let Err(e) = schedule_promo_expiry(&config, server.ledger(), &shutdown) {
+                eprintln!("{}", tr(Key::ConfigUnreadable, &[&e]));
+                return;
+            }
+            if let Err(e) = schedule_standing_orders(&args, &config, server.ledger(), &shutdown) {
+                eprintln!("{}", tr(Key::ConfigUnreadable, &[&e]));
+                return;
+            }
+            if let Some(config) = webhook_config(&args) {
+                webhooks::spawn(config, server.subscribe());
+            }
+            let result = match flag_value(&args, "--listen") {
+                Some(addr) => server.serve_tcp(addr),
+                None => server.serve_stdio(),
+            };
+            if let Err(e) = result {
+                error!(error = %e, "rpc server stopped");
+            }
+            shutdown.complete();
+            return;
+        }
+        // `serve [--listen <addr>] [--tls-cert <pem> --tls-key <pem>]` runs the HTTP server mode,
+        // rate limited by `rate_limit.client = <n>/<second|minute|hour>` and
+        // `rate_limit.account = ...`, and only for the keys of
+        // `api_key.<name> = <secret> <read|full> [<account>,...]` if any, performing their calls
+        // as the principal `<name>` rather than `--as`. Without the TLS flags
+        // the `tls.cert` and `tls.key` settings are used, and without those plain HTTP. Commits
+        // are persisted to the `--tx-log`/`--wal` if given, and backed up every `backup.interval`.
+        // SIGTERM stops it gracefully.
+        Some("serve") => {
+            let addr = flag_value(&args, "--listen").unwrap_or("127.0.0.1:8080");
+            let tls_path = |flag, key| flag_value(&args, flag).or_else(|| config.get(key));
+            let tls = match (
+                tls_path("--tls-cert", "tls.cert"),
+                tls_path("--tls-key", "tls.key"),
+            ) {
+                (Some(cert), Some(key)) => match (fs::read(cert), fs::read(key)) {
+                    (Ok(cert), Ok(key)) => Some((cert, key)),
+                    (Err(e), _) | (_, Err(e)) => {
+                        eprintln!("{}", tr(Key::TlsUnreadable, &[&e]));
+                        return;
+                    }
+                },
+                (None, None) => None,
+                _ => {
+                    eprintln!("{}", tr(Key::TlsIncomplete, &[]));
+                    return;
+                }
+            };
+            let limit = |key| config.get(key).map(str::parse::<RateLimit>).transpose();
+            let (client_limit, account_limit) =
+                match (limit("rate_limit.client"), limit("rate_limit.account")) {
+                    (Ok(client), Ok(account)) => (client, account),
+                    (Err(e), _) | (_, Err(e)) => {
+                        eprintln!("{}", tr(Key::ConfigUnreadable, &[&e]));
+                        return;
+                    }
+                };
+            let keys = match ApiKeys::from_config(&config) {
+                Ok(keys) => keys,
+                Err(e) => {
+                    eprintln!("{}", tr(Key::ConfigUnreadable, &[&e]));
+                    return;
+                }
+            };
+            if keys.is_empty() {
+                warn!("no API keys configured, serving every request");
+            }
+            let (ledger, history) = match serve_ledger(&args, &rules, &shutdown) {
+                Ok(served) => served,
+                Err(e) => {
+                    eprintln!("{}", tr(Key::LogUnreadable, &[&e]));
+                    hint_backup(&args, &config, &rules.ledger(), &*e);
+                    return;
+                }
+            };
+            if let Err(e) = schedule_backups(&args, &config, &rules, &shutdown) {
+                eprintln!("{}", tr(Key::ConfigUnreadable, &[&e]));
+                return;
+            }
+            let rpc = RpcServer::with_history(ledger, history);
+            if let Err(e) = schedule_promo_expiry(&config, rpc.ledger(), &shutdown) {
+                eprintln!("{}", tr(Key::ConfigUnreadable, &[&e]));
+                return;
+            }
+            if let Err(e) = schedule_standing_orders(&args, &config, rpc.ledger(), &shutdown) {
+                eprintln!("{}", tr(Key::ConfigUnreadable, &[&e]));
+                return;
+            }
+            if let Some(config) = webhook_config(&args) {
+                webhooks::spawn(config, rpc.subscribe());
+            }
+            let tls_enabled = tls.is_some();
+            let server = match tls {
+                Some((cert, key)) => HttpServer::bind_tls(addr, rpc, cert, key),
+                None => HttpServer::bind(addr, rpc),
+            };
+            let server = server.map(|mut server| {
+                server = server.with_api_keys(keys);
+                if let Some(limit) = client_limit {
+                    server = server.with_client_limit(RateLimiter::new(limit));
+                }
+                if let Some(limit) = account_limit {
+                    server = server.with_account_limit(RateLimiter::new(limit));
+                }
+                server
+            });
+            match server {
+                Ok(server) => {
+                    info!(addr, tls = tls_enabled, "listening");
+                    exit_on_signals(shutdown.clone(), OnSignal::Request);
+                    server.run_until(&shutdown);
+                    info!("server stopped");
+                    shutdown.complete();
+                }
+                Err(e) => error!(error = %e, "couldn't start server"),
+            }
+            return;
+        }
+        // `script <file>` runs a rhai script against the persisted or `--remote` ledger
+        Some("script") => {
+            if let Err(e) = script(&args, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"script", &e]));
+            }
+            return;
+        }
+        // `export [--format <format>]` writes the persisted `--tx-log`/`--wal` history to stdout
+        Some("export") => {
+            let format = flag_value(&args, "--format").unwrap_or("ledger");
+            let result = read_txs(&args)
+                .and_then(|txs| export(format, &txs, &args, &config, &mut io::stdout().lock()));
+            if let Err(e) = result {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"export", &e]));
+            }
+            return;
+        }
+        // `export-state [--out <file>]` writes the balances, metadata and history of the
+        // persisted ledger as one JSON document, to stdout without `--out`
+        Some("export-state") => {
+            if let Err(e) = export_state(&args, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"export-state", &e]));
+                process::exit(1);
+            }
+            return;
+        }
+        // `import-state <file> [--replace] [--dry-run] [--yes]` merges an exported state into
+        // the persisted ledger, or replaces it
+        Some("import-state") => {
+            if let Err(e) = import_state(&args, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"import-state", &e]));
+                process::exit(1);
+            }
+            return;
+        }
+        // `simulate <file>` checks what a batch of operations would do to the persisted ledger
+        Some("simulate") => {
+            match simulate(&args, &rules) {
+                Ok(true) => {}
+                Ok(false) => process::exit(1),
+                Err(e) => {
+                    eprintln!("{}", tr(Key::CommandFailed, &[&"simulate", &e]));
+                    process::exit(2);
+                }
+            }
+            return;
+        }
+        // `import <file> --account <name>` applies a bank statement to the persisted ledger
+        Some("import") => {
+            if let Err(e) = import(&args, &config, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"import", &e]));
+            }
+            return;
+        }
+        // `reconcile <file> --account <name>` compares a bank statement with the persisted ledger
+        Some("reconcile") => {
+            match reconcile(&args, &config) {
+                Ok(result) => {
+                    if !result.unmatched_statement.is_empty() || !result.unmatched_ledger.is_empty()
+                    {
+                        process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{}", tr(Key::CommandFailed, &[&"reconcile", &e]));
+                    process::exit(2);
+                }
+            }
+            return;
+        }
+        // `backup [dir] [--compress]` archives the persisted ledger, see `backup`
+        Some("backup") => {
+            if let Err(e) = backup(&args, &config, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"backup", &e]));
+                process::exit(1);
+            }
+            return;
+        }
+        // `restore <archive> [--yes]` replaces the persisted ledger with a backup
+        Some("restore") => {
+            if let Err(e) = restore(&args, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"restore", &e]));
+                process::exit(1);
+            }
+            return;
+        }
+        // `snapshot --out <file>` saves the state of the persisted ledger with its hash
+        Some("snapshot") => {
+            if let Err(e) = snapshot(&args, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"snapshot", &e]));
+            }
+            return;
+        }
+        // `verify --snapshot <file>` checks that replaying the persisted log reproduces a snapshot
+        Some("verify") => {
+            match verify(&args, &rules) {
+                Ok(None) => println!("{}", tr(Key::Ok, &[])),
+                Ok(Some(divergence)) => {
+                    eprintln!("{}", tr(Key::Diverged, &[&divergence]));
+                    process::exit(1);
+                }
+                Err(e) => {
+                    eprintln!("{}", tr(Key::CommandFailed, &[&"verify", &e]));
+                    process::exit(2);
+                }
+            }
+            return;
+        }
+        // `diff <left> <right>` compares the balances of two tx logs, WALs or snapshots
+        Some("diff") => {
+            match diff(&args, &rules) {
+                Ok(diff) if diff.is_empty() => {}
+                Ok(diff) => {
+                    print!("{}", diff);
+                    process::exit(1);
+                }
+                Err(e) => {
+                    eprintln!("{}", tr(Key::CommandFailed, &[&"diff", &e]));
+                    process::exit(2);
+                }
+            }
+            return;
+        }
+        // `balance --account <name> [--as-of <date>]` prints a current or historical balance
+        Some("balance") => {
+            if let Err(e) = balance(&args) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"balance", &e]));
+            }
+            return;
+        }
+        // `recipients <account> [allow | disallow | block | unblock <recipient>]` shows or
+        // changes whom an account of the persisted ledger may send to
+        Some("recipients") => {
+            if let Err(e) = recipients(&args, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"recipients", &e]));
+            }
+            return;
+        }
+        // `send-multi (chain <amount> <account> <account>... | split <from> <to> <amount>...)`
+        // sends along several legs of the persisted ledger at once, all of them or none
+        Some("send-multi") => {
+            if let Err(e) = send_multi(&args, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"send-multi", &e]));
+            }
+            return;
+        }
+        // `net <file>` settles the obligations in a JSON file on the persisted ledger by their
+        // net positions
+        Some("net") => {
+            if let Err(e) = net(&args, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"net", &e]));
+            }
+            return;
+        }
+        // `mandates (grant <payer> <payee> <max>/<period> | revoke <payer> <payee> | collect
+        // <payee> <payer> <amount> | list)` manages the direct-debit mandates of the persisted
+        // ledger and collects under them
+        Some("mandates") => {
+            if let Err(e) = mandates(&args, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"mandates", &e]));
+            }
+            return;
+        }
+        // `interest [--days <n>]` accrues interest on the persisted ledger by the
+        // `interest.<kind>` schedules of the `account_kind.<account>` settings
+        Some("interest") => {
+            if let Err(e) = accrue_interest(&args, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"interest", &e]));
+            }
+            return;
+        }
+        // `orders (add <from> <to> <amount> <day> | list | skip <id> | cancel <id> | run)` manages
+        // the standing orders of the persisted ledger
+        Some("orders") => {
+            if let Err(e) = orders(&args, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"orders", &e]));
+            }
+            return;
+        }
+        // `rates (import <file> | list | convert <amount> <from> <to> | value <account>
+        // <currency>)` manages the exchange rates of the persisted ledger and converts with them
+        Some("rates") => {
+            if let Err(e) = rates(&args, &config, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"rates", &e]));
+            }
+            return;
+        }
+        // `goals <account> [set <name> <target> [--by <date>] | remove <name>]` shows or
+        // changes the savings goals of an account of the persisted ledger
+        Some("goals") => {
+            if let Err(e) = goals(&args, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"goals", &e]));
+            }
+            return;
+        }
+        // `owners <account> [add | remove <owner>]` shows or changes who may operate an account
+        // of the persisted ledger
+        Some("owners") => {
+            if let Err(e) = owners(&args, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"owners", &e]));
+            }
+            return;
+        }
+        // `archive <account>... | --inactive-days <n> | list | restore <account>` moves accounts
+        // of the persisted ledger out of use and back
+        Some("archive") => {
+            if let Err(e) = archive(&args, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"archive", &e]));
+            }
+            return;
+        }
+        // `dispute open <position> | uphold <position> | deny <position> | list` manages the
+        // disputed deposits of the persisted ledger
+        Some("dispute") => {
+            if let Err(e) = dispute(&args, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"dispute", &e]));
+            }
+            return;
+        }
+        // `escrow hold <payer> <payee> <amount> | release <id> | refund <id> | list` manages
+        // the escrows of the persisted ledger
+        Some("escrow") => {
+            if let Err(e) = escrow(&args, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"escrow", &e]));
+            }
+            return;
+        }
+        // `history [<account>] [--min <amount>] [--max <amount>] [--from <date>] [--to <date>]
+        // [--offset <n>] [--limit <n>] [--follow]` lists matching transactions
+        Some("history") => {
+            if let Err(e) = history(&args) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"history", &e]));
+            }
+            return;
+        }
+        // `accounts [--sort name|balance|activity] [--desc] [--offset <n>] [--limit <n>]
+        // [--prefix <account>] [--match <regex>]` lists the accounts of the `--tx-log`/`--wal`
+        // ledger
+        Some("accounts") => {
+            if let Err(e) = list_accounts(&args, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"accounts", &e]));
+            }
+            return;
+        }
+        // `stats` sums up the `--tx-log`/`--wal` ledger
+        Some("stats") => {
+            if let Err(e) = print_stats(&args, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"stats", &e]));
+            }
+            return;
+        }
+        // `watch [<account>...]` keeps showing the balances of the accounts, all if none are
+        // given, of the `--remote` server or the `--tx-log`/`--wal` ledger
+        Some("watch") => {
+            if let Err(e) = watch(&args, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"watch", &e]));
+            }
+            return;
+        }
+        // `state --at <n>` prints the balances after the first `n` transactions
+        Some("state") => {
+            if let Err(e) = state(&args, &rules) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"state", &e]));
+            }
+            return;
+        }
+        // `check` validates the internal consistency of the persisted ledger
+        Some("check") => {
+            let result = open_tx_log(&args, &rules).map(|(ledger, _)| ledger.check_invariants());
+            match result {
+                Ok(Ok(())) => println!("{}", tr(Key::Ok, &[])),
+                Ok(Err(violations)) => {
+                    violations
+                        .iter()
+                        .for_each(|v| eprintln!("{}", tr(Key::Violated, &[v])));
+                    process::exit(1);
+                }
+                Err(e) => {
+                    eprintln!("{}", tr(Key::CommandFailed, &[&"check", &e]));
+                    hint_backup(&args, &config, &rules.ledger(), &*e);
+                    process::exit(2);
+                }
+            }
+            return;
+        }
+        // `statement --account <name> --html <file>` renders an account statement
+        Some("statement") => {
+            if let Err(e) = statement(&args) {
+                eprintln!("{}", tr(Key::CommandFailed, &[&"statement", &e]));
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    // `low_balance.<account> = <n>` and `high_balance.<account> = <n>` in the config file
+    let thresholds = match thresholds(&config) {
+        Ok(thresholds) => Rc::new(thresholds),
+        Err(e) => {
+            eprintln!("{}", tr(Key::ConfigUnreadable, &[&e]));
+            return;
+        }
+    };
+
+    // What the persisted logs replay into outside the session, e.g. to snapshot them
+    let empty = rules.ledger();
+    // Creates the basic ledger (or connects to a remote one) and a tx log container
+    let mut session = match (flag_value(&args, "--remote"), ledger_name) {
+        (Some(url), _) => Session::Single(Box::new(RemoteLedger::new(url)), None),
+        (None, Some(name)) if !dry_run(&args) => {
+            let mut manager = manager;
+            manager.on_create(move |accounts| rules.apply(accounts));
+            manager.on_open(move |accounts| watch_thresholds(accounts, &thresholds));
+            if let Err(e) = manager.select(&name) {
+                eprintln!("{}", tr(Key::LedgerUnreadable, &[&name, &e]));
+                hint_backup(&args, &config, &empty, &e);
+                return;
+            }
+            Session::Managed(manager)
+        }
+        (None, _) => match open_tx_log(&args, &rules) {
+            Ok((mut accounts, _)) if dry_run(&args) => {
+                watch_thresholds(&mut accounts, &thresholds);
+                Session::DryRun(Box::new(accounts))
+            }
+            Ok((mut accounts, persist)) => {
+                watch_thresholds(&mut accounts, &thresholds);
+                Session::Single(Box::new(accounts), persist)
+            }
+            Err(e) => {
+                eprintln!("{}", tr(Key::LogUnreadable, &[&e]));
+                hint_backup(&args, &config, &empty, &*e);
+                return;
+            }
+        },
+    };
+    let metrics = Metrics::new();
+
+    // The logs the session persists to, and without one the transactions only kept in memory
+    let logs: Arc<Mutex<Vec<String>>> = Default::default();
+    let unsaved: Arc<Mutex<Vec<Tx>>> = Default::default();
+    let in_memory =
+        matches!(session, Session::Single(_, None)) && flag_value(&args, "--remote").is_none();
+    if let Some((path, wal)) = log_path(&args).filter(|_| !dry_run(&args)) {
+        logs.lock().unwrap().push(path.to_string());
+        snapshot_on_shutdown(&shutdown, path.to_string(), wal, empty.fork());
+    }
+    // The interactive loop blocks on stdin, so a signal completes the shutdown right away and
+    // tells where everything went, saving what was only in memory
+    let report = {
+        let (logs, unsaved) = (logs.clone(), unsaved.clone());
+        move || report_saved(&logs.lock().unwrap(), &unsaved.lock().unwrap())
+    };
+    exit_on_signals(shutdown.clone(), OnSignal::Exit(Box::new(report)));
+
+    // Organization specific commands are added by registering more plugins here
+    let mut plugins = PluginRegistry::new();
+    plugins
+        .register(Box::new(BalancePlugin))
+        .expect("bundled plugins don't conflict");
+
+    // Committed transactions are forwarded to the webhooks, if any are configured
+    let mut webhooks = webhook_config(&args).map(|config| {
+        let (sender, receiver) = mpsc::channel();
+        (sender, webhooks::spawn(config, receiver))
+    });
+
+    // Only commands typed on a terminal are saved to the history file, not piped ones
+    let history_path = PromptHistory::default_path().filter(|_| io::stdin().is_terminal());
+    let history = match PromptHistory::load(history_path, &config) {
+        Ok(history) => history,
+        Err(e) => {
+            eprintln!("{}", tr(Key::PromptHistoryUnreadable, &[&e]));
+            return;
+        }
+    };
+    let mut prompt = match (Aliases::from_config(&config), page_size(&config)) {
+        (Ok(aliases), Ok(page_size)) => Prompt {
+            history,
+            aliases,
+            page_size,
+        },
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", tr(Key::ConfigUnreadable, &[&e]));
+            return;
+        }
+    };
 
     loop {
-        match handle_input(&mut ledger) {
+        ANSWERS.take();
+        let input = match &mut session {
+            Session::DryRun(ledger) => dry_run_input(ledger, &plugins, &mut prompt),
+            session => handle_input(session.ledger(), &plugins, &mut prompt),
+        };
+        if let Err(e) = prompt.history.push(ANSWERS.take()) {
+            warn!(error = %e, "couldn't save the prompt history");
+        }
+        match input {
             Ok(InputResult::Confirmed(mut tx)) => {
-                tx_log.append(&mut tx);
+                let persisted = match shutdown.begin_write() {
+                    Some(_write) => session.persist(&tx),
+                    None => Err(io::Error::other("shutting down")),
+                };
+                if let Err(e) = persisted {
+                    error!(error = %e, "couldn't persist transactions");
+                }
+                // A worker that died takes no more deliveries, but the session goes on
+                if let Some((sender, _)) = &webhooks {
+                    if tx.iter().any(|tx| sender.send(tx.clone()).is_err()) {
+                        error!("the webhook worker stopped, dropping webhook deliveries");
+                        webhooks = None;
+                    }
+                }
+                info!(count = tx.len(), "transactions committed");
+                tx.iter().for_each(|tx| metrics.record_tx(tx));
+                if in_memory {
+                    unsaved.lock().unwrap().append(&mut tx);
+                }
                 continue;
             }
+            Ok(InputResult::Metrics) => print!("{}", metrics.render()),
+            Ok(InputResult::Use(name)) => match &mut session {
+                Session::Managed(manager) => {
+                    // Whoever is logged in stays logged in on the other ledger
+                    let user = manager
+                        .ledger()
+                        .and_then(|l| l.principal().map(str::to_string));
+                    match manager.select(&name) {
+                        Ok(()) => {
+                            if let Some(ledger) = manager.ledger() {
+                                ledger.set_principal(user);
+                            }
+                            if let Ok(path) = manager.log_path(&name) {
+                                let path = path.display().to_string();
+                                let mut logs = logs.lock().unwrap();
+                                if !logs.contains(&path) {
+                                    logs.push(path.clone());
+                                    snapshot_on_shutdown(&shutdown, path, false, empty.fork());
+                                }
+                            }
+                            println!("{}", tr(Key::UsingLedger, &[&name]))
+                        }
+                        Err(e) => println!("{}", tr(Key::EncounteredError, &[&e])),
+                    }
+                }
+                Session::Single(..) => println!(
+                    "{}",
+                    tr(Key::EncounteredError, &[&tr(Key::UseNeedsLedger, &[])])
+                ),
+                Session::DryRun(_) => println!(
+                    "{}",
+                    tr(Key::EncounteredError, &[&tr(Key::UseInDryRun, &[])])
+                ),
+            },
             Ok(InputResult::Quit) => break,
-            Err(e) => println!("encountered error: {}", e),
+            Err(e) => {
+                // The rest of a failed alias, or recalled command, isn't entered
+                QUEUED.take();
+                if let Some(e) = e.downcast_ref::<ApplicationError>() {
+                    metrics.record_error(e);
+                }
+                // The user always sees the error, the operational log may go elsewhere
+                println!("{}", tr(Key::EncounteredError, &[&e]));
+                debug!(error = %e, "command failed");
+            }
             _ => continue,
         }
     }
+
+    shutdown.complete();
+    // Let pending webhook deliveries finish
+    if let Some((sender, worker)) = webhooks {
+        drop(sender);
+        let _ = worker.join();
+    }
 }
 
-fn handle_input(ledger: &mut Accounts) -> Result<InputResult, Box<dyn std::error::Error>> {
-    let input =
-        read_from_stdin("Please choose [deposit, withdraw, send, print, quit] and  hit return:");
+fn handle_input(
+    ledger: &mut dyn LedgerApi,
+    plugins: &PluginRegistry,
+    prompt: &mut Prompt,
+) -> Result<InputResult, Box<dyn Error>> {
+    let mut commands = vec![
+        "deposit", "withdraw", "mint", "burn", "promo", "send", "approve",
+    ];
+    commands.push("print [<regex>]");
+    commands.push("metrics");
+    commands.extend(plugins.commands());
+    commands.extend([
+        "use <ledger>",
+        "login <user>",
+        "logout",
+        "whoami",
+        "history",
+    ]);
+    commands.extend(["!<n>", "alias [<name> = <command>; ...]", "quit"]);
+    let input = read_from_stdin(&tr(Key::Choose, &[&commands.join(", ")]));
+
+    let _span = info_span!("command", name = %input).entered();
 
     match input.as_str() {
         "deposit" => {
-            let account = read_from_stdin("Account:");
-            let amount: u64 = read_from_stdin("Amount").parse()?;
+            let account = read_from_stdin(&tr(Key::Account, &[]));
+            let amount: Units = read_amount()?;
             let tx = ledger.deposit(&account, amount)?;
             Ok(InputResult::Confirmed(vec![tx]))
         }
         "withdraw" => {
-            let account = read_from_stdin("Account:");
-            let amount: u64 = read_from_stdin("Amount").parse()?;
+            let account = read_from_stdin(&tr(Key::Account, &[]));
+            let amount: Units = read_amount()?;
             let tx = ledger.withdraw(&account, amount)?;
             Ok(InputResult::Confirmed(vec![tx]))
         }
+        "mint" => {
+            let account = read_from_stdin(&tr(Key::Account, &[]));
+            let amount: Units = read_amount()?;
+            let tx = ledger.mint(&account, amount)?;
+            Ok(InputResult::Confirmed(vec![tx]))
+        }
+        "burn" => {
+            let account = read_from_stdin(&tr(Key::Account, &[]));
+            let amount: Units = read_amount()?;
+            let tx = ledger.burn(&account, amount)?;
+            Ok(InputResult::Confirmed(vec![tx]))
+        }
+        // Promotional credit can be spent through the day it expires after
+        "promo" => {
+            let account = read_from_stdin(&tr(Key::Account, &[]));
+            let amount: Units = read_amount()?;
+            let date = read_from_stdin(&tr(Key::Expires, &[]));
+            let date = Date::parse_iso(&date).ok_or_else(|| tr(Key::InvalidDate, &[&date]))?;
+            let tx = ledger.grant_promo(&account, amount, Timestamp::end_of(date))?;
+            Ok(InputResult::Confirmed(vec![tx]))
+        }
         "send" => {
-            let sender = read_from_stdin("Sender:");
-            let amount: u64 = read_from_stdin("Amount").parse().unwrap();
-            let receiver = read_from_stdin("Receiver");
-            let (tx1, tx2) = ledger.send(&sender, &receiver, amount)?;
-            Ok(InputResult::Confirmed(vec![tx1, tx2]))
-        }
-        "print" => {
-            println!("ledger: {:?}", ledger);
+            let sender = read_from_stdin(&tr(Key::Sender, &[]));
+            let amount: Units = read_amount()?;
+            let receiver = read_from_stdin(&tr(Key::Receiver, &[]));
+            let txs = match ledger.pay(&sender, &receiver, amount) {
+                Err(e) if needs_confirmation(&*e) => {
+                    println!("{}", e);
+                    if !is_yes(&read_from_stdin(&tr(Key::Confirm, &[]))) {
+                        return Ok(InputResult::Confirmed(vec![]));
+                    }
+                    ledger.pay_confirmed(&sender, &receiver, amount)?
+                }
+                result => result?,
+            };
+            Ok(InputResult::Confirmed(txs))
+        }
+        "approve" => {
+            let id: u64 = read_from_stdin(&tr(Key::Transfer, &[])).parse()?;
+            // Approvals are made as the logged-in user, see `login`
+            Ok(InputResult::Confirmed(ledger.approve(id)?))
+        }
+        // `print <regex>` only prints the accounts whose names match, see `AccountFilter`
+        command if command == "print" || command.starts_with("print ") => {
+            let filter = match command["print".len()..].trim() {
+                "" => AccountFilter::default(),
+                pattern => AccountFilter::default().regex(pattern)?,
+            };
+            println!("{}", tr(Key::Ledger, &[]));
+            let mut goals = ledger.goals()?;
+            let accounts = ledger.accounts()?.into_iter();
+            let accounts = accounts.filter(|(account, _)| filter.matches(account));
+            let format = |units: Units| amount::format_current(units as i128);
+            let lines = accounts.flat_map(|(account, balance)| {
+                let goals = goals.remove(&account).unwrap_or_default();
+                let progress = goals.into_iter().map(move |(name, goal)| {
+                    let progress = goal.progress(balance as i128);
+                    let (saved, target) = (format(progress.saved), format(progress.target));
+                    let percent = progress.percent();
+                    let line = match goal.deadline {
+                        Some(by) => tr(
+                            Key::GoalProgressBy,
+                            &[&name, &saved, &target, &percent, &by.iso()],
+                        ),
+                        None => tr(Key::GoalProgress, &[&name, &saved, &target, &percent]),
+                    };
+                    format!("    {}", line)
+                });
+                std::iter::once(format!("  {}: {}", account, format(balance))).chain(progress)
+            });
+            page(lines, prompt.page_size);
+            Ok(InputResult::Print)
+        }
+        "metrics" => Ok(InputResult::Metrics),
+        "history" => {
+            let lines = prompt.history.entries().enumerate();
+            let lines = lines.map(|(i, entry)| format!("{:5}  {}", i + 1, entry.join(" ")));
+            page(lines, prompt.page_size);
+            Ok(InputResult::Print)
+        }
+        // `!<n>` enters the `n`th command of the history again, answering its prompts the same
+        command if command.starts_with('!') => {
+            let entry = command[1..]
+                .parse()
+                .ok()
+                .and_then(|n| prompt.history.get(n))
+                .ok_or_else(|| tr(Key::NoSuchCommand, &[&&command[1..]]))?;
+            ANSWERS.take();
+            let answers = entry.iter().map(|answer| (answer.clone(), true));
+            QUEUED.with_borrow_mut(|queued| queued.extend(answers));
+            handle_input(ledger, plugins, prompt)
+        }
+        "alias" => {
+            for (name, commands) in prompt.aliases.iter() {
+                println!("alias {} = {}", name, commands);
+            }
+            Ok(InputResult::Print)
+        }
+        // `alias <name> = <command>; ...` defines an alias for the session, see `Aliases`, and
+        // `alias <name> =` removes it
+        command if command.starts_with("alias ") => {
+            let Some((name, definition)) = command["alias ".len()..].split_once('=') else {
+                return Err(tr(Key::AliasUsage, &[]).into());
+            };
+            let name = name.trim();
+            if commands.iter().any(|c| c.split(' ').next() == Some(name)) {
+                return Err(tr(Key::AliasHidesCommand, &[&name]).into());
+            }
+            prompt.aliases.define(name, definition)?;
             Ok(InputResult::Print)
         }
         "quit" => Ok(InputResult::Quit),
-        _ => {
-            println!("command not supported");
-            Ok(InputResult::NotSupported)
+        command if command.starts_with("use ") => {
+            Ok(InputResult::Use(command["use ".len()..].trim().to_string()))
+        }
+        command if command.starts_with("login ") => {
+            let user = command["login ".len()..].trim();
+            ledger.login(Some(user))?;
+            println!("{}", tr(Key::LoggedIn, &[&user]));
+            Ok(InputResult::Print)
+        }
+        "logout" => {
+            ledger.login(None)?;
+            println!("{}", tr(Key::LoggedOut, &[]));
+            Ok(InputResult::Print)
+        }
+        "whoami" => {
+            match ledger.whoami() {
+                Some(user) => println!("{}", user),
+                None => println!("{}", tr(Key::LoggedOut, &[])),
+            }
+            Ok(InputResult::Print)
+        }
+        command => match plugins.find(command) {
+            Some(plugin) => Ok(InputResult::Confirmed(plugin.run(
+                command,
+                ledger,
+                &mut |label| read_from_stdin(label),
+            )?)),
+            None => match prompt.aliases.expand(command) {
+                // The alias is what the history remembers, not what it stands for
+                Some(expanded) => {
+                    let answers = expanded?.into_iter().flat_map(|words| match words[0] {
+                        "use" | "login" => vec![words.join(" ")],
+                        _ => words.into_iter().map(str::to_string).collect(),
+                    });
+                    let answers: Vec<_> = answers.map(|answer| (answer, false)).collect();
+                    QUEUED.with_borrow_mut(|queued| queued.extend(answers));
+                    handle_input(ledger, plugins, prompt)
+                }
+                None => {
+                    println!("{}", tr(Key::NotSupported, &[]));
+                    Ok(InputResult::NotSupported)
+                }
+            },
+        },
+    }
+}
+
+/// Runs the command read by [`handle_input`] on a fork of `ledger` and prints what it would
+/// commit instead of committing it, see [`dryrun::run`]. Only logging in and out carries over
+/// to `ledger`.
+fn dry_run_input(
+    ledger: &mut Accounts,
+    plugins: &PluginRegistry,
+    prompt: &mut Prompt,
+) -> Result<InputResult, Box<dyn Error>> {
+    let ((result, principal), report) = dryrun::run(ledger, |fork| {
+        let result = handle_input(fork, plugins, prompt)?;
+        Ok::<_, Box<dyn Error>>((result, fork.principal().map(str::to_string)))
+    })?;
+    ledger.set_principal(principal);
+    match result {
+        InputResult::Confirmed(txs) => {
+            if !txs.is_empty() {
+                print!("{}", report);
+            }
+            Ok(InputResult::Print)
+        }
+        result => Ok(result),
+    }
+}
+
+/// Installs the log subscriber configured by `--log-level <level>`, `--log-file <path>` and `--log-json`.
+/// `--verbose` and `--quiet` are shorthands for the `debug` and `error` levels.
+fn init_logging(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut config = LogConfig {
+        file: flag_value(args, "--log-file").map(PathBuf::from),
+        json: args.iter().any(|arg| arg == "--log-json"),
+        ..Default::default()
+    };
+    if let Some(level) = flag_value(args, "--log-level") {
+        config.level = level.parse()?;
+    } else if args.iter().any(|arg| arg == "--verbose") {
+        config.level = Level::DEBUG;
+    } else if args.iter().any(|arg| arg == "--quiet") {
+        config.level = Level::ERROR;
+    }
+    Ok(config.init()?)
+}
+
+/// The per-account rules from the config file that new ledgers start out with
+#[derive(Default)]
+struct LedgerRules {
+    /// `credit_limit.<account> = <n>`
+    credit_limits: BTreeMap<String, Units>,
+    /// `multisig.<account> = <m> of <signers> above <amount>`
+    multisig: BTreeMap<String, MultisigPolicy>,
+    /// `spending_limit.<sender>.<recipient> = <max>/<hour|day|week>`
+    spending_limits: BTreeMap<(String, String), SpendingLimit>,
+    /// `anomaly.velocity = <sends>/<second|minute|hour>`, `anomaly.drain = <percent>%` and
+    /// `anomaly.action = warn|confirm|block`
+    anomalies: AnomalyPolicy,
+    /// `admins = <user>,...`, who may mint and burn, see [`Accounts::set_admins`]
+    admins: Vec<String>,
+    /// `rounding = <policy>` and `rounding.remainder = <account>`, see [`Rounding`]
+    rounding: Rounding,
+    /// `fee.<operation> = <schedule>` and `fee_account = <account>`, see [`FeeSchedules`]
+    fees: FeeSchedules,
+    /// `interest.<kind> = <schedule>`, see [`InterestSchedules`]
+    interest: InterestSchedules,
+    /// `account_kind.<account> = <kind>`, see [`Accounts::set_account_kind`]
+    account_kinds: BTreeMap<String, String>,
+    /// `--as <name>` on the command line, see [`Accounts::set_principal`]
+    principal: Option<String>,
+    /// `--viewer` on the command line, see [`Accounts::set_read_only`]
+    viewer: bool,
+}
+
+impl LedgerRules {
+    fn from_config(config: &Config) -> Result<Self, String> {
+        fn parse<T: FromStr>(config: &Config, prefix: &str) -> Result<BTreeMap<String, T>, String>
+        where
+            T::Err: Display,
+        {
+            config
+                .with_prefix(prefix)
+                .map(|(account, value)| match value.parse() {
+                    Ok(value) => Ok((account.to_string(), value)),
+                    Err(e) => Err(format!("invalid {}{} {:?}: {}", prefix, account, value, e)),
+                })
+                .collect()
+        }
+        let spending_limits = parse(config, "spending_limit.")?
+            .into_iter()
+            .map(|(pair, limit)| match pair.split_once('.') {
+                Some((sender, recipient)) => {
+                    Ok(((sender.to_string(), recipient.to_string()), limit))
+                }
+                None => Err(format!(
+                    "expected spending_limit.<sender>.<recipient>, got spending_limit.{}",
+                    pair
+                )),
+            })
+            .collect::<Result<_, String>>()?;
+        let anomaly = parse::<String>(config, "anomaly.")?;
+        let setting = |key: &str| anomaly.get(key).map(String::as_str);
+        let anomalies = AnomalyPolicy {
+            velocity: setting("velocity")
+                .map(str::parse)
+                .transpose()
+                .map_err(|e| format!("anomaly.velocity: {}", e))?,
+            drain_percent: setting("drain")
+                .map(|drain| drain.trim_end_matches('%').trim().parse())
+                .transpose()
+                .map_err(|_| {
+                    format!(
+                        "invalid anomaly.drain {:?}",
+                        setting("drain").unwrap_or_default()
+                    )
+                })?,
+            action: setting("action")
+                .map_or(Ok(Action::Warn), str::parse)
+                .map_err(|e| format!("anomaly.action: {}", e))?,
+        };
+        Ok(LedgerRules {
+            credit_limits: parse(config, "credit_limit.")?,
+            multisig: parse(config, "multisig.")?,
+            spending_limits,
+            anomalies,
+            admins: config
+                .get("admins")
+                .into_iter()
+                .flat_map(|admins| admins.split(','))
+                .map(|admin| admin.trim().to_string())
+                .filter(|admin| !admin.is_empty())
+                .collect(),
+            rounding: Rounding::from_config(config)?,
+            fees: FeeSchedules::from_config(config)?,
+            interest: InterestSchedules::from_config(config)?,
+            account_kinds: parse(config, "account_kind.")?,
+            principal: None,
+            viewer: false,
+        })
+    }
+
+    fn apply(&self, accounts: &mut Accounts) {
+        for (account, limit) in &self.credit_limits {
+            accounts.set_credit_limit(account, *limit);
+        }
+        for (account, policy) in &self.multisig {
+            accounts.set_multisig(account, policy.clone());
+        }
+        for ((sender, recipient), limit) in &self.spending_limits {
+            accounts.set_spending_limit(sender, recipient, *limit);
+        }
+        accounts.set_anomaly_policy(self.anomalies);
+        accounts.set_admins(self.admins.iter().cloned());
+        accounts.set_rounding(self.rounding.clone());
+        accounts.set_fees(self.fees.clone());
+        accounts.set_interest(self.interest.clone());
+        for (account, kind) in &self.account_kinds {
+            accounts.set_account_kind(account, kind);
+        }
+        accounts.set_principal(self.principal.clone());
+        accounts.set_read_only(self.viewer);
+    }
+
+    /// An empty ledger following these rules
+    fn ledger(&self) -> Accounts {
+        let mut accounts = Accounts::new();
+        self.apply(&mut accounts);
+        accounts
+    }
+}
+
+/// The balance limits configured as `low_balance.<account>` and `high_balance.<account>`
+fn thresholds(config: &Config) -> Result<BTreeMap<String, Thresholds>, String> {
+    let mut thresholds: BTreeMap<String, Thresholds> = BTreeMap::new();
+    for (prefix, high) in [("low_balance.", false), ("high_balance.", true)] {
+        for (account, value) in config.with_prefix(prefix) {
+            let value = value
+                .parse()
+                .map_err(|_| format!("invalid {}{} {:?}", prefix, account, value))?;
+            let limits = thresholds.entry(account.to_string()).or_default();
+            if high {
+                limits.high = Some(value);
+            } else {
+                limits.low = Some(value);
+            }
+        }
+    }
+    Ok(thresholds)
+}
+
+/// Applies `thresholds` to `accounts` and warns on stdout whenever one is crossed, or a send
+/// looks suspicious but goes through anyway. Also tells when a savings goal is reached.
+fn watch_thresholds(accounts: &mut Accounts, thresholds: &BTreeMap<String, Thresholds>) {
+    for (account, limits) in thresholds {
+        accounts.set_thresholds(account, *limits);
+    }
+    accounts.subscribe(|event| match event {
+        LedgerEvent::ThresholdCrossed {
+            account,
+            threshold,
+            balance,
+        } => {
+            let (key, limit) = match threshold {
+                Threshold::Low(limit) => (Key::LowBalance, limit),
+                Threshold::High(limit) => (Key::HighBalance, limit),
+            };
+            println!("{}", tr(key, &[account, limit, balance]));
+        }
+        LedgerEvent::AnomalyDetected {
+            anomaly,
+            action: Action::Warn,
+        } => println!("{}", tr(Key::AnomalyWarning, &[anomaly])),
+        LedgerEvent::GoalReached {
+            account,
+            goal,
+            target,
+        } => {
+            let target = amount::format_current(*target as i128);
+            println!("{}", tr(Key::GoalReached, &[account, goal, &target]));
+        }
+        _ => {}
+    });
+}
+
+/// Appends committed transactions to the persisted log, along with who committed them if the
+/// log records that
+type Persist = Box<dyn Fn(&[Tx], Option<&str>) -> io::Result<()> + Send + Sync>;
+
+/// The `--wal <path>` or else `--tx-log <path>` the ledger is persisted to, and whether it's a WAL
+fn log_path(args: &[String]) -> Option<(&str, bool)> {
+    match flag_value(args, "--wal") {
+        Some(path) => Some((path, true)),
+        None => flag_value(args, "--tx-log").map(|path| (path, false)),
+    }
+}
+
+/// The ledger of the server modes and the history it was replayed from: like [`open_tx_log`],
+/// persisting what each operation commits in one append as long as `shutdown` allows writes,
+/// and snapshotting the log when it completes.
+///
+/// The servers answer each request on a blocking thread without an executor to await a
+/// [`storage::TxStore`] on, so rather than the `_async` methods of [`SharedAccounts`] they
+/// persist through its journal, which appends within the same writer turn.
+fn serve_ledger(
+    args: &[String],
+    rules: &LedgerRules,
+    shutdown: &Arc<Shutdown>,
+) -> Result<(SharedAccounts, TxLog), Box<dyn Error>> {
+    let (accounts, persist) = open_tx_log(args, rules)?;
+    let history = match log_path(args) {
+        Some((path, _)) if fs::exists(path)? => read_tx_log(args)?.into_iter().collect(),
+        _ => TxLog::default(),
+    };
+    let Some(persist) = persist else {
+        return Ok((SharedAccounts::new(accounts), history));
+    };
+    let writes = shutdown.clone();
+    let ledger = SharedAccounts::journaled(accounts, move |txs| {
+        let persisted = match writes.begin_write() {
+            Some(_write) => persist(txs, None),
+            None => Err(io::Error::other("shutting down")),
+        };
+        if let Err(e) = persisted {
+            error!(error = %e, count = txs.len(), "couldn't persist transactions");
+        }
+    });
+    if let Some((path, wal)) = log_path(args) {
+        snapshot_on_shutdown(shutdown, path.to_string(), wal, rules.ledger());
+    }
+    Ok((ledger, history))
+}
+
+/// Saves a [`Snapshot`] of the log at `path` next to it once `shutdown` completes, see
+/// [`snapshot::path_for`], replaying it into the empty `ledger`
+fn snapshot_on_shutdown(shutdown: &Shutdown, path: String, wal: bool, mut ledger: Accounts) {
+    shutdown.on_shutdown(format!("snapshot of {}", path), move || {
+        if !fs::exists(&path)? {
+            return Ok(());
+        }
+        let entries = if wal {
+            MmapWal::open(&path)?.replay(&mut ledger)?
+        } else {
+            storage::replay(&mut ledger, LogReader::open(&path)?)?
+        };
+        Snapshot::of(&ledger, entries).save(snapshot::path_for(&path))
+    });
+}
+
+/// What [`exit_on_signals`] does
+enum OnSignal {
+    /// Requests the shutdown from whoever waits for it, like the server loop
+    Request,
+    /// Completes the shutdown, runs the closure and exits
+    Exit(Box<dyn Fn() + Send>),
+}
+
+/// Handles SIGINT and SIGTERM on a thread of its own
+fn exit_on_signals(shutdown: Arc<Shutdown>, on_signal: OnSignal) {
+    let mut signals = match Signals::new([SIGINT, SIGTERM]) {
+        Ok(signals) => signals,
+        Err(e) => {
+            error!(error = %e, "couldn't handle signals");
+            return;
+        }
+    };
+    thread::spawn(move || {
+        for signal in signals.forever() {
+            info!(signal, "shutting down");
+            match &on_signal {
+                OnSignal::Request => shutdown.request(),
+                OnSignal::Exit(then) => {
+                    shutdown.complete();
+                    then();
+                    process::exit(128 + signal);
+                }
+            }
+        }
+    });
+}
+
+/// Tells the interrupted user where the ledger is: in `logs`, or for a session without any, in
+/// a new log in the working directory that `unsaved` is saved to
+fn report_saved(logs: &[String], unsaved: &[Tx]) {
+    if !logs.is_empty() {
+        println!("{}", tr(Key::SavedIn, &[&logs.join(", ")]));
+        return;
+    }
+    if unsaved.is_empty() {
+        return;
+    }
+    let path = format!("crabbux-interrupted-{}.jsonl", SystemClock.now().0);
+    match FileStore::open(&path).and_then(|store| store.write(unsaved)) {
+        Ok(()) => println!("{}", tr(Key::SavedTo, &[&unsaved.len(), &path])),
+        Err(e) => error!(error = %e, path, "couldn't save transactions"),
+    }
+}
+
+/// Replays the log given by `--tx-log <path>` (JSON lines) or `--wal <path>` (binary), if any,
+/// into a fresh ledger following `rules` and opens it for appending. The account metadata is
+/// loaded from `<log>.meta` afterwards, archiving the accounts it marks.
+///
+/// With `--dry-run` the log is only read, see [`load_tx_log`].
+fn open_tx_log(
+    args: &[String],
+    rules: &LedgerRules,
+) -> Result<(Accounts, Option<Persist>), Box<dyn Error>> {
+    load_tx_log(args, rules, dry_run(args))
+}
+
+/// Like [`open_tx_log`], but if `read_only` is set the log is only read: it isn't migrated or
+/// created, and persisting to it does nothing.
+fn load_tx_log(
+    args: &[String],
+    rules: &LedgerRules,
+    read_only: bool,
+) -> Result<(Accounts, Option<Persist>), Box<dyn Error>> {
+    let mut accounts = rules.ledger();
+    if let Some(path) = flag_value(args, "--wal") {
+        if !read_only {
+            migrate(path, true)?;
+        }
+        if fs::exists(path)? {
+            let applied = MmapWal::open(path)?.replay(&mut accounts)?;
+            info!(path, applied, "replayed WAL");
+        }
+        accounts.set_metadata(LedgerMetadata::load(metadata::path_for(path))?);
+        if read_only {
+            return Ok((accounts, Some(Box::new(|_, _| Ok(())))));
+        }
+        let wal = WalWriter::open(path)?;
+        // The binary WAL format has no room for actors
+        return Ok((accounts, Some(Box::new(move |txs, _| wal.write(txs)))));
+    }
+    let Some(path) = flag_value(args, "--tx-log") else {
+        return Ok((accounts, None));
+    };
+    if !read_only {
+        migrate(path, false)?;
+    }
+    if fs::exists(path)? {
+        let applied = storage::replay(&mut accounts, LogReader::open(path)?)?;
+        info!(path, applied, "replayed transaction log");
+    }
+    accounts.set_metadata(LedgerMetadata::load(metadata::path_for(path))?);
+    if read_only {
+        return Ok((accounts, Some(Box::new(|_, _| Ok(())))));
+    }
+    let store = FileStore::open(path)?;
+    Ok((
+        accounts,
+        Some(Box::new(move |txs, actor| store.write_as(txs, actor))),
+    ))
+}
+
+/// Upgrades the log at `path` to the current format before it's appended to, see
+/// [`migrations::migrate_log`]
+fn migrate(path: &str, wal: bool) -> io::Result<()> {
+    if let Some(from) = migrations::migrate_log(Path::new(path), wal)? {
+        let original = migrations::original_path(Path::new(path), from);
+        info!(path, from, original = %original.display(), "migrated log to the current format");
+    }
+    Ok(())
+}
+
+/// Imports the statement `args[1]`, see [`read_statement`], into `--account <name>` of the
+/// `--tx-log`/`--wal` ledger.
+///
+/// The keys of imported entries are kept next to the log in `<log>.imported`, so importing
+/// overlapping statements doesn't count entries twice.
+fn import(args: &[String], config: &Config, rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let (Some(path), Some(account)) = (args.get(1), flag_value(args, "--account")) else {
+        return Err("usage: crabbux import <file> --account <name> [--format qif|ofx|camt053|csv] (--tx-log <path> | --wal <path>)".into());
+    };
+    let entries = read_statement(path, args, config)?;
+
+    let (mut ledger, persist) = open_tx_log(args, rules)?;
+    let (Some(persist), Some(log)) = (
+        persist,
+        flag_value(args, "--wal").or(flag_value(args, "--tx-log")),
+    ) else {
+        return Err(tr(Key::NeedsLog, &[&"import"]).into());
+    };
+    let seen_path = format!("{}.imported", log);
+    let mut seen: HashSet<String> = match fs::read_to_string(&seen_path) {
+        Ok(keys) => keys.lines().map(str::to_string).collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => HashSet::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let before = seen.clone();
+
+    let recorder = record_dry_run(args, &mut ledger);
+    let summary = import::apply(&mut ledger, account, &entries, &mut seen)?;
+    if report_dry_run(recorder, &ledger) {
+        return Ok(());
+    }
+    persist(&summary.txs, ledger.principal())?;
+    let mut keys = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&seen_path)?;
+    for key in seen.difference(&before) {
+        writeln!(keys, "{}", key)?;
+    }
+    println!(
+        "{}",
+        tr(Key::Imported, &[&summary.txs.len(), &summary.skipped])
+    );
+    Ok(())
+}
+
+/// Simulates the batch of operations in the JSON file `args[1]` on the `--tx-log`/`--wal`
+/// ledger without changing it, see [`Accounts::simulate_batch`], e.g. to validate a payroll
+/// run before doing it. Prints what the batch would do up to the first failing operation, and
+/// returns whether none would fail.
+///
+/// The file holds an array of operations in the encoding of the `simulate` RPC method, e.g.
+/// `[{"Send": {"sender": "payroll", "recipient": "ALICE", "amount": 100}}]`.
+fn simulate(args: &[String], rules: &LedgerRules) -> Result<bool, Box<dyn Error>> {
+    let Some(path) = args.get(1).filter(|arg| !arg.starts_with("--")) else {
+        return Err("usage: crabbux simulate <file> (--tx-log <path> | --wal <path>)".into());
+    };
+    let ops: Vec<Op> = serde_json::from_slice(&fs::read(path)?)?;
+    let (ledger, _) = load_tx_log(args, rules, true)?;
+    let simulated = ledger.simulate_batch(&ops);
+    print!("{}", simulated.dry_run);
+    if let Some((index, error)) = &simulated.failure {
+        eprintln!("{}", tr(Key::WouldFail, &[&(index + 1), &ops.len(), error]));
+    }
+    Ok(simulated.succeeds())
+}
+
+/// Parses the statement at `path` in `--format <format>`: `qif`, the default, `ofx`, `camt053`
+/// or `csv`. Amounts are read with [`statement_decimals`].
+fn read_statement(
+    path: &str,
+    args: &[String],
+    config: &Config,
+) -> Result<Vec<StatementEntry>, Box<dyn Error>> {
+    let decimals = statement_decimals(args, config)?;
+    let input = fs::read_to_string(path)?;
+    Ok(match flag_value(args, "--format").unwrap_or("qif") {
+        "qif" => qif::parse(&input, decimals)?,
+        "ofx" | "qfx" => ofx::parse(&input, decimals)?,
+        "camt053" => camt::parse(&input, decimals)?,
+        "csv" => csv::parse(&input, decimals)?,
+        format => return Err(tr(Key::UnknownFormat, &[&format]).into()),
+    })
+}
+
+/// The decimal places of statement amounts: `--decimals <n>`, or else the ledger's `decimals`,
+/// or else 2 like most bank statements have
+fn statement_decimals(args: &[String], config: &Config) -> Result<u32, Box<dyn Error>> {
+    Ok(match flag_value(args, "--decimals") {
+        Some(decimals) => decimals.parse()?,
+        None => amount::decimals_of(config)?.unwrap_or(2),
+    })
+}
+
+/// Matches the statement `args[1]`, see [`read_statement`], against the entries of
+/// `--account <name>` in the `--tx-log`/`--wal` history, booked at most `--days <n>` (default 3)
+/// days apart, and prints what either side is missing. Amounts are shown with the statement's
+/// decimals.
+fn reconcile(args: &[String], config: &Config) -> Result<Reconciliation, Box<dyn Error>> {
+    let (Some(path), Some(account)) = (args.get(1), flag_value(args, "--account")) else {
+        return Err("usage: crabbux reconcile <file> --account <name> [--format qif|ofx|camt053|csv] [--days <n>] (--tx-log <path> | --wal <path>)".into());
+    };
+    let days = flag_value(args, "--days").map_or(Ok(3), str::parse)?;
+    let decimals = statement_decimals(args, config)?;
+    let entries = read_statement(path, args, config)?;
+    let log: TxLog = read_tx_log(args)?.into_iter().collect();
+    let result = reconcile::reconcile(&entries, &log, account, days);
+
+    let format = |amount: i128| amount::format(amount, decimals, NumberFormat::current());
+    println!("{}", tr(Key::Matched, &[&result.matched.len()]));
+    if !result.unmatched_statement.is_empty() {
+        println!("{}", tr(Key::OnlyOnStatement, &[]));
+    }
+    for entry in &result.unmatched_statement {
+        let id = entry
+            .id
+            .as_ref()
+            .map_or(String::new(), |id| format!(" ({})", id));
+        println!(
+            "  {} {} {}{}",
+            entry.date.iso(),
+            format(entry.amount),
+            entry.payee,
+            id
+        );
+    }
+    if !result.unmatched_ledger.is_empty() {
+        println!("{}", tr(Key::OnlyInLedger, &[]));
+    }
+    for &position in &result.unmatched_ledger {
+        let entry = &log.entries()[position];
+        println!(
+            "  #{} {} {} {}",
+            position,
+            entry.timestamp.date().iso(),
+            entry.tx.kind(),
+            format(entry.tx.amount() as i128)
+        );
+    }
+    Ok(result)
+}
+
+/// Writes the HTML statement of `--account <name>` to `--html <file>`, covering transactions
+/// `--from <n>` to `--to <n>` (1-based and inclusive, all by default) of the `--tx-log`/`--wal` history
+fn statement(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (Some(account), Some(path)) = (flag_value(args, "--account"), flag_value(args, "--html"))
+    else {
+        return Err("usage: crabbux statement --account <name> --html <file> [--from <n>] [--to <n>] (--tx-log <path> | --wal <path>)".into());
+    };
+    let txs = read_txs(args)?;
+    let from: usize = flag_value(args, "--from").map_or(Ok(1), str::parse)?;
+    let to: usize = flag_value(args, "--to").map_or(Ok(txs.len()), str::parse)?;
+    let mut out = io::BufWriter::new(fs::File::create(path)?);
+    html::write_statement(
+        &mut out,
+        &txs,
+        account,
+        from.saturating_sub(1)..to,
+        date::today(),
+        amount::decimals(),
+    )?;
+    Ok(out.flush()?)
+}
+
+/// Archives the `--tx-log`/`--wal` ledger into `args[1]`, or else the `backup.dir` setting, or
+/// else `backups` next to the log; gzip compressed with `--compress` or `backup.compress = true`.
+/// See [`backup::create`]. Then prunes the backups the `backup.keep` setting doesn't keep, if
+/// any, see [`backup::Retention`].
+fn backup(args: &[String], config: &Config, rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let Some((log, wal)) = log_path(args) else {
+        return Err(
+            "usage: crabbux backup [dir] [--compress] (--tx-log <path> | --wal <path>)".into(),
+        );
+    };
+    let log = Path::new(log);
+    let dir = match args.get(1).filter(|arg| !arg.starts_with("--")) {
+        Some(dir) => PathBuf::from(dir),
+        None => backup_dir(config, log),
+    };
+    let compress = args.iter().any(|arg| arg == "--compress") || backup_compressed(config);
+    let retention = config.get("backup.keep").map(str::parse).transpose()?;
+    let ledger = rules.ledger();
+    let (path, manifest) = backup::create(log, wal, ledger, &dir, compress, SystemClock.now())?;
+    println!(
+        "{}",
+        tr(Key::Entries, &[&manifest.entries, &path.display()])
+    );
+    if let Some(retention) = retention {
+        let name = log.file_name().unwrap_or_default().to_string_lossy();
+        for pruned in backup::prune(&dir, &name, &retention)? {
+            println!("{}", tr(Key::Pruned, &[&pruned.display()]));
         }
     }
+    Ok(())
+}
+
+/// The `backup.dir` setting, or else `backups` next to `log`
+fn backup_dir(config: &Config, log: &Path) -> PathBuf {
+    match config.get("backup.dir") {
+        Some(dir) => PathBuf::from(dir),
+        None => log.with_file_name("backups"),
+    }
+}
+
+/// Points at the last good backup of the `--tx-log`/`--wal` ledger if loading it failed with
+/// `error` because of damaged data, see [`checksum::is_corrupt`]. The backups are replayed into
+/// forks of the empty `ledger`.
+fn hint_backup(args: &[String], config: &Config, ledger: &Accounts, error: &(dyn Error + 'static)) {
+    let Some((log, wal)) = log_path(args).filter(|_| checksum::is_corrupt(error)) else {
+        return;
+    };
+    let flag = format!("{} {}", if wal { "--wal" } else { "--tx-log" }, log);
+    let log = Path::new(log);
+    let dir = backup_dir(config, log);
+    let name = log.file_name().unwrap_or_default().to_string_lossy();
+    match backup::last_good(&dir, &name, || ledger.fork()) {
+        Some(path) => eprintln!("{}", tr(Key::LastGoodBackup, &[&path.display(), &flag])),
+        None => eprintln!("{}", tr(Key::NoGoodBackup, &[&dir.display()])),
+    }
+}
+
+fn backup_compressed(config: &Config) -> bool {
+    config.get("backup.compress") == Some("true")
+}
+
+/// Backs up the `--tx-log`/`--wal` ledger every `backup.interval = <n><m|h|d>` until `shutdown`,
+/// like the `backup` command without a directory does
+fn schedule_backups(
+    args: &[String],
+    config: &Config,
+    rules: &LedgerRules,
+    shutdown: &Arc<Shutdown>,
+) -> Result<(), String> {
+    let (Some(every), Some((log, wal))) = (config.get("backup.interval"), log_path(args)) else {
+        return Ok(());
+    };
+    let log = PathBuf::from(log);
+    let schedule = backup::Schedule {
+        dir: backup_dir(config, &log),
+        log,
+        wal,
+        ledger: rules.ledger(),
+        compress: backup_compressed(config),
+        every: backup::parse_interval(every)?,
+        retention: config.get("backup.keep").map(str::parse).transpose()?,
+    };
+    backup::spawn_scheduler(schedule, shutdown.clone());
+    Ok(())
+}
+
+/// Writes off expired promotional credit of `ledger` every `promo.expire_every = <n><m|h|d>`,
+/// an hour by default, until `shutdown`
+fn schedule_promo_expiry(
+    config: &Config,
+    ledger: &SharedAccounts,
+    shutdown: &Arc<Shutdown>,
+) -> Result<(), String> {
+    let every = match config.get("promo.expire_every") {
+        Some(every) => {
+            backup::parse_interval(every).map_err(|e| format!("promo.expire_every: {}", e))?
+        }
+        None => Duration::from_secs(3600),
+    };
+    promo::spawn_expiry(ledger.clone(), every, shutdown.clone());
+    Ok(())
+}
+
+/// Runs the standing orders of the `--tx-log`/`--wal` ledger that are due every
+/// `orders.run_every = <n><m|h|d>`, an hour by default, until `shutdown`
+fn schedule_standing_orders(
+    args: &[String],
+    config: &Config,
+    ledger: &SharedAccounts,
+    shutdown: &Arc<Shutdown>,
+) -> Result<(), String> {
+    let every = match config.get("orders.run_every") {
+        Some(every) => {
+            backup::parse_interval(every).map_err(|e| format!("orders.run_every: {}", e))?
+        }
+        None => Duration::from_secs(3600),
+    };
+    if let Some((log, _)) = log_path(args) {
+        let path = standing::path_for(log);
+        standing::spawn_runner(ledger.clone(), path, every, shutdown.clone());
+    }
+    Ok(())
+}
+
+/// Replaces the `--tx-log`/`--wal` ledger with the backup archive `args[1]`, once it's been
+/// checked and replayed, see [`backup::Backup::verify`]. Asks first unless given `--yes`.
+fn restore(args: &[String], rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let (Some(archive), Some((log, wal))) = (args.get(1), log_path(args)) else {
+        return Err(
+            "usage: crabbux restore <archive> [--yes] (--tx-log <path> | --wal <path>)".into(),
+        );
+    };
+    let backup = backup::read(Path::new(archive))?;
+    backup.verify(rules.ledger())?;
+    let manifest = &backup.manifest;
+    if !args.iter().any(|arg| arg == "--yes") {
+        let question = tr(
+            Key::ConfirmRestore,
+            &[&log, &manifest.entries, &manifest.created],
+        );
+        if !is_yes(&read_from_stdin(&question)) {
+            return Ok(());
+        }
+    }
+    backup.restore(Path::new(log), wal)?;
+    println!("{}", tr(Key::Restored, &[&manifest.entries, &log]));
+    Ok(())
+}
+
+/// Replays the `--tx-log`/`--wal` history and saves the resulting state to `--out <file>`
+fn snapshot(args: &[String], rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let Some(path) = flag_value(args, "--out") else {
+        return Err("usage: crabbux snapshot --out <file> (--tx-log <path> | --wal <path>)".into());
+    };
+    let mut ledger = rules.ledger();
+    let entries = storage::replay(&mut ledger, read_tx_log(args)?.into_iter().map(Ok))?;
+    let snapshot = Snapshot::of(&ledger, entries);
+    snapshot.save(path)?;
+    println!("{}", tr(Key::Entries, &[&entries, &snapshot.hash]));
+    Ok(())
+}
+
+/// Replays the `--tx-log`/`--wal` history against an empty ledger and compares it with `--snapshot <file>`
+fn verify(args: &[String], rules: &LedgerRules) -> Result<Option<Divergence>, Box<dyn Error>> {
+    let Some(path) = flag_value(args, "--snapshot") else {
+        return Err(
+            "usage: crabbux verify --snapshot <file> (--tx-log <path> | --wal <path>)".into(),
+        );
+    };
+    let snapshot = Snapshot::load(path)?;
+    Ok(snapshot::verify(
+        &snapshot,
+        rules.ledger(),
+        read_tx_log(args)?.into_iter().map(Ok),
+    )?)
+}
+
+/// Prints the balance of `--account <name>` in the `--tx-log`/`--wal` history, as of the end of
+/// `--as-of <YYYY-MM-DD>` (UTC) or now
+fn balance(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let Some(account) = flag_value(args, "--account") else {
+        return Err("usage: crabbux balance --account <name> [--as-of <date>] (--tx-log <path> | --wal <path>)".into());
+    };
+    let at = match flag_value(args, "--as-of") {
+        Some(date) => {
+            Timestamp::end_of(Date::parse_iso(date).ok_or_else(|| tr(Key::InvalidDate, &[&date]))?)
+        }
+        None => SystemClock.now(),
+    };
+    let log: TxLog = read_tx_log(args)?.into_iter().collect();
+    let balance = log
+        .balance_at(account, at)
+        .ok_or_else(|| ApplicationError::NotFound(account.to_string()))?;
+    let balance = amount::format_current(balance as i128);
+    println!("{}", tr(Key::Balance, &[&account, &balance]));
+    Ok(())
+}
+
+/// Runs `escrow <subcommand>` on the `--tx-log`/`--wal` ledger and persists its transactions
+fn escrow(args: &[String], rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let usage = "usage: crabbux escrow (hold <payer> <payee> <amount> | release <id> | refund <id> | list) (--tx-log <path> | --wal <path>)";
+    let (mut ledger, persist) = open_tx_log(args, rules)?;
+    let recorder = record_dry_run(args, &mut ledger);
+    let operands: Vec<&str> = args[1..]
+        .iter()
+        .map(String::as_str)
+        .take_while(|arg| !arg.starts_with("--"))
+        .collect();
+    let txs = match operands.as_slice() {
+        ["list"] => {
+            for (escrow, amount) in ledger.escrows() {
+                let amount = amount::format_current(amount as i128);
+                println!(
+                    "#{} {} -> {}: {}",
+                    escrow.id, escrow.payer, escrow.payee, amount
+                );
+            }
+            return Ok(());
+        }
+        ["hold", payer, payee, amount] => {
+            let (escrow, (withdrawal, deposit)) =
+                ledger.hold_in_escrow(payer, payee, amount::parse_entered(amount)?)?;
+            println!("{}", tr(Key::EscrowOpened, &[&escrow.id]));
+            vec![withdrawal, deposit]
+        }
+        ["release", id] => {
+            let (withdrawal, deposit) = ledger.release_escrow(id.parse()?)?;
+            vec![withdrawal, deposit]
+        }
+        ["refund", id] => {
+            let (withdrawal, deposit) = ledger.refund_escrow(id.parse()?)?;
+            vec![withdrawal, deposit]
+        }
+        _ => return Err(usage.into()),
+    };
+    if report_dry_run(recorder, &ledger) {
+        return Ok(());
+    }
+    let persist = persist.ok_or_else(|| tr(Key::NeedsLog, &[&"escrow"]))?;
+    Ok(persist(&txs, ledger.principal())?)
+}
+
+/// Manages the disputes of the `--tx-log`/`--wal` ledger, identified by the position of the disputed
+/// deposit in the log as printed by `history`
+fn dispute(args: &[String], rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let usage = "usage: crabbux dispute (open <position> | uphold <position> | deny <position> | list) (--tx-log <path> | --wal <path>)";
+    let (mut ledger, persist) = open_tx_log(args, rules)?;
+    let recorder = record_dry_run(args, &mut ledger);
+    let operands: Vec<&str> = args[1..]
+        .iter()
+        .map(String::as_str)
+        .take_while(|arg| !arg.starts_with("--"))
+        .collect();
+    let txs = match operands.as_slice() {
+        ["list"] => {
+            for (dispute, amount) in ledger.disputes() {
+                let amount = amount::format_current(amount as i128);
+                println!("#{} {}: {}", dispute.position, dispute.account, amount);
+            }
+            return Ok(());
+        }
+        ["open", position] => {
+            let log: TxLog = read_tx_log(args)?.into_iter().collect();
+            let (_, (withdrawal, deposit)) = ledger.open_dispute(&log, position.parse()?)?;
+            vec![withdrawal, deposit]
+        }
+        ["uphold", position] => vec![ledger.uphold_dispute(position.parse()?)?],
+        ["deny", position] => {
+            let (withdrawal, deposit) = ledger.deny_dispute(position.parse()?)?;
+            vec![withdrawal, deposit]
+        }
+        _ => return Err(usage.into()),
+    };
+    if report_dry_run(recorder, &ledger) {
+        return Ok(());
+    }
+    let persist = persist.ok_or_else(|| tr(Key::NeedsLog, &[&"dispute"]))?;
+    Ok(persist(&txs, ledger.principal())?)
+}
+
+/// Shows the owners of account `args[1]` of the `--tx-log`/`--wal` ledger, or changes them with
+/// `add` or `remove <owner>` on behalf of the `--as` principal. The owners are kept in
+/// `<log>.meta`, and every change is appended to the audit trail in `<log>.audit`.
+fn owners(args: &[String], rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let usage = "usage: crabbux owners <account> [(add | remove) <owner>] [--as <name>] (--tx-log <path> | --wal <path>)";
+    let log = flag_value(args, "--wal")
+        .or(flag_value(args, "--tx-log"))
+        .ok_or(usage)?;
+    let (mut ledger, _) = open_tx_log(args, rules)?;
+    let operands: Vec<&str> = args[1..]
+        .iter()
+        .map(String::as_str)
+        .take_while(|arg| !arg.starts_with("--"))
+        .collect();
+    let recorder = record_dry_run(args, &mut ledger);
+    let (changes, changed) = mpsc::channel();
+    ledger.subscribe(move |event| {
+        if let LedgerEvent::OwnershipChanged { .. } = event {
+            let _ = changes.send(event.clone());
+        }
+    });
+    match operands.as_slice() {
+        [account] => {
+            let owners: Vec<&str> = ledger.owners(account).collect();
+            if owners.is_empty() {
+                println!("{}", tr(Key::NoOwners, &[]));
+            } else {
+                println!("{}", tr(Key::Owners, &[&owners.join(", ")]));
+            }
+            return Ok(());
+        }
+        [account, "add", owner] => ledger.add_owner(account, owner)?,
+        [account, "remove", owner] => ledger.remove_owner(account, owner)?,
+        _ => return Err(usage.into()),
+    }
+    if report_dry_run(recorder, &ledger) {
+        return Ok(());
+    }
+    ledger.metadata().save(metadata::path_for(log))?;
+    let mut audit = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(format!("{}.audit", log))?;
+    for event in changed.try_iter() {
+        let LedgerEvent::OwnershipChanged {
+            account,
+            change,
+            by,
+        } = event
+        else {
+            continue;
+        };
+        let (kind, owner) = match change {
+            OwnershipChange::Added(owner) => ("owner_added", owner),
+            OwnershipChange::Removed(owner) => ("owner_removed", owner),
+        };
+        let line = serde_json::json!({
+            "timestamp": SystemClock.now(),
+            "event": kind,
+            "account": account,
+            "owner": owner,
+            "by": by,
+        });
+        writeln!(audit, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// Archives accounts of the `--tx-log`/`--wal` ledger, either those given or those without
+/// entries in the last `--inactive-days <n>` days, lists the archived ones or restores one.
+/// Which accounts are archived is kept in `<log>.meta`.
+fn archive(args: &[String], rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let usage = "usage: crabbux archive (<account>... | --inactive-days <n> | list | restore <account>) (--tx-log <path> | --wal <path>)";
+    let log = flag_value(args, "--wal")
+        .or(flag_value(args, "--tx-log"))
+        .ok_or(usage)?;
+    let (mut ledger, _) = open_tx_log(args, rules)?;
+    let recorder = record_dry_run(args, &mut ledger);
+    let operands: Vec<&str> = args[1..]
+        .iter()
+        .map(String::as_str)
+        .take_while(|arg| !arg.starts_with("--"))
+        .collect();
+    match (operands.as_slice(), flag_value(args, "--inactive-days")) {
+        (["list"], None) => {
+            let mut archived: Vec<_> = ledger.archived().collect();
+            archived.sort_unstable();
+            for (account, balance) in archived {
+                let balance = amount::format_current(*balance as i128);
+                println!("{}: {}", account, balance);
+            }
+            return Ok(());
+        }
+        (["restore", account], None) => ledger.restore(account)?,
+        ([], Some(days)) => {
+            let days: u64 = days.parse()?;
+            let cutoff = SystemClock.now().0.saturating_sub(days * 86_400_000);
+            let log: TxLog = read_tx_log(args)?.into_iter().collect();
+            let mut inactive: Vec<&str> = log
+                .last_activity()
+                .filter(|(account, last)| {
+                    let locked = escrow::is_escrow(account) || dispute::is_dispute(account);
+                    last.0 < cutoff && !locked && ledger.balance_of(account).is_ok()
+                })
+                .map(|(account, _)| account)
+                .collect();
+            inactive.sort_unstable();
+            for account in &inactive {
+                ledger.archive(account)?;
+            }
+            if recorder.is_none() {
+                println!("{}", tr(Key::ArchivedAccounts, &[&inactive.len()]));
+            }
+        }
+        (accounts @ [_, ..], None) => {
+            for account in accounts {
+                ledger.archive(account)?;
+            }
+        }
+        _ => return Err(usage.into()),
+    }
+    if report_dry_run(recorder, &ledger) {
+        return Ok(());
+    }
+    Ok(ledger.metadata().save(metadata::path_for(log))?)
+}
+
+/// Shows the recipient lists of account `args[1]` of the `--tx-log`/`--wal` ledger, or changes
+/// them with `allow`, `disallow`, `block` or `unblock <recipient>`
+fn recipients(args: &[String], rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let usage = "usage: crabbux recipients <account> [(allow | disallow | block | unblock) <recipient>] (--tx-log <path> | --wal <path>)";
+    let log = flag_value(args, "--wal")
+        .or(flag_value(args, "--tx-log"))
+        .ok_or(usage)?;
+    let (mut ledger, _) = open_tx_log(args, rules)?;
+    let recorder = record_dry_run(args, &mut ledger);
+    let operands: Vec<&str> = args[1..]
+        .iter()
+        .map(String::as_str)
+        .take_while(|arg| !arg.starts_with("--"))
+        .collect();
+    if operands.len() > 1 && ledger.is_read_only() {
+        return Err(
+            ApplicationError::PermissionDenied("change recipient lists".to_string()).into(),
+        );
+    }
+    let metadata = ledger.metadata_mut();
+    match operands.as_slice() {
+        [account] => {
+            let Some(account) = metadata.get(account) else {
+                println!("{}", tr(Key::NoRestrictions, &[]));
+                return Ok(());
+            };
+            if let Some(allowlist) = &account.allowlist {
+                let allowed: Vec<&str> = allowlist.iter().map(String::as_str).collect();
+                println!("{}", tr(Key::Allowed, &[&allowed.join(", ")]));
+            }
+            if !account.blocklist.is_empty() {
+                let blocked: Vec<&str> = account.blocklist.iter().map(String::as_str).collect();
+                println!("{}", tr(Key::BlockedRecipients, &[&blocked.join(", ")]));
+            }
+            return Ok(());
+        }
+        [account, "allow", recipient] => metadata.entry(account).allow(recipient),
+        [account, "disallow", recipient] => metadata.entry(account).disallow(recipient),
+        [account, "block", recipient] => metadata.entry(account).block(recipient),
+        [account, "unblock", recipient] => metadata.entry(account).unblock(recipient),
+        _ => return Err(usage.into()),
+    }
+    if report_dry_run(recorder, &ledger) {
+        return Ok(());
+    }
+    Ok(ledger.metadata().save(metadata::path_for(log))?)
+}
+
+/// Shows the progress of the savings goals of account `args[1]` of the `--tx-log`/`--wal`
+/// ledger, or changes them with `set <name> <target> [--by <YYYY-MM-DD>]` or `remove <name>`
+fn goals(args: &[String], rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let usage = "usage: crabbux goals <account> [set <name> <target> [--by <YYYY-MM-DD>] | remove <name>] (--tx-log <path> | --wal <path>)";
+    let log = flag_value(args, "--wal")
+        .or(flag_value(args, "--tx-log"))
+        .ok_or(usage)?;
+    let (mut ledger, _) = open_tx_log(args, rules)?;
+    let recorder = record_dry_run(args, &mut ledger);
+    let operands: Vec<&str> = args[1..]
+        .iter()
+        .map(String::as_str)
+        .take_while(|arg| !arg.starts_with("--"))
+        .collect();
+    if operands.len() > 1 && ledger.is_read_only() {
+        return Err(ApplicationError::PermissionDenied("change savings goals".to_string()).into());
+    }
+    let format = |units: Units| amount::format_current(units as i128);
+    match operands.as_slice() {
+        [account] => {
+            let goals = ledger.metadata().get(account).map(|m| &m.goals);
+            let Some(goals) = goals.filter(|goals| !goals.is_empty()) else {
+                println!("{}", tr(Key::NoGoals, &[]));
+                return Ok(());
+            };
+            let balance = ledger.signed_balance_of(account).unwrap_or(0);
+            for (name, goal) in goals {
+                let progress = goal.progress(balance);
+                let (saved, target) = (format(progress.saved), format(progress.target));
+                let percent = progress.percent();
+                let line = match goal.deadline {
+                    Some(by) if goal.missed(balance, date::today()) => tr(
+                        Key::GoalMissed,
+                        &[name, &saved, &target, &percent, &by.iso()],
+                    ),
+                    Some(by) => tr(
+                        Key::GoalProgressBy,
+                        &[name, &saved, &target, &percent, &by.iso()],
+                    ),
+                    None => tr(Key::GoalProgress, &[name, &saved, &target, &percent]),
+                };
+                println!("{}", line);
+            }
+            return Ok(());
+        }
+        [account, "set", name, target] => {
+            let target = amount::parse_entered(target)?;
+            let deadline = flag_value(args, "--by")
+                .map(|date| Date::parse_iso(date).ok_or_else(|| tr(Key::InvalidDate, &[&date])))
+                .transpose()?;
+            let goal = Goal { target, deadline };
+            let metadata = ledger.metadata_mut().entry(account);
+            metadata.goals.insert(name.to_string(), goal);
+        }
+        [account, "remove", name] => {
+            if ledger
+                .metadata_mut()
+                .entry(account)
+                .goals
+                .remove(*name)
+                .is_none()
+            {
+                return Err(tr(Key::NoGoal, &[account, name]).into());
+            }
+        }
+        _ => return Err(usage.into()),
+    }
+    if report_dry_run(recorder, &ledger) {
+        return Ok(());
+    }
+    Ok(ledger.metadata().save(metadata::path_for(log))?)
+}
+
+/// Manages the direct-debit mandates of the `--tx-log`/`--wal` ledger: `grant` one letting
+/// `<payee>` collect up to `<max>` from `<payer>` per hour, day or week, `revoke` it, `list`
+/// them or `collect` under one, see [`Accounts::collect`]
+fn mandates(args: &[String], rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let usage = "usage: crabbux mandates (grant <payer> <payee> <max>/<hour|day|week> | revoke <payer> <payee> | collect <payee> <payer> <amount> | list) (--tx-log <path> | --wal <path>)";
+    let (mut ledger, persist) = open_tx_log(args, rules)?;
+    let recorder = record_dry_run(args, &mut ledger);
+    let operands: Vec<&str> = args[1..]
+        .iter()
+        .map(String::as_str)
+        .take_while(|arg| !arg.starts_with("--"))
+        .collect();
+    let txs = match operands.as_slice() {
+        ["list"] => {
+            let format = |units: Units| amount::format_current(units as i128);
+            for (payer, payee, limit) in ledger.mandates() {
+                let remaining = ledger.remaining_mandate(payer, payee).unwrap_or(0);
+                println!(
+                    "{} -> {}: {}/{}, {} left",
+                    payer,
+                    payee,
+                    format(limit.max),
+                    limit.period,
+                    format(remaining)
+                );
+            }
+            return Ok(());
+        }
+        ["grant", payer, payee, limit] => {
+            let (max, period) = limit.split_once('/').ok_or(usage)?;
+            let limit: SpendingLimit =
+                format!("{}/{}", amount::parse_entered(max)?, period).parse()?;
+            vec![ledger.grant_mandate(payer, payee, limit)?]
+        }
+        ["revoke", payer, payee] => match ledger.revoke_mandate(payer, payee)? {
+            Some(tx) => vec![tx],
+            None => return Err(tr(Key::NoMandate, &[&payee, &payer]).into()),
+        },
+        ["collect", payee, payer, amount] => {
+            ledger.collect(payee, payer, amount::parse_entered(amount)?)?
+        }
+        _ => return Err(usage.into()),
+    };
+    if report_dry_run(recorder, &ledger) {
+        return Ok(());
+    }
+    let persist = persist.ok_or_else(|| tr(Key::NeedsLog, &[&"mandates"]))?;
+    Ok(persist(&txs, ledger.principal())?)
+}
+
+/// Sends along several legs of the `--tx-log`/`--wal` ledger as one unit and persists the
+/// transactions, see [`Accounts::send_multi`]: `chain` passes `<amount>` from each account on
+/// to the next, `split` sends each `<amount>` from `<from>` to the `<to>` before it.
+fn send_multi(args: &[String], rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let usage = "usage: crabbux send-multi (chain <amount> <account> <account>... | split <from> <to> <amount> [<to> <amount>...]) (--tx-log <path> | --wal <path>)";
+    let operands: Vec<&str> = args[1..]
+        .iter()
+        .map(String::as_str)
+        .take_while(|arg| !arg.starts_with("--"))
+        .collect();
+    let legs = match operands.as_slice() {
+        ["chain", amount, accounts @ ..] if accounts.len() >= 2 => {
+            let amount = amount::parse_entered(amount)?;
+            accounts
+                .windows(2)
+                .map(|pair| (pair[0], pair[1], amount))
+                .collect::<Vec<_>>()
+        }
+        ["split", from, credits @ ..] if !credits.is_empty() && credits.len() % 2 == 0 => credits
+            .chunks(2)
+            .map(|credit| Ok((*from, credit[0], amount::parse_entered(credit[1])?)))
+            .collect::<Result<Vec<_>, String>>()?,
+        _ => return Err(usage.into()),
+    };
+    let (mut ledger, persist) = open_tx_log(args, rules)?;
+    let recorder = record_dry_run(args, &mut ledger);
+    let txs = ledger.send_multi(&legs)?;
+    if report_dry_run(recorder, &ledger) {
+        return Ok(());
+    }
+    let persist = persist.ok_or_else(|| tr(Key::NeedsLog, &[&"send-multi"]))?;
+    Ok(persist(&txs, ledger.principal())?)
+}
+
+/// Runs the rhai script `args[1]`, see [`run_script`], against `--remote <url>` or else the
+/// `--tx-log`/`--wal` ledger, and prints and persists the transactions it committed. A script
+/// that fails persists nothing.
+fn script(args: &[String], rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let Some(path) = args.get(1).filter(|arg| !arg.starts_with("--")) else {
+        return Err(
+            "usage: crabbux script <file> [--remote <url> | --tx-log <path> | --wal <path>]".into(),
+        );
+    };
+    let source = fs::read_to_string(path)?;
+    if let Some(url) = flag_value(args, "--remote") {
+        let txs = run_script(&source, Rc::new(RefCell::new(RemoteLedger::new(url))))?;
+        txs.iter().for_each(|tx| println!("{:?}", tx));
+        return Ok(());
+    }
+    let (mut ledger, persist) = open_tx_log(args, rules)?;
+    let recorder = record_dry_run(args, &mut ledger);
+    let ledger = Rc::new(RefCell::new(ledger));
+    let txs = run_script(&source, ledger.clone())?;
+    let ledger = ledger.borrow();
+    if report_dry_run(recorder, &ledger) {
+        return Ok(());
+    }
+    txs.iter().for_each(|tx| println!("{:?}", tx));
+    match persist {
+        Some(persist) => Ok(persist(&txs, ledger.principal())?),
+        None => Ok(()),
+    }
+}
+
+/// Settles the obligations in the JSON file `args[1]`, e.g. `[{"from": "alice", "to": "bob",
+/// "amount": "12.50"}]`, on the `--tx-log`/`--wal` ledger by their net positions, see
+/// [`netting::settle`], prints the settlement and persists its transactions
+fn net(args: &[String], rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let Some(path) = args.get(1).filter(|arg| !arg.starts_with("--")) else {
+        return Err("usage: crabbux net <file> (--tx-log <path> | --wal <path>)".into());
+    };
+    let obligations: Vec<Obligation> = serde_json::from_slice(&fs::read(path)?)?;
+    let (mut ledger, persist) = open_tx_log(args, rules)?;
+    let recorder = record_dry_run(args, &mut ledger);
+    let settlement = netting::settle(&mut ledger, &obligations)?;
+    print!("{}", settlement);
+    if report_dry_run(recorder, &ledger) {
+        return Ok(());
+    }
+    let persist = persist.ok_or_else(|| tr(Key::NeedsLog, &[&"net"]))?;
+    Ok(persist(&settlement.txs, ledger.principal())?)
+}
+
+/// Mints the interest the accounts of the `--tx-log`/`--wal` ledger earned over `--days <n>`
+/// days, one by default, see [`Accounts::accrue_interest`], prints it per account and
+/// persists it
+fn accrue_interest(args: &[String], rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let days = flag_value(args, "--days").map_or(Ok(1), str::parse)?;
+    let (mut ledger, persist) = open_tx_log(args, rules)?;
+    let recorder = record_dry_run(args, &mut ledger);
+    let txs = ledger.accrue_interest(days)?;
+    for tx in &txs {
+        let amount = amount::format_current(tx.amount() as i128);
+        println!("{} {}", tx.account(), amount);
+    }
+    if report_dry_run(recorder, &ledger) {
+        return Ok(());
+    }
+    let persist = persist.ok_or_else(|| tr(Key::NeedsLog, &[&"interest"]))?;
+    Ok(persist(&txs, ledger.principal())?)
+}
+
+/// Manages the standing orders of the `--tx-log`/`--wal` ledger, see [`standing`]: `add` one
+/// sending `<amount>` on `<day>` of every month, `list` them with the date of their next run,
+/// `skip` the next run of one or `cancel` it. `run` pays those that are due today and persists
+/// the transactions, like the server modes do on their own.
+fn orders(args: &[String], rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let usage = "usage: crabbux orders (add <from> <to> <amount> <day> | list | skip <id> | cancel <id> | run) (--tx-log <path> | --wal <path>)";
+    let (log, _) = log_path(args).ok_or(usage)?;
+    let path = standing::path_for(log);
+    let (mut ledger, persist) = open_tx_log(args, rules)?;
+    let recorder = record_dry_run(args, &mut ledger);
+    let mut orders = StandingOrders::load(&path)?;
+    let operands: Vec<&str> = args[1..]
+        .iter()
+        .map(String::as_str)
+        .take_while(|arg| !arg.starts_with("--"))
+        .collect();
+    if operands != ["list"] && ledger.is_read_only() {
+        return Err(
+            ApplicationError::PermissionDenied("change standing orders".to_string()).into(),
+        );
+    }
+    let missing = |id: &str| tr(Key::NoStandingOrder, &[&id]);
+    let mut txs = vec![];
+    match operands.as_slice() {
+        ["list"] => {
+            for (id, order) in orders.iter() {
+                let amount = amount::format_current(order.amount as i128);
+                println!(
+                    "#{} {} -> {}: {} on day {}, next {}",
+                    id,
+                    order.from,
+                    order.to,
+                    amount,
+                    order.day,
+                    order.next.iso()
+                );
+            }
+            return Ok(());
+        }
+        ["add", from, to, amount, day] => {
+            let amount = amount::parse_entered(amount)?;
+            let order = StandingOrder::new(from, to, amount, day.parse()?, date::today())?;
+            let next = order.next;
+            println!(
+                "{}",
+                tr(Key::StandingOrderAdded, &[&orders.add(order), &next.iso()])
+            );
+        }
+        ["skip", id] => {
+            let order = orders.get_mut(id.parse()?).ok_or_else(|| missing(id))?;
+            order.skip();
+            println!("{}", tr(Key::NextRun, &[&order.next.iso()]));
+        }
+        ["cancel", id] => {
+            orders.cancel(id.parse()?).ok_or_else(|| missing(id))?;
+        }
+        ["run"] => {
+            for run in orders.run_due(&mut ledger, date::today()) {
+                match run.result {
+                    Ok(paid) => {
+                        println!("{}", tr(Key::OrderPaid, &[&run.id, &run.due.iso()]));
+                        txs.extend(paid);
+                    }
+                    Err(e) => println!("{}", tr(Key::OrderFailed, &[&run.id, &e])),
+                }
+            }
+        }
+        _ => return Err(usage.into()),
+    }
+    if report_dry_run(recorder, &ledger) {
+        return Ok(());
+    }
+    if !txs.is_empty() {
+        let persist = persist.ok_or(usage)?;
+        persist(&txs, ledger.principal())?;
+    }
+    Ok(orders.save(&path)?)
+}
+
+/// Runs `rates <subcommand>` on the exchange rates kept next to the `--tx-log`/`--wal` ledger,
+/// see [`rates::path_for`]. `import <file>` adds a CSV or JSON table of dated rates, by
+/// `--format csv|json` or else the file's extension; `convert <amount> <from> <to>` converts at
+/// the rate of `--as-of <YYYY-MM-DD>` (default today), and `value <account> <currency>` values
+/// what the account had at the end of that day in the ledger's `currency`.
+fn rates(args: &[String], config: &Config, rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let usage = "usage: crabbux rates (import <file> [--format csv|json] | list | convert <amount> <from> <to> | value <account> <currency>) [--as-of <date>] (--tx-log <path> | --wal <path>)";
+    let (log, _) = log_path(args).ok_or(usage)?;
+    let path = rates::path_for(log);
+    let mut table = RateTable::load(&path)?;
+    let operands: Vec<&str> = args[1..]
+        .iter()
+        .map(String::as_str)
+        .take_while(|arg| !arg.starts_with("--"))
+        .collect();
+    let on = match flag_value(args, "--as-of") {
+        Some(date) => Date::parse_iso(date).ok_or_else(|| tr(Key::InvalidDate, &[&date]))?,
+        None => date::today(),
+    };
+    let currencies = CurrencyRegistry::from_config(config)?;
+    let format =
+        |units: Units, decimals| amount::format(units as i128, decimals, NumberFormat::current());
+    match operands.as_slice() {
+        ["import", file] => {
+            if rules.viewer {
+                return Err(ApplicationError::PermissionDenied("import rates".to_string()).into());
+            }
+            let input = fs::read_to_string(file)?;
+            let imported = match flag_value(args, "--format") {
+                Some("json") => RateTable::parse_json(&input)?,
+                Some("csv") => RateTable::parse_csv(&input)?,
+                None if file.ends_with(".json") => RateTable::parse_json(&input)?,
+                None => RateTable::parse_csv(&input)?,
+                Some(format) => return Err(tr(Key::UnknownFormat, &[&format]).into()),
+            };
+            println!("{}", tr(Key::ImportedRates, &[&imported.len()]));
+            table.extend(imported);
+            table.save(&path)?;
+        }
+        ["list"] => {
+            for rate in table.dated() {
+                println!(
+                    "{} {} -> {}: {}",
+                    rate.date.iso(),
+                    rate.from,
+                    rate.to,
+                    rate.rate
+                );
+            }
+        }
+        ["convert", amount, from, to] => {
+            let (from_decimals, to_decimals) =
+                (currencies.validate(from)?, currencies.validate(to)?);
+            let amount = amount::parse_unsigned(amount, from_decimals, NumberFormat::current())?;
+            let quote = table.rate(from, to, on)?;
+            let converted = quote
+                .rate
+                .convert(amount, from_decimals, to_decimals, rules.rounding.policy)
+                .ok_or(RateError::Overflow)?;
+            println!(
+                "{} {} = {} {} at {} from {}",
+                format(amount, from_decimals),
+                from.to_uppercase(),
+                format(converted, to_decimals),
+                to.to_uppercase(),
+                quote.rate,
+                quote.date.iso()
+            );
+        }
+        ["value", account, to] => {
+            let currency = amount::currency().ok_or_else(|| tr(Key::ValuingNeedsCurrency, &[]))?;
+            let to_decimals = currencies.validate(to)?;
+            let log: TxLog = read_tx_log(args)?.into_iter().collect();
+            let balance = log
+                .balance_at(account, Timestamp::end_of(on))
+                .ok_or_else(|| ApplicationError::NotFound(account.to_string()))?;
+            // Balances are in the ledger's smallest unit, whatever the currency's precision
+            let quote = table.rate(&currency, to, on)?;
+            let value = quote
+                .rate
+                .convert(
+                    balance,
+                    amount::decimals(),
+                    to_decimals,
+                    rules.rounding.policy,
+                )
+                .ok_or(RateError::Overflow)?;
+            println!(
+                "{}: {} {} = {} {} at {} from {}",
+                account,
+                amount::format_current(balance as i128),
+                currency,
+                format(value, to_decimals),
+                to.to_uppercase(),
+                quote.rate,
+                quote.date.iso()
+            );
+        }
+        _ => return Err(usage.into()),
+    }
+    Ok(())
+}
+
+/// Prints the entries of the `--tx-log`/`--wal` history, oldest first, with their position in the
+/// log, who committed them and the state of their dispute, if any. Only those affecting account `args[1]` if given, moving at least `--min <amount>` and at
+/// most `--max <amount>`, and stored from the start of `--from <YYYY-MM-DD>` to the end of
+/// `--to <YYYY-MM-DD>` (UTC). Of those only the page given by [`page_flags`].
+///
+/// With `--follow` it then keeps printing matching entries as they're committed: those a
+/// `--remote` server pushes, without their position, or those appended to the local log.
+fn history(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let account = args.get(1).filter(|a| !a.starts_with("--"));
+    let min = flag_value(args, "--min").map_or(Ok(0), amount::parse_entered)?;
+    let max = flag_value(args, "--max").map_or(Ok(Units::MAX), amount::parse_entered)?;
+    let parse_date = |flag| {
+        flag_value(args, flag)
+            .map(|date| Date::parse_iso(date).ok_or_else(|| tr(Key::InvalidDate, &[&date])))
+            .transpose()
+    };
+    let from = parse_date("--from")?.map_or(Timestamp(0), Timestamp::start_of);
+    let to = parse_date("--to")?.map_or(Timestamp(u64::MAX), Timestamp::end_of);
+    let matches = |entry: &LogEntry| {
+        account.is_none_or(|account| entry.tx.account() == account)
+            && (min..=max).contains(&entry.tx.amount())
+            && (from..=to).contains(&entry.timestamp)
+    };
+    let follow = args.iter().any(|arg| arg == "--follow");
+    if let Some(url) = flag_value(args, "--remote") {
+        if !follow {
+            return Err(tr(Key::RemoteHistoryNeedsFollow, &[]).into());
+        }
+        for tx in RemoteLedger::new(url).subscribe(account.map(String::as_str))? {
+            let entry = LogEntry {
+                timestamp: SystemClock.now(),
+                tx: tx?,
+                actor: None,
+            };
+            if matches(&entry) {
+                println!("{}", history_line(None, &entry));
+            }
+        }
+        return Ok(());
+    }
+    let (entries, mut read) = read_appended(args, 0)?;
+    let log: TxLog = entries.into_iter().collect();
+    let disputes = log.disputes();
+    let entries: Box<dyn Iterator<Item = (usize, &LogEntry)>> = match account {
+        Some(account) => Box::new(log.account_entries(account)),
+        None => Box::new(log.find_txs(min, max)),
+    };
+    let (offset, limit) = page_flags(args)?;
+    let entries = entries
+        .filter(|(_, entry)| {
+            (min..=max).contains(&entry.tx.amount()) && (from..=to).contains(&entry.timestamp)
+        })
+        .skip(offset)
+        .take(limit.unwrap_or(usize::MAX));
+    for (position, entry) in entries {
+        let dispute = disputes
+            .get(&position)
+            .map_or(String::new(), |state| format!(" [{}]", state));
+        println!("{}{}", history_line(Some(position), entry), dispute);
+    }
+    if !follow {
+        return Ok(());
+    }
+    // Only the entries appended since are read
+    let mut seen = log.len();
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+        let (entries, end) = read_appended(args, read)?;
+        for entry in entries {
+            if matches(&entry) {
+                println!("{}", history_line(Some(seen), &entry));
+            }
+            seen += 1;
+        }
+        read = end;
+    }
+}
+
+/// `#<position> <timestamp> <kind> <account> <amount>`, and ` by <actor>` if recorded
+fn history_line(position: Option<usize>, entry: &LogEntry) -> String {
+    let amount = amount::format_current(entry.tx.amount() as i128);
+    let position = position.map_or(String::new(), |position| format!("#{} ", position));
+    let actor = entry
+        .actor
+        .as_ref()
+        .map_or(String::new(), |actor| tr(Key::ByActor, &[actor]));
+    format!(
+        "{}{} {} {} {}{}",
+        position,
+        entry.timestamp,
+        entry.tx.kind(),
+        entry.tx.account(),
+        amount,
+        actor
+    )
+}
+
+/// Prints all balances after the first `--at <n>` entries of the `--tx-log`/`--wal` history
+fn state(args: &[String], rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let Some(at) = flag_value(args, "--at") else {
+        return Err("usage: crabbux state --at <n> (--tx-log <path> | --wal <path>)".into());
+    };
+    let at: usize = at.parse()?;
+    let log: TxLog = read_tx_log(args)?.into_iter().collect();
+    let view = log.state_at(rules.ledger(), at)?;
+    println!("{}", tr(Key::Ledger, &[]));
+    for (account, balance) in Snapshot::of(&view, at).balances {
+        let balance = amount::format_current(balance as i128);
+        println!("  {}: {}", account, balance);
+    }
+    Ok(())
+}
+
+/// Compares the ledgers `args[1]` and `args[2]`, see [`load_balances`]
+fn diff(args: &[String], rules: &LedgerRules) -> Result<LedgerDiff, Box<dyn Error>> {
+    let (Some(left), Some(right)) = (args.get(1), args.get(2)) else {
+        return Err("usage: crabbux diff <left> <right>".into());
+    };
+    Ok(LedgerDiff::between(
+        &load_balances(left, rules)?,
+        &load_balances(right, rules)?,
+    ))
+}
+
+/// The balances stored at `path`: a snapshot if it ends in `.json`, a WAL if it ends in `.wal`,
+/// a JSON lines tx log otherwise, replayed following `rules`
+fn load_balances(
+    path: &str,
+    rules: &LedgerRules,
+) -> Result<BTreeMap<String, Units>, Box<dyn Error>> {
+    if path.ends_with(".json") {
+        return Ok(Snapshot::load(path)?.balances);
+    }
+    let mut ledger = rules.ledger();
+    if path.ends_with(".wal") {
+        MmapWal::open(path)?.replay(&mut ledger)?;
+    } else {
+        storage::replay(&mut ledger, LogReader::open(path)?)?;
+    }
+    Ok(Snapshot::of(&ledger, 0).balances)
+}
+
+/// Reads the whole timestamped history from `--tx-log <path>` or `--wal <path>`
+fn read_tx_log(args: &[String]) -> Result<Vec<LogEntry>, Box<dyn Error>> {
+    if let Some(path) = flag_value(args, "--wal") {
+        return Ok(MmapWal::open(path)?
+            .iter()
+            .map(|entry| entry.map(|entry| entry.to_entry()))
+            .collect::<io::Result<_>>()?);
+    }
+    match flag_value(args, "--tx-log") {
+        Some(path) => Ok(LogReader::open(path)?.collect::<io::Result<_>>()?),
+        None => Err(tr(Key::NoLog, &[]).into()),
+    }
+}
+
+/// The entries of the `--wal` or `--tx-log` after its first `offset` bytes, and the offset after
+/// them, see [`storage::read_appended`] and [`MmapWal::entries_after`]
+fn read_appended(args: &[String], offset: u64) -> Result<(Vec<LogEntry>, u64), Box<dyn Error>> {
+    if let Some(path) = flag_value(args, "--wal") {
+        let (entries, end) = MmapWal::open(path)?.entries_after(offset as usize)?;
+        return Ok((entries, end as u64));
+    }
+    match flag_value(args, "--tx-log") {
+        Some(path) => Ok(storage::read_appended(path, offset)?),
+        None => Err(tr(Key::NoLog, &[]).into()),
+    }
+}
+
+/// Prints the accounts of the `--tx-log`/`--wal` ledger with their balances and number of
+/// transactions, ordered by `--sort <key>` (default `name`) and descending with `--desc`, see
+/// [`AccountQuery`]. Only the accounts under `--prefix <account>` whose names match
+/// `--match <regex>` are included, see [`AccountFilter`], and only the page given by
+/// [`page_flags`] is printed.
+fn list_accounts(args: &[String], rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    if log_path(args).is_none() {
+        return Err("usage: crabbux accounts [--sort name|balance|activity] [--desc] [--offset <n>] [--limit <n>] [--prefix <account>] [--match <regex>] (--tx-log <path> | --wal <path>)".into());
+    }
+    let mut filter = AccountFilter::default();
+    if let Some(prefix) = flag_value(args, "--prefix") {
+        filter = filter.prefix(prefix);
+    }
+    if let Some(pattern) = flag_value(args, "--match") {
+        filter = filter.regex(pattern)?;
+    }
+    let (offset, limit) = page_flags(args)?;
+    let query = AccountQuery {
+        sort: flag_value(args, "--sort").map_or(Ok(SortKey::Name), str::parse)?,
+        descending: args.iter().any(|arg| arg == "--desc"),
+        offset,
+        limit,
+        filter,
+    };
+    let (ledger, _) = load_tx_log(args, rules, true)?;
+    for listing in query.run(&ledger) {
+        let balance = amount::format_current(listing.balance as i128);
+        println!(
+            "  {}: {}, {} transactions",
+            listing.account, balance, listing.txs
+        );
+    }
+    Ok(())
+}
+
+/// Prints the funds in the ledger, how many accounts and transactions it has, the account with
+/// the most transactions and when the latest one was committed. A transfer counts as two
+/// transactions, as it's logged.
+fn print_stats(args: &[String], rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    if log_path(args).is_none() {
+        return Err("usage: crabbux stats (--tx-log <path> | --wal <path>)".into());
+    }
+    let (ledger, _) = load_tx_log(args, rules, true)?;
+    let stats = ledger.stats();
+    let format = |amount: u128| amount::format_current(i128::try_from(amount).unwrap_or(i128::MAX));
+    println!("{}", tr(Key::StatsFunds, &[&format(ledger.supply())]));
+    println!("{}", tr(Key::StatsAccounts, &[&ledger.len()]));
+    println!("{}", tr(Key::StatsTxs, &[&stats.totals().txs]));
+    println!(
+        "{}",
+        tr(
+            Key::StatsMinted,
+            &[
+                &format(stats.totals().minted),
+                &format(stats.totals().burned)
+            ]
+        )
+    );
+    match stats.busiest() {
+        Some((account, busiest)) => {
+            println!("{}", tr(Key::StatsBusiest, &[&account, &busiest.txs]))
+        }
+        None => println!("{}", tr(Key::StatsNoBusiest, &[])),
+    }
+    match stats.last_activity() {
+        Some(at) => println!("{}", tr(Key::StatsLastActivity, &[&at])),
+        None => println!("{}", tr(Key::StatsLastActivity, &[&"-"])),
+    }
+    Ok(())
+}
+
+/// `--offset <n>` (default 0) and `--limit <n>` (default all), which lines of a listing to print
+fn page_flags(args: &[String]) -> Result<(usize, Option<usize>), Box<dyn Error>> {
+    let offset = flag_value(args, "--offset").map_or(Ok(0), str::parse)?;
+    let limit = flag_value(args, "--limit").map(str::parse).transpose()?;
+    Ok((offset, limit))
+}
+
+/// How often [`watch`] redraws without a transaction committed in between
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Fetches the balances [`watch`] shows
+type FetchBalances<'a> = Box<dyn FnMut() -> Result<BTreeMap<String, Units>, Box<dyn Error>> + 'a>;
+
+/// Redraws the balances of the accounts `args[1..]`, or of all accounts, every
+/// [`WATCH_INTERVAL`], and right away on every transaction a `--remote` server commits. A local
+/// ledger is reread from its `--tx-log`/`--wal` each time, so what other processes append,
+/// e.g. a server, shows up. Output that isn't a terminal only gets the balances that changed.
+fn watch(args: &[String], rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let accounts: Vec<&str> = args[1..]
+        .iter()
+        .map(String::as_str)
+        .take_while(|arg| !arg.starts_with("--"))
+        .collect();
+    let (committed, redraw) = mpsc::channel();
+    let mut fetch: FetchBalances =
+        match flag_value(args, "--remote") {
+            Some(url) => {
+                let mut remote = RemoteLedger::new(url);
+                match remote.subscribe(None) {
+                    Ok(txs) => {
+                        thread::spawn(move || {
+                            for tx in txs {
+                                if tx.is_err() || committed.send(()).is_err() {
+                                    break;
+                                }
+                            }
+                        });
+                    }
+                    Err(e) => warn!(error = %e, "couldn't subscribe to committed transactions"),
+                }
+                Box::new(move || Ok(remote.accounts()?))
+            }
+            None => match log_path(args) {
+                // The log is only reread once its size or modification time changed
+                Some((path, _)) => {
+                    let mut read: Option<(_, BTreeMap<String, Units>)> = None;
+                    Box::new(move || {
+                        let stat = fs::metadata(path).map(|m| (m.len(), m.modified().ok()));
+                        let stat = stat.ok();
+                        match &read {
+                            Some((seen, balances)) if *seen == stat => Ok(balances.clone()),
+                            _ => {
+                                let (ledger, _) = load_tx_log(args, rules, true)?;
+                                let balances = Snapshot::of(&ledger, 0).balances;
+                                read = Some((stat, balances.clone()));
+                                Ok(balances)
+                            }
+                        }
+                    })
+                }
+                None => return Err("usage: crabbux watch [<account>...] (--remote <url> | --tx-log <path> | --wal <path>)".into()),
+            },
+        };
+    let terminal = io::stdout().is_terminal();
+    let mut shown = None;
+    loop {
+        let balances = fetch()?;
+        let view: Vec<(String, Option<Units>)> = match accounts.as_slice() {
+            [] => balances.into_iter().map(|(a, b)| (a, Some(b))).collect(),
+            accounts => accounts
+                .iter()
+                .map(|account| (account.to_string(), balances.get(*account).copied()))
+                .collect(),
+        };
+        if terminal || shown.as_ref() != Some(&view) {
+            let mut out = io::stdout().lock();
+            if terminal {
+                // Clear the screen and start at the top
+                write!(out, "\x1b[2J\x1b[H")?;
+            }
+            writeln!(out, "{}", tr(Key::Ledger, &[]))?;
+            for (account, balance) in &view {
+                let balance = balance.map_or("-".to_string(), |balance| {
+                    amount::format_current(balance as i128)
+                });
+                writeln!(out, "  {}: {}", account, balance)?;
+            }
+            out.flush()?;
+            shown = Some(view);
+        }
+        match redraw.recv_timeout(WATCH_INTERVAL) {
+            Ok(()) => while redraw.try_recv().is_ok() {},
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            // The subscription ended, there's only the interval left
+            Err(mpsc::RecvTimeoutError::Disconnected) => thread::sleep(WATCH_INTERVAL),
+        }
+    }
+}
+
+/// The transactions of the `--tx-log`/`--wal` history, without their timestamps
+fn read_txs(args: &[String]) -> Result<Vec<Tx>, Box<dyn Error>> {
+    Ok(read_tx_log(args)?
+        .into_iter()
+        .map(|entry| entry.tx)
+        .collect())
+}
+
+/// Writes `txs` in `format`, `ledger` or `beancount`. `--commodity <name>` (default the
+/// `currency` config key, or else `CBX`) names the currency where needed, its amounts having
+/// the ledger's decimals, and `--balance-assertions` adds those to beancount exports.
+fn export(
+    format: &str,
+    txs: &[Tx],
+    args: &[String],
+    config: &Config,
+    out: &mut impl Write,
+) -> Result<(), Box<dyn Error>> {
+    let commodity = flag_value(args, "--commodity")
+        .or(config.get("currency"))
+        .unwrap_or("CBX");
+    let date = date::today();
+    match format {
+        "ledger" => journal::write(out, txs, date, commodity, amount::decimals())?,
+        "beancount" => {
+            let assertions = args.iter().any(|arg| arg == "--balance-assertions");
+            beancount::write(out, txs, date, commodity, amount::decimals(), assertions)?
+        }
+        _ => return Err(tr(Key::UnknownFormat, &[&format]).into()),
+    }
+    Ok(())
+}
+
+/// Exports the `--tx-log`/`--wal` ledger with its `<log>.meta` to `--out <file>` or stdout,
+/// see [`state::LedgerState`]
+fn export_state(args: &[String], rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let Some((log, _)) = log_path(args) else {
+        return Err(
+            "usage: crabbux export-state [--out <file>] (--tx-log <path> | --wal <path>)".into(),
+        );
+    };
+    let metadata = LedgerMetadata::load(metadata::path_for(log))?;
+    let log = read_tx_log(args)?;
+    let ledger = state::LedgerState::of(rules.ledger(), log, metadata, SystemClock.now())?;
+    match flag_value(args, "--out") {
+        Some(path) => {
+            let mut out = io::BufWriter::new(fs::File::create(path)?);
+            state::write(&mut out, &ledger)?;
+            out.flush()?;
+        }
+        None => state::write(&mut io::stdout().lock(), &ledger)?,
+    }
+    Ok(())
+}
+
+/// Imports the state exported to `args[1]` into the `--tx-log`/`--wal` ledger and its
+/// `<log>.meta`, merging it or with `--replace` replacing the ledger, see
+/// [`import::state::plan`]. Prints what changes, and with `--dry-run` stops there. Replacing
+/// asks first unless given `--yes`.
+fn import_state(args: &[String], rules: &LedgerRules) -> Result<(), Box<dyn Error>> {
+    let (Some(file), Some((log, wal))) = (args.get(1), log_path(args)) else {
+        return Err("usage: crabbux import-state <file> [--replace] [--dry-run] [--yes] (--tx-log <path> | --wal <path>)".into());
+    };
+    let has_flag = |flag| args.iter().any(|arg| arg == flag);
+    let imported = import::state::read(&fs::read(file)?, || rules.ledger())?;
+    let mode = if has_flag("--replace") {
+        import::state::Mode::Replace
+    } else {
+        import::state::Mode::Merge
+    };
+    let entries = if fs::exists(log)? {
+        read_tx_log(args)?
+    } else {
+        vec![]
+    };
+    let meta = metadata::path_for(log);
+    let metadata = LedgerMetadata::load(&meta)?;
+    let plan = import::state::plan(&entries, &metadata, &imported, mode, || rules.ledger())?;
+    print!("{}", plan);
+    if plan.is_empty() || has_flag("--dry-run") {
+        return Ok(());
+    }
+    if mode == import::state::Mode::Replace && !has_flag("--yes") {
+        let question = tr(Key::ConfirmReplaceState, &[&log, &plan.log.len()]);
+        if !is_yes(&read_from_stdin(&question)) {
+            return Ok(());
+        }
+    }
+    let contents = if wal {
+        wal::encode_wal(&plan.log)?
+    } else {
+        storage::encode_log(&plan.log)?
+    };
+    storage::replace_file(log, &contents)?;
+    plan.metadata.save(meta)?;
+    println!("{}", tr(Key::ImportedInto, &[&log]));
+    Ok(())
+}
+
+/// Returns the value following `name` in the command line arguments
+/// Whether `--dry-run` was given, so commands only print what they would do
+fn dry_run(args: &[String]) -> bool {
+    args.iter().any(|arg| arg == "--dry-run")
+}
+
+/// With `--dry-run`, starts recording what a command does to the `ledger` it loaded, see
+/// [`report_dry_run`]. The ledger is the command's own copy, so it needn't be forked.
+fn record_dry_run(args: &[String], ledger: &mut Accounts) -> Option<Recorder> {
+    dry_run(args).then(|| Recorder::start(ledger))
+}
+
+/// Prints what the command recorded by `recorder` did to `ledger`, if anything recorded it,
+/// in which case the command must not persist anything
+fn report_dry_run(recorder: Option<Recorder>, ledger: &Accounts) -> bool {
+    let Some(recorder) = recorder else {
+        return false;
+    };
+    print!("{}", recorder.finish(ledger));
+    true
+}
+
+fn flag_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    flag_values(args, name).into_iter().next()
+}
+
+/// Returns the values following every occurrence of `name` in the command line arguments
+fn flag_values<'a>(args: &'a [String], name: &str) -> Vec<&'a str> {
+    args.windows(2)
+        .filter(|pair| pair[0] == name)
+        .map(|pair| pair[1].as_str())
+        .collect()
+}
+
+/// Builds the webhook config from `--webhook <url>` (repeatable) and `--webhook-secret <secret>`
+fn webhook_config(args: &[String]) -> Option<WebhookConfig> {
+    let urls: Vec<String> = flag_values(args, "--webhook")
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    if urls.is_empty() {
+        return None;
+    }
+    let secret = flag_value(args, "--webhook-secret").unwrap_or_default();
+    Some(WebhookConfig::new(urls, secret))
+}
+
+/// Reads an amount written the way the current locale does, see [`amount::parse_entered`]
+fn read_amount() -> Result<Units, String> {
+    amount::parse_entered(&read_from_stdin(&tr(Key::Amount, &[])))
+}
+
+/// The ledger directory: `--ledger-dir <dir>`, the `ledger_dir` config key, or [`LedgerManager::default_dir`]
+fn ledger_dir(args: &[String], config: &Config) -> PathBuf {
+    flag_value(args, "--ledger-dir")
+        .or(config.get("ledger_dir"))
+        .map(PathBuf::from)
+        .or_else(LedgerManager::default_dir)
+        .unwrap_or_else(|| PathBuf::from("ledgers"))
+}
+
+/// Reads `--config <path>`, or the config file at [`Config::default_path`] if there is one
+fn load_config(args: &[String]) -> io::Result<Config> {
+    match flag_value(args, "--config")
+        .map(PathBuf::from)
+        .or_else(Config::default_path)
+    {
+        Some(path) => Config::load(&path),
+        None => Ok(Config::default()),
+    }
+}
+
+/// Whether this is the first launch: the interactive mode was started on a terminal without a
+/// ledger, config file or `--dry-run`, and neither the config file at `path` nor the ledger
+/// directory exist yet
+fn needs_setup(args: &[String], config: &Config, path: &Path) -> bool {
+    let flags = ["--tx-log", "--wal", "--ledger", "--remote", "--config"];
+    args.first().is_none_or(|arg| arg.starts_with("--"))
+        && !flags.iter().any(|flag| flag_value(args, flag).is_some())
+        && !dry_run(args)
+        && io::stdin().is_terminal()
+        && !path.exists()
+        && !ledger_dir(args, config).exists()
+}
+
+/// Walks the user through the first launch, see [`needs_setup`]: where to keep the ledger and
+/// in which format, the default currency and the initial accounts. The answers are saved to
+/// the config file at `path`, and the ledger is created with the accounts and opened by adding
+/// its flag to `args`.
+/// # Errors
+/// The input ended, or the config file or ledger couldn't be written
+fn first_run_setup(
+    args: &mut Vec<String>,
+    config: &mut Config,
+    path: &Path,
+    rules: &LedgerRules,
+) -> Result<(), Box<dyn Error>> {
+    let ask = |label: String| match read_from_stdin(&label) {
+        answer if answer == "quit" => Err(tr(Key::SetupCancelled, &[])),
+        answer => Ok(answer),
+    };
+    let invalid = |e: &dyn Display| println!("{}", tr(Key::EncounteredError, &[e]));
+    println!("{}", tr(Key::SetupWelcome, &[]));
+    let suggested = ledger_dir(args, config);
+    let dir = match ask(tr(Key::SetupDirectory, &[&suggested.display()]))?.as_str() {
+        "" => suggested,
+        dir => PathBuf::from(dir),
+    };
+    let wal = loop {
+        match ask(tr(Key::SetupFormat, &[]))?.as_str() {
+            "" | "jsonl" => break false,
+            "wal" => break true,
+            other => invalid(&tr(Key::SetupExpectedFormat, &[&format!("{:?}", other)])),
+        }
+    };
+    let currency = loop {
+        match ask(tr(Key::SetupCurrency, &[&"CBX"]))? {
+            currency if currency.is_empty() => break "CBX".to_string(),
+            currency if currency.chars().all(|c| c.is_ascii_alphanumeric()) => {
+                break currency.to_uppercase()
+            }
+            other => invalid(&tr(Key::SetupExpectedCurrency, &[&format!("{:?}", other)])),
+        }
+    };
+    let mut accounts = vec![];
+    loop {
+        let line = ask(tr(Key::SetupAccount, &[]))?;
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (None, ..) => break,
+            (Some(name), Some(balance), None) => match amount::parse_entered(balance) {
+                Ok(balance) => accounts.push((name.to_string(), balance)),
+                Err(e) => invalid(&e),
+            },
+            _ => invalid(&tr(Key::SetupExpectedAccount, &[])),
+        }
+    }
+
+    let (flag, key, file) = match wal {
+        true => ("--wal", "wal", "crabbux.wal"),
+        false => ("--tx-log", "tx_log", "crabbux.jsonl"),
+    };
+    let log = dir.join(file).display().to_string();
+    fs::create_dir_all(&dir)?;
+    config.set("ledger_dir", dir.display().to_string());
+    config.set(key, log.as_str());
+    // Codes that aren't ISO 4217 are registered with the ledger's decimals
+    if CurrencyRegistry::from_config(config)?
+        .decimals_of(&currency)
+        .is_none()
+    {
+        config.set(
+            &format!("currency.{}", currency),
+            amount::decimals().to_string(),
+        );
+    }
+    config.set("currency", currency);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, config.to_string())?;
+    args.extend([flag.to_string(), log.clone()]);
+    let (mut ledger, persist) = open_tx_log(args, rules)?;
+    let txs = accounts
+        .iter()
+        .map(|(name, balance)| ledger.deposit(name, *balance))
+        .collect::<Result<Vec<_>, _>>()?;
+    if let Some(persist) = persist {
+        persist(&txs, None)?;
+    }
+    println!("{}", tr(Key::SetupDone, &[&path.display(), &log]));
+    Ok(())
+}
+
+/// Returns `true` if `e` is an [`ApplicationError::ConfirmationRequired`] from a local or remote ledger
+fn needs_confirmation(e: &(dyn Error + 'static)) -> bool {
+    match (e.downcast_ref(), e.downcast_ref()) {
+        (Some(ApplicationError::ConfirmationRequired(_)), _) => true,
+        (_, Some(ClientError::Rpc(e))) => e.code == CONFIRMATION_REQUIRED,
+        _ => false,
+    }
+}
+
+/// Whether `answer` agrees, in any of the supported languages
+fn is_yes(answer: &str) -> bool {
+    ["y", "yes", "s", "si", "sí", "j", "ja"].contains(&answer.to_lowercase().as_str())
+}
+
+thread_local! {
+    /// Answers to the next prompts, read before stdin, e.g. those of a command recalled from
+    /// the history or of an alias, and whether the history remembers them
+    static QUEUED: RefCell<VecDeque<(String, bool)>> = const { RefCell::new(VecDeque::new()) };
+    /// The answers given since the command prompt, for the history
+    static ANSWERS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// How many lines a page of [`page`] has when both stdin and stdout are a terminal:
+/// `page_size = <lines>` in the config, `0` for no paging, or else what fits the terminal
+/// according to `$LINES`, 24 lines without it
+fn page_size(config: &Config) -> Result<Option<usize>, String> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Ok(None);
+    }
+    let size = match config.get("page_size") {
+        Some(size) => size.parse().map_err(|e| format!("page_size: {}", e))?,
+        None => env::var("LINES")
+            .ok()
+            .and_then(|lines| lines.parse::<usize>().ok())
+            .unwrap_or(24)
+            .saturating_sub(2)
+            .max(1),
+    };
+    Ok(Some(size).filter(|&size| size > 0))
+}
+
+/// Prints `lines`, waiting for return after every `page_size` of them if given. `q` or the end
+/// of the input stops. What's typed here doesn't answer prompts or go to the history.
+fn page(lines: impl IntoIterator<Item = String>, page_size: Option<usize>) {
+    for (i, line) in lines.into_iter().enumerate() {
+        if page_size.is_some_and(|size| i > 0 && i % size == 0) {
+            println!("{}", tr(Key::More, &[]));
+            let mut answer = String::new();
+            let read = io::stdin().read_line(&mut answer);
+            if read.is_err() || read.is_ok_and(|read| read == 0) || answer.trim() == "q" {
+                return;
+            }
+        }
+        println!("{}", line);
+    }
 }
 
 fn read_from_stdin(label: &str) -> String {
-    let mut buffer = String::new();
     println!("{}", label);
-    io::stdin()
-        .read_line(&mut buffer)
-        .expect("Couldn't read from stdin");
-    buffer.trim().to_owned()
+    let answer = match QUEUED.with_borrow_mut(VecDeque::pop_front) {
+        Some((answer, remembered)) => {
+            println!("{}", answer);
+            if !remembered {
+                return answer;
+            }
+            answer
+        }
+        None => {
+            let mut buffer = String::new();
+            let read = io::stdin()
+                .read_line(&mut buffer)
+                .expect("Couldn't read from stdin");
+            // The end of the input quits, like `quit`
+            if read == 0 {
+                return "quit".to_string();
+            }
+            buffer.trim().to_owned()
+        }
+    };
+    ANSWERS.with_borrow_mut(|answers| answers.push(answer.clone()));
+    answer
 }
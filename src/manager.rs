@@ -0,0 +1,191 @@
+use crate::{
+    accounts::Accounts,
+    metadata::{self, LedgerMetadata},
+    migrations,
+    storage::{self, FileStore, LogReader},
+    tx::Tx,
+};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Called on every ledger before or after it is replayed
+type OnOpen = Box<dyn Fn(&mut Accounts)>;
+
+/// Hosts several independent, named ledgers (e.g. `personal` and `business`) in one process.
+///
+/// Each ledger is persisted to its own tx log, `<name>.jsonl`, in the manager's directory,
+/// and is replayed the first time it is selected, along with its metadata in `<name>.jsonl.meta`.
+pub struct LedgerManager {
+    dir: PathBuf,
+    ledgers: BTreeMap<String, (Accounts, FileStore)>,
+    current: Option<String>,
+    on_create: Option<OnOpen>,
+    on_open: Option<OnOpen>,
+}
+
+impl LedgerManager {
+    /// Manages the ledgers in `dir`, which is created when the first ledger is selected
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        LedgerManager {
+            dir: dir.into(),
+            ledgers: BTreeMap::new(),
+            current: None,
+            on_create: None,
+            on_open: None,
+        }
+    }
+
+    /// Calls `f` on every ledger before its log is replayed, e.g. to set credit limits
+    pub fn on_create(&mut self, f: impl Fn(&mut Accounts) + 'static) {
+        self.on_create = Some(Box::new(f));
+    }
+
+    /// Calls `f` on every ledger right after it is replayed, e.g. to configure it or subscribe
+    /// to its events
+    pub fn on_open(&mut self, f: impl Fn(&mut Accounts) + 'static) {
+        self.on_open = Some(Box::new(f));
+    }
+
+    /// `$XDG_DATA_HOME/crabbux/ledgers`, falling back to `~/.local/share/crabbux/ledgers`
+    pub fn default_dir() -> Option<PathBuf> {
+        let base = env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))?;
+        Some(base.join("crabbux").join("ledgers"))
+    }
+
+    /// The tx log of the ledger called `name`
+    /// # Errors
+    /// `name` is empty or contains anything but ASCII letters, digits, `-` and `_`
+    pub fn log_path(&self, name: &str) -> io::Result<PathBuf> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid ledger name {:?}", name),
+            ));
+        }
+        Ok(self.dir.join(format!("{}.jsonl", name)))
+    }
+
+    /// The names of all ledgers in the directory or opened so far, in order
+    pub fn names(&self) -> io::Result<Vec<String>> {
+        let mut names: Vec<String> = self.ledgers.keys().cloned().collect();
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(names),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "jsonl") {
+                if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    /// Makes `name` the current ledger, replaying its log if it isn't open yet.
+    /// A ledger without a log starts out empty.
+    pub fn select(&mut self, name: &str) -> io::Result<()> {
+        if !self.ledgers.contains_key(name) {
+            let path = self.log_path(name)?;
+            let mut accounts = Accounts::new();
+            if let Some(on_create) = &self.on_create {
+                on_create(&mut accounts);
+            }
+            if let Some(from) = migrations::migrate_log(&path, false)? {
+                info!(ledger = name, from, "migrated ledger to the current format");
+            }
+            if fs::exists(&path)? {
+                let applied = storage::replay(&mut accounts, LogReader::open(&path)?)?;
+                info!(ledger = name, applied, "replayed ledger");
+            }
+            accounts.set_metadata(LedgerMetadata::load(metadata::path_for(&path))?);
+            if let Some(on_open) = &self.on_open {
+                on_open(&mut accounts);
+            }
+            fs::create_dir_all(&self.dir)?;
+            let store = FileStore::open(&path)?;
+            self.ledgers.insert(name.to_string(), (accounts, store));
+        }
+        self.current = Some(name.to_string());
+        Ok(())
+    }
+
+    /// The name of the current ledger, if one was selected
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    /// The current ledger, if one was selected
+    pub fn ledger(&mut self) -> Option<&mut Accounts> {
+        let current = self.current.as_ref()?;
+        self.ledgers.get_mut(current).map(|(accounts, _)| accounts)
+    }
+
+    /// Appends `txs` to the log of the current ledger, recording its
+    /// [`Accounts::principal`] as their actor
+    /// # Errors
+    /// Writing failed, or no ledger was selected
+    pub fn persist(&self, txs: &[Tx]) -> io::Result<()> {
+        let (accounts, store) = self
+            .current
+            .as_ref()
+            .and_then(|current| self.ledgers.get(current))
+            .ok_or_else(|| io::Error::other("no ledger selected"))?;
+        store.write_as(txs, accounts.principal())
+    }
+}
+
+impl fmt::Debug for LedgerManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LedgerManager")
+            .field("dir", &self.dir)
+            .field("ledgers", &self.ledgers)
+            .field("current", &self.current)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ledger_manager_keeps_ledgers_apart() {
+        let dir = env::temp_dir().join(format!("crabbux-ledgers-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut manager = LedgerManager::new(&dir);
+        manager.select("personal").unwrap();
+        let tx = manager.ledger().unwrap().deposit("ALICE", 10).unwrap();
+        manager.persist(&[tx]).unwrap();
+        manager.select("business").unwrap();
+        let tx = manager.ledger().unwrap().deposit("ALICE", 99).unwrap();
+        manager.persist(&[tx]).unwrap();
+
+        //act
+        let mut reopened = LedgerManager::new(&dir);
+        reopened.select("personal").unwrap();
+        let balance = *reopened.ledger().unwrap().balance_of("ALICE").unwrap();
+        let names = reopened.names().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(balance, 10);
+        assert_eq!(names, vec!["business", "personal"]);
+        assert_eq!(reopened.current(), Some("personal"));
+        assert!(reopened.select("../escape").is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! Settings the owners make for their accounts, like whom they may send to.
+//!
+//! Unlike balances they aren't derived from the tx log, so they are kept next to it in
+//! `<log>.meta`, see [`path_for`].
+
+use crate::{checksum, goals::Goal, migrations};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The settings of one account
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountMetadata {
+    /// If set, the only recipients the account may send to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowlist: Option<BTreeSet<String>>,
+    /// Recipients the account may never send to, even if they are allowlisted
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub blocklist: BTreeSet<String>,
+    /// Who may operate the account, see [`crate::accounts::Accounts::add_owner`]; anybody if empty
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub owners: BTreeSet<String>,
+    /// Whether the account is archived, see [`crate::accounts::Accounts::archive`]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archived: bool,
+    /// The savings goals of the account by name, see [`crate::goals`]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub goals: BTreeMap<String, Goal>,
+}
+
+impl AccountMetadata {
+    /// Returns `true` if the account may send to `recipient`
+    pub fn may_send_to(&self, recipient: &str) -> bool {
+        !self.blocklist.contains(recipient)
+            && self
+                .allowlist
+                .as_ref()
+                .is_none_or(|allowlist| allowlist.contains(recipient))
+    }
+
+    /// Returns `true` if `principal` may operate the account
+    pub fn may_operate(&self, principal: &str) -> bool {
+        self.owners.is_empty() || self.owners.contains(principal)
+    }
+
+    /// Adds `recipient` to the allowlist, starting one if there is none
+    pub fn allow(&mut self, recipient: &str) {
+        self.allowlist
+            .get_or_insert_with(BTreeSet::new)
+            .insert(recipient.to_string());
+    }
+
+    /// Removes `recipient` from the allowlist. The allowlist stays in force even once it is empty.
+    pub fn disallow(&mut self, recipient: &str) {
+        if let Some(allowlist) = &mut self.allowlist {
+            allowlist.remove(recipient);
+        }
+    }
+
+    pub fn block(&mut self, recipient: &str) {
+        self.blocklist.insert(recipient.to_string());
+    }
+
+    pub fn unblock(&mut self, recipient: &str) {
+        self.blocklist.remove(recipient);
+    }
+
+    /// Returns `true` if nothing was set, so the account needn't be stored
+    pub fn is_empty(&self) -> bool {
+        self == &AccountMetadata::default()
+    }
+}
+
+/// The [`AccountMetadata`] of every account of a ledger, stored as JSON
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedgerMetadata {
+    accounts: BTreeMap<String, AccountMetadata>,
+}
+
+impl LedgerMetadata {
+    /// Reads the metadata at `path`; a missing file is empty metadata
+    /// # Errors
+    /// Reading failed, the file doesn't match its checksum, see [`checksum::read`], or a newer
+    /// crabbux wrote it, see [`migrations::upgrade`]
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        match migrations::load(path.as_ref(), migrations::METADATA) {
+            Ok(metadata) => Ok(metadata),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(LedgerMetadata::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the metadata and its checksum to `path`, replacing it atomically
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        checksum::write(path, &migrations::to_json(self, migrations::METADATA)?)
+    }
+
+    /// The settings of `account`, if any were made
+    pub fn get(&self, account: &str) -> Option<&AccountMetadata> {
+        self.accounts.get(account)
+    }
+
+    /// The settings of `account`, to change them
+    pub fn entry(&mut self, account: &str) -> &mut AccountMetadata {
+        self.accounts.entry(account.to_string()).or_default()
+    }
+
+    /// The accounts whose settings differ in `other`, in name order
+    pub fn changed(&self, other: &LedgerMetadata) -> Vec<String> {
+        fn settings<'a>(
+            metadata: &'a LedgerMetadata,
+            account: &str,
+        ) -> Option<&'a AccountMetadata> {
+            metadata
+                .get(account)
+                .filter(|settings| !settings.is_empty())
+        }
+        let mut changed: Vec<String> = self
+            .iter()
+            .chain(other.iter())
+            .map(|(account, _)| account)
+            .filter(|account| settings(self, account) != settings(other, account))
+            .map(str::to_string)
+            .collect();
+        changed.sort();
+        changed.dedup();
+        changed
+    }
+
+    /// The savings goals of every account that has some, by account and goal name
+    pub fn goals(&self) -> BTreeMap<String, BTreeMap<String, Goal>> {
+        self.iter()
+            .filter(|(_, metadata)| !metadata.goals.is_empty())
+            .map(|(account, metadata)| (account.to_string(), metadata.goals.clone()))
+            .collect()
+    }
+
+    /// Iterates over the accounts with settings in name order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &AccountMetadata)> {
+        self.accounts
+            .iter()
+            .filter(|(_, metadata)| !metadata.is_empty())
+            .map(|(account, metadata)| (account.as_str(), metadata))
+    }
+}
+
+/// Where the metadata of the ledger persisted to `log` is kept: `<log>.meta`
+pub fn path_for(log: impl AsRef<Path>) -> PathBuf {
+    let mut path = OsString::from(log.as_ref());
+    path.push(".meta");
+    path.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_metadata_may_send_to_works() {
+        let mut metadata = AccountMetadata::default();
+        let open = metadata.may_send_to("BOB");
+
+        //act
+        metadata.allow("BOB");
+        metadata.allow("CAROL");
+        metadata.block("CAROL");
+
+        assert!(open);
+        assert!(metadata.may_send_to("BOB"));
+        assert!(!metadata.may_send_to("CAROL"));
+        assert!(!metadata.may_send_to("DAVE"));
+        metadata.disallow("BOB");
+        assert!(!metadata.may_send_to("BOB"));
+        assert_eq!(path_for("ledger.jsonl"), PathBuf::from("ledger.jsonl.meta"));
+    }
+}
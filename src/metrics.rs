@@ -0,0 +1,220 @@
+use crate::{errors::ApplicationError, tx::Tx};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds (in seconds) of the latency histogram buckets
+pub const LATENCY_BUCKETS: [f64; 8] = [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5];
+
+#[derive(Default)]
+struct Histogram {
+    /// One counter per bucket in [`LATENCY_BUCKETS`], not cumulative
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|le| seconds <= *le) {
+            self.buckets[i] += 1;
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    txs: BTreeMap<&'static str, u64>,
+    errors: BTreeMap<&'static str, u64>,
+    value_moved: u128,
+    accounts: usize,
+    latencies: BTreeMap<String, Histogram>,
+}
+
+/// Operational counters for the ledger, rendered in the Prometheus text format.
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+impl Metrics {
+    /// Creates a set of metrics where everything is zero
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Counts a committed transaction and the value it moved
+    pub fn record_tx(&self, tx: &Tx) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner.txs.entry(tx.kind()).or_default() += 1;
+        inner.value_moved = inner.value_moved.saturating_add(tx.amount() as u128);
+    }
+
+    /// Counts a failed operation by its error variant
+    pub fn record_error(&self, error: &ApplicationError) {
+        *self
+            .inner
+            .lock()
+            .unwrap()
+            .errors
+            .entry(error.kind())
+            .or_default() += 1;
+    }
+
+    /// Updates the number of accounts in the ledger
+    pub fn set_accounts(&self, accounts: usize) {
+        self.inner.lock().unwrap().accounts = accounts;
+    }
+
+    /// Counts a newly created account
+    pub fn account_created(&self) {
+        self.inner.lock().unwrap().accounts += 1;
+    }
+
+    /// Records how long handling a `method` took
+    pub fn observe_latency(&self, method: &str, elapsed: Duration) {
+        self.inner
+            .lock()
+            .unwrap()
+            .latencies
+            .entry(method.to_string())
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let inner = self.inner.lock().unwrap();
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "crabbux_transactions_total",
+            "counter",
+            "Committed transactions by type",
+        );
+        for (kind, count) in &inner.txs {
+            let _ = writeln!(
+                out,
+                "crabbux_transactions_total{{type=\"{}\"}} {}",
+                kind, count
+            );
+        }
+        header(
+            &mut out,
+            "crabbux_errors_total",
+            "counter",
+            "Failed operations by error",
+        );
+        for (kind, count) in &inner.errors {
+            let _ = writeln!(out, "crabbux_errors_total{{error=\"{}\"}} {}", kind, count);
+        }
+        header(
+            &mut out,
+            "crabbux_value_moved_total",
+            "counter",
+            "Sum of all committed transaction amounts",
+        );
+        let _ = writeln!(out, "crabbux_value_moved_total {}", inner.value_moved);
+        header(
+            &mut out,
+            "crabbux_accounts",
+            "gauge",
+            "Number of accounts in the ledger",
+        );
+        let _ = writeln!(out, "crabbux_accounts {}", inner.accounts);
+
+        let name = "crabbux_request_duration_seconds";
+        header(
+            &mut out,
+            name,
+            "histogram",
+            "Time spent handling requests by method",
+        );
+        for (method, histogram) in &inner.latencies {
+            let mut cumulative = 0;
+            for (le, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{method=\"{}\",le=\"{}\"}} {}",
+                    name, method, le, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_bucket{{method=\"{}\",le=\"+Inf\"}} {}",
+                name, method, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "{}_sum{{method=\"{}\"}} {}",
+                name, method, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "{}_count{{method=\"{}\"}} {}",
+                name, method, histogram.count
+            );
+        }
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_render_counters() {
+        let metrics = Metrics::new();
+
+        metrics.record_tx(&Tx::Deposit {
+            account: "ALICE".into(),
+            amount: 100,
+        });
+        metrics.record_tx(&Tx::Withdraw {
+            account: "ALICE".into(),
+            amount: 10,
+            recipient: None,
+        });
+        metrics.record_error(&ApplicationError::NotFound("BOB".to_string()));
+        metrics.set_accounts(1);
+
+        let out = metrics.render();
+        assert!(out.contains("crabbux_transactions_total{type=\"deposit\"} 1\n"));
+        assert!(out.contains("crabbux_transactions_total{type=\"withdraw\"} 1\n"));
+        assert!(out.contains("crabbux_errors_total{error=\"not_found\"} 1\n"));
+        assert!(out.contains("crabbux_value_moved_total 110\n"));
+        assert!(out.contains("crabbux_accounts 1\n"));
+    }
+
+    #[test]
+    fn test_metrics_latency_buckets_are_cumulative() {
+        let metrics = Metrics::new();
+
+        metrics.observe_latency("deposit", Duration::from_micros(50));
+        metrics.observe_latency("deposit", Duration::from_millis(20));
+        metrics.observe_latency("deposit", Duration::from_secs(2));
+
+        let out = metrics.render();
+        assert!(out.contains(
+            "crabbux_request_duration_seconds_bucket{method=\"deposit\",le=\"0.0001\"} 1\n"
+        ));
+        assert!(out.contains(
+            "crabbux_request_duration_seconds_bucket{method=\"deposit\",le=\"0.05\"} 2\n"
+        ));
+        assert!(out.contains(
+            "crabbux_request_duration_seconds_bucket{method=\"deposit\",le=\"+Inf\"} 3\n"
+        ));
+        assert!(out.contains("crabbux_request_duration_seconds_count{method=\"deposit\"} 3\n"));
+    }
+}
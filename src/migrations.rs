@@ -0,0 +1,264 @@
+//! Upgrading what older versions of crabbux persisted, so ledgers survive upgrading crabbux.
+//!
+//! Every persisted file carries the version of its format: snapshots, `<log>.meta`,
+//! `<log>.orders` and `<log>.rates` files and state exports in a `version` field, tx logs in a
+//! header line, see [`storage::LOG_VERSION`], and WALs in their first bytes, see
+//! [`crate::wal::Format`]. Files from before versions were embedded count as version 1.
+//!
+//! JSON documents are upgraded as they are loaded, by applying the [`Migration`]s from their
+//! version on; they are written in the current version the next time they are saved. Logs are
+//! appended to in place, so [`migrate_log`] rewrites them before they are opened for writing.
+
+use crate::{
+    checksum,
+    storage::{self, LogReader},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// One step upgrading a JSON document to version `to` from the version before
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub to: u32,
+    pub description: &'static str,
+    pub apply: fn(&mut Value),
+}
+
+/// The steps upgrading [`crate::snapshot::Snapshot`]s
+pub const SNAPSHOT: &[Migration] = &[Migration {
+    to: 2,
+    description: "embed the format version",
+    apply: unchanged,
+}];
+
+/// The steps upgrading [`crate::metadata::LedgerMetadata`]
+pub const METADATA: &[Migration] = &[Migration {
+    to: 2,
+    description: "move the settings of the accounts under `accounts`",
+    apply: nest_accounts,
+}];
+
+/// The steps upgrading [`crate::export::state::LedgerState`] documents, none so far
+pub const STATE: &[Migration] = &[];
+
+/// The steps upgrading [`crate::standing::StandingOrders`], none so far
+pub const ORDERS: &[Migration] = &[];
+
+/// The steps upgrading [`crate::rates::RateTable`]s, none so far
+pub const RATES: &[Migration] = &[];
+
+fn unchanged(_: &mut Value) {}
+
+fn nest_accounts(json: &mut Value) {
+    *json = json!({ "accounts": json.take() });
+}
+
+/// The version documents upgraded by `steps` are written in
+pub const fn current(steps: &[Migration]) -> u32 {
+    steps.len() as u32 + 1
+}
+
+/// Upgrades `json` by applying `steps` from its version on, and returns the version it had.
+/// # Errors
+/// [`io::ErrorKind::Unsupported`] if a newer crabbux wrote it, and
+/// [`io::ErrorKind::InvalidData`] if it isn't an object with a valid version
+pub fn upgrade(json: &mut Value, steps: &[Migration]) -> io::Result<u32> {
+    let version = match json.as_object_mut() {
+        Some(object) => match object.remove("version") {
+            None => 1,
+            Some(version) => version
+                .as_u64()
+                .and_then(|version| u32::try_from(version).ok())
+                .filter(|&version| version > 0)
+                .ok_or_else(|| invalid(format!("invalid format version {}", version)))?,
+        },
+        None => return Err(invalid("expected a JSON object".to_string())),
+    };
+    check_supported(version, current(steps))?;
+    for step in &steps[version as usize - 1..] {
+        (step.apply)(json);
+    }
+    Ok(version)
+}
+
+/// Refuses files of a format `version` newer than the `current` one
+/// # Errors
+/// [`io::ErrorKind::Unsupported`] naming both versions
+pub fn check_supported(version: u32, current: u32) -> io::Result<()> {
+    if version > current {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "format version {} is newer than this crabbux supports ({})",
+                version, current
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Reads a document of the kind `steps` upgrades from `json`
+/// # Errors
+/// See [`upgrade`]; also [`io::ErrorKind::InvalidData`] if it doesn't parse
+pub fn from_json<T: DeserializeOwned>(json: &[u8], steps: &[Migration]) -> io::Result<T> {
+    let mut json: Value = serde_json::from_slice(json)?;
+    upgrade(&mut json, steps)?;
+    Ok(serde_json::from_value(json)?)
+}
+
+/// Writes `document` as JSON in the current version of `steps`
+pub fn to_json(document: &impl Serialize, steps: &[Migration]) -> io::Result<Vec<u8>> {
+    let mut json = serde_json::to_value(document)?;
+    let object = json
+        .as_object_mut()
+        .ok_or_else(|| invalid("expected a JSON object".to_string()))?;
+    object.insert("version".to_string(), current(steps).into());
+    Ok(serde_json::to_vec_pretty(&json)?)
+}
+
+/// Reads the document at `path` like [`from_json`], checking its checksum first, see
+/// [`checksum::read`]. A document that doesn't parse is [`checksum::corrupt`].
+pub fn load<T: DeserializeOwned>(path: &Path, steps: &[Migration]) -> io::Result<T> {
+    let json = checksum::read(path)?;
+    from_json(&json, steps).map_err(|e| match e.kind() {
+        io::ErrorKind::InvalidData => checksum::corrupt(path, e),
+        _ => e,
+    })
+}
+
+/// Rewrites the tx log at `path`, or WAL if `wal` is set, in the current format if it's in an
+/// older one, keeping the original as `<path>.v<version>`. Returns the version it had, or
+/// `None` if it's current or doesn't exist.
+/// # Errors
+/// The log can't be read or rewritten, or a newer crabbux wrote it
+pub fn migrate_log(path: &Path, wal: bool) -> io::Result<Option<u32>> {
+    if !fs::exists(path)? {
+        return Ok(None);
+    }
+    if wal {
+        return migrate_wal(path);
+    }
+    let version = storage::log_version(path)?;
+    check_supported(version, storage::LOG_VERSION)?;
+    if version == storage::LOG_VERSION {
+        return Ok(None);
+    }
+    let entries = LogReader::open(path)?.collect::<io::Result<Vec<_>>>()?;
+    rewrite(path, &storage::encode_log(&entries)?, version)?;
+    Ok(Some(version))
+}
+
+#[cfg(feature = "native")]
+fn migrate_wal(path: &Path) -> io::Result<Option<u32>> {
+    use crate::wal::{self, Format, MmapWal};
+
+    let old = MmapWal::open(path)?;
+    if old.format() == Format::V3 {
+        return Ok(None);
+    }
+    let entries = old
+        .iter()
+        .map(|entry| entry.map(|entry| entry.to_entry()))
+        .collect::<io::Result<Vec<_>>>()?;
+    let version = old.format().version();
+    drop(old);
+    rewrite(path, &wal::encode_wal(&entries)?, version)?;
+    Ok(Some(version))
+}
+
+#[cfg(not(feature = "native"))]
+fn migrate_wal(_: &Path) -> io::Result<Option<u32>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "WALs need the native feature",
+    ))
+}
+
+/// Where [`migrate_log`] keeps the original of `log` in format `version`: `<log>.v<version>`
+pub fn original_path(log: &Path, version: u32) -> PathBuf {
+    let mut path = OsString::from(log);
+    path.push(format!(".v{}", version));
+    path.into()
+}
+
+/// Replaces `path` with `contents` through a temporary file, after copying it to
+/// [`original_path`]
+fn rewrite(path: &Path, contents: &[u8], version: u32) -> io::Result<()> {
+    fs::copy(path, original_path(path, version))?;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".migrating");
+    let file = File::create(&tmp)?;
+    (&file).write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(tmp, path)
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{metadata::LedgerMetadata, tx::Tx};
+    use std::env;
+
+    #[test]
+    fn test_migrations_upgrade_documents_and_logs() {
+        let path = env::temp_dir().join(format!("crabbux-{}-migrate.jsonl", std::process::id()));
+        let deposit = Tx::Deposit {
+            account: "ALICE".into(),
+            amount: 100,
+        };
+        let legacy = format!(
+            "{}\n{}\n",
+            serde_json::to_string(&deposit).unwrap(),
+            r#"{"timestamp":5,"tx":{"Withdraw":{"account":"ALICE","amount":30}}}"#
+        );
+        fs::write(&path, &legacy).unwrap();
+
+        //act
+        let metadata: LedgerMetadata =
+            from_json(br#"{"ALICE": {"archived": true}}"#, METADATA).unwrap();
+        let newer = from_json::<LedgerMetadata>(br#"{"version": 9, "accounts": {}}"#, METADATA);
+        let migrated = migrate_log(&path, false).unwrap();
+        let again = migrate_log(&path, false).unwrap();
+        let entries: Vec<_> = LogReader::open(&path)
+            .unwrap()
+            .map(|entry| entry.unwrap().tx)
+            .collect();
+        let log = fs::read_to_string(&path).unwrap();
+        let original = fs::read_to_string(original_path(&path, 1)).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(original_path(&path, 1)).unwrap();
+
+        for (i, steps) in [SNAPSHOT, METADATA, STATE].into_iter().enumerate() {
+            for (j, step) in steps.iter().enumerate() {
+                assert_eq!(step.to as usize, j + 2, "steps {} out of order", i);
+            }
+        }
+        assert!(metadata.get("ALICE").unwrap().archived);
+        assert_eq!(newer.unwrap_err().kind(), io::ErrorKind::Unsupported);
+        let json: Value = serde_json::from_slice(&to_json(&metadata, METADATA).unwrap()).unwrap();
+        assert_eq!(json["version"], current(METADATA));
+        assert_eq!(migrated, Some(1));
+        assert_eq!(again, None);
+        assert_eq!(
+            entries,
+            vec![
+                deposit,
+                Tx::Withdraw {
+                    account: "ALICE".into(),
+                    amount: 30,
+                    recipient: None
+                }
+            ]
+        );
+        assert!(log.starts_with(&storage::header()));
+        assert_eq!(original, legacy);
+    }
+}
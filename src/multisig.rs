@@ -0,0 +1,82 @@
+//! M-of-N approval of large transfers, see [`crate::accounts::Accounts::set_multisig`].
+
+use crate::tx::Units;
+use serde::Serialize;
+use std::str::FromStr;
+
+/// Who has to approve the large transfers of an account.
+///
+/// Parsed from `<required> of <signer>,<signer>,... above <amount>`, e.g.
+/// `2 of ALICE,BOB,CAROL above 1000`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultisigPolicy {
+    /// The designated signers
+    pub signers: Vec<String>,
+    /// How many different signers must approve
+    pub required: usize,
+    /// Transfers of more than this need approval
+    pub above: Units,
+}
+
+impl MultisigPolicy {
+    /// Returns `true` if sending `amount` needs approval
+    pub fn applies_to(&self, amount: Units) -> bool {
+        amount > self.above
+    }
+
+    /// Returns `true` if `signer` is one of the designated signers
+    pub fn is_signer(&self, signer: &str) -> bool {
+        self.signers.iter().any(|s| s == signer)
+    }
+}
+
+impl FromStr for MultisigPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let invalid = || format!("expected `<m> of <signers> above <amount>`, got {:?}", s);
+        let (required, rest) = s.split_once(" of ").ok_or_else(invalid)?;
+        let (signers, above) = rest.rsplit_once(" above ").ok_or_else(invalid)?;
+        let policy = MultisigPolicy {
+            signers: signers.split(',').map(|s| s.trim().to_string()).collect(),
+            required: required.trim().parse().map_err(|_| invalid())?,
+            above: above.trim().parse().map_err(|_| invalid())?,
+        };
+        if policy.required == 0 || policy.required > policy.signers.len() {
+            return Err(format!(
+                "{} of {} signers can never approve",
+                policy.required,
+                policy.signers.len()
+            ));
+        }
+        Ok(policy)
+    }
+}
+
+/// A transfer waiting for approval, see [`crate::accounts::Accounts::approve`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PendingTransfer {
+    pub id: u64,
+    pub sender: String,
+    pub recipient: String,
+    pub amount: Units,
+    /// The signers who approved so far, in order
+    pub approvals: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multisig_policy_parse_works() {
+        let policy: MultisigPolicy = "2 of ALICE, BOB,CAROL above 1000".parse().unwrap();
+
+        assert_eq!(policy.signers, vec!["ALICE", "BOB", "CAROL"]);
+        assert_eq!((policy.required, policy.above), (2, 1000));
+        assert!(policy.applies_to(1001) && !policy.applies_to(1000));
+        assert!(policy.is_signer("BOB") && !policy.is_signer("DAVE"));
+        assert!("3 of ALICE,BOB above 5".parse::<MultisigPolicy>().is_err());
+        assert!("2 of ALICE,BOB".parse::<MultisigPolicy>().is_err());
+    }
+}
@@ -0,0 +1,284 @@
+//! Settling what accounts owe each other in a batch: instead of paying every obligation on its
+//! own, each account's obligations are netted to a single position, and only the positions are
+//! paid, like a clearing house does at the end of a day.
+//!
+//! [`net_positions`] nets a set of [`Obligation`]s, [`transfers`] finds the transfers settling
+//! the positions and [`settle`] makes them on a ledger as one unit, see
+//! [`Accounts::send_multi`], reporting what it did as a [`Settlement`]. `crabbux net` settles
+//! the obligations of a JSON file on the persisted ledger.
+
+use crate::accounts::Accounts;
+use crate::errors::ApplicationError;
+use crate::tx::{Tx, Units};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// `from` owes `to` `amount`, read in the smallest unit or as written, see
+/// [`crate::amount::deserialize_units`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Obligation {
+    pub from: String,
+    pub to: String,
+    #[serde(deserialize_with = "crate::amount::deserialize_units")]
+    pub amount: Units,
+}
+
+/// A payment settling net positions: `from` pays `to` `amount`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transfer {
+    pub from: String,
+    pub to: String,
+    pub amount: Units,
+}
+
+/// What each account is owed in total minus what it owes, for the accounts where that isn't
+/// zero. The positions add up to zero.
+/// # Errors
+/// [`ApplicationError::OverFunded`] if a position doesn't fit
+pub fn net_positions(
+    obligations: &[Obligation],
+) -> Result<BTreeMap<String, i128>, ApplicationError> {
+    let mut positions: BTreeMap<String, i128> = BTreeMap::new();
+    for obligation in obligations.iter().filter(|o| o.from != o.to) {
+        let amount = i128::try_from(obligation.amount as u128)
+            .map_err(|_| ApplicationError::OverFunded(obligation.to.clone(), obligation.amount))?;
+        for (account, change) in [(&obligation.from, -amount), (&obligation.to, amount)] {
+            let position = positions.entry(account.clone()).or_default();
+            *position = position
+                .checked_add(change)
+                .ok_or_else(|| ApplicationError::OverFunded(account.clone(), obligation.amount))?;
+        }
+    }
+    positions.retain(|_, position| *position != 0);
+    Ok(positions)
+}
+
+/// The transfers settling `positions`, see [`net_positions`], from the accounts owing to those
+/// owed. A debtor and a creditor whose positions cancel out are paired first, then the largest
+/// debtor pays the largest creditor until every position is settled.
+///
+/// That takes at most one transfer less than there are positions. Finding the fewest in every
+/// case means splitting the positions into as many groups adding up to zero as possible, which
+/// takes exponential time, so the result can be a few transfers more than that.
+/// # Errors
+/// [`ApplicationError::OverFunded`] if a position doesn't fit in [`Units`]
+pub fn transfers(positions: &BTreeMap<String, i128>) -> Result<Vec<Transfer>, ApplicationError> {
+    let mut debtors: Vec<(&str, Units)> = vec![];
+    let mut creditors: Vec<(&str, Units)> = vec![];
+    for (account, &position) in positions {
+        let amount = Units::try_from(position.unsigned_abs())
+            .map_err(|_| ApplicationError::OverFunded(account.clone(), Units::MAX))?;
+        match position {
+            ..0 => debtors.push((account, amount)),
+            _ => creditors.push((account, amount)),
+        }
+    }
+    let mut transfers = vec![];
+    let mut pay = |from: &str, to: &str, amount: Units| {
+        transfers.push(Transfer {
+            from: from.to_string(),
+            to: to.to_string(),
+            amount,
+        })
+    };
+    debtors.retain(|&(debtor, owed)| {
+        let Some(index) = creditors.iter().position(|&(_, due)| due == owed) else {
+            return true;
+        };
+        pay(debtor, creditors.remove(index).0, owed);
+        false
+    });
+    let largest_first = |a: &(&str, Units), b: &(&str, Units)| b.1.cmp(&a.1).then(a.0.cmp(b.0));
+    loop {
+        debtors.sort_by(largest_first);
+        creditors.sort_by(largest_first);
+        let (Some(debtor), Some(creditor)) = (debtors.first_mut(), creditors.first_mut()) else {
+            break;
+        };
+        let amount = debtor.1.min(creditor.1);
+        debtor.1 -= amount;
+        creditor.1 -= amount;
+        pay(debtor.0, creditor.0, amount);
+        debtors.retain(|&(_, owed)| owed > 0);
+        creditors.retain(|&(_, due)| due > 0);
+    }
+    Ok(transfers)
+}
+
+/// What [`settle`] did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settlement {
+    /// How many obligations were settled
+    pub obligations: usize,
+    /// What they added up to, at most [`u128::MAX`]
+    pub gross: u128,
+    /// The net position of each account, see [`net_positions`]
+    pub positions: BTreeMap<String, i128>,
+    /// The transfers settling them, see [`transfers`]
+    pub transfers: Vec<Transfer>,
+    /// The committed transactions
+    pub txs: Vec<Tx>,
+}
+
+impl Settlement {
+    /// What the transfers added up to, never more than [`Settlement::gross`]
+    pub fn net(&self) -> u128 {
+        self.transfers
+            .iter()
+            .map(|t| t.amount as u128)
+            .fold(0, u128::saturating_add)
+    }
+}
+
+/// A summary line, then a line per transfer and one per net position
+impl fmt::Display for Settlement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "settled {} obligations of {} with {} transfers of {}",
+            self.obligations,
+            self.gross,
+            self.transfers.len(),
+            self.net()
+        )?;
+        for transfer in &self.transfers {
+            writeln!(
+                f,
+                "  {} pays {} {}",
+                transfer.from, transfer.to, transfer.amount
+            )?;
+        }
+        for (account, position) in &self.positions {
+            writeln!(f, "{} {:+}", account, position)?;
+        }
+        Ok(())
+    }
+}
+
+/// Settles `obligations` on `ledger` by their net positions, all of them or none, see
+/// [`Accounts::send_multi`]
+/// # Errors
+/// A position doesn't fit, see [`net_positions`], or a transfer would fail, see
+/// [`Accounts::send`]
+pub fn settle(
+    ledger: &mut Accounts,
+    obligations: &[Obligation],
+) -> Result<Settlement, ApplicationError> {
+    let positions = net_positions(obligations)?;
+    let transfers = transfers(&positions)?;
+    let legs: Vec<(&str, &str, Units)> = transfers
+        .iter()
+        .map(|t| (t.from.as_str(), t.to.as_str(), t.amount))
+        .collect();
+    let txs = ledger.send_multi(&legs)?;
+    Ok(Settlement {
+        obligations: obligations.len(),
+        gross: obligations
+            .iter()
+            .map(|o| o.amount as u128)
+            .fold(0, u128::saturating_add),
+        positions,
+        transfers,
+        txs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owes(from: &str, to: &str, amount: Units) -> Obligation {
+        Obligation {
+            from: from.to_string(),
+            to: to.to_string(),
+            amount,
+        }
+    }
+
+    #[test]
+    fn test_netting_settles_with_few_transfers() {
+        let obligations = [
+            owes("A", "B", 100),
+            owes("B", "C", 100),
+            owes("C", "A", 30),
+            owes("D", "E", 25),
+            owes("E", "D", 25),
+            owes("F", "G", 40),
+            owes("F", "F", 99),
+        ];
+        let mut ledger = Accounts::new();
+        ledger.deposit("A", 70).unwrap();
+        ledger.deposit("F", 10).unwrap();
+
+        //act
+        let positions = net_positions(&obligations).unwrap();
+        let transfers = transfers(&positions).unwrap();
+        let underfunded = settle(&mut ledger, &obligations);
+        ledger.deposit("F", 30).unwrap();
+        let settlement = settle(&mut ledger, &obligations).unwrap();
+
+        assert_eq!(
+            positions,
+            BTreeMap::from([
+                ("A".to_string(), -70),
+                ("C".to_string(), 70),
+                ("F".to_string(), -40),
+                ("G".to_string(), 40)
+            ])
+        );
+        assert_eq!(
+            transfers,
+            vec![
+                Transfer {
+                    from: "A".to_string(),
+                    to: "C".to_string(),
+                    amount: 70
+                },
+                Transfer {
+                    from: "F".to_string(),
+                    to: "G".to_string(),
+                    amount: 40
+                }
+            ]
+        );
+        assert!(matches!(
+            underfunded,
+            Err(ApplicationError::UnderFunded(_, 40))
+        ));
+        assert_eq!((settlement.gross, settlement.net()), (419, 110));
+        assert_eq!(settlement.txs.len(), 4);
+        assert_eq!(ledger.balance_of("C"), Ok(&70));
+        assert_eq!(ledger.balance_of("G"), Ok(&40));
+        assert!(settlement
+            .to_string()
+            .starts_with("settled 7 obligations of 419 with 2 transfers of 110\n  A pays C 70\n"));
+    }
+
+    #[test]
+    fn test_netting_transfers_settle_every_position() {
+        let positions = BTreeMap::from([
+            ("A".to_string(), -50),
+            ("B".to_string(), -30),
+            ("C".to_string(), -20),
+            ("D".to_string(), 45),
+            ("E".to_string(), 35),
+            ("F".to_string(), 20),
+        ]);
+
+        //act
+        let transfers = transfers(&positions).unwrap();
+
+        let mut settled = positions.clone();
+        for transfer in &transfers {
+            *settled.get_mut(&transfer.from).unwrap() += transfer.amount as i128;
+            *settled.get_mut(&transfer.to).unwrap() -= transfer.amount as i128;
+        }
+        assert!(settled.values().all(|&position| position == 0));
+        assert_eq!(transfers.len(), 4);
+        assert_eq!(
+            (transfers[0].from.as_str(), transfers[0].to.as_str()),
+            ("C", "F")
+        );
+    }
+}
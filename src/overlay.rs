@@ -0,0 +1,331 @@
+//! A hash map that is cheap to copy, for the per-account state of a ledger that
+//! [`crate::accounts::Accounts::fork`] shares with its forks.
+//!
+//! Copying an [`Overlay`] shares its entries instead of copying them. A copy that is changed
+//! while they are shared keeps the changed entries on top of the shared ones, so trying an
+//! operation on a fork costs as much as the entries it touches, not as much as the ledger.
+
+use hashbrown::HashMap;
+use std::borrow::Borrow;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// A hash map whose copies share their entries until they are changed, see [`crate::overlay`]
+pub struct Overlay<K, V> {
+    base: Arc<HashMap<K, V>>,
+    /// The entries changed while `base` is shared, `None` for removed ones
+    changes: HashMap<K, Option<V>>,
+    len: usize,
+}
+
+impl<K, V> Default for Overlay<K, V> {
+    fn default() -> Self {
+        Overlay {
+            base: Default::default(),
+            changes: HashMap::new(),
+            len: 0,
+        }
+    }
+}
+
+/// Shares the entries, and copies only those changed since they were shared
+impl<K: Clone, V: Clone> Clone for Overlay<K, V> {
+    fn clone(&self) -> Self {
+        Overlay {
+            base: self.base.clone(),
+            changes: self.changes.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<K: Eq + Hash + fmt::Debug, V: fmt::Debug> fmt::Debug for Overlay<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Eq + Hash, V> Overlay<K, V> {
+    /// An empty map with room for at least `capacity` entries before reallocating
+    pub fn with_capacity(capacity: usize) -> Self {
+        Overlay {
+            base: Arc::new(HashMap::with_capacity(capacity)),
+            ..Default::default()
+        }
+    }
+
+    /// The number of entries
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no entries
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of entries that fit without reallocating, while the entries aren't shared
+    pub fn capacity(&self) -> usize {
+        self.base.capacity()
+    }
+
+    /// The entry of `key`, if there is one
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.changes.get_key_value(key) {
+            Some((key, value)) => Some((key, value.as_ref()?)),
+            None => self.base.get_key_value(key),
+        }
+    }
+
+    /// The value of `key`, if there is one
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_key_value(key).map(|(_, value)| value)
+    }
+
+    /// Returns `true` if there is an entry for `key`
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_key_value(key).is_some()
+    }
+
+    /// Iterates over the entries in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let unchanged = self.base.iter();
+        let unchanged = unchanged.filter(|(key, _)| !self.changes.contains_key(*key));
+        let changed = self.changes.iter();
+        unchanged.chain(changed.filter_map(|(key, value)| Some((key, value.as_ref()?))))
+    }
+
+    /// Iterates over the keys in no particular order
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// Iterates over the values in no particular order
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Overlay<K, V> {
+    /// Takes the entries back once they aren't shared anymore, and returns whether they are
+    /// this map's own
+    fn own(&mut self) -> bool {
+        let Some(base) = Arc::get_mut(&mut self.base) else {
+            return false;
+        };
+        for (key, value) in self.changes.drain() {
+            match value {
+                Some(value) => base.insert(key, value),
+                None => base.remove(&key),
+            };
+        }
+        true
+    }
+
+    /// Takes the changed entries back once the entries aren't shared anymore, e.g. after a
+    /// copy took the place of the map it was copied from, so later copies don't copy them again
+    pub fn compact(&mut self) {
+        self.own();
+    }
+
+    /// The entries, once [`Overlay::own`] found them not shared
+    fn base_mut(&mut self) -> &mut HashMap<K, V> {
+        Arc::get_mut(&mut self.base).expect("the entries aren't shared")
+    }
+
+    /// Makes room for at least `additional` more entries
+    pub fn reserve(&mut self, additional: usize) {
+        if self.own() {
+            self.base_mut().reserve(additional);
+        } else {
+            self.changes.reserve(additional);
+        }
+    }
+
+    /// The entry of `key` to change, if there is one
+    pub fn get_key_value_mut<Q>(&mut self, key: &Q) -> Option<(&K, &mut V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.own() {
+            return self.base_mut().get_key_value_mut(key);
+        }
+        if !self.changes.contains_key(key) {
+            let (key, value) = self.base.get_key_value(key)?;
+            self.changes.insert(key.clone(), Some(value.clone()));
+        }
+        let (key, value) = self.changes.get_key_value_mut(key)?;
+        Some((key, value.as_mut()?))
+    }
+
+    /// The value of `key` to change, if there is one
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_key_value_mut(key).map(|(_, value)| value)
+    }
+
+    /// The value of `key` to change, inserting the default value if there is none
+    pub fn get_or_insert_default<Q>(&mut self, key: &Q) -> &mut V
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+        V: Default,
+    {
+        if !self.contains_key(key) {
+            self.insert(key.to_owned(), V::default());
+        }
+        self.get_mut(key).expect("the entry was just inserted")
+    }
+
+    /// Sets the value of `key` and returns the one it replaced, if any
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let replaced = if self.own() {
+            self.base_mut().insert(key, value)
+        } else {
+            let shared = self.base.get(&key).cloned();
+            let changed = self.changes.insert(key, Some(value));
+            changed.unwrap_or(shared)
+        };
+        if replaced.is_none() {
+            self.len += 1;
+        }
+        replaced
+    }
+
+    /// Removes the entry of `key` and returns it, if there was one
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let removed = if self.own() {
+            self.base_mut().remove_entry(key)
+        } else {
+            let key = self.get_key_value(key)?.0.clone();
+            let value = match self.changes.remove(&key) {
+                Some(changed) => changed?,
+                None => self.base.get(&key)?.clone(),
+            };
+            if self.base.contains_key(&key) {
+                self.changes.insert(key.clone(), None);
+            }
+            Some((key, value))
+        };
+        if removed.is_some() {
+            self.len -= 1;
+        }
+        removed
+    }
+
+    /// Removes the entry of `key` and returns its value, if there was one
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Extend<(K, V)> for Overlay<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        for (key, value) in entries {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> FromIterator<(K, V)> for Overlay<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(entries: I) -> Self {
+        let mut overlay = Overlay::default();
+        overlay.extend(entries);
+        overlay
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlay_copies_change_independently() {
+        let mut original: Overlay<String, u32> = [("A".to_string(), 1), ("B".to_string(), 2)]
+            .into_iter()
+            .collect();
+
+        //act
+        let mut copy = original.clone();
+        *copy.get_mut("A").unwrap() += 10;
+        copy.insert("C".to_string(), 3);
+        let removed = copy.remove("B");
+        original.insert("D".to_string(), 4);
+        let copy_of_copy = copy.clone();
+
+        let sorted = |overlay: &Overlay<String, u32>| {
+            let mut entries: Vec<_> = overlay.iter().map(|(k, v)| (k.clone(), *v)).collect();
+            entries.sort();
+            entries
+        };
+        assert_eq!(removed, Some(2));
+        assert_eq!(
+            sorted(&copy),
+            vec![("A".to_string(), 11), ("C".to_string(), 3)]
+        );
+        assert_eq!(
+            sorted(&original),
+            vec![
+                ("A".to_string(), 1),
+                ("B".to_string(), 2),
+                ("D".to_string(), 4)
+            ]
+        );
+        assert_eq!((copy.len(), original.len()), (2, 3));
+        assert!(copy.get("B").is_none() && copy_of_copy.get("B").is_none());
+        assert_eq!(copy_of_copy.get("A"), Some(&11));
+        drop(original);
+        drop(copy_of_copy);
+        copy.insert("B".to_string(), 5);
+        assert_eq!(
+            sorted(&copy),
+            vec![
+                ("A".to_string(), 11),
+                ("B".to_string(), 5),
+                ("C".to_string(), 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overlay_compact_takes_back_the_changes_of_a_copy() {
+        let original: Overlay<String, u32> = [("A".to_string(), 1)].into_iter().collect();
+        let mut copy = original.clone();
+        copy.insert("B".to_string(), 2);
+        drop(original);
+
+        //act
+        copy.compact();
+
+        assert!(copy.changes.is_empty());
+        assert_eq!(
+            (copy.len(), copy.get("A"), copy.get("B")),
+            (2, Some(&1), Some(&2))
+        );
+    }
+}
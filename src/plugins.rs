@@ -0,0 +1,487 @@
+#[cfg(feature = "native")]
+use crate::client::RemoteLedger;
+use crate::{
+    accounts::Accounts,
+    amount::{self, NumberFormat},
+    clock::Timestamp,
+    goals::Goal,
+    i18n::{tr, Key},
+    tx::{Tx, Units},
+};
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// The ledger operations available to commands, whether the ledger is local or on a remote server.
+///
+/// This is the only access plugins get: they can't touch balances directly or register listeners.
+pub trait LedgerApi {
+    /// Returns the balance of the `signer` account
+    fn balance_of(&mut self, signer: &str) -> Result<Units, Box<dyn Error>>;
+    /// Returns all accounts and their balances
+    fn accounts(&mut self) -> Result<BTreeMap<String, Units>, Box<dyn Error>>;
+    /// Deposits `amount` into the `signer` account
+    fn deposit(&mut self, signer: &str, amount: Units) -> Result<Tx, Box<dyn Error>>;
+    /// Withdraws `amount` from the `signer` account
+    fn withdraw(&mut self, signer: &str, amount: Units) -> Result<Tx, Box<dyn Error>>;
+    /// Transfers `amount` from `sender` to `recipient`
+    fn send(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx), Box<dyn Error>>;
+    /// Sends even if the transfer looks suspicious, see [`Accounts::send_confirmed`]. Ledgers
+    /// without anomaly detection just send.
+    fn send_confirmed(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx), Box<dyn Error>> {
+        self.send(sender, recipient, amount)
+    }
+    /// Sends, spending promotional credit first and charging the fee, see [`Accounts::pay`].
+    /// Ledgers without either just send.
+    fn pay(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<Vec<Tx>, Box<dyn Error>> {
+        let (withdrawal, deposit) = self.send(sender, recipient, amount)?;
+        Ok(vec![withdrawal, deposit])
+    }
+    /// Like [`LedgerApi::pay`], see [`Accounts::pay_confirmed`]
+    fn pay_confirmed(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<Vec<Tx>, Box<dyn Error>> {
+        let (withdrawal, deposit) = self.send_confirmed(sender, recipient, amount)?;
+        Ok(vec![withdrawal, deposit])
+    }
+    /// Approves the pending transfer `id` as the logged-in user and returns its transactions if
+    /// that was the last approval needed, see [`Accounts::approve`]. Not every ledger supports
+    /// approvals.
+    fn approve(&mut self, id: u64) -> Result<Vec<Tx>, Box<dyn Error>> {
+        let _ = id;
+        Err("approvals aren't supported by this ledger".into())
+    }
+    /// Issues `amount` of new money into the `signer` account, see [`Accounts::mint`]. Not
+    /// every ledger supports minting.
+    fn mint(&mut self, signer: &str, amount: Units) -> Result<Tx, Box<dyn Error>> {
+        let _ = (signer, amount);
+        Err("minting isn't supported by this ledger".into())
+    }
+    /// Grants `signer` promotional credit until `expires`, see [`Accounts::grant_promo`]. Not
+    /// every ledger supports promotional credit.
+    fn grant_promo(
+        &mut self,
+        signer: &str,
+        amount: Units,
+        expires: Timestamp,
+    ) -> Result<Tx, Box<dyn Error>> {
+        let _ = (signer, amount, expires);
+        Err("promotional credit isn't supported by this ledger".into())
+    }
+    /// Takes `amount` out of circulation from the `signer` account, see [`Accounts::burn`].
+    /// Not every ledger supports burning.
+    fn burn(&mut self, signer: &str, amount: Units) -> Result<Tx, Box<dyn Error>> {
+        let _ = (signer, amount);
+        Err("burning isn't supported by this ledger".into())
+    }
+    /// The savings goals of every account that has some, by account and goal name, see
+    /// [`crate::goals`]. Ledgers without goals have none.
+    fn goals(&mut self) -> Result<BTreeMap<String, BTreeMap<String, Goal>>, Box<dyn Error>> {
+        Ok(BTreeMap::new())
+    }
+    /// Acts as `user` from now on, or as nobody with `None`, see [`Accounts::set_principal`].
+    /// Not every ledger supports sessions.
+    fn login(&mut self, user: Option<&str>) -> Result<(), Box<dyn Error>> {
+        let _ = user;
+        Err("sessions aren't supported by this ledger".into())
+    }
+    /// Who is logged in, see [`LedgerApi::login`]
+    fn whoami(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl LedgerApi for Accounts {
+    fn balance_of(&mut self, signer: &str) -> Result<Units, Box<dyn Error>> {
+        Ok(*Accounts::balance_of(self, signer)?)
+    }
+
+    fn accounts(&mut self) -> Result<BTreeMap<String, Units>, Box<dyn Error>> {
+        Ok(self.iter().map(|(k, v)| (k.to_string(), *v)).collect())
+    }
+
+    fn deposit(&mut self, signer: &str, amount: Units) -> Result<Tx, Box<dyn Error>> {
+        Ok(Accounts::deposit(self, signer, amount)?)
+    }
+
+    fn withdraw(&mut self, signer: &str, amount: Units) -> Result<Tx, Box<dyn Error>> {
+        Ok(Accounts::withdraw(self, signer, amount)?)
+    }
+
+    fn send(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx), Box<dyn Error>> {
+        Ok(Accounts::send(self, sender, recipient, amount)?)
+    }
+
+    fn send_confirmed(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx), Box<dyn Error>> {
+        Ok(Accounts::send_confirmed(self, sender, recipient, amount)?)
+    }
+
+    fn pay(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<Vec<Tx>, Box<dyn Error>> {
+        Ok(Accounts::pay(self, sender, recipient, amount)?)
+    }
+
+    fn pay_confirmed(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<Vec<Tx>, Box<dyn Error>> {
+        Ok(Accounts::pay_confirmed(self, sender, recipient, amount)?)
+    }
+
+    fn approve(&mut self, id: u64) -> Result<Vec<Tx>, Box<dyn Error>> {
+        Ok(Accounts::approve(self, id)?.unwrap_or_default())
+    }
+
+    fn mint(&mut self, signer: &str, amount: Units) -> Result<Tx, Box<dyn Error>> {
+        Ok(Accounts::mint(self, signer, amount)?)
+    }
+
+    fn burn(&mut self, signer: &str, amount: Units) -> Result<Tx, Box<dyn Error>> {
+        Ok(Accounts::burn(self, signer, amount)?)
+    }
+
+    fn grant_promo(
+        &mut self,
+        signer: &str,
+        amount: Units,
+        expires: Timestamp,
+    ) -> Result<Tx, Box<dyn Error>> {
+        Ok(Accounts::grant_promo(self, signer, amount, expires)?)
+    }
+
+    fn goals(&mut self) -> Result<BTreeMap<String, BTreeMap<String, Goal>>, Box<dyn Error>> {
+        Ok(self.metadata().goals())
+    }
+
+    fn login(&mut self, user: Option<&str>) -> Result<(), Box<dyn Error>> {
+        self.set_principal(user.map(str::to_string));
+        Ok(())
+    }
+
+    fn whoami(&self) -> Option<&str> {
+        self.principal()
+    }
+}
+
+#[cfg(feature = "native")]
+impl LedgerApi for RemoteLedger {
+    fn balance_of(&mut self, signer: &str) -> Result<Units, Box<dyn Error>> {
+        Ok(RemoteLedger::balance_of(self, signer)?)
+    }
+
+    fn accounts(&mut self) -> Result<BTreeMap<String, Units>, Box<dyn Error>> {
+        Ok(RemoteLedger::accounts(self)?)
+    }
+
+    fn deposit(&mut self, signer: &str, amount: Units) -> Result<Tx, Box<dyn Error>> {
+        Ok(RemoteLedger::deposit(self, signer, amount)?)
+    }
+
+    fn withdraw(&mut self, signer: &str, amount: Units) -> Result<Tx, Box<dyn Error>> {
+        Ok(RemoteLedger::withdraw(self, signer, amount)?)
+    }
+
+    fn send(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx), Box<dyn Error>> {
+        Ok(RemoteLedger::send(self, sender, recipient, amount)?)
+    }
+
+    fn send_confirmed(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx), Box<dyn Error>> {
+        Ok(RemoteLedger::send_confirmed(
+            self, sender, recipient, amount,
+        )?)
+    }
+
+    fn pay(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<Vec<Tx>, Box<dyn Error>> {
+        Ok(RemoteLedger::pay(self, sender, recipient, amount)?)
+    }
+
+    fn pay_confirmed(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<Vec<Tx>, Box<dyn Error>> {
+        Ok(RemoteLedger::pay_confirmed(
+            self, sender, recipient, amount,
+        )?)
+    }
+
+    fn approve(&mut self, id: u64) -> Result<Vec<Tx>, Box<dyn Error>> {
+        Ok(RemoteLedger::approve(self, id)?)
+    }
+
+    fn mint(&mut self, signer: &str, amount: Units) -> Result<Tx, Box<dyn Error>> {
+        Ok(RemoteLedger::mint(self, signer, amount)?)
+    }
+
+    fn burn(&mut self, signer: &str, amount: Units) -> Result<Tx, Box<dyn Error>> {
+        Ok(RemoteLedger::burn(self, signer, amount)?)
+    }
+
+    fn grant_promo(
+        &mut self,
+        signer: &str,
+        amount: Units,
+        expires: Timestamp,
+    ) -> Result<Tx, Box<dyn Error>> {
+        Ok(RemoteLedger::grant_promo(self, signer, amount, expires)?)
+    }
+
+    fn goals(&mut self) -> Result<BTreeMap<String, BTreeMap<String, Goal>>, Box<dyn Error>> {
+        Ok(RemoteLedger::goals(self)?)
+    }
+
+    // The server performs every call as the principal of its API key, see
+    // `HttpServer::with_api_keys`, so there is no one to log in as
+    fn login(&mut self, user: Option<&str>) -> Result<(), Box<dyn Error>> {
+        let _ = user;
+        Err(
+            "logging in isn't supported remotely: the server acts as its API key's principal"
+                .into(),
+        )
+    }
+}
+
+/// A set of custom CLI commands
+pub trait Plugin {
+    /// The commands this plugin handles
+    fn commands(&self) -> Vec<&'static str>;
+
+    /// Runs one of the plugin's `command`s and returns the transactions it committed.
+    /// `prompt` shows a label to the user and returns their input.
+    fn run(
+        &self,
+        command: &str,
+        ledger: &mut dyn LedgerApi,
+        prompt: &mut dyn FnMut(&str) -> String,
+    ) -> Result<Vec<Tx>, Box<dyn Error>>;
+}
+
+/// Looks up which [`Plugin`] handles a command
+#[derive(Default)]
+pub struct PluginRegistry {
+    plugins: Vec<Box<dyn Plugin>>,
+}
+
+impl PluginRegistry {
+    /// Creates a registry without plugins
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a plugin.
+    /// # Errors
+    /// One of its commands is already taken by another plugin; the error contains the command name
+    pub fn register(&mut self, plugin: Box<dyn Plugin>) -> Result<(), String> {
+        let commands = self.commands();
+        if let Some(taken) = plugin.commands().into_iter().find(|c| commands.contains(c)) {
+            return Err(taken.to_string());
+        }
+        self.plugins.push(plugin);
+        Ok(())
+    }
+
+    /// All commands provided by the registered plugins
+    pub fn commands(&self) -> Vec<&'static str> {
+        self.plugins.iter().flat_map(|p| p.commands()).collect()
+    }
+
+    /// Returns the plugin handling `command`, if any
+    pub fn find(&self, command: &str) -> Option<&dyn Plugin> {
+        self.plugins
+            .iter()
+            .find(|p| p.commands().contains(&command))
+            .map(|p| p.as_ref())
+    }
+}
+
+/// Bundled plugin: `balance` prints the balance of a single account
+pub struct BalancePlugin;
+
+impl Plugin for BalancePlugin {
+    fn commands(&self) -> Vec<&'static str> {
+        vec!["balance"]
+    }
+
+    fn run(
+        &self,
+        _command: &str,
+        ledger: &mut dyn LedgerApi,
+        prompt: &mut dyn FnMut(&str) -> String,
+    ) -> Result<Vec<Tx>, Box<dyn Error>> {
+        let account = prompt(&tr(Key::Account, &[]));
+        let balance = amount::format(
+            ledger.balance_of(&account)? as i128,
+            amount::decimals(),
+            NumberFormat::current(),
+        );
+        println!("{}", tr(Key::Balance, &[&account, &balance]));
+        Ok(vec![])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Doubles an account's balance
+    struct DoublePlugin;
+
+    impl Plugin for DoublePlugin {
+        fn commands(&self) -> Vec<&'static str> {
+            vec!["double", "twice"]
+        }
+
+        fn run(
+            &self,
+            _command: &str,
+            ledger: &mut dyn LedgerApi,
+            prompt: &mut dyn FnMut(&str) -> String,
+        ) -> Result<Vec<Tx>, Box<dyn Error>> {
+            let account = prompt("Account:");
+            let balance = ledger.balance_of(&account)?;
+            Ok(vec![ledger.deposit(&account, balance)?])
+        }
+    }
+
+    #[test]
+    fn test_plugin_registry_runs_plugin_commands() {
+        let mut registry = PluginRegistry::new();
+        assert!(registry.register(Box::new(DoublePlugin)).is_ok());
+        let mut ledger = Accounts::new();
+        ledger.deposit("ALICE", 21).unwrap();
+
+        //act
+        let plugin = registry.find("twice").unwrap();
+        let txs = plugin
+            .run("twice", &mut ledger, &mut |_| "ALICE".to_string())
+            .unwrap();
+
+        assert_eq!(txs.len(), 1);
+        assert_eq!(ledger.balance_of("ALICE"), Ok(&42));
+        assert!(registry.find("triple").is_none());
+    }
+
+    #[test]
+    fn test_plugin_registry_rejects_taken_commands() {
+        let mut registry = PluginRegistry::new();
+        assert!(registry.register(Box::new(DoublePlugin)).is_ok());
+
+        assert_eq!(
+            registry.register(Box::new(DoublePlugin)),
+            Err("double".to_string())
+        );
+        assert_eq!(registry.commands(), vec!["double", "twice"]);
+    }
+
+    /// A ledger with a savings goal, for [`run_commands`]
+    #[cfg(feature = "native")]
+    fn ledger_with_goal() -> Accounts {
+        let mut ledger = Accounts::new();
+        let goal = Goal {
+            target: 500,
+            deadline: None,
+        };
+        let goals = &mut ledger.metadata_mut().entry("ALICE").goals;
+        goals.insert("holiday".to_string(), goal);
+        ledger
+    }
+
+    /// Runs what the interactive commands do, and how each went
+    #[cfg(feature = "native")]
+    fn run_commands(ledger: &mut dyn LedgerApi) -> Vec<String> {
+        fn outcome<T: std::fmt::Debug>(result: Result<T, Box<dyn Error>>) -> String {
+            format!("{:?}", result.map_err(|e| e.to_string()))
+        }
+        // Spendable through 2100
+        let expires = Timestamp(4_102_444_800_000);
+        vec![
+            outcome(ledger.deposit("ALICE", 100)),
+            outcome(ledger.withdraw("ALICE", 10)),
+            outcome(ledger.send("ALICE", "BOB", 20)),
+            outcome(ledger.send_confirmed("ALICE", "BOB", 1)),
+            outcome(ledger.pay("ALICE", "BOB", 5)),
+            outcome(ledger.pay_confirmed("ALICE", "BOB", 5)),
+            outcome(ledger.mint("BOB", 7)),
+            outcome(ledger.burn("BOB", 2)),
+            outcome(ledger.grant_promo("BOB", 3, expires)),
+            outcome(ledger.pay("BOB", "ALICE", 4)),
+            outcome(ledger.withdraw("CAROL", 1)),
+            outcome(ledger.balance_of("ALICE")),
+            outcome(ledger.accounts()),
+            outcome(ledger.goals()),
+        ]
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_ledger_api_runs_commands_the_same_locally_and_remotely() {
+        use crate::{rpc::RpcServer, server::HttpServer};
+        let server = HttpServer::bind("127.0.0.1:0", RpcServer::new(ledger_with_goal())).unwrap();
+        let mut remote = RemoteLedger::new(&format!("http://{}", server.local_addr().unwrap()));
+        std::thread::spawn(move || server.run());
+        let mut local = ledger_with_goal();
+
+        //act
+        let locally = run_commands(&mut local);
+        let remotely = run_commands(&mut remote);
+
+        assert_eq!(remotely, locally);
+        assert!(locally[..10]
+            .iter()
+            .all(|outcome| outcome.starts_with("Ok")));
+        assert!(locally[10].starts_with("Err"));
+        assert!(locally[13].contains("holiday"));
+        assert!(local.login(Some("ALICE")).is_ok());
+        let login = remote.login(Some("ALICE")).unwrap_err();
+        assert!(login.to_string().contains("isn't supported remotely"));
+    }
+}
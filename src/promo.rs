@@ -0,0 +1,148 @@
+//! Promotional credit: bonus money granted to an account until a deadline, kept apart from
+//! its balance and spent before it.
+//!
+//! Credit is granted with [`crate::accounts::Accounts::grant_promo`] and spent first by
+//! [`crate::accounts::Accounts::pay`]. Whatever is left of it once it expires is written off
+//! by [`crate::accounts::Accounts::expire_promos`], which the server modes run every
+//! `promo.expire_every = <n><m|h|d>` (default `1h`) on a thread of their own, see
+//! [`spawn_expiry`]. Until then, expired credit just can't be spent.
+
+use crate::clock::Timestamp;
+use crate::tx::Units;
+use std::collections::BTreeMap;
+#[cfg(feature = "native")]
+use {
+    crate::{accounts::Accounts, shared::SharedAccounts, shutdown::Shutdown},
+    std::sync::Arc,
+    std::thread::{self, JoinHandle},
+    std::time::Duration,
+    tracing::{error, info},
+};
+
+/// The promotional credit of an account, by when it expires. Grants expiring at the same
+/// time are merged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PromoCredits {
+    by_expiry: BTreeMap<Timestamp, Units>,
+}
+
+impl PromoCredits {
+    /// All of the credit, whether it expired or not
+    pub fn total(&self) -> Units {
+        self.by_expiry
+            .values()
+            .fold(0, |total: Units, amount| total.saturating_add(*amount))
+    }
+
+    /// The credit that can still be spent at `now`
+    pub fn available(&self, now: Timestamp) -> Units {
+        self.iter()
+            .filter(|&(expires, _)| expires > now)
+            .fold(0, |total: Units, (_, amount)| total.saturating_add(amount))
+    }
+
+    /// The credit by when it expires, soonest first
+    pub fn iter(&self) -> impl Iterator<Item = (Timestamp, Units)> + '_ {
+        self.by_expiry
+            .iter()
+            .map(|(expires, amount)| (*expires, *amount))
+    }
+
+    /// Returns `true` if there's no credit left
+    pub fn is_empty(&self) -> bool {
+        self.by_expiry.is_empty()
+    }
+
+    /// Adds `amount` of credit spendable until `expires`
+    pub fn grant(&mut self, amount: Units, expires: Timestamp) {
+        let credit = self.by_expiry.entry(expires).or_default();
+        *credit = credit.saturating_add(amount);
+    }
+
+    /// Takes `amount` off the credit expiring at `expires`, and returns whether there was
+    /// enough of it
+    pub fn take(&mut self, amount: Units, expires: Timestamp) -> bool {
+        let Some(credit) = self.by_expiry.get_mut(&expires) else {
+            return amount == 0;
+        };
+        let Some(rest) = credit.checked_sub(amount) else {
+            return false;
+        };
+        *credit = rest;
+        if rest == 0 {
+            self.by_expiry.remove(&expires);
+        }
+        true
+    }
+
+    /// The credit to spend on `amount` at `now`, soonest expiring first, as what to take off
+    /// which grant. Spends all of the available credit if that's less than `amount`.
+    pub fn spending(&self, amount: Units, now: Timestamp) -> Vec<(Timestamp, Units)> {
+        let mut left = amount;
+        let mut spent = vec![];
+        for (expires, credit) in self.iter().filter(|&(expires, _)| expires > now) {
+            if left == 0 {
+                break;
+            }
+            let taken = credit.min(left);
+            spent.push((expires, taken));
+            left -= taken;
+        }
+        spent
+    }
+
+    /// The credit that expired at `now`, as what to write off which grant
+    pub fn expired(&self, now: Timestamp) -> Vec<(Timestamp, Units)> {
+        self.iter()
+            .take_while(|&(expires, _)| expires <= now)
+            .collect()
+    }
+}
+
+/// Writes off the expired promotional credit of `ledger` every `every` on a thread of its own,
+/// see [`Accounts::expire_promos`], until `shutdown` is requested
+#[cfg(feature = "native")]
+pub fn spawn_expiry(
+    ledger: SharedAccounts,
+    every: Duration,
+    shutdown: Arc<Shutdown>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        while !shutdown.wait_timeout(every) {
+            match ledger.write(Accounts::expire_promos) {
+                Ok(txs) if txs.is_empty() => {}
+                Ok(txs) => info!(count = txs.len(), "promotional credit expired"),
+                Err(e) => error!(error = %e, "expiring promotional credit failed"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_promo_credits_spend_the_soonest_expiring_first() {
+        let mut credits = PromoCredits::default();
+        credits.grant(10, Timestamp(100));
+        credits.grant(20, Timestamp(300));
+        credits.grant(5, Timestamp(100));
+
+        //act
+        let spending = credits.spending(25, Timestamp(50));
+        let after_expiry = credits.spending(25, Timestamp(100));
+        let expired = credits.expired(Timestamp(100));
+        let taken = credits.take(15, Timestamp(100));
+        let too_much = credits.take(1, Timestamp(100));
+
+        assert_eq!(spending, vec![(Timestamp(100), 15), (Timestamp(300), 10)]);
+        assert_eq!(after_expiry, vec![(Timestamp(300), 20)]);
+        assert_eq!(expired, vec![(Timestamp(100), 15)]);
+        assert!(taken);
+        assert!(!too_much);
+        assert_eq!(credits.total(), 20);
+        assert_eq!(credits.available(Timestamp(300)), 0);
+        assert_eq!(credits.available(Timestamp(299)), 20);
+    }
+}
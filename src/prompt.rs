@@ -0,0 +1,284 @@
+//! The prompt of the interactive mode: the commands entered at it and shortcuts for them.
+//!
+//! A [`PromptHistory`] entry is a command along with the answers to the prompts it asked, e.g.
+//! `send`, the sender, the amount and the recipient, so recalling it can answer them again.
+//! [`Aliases`] stand for such commands written out on one line.
+
+use crate::config::Config;
+use std::collections::{BTreeMap, VecDeque};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How many entries are kept unless `history.size` says otherwise
+pub const DEFAULT_SIZE: usize = 1000;
+
+/// The latest commands entered at the prompt, oldest first, saved to a file after every
+/// command if it has one.
+///
+/// The file holds one entry per line, its answers separated by tabs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PromptHistory {
+    path: Option<PathBuf>,
+    size: usize,
+    exclude: Vec<String>,
+    entries: VecDeque<Vec<String>>,
+}
+
+impl PromptHistory {
+    /// `$XDG_STATE_HOME/crabbux/history`, falling back to `~/.local/state/crabbux/history`
+    pub fn default_path() -> Option<PathBuf> {
+        let base = env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))?;
+        Some(base.join("crabbux").join("history"))
+    }
+
+    /// A history kept in memory only, of at most `size` entries
+    pub fn new(size: usize) -> Self {
+        PromptHistory {
+            size,
+            ..Default::default()
+        }
+    }
+
+    /// Reads the history saved at `path`, a missing file being an empty history, configured by
+    /// `history.size = <entries>` (default [`DEFAULT_SIZE`], `0` keeps nothing) and
+    /// `history.exclude = <command>,...` naming commands that are never saved, like `login`
+    /// # Errors
+    /// The file can't be read, or a setting is invalid; the error names it
+    pub fn load(path: Option<PathBuf>, config: &Config) -> io::Result<Self> {
+        let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+        let size = match config.get("history.size") {
+            Some(size) => size
+                .parse()
+                .map_err(|e| invalid(format!("history.size: {}", e)))?,
+            None => DEFAULT_SIZE,
+        };
+        let exclude = config
+            .get("history.exclude")
+            .into_iter()
+            .flat_map(|commands| commands.split(','))
+            .map(|command| command.trim().to_string())
+            .filter(|command| !command.is_empty())
+            .collect();
+        let mut history = PromptHistory {
+            exclude,
+            ..PromptHistory::new(size)
+        };
+        if let Some(path) = &path {
+            match fs::read_to_string(path) {
+                Ok(saved) => {
+                    for line in saved.lines().filter(|line| !line.is_empty()) {
+                        history.remember(line.split('\t').map(str::to_string).collect());
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        history.path = path;
+        Ok(history)
+    }
+
+    /// The entries, oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &[String]> {
+        self.entries.iter().map(Vec::as_slice)
+    }
+
+    /// The `n`th entry, counting from 1 as [`PromptHistory::entries`] are listed
+    pub fn get(&self, n: usize) -> Option<&[String]> {
+        self.entries.get(n.checked_sub(1)?).map(Vec::as_slice)
+    }
+
+    /// Adds `entry` and saves the history. Entries of excluded commands, entries with a tab or
+    /// line break in an answer and repeats of the latest entry are left out.
+    /// # Errors
+    /// The history file can't be written
+    pub fn push(&mut self, entry: Vec<String>) -> io::Result<()> {
+        if !self.remember(entry) {
+            return Ok(());
+        }
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let lines: String = self
+            .entries
+            .iter()
+            .map(|entry| entry.join("\t") + "\n")
+            .collect();
+        fs::write(path, lines)
+    }
+
+    /// Adds `entry` unless it's left out, see [`PromptHistory::push`], and returns whether it
+    /// was added
+    fn remember(&mut self, entry: Vec<String>) -> bool {
+        let kept = match entry.first() {
+            None => false,
+            Some(command) => {
+                let name = command.split_whitespace().next().unwrap_or_default();
+                self.size > 0
+                    && !self.exclude.iter().any(|excluded| excluded == name)
+                    && !entry.iter().any(|answer| answer.contains(['\t', '\n']))
+                    && self.entries.back() != Some(&entry)
+            }
+        };
+        if kept {
+            self.entries.push_back(entry);
+            while self.entries.len() > self.size {
+                self.entries.pop_front();
+            }
+        }
+        kept
+    }
+}
+
+/// Names standing for commands: `alias <name> = <command>; <command>...`, each command
+/// followed by the answers to its prompts, e.g. `alias payrent = send me 1200 landlord`
+/// answering the sender, amount and recipient prompts of `send`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Aliases {
+    aliases: BTreeMap<String, String>,
+}
+
+impl Aliases {
+    /// Reads the `alias <name> = ...` lines of the config file
+    /// # Errors
+    /// An alias has an invalid name, see [`Aliases::define`]; the error names it
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let mut aliases = Aliases::default();
+        for (name, commands) in config.with_prefix("alias ") {
+            aliases
+                .define(name, commands)
+                .map_err(|e| format!("alias {}: {}", name, e))?;
+        }
+        Ok(aliases)
+    }
+
+    /// Makes `name` stand for `commands`, or removes the alias if `commands` is empty
+    /// # Errors
+    /// `name` is empty or contains anything but ASCII letters, digits, `-` and `_`
+    pub fn define(&mut self, name: &str, commands: &str) -> Result<(), String> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(format!(
+                "invalid alias name {:?}, expected letters, digits, - and _",
+                name
+            ));
+        }
+        match commands.trim() {
+            "" => self.aliases.remove(name),
+            commands => self.aliases.insert(name.to_string(), commands.to_string()),
+        };
+        Ok(())
+    }
+
+    /// What `name` stands for, if it's an alias
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.aliases.get(name).map(String::as_str)
+    }
+
+    /// The aliases and what they stand for, ordered by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.aliases
+            .iter()
+            .map(|(name, commands)| (name.as_str(), commands.as_str()))
+    }
+
+    /// The commands `name` stands for, each split into its words, or `None` if it isn't an
+    /// alias
+    /// # Errors
+    /// One of the commands is an alias itself; aliases don't nest, so they can't loop
+    pub fn expand(&self, name: &str) -> Option<Result<Vec<Vec<&str>>, String>> {
+        let commands = self
+            .get(name)?
+            .split(';')
+            .map(|command| command.split_whitespace().collect::<Vec<_>>())
+            .filter(|words| !words.is_empty())
+            .collect::<Vec<_>>();
+        match commands.iter().find(|words| self.get(words[0]).is_some()) {
+            Some(words) => Some(Err(format!(
+                "alias {} uses the alias {}, aliases don't nest",
+                name, words[0]
+            ))),
+            None => Some(Ok(commands)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(answers: &[&str]) -> Vec<String> {
+        answers.iter().map(|answer| answer.to_string()).collect()
+    }
+
+    #[test]
+    fn test_prompt_history_is_saved_and_excludes_commands() {
+        let path = env::temp_dir().join(format!("crabbux-{}-history", std::process::id()));
+        let config = Config::parse("history.size = 2\nhistory.exclude = login, approve").unwrap();
+        let mut history = PromptHistory::load(Some(path.clone()), &config).unwrap();
+
+        //act
+        history.push(entry(&["deposit", "ALICE", "100"])).unwrap();
+        history.push(entry(&["login alice"])).unwrap();
+        history
+            .push(entry(&["send", "ALICE", "10", "BOB"]))
+            .unwrap();
+        history
+            .push(entry(&["send", "ALICE", "10", "BOB"]))
+            .unwrap();
+        history.push(entry(&["print"])).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        let reloaded = PromptHistory::load(Some(path.clone()), &config).unwrap();
+        fs::remove_file(&path).unwrap();
+        let invalid = Config::parse("history.size = many").unwrap();
+
+        assert_eq!(saved, "send\tALICE\t10\tBOB\nprint\n");
+        assert_eq!(
+            reloaded.entries().collect::<Vec<_>>(),
+            vec![entry(&["send", "ALICE", "10", "BOB"]), entry(&["print"])]
+        );
+        assert_eq!(reloaded.get(2), Some(entry(&["print"]).as_slice()));
+        assert_eq!(reloaded.get(0), None);
+        assert!(PromptHistory::load(None, &invalid).is_err());
+    }
+
+    #[test]
+    fn test_aliases_expand_to_commands() {
+        let config = Config::parse(
+            "alias payrent = send me 1200 landlord\nalias payday = deposit me 3000; payrent",
+        )
+        .unwrap();
+
+        //act
+        let mut aliases = Aliases::from_config(&config).unwrap();
+        let nested = aliases.expand("payday").unwrap().is_err();
+        aliases
+            .define("payday", "deposit me 3000 ; print;")
+            .unwrap();
+        let invalid = aliases.define("pay day", "print");
+
+        assert_eq!(
+            aliases.expand("payrent"),
+            Some(Ok(vec![vec!["send", "me", "1200", "landlord"]]))
+        );
+        assert!(nested);
+        assert_eq!(
+            aliases.expand("payday"),
+            Some(Ok(vec![vec!["deposit", "me", "3000"], vec!["print"]]))
+        );
+        assert_eq!(aliases.expand("print"), None);
+        assert!(invalid.is_err());
+        aliases.define("payday", "").unwrap();
+        assert_eq!(aliases.iter().count(), 1);
+    }
+}
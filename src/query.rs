@@ -0,0 +1,230 @@
+//! Listing accounts in a chosen order, shared by `crabbux accounts` and the `GET /accounts`
+//! endpoint of [`crate::server::HttpServer`].
+//!
+//! Account names can be hierarchical, with `:` separating the levels like in `corp:payroll`,
+//! so an [`AccountFilter`] can pick an account along with those under it.
+
+use crate::accounts::Accounts;
+use crate::tx::Units;
+use regex::Regex;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+/// Which accounts a listing includes: all of them, unless limited to those under a prefix or
+/// matching a regex, or both
+#[derive(Debug, Clone, Default)]
+pub struct AccountFilter {
+    prefix: Option<String>,
+    pattern: Option<Regex>,
+}
+
+impl AccountFilter {
+    /// Only the account `prefix` and those under it, e.g. `corp` includes `corp` and
+    /// `corp:payroll` but not `corporate`
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = Some(prefix.trim_end_matches(':').to_string());
+        self
+    }
+
+    /// Only the accounts whose whole name matches `pattern`, e.g. `corp:.*`
+    /// # Errors
+    /// `pattern` isn't a valid regex
+    pub fn regex(mut self, pattern: &str) -> Result<Self, String> {
+        // Checked on its own first, so errors point into `pattern` as given
+        Regex::new(pattern).map_err(|e| e.to_string())?;
+        let anchored = format!("^(?:{})$", pattern);
+        self.pattern = Some(Regex::new(&anchored).map_err(|e| e.to_string())?);
+        Ok(self)
+    }
+
+    /// Whether the account called `name` is included
+    pub fn matches(&self, name: &str) -> bool {
+        let under = |prefix: &String| {
+            name.strip_prefix(prefix.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+        };
+        self.prefix.as_ref().is_none_or(under)
+            && self.pattern.as_ref().is_none_or(|re| re.is_match(name))
+    }
+}
+
+/// Filters are equal if they have the same prefix and pattern
+impl PartialEq for AccountFilter {
+    fn eq(&self, other: &Self) -> bool {
+        self.prefix == other.prefix
+            && self.pattern.as_ref().map(Regex::as_str) == other.pattern.as_ref().map(Regex::as_str)
+    }
+}
+
+impl Eq for AccountFilter {}
+
+/// What [`AccountQuery`] orders accounts by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortKey {
+    #[default]
+    Name,
+    Balance,
+    /// The number of committed transactions touching the account
+    Activity,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "name" => Ok(SortKey::Name),
+            "balance" => Ok(SortKey::Balance),
+            "activity" => Ok(SortKey::Activity),
+            _ => Err(format!("expected name, balance or activity, got {:?}", s)),
+        }
+    }
+}
+
+/// An account as [`AccountQuery::run`] lists it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccountListing {
+    pub account: String,
+    pub balance: Units,
+    /// The number of committed transactions touching the account
+    pub txs: u64,
+}
+
+/// Which order to list the accounts of a ledger in, ascending unless `descending` is set, and
+/// which page of them. Accounts that compare equal are ordered by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccountQuery {
+    pub sort: SortKey,
+    pub descending: bool,
+    /// How many accounts to skip
+    pub offset: usize,
+    /// How many accounts to list at most, all if `None`
+    pub limit: Option<usize>,
+    pub filter: AccountFilter,
+}
+
+impl AccountQuery {
+    /// The page of the accounts of `ledger` the filter includes but the archived ones, in order
+    pub fn run(&self, ledger: &Accounts) -> Vec<AccountListing> {
+        let mut listings: Vec<_> = ledger
+            .iter()
+            .filter(|(account, _)| self.filter.matches(account))
+            .map(|(account, balance)| AccountListing {
+                account: account.to_string(),
+                balance: *balance,
+                txs: ledger.stats().account(account).map_or(0, |stats| stats.txs),
+            })
+            .collect();
+        listings.sort_by(|a, b| self.compare(a, b));
+        listings
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    fn compare(&self, a: &AccountListing, b: &AccountListing) -> Ordering {
+        let order = match self.sort {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Balance => a.balance.cmp(&b.balance),
+            SortKey::Activity => a.txs.cmp(&b.txs),
+        };
+        let order = match self.descending {
+            true => order.reverse(),
+            false => order,
+        };
+        order.then_with(|| match (self.sort, self.descending) {
+            (SortKey::Name, true) => b.account.cmp(&a.account),
+            _ => a.account.cmp(&b.account),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_query_sorts_accounts() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("CAROL", 50).unwrap();
+        ledger.deposit("ALICE", 100).unwrap();
+        ledger.deposit("BOB", 50).unwrap();
+        ledger.send("ALICE", "BOB", 10).unwrap();
+        ledger.send("ALICE", "BOB", 10).unwrap();
+        let names = |query: AccountQuery| -> Vec<String> {
+            query
+                .run(&ledger)
+                .into_iter()
+                .map(|listing| listing.account)
+                .collect()
+        };
+
+        //act
+        let by_name = names(AccountQuery::default());
+        let by_name_desc = names(AccountQuery {
+            descending: true,
+            ..Default::default()
+        });
+        let by_balance = names(AccountQuery {
+            sort: SortKey::Balance,
+            descending: true,
+            ..Default::default()
+        });
+        let by_activity = names(AccountQuery {
+            sort: SortKey::Activity,
+            ..Default::default()
+        });
+        let second_page = names(AccountQuery {
+            offset: 1,
+            limit: Some(1),
+            ..Default::default()
+        });
+
+        assert_eq!(by_name, vec!["ALICE", "BOB", "CAROL"]);
+        assert_eq!(by_name_desc, vec!["CAROL", "BOB", "ALICE"]);
+        assert_eq!(by_balance, vec!["ALICE", "BOB", "CAROL"]);
+        assert_eq!(by_activity, vec!["CAROL", "ALICE", "BOB"]);
+        assert_eq!(second_page, vec!["BOB"]);
+        assert_eq!("activity".parse(), Ok(SortKey::Activity));
+        assert!("size".parse::<SortKey>().is_err());
+    }
+
+    #[test]
+    fn test_account_filter_matches_prefixes_and_regexes() {
+        let mut ledger = Accounts::new();
+        for account in [
+            "corp",
+            "corp:payroll",
+            "corp:rent:office",
+            "corporate",
+            "home",
+        ] {
+            ledger.deposit(account, 1).unwrap();
+        }
+        let names = |filter: AccountFilter| -> Vec<String> {
+            let query = AccountQuery {
+                filter,
+                ..Default::default()
+            };
+            query.run(&ledger).into_iter().map(|l| l.account).collect()
+        };
+
+        //act
+        let under_corp = names(AccountFilter::default().prefix("corp:"));
+        let corp_leaves = names(AccountFilter::default().regex("corp:[a-z]+").unwrap());
+        let both = names(
+            AccountFilter::default()
+                .prefix("corp")
+                .regex(".*office")
+                .unwrap(),
+        );
+
+        assert_eq!(under_corp, vec!["corp", "corp:payroll", "corp:rent:office"]);
+        assert_eq!(corp_leaves, vec!["corp:payroll"]);
+        assert_eq!(both, vec!["corp:rent:office"]);
+        assert_eq!(names(AccountFilter::default()).len(), 5);
+        assert!(AccountFilter::default().regex("corp:(").is_err());
+    }
+}
@@ -0,0 +1,144 @@
+//! Token bucket rate limiting, e.g. of the requests [`crate::server::HttpServer`] serves per
+//! client and per account.
+
+use crate::clock::{Clock, SystemClock, Timestamp};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Tokens are counted in thousandths so refilling by less than a token per millisecond works
+const MILLI_TOKENS: u128 = 1000;
+
+/// Buckets that refilled completely are dropped once there are more than this many
+const MAX_BUCKETS: usize = 10_000;
+
+/// At most `requests` per `per` on average, and at most `requests` in a burst.
+///
+/// Parsed from `<requests>/<second|minute|hour>`, e.g. `100/minute`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub per: Duration,
+}
+
+impl RateLimit {
+    fn capacity(&self) -> u128 {
+        self.requests as u128 * MILLI_TOKENS
+    }
+
+    /// The milli-tokens refilled in `millis` milliseconds
+    fn refill(&self, millis: u64) -> u128 {
+        millis as u128 * self.capacity() / self.per.as_millis().max(1)
+    }
+}
+
+impl FromStr for RateLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let invalid = || format!("expected `<requests>/<second|minute|hour>`, got {:?}", s);
+        let (requests, per) = s.split_once('/').ok_or_else(invalid)?;
+        let per = match per.trim() {
+            "second" => Duration::from_secs(1),
+            "minute" => Duration::from_secs(60),
+            "hour" => Duration::from_secs(3600),
+            _ => return Err(invalid()),
+        };
+        match requests.trim().parse() {
+            Ok(requests) if requests > 0 => Ok(RateLimit { requests, per }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// In thousandths of a token
+    tokens: u128,
+    updated: Timestamp,
+}
+
+/// Applies a [`RateLimit`] to each key, like a client address or an account, separately
+#[derive(Debug)]
+pub struct RateLimiter {
+    limit: RateLimit,
+    clock: Arc<dyn Clock>,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        RateLimiter {
+            limit,
+            clock: Arc::new(SystemClock),
+            buckets: Default::default(),
+        }
+    }
+
+    /// Uses `clock` instead of the system clock to refill the buckets
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn limit(&self) -> &RateLimit {
+        &self.limit
+    }
+
+    /// Takes a token from the bucket of `key`, which starts out full.
+    /// # Errors
+    /// The bucket is empty; the error is how long until the next token is available
+    pub fn acquire(&self, key: &str) -> Result<(), Duration> {
+        let now = self.clock.now();
+        let capacity = self.limit.capacity();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > MAX_BUCKETS {
+            let limit = self.limit;
+            buckets.retain(|_, bucket| {
+                bucket.tokens + limit.refill(now.0.saturating_sub(bucket.updated.0)) < capacity
+            });
+        }
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let refilled = self.limit.refill(now.0.saturating_sub(bucket.updated.0));
+        bucket.tokens = (bucket.tokens + refilled).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens >= MILLI_TOKENS {
+            bucket.tokens -= MILLI_TOKENS;
+            return Ok(());
+        }
+        let missing = MILLI_TOKENS - bucket.tokens;
+        let millis = (missing * self.limit.per.as_millis()).div_ceil(capacity);
+        Err(Duration::from_millis(millis as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_rate_limiter_refills_over_time() {
+        let clock = Arc::new(ManualClock::new(Timestamp(0)));
+        let limiter = RateLimiter::new("2/second".parse().unwrap()).with_clock(clock.clone());
+
+        //act
+        let burst = [limiter.acquire("a"), limiter.acquire("a")];
+        let exhausted = limiter.acquire("a");
+        let other = limiter.acquire("b");
+        clock.advance(Duration::from_millis(500));
+        let refilled = limiter.acquire("a");
+
+        assert_eq!(burst, [Ok(()), Ok(())]);
+        assert_eq!(exhausted, Err(Duration::from_millis(500)));
+        assert_eq!(other, Ok(()));
+        assert_eq!(refilled, Ok(()));
+        assert!(limiter.acquire("a").is_err());
+        assert!("0/second".parse::<RateLimit>().is_err());
+        assert!("5/day".parse::<RateLimit>().is_err());
+    }
+}
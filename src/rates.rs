@@ -0,0 +1,737 @@
+//! Exchange rates between currencies, for converting amounts and valuing balances.
+//!
+//! Rates come from a [`RateProvider`]: a [`RateTable`] of dated rates, or with the
+//! `rates-http` feature an `HttpRates` fetching the ECB reference rates. A provider answers
+//! with the latest rate on or before the day asked about, converting through a common
+//! currency if there's no direct rate, and refuses rates older than its maximum age rather
+//! than silently valuing with them.
+//!
+//! A ledger's rates are kept next to its log in `<log>.rates`, see [`path_for`], and imported
+//! from CSV or JSON tables with `crabbux rates import`, so converting and valuing need no
+//! network.
+
+use crate::currency::CurrencyRegistry;
+use crate::date::Date;
+use crate::import::ParseError;
+use crate::rounding::RoundingPolicy;
+use crate::tx::Units;
+use crate::{checksum, migrations};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+#[cfg(feature = "rates-http")]
+use {
+    quick_xml::{events::Event, Reader, XmlVersion},
+    std::sync::Mutex,
+    std::time::{Duration, Instant},
+    tracing::warn,
+};
+
+/// The decimal places of a [`Rate`]
+pub const RATE_DECIMALS: u32 = 9;
+
+const SCALE: u128 = 10u128.pow(RATE_DECIMALS);
+
+/// How much one unit of a currency is worth in another, with up to [`RATE_DECIMALS`] decimal
+/// places, like `1.0845` for EUR to USD
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Rate {
+    /// The rate in billionths
+    scaled: u128,
+}
+
+impl Rate {
+    /// A rate of 1, between a currency and itself
+    pub const ONE: Rate = Rate { scaled: SCALE };
+
+    /// The rate the other way round, rounded to the nearest billionth, `None` if it's too
+    /// small to invert
+    pub fn inverse(self) -> Option<Rate> {
+        let scaled = RoundingPolicy::HalfUp.divide(SCALE * SCALE, self.scaled);
+        (scaled > 0).then_some(Rate { scaled })
+    }
+
+    /// The rate of converting with `self` and then with `other`, rounded to the nearest
+    /// billionth, `None` if it overflows or rounds to 0
+    pub fn then(self, other: Rate) -> Option<Rate> {
+        let product = self.scaled.checked_mul(other.scaled)?;
+        let scaled = RoundingPolicy::HalfUp.divide(product, SCALE);
+        (scaled > 0).then_some(Rate { scaled })
+    }
+
+    /// Converts `amount`, in the smallest unit of a currency with `from_decimals` decimal
+    /// places, into the smallest unit of one with `to_decimals`, rounded by `policy`. `None`
+    /// if the result doesn't fit.
+    pub fn convert(
+        self,
+        amount: Units,
+        from_decimals: u32,
+        to_decimals: u32,
+        policy: RoundingPolicy,
+    ) -> Option<Units> {
+        let numerator = (amount as u128)
+            .checked_mul(self.scaled)?
+            .checked_mul(10u128.checked_pow(to_decimals)?)?;
+        let denominator = SCALE.checked_mul(10u128.checked_pow(from_decimals)?)?;
+        Units::try_from(policy.divide(numerator, denominator)).ok()
+    }
+}
+
+impl FromStr for Rate {
+    type Err = String;
+
+    /// Parses a positive decimal like `1.0845` or `160`
+    fn from_str(s: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid rate {:?}", s);
+        let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+        let digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if whole.is_empty() || !digits(whole) || !digits(fraction) {
+            return Err(invalid());
+        }
+        if fraction.len() > RATE_DECIMALS as usize {
+            return Err(format!(
+                "rate {} has more than {} decimals",
+                s, RATE_DECIMALS
+            ));
+        }
+        let padded = format!(
+            "{}{:0<width$}",
+            whole,
+            fraction,
+            width = RATE_DECIMALS as usize
+        );
+        match padded.parse() {
+            Ok(0) => Err(format!("rate {} isn't positive", s)),
+            Ok(scaled) => Ok(Rate { scaled }),
+            Err(_) => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Rate {
+    /// Without trailing zeros, like `1.0845`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (whole, fraction) = (self.scaled / SCALE, self.scaled % SCALE);
+        let fraction = format!("{:0>width$}", fraction, width = RATE_DECIMALS as usize);
+        match fraction.trim_end_matches('0') {
+            "" => write!(f, "{}", whole),
+            fraction => write!(f, "{}.{}", whole, fraction),
+        }
+    }
+}
+
+/// Stored as a string like `"1.0845"`, so no digit is lost to floats
+impl Serialize for Rate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Rate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// A rate and the day it was set on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quote {
+    pub rate: Rate,
+    pub date: Date,
+}
+
+/// Why a [`RateProvider`] has no rate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RateError {
+    /// There's no rate between the currencies on or before the day
+    Unknown { from: String, to: String },
+    /// The latest rate between the currencies is older than the provider accepts
+    Stale {
+        from: String,
+        to: String,
+        date: Date,
+    },
+    /// The currency is neither ISO 4217 nor registered, see [`CurrencyRegistry`]
+    UnknownCurrency(String),
+    /// The converted amount doesn't fit into [`Units`]
+    Overflow,
+    /// The rates couldn't be fetched
+    Fetch(String),
+}
+
+impl fmt::Display for RateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RateError::Unknown { from, to } => write!(f, "No rate from {} to {}", from, to),
+            RateError::Stale { from, to, date } => write!(
+                f,
+                "The rate from {} to {} is from {}, which is too old",
+                from,
+                to,
+                date.iso()
+            ),
+            RateError::UnknownCurrency(code) => write!(f, "Unknown currency {}", code),
+            RateError::Overflow => f.write_str("The converted amount is too large"),
+            RateError::Fetch(e) => write!(f, "Couldn't fetch rates: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RateError {}
+
+/// Where exchange rates come from
+pub trait RateProvider {
+    /// The latest rate from `from` to `to` on or before `on`
+    /// # Errors
+    /// There's no such rate, it's stale, or the rates couldn't be fetched
+    fn rate(&self, from: &str, to: &str, on: Date) -> Result<Quote, RateError>;
+
+    /// Converts `amount` in the smallest unit of `from` into that of `to` at the rate on
+    /// `on`, with the decimal places of both in `currencies`, rounded by `policy`
+    /// # Errors
+    /// See [`RateProvider::rate`]; a currency is unknown, or the result doesn't fit
+    fn convert(
+        &self,
+        amount: Units,
+        from: &str,
+        to: &str,
+        on: Date,
+        currencies: &CurrencyRegistry,
+        policy: RoundingPolicy,
+    ) -> Result<Units, RateError> {
+        let decimals = |code: &str| {
+            currencies
+                .decimals_of(code)
+                .ok_or_else(|| RateError::UnknownCurrency(code.to_string()))
+        };
+        let (from_decimals, to_decimals) = (decimals(from)?, decimals(to)?);
+        let rate = match from.eq_ignore_ascii_case(to) {
+            true => Rate::ONE,
+            false => self.rate(from, to, on)?.rate,
+        };
+        rate.convert(amount, from_decimals, to_decimals, policy)
+            .ok_or(RateError::Overflow)
+    }
+}
+
+/// One rate of a [`RateTable`] as stored and imported, like
+/// `{"date": "2026-10-13", "from": "EUR", "to": "USD", "rate": "1.0845"}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DatedRate {
+    pub date: Date,
+    pub from: String,
+    pub to: String,
+    pub rate: Rate,
+}
+
+/// Rates by currency pair and the day they were set on, kept in memory
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "StoredRates", into = "StoredRates")]
+pub struct RateTable {
+    rates: BTreeMap<(String, String), BTreeMap<Date, Rate>>,
+    max_age: Option<u32>,
+}
+
+/// How a [`RateTable`] is stored, as a JSON document
+#[derive(Serialize, Deserialize)]
+struct StoredRates {
+    rates: Vec<DatedRate>,
+}
+
+impl From<StoredRates> for RateTable {
+    fn from(stored: StoredRates) -> Self {
+        let mut table = RateTable::default();
+        table.extend_from(stored.rates);
+        table
+    }
+}
+
+impl From<RateTable> for StoredRates {
+    fn from(table: RateTable) -> Self {
+        StoredRates {
+            rates: table.dated().collect(),
+        }
+    }
+}
+
+impl RateTable {
+    /// Reads the rates at `path`; a missing file has none
+    /// # Errors
+    /// Reading failed, the file doesn't match its checksum, see [`checksum::read`], or a newer
+    /// crabbux wrote it, see [`migrations::upgrade`]
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        match migrations::load(path.as_ref(), migrations::RATES) {
+            Ok(table) => Ok(table),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(RateTable::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the rates and their checksum to `path`, replacing it atomically
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        checksum::write(path, &migrations::to_json(self, migrations::RATES)?)
+    }
+
+    /// Parses a CSV table of `date,from,to,rate` lines, like `2026-10-13,EUR,USD,1.0845`. A
+    /// first line starting with `date` is a header, and blank lines are skipped.
+    pub fn parse_csv(input: &str) -> Result<Self, ParseError> {
+        let mut rates = vec![];
+        for (index, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || index == 0 && line.starts_with("date") {
+                continue;
+            }
+            let error = |message: String| ParseError {
+                line: index + 1,
+                message,
+            };
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let [date, from, to, rate] = fields.as_slice() else {
+                return Err(error(format!("expected date,from,to,rate, got {:?}", line)));
+            };
+            rates.push(DatedRate {
+                date: Date::parse_iso(date)
+                    .ok_or_else(|| error(format!("invalid date {:?}", date)))?,
+                from: from.to_string(),
+                to: to.to_string(),
+                rate: rate.parse().map_err(error)?,
+            });
+        }
+        let mut table = RateTable::default();
+        table.extend_from(rates);
+        Ok(table)
+    }
+
+    /// Parses a JSON array of [`DatedRate`]s
+    pub fn parse_json(input: &str) -> Result<Self, ParseError> {
+        let rates: Vec<DatedRate> = serde_json::from_str(input).map_err(|e| ParseError {
+            line: e.line(),
+            message: e.to_string(),
+        })?;
+        let mut table = RateTable::default();
+        table.extend_from(rates);
+        Ok(table)
+    }
+
+    /// Treats rates set more than `days` before the day asked about as stale
+    pub fn with_max_age(self, days: u32) -> Self {
+        RateTable {
+            max_age: Some(days),
+            ..self
+        }
+    }
+
+    /// Sets the rate from `from` to `to` on `date`, replacing the rate set then. Codes are
+    /// kept in upper case.
+    pub fn insert(&mut self, from: &str, to: &str, date: Date, rate: Rate) {
+        self.rates
+            .entry((from.to_uppercase(), to.to_uppercase()))
+            .or_default()
+            .insert(date, rate);
+    }
+
+    /// Adds every rate of `other`, its rates winning where both have one
+    pub fn extend(&mut self, other: RateTable) {
+        for ((from, to), rates) in other.rates {
+            self.rates.entry((from, to)).or_default().extend(rates);
+        }
+    }
+
+    /// The number of rates
+    pub fn len(&self) -> usize {
+        self.rates.values().map(BTreeMap::len).sum()
+    }
+
+    /// Returns `true` if there are no rates
+    pub fn is_empty(&self) -> bool {
+        self.rates.is_empty()
+    }
+
+    /// Sets every rate of `rates`, see [`RateTable::insert`]
+    pub fn extend_from(&mut self, rates: impl IntoIterator<Item = DatedRate>) {
+        for DatedRate {
+            date,
+            from,
+            to,
+            rate,
+        } in rates
+        {
+            self.insert(&from, &to, date, rate);
+        }
+    }
+
+    /// Every rate, ordered by pair and date
+    pub fn dated(&self) -> impl Iterator<Item = DatedRate> + '_ {
+        self.iter().map(|(from, to, date, rate)| DatedRate {
+            date,
+            from: from.to_string(),
+            to: to.to_string(),
+            rate,
+        })
+    }
+
+    /// Every rate as from, to, date and rate, ordered by pair and date
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str, Date, Rate)> {
+        self.rates.iter().flat_map(|((from, to), rates)| {
+            rates
+                .iter()
+                .map(move |(date, rate)| (from.as_str(), to.as_str(), *date, *rate))
+        })
+    }
+
+    /// The latest rate set directly between `from` and `to`, either way round, on or before
+    /// `on`
+    fn direct(&self, from: &str, to: &str, on: Date) -> Option<Quote> {
+        let latest = |from: &str, to: &str| {
+            let rates = self.rates.get(&(from.to_string(), to.to_string()))?;
+            let (date, rate) = rates.range(..=on).next_back()?;
+            Some(Quote {
+                rate: *rate,
+                date: *date,
+            })
+        };
+        let inverse = latest(to, from).and_then(|quote| {
+            Some(Quote {
+                rate: quote.rate.inverse()?,
+                date: quote.date,
+            })
+        });
+        match (latest(from, to), inverse) {
+            (Some(quote), Some(inverse)) if inverse.date > quote.date => Some(inverse),
+            (Some(quote), _) => Some(quote),
+            (None, inverse) => inverse,
+        }
+    }
+
+    /// The rate through the currency both `from` and `to` have the most recent rates with,
+    /// dated like the older of the two
+    fn cross(&self, from: &str, to: &str, on: Date) -> Option<Quote> {
+        let via: Vec<&str> = self
+            .rates
+            .keys()
+            .filter_map(|(a, b)| match (a == from, b == from) {
+                (true, _) => Some(b.as_str()),
+                (_, true) => Some(a.as_str()),
+                _ => None,
+            })
+            .filter(|via| *via != to)
+            .collect();
+        via.into_iter()
+            .filter_map(|via| {
+                let (first, second) = (self.direct(from, via, on)?, self.direct(via, to, on)?);
+                Some(Quote {
+                    rate: first.rate.then(second.rate)?,
+                    date: first.date.min(second.date),
+                })
+            })
+            .max_by_key(|quote| quote.date)
+    }
+}
+
+impl RateProvider for RateTable {
+    fn rate(&self, from: &str, to: &str, on: Date) -> Result<Quote, RateError> {
+        let (from, to) = (from.to_uppercase(), to.to_uppercase());
+        if from == to {
+            return Ok(Quote {
+                rate: Rate::ONE,
+                date: on,
+            });
+        }
+        let Some(quote) = self
+            .direct(&from, &to, on)
+            .or_else(|| self.cross(&from, &to, on))
+        else {
+            return Err(RateError::Unknown { from, to });
+        };
+        let age = on.unix_days() - quote.date.unix_days();
+        match self.max_age {
+            Some(days) if age > days as i64 => Err(RateError::Stale {
+                from,
+                to,
+                date: quote.date,
+            }),
+            _ => Ok(quote),
+        }
+    }
+}
+
+/// Where the rates of the ledger persisted to `log` are kept: `<log>.rates`
+pub fn path_for(log: impl AsRef<Path>) -> PathBuf {
+    let mut path = OsString::from(log.as_ref());
+    path.push(".rates");
+    path.into()
+}
+
+/// The daily euro reference rates of the European Central Bank. crabbux's HTTP client is built
+/// without TLS, so [`HttpRates`] needs a mirror or proxy of it served over `http`.
+#[cfg(feature = "rates-http")]
+pub const ECB_DAILY_URL: &str = "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-daily.xml";
+
+/// Rates fetched over HTTP from a document in the format of [`ECB_DAILY_URL`], from EUR to
+/// each currency.
+///
+/// The rates are fetched again once they were cached for longer than the time to live, and
+/// the cached ones are used while fetching fails, until they're older than the maximum age
+/// like any [`RateTable`]. The ECB publishes on working days, so the default maximum age of
+/// 4 days covers weekends and holidays.
+#[cfg(feature = "rates-http")]
+#[derive(Debug)]
+pub struct HttpRates {
+    url: String,
+    ttl: Duration,
+    max_age: u32,
+    cache: Mutex<Option<(Instant, RateTable)>>,
+}
+
+#[cfg(feature = "rates-http")]
+impl HttpRates {
+    /// Fetches from `url`, caching the rates for an hour
+    pub fn new(url: impl Into<String>) -> Self {
+        HttpRates {
+            url: url.into(),
+            ttl: Duration::from_secs(3600),
+            max_age: 4,
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// Caches the rates for `ttl` before fetching them again
+    pub fn with_ttl(self, ttl: Duration) -> Self {
+        HttpRates { ttl, ..self }
+    }
+
+    /// Treats rates set more than `days` before the day asked about as stale
+    pub fn with_max_age(self, days: u32) -> Self {
+        HttpRates {
+            max_age: days,
+            ..self
+        }
+    }
+
+    /// The cached rates, fetched anew if they expired
+    fn table(&self) -> Result<RateTable, RateError> {
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((fetched, table)) = cache.as_ref() {
+            if fetched.elapsed() < self.ttl {
+                return Ok(table.clone());
+            }
+        }
+        match self.fetch() {
+            Ok(table) => {
+                *cache = Some((Instant::now(), table.clone()));
+                Ok(table)
+            }
+            Err(e) => match cache.as_ref() {
+                Some((_, table)) => {
+                    warn!(url = %self.url, error = %e, "fetching rates failed, using cached rates");
+                    Ok(table.clone())
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    fn fetch(&self) -> Result<RateTable, RateError> {
+        let fetch = |e: &dyn fmt::Display| RateError::Fetch(e.to_string());
+        let body = ureq::get(&self.url)
+            .call()
+            .map_err(|e| fetch(&e))?
+            .body_mut()
+            .read_to_string()
+            .map_err(|e| fetch(&e))?;
+        let table = parse_ecb(&body).map_err(|e| fetch(&e))?;
+        Ok(table.with_max_age(self.max_age))
+    }
+}
+
+#[cfg(feature = "rates-http")]
+impl RateProvider for HttpRates {
+    fn rate(&self, from: &str, to: &str, on: Date) -> Result<Quote, RateError> {
+        self.table()?.rate(from, to, on)
+    }
+}
+
+/// Parses the rates of an ECB reference rate document: `<Cube currency=".." rate=".."/>`
+/// elements within `<Cube time="YYYY-MM-DD">`, from EUR to each currency
+#[cfg(feature = "rates-http")]
+pub fn parse_ecb(xml: &str) -> Result<RateTable, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut table = RateTable::default();
+    let mut date = None;
+    loop {
+        let cube = match reader.read_event().map_err(|e| e.to_string())? {
+            Event::Start(cube) | Event::Empty(cube) if cube.local_name().as_ref() == "Cube" => cube,
+            Event::Eof => break,
+            _ => continue,
+        };
+        let attribute = |name: &str| -> Result<Option<String>, String> {
+            let Some(attribute) = cube.try_get_attribute(name).map_err(|e| e.to_string())? else {
+                return Ok(None);
+            };
+            let value = attribute
+                .normalized_value(XmlVersion::Implicit1_0)
+                .map_err(|e| e.to_string())?;
+            Ok(Some(value.into_owned()))
+        };
+        if let Some(time) = attribute("time")? {
+            date = Some(Date::parse_iso(&time).ok_or_else(|| format!("invalid date {:?}", time))?);
+        }
+        if let (Some(currency), Some(rate)) = (attribute("currency")?, attribute("rate")?) {
+            let date = date.ok_or_else(|| format!("the rate of {} has no date", currency))?;
+            table.insert("EUR", &currency, date, rate.parse()?);
+        }
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> Date {
+        Date::new(2026, 10, day).unwrap()
+    }
+
+    #[test]
+    fn test_rate_converts_between_decimals() {
+        let rate: Rate = "1.0845".parse().unwrap();
+
+        //act
+        let usd = rate.convert(10_000, 2, 2, RoundingPolicy::Floor);
+        let yen = "163.2"
+            .parse::<Rate>()
+            .unwrap()
+            .convert(1_99, 2, 0, RoundingPolicy::HalfUp);
+        let inverse = rate.inverse().unwrap();
+
+        assert_eq!(usd, Some(10_845));
+        assert_eq!(yen, Some(325));
+        assert_eq!(inverse.to_string(), "0.92208391");
+        assert_eq!(rate.to_string(), "1.0845");
+        assert_eq!(Rate::ONE.to_string(), "1");
+        assert!("0".parse::<Rate>().is_err());
+        assert!("-1".parse::<Rate>().is_err());
+        assert!("1.0000000001".parse::<Rate>().is_err());
+        assert_eq!(rate.convert(Units::MAX, 0, 18, RoundingPolicy::Floor), None);
+    }
+
+    #[test]
+    fn test_rate_table_finds_direct_inverse_and_cross_rates() {
+        let mut table = RateTable::default().with_max_age(3);
+        table.insert("eur", "usd", date(12), "1.1".parse().unwrap());
+        table.insert("EUR", "USD", date(13), "1.2".parse().unwrap());
+        table.insert("EUR", "JPY", date(13), "160".parse().unwrap());
+        let currencies = CurrencyRegistry::default();
+
+        //act
+        let direct = table.rate("EUR", "USD", date(12)).unwrap();
+        let latest = table.rate("EUR", "USD", date(14)).unwrap();
+        let inverse = table.rate("USD", "EUR", date(13)).unwrap();
+        let cross = table.rate("USD", "JPY", date(13)).unwrap();
+        let converted = table.convert(
+            1_00,
+            "USD",
+            "JPY",
+            date(13),
+            &currencies,
+            RoundingPolicy::Floor,
+        );
+        let stale = table.rate("EUR", "USD", date(17));
+        let before = table.rate("EUR", "USD", date(11));
+
+        assert_eq!(direct.rate.to_string(), "1.1");
+        assert_eq!(
+            (latest.rate.to_string(), latest.date),
+            ("1.2".to_string(), date(13))
+        );
+        assert_eq!(inverse.rate.to_string(), "0.833333333");
+        assert_eq!(cross.rate.to_string(), "133.33333328");
+        assert_eq!(converted, Ok(133));
+        assert!(matches!(stale, Err(RateError::Stale { date: d, .. }) if d == date(13)));
+        assert!(matches!(before, Err(RateError::Unknown { .. })));
+        assert_eq!(table.len(), 3);
+        assert_eq!(
+            table.convert(
+                5,
+                "CRAB",
+                "EUR",
+                date(13),
+                &currencies,
+                RoundingPolicy::Floor
+            ),
+            Err(RateError::UnknownCurrency("CRAB".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_rate_table_imports_and_stores_tables() {
+        let csv = "date,from,to,rate\n2026-10-12,EUR,USD,1.1\n\n2026-10-13, eur, usd, 1.2\n";
+        let json = r#"[{"date": "2026-10-13", "from": "EUR", "to": "JPY", "rate": "160"}]"#;
+        let path = std::env::temp_dir().join(format!("crabbux-{}.rates", std::process::id()));
+
+        //act
+        let mut table = RateTable::parse_csv(csv).unwrap();
+        table.extend(RateTable::parse_json(json).unwrap());
+        table.save(&path).unwrap();
+        let loaded = RateTable::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let bad_rate = RateTable::parse_csv("2026-10-13,EUR,USD,1,2");
+        let bad_json = RateTable::parse_json("[{\"date\": \"2026-10-13\"}]");
+
+        assert_eq!(loaded, table);
+        assert_eq!(loaded.len(), 3);
+        assert_eq!(
+            loaded.dated().next(),
+            Some(DatedRate {
+                date: date(13),
+                from: "EUR".to_string(),
+                to: "JPY".to_string(),
+                rate: "160".parse().unwrap(),
+            })
+        );
+        assert_eq!(bad_rate.unwrap_err().line, 1);
+        assert!(bad_json.is_err());
+        assert_eq!(path_for("l.jsonl"), PathBuf::from("l.jsonl.rates"));
+        assert_eq!(
+            RateTable::load("/nonexistent/l.rates").unwrap(),
+            RateTable::default()
+        );
+    }
+
+    #[cfg(feature = "rates-http")]
+    #[test]
+    fn test_http_rates_caches_and_falls_back_to_cached_rates() {
+        use tiny_http::{Response, Server};
+
+        let xml = r#"<gesmes:Envelope xmlns:gesmes="http://www.gesmes.org/xml/2002-08-01" xmlns="http://www.ecb.int/vocabulary/2002-08-01/eurofxref">
+            <Cube><Cube time="2026-10-13"><Cube currency="USD" rate="1.0845"/><Cube currency="JPY" rate="163.2"/></Cube></Cube>
+        </gesmes:Envelope>"#;
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/rates.xml", server.server_addr().to_ip().unwrap());
+        let serving = std::thread::spawn(move || {
+            let request = server.recv().unwrap();
+            request.respond(Response::from_string(xml)).unwrap();
+        });
+        let rates = HttpRates::new(url).with_ttl(Duration::ZERO);
+
+        //act
+        let fetched = rates.rate("EUR", "USD", date(14));
+        serving.join().unwrap();
+        let cached = rates.rate("USD", "EUR", date(14));
+        let stale = rates.rate("EUR", "JPY", date(20));
+        let unreachable =
+            HttpRates::new("http://127.0.0.1:1/rates.xml").rate("EUR", "USD", date(14));
+
+        assert_eq!(fetched.unwrap().rate.to_string(), "1.0845");
+        assert_eq!(cached.unwrap().date, date(13));
+        assert!(matches!(stale, Err(RateError::Stale { .. })));
+        assert!(matches!(unreachable, Err(RateError::Fetch(_))));
+        assert!(parse_ecb("<Cube currency=\"USD\" rate=\"1\"/>").is_err());
+    }
+}
@@ -0,0 +1,176 @@
+//! Rounding the amounts derived from others, like interest, fees and conversions, to whole
+//! units of the currency.
+//!
+//! The ledger's [`Rounding`] is configured once with `rounding = floor|half-up|half-even` and
+//! `rounding.remainder = <account>` in the config file, see [`Rounding::from_config`], and
+//! applies to everything derived on it. What rounding leaves of a total goes to the remainder
+//! account, see [`crate::accounts::Accounts::pay_out`].
+
+use crate::config::Config;
+use crate::tx::Units;
+use std::fmt;
+use std::str::FromStr;
+
+/// How a fractional amount becomes a whole one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RoundingPolicy {
+    /// Down, so nothing more than the exact amount is ever paid
+    #[default]
+    Floor,
+    /// To the nearest unit, halves up
+    HalfUp,
+    /// To the nearest unit, halves to the even one, so halves don't add up in one direction.
+    /// Also known as banker's rounding.
+    HalfEven,
+}
+
+impl RoundingPolicy {
+    /// `numerator / denominator` rounded to a whole unit, 0 if `denominator` is 0
+    pub fn divide(self, numerator: u128, denominator: u128) -> u128 {
+        if denominator == 0 {
+            return 0;
+        }
+        let (quotient, remainder) = (numerator / denominator, numerator % denominator);
+        // `remainder` against the rest of `denominator`, as doubling it could overflow
+        let up = match self {
+            RoundingPolicy::Floor => false,
+            RoundingPolicy::HalfUp => remainder >= denominator - remainder,
+            RoundingPolicy::HalfEven => match remainder.cmp(&(denominator - remainder)) {
+                std::cmp::Ordering::Less => false,
+                std::cmp::Ordering::Equal => quotient % 2 == 1,
+                std::cmp::Ordering::Greater => true,
+            },
+        };
+        quotient + up as u128
+    }
+}
+
+impl FromStr for RoundingPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "floor" => Ok(RoundingPolicy::Floor),
+            "half-up" => Ok(RoundingPolicy::HalfUp),
+            "half-even" | "bankers" => Ok(RoundingPolicy::HalfEven),
+            _ => Err(format!("expected floor, half-up or half-even, got {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for RoundingPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            RoundingPolicy::Floor => "floor",
+            RoundingPolicy::HalfUp => "half-up",
+            RoundingPolicy::HalfEven => "half-even",
+        })
+    }
+}
+
+/// A total split into rounded shares, see [`Rounding::split`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Split {
+    /// The share of each account, in the order they were given
+    pub shares: Vec<(String, Units)>,
+    /// What the shares leave of the total: positive if they add up to less, negative if
+    /// rounding up made them add up to more
+    pub remainder: i128,
+}
+
+/// How a ledger rounds derived amounts, and where what rounding leaves goes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rounding {
+    pub policy: RoundingPolicy,
+    /// The account receiving the remainders of splits, or covering them when they're
+    /// negative. Without one, the payer keeps or covers them.
+    pub remainder_account: Option<String>,
+}
+
+impl Rounding {
+    /// Reads `rounding = <policy>` (default `floor`) and `rounding.remainder = <account>`
+    /// # Errors
+    /// The policy is invalid; the error names the setting
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let policy = match config.get("rounding") {
+            Some(policy) => policy.parse().map_err(|e| format!("rounding: {}", e))?,
+            None => RoundingPolicy::default(),
+        };
+        Ok(Rounding {
+            policy,
+            remainder_account: config.get("rounding.remainder").map(str::to_string),
+        })
+    }
+
+    /// Splits `total` into shares proportional to `weights`, each rounded by the policy. With
+    /// nothing to weigh, the whole total is left over.
+    pub fn split(&self, total: Units, weights: &[(&str, Units)]) -> Split {
+        let sum: u128 = weights.iter().map(|&(_, weight)| weight as u128).sum();
+        let shares: Vec<_> = weights
+            .iter()
+            .map(|&(account, weight)| {
+                let exact = (total as u128).saturating_mul(weight as u128);
+                let share = self.policy.divide(exact, sum);
+                (account.to_string(), share.min(Units::MAX as u128) as Units)
+            })
+            .collect();
+        let paid: i128 = shares.iter().map(|&(_, share)| share as i128).sum();
+        Split {
+            shares,
+            remainder: total as i128 - paid,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rounding_policy_divide_works() {
+        let all = [
+            RoundingPolicy::Floor,
+            RoundingPolicy::HalfUp,
+            RoundingPolicy::HalfEven,
+        ];
+
+        //act
+        let rounded = |numerator| all.map(|policy| policy.divide(numerator, 10));
+
+        assert_eq!(rounded(14), [1, 1, 1]);
+        assert_eq!(rounded(15), [1, 2, 2]);
+        assert_eq!(rounded(25), [2, 3, 2]);
+        assert_eq!(rounded(26), [2, 3, 3]);
+        assert_eq!(RoundingPolicy::HalfUp.divide(u128::MAX, u128::MAX - 1), 1);
+        assert_eq!(RoundingPolicy::HalfUp.divide(1, 0), 0);
+        assert_eq!("bankers".parse(), Ok(RoundingPolicy::HalfEven));
+        assert!("up".parse::<RoundingPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_rounding_split_leaves_a_remainder() {
+        let weights = [("ALICE", 1), ("BOB", 1), ("CAROL", 1)];
+        let config = Config::parse("rounding = half-up\nrounding.remainder = fees").unwrap();
+
+        //act
+        let floor = Rounding::default().split(100, &weights);
+        let half_up = Rounding::from_config(&config).unwrap();
+        let halves = half_up.split(5, &[("ALICE", 1), ("BOB", 1)]);
+        let nobody = Rounding::default().split(100, &[]);
+
+        assert_eq!(
+            floor.shares,
+            vec![
+                ("ALICE".to_string(), 33),
+                ("BOB".to_string(), 33),
+                ("CAROL".to_string(), 33)
+            ]
+        );
+        assert_eq!(floor.remainder, 1);
+        assert_eq!(half_up.remainder_account.as_deref(), Some("fees"));
+        assert_eq!(halves.remainder, -1);
+        assert_eq!(nobody.remainder, 100);
+        let invalid = Config::parse("rounding = up").unwrap();
+        assert!(Rounding::from_config(&invalid).is_err());
+    }
+}
@@ -0,0 +1,750 @@
+use crate::{
+    amount,
+    clock::{Clock, SystemClock, Timestamp},
+    dryrun::Op,
+    errors::ApplicationError,
+    events::LedgerEvent,
+    history::{Cursor, TxLog},
+    metrics::Metrics,
+    shared::SharedAccounts,
+    storage::LogEntry,
+    tx::{Tx, Units},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+use tracing::debug_span;
+
+/// The page size of `history` when the request doesn't give a limit
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// Invalid JSON was received
+pub const PARSE_ERROR: i64 = -32700;
+/// The JSON sent is not a valid request object
+pub const INVALID_REQUEST: i64 = -32600;
+/// The method does not exist
+pub const METHOD_NOT_FOUND: i64 = -32601;
+/// Invalid method parameters
+pub const INVALID_PARAMS: i64 = -32602;
+/// [`ApplicationError::NotFound`]
+pub const ACCOUNT_NOT_FOUND: i64 = -32001;
+/// [`ApplicationError::UnderFunded`]
+pub const UNDERFUNDED: i64 = -32002;
+/// [`ApplicationError::OverFunded`]
+pub const OVERFUNDED: i64 = -32003;
+/// [`ApplicationError::Storage`]
+pub const STORAGE_ERROR: i64 = -32004;
+/// [`ApplicationError::AlreadyExists`]
+pub const ACCOUNT_EXISTS: i64 = -32005;
+/// [`ApplicationError::Locked`]
+pub const LOCKED: i64 = -32006;
+/// [`ApplicationError::ApprovalRequired`]
+pub const APPROVAL_REQUIRED: i64 = -32007;
+/// [`ApplicationError::Unauthorized`]
+pub const UNAUTHORIZED: i64 = -32008;
+/// [`ApplicationError::LimitExceeded`]
+pub const LIMIT_EXCEEDED: i64 = -32009;
+/// [`ApplicationError::Blocked`]
+pub const BLOCKED: i64 = -32010;
+/// [`ApplicationError::Suspicious`]
+pub const SUSPICIOUS: i64 = -32011;
+/// [`ApplicationError::ConfirmationRequired`]
+pub const CONFIRMATION_REQUIRED: i64 = -32012;
+/// [`ApplicationError::Archived`]
+pub const ARCHIVED: i64 = -32013;
+/// [`ApplicationError::PermissionDenied`]
+pub const PERMISSION_DENIED: i64 = -32014;
+/// [`ApplicationError::CorruptData`]
+pub const CORRUPT_DATA: i64 = -32015;
+
+/// The error object of a JSON-RPC 2.0 response
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<ApplicationError> for RpcError {
+    fn from(e: ApplicationError) -> Self {
+        let code = match e {
+            ApplicationError::NotFound(_) => ACCOUNT_NOT_FOUND,
+            ApplicationError::UnderFunded(_, _) => UNDERFUNDED,
+            ApplicationError::OverFunded(_, _) => OVERFUNDED,
+            ApplicationError::Storage(_) => STORAGE_ERROR,
+            ApplicationError::AlreadyExists(_) => ACCOUNT_EXISTS,
+            ApplicationError::Locked(_) => LOCKED,
+            ApplicationError::ApprovalRequired(_) => APPROVAL_REQUIRED,
+            ApplicationError::Unauthorized(_) => UNAUTHORIZED,
+            ApplicationError::LimitExceeded(_, _) => LIMIT_EXCEEDED,
+            ApplicationError::Blocked(_) => BLOCKED,
+            ApplicationError::Suspicious(_) => SUSPICIOUS,
+            ApplicationError::ConfirmationRequired(_) => CONFIRMATION_REQUIRED,
+            ApplicationError::Archived(_) => ARCHIVED,
+            ApplicationError::PermissionDenied(_) => PERMISSION_DENIED,
+            ApplicationError::CorruptData(_) => CORRUPT_DATA,
+        };
+        RpcError::new(code, e.to_string())
+    }
+}
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    #[serde(default)]
+    id: Option<Value>,
+}
+
+#[derive(Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+    id: Value,
+}
+
+impl Response {
+    fn new(id: Value, outcome: Result<Value, RpcError>) -> Self {
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Response {
+            jsonrpc: "2.0",
+            result,
+            error,
+            id,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AccountParams {
+    account: String,
+}
+
+#[derive(Deserialize)]
+struct AmountParams {
+    account: String,
+    #[serde(deserialize_with = "amount::deserialize_units")]
+    amount: Units,
+}
+
+#[derive(Deserialize)]
+struct PromoParams {
+    account: String,
+    #[serde(deserialize_with = "amount::deserialize_units")]
+    amount: Units,
+    expires: Timestamp,
+}
+
+#[derive(Deserialize)]
+struct HistoryParams {
+    cursor: Option<Cursor>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct ApproveParams {
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct BatchParams {
+    ops: Vec<Op>,
+}
+
+#[derive(Deserialize)]
+struct SendParams {
+    sender: String,
+    recipient: String,
+    #[serde(deserialize_with = "amount::deserialize_units")]
+    amount: Units,
+    #[serde(default)]
+    confirmed: bool,
+}
+
+/// Exposes the ledger operations as JSON-RPC 2.0 methods over a newline-delimited stream.
+///
+/// Every line is either a single request object or a batch (array) of requests, and every
+/// response is written back as a single line. Parameters are passed by name:
+/// - `deposit` / `withdraw`: `{"account": "...", "amount": 1}`, the amount in the smallest unit
+///   or written out like `"12.34 USD"`, see [`crate::amount::deserialize_units`]
+/// - `send`: `{"sender": "...", "recipient": "...", "amount": 1}`, with `"confirmed": true` to
+///   go through even if it looks suspicious, paid like [`crate::accounts::Accounts::pay`] and
+///   [`crate::accounts::Accounts::pay_confirmed`]: the promotional credit spent, the transfer
+///   and the fee
+/// - `mint` / `burn`: as `deposit`, made as the ledger's principal, see
+///   [`crate::accounts::Accounts::mint`] and [`crate::accounts::Accounts::burn`]
+/// - `grant_promo`: `{"account": "...", "amount": 1, "expires": 1700000000000}`, the expiry in
+///   milliseconds since the Unix epoch, see [`crate::accounts::Accounts::grant_promo`]
+/// - `balance`: `{"account": "..."}`
+/// - `accounts`: no parameters
+/// - `goals`: no parameters, the savings goals of every account that has some, by account and
+///   goal name, see [`crate::goals`]
+/// - `approve`: `{"id": 1}`, approved as the ledger's principal, see
+///   [`crate::accounts::Accounts::approve`]. The transactions of the approved transfer, or none
+///   while it needs more approvals.
+/// - `pending`: no parameters, the transfers waiting for approval
+/// - `history`: without parameters, every committed transaction. With `{"cursor": .., "limit": 100}`
+///   (both optional), a [`crate::history::Page`] of timestamped entries.
+/// - `simulate`: an operation in the encoding of [`Op`], e.g.
+///   `{"Send": {"sender": "...", "recipient": "...", "amount": 1}}`, and returns what it would
+///   do without doing it, see [`crate::accounts::Accounts::simulate`]:
+///   `{"balances": {...}, "txs": [...], "error": null}`, with the error as an error object if
+///   the operation would fail
+/// - `simulate_batch`: `{"ops": [...]}`, operations as for `simulate` to simulate in order up
+///   to the first failing one, see [`crate::accounts::Accounts::simulate_batch`]:
+///   `{"balances": {...}, "txs": [...], "failure": null}`, with the balances of all accounts
+///   and the failure as `{"index": 0, "error": {...}}`
+#[derive(Clone)]
+pub struct RpcServer {
+    ledger: SharedAccounts,
+    tx_log: Arc<Mutex<TxLog>>,
+    subscribers: Arc<Mutex<Vec<Sender<Tx>>>>,
+    metrics: Arc<Metrics>,
+}
+
+impl RpcServer {
+    /// Creates a server that operates on the provided `ledger`
+    pub fn new(ledger: impl Into<SharedAccounts>) -> Self {
+        Self::with_history(ledger, TxLog::default())
+    }
+
+    /// Like [`RpcServer::new`], but starting from the `history` the `ledger` was replayed
+    /// from, so transactions committed before a restart are still found
+    pub fn with_history(ledger: impl Into<SharedAccounts>, history: TxLog) -> Self {
+        let ledger = ledger.into();
+        let tx_log = Arc::new(Mutex::new(history));
+        let subscribers: Arc<Mutex<Vec<Sender<Tx>>>> = Default::default();
+        let metrics: Arc<Metrics> = Default::default();
+        metrics.set_accounts(ledger.len());
+
+        // Logging, streaming and metrics all hang off the ledger's events
+        let (log, subs, m) = (tx_log.clone(), subscribers.clone(), metrics.clone());
+        ledger.subscribe(move |event| match event {
+            LedgerEvent::AccountCreated { .. } => m.account_created(),
+            LedgerEvent::TxCommitted(tx) => {
+                m.record_tx(tx);
+                log.lock().unwrap().push(LogEntry {
+                    timestamp: SystemClock.now(),
+                    tx: tx.clone(),
+                    actor: None,
+                });
+                // Subscribers that hung up are dropped
+                subs.lock().unwrap().retain(|s| s.send(tx.clone()).is_ok());
+            }
+            LedgerEvent::TxFailed { error, .. } => m.record_error(error),
+            LedgerEvent::ThresholdCrossed { .. }
+            | LedgerEvent::GoalReached { .. }
+            | LedgerEvent::AnomalyDetected { .. }
+            | LedgerEvent::OwnershipChanged { .. } => {}
+        });
+
+        RpcServer {
+            ledger,
+            tx_log,
+            subscribers,
+            metrics,
+        }
+    }
+
+    /// The ledger this server operates on, e.g. for background tasks
+    pub fn ledger(&self) -> &SharedAccounts {
+        &self.ledger
+    }
+
+    /// A server handling requests on the same ledger, history and subscribers as `principal`,
+    /// see [`SharedAccounts::acting_as`]
+    pub fn acting_as(&self, principal: &str) -> Self {
+        RpcServer {
+            ledger: self.ledger.acting_as(principal),
+            ..self.clone()
+        }
+    }
+
+    /// The metrics collected while serving requests
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// The transactions committed through this server, e.g. for paginated queries
+    pub fn history(&self) -> &Mutex<TxLog> {
+        &self.tx_log
+    }
+
+    /// Returns a channel that receives every [`Tx`] committed from now on.
+    /// Dropping the receiver unsubscribes.
+    pub fn subscribe(&self) -> Receiver<Tx> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Handles one line of input and returns the line to respond with.
+    /// Notifications (requests without an `id`) don't produce a response.
+    pub fn handle_line(&self, line: &str) -> Option<String> {
+        let response = match serde_json::from_str::<Value>(line) {
+            Ok(Value::Array(batch)) if !batch.is_empty() => {
+                let responses: Vec<Response> =
+                    batch.into_iter().filter_map(|r| self.handle(r)).collect();
+                if responses.is_empty() {
+                    return None;
+                }
+                serde_json::to_string(&responses)
+            }
+            Ok(Value::Array(_)) => serde_json::to_string(&Response::new(
+                Value::Null,
+                Err(RpcError::new(INVALID_REQUEST, "Empty batch")),
+            )),
+            Ok(request) => serde_json::to_string(&self.handle(request)?),
+            Err(e) => serde_json::to_string(&Response::new(
+                Value::Null,
+                Err(RpcError::new(PARSE_ERROR, e.to_string())),
+            )),
+        };
+        Some(response.expect("responses are always serializable"))
+    }
+
+    fn handle(&self, request: Value) -> Option<Response> {
+        let request: Request = match serde_json::from_value(request) {
+            Ok(request) => request,
+            Err(e) => {
+                return Some(Response::new(
+                    Value::Null,
+                    Err(RpcError::new(INVALID_REQUEST, e.to_string())),
+                ))
+            }
+        };
+        let outcome = if request.jsonrpc == "2.0" {
+            self.call(&request.method, request.params)
+        } else {
+            Err(RpcError::new(
+                INVALID_REQUEST,
+                "Unsupported jsonrpc version",
+            ))
+        };
+        request.id.map(|id| Response::new(id, outcome))
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let _span = debug_span!("rpc", method).entered();
+        let started = Instant::now();
+        let outcome = self.dispatch(method, params);
+        // Unknown methods aren't tracked to keep the number of label values bounded
+        if !matches!(&outcome, Err(e) if e.code == METHOD_NOT_FOUND) {
+            self.metrics.observe_latency(method, started.elapsed());
+        }
+        outcome
+    }
+
+    fn dispatch(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let ledger = &self.ledger;
+        let txs = match method {
+            "deposit" => {
+                let p: AmountParams = parse_params(params)?;
+                vec![ledger.deposit(&p.account, p.amount)?]
+            }
+            "withdraw" => {
+                let p: AmountParams = parse_params(params)?;
+                vec![ledger.withdraw(&p.account, p.amount)?]
+            }
+            "send" => {
+                let p: SendParams = parse_params(params)?;
+                if p.confirmed {
+                    ledger.pay_confirmed(&p.sender, &p.recipient, p.amount)?
+                } else {
+                    ledger.pay(&p.sender, &p.recipient, p.amount)?
+                }
+            }
+            "mint" => {
+                let p: AmountParams = parse_params(params)?;
+                vec![ledger.mint(&p.account, p.amount)?]
+            }
+            "burn" => {
+                let p: AmountParams = parse_params(params)?;
+                vec![ledger.burn(&p.account, p.amount)?]
+            }
+            "grant_promo" => {
+                let p: PromoParams = parse_params(params)?;
+                vec![ledger.grant_promo(&p.account, p.amount, p.expires)?]
+            }
+            "balance" => {
+                let p: AccountParams = parse_params(params)?;
+                return Ok(to_value(ledger.balance_of(&p.account)?));
+            }
+            "accounts" => return Ok(to_value(ledger.balances())),
+            "goals" => return Ok(to_value(ledger.goals())),
+            "approve" => {
+                let p: ApproveParams = parse_params(params)?;
+                ledger.approve(p.id)?.unwrap_or_default()
+            }
+            "pending" => return Ok(to_value(ledger.pending_transfers())),
+            "simulate" => {
+                let op: Op = parse_params(params)?;
+                let simulated = ledger.simulate(&op);
+                return Ok(json!({
+                    "balances": simulated.balances,
+                    "txs": simulated.dry_run.txs,
+                    "error": simulated.error.map(RpcError::from),
+                }));
+            }
+            "simulate_batch" => {
+                let p: BatchParams = parse_params(params)?;
+                let simulated = ledger.simulate_batch(&p.ops);
+                let failure = simulated.failure.map(
+                    |(index, error)| json!({ "index": index, "error": RpcError::from(error) }),
+                );
+                return Ok(json!({
+                    "balances": simulated.balances,
+                    "txs": simulated.dry_run.txs,
+                    "failure": failure,
+                }));
+            }
+            "history" if params.is_null() => {
+                let log = self.tx_log.lock().unwrap();
+                let txs: Vec<&Tx> = log.entries().iter().map(|entry| &entry.tx).collect();
+                return Ok(to_value(txs));
+            }
+            "history" => {
+                let p: HistoryParams = parse_params(params)?;
+                let log = self.tx_log.lock().unwrap();
+                return Ok(to_value(
+                    log.after(p.cursor, p.limit.unwrap_or(DEFAULT_PAGE_SIZE)),
+                ));
+            }
+            _ => {
+                return Err(RpcError::new(
+                    METHOD_NOT_FOUND,
+                    format!("Method {} not found", method),
+                ))
+            }
+        };
+        Ok(to_value(txs))
+    }
+
+    /// Reads requests from `reader` line by line and writes the responses to `writer` until EOF.
+    pub fn serve<R: BufRead, W: Write>(&self, reader: R, mut writer: W) -> io::Result<()> {
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle_line(&line) {
+                writeln!(writer, "{}", response)?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Serves requests from stdin and responds on stdout.
+    pub fn serve_stdio(&self) -> io::Result<()> {
+        self.serve(io::stdin().lock(), io::stdout().lock())
+    }
+
+    /// Listens on `addr` and serves every TCP connection on its own thread, sharing the ledger.
+    pub fn serve_tcp<A: ToSocketAddrs>(&self, addr: A) -> io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = self.clone();
+            thread::spawn(move || {
+                let reader = BufReader::new(stream.try_clone()?);
+                server.serve(reader, stream)
+            });
+        }
+        Ok(())
+    }
+}
+
+fn parse_params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+fn to_value<T: Serialize>(value: T) -> Value {
+    serde_json::to_value(value).expect("ledger types are always serializable")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accounts::Accounts;
+    use crate::clock::Timestamp;
+
+    fn call(server: &RpcServer, request: Value) -> Value {
+        let response = server.handle_line(&request.to_string()).unwrap();
+        serde_json::from_str(&response).unwrap()
+    }
+
+    #[test]
+    fn test_rpc_deposit_and_balance_works() {
+        let server = RpcServer::new(Accounts::new());
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "method": "deposit", "params": {"account": "ALICE", "amount": 100}, "id": 1}),
+        );
+        assert_eq!(
+            response["result"],
+            json!([{"Deposit": {"account": "ALICE", "amount": 100}}])
+        );
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "method": "balance", "params": {"account": "ALICE"}, "id": 2}),
+        );
+        assert_eq!(response, json!({"jsonrpc": "2.0", "result": 100, "id": 2}));
+    }
+
+    #[test]
+    fn test_rpc_accepts_written_amounts() {
+        let server = RpcServer::new(Accounts::new());
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "method": "deposit", "params": {"account": "ALICE", "amount": "1,500 CBX"}, "id": 1}),
+        );
+        assert_eq!(
+            response["result"],
+            json!([{"Deposit": {"account": "ALICE", "amount": 1500}}])
+        );
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "method": "send", "params": {"sender": "ALICE", "recipient": "BOB", "amount": "lots"}, "id": 2}),
+        );
+        assert_eq!(response["error"]["code"], json!(INVALID_PARAMS));
+    }
+
+    #[test]
+    fn test_rpc_application_error_is_mapped() {
+        let server = RpcServer::new(Accounts::new());
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "method": "withdraw", "params": {"account": "ALICE", "amount": 1}, "id": 1}),
+        );
+        assert_eq!(response["error"]["code"], json!(ACCOUNT_NOT_FOUND));
+        assert!(response.get("result").is_none());
+    }
+
+    #[test]
+    fn test_rpc_approve_acts_as_principal() {
+        let servers = [None, Some("BOB")].map(|principal| {
+            let mut ledger = Accounts::new();
+            ledger.deposit("ALICE", 5000).unwrap();
+            ledger.set_multisig("ALICE", "1 of BOB above 1000".parse().unwrap());
+            ledger.set_principal(principal.map(str::to_string));
+            let server = RpcServer::new(ledger);
+            call(
+                &server,
+                json!({"jsonrpc": "2.0", "method": "send", "params": {"sender": "ALICE", "recipient": "EVE", "amount": 3000}, "id": 1}),
+            );
+            server
+        });
+
+        //act
+        let [anonymous, bob] = servers.map(|server| {
+            call(
+                &server,
+                json!({"jsonrpc": "2.0", "method": "approve", "params": {"id": 1}, "id": 2}),
+            )
+        });
+
+        assert_eq!(anonymous["error"]["code"], json!(UNAUTHORIZED));
+        assert_eq!(bob["result"].as_array().map(Vec::len), Some(2));
+    }
+
+    #[test]
+    fn test_rpc_simulate_changes_nothing() {
+        let server = RpcServer::new(Accounts::new());
+        call(
+            &server,
+            json!({"jsonrpc": "2.0", "method": "deposit", "params": {"account": "ALICE", "amount": 100}, "id": 1}),
+        );
+
+        //act
+        let simulated = call(
+            &server,
+            json!({"jsonrpc": "2.0", "method": "simulate", "params": {"Send": {"sender": "ALICE", "recipient": "BOB", "amount": 30}}, "id": 2}),
+        );
+        let failing = call(
+            &server,
+            json!({"jsonrpc": "2.0", "method": "simulate", "params": {"Withdraw": {"account": "ALICE", "amount": 500}}, "id": 3}),
+        );
+
+        assert_eq!(
+            simulated["result"]["balances"],
+            json!({"ALICE": 70, "BOB": 30})
+        );
+        assert_eq!(simulated["result"]["txs"].as_array().unwrap().len(), 2);
+        assert_eq!(simulated["result"]["error"], Value::Null);
+        assert_eq!(failing["result"]["balances"], json!({"ALICE": 100}));
+        assert_eq!(failing["result"]["error"]["code"], json!(UNDERFUNDED));
+        assert_eq!(server.ledger().balances().len(), 1);
+
+        //act
+        let batch = call(
+            &server,
+            json!({"jsonrpc": "2.0", "method": "simulate_batch", "params": {"ops": [
+                {"Withdraw": {"account": "ALICE", "amount": 60}},
+                {"Withdraw": {"account": "ALICE", "amount": 60}}
+            ]}, "id": 4}),
+        );
+
+        assert_eq!(batch["result"]["balances"], json!({"ALICE": 40}));
+        assert_eq!(batch["result"]["failure"]["index"], 1);
+        assert_eq!(
+            batch["result"]["failure"]["error"]["code"],
+            json!(UNDERFUNDED)
+        );
+    }
+
+    #[test]
+    fn test_rpc_protocol_errors() {
+        let server = RpcServer::new(Accounts::new());
+
+        let response: Value = serde_json::from_str(&server.handle_line("{nope").unwrap()).unwrap();
+        assert_eq!(response["error"]["code"], json!(PARSE_ERROR));
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "method": "transmute", "id": 1}),
+        );
+        assert_eq!(response["error"]["code"], json!(METHOD_NOT_FOUND));
+
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "method": "deposit", "params": {"account": "ALICE"}, "id": 1}),
+        );
+        assert_eq!(response["error"]["code"], json!(INVALID_PARAMS));
+    }
+
+    #[test]
+    fn test_rpc_batch_skips_notifications() {
+        let server = RpcServer::new(Accounts::new());
+
+        let response = call(
+            &server,
+            json!([
+                {"jsonrpc": "2.0", "method": "deposit", "params": {"account": "ALICE", "amount": 10}},
+                {"jsonrpc": "2.0", "method": "balance", "params": {"account": "ALICE"}, "id": "b"}
+            ]),
+        );
+        assert_eq!(
+            response,
+            json!([{"jsonrpc": "2.0", "result": 10, "id": "b"}])
+        );
+        assert_eq!(server.tx_log.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_rpc_history_starts_from_the_replayed_log() {
+        let replayed = LogEntry {
+            timestamp: Timestamp(1),
+            tx: Tx::Deposit {
+                account: "ALICE".into(),
+                amount: 10,
+            },
+            actor: Some("ALICE".to_string()),
+        };
+        let mut ledger = Accounts::new();
+        ledger.apply(&replayed.tx).unwrap();
+        let server = RpcServer::with_history(ledger, [replayed.clone()].into_iter().collect());
+
+        //act
+        call(
+            &server,
+            json!({"jsonrpc": "2.0", "method": "deposit", "params": {"account": "BOB", "amount": 5}, "id": 1}),
+        );
+
+        let log = server.history().lock().unwrap();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log.entries()[0].timestamp, replayed.timestamp);
+        assert_eq!(log.entries()[0].actor, replayed.actor);
+        drop(log);
+        let response = call(
+            &server,
+            json!({"jsonrpc": "2.0", "method": "history", "id": 2}),
+        );
+        assert_eq!(response["result"][0]["Deposit"]["account"], json!("ALICE"));
+        assert_eq!(response["result"][1]["Deposit"]["account"], json!("BOB"));
+    }
+
+    #[test]
+    fn test_rpc_subscribe_receives_committed_txs() {
+        let server = RpcServer::new(Accounts::new());
+        let receiver = server.subscribe();
+        drop(server.subscribe());
+
+        call(
+            &server,
+            json!({"jsonrpc": "2.0", "method": "deposit", "params": {"account": "ALICE", "amount": 10}, "id": 1}),
+        );
+
+        assert_eq!(
+            receiver.try_recv(),
+            Ok(Tx::Deposit {
+                account: "ALICE".into(),
+                amount: 10
+            })
+        );
+        assert_eq!(server.subscribers.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_rpc_records_metrics() {
+        let server = RpcServer::new(Accounts::new());
+
+        call(
+            &server,
+            json!({"jsonrpc": "2.0", "method": "deposit", "params": {"account": "ALICE", "amount": 10}, "id": 1}),
+        );
+        call(
+            &server,
+            json!({"jsonrpc": "2.0", "method": "withdraw", "params": {"account": "ALICE", "amount": 20}, "id": 2}),
+        );
+        call(
+            &server,
+            json!({"jsonrpc": "2.0", "method": "nope", "id": 3}),
+        );
+
+        let out = server.metrics().render();
+        assert!(out.contains("crabbux_transactions_total{type=\"deposit\"} 1\n"));
+        assert!(out.contains("crabbux_errors_total{error=\"underfunded\"} 1\n"));
+        assert!(out.contains("crabbux_accounts 1\n"));
+        assert!(out.contains("crabbux_request_duration_seconds_count{method=\"withdraw\"} 1\n"));
+        assert!(!out.contains("method=\"nope\""));
+    }
+
+    #[test]
+    fn test_rpc_serve_writes_one_line_per_response() {
+        let server = RpcServer::new(Accounts::new());
+        let input = "{\"jsonrpc\":\"2.0\",\"method\":\"accounts\",\"id\":1}\n\n{\"jsonrpc\":\"2.0\",\"method\":\"history\",\"id\":2}\n";
+        let mut output = vec![];
+
+        server.serve(input.as_bytes(), &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), 2);
+    }
+}
@@ -0,0 +1,152 @@
+use crate::{
+    plugins::LedgerApi,
+    tx::{Tx, Units},
+};
+use rhai::{Dynamic, Engine, EvalAltResult, Map, INT};
+use std::cell::RefCell;
+use std::error::Error;
+use std::rc::Rc;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Runs a [rhai](https://rhai.rs) script against `ledger` and returns the transactions it committed.
+///
+/// Scripts can call:
+/// - `balance(account)` and `accounts()` (a map of account names to balances)
+/// - `deposit(account, amount)`, `withdraw(account, amount)` and `send(sender, recipient, amount)`
+///
+/// For example, sweeping 10% of every account over 1000 to savings:
+/// ```text
+/// for account in accounts().keys() {
+///     let balance = balance(account);
+///     if balance > 1000 { send(account, "savings", balance / 10); }
+/// }
+/// ```
+/// A failing ledger operation aborts the script; transactions committed before that stay committed.
+pub fn run_script(
+    source: &str,
+    ledger: Rc<RefCell<dyn LedgerApi>>,
+) -> Result<Vec<Tx>, Box<dyn Error>> {
+    let committed: Rc<RefCell<Vec<Tx>>> = Default::default();
+    let mut engine = Engine::new();
+
+    let l = ledger.clone();
+    engine.register_fn("balance", move |account: &str| -> ScriptResult<INT> {
+        let balance = l.borrow_mut().balance_of(account).map_err(runtime)?;
+        to_int(balance)
+    });
+
+    let l = ledger.clone();
+    engine.register_fn("accounts", move || -> ScriptResult<Map> {
+        let mut map = Map::new();
+        for (account, balance) in l.borrow_mut().accounts().map_err(runtime)? {
+            map.insert(account.into(), Dynamic::from_int(to_int(balance)?));
+        }
+        Ok(map)
+    });
+
+    let (l, c) = (ledger.clone(), committed.clone());
+    engine.register_fn(
+        "deposit",
+        move |account: &str, amount: INT| -> ScriptResult<()> {
+            let tx = l
+                .borrow_mut()
+                .deposit(account, to_amount(amount)?)
+                .map_err(runtime)?;
+            c.borrow_mut().push(tx);
+            Ok(())
+        },
+    );
+
+    let (l, c) = (ledger.clone(), committed.clone());
+    engine.register_fn(
+        "withdraw",
+        move |account: &str, amount: INT| -> ScriptResult<()> {
+            let tx = l
+                .borrow_mut()
+                .withdraw(account, to_amount(amount)?)
+                .map_err(runtime)?;
+            c.borrow_mut().push(tx);
+            Ok(())
+        },
+    );
+
+    let (l, c) = (ledger, committed.clone());
+    engine.register_fn(
+        "send",
+        move |sender: &str, recipient: &str, amount: INT| -> ScriptResult<()> {
+            let (tx1, tx2) = l
+                .borrow_mut()
+                .send(sender, recipient, to_amount(amount)?)
+                .map_err(runtime)?;
+            c.borrow_mut().extend([tx1, tx2]);
+            Ok(())
+        },
+    );
+
+    engine.run(source).map_err(|e| e.to_string())?;
+    drop(engine);
+    Ok(committed.take())
+}
+
+fn runtime(e: impl ToString) -> Box<EvalAltResult> {
+    e.to_string().into()
+}
+
+fn to_amount(amount: INT) -> ScriptResult<Units> {
+    Units::try_from(amount).map_err(|_| runtime(format!("Invalid amount {}", amount)))
+}
+
+fn to_int(amount: Units) -> ScriptResult<INT> {
+    INT::try_from(amount).map_err(|_| runtime(format!("Amount {} is too large", amount)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accounts::Accounts;
+
+    #[test]
+    fn test_run_script_sweeps_accounts() {
+        let ledger = Rc::new(RefCell::new(Accounts::new()));
+        ledger.borrow_mut().deposit("ALICE", 5000).unwrap();
+        ledger.borrow_mut().deposit("BOB", 500).unwrap();
+
+        let txs = run_script(
+            r#"
+            for account in accounts().keys() {
+                let balance = balance(account);
+                if balance > 1000 { send(account, "savings", balance / 10); }
+            }
+            "#,
+            ledger.clone(),
+        )
+        .unwrap();
+
+        assert_eq!(txs.len(), 2);
+        assert_eq!(ledger.borrow().balance_of("ALICE"), Ok(&4500));
+        assert_eq!(ledger.borrow().balance_of("BOB"), Ok(&500));
+        assert_eq!(ledger.borrow().balance_of("savings"), Ok(&500));
+    }
+
+    #[test]
+    fn test_run_script_ledger_errors_abort() {
+        let ledger = Rc::new(RefCell::new(Accounts::new()));
+
+        let result = run_script(
+            r#"deposit("ALICE", 10); withdraw("ALICE", 20); deposit("BOB", 1);"#,
+            ledger.clone(),
+        );
+
+        assert!(result.unwrap_err().to_string().contains("underfunded"));
+        assert_eq!(ledger.borrow().balance_of("ALICE"), Ok(&10));
+        assert!(ledger.borrow().balance_of("BOB").is_err());
+    }
+
+    #[test]
+    fn test_run_script_rejects_negative_amounts() {
+        let ledger = Rc::new(RefCell::new(Accounts::new()));
+
+        assert!(run_script(r#"deposit("ALICE", -1);"#, ledger).is_err());
+    }
+}
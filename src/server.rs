@@ -0,0 +1,816 @@
+use crate::{
+    apikey::{ApiKeys, AuthError, Operation},
+    dryrun::Op,
+    history::Cursor,
+    query::{AccountFilter, AccountQuery},
+    ratelimit::RateLimiter,
+    rpc::{RpcServer, DEFAULT_PAGE_SIZE},
+    shutdown::Shutdown,
+    tx::Tx,
+};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response, Server, SslConfig, StatusCode};
+use tracing::error;
+use tungstenite::{handshake::derive_accept_key, protocol::Role, Message, WebSocket};
+
+/// How often [`HttpServer::run_until`] checks whether to shut down
+const SHUTDOWN_POLL: Duration = Duration::from_millis(100);
+
+/// Server mode: exposes the ledger over HTTP, or HTTPS with [`HttpServer::bind_tls`].
+///
+/// Routes:
+/// - `POST /rpc`: a JSON-RPC request (or batch) as accepted by [`RpcServer`]
+/// - `GET /txs[?cursor=<cursor>&limit=<n>]`: a page of the committed transactions, oldest first,
+///   as `{"entries": [...], "next_cursor": ...}`; see [`crate::history::TxLog::after`]
+/// - `GET /accounts[?sort=<name|balance|activity>&desc=true&offset=<n>&limit=<n>]`: the accounts
+///   with their balances and numbers of transactions, as a JSON array ordered by
+///   [`AccountQuery`]; `prefix=<account>` and `match=<regex>` filter them, see
+///   [`AccountFilter`]
+/// - `GET /accounts/<name>/txs`: the committed transactions affecting account `name`, oldest first,
+///   as a JSON array of log entries; see [`crate::history::TxLog::account_entries`]
+/// - `GET /metrics`: the [`crate::metrics::Metrics`] in the Prometheus text format
+/// - `GET /ws/txs[?account=<name>]`: a WebSocket pushing every committed [`Tx`] as JSON,
+///   optionally only those affecting `account`
+///
+/// With rate limits, requests beyond them are answered with `429 Too Many Requests` and a
+/// `Retry-After` header, see [`HttpServer::with_client_limit`] and [`HttpServer::with_account_limit`].
+///
+/// With API keys, every request must carry one as `Authorization: Bearer <secret>` or
+/// `X-Api-Key: <secret>`, else it is answered with `401 Unauthorized`; requests the key may not
+/// make with `403 Forbidden`, see [`HttpServer::with_api_keys`].
+pub struct HttpServer {
+    server: Server,
+    rpc: RpcServer,
+    limits: Arc<Limits>,
+    keys: Arc<ApiKeys>,
+}
+
+#[derive(Debug, Default)]
+struct Limits {
+    client: Option<RateLimiter>,
+    account: Option<RateLimiter>,
+}
+
+impl HttpServer {
+    /// Binds to `addr` without accepting connections yet
+    pub fn bind<A: ToSocketAddrs>(addr: A, rpc: RpcServer) -> io::Result<Self> {
+        let server = Server::http(addr).map_err(io::Error::other)?;
+        Ok(HttpServer::new(server, rpc))
+    }
+
+    /// Binds to `addr` serving HTTPS with the PEM encoded `certificate` chain and `private_key`
+    /// # Errors
+    /// Binding fails or the certificate or key can't be read, with [`io::ErrorKind::InvalidInput`]
+    pub fn bind_tls<A: ToSocketAddrs>(
+        addr: A,
+        rpc: RpcServer,
+        certificate: Vec<u8>,
+        private_key: Vec<u8>,
+    ) -> io::Result<Self> {
+        let config = SslConfig {
+            certificate,
+            private_key,
+        };
+        let server = Server::https(addr, config).map_err(|e| match e.downcast::<io::Error>() {
+            Ok(e) => *e,
+            Err(e) => io::Error::new(io::ErrorKind::InvalidInput, e),
+        })?;
+        Ok(HttpServer::new(server, rpc))
+    }
+
+    fn new(server: Server, rpc: RpcServer) -> Self {
+        HttpServer {
+            server,
+            rpc,
+            limits: Default::default(),
+            keys: Default::default(),
+        }
+    }
+
+    /// Limits the requests of every client IP address
+    pub fn with_client_limit(mut self, limiter: RateLimiter) -> Self {
+        self.limits_mut().client = Some(limiter);
+        self
+    }
+
+    /// Limits the requests concerning every account: JSON-RPC calls by their `account` or
+    /// `sender` parameter, and `GET /accounts/<name>/txs`. A batch counts once for every call.
+    pub fn with_account_limit(mut self, limiter: RateLimiter) -> Self {
+        self.limits_mut().account = Some(limiter);
+        self
+    }
+
+    /// Only serves requests authorized by one of `keys`. JSON-RPC calls act on their `account`
+    /// or `sender` parameter, and `deposit`, `withdraw`, `mint`, `burn`, `grant_promo`, `send`
+    /// and `approve` write. Listing
+    /// every account's balances or transactions, or approving, needs a key that isn't scoped.
+    ///
+    /// The calls are performed as the principal named like their key rather than the ledger's,
+    /// see [`RpcServer::acting_as`], so e.g. only owners of an account can withdraw from it, and
+    /// a transfer is approved by its key's name.
+    pub fn with_api_keys(mut self, keys: ApiKeys) -> Self {
+        self.keys = Arc::new(keys);
+        self
+    }
+
+    fn limits_mut(&mut self) -> &mut Limits {
+        Arc::get_mut(&mut self.limits).expect("the limits are only shared once running")
+    }
+
+    /// The address the server is listening on
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Accepts requests forever, handling each one on its own thread.
+    pub fn run(self) {
+        for request in self.server.incoming_requests() {
+            self.spawn(request);
+        }
+    }
+
+    /// Accepts requests until `shutdown` is requested, then waits for the requests in flight
+    /// except WebSocket streams, which end with the process.
+    pub fn run_until(self, shutdown: &Shutdown) {
+        let mut handlers: Vec<JoinHandle<_>> = vec![];
+        while !shutdown.is_requested() {
+            match self.server.recv_timeout(SHUTDOWN_POLL) {
+                Ok(Some(request)) => {
+                    let streaming = request.url().starts_with("/ws/");
+                    let handler = self.spawn(request);
+                    if !streaming {
+                        handlers.retain(|handler| !handler.is_finished());
+                        handlers.push(handler);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => error!(error = %e, "couldn't accept request"),
+            }
+        }
+        for handler in handlers {
+            let _ = handler.join();
+        }
+    }
+
+    fn spawn(&self, request: Request) -> JoinHandle<io::Result<()>> {
+        let (rpc, limits, keys) = (self.rpc.clone(), self.limits.clone(), self.keys.clone());
+        thread::spawn(move || handle(&rpc, &limits, &keys, request))
+    }
+}
+
+fn handle(
+    rpc: &RpcServer,
+    limits: &Limits,
+    keys: &ApiKeys,
+    mut request: Request,
+) -> io::Result<()> {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+    if let Some(limiter) = &limits.client {
+        let client = request
+            .remote_addr()
+            .map_or("unknown".to_string(), |addr| addr.ip().to_string());
+        if let Err(retry_after) = limiter.acquire(&client) {
+            return too_many_requests(request, retry_after);
+        }
+    }
+    let read = |accounts| Operation {
+        writes: false,
+        accounts,
+    };
+    let operation = match (request.method(), path) {
+        (Method::Post, "/rpc") => None,
+        (Method::Get, "/metrics") => Some(read(Some(vec![]))),
+        (Method::Get, "/ws/txs") => Some(read(
+            query_param(query, "account").map(|account| vec![account.to_string()]),
+        )),
+        (Method::Get, "/accounts") => Some(read(None)),
+        (Method::Get, _) => Some(read(
+            account_txs_path(path).map(|account| vec![account.to_string()]),
+        )),
+        _ => Some(read(Some(vec![]))),
+    };
+    if let Some(operation) = operation {
+        if let Err(e) = keys.authorize(api_key(&request), &[operation]) {
+            return refuse(request, e);
+        }
+    }
+    match (request.method(), path) {
+        (Method::Post, "/rpc") => {
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body)?;
+            let rpc = match keys.authorize(api_key(&request), &rpc_operations(&body)) {
+                Ok(Some(key)) => &rpc.acting_as(&key.name),
+                Ok(None) => rpc,
+                Err(e) => return refuse(request, e),
+            };
+            if let Err(retry_after) = acquire_accounts(limits, &rpc_accounts(&body)) {
+                return too_many_requests(request, retry_after);
+            }
+            match rpc.handle_line(&body) {
+                Some(response) => request.respond(
+                    Response::from_string(response)
+                        .with_header(header("Content-Type", "application/json")),
+                ),
+                None => request.respond(Response::empty(StatusCode(204))),
+            }
+        }
+        (Method::Get, "/metrics") => request.respond(
+            Response::from_string(rpc.metrics().render())
+                .with_header(header("Content-Type", "text/plain; version=0.0.4")),
+        ),
+        (Method::Get, "/txs") => match list_txs(rpc, query) {
+            Ok(page) => request.respond(
+                Response::from_string(page).with_header(header("Content-Type", "application/json")),
+            ),
+            Err(message) => request.respond(Response::from_string(message).with_status_code(400)),
+        },
+        (Method::Get, "/ws/txs") => {
+            let account = query_param(query, "account").map(Cow::into_owned);
+            stream_txs(rpc, request, account)
+        }
+        (Method::Get, "/accounts") => match list_accounts(rpc, query) {
+            Ok(accounts) => request.respond(
+                Response::from_string(accounts)
+                    .with_header(header("Content-Type", "application/json")),
+            ),
+            Err(message) => request.respond(Response::from_string(message).with_status_code(400)),
+        },
+        (Method::Get, _) => match account_txs_path(path) {
+            Some(account) => {
+                if let Err(retry_after) = acquire_accounts(limits, &[&account]) {
+                    return too_many_requests(request, retry_after);
+                }
+                request.respond(
+                    Response::from_string(account_txs(rpc, &account))
+                        .with_header(header("Content-Type", "application/json")),
+                )
+            }
+            None => request.respond(Response::empty(StatusCode(404))),
+        },
+        _ => request.respond(Response::empty(StatusCode(404))),
+    }
+}
+
+/// The accounts the JSON-RPC request or batch in `body` acts on, once per call
+fn rpc_accounts(body: &str) -> Vec<String> {
+    let calls = match serde_json::from_str(body) {
+        Ok(Value::Array(batch)) => batch,
+        Ok(call) => vec![call],
+        Err(_) => vec![],
+    };
+    calls
+        .iter()
+        .filter_map(|call| {
+            let params = call.get("params")?;
+            params.get("account").or(params.get("sender"))?.as_str()
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+/// What each call of the JSON-RPC request or batch in `body` does, for [`ApiKeys::authorize`]
+fn rpc_operations(body: &str) -> Vec<Operation> {
+    let calls = match serde_json::from_str(body) {
+        Ok(Value::Array(batch)) => batch,
+        Ok(call) => vec![call],
+        Err(_) => vec![],
+    };
+    calls
+        .iter()
+        .map(|call| {
+            let method = call
+                .get("method")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let param = |name| {
+                call.get("params")
+                    .and_then(|params| params.get(name))
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+            };
+            let accounts = match method {
+                "deposit" | "withdraw" | "mint" | "burn" | "grant_promo" | "balance" => {
+                    Some(param("account").into_iter().collect())
+                }
+                "send" => Some(param("sender").into_iter().collect()),
+                // The projected balances of all involved accounts are returned, and those of
+                // every account by `simulate_batch`
+                "simulate" => call
+                    .get("params")
+                    .and_then(|params| serde_json::from_value::<Op>(params.clone()).ok())
+                    .map(|op| op.accounts().into_iter().map(str::to_string).collect()),
+                _ => None,
+            };
+            Operation {
+                writes: matches!(
+                    method,
+                    "deposit" | "withdraw" | "mint" | "burn" | "grant_promo" | "send" | "approve"
+                ),
+                accounts,
+            }
+        })
+        .collect()
+}
+
+/// Takes a token for each of `accounts` from the account limit, if there is one
+fn acquire_accounts(limits: &Limits, accounts: &[impl AsRef<str>]) -> Result<(), Duration> {
+    let Some(limiter) = &limits.account else {
+        return Ok(());
+    };
+    accounts
+        .iter()
+        .try_for_each(|account| limiter.acquire(account.as_ref()))
+}
+
+/// The secret of the API key the request carries, if any
+fn api_key(request: &Request) -> Option<&str> {
+    let value = |field| {
+        request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv(field))
+            .map(|h| h.value.as_str().trim())
+    };
+    value("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| value("X-Api-Key"))
+}
+
+fn refuse(request: Request, error: AuthError) -> io::Result<()> {
+    let response = Response::from_string(error.to_string());
+    request.respond(match error {
+        AuthError::Unauthenticated => response
+            .with_status_code(401)
+            .with_header(header("WWW-Authenticate", "Bearer")),
+        AuthError::Forbidden(_) => response.with_status_code(403),
+    })
+}
+
+fn too_many_requests(request: Request, retry_after: Duration) -> io::Result<()> {
+    let seconds = retry_after.as_millis().div_ceil(1000).max(1);
+    request.respond(
+        Response::from_string("rate limit exceeded")
+            .with_status_code(429)
+            .with_header(header("Retry-After", &seconds.to_string())),
+    )
+}
+
+/// The JSON encoded [`crate::history::Page`] for `?cursor=<cursor>&limit=<n>`, both optional
+fn list_txs(rpc: &RpcServer, query: &str) -> Result<String, String> {
+    let cursor = query_param(query, "cursor")
+        .as_deref()
+        .map(|c| c.parse().map(Cursor))
+        .transpose()
+        .map_err(|_| "invalid cursor".to_string())?;
+    let limit = query_param(query, "limit")
+        .as_deref()
+        .map_or(Ok(DEFAULT_PAGE_SIZE), str::parse)
+        .map_err(|_| "invalid limit".to_string())?;
+    let log = rpc.history().lock().unwrap();
+    Ok(serde_json::to_string(&log.after(cursor, limit)).expect("pages are always serializable"))
+}
+
+/// The JSON encoded accounts for
+/// `?sort=<key>&desc=<true|false>&offset=<n>&limit=<n>&prefix=<account>&match=<regex>`, all
+/// optional
+fn list_accounts(rpc: &RpcServer, query: &str) -> Result<String, String> {
+    let mut filter = AccountFilter::default();
+    if let Some(prefix) = query_param(query, "prefix").as_deref() {
+        filter = filter.prefix(prefix);
+    }
+    if let Some(pattern) = query_param(query, "match").as_deref() {
+        filter = filter.regex(pattern)?;
+    }
+    let accounts = AccountQuery {
+        sort: query_param(query, "sort")
+            .as_deref()
+            .map_or(Ok(Default::default()), str::parse)?,
+        descending: query_param(query, "desc")
+            .as_deref()
+            .map_or(Ok(false), str::parse)
+            .map_err(|_| "invalid desc".to_string())?,
+        offset: query_param(query, "offset")
+            .as_deref()
+            .map_or(Ok(0), str::parse)
+            .map_err(|_| "invalid offset".to_string())?,
+        limit: query_param(query, "limit")
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(|_| "invalid limit".to_string())?,
+        filter,
+    };
+    let accounts = rpc.ledger().read(|ledger| accounts.run(ledger));
+    Ok(serde_json::to_string(&accounts).expect("account listings are always serializable"))
+}
+
+/// The decoded account name in `/accounts/<name>/txs`, see [`decode`]
+fn account_txs_path(path: &str) -> Option<Cow<'_, str>> {
+    path.strip_prefix("/accounts/")?
+        .strip_suffix("/txs")
+        .filter(|account| !account.is_empty() && !account.contains('/'))
+        .map(decode)
+}
+
+/// The JSON encoded log entries affecting `account`
+fn account_txs(rpc: &RpcServer, account: &str) -> String {
+    let log = rpc.history().lock().unwrap();
+    let entries: Vec<_> = log
+        .account_entries(account)
+        .map(|(_, entry)| entry)
+        .collect();
+    serde_json::to_string(&entries).expect("log entries are always serializable")
+}
+
+/// Upgrades the request to a WebSocket and forwards committed transactions until the client goes away.
+fn stream_txs(rpc: &RpcServer, request: Request, account: Option<String>) -> io::Result<()> {
+    let key = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Sec-WebSocket-Key"))
+        .map(|h| derive_accept_key(h.value.as_bytes()));
+    let Some(accept) = key else {
+        return request.respond(Response::empty(StatusCode(400)));
+    };
+
+    // Subscribe before the handshake completes so nothing committed in between is missed
+    let txs = rpc.subscribe();
+    let response = Response::empty(StatusCode(101))
+        .with_header(header("Connection", "Upgrade"))
+        .with_header(header("Sec-WebSocket-Accept", &accept));
+    let stream = request.upgrade("websocket", response);
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+
+    for tx in txs
+        .iter()
+        .filter(|tx| matches_account(tx, account.as_deref()))
+    {
+        let json = serde_json::to_string(&tx).expect("transactions are always serializable");
+        if socket.send(Message::text(json)).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn matches_account(tx: &Tx, account: Option<&str>) -> bool {
+    account.is_none_or(|account| tx.account() == account)
+}
+
+/// The decoded value of the parameter `name` in the query string `query`, see [`decode`]
+fn query_param<'a>(query: &'a str, name: &str) -> Option<Cow<'a, str>> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| decode(&key.replace('+', " ")) == name)
+        .map(|(_, value)| {
+            if value.contains('+') {
+                Cow::Owned(decode(&value.replace('+', " ")).into_owned())
+            } else {
+                decode(value)
+            }
+        })
+}
+
+/// `s` with its `%XX` escapes decoded, keeping malformed ones as they are and replacing what
+/// isn't UTF-8 then
+fn decode(s: &str) -> Cow<'_, str> {
+    if !s.contains('%') {
+        return Cow::Borrowed(s);
+    }
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| bytes[i] == b'%' && hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    Cow::Owned(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field, value).expect("header names and values are ASCII")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{accounts::Accounts, tx::Units};
+
+    fn deposit(rpc: &RpcServer, account: &str, amount: Units) {
+        let request = format!(
+            r#"{{"jsonrpc":"2.0","method":"deposit","params":{{"account":"{}","amount":{}}},"id":1}}"#,
+            account, amount
+        );
+        assert!(rpc.handle_line(&request).unwrap().contains("result"));
+    }
+
+    #[test]
+    fn test_query_param_works() {
+        let param = |query, name| query_param(query, name).map(Cow::into_owned);
+        assert_eq!(
+            param("a=1&account=ALICE", "account").as_deref(),
+            Some("ALICE")
+        );
+        assert_eq!(param("", "account"), None);
+        assert_eq!(
+            param("account=ALICE%20%26%20BOB", "account").as_deref(),
+            Some("ALICE & BOB")
+        );
+        assert_eq!(param("match=A%2B+B", "match").as_deref(), Some("A+ B"));
+        assert_eq!(
+            param("%61ccount=ALICE", "account").as_deref(),
+            Some("ALICE")
+        );
+        assert_eq!(param("prefix=100%", "prefix").as_deref(), Some("100%"));
+        assert_eq!(param("prefix=%+1", "prefix").as_deref(), Some("% 1"));
+    }
+
+    #[test]
+    fn test_list_txs_pages_through_history() {
+        let rpc = RpcServer::new(Accounts::new());
+        for amount in 1..=3 {
+            deposit(&rpc, "ALICE", amount);
+        }
+
+        //act
+        let first: serde_json::Value =
+            serde_json::from_str(&list_txs(&rpc, "limit=2").unwrap()).unwrap();
+        let cursor = first["next_cursor"].to_string();
+        let second: serde_json::Value =
+            serde_json::from_str(&list_txs(&rpc, &format!("cursor={}&limit=2", cursor)).unwrap())
+                .unwrap();
+
+        assert_eq!(first["entries"].as_array().unwrap().len(), 2);
+        assert_eq!(second["entries"][0]["tx"]["Deposit"]["amount"], 3);
+        assert!(second["next_cursor"].is_null());
+        assert!(list_txs(&rpc, "limit=many").is_err());
+    }
+
+    #[test]
+    fn test_list_accounts_sorts_accounts() {
+        let rpc = RpcServer::new(Accounts::new());
+        deposit(&rpc, "ALICE", 5);
+        deposit(&rpc, "BOB", 10);
+
+        //act
+        let by_name: serde_json::Value =
+            serde_json::from_str(&list_accounts(&rpc, "").unwrap()).unwrap();
+        let by_balance: serde_json::Value =
+            serde_json::from_str(&list_accounts(&rpc, "sort=balance&desc=true").unwrap()).unwrap();
+
+        assert_eq!(by_name[0]["account"], "ALICE");
+        assert_eq!(by_name[0]["txs"], 1);
+        assert_eq!(by_balance[0]["account"], "BOB");
+        assert_eq!(
+            list_accounts(&rpc, "offset=1&limit=5").unwrap(),
+            r#"[{"account":"BOB","balance":10,"txs":1}]"#
+        );
+        assert!(list_accounts(&rpc, "match=A.*").unwrap().contains("ALICE"));
+        assert_eq!(list_accounts(&rpc, "prefix=B&match=A.*").unwrap(), "[]");
+        assert!(list_accounts(&rpc, "sort=size").is_err());
+        assert!(list_accounts(&rpc, "desc=yes").is_err());
+    }
+
+    #[test]
+    fn test_account_txs_lists_only_that_account() {
+        let rpc = RpcServer::new(Accounts::new());
+        deposit(&rpc, "ALICE", 1);
+        deposit(&rpc, "BOB", 2);
+        deposit(&rpc, "ALICE", 3);
+
+        //act
+        let entries: serde_json::Value = serde_json::from_str(&account_txs(&rpc, "ALICE")).unwrap();
+
+        assert_eq!(entries.as_array().unwrap().len(), 2);
+        assert_eq!(entries[1]["tx"]["Deposit"]["amount"], 3);
+        assert_eq!(account_txs(&rpc, "CAROL"), "[]");
+        assert_eq!(
+            account_txs_path("/accounts/BOB/txs").as_deref(),
+            Some("BOB")
+        );
+        assert_eq!(
+            account_txs_path("/accounts/BOB%20SMITH/txs").as_deref(),
+            Some("BOB SMITH")
+        );
+        assert_eq!(account_txs_path("/accounts//txs"), None);
+    }
+
+    #[test]
+    fn test_account_limit_rejects_floods_with_429() {
+        let rpc = RpcServer::new(Accounts::new());
+        let limiter = RateLimiter::new("2/hour".parse().unwrap());
+        let server = HttpServer::bind("127.0.0.1:0", rpc)
+            .unwrap()
+            .with_account_limit(limiter);
+        let url = format!("http://{}/rpc", server.local_addr().unwrap());
+        thread::spawn(move || server.run());
+        let post = |account: &str| {
+            let body = format!(
+                r#"{{"jsonrpc":"2.0","method":"deposit","params":{{"account":"{}","amount":1}},"id":1}}"#,
+                account
+            );
+            match ureq::post(&url).send(body.as_str()) {
+                Ok(response) => response.status().as_u16(),
+                Err(ureq::Error::StatusCode(status)) => status,
+                Err(e) => panic!("request failed: {}", e),
+            }
+        };
+
+        //act
+        let statuses: Vec<u16> = ["ALICE", "ALICE", "ALICE", "BOB"]
+            .into_iter()
+            .map(post)
+            .collect();
+
+        assert_eq!(statuses, vec![200, 200, 429, 200]);
+        assert_eq!(
+            rpc_accounts(
+                r#"[{"params":{"sender":"A","recipient":"B"}},{"params":{"account":"C"}},{}]"#
+            ),
+            vec!["A", "C"]
+        );
+    }
+
+    #[test]
+    fn test_api_keys_guard_every_route() {
+        let rpc = RpcServer::new(Accounts::new());
+        deposit(&rpc, "BOB", 5);
+        let config = crate::config::Config::parse(
+            "api_key.admin = s3cret full\napi_key.alice = alice-key read ALICE",
+        )
+        .unwrap();
+        let server = HttpServer::bind("127.0.0.1:0", rpc)
+            .unwrap()
+            .with_api_keys(ApiKeys::from_config(&config).unwrap());
+        let base = format!("http://{}", server.local_addr().unwrap());
+        thread::spawn(move || server.run());
+        let status = |result: Result<ureq::http::Response<ureq::Body>, ureq::Error>| match result {
+            Ok(response) => response.status().as_u16(),
+            Err(ureq::Error::StatusCode(status)) => status,
+            Err(e) => panic!("request failed: {}", e),
+        };
+        let post = |key: &str, method: &str, account: &str| {
+            let body = format!(
+                r#"{{"jsonrpc":"2.0","method":"{}","params":{{"account":"{}","amount":1}},"id":1}}"#,
+                method, account
+            );
+            status(
+                ureq::post(&format!("{}/rpc", base))
+                    .header("Authorization", &format!("Bearer {}", key))
+                    .send(body.as_str()),
+            )
+        };
+        let get = |key: &str, path: &str| {
+            status(
+                ureq::get(&format!("{}{}", base, path))
+                    .header("X-Api-Key", key)
+                    .call(),
+            )
+        };
+
+        //act
+        let statuses = vec![
+            post("s3cret", "deposit", "ALICE"),
+            post("alice-key", "balance", "ALICE"),
+            post("alice-key", "withdraw", "ALICE"),
+            post("alice-key", "balance", "BOB"),
+            post("guess", "balance", "ALICE"),
+            get("alice-key", "/accounts/ALICE/txs"),
+            get("alice-key", "/accounts/BOB/txs"),
+            get("alice-key", "/txs"),
+            get("s3cret", "/txs"),
+            status(ureq::get(&format!("{}/metrics", base)).call()),
+        ];
+
+        assert_eq!(
+            statuses,
+            vec![200, 200, 403, 403, 401, 200, 403, 403, 200, 401]
+        );
+        assert_eq!(
+            rpc_operations(r#"[{"method":"send","params":{"sender":"A"}},{"method":"accounts"}]"#),
+            vec![
+                Operation {
+                    writes: true,
+                    accounts: Some(vec!["A".to_string()])
+                },
+                Operation {
+                    writes: false,
+                    accounts: None
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_api_keys_act_as_their_principal() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("BOB", 10).unwrap();
+        ledger
+            .metadata_mut()
+            .entry("BOB")
+            .owners
+            .insert("bob".to_string());
+        let rpc = RpcServer::new(ledger);
+        let config =
+            crate::config::Config::parse("api_key.bob = bob-key full\napi_key.eve = eve-key full")
+                .unwrap();
+        let server = HttpServer::bind("127.0.0.1:0", rpc.clone())
+            .unwrap()
+            .with_api_keys(ApiKeys::from_config(&config).unwrap());
+        let url = format!("http://{}/rpc", server.local_addr().unwrap());
+        thread::spawn(move || server.run());
+        let withdraw = |key: &str| {
+            let body = r#"{"jsonrpc":"2.0","method":"withdraw","params":{"account":"BOB","amount":1},"id":1}"#;
+            let mut response = ureq::post(&url)
+                .header("X-Api-Key", key)
+                .send(body)
+                .unwrap();
+            let response: Value =
+                serde_json::from_str(&response.body_mut().read_to_string().unwrap()).unwrap();
+            response
+        };
+
+        //act
+        let by_eve = withdraw("eve-key");
+        let by_bob = withdraw("bob-key");
+
+        assert_eq!(by_eve["error"]["message"], "eve may not do this");
+        assert_eq!(by_bob["result"][0]["Withdraw"]["amount"], 1);
+        assert_eq!(rpc.ledger().balance_of("BOB"), Ok(9));
+        assert!(rpc.ledger().read(|ledger| ledger.principal().is_none()));
+    }
+
+    #[test]
+    fn test_run_until_stops_on_shutdown() {
+        let rpc = RpcServer::new(Accounts::new());
+        let server = HttpServer::bind("127.0.0.1:0", rpc).unwrap();
+        let url = format!("http://{}/metrics", server.local_addr().unwrap());
+        let shutdown = std::sync::Arc::new(Shutdown::new());
+        let running = {
+            let shutdown = shutdown.clone();
+            thread::spawn(move || server.run_until(&shutdown))
+        };
+        assert!(ureq::get(&url).call().is_ok());
+
+        //act
+        shutdown.request();
+
+        running.join().unwrap();
+        assert!(ureq::get(&url).call().is_err());
+    }
+
+    #[test]
+    fn test_bind_tls_rejects_invalid_pem() {
+        let rpc = RpcServer::new(Accounts::new());
+
+        //act
+        let result = HttpServer::bind_tls("127.0.0.1:0", rpc, b"cert".to_vec(), b"key".to_vec());
+
+        assert_eq!(
+            result.err().map(|e| e.kind()),
+            Some(io::ErrorKind::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn test_ws_txs_streams_filtered_txs() {
+        let rpc = RpcServer::new(Accounts::new());
+        let server = HttpServer::bind("127.0.0.1:0", rpc.clone()).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let (mut socket, _) =
+            tungstenite::connect(format!("ws://{}/ws/txs?account=BOB", addr)).unwrap();
+
+        deposit(&rpc, "ALICE", 5);
+        deposit(&rpc, "BOB", 10);
+
+        let message = socket.read().unwrap();
+        assert_eq!(
+            message.to_text().unwrap(),
+            r#"{"Deposit":{"account":"BOB","amount":10}}"#
+        );
+    }
+}
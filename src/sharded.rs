@@ -0,0 +1,502 @@
+use crate::{
+    accounts::Accounts,
+    dispute::{self, Dispute},
+    errors::ApplicationError,
+    escrow::{self, Escrow},
+    events::LedgerEvent,
+    plugins::LedgerApi,
+    stats::AccountStats,
+    tx::{Tx, Units},
+};
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::hash::BuildHasher;
+use std::sync::{Arc, RwLock, RwLockWriteGuard};
+
+type ShardGuard<'a> = RwLockWriteGuard<'a, Accounts>;
+
+/// A thread-safe ledger splitting its accounts into shards by key hash, each a whole
+/// [`Accounts`] behind its own lock.
+///
+/// Operations on accounts in different shards don't contend, unlike with [`crate::shared::SharedAccounts`]
+/// where every write takes the same lock. Each shard keeps everything about its accounts: their
+/// balances, their part of the supply, their stats, metadata and spending limits. Escrow and
+/// dispute accounts are kept with the payer or disputed account, so holding funds never
+/// leaves its shard.
+///
+/// A [`ShardedAccounts::send`] between shards locks both (in shard order, so concurrent sends
+/// can't deadlock), checks the sender like [`Accounts::send`] does and the recipient like
+/// crediting it would, and only then commits both sides. It's never observed half-applied.
+///
+/// Reads spanning all accounts ([`ShardedAccounts::len`], [`ShardedAccounts::balances`],
+/// [`ShardedAccounts::supply`], ...) visit the shards one after another and aren't a consistent
+/// snapshot under concurrent writes.
+#[derive(Debug)]
+pub struct ShardedAccounts {
+    shards: Box<[RwLock<Accounts>]>,
+    hasher: RandomState,
+}
+
+impl Default for ShardedAccounts {
+    fn default() -> Self {
+        ShardedAccounts::new(16)
+    }
+}
+
+impl ShardedAccounts {
+    /// Creates an empty ledger with `shards` shards, at least one
+    pub fn new(shards: usize) -> Self {
+        ShardedAccounts {
+            shards: (0..shards.max(1)).map(|_| Default::default()).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// The number of shards
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Applies `f` to every shard, for what all accounts share, e.g. the clock, the principal,
+    /// admins or the [`crate::anomaly::AnomalyPolicy`]
+    pub fn configure(&self, mut f: impl FnMut(&mut Accounts)) {
+        self.shards.iter().for_each(|s| f(&mut s.write().unwrap()));
+    }
+
+    /// Calls `listener` with the events of every shard
+    pub fn subscribe(&self, listener: impl Fn(&LedgerEvent) + Send + Sync + 'static) {
+        let listener = Arc::new(listener);
+        self.configure(|shard| {
+            let listener = listener.clone();
+            shard.subscribe(move |event| listener(event));
+        });
+    }
+
+    /// Runs `f` on the shard keeping `account`, e.g. for its metadata or stats
+    pub fn read<R>(&self, account: &str, f: impl FnOnce(&Accounts) -> R) -> R {
+        f(&self.shards[self.shard_of(account)].read().unwrap())
+    }
+
+    /// Runs `f` on the shard keeping `account`, e.g. to set its metadata or spending limits.
+    /// Operations touching accounts of other shards fail there as if those didn't exist.
+    pub fn write<R>(&self, account: &str, f: impl FnOnce(&mut Accounts) -> R) -> R {
+        f(&mut self.lock(account))
+    }
+
+    /// Returns the current balance of the `signer` account.
+    /// # Errors
+    /// The account doesn't exist
+    pub fn balance_of(&self, signer: &str) -> Result<Units, ApplicationError> {
+        self.read(signer, |shard| shard.balance_of(signer).copied())
+    }
+
+    /// The number of accounts
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.read().unwrap().len()).sum()
+    }
+
+    /// Returns `true` if there are no accounts
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|s| s.read().unwrap().is_empty())
+    }
+
+    /// A copy of all accounts and their balances
+    pub fn balances(&self) -> BTreeMap<String, Units> {
+        self.shards
+            .iter()
+            .flat_map(|s| {
+                let shard = s.read().unwrap();
+                shard
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), *v))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// The money in all shards, see [`Accounts::supply`]
+    pub fn supply(&self) -> u128 {
+        self.shards
+            .iter()
+            .map(|s| s.read().unwrap().supply())
+            .fold(0, u128::wrapping_add)
+    }
+
+    /// The statistics of `account`, or `None` if it had no transactions
+    pub fn account_stats(&self, account: &str) -> Option<AccountStats> {
+        self.read(account, |shard| shard.stats().account(account).copied())
+    }
+
+    /// The activity of all shards, see [`crate::stats::LedgerStats::totals`]
+    pub fn totals(&self) -> AccountStats {
+        self.shards.iter().fold(AccountStats::default(), |sum, s| {
+            let totals = *s.read().unwrap().stats().totals();
+            AccountStats {
+                deposited: sum.deposited.wrapping_add(totals.deposited),
+                withdrawn: sum.withdrawn.wrapping_add(totals.withdrawn),
+                sent: sum.sent.wrapping_add(totals.sent),
+                received: sum.received.wrapping_add(totals.received),
+                minted: sum.minted.wrapping_add(totals.minted),
+                burned: sum.burned.wrapping_add(totals.burned),
+                txs: sum.txs + totals.txs,
+            }
+        })
+    }
+
+    /// See [`Accounts::deposit`]
+    pub fn deposit(&self, signer: &str, amount: Units) -> Result<Tx, ApplicationError> {
+        self.lock(signer).deposit(signer, amount)
+    }
+
+    /// See [`Accounts::withdraw`]
+    pub fn withdraw(&self, signer: &str, amount: Units) -> Result<Tx, ApplicationError> {
+        self.lock(signer).withdraw(signer, amount)
+    }
+
+    /// See [`Accounts::send`]
+    pub fn send(
+        &self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx), ApplicationError> {
+        self.checked_send(sender, recipient, amount, false)
+    }
+
+    /// See [`Accounts::send_confirmed`]
+    pub fn send_confirmed(
+        &self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx), ApplicationError> {
+        self.checked_send(sender, recipient, amount, true)
+    }
+
+    fn checked_send(
+        &self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+        confirmed: bool,
+    ) -> Result<(Tx, Tx), ApplicationError> {
+        match self.lock_pair(sender, recipient) {
+            (mut shard, None) if confirmed => shard.send_confirmed(sender, recipient, amount),
+            (mut shard, None) => shard.send(sender, recipient, amount),
+            (mut from, Some(mut to)) => {
+                from.check_send(sender, recipient, amount, confirmed)?;
+                let txs = transfer(&mut from, &mut to, "send", sender, recipient, amount)?;
+                from.record_send(sender, recipient);
+                Ok(txs)
+            }
+        }
+    }
+
+    /// See [`Accounts::hold_in_escrow`]. The escrow is kept in the shard of `payer`, whose own
+    /// escrow ids may repeat those of other shards, so escrows are told apart by [`Escrow`].
+    pub fn hold_in_escrow(
+        &self,
+        payer: &str,
+        payee: &str,
+        amount: Units,
+    ) -> Result<(Escrow, (Tx, Tx)), ApplicationError> {
+        self.lock(payer).hold_in_escrow(payer, payee, amount)
+    }
+
+    /// See [`Accounts::release_escrow`]
+    pub fn release_escrow(&self, escrow: &Escrow) -> Result<(Tx, Tx), ApplicationError> {
+        match self.lock_pair(&escrow.payer, &escrow.payee) {
+            (mut shard, None) => shard.release_escrow(escrow.id),
+            (mut holder, Some(mut payee)) => {
+                let (escrow, amount) = holder.open_escrow("release", escrow.id)?;
+                holder.check_party("release", &escrow.payer)?;
+                let (account, recipient) = (&escrow.account, &escrow.payee);
+                transfer(
+                    &mut holder,
+                    &mut payee,
+                    "release",
+                    account,
+                    recipient,
+                    amount,
+                )
+            }
+        }
+    }
+
+    /// See [`Accounts::refund_escrow`]
+    pub fn refund_escrow(&self, escrow: &Escrow) -> Result<(Tx, Tx), ApplicationError> {
+        match self.lock_pair(&escrow.payer, &escrow.payee) {
+            (mut shard, None) => shard.refund_escrow(escrow.id),
+            // The funds go back within the payer's shard, but the payee's metadata says who
+            // may refund them
+            (mut holder, Some(payee)) => {
+                let (escrow, amount) = holder.open_escrow("refund", escrow.id)?;
+                payee.check_party("refund", &escrow.payee)?;
+                holder.commit_send("refund", &escrow.account, &escrow.payer, amount)
+            }
+        }
+    }
+
+    /// The escrows of all shards still holding funds, with their amounts
+    pub fn escrows(&self) -> Vec<(Escrow, Units)> {
+        self.shards
+            .iter()
+            .flat_map(|s| {
+                let shard = s.read().unwrap();
+                shard
+                    .escrows()
+                    .map(|(escrow, amount)| (escrow.clone(), amount))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// The shard keeping `account`, for escrow and dispute accounts the one keeping the payer
+    /// or disputed account
+    fn shard_of(&self, account: &str) -> usize {
+        let owner = if escrow::is_escrow(account) || dispute::is_dispute(account) {
+            let account: Arc<str> = account.into();
+            Escrow::parse(&account)
+                .map(|escrow| escrow.payer)
+                .or_else(|| Dispute::parse(&account).map(|dispute| dispute.account))
+        } else {
+            None
+        };
+        let key = owner.as_deref().unwrap_or(account);
+        (self.hasher.hash_one(key) % self.shards.len() as u64) as usize
+    }
+
+    fn lock(&self, account: &str) -> ShardGuard<'_> {
+        self.shards[self.shard_of(account)].write().unwrap()
+    }
+
+    /// Locks the shards keeping `first` and `second`, always the lower shard first, or only
+    /// one if they are kept by the same shard
+    fn lock_pair(&self, first: &str, second: &str) -> (ShardGuard<'_>, Option<ShardGuard<'_>>) {
+        let (a, b) = (self.shard_of(first), self.shard_of(second));
+        if a == b {
+            return (self.shards[a].write().unwrap(), None);
+        }
+        if a < b {
+            let first = self.shards[a].write().unwrap();
+            (first, Some(self.shards[b].write().unwrap()))
+        } else {
+            let second = self.shards[b].write().unwrap();
+            (self.shards[a].write().unwrap(), Some(second))
+        }
+    }
+}
+
+/// Moves `amount` from `sender` kept by `from` to `recipient` kept by `to` once the transfer
+/// was checked. The receiving side is checked first, so either both sides commit or nothing
+/// changes.
+fn transfer(
+    from: &mut Accounts,
+    to: &mut Accounts,
+    operation: &'static str,
+    sender: &str,
+    recipient: &str,
+    amount: Units,
+) -> Result<(Tx, Tx), ApplicationError> {
+    to.check_receive(operation, recipient, amount)?;
+    let withdrawal = from.send_out(operation, sender, recipient, amount)?;
+    let deposit = to
+        .receive(operation, recipient, amount)
+        .expect("receiving was checked");
+    Ok((withdrawal, deposit))
+}
+
+impl LedgerApi for ShardedAccounts {
+    fn balance_of(&mut self, signer: &str) -> Result<Units, Box<dyn Error>> {
+        Ok(ShardedAccounts::balance_of(self, signer)?)
+    }
+
+    fn accounts(&mut self) -> Result<BTreeMap<String, Units>, Box<dyn Error>> {
+        Ok(self.balances())
+    }
+
+    fn deposit(&mut self, signer: &str, amount: Units) -> Result<Tx, Box<dyn Error>> {
+        Ok(ShardedAccounts::deposit(self, signer, amount)?)
+    }
+
+    fn withdraw(&mut self, signer: &str, amount: Units) -> Result<Tx, Box<dyn Error>> {
+        Ok(ShardedAccounts::withdraw(self, signer, amount)?)
+    }
+
+    fn send(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx), Box<dyn Error>> {
+        Ok(ShardedAccounts::send(self, sender, recipient, amount)?)
+    }
+
+    fn send_confirmed(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx), Box<dyn Error>> {
+        Ok(ShardedAccounts::send_confirmed(
+            self, sender, recipient, amount,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    /// Two account names kept by different shards of `ledger`
+    fn in_different_shards(ledger: &ShardedAccounts) -> (String, String) {
+        let names = (0..).map(|i| format!("ACCOUNT{}", i));
+        let mut names = names.filter(|name| ledger.shard_of(name) != ledger.shard_of("ACCOUNT0"));
+        ("ACCOUNT0".to_string(), names.next().unwrap())
+    }
+
+    #[test]
+    fn test_sharded_accounts_operations_work() {
+        let ledger = ShardedAccounts::new(4);
+
+        //act
+        ledger.deposit("ALICE", 100).unwrap();
+        ledger.withdraw("ALICE", 10).unwrap();
+        ledger.send("ALICE", "BOB", 40).unwrap();
+
+        assert_eq!(
+            ledger.balances(),
+            BTreeMap::from([("ALICE".to_string(), 50), ("BOB".to_string(), 40)])
+        );
+        assert_eq!(
+            ledger.withdraw("CAROL", 1),
+            Err(ApplicationError::NotFound("CAROL".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_sharded_accounts_send_overfunded_rolls_back() {
+        // A single shard covers the same-shard path, the other pair the cross-shard one
+        for ledger in [ShardedAccounts::new(1), ShardedAccounts::new(64)] {
+            let (sender, recipient) = match ledger.shard_count() {
+                1 => ("ALICE".to_string(), "BOB".to_string()),
+                _ => in_different_shards(&ledger),
+            };
+            ledger.deposit(&sender, 10).unwrap();
+            ledger.deposit(&recipient, Units::MAX).unwrap();
+
+            //act
+            let result = ledger.send(&sender, &recipient, 10);
+
+            assert_eq!(result, Err(ApplicationError::OverFunded(recipient, 10)));
+            assert_eq!(ledger.balance_of(&sender), Ok(10));
+            assert_eq!(ledger.supply(), (Units::MAX as u128).wrapping_add(10));
+            assert_eq!(ledger.account_stats(&sender).unwrap().sent, 0);
+        }
+    }
+
+    #[test]
+    fn test_sharded_accounts_keep_supply_stats_and_events_across_shards() {
+        let ledger = ShardedAccounts::new(64);
+        let (sender, recipient) = in_different_shards(&ledger);
+        let events = Arc::new(Mutex::new(vec![]));
+        let published = events.clone();
+        ledger.subscribe(move |event| published.lock().unwrap().push(event.clone()));
+        ledger.deposit(&sender, 100).unwrap();
+
+        //act
+        let (withdrawal, deposit) = ledger.send(&sender, &recipient, 30).unwrap();
+
+        assert_eq!(withdrawal.recipient(), Some(recipient.as_str()));
+        assert_eq!(ledger.supply(), 100);
+        assert_eq!(ledger.account_stats(&sender).unwrap().sent, 30);
+        assert_eq!(ledger.account_stats(&recipient).unwrap().received, 30);
+        let totals = ledger.totals();
+        assert_eq!(
+            (totals.deposited, totals.sent, totals.received),
+            (100, 30, 30)
+        );
+        for account in [&sender, &recipient] {
+            assert_eq!(ledger.read(account, Accounts::check_conservation), Ok(()));
+        }
+        assert_eq!(
+            events.lock().unwrap()[2..],
+            [
+                LedgerEvent::TxCommitted(withdrawal),
+                LedgerEvent::AccountCreated {
+                    account: recipient.clone()
+                },
+                LedgerEvent::TxCommitted(deposit),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sharded_accounts_check_the_sender_across_shards() {
+        let ledger = ShardedAccounts::new(64);
+        let (sender, recipient) = in_different_shards(&ledger);
+        ledger.deposit(&sender, 100).unwrap();
+        let limit = "50/day".parse().unwrap();
+        ledger.write(&sender, |shard| {
+            shard.set_spending_limit(&sender, &recipient, limit)
+        });
+        ledger.send(&sender, &recipient, 40).unwrap();
+
+        //act
+        let result = ledger.send(&sender, &recipient, 20);
+
+        assert_eq!(
+            result,
+            Err(ApplicationError::LimitExceeded(recipient.clone(), 10))
+        );
+        assert_eq!(ledger.balance_of(&recipient), Ok(40));
+    }
+
+    #[test]
+    fn test_sharded_accounts_release_escrows_across_shards() {
+        let ledger = ShardedAccounts::new(64);
+        let (payer, payee) = in_different_shards(&ledger);
+        ledger.deposit(&payer, 100).unwrap();
+        let (escrow, _) = ledger.hold_in_escrow(&payer, &payee, 60).unwrap();
+
+        //act
+        ledger.release_escrow(&escrow).unwrap();
+
+        assert_eq!(ledger.balance_of(&payee), Ok(60));
+        assert_eq!(ledger.escrows(), vec![]);
+        assert_eq!(ledger.supply(), 100);
+        assert!(ledger.refund_escrow(&escrow).is_err());
+    }
+
+    #[test]
+    fn test_sharded_accounts_concurrent_sends_conserve_total() {
+        let ledger = Arc::new(ShardedAccounts::new(8));
+        let accounts: Vec<String> = (0..16).map(|i| format!("ACCOUNT{}", i)).collect();
+        accounts
+            .iter()
+            .for_each(|a| drop(ledger.deposit(a, 1000).unwrap()));
+
+        //act
+        let workers: Vec<_> = (0..4)
+            .map(|t| {
+                let (ledger, accounts) = (ledger.clone(), accounts.clone());
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        let sender = &accounts[(i + t) % accounts.len()];
+                        let recipient = &accounts[(i * 7 + t + 1) % accounts.len()];
+                        let _ = ledger.send(sender, recipient, 3);
+                    }
+                })
+            })
+            .collect();
+        workers.into_iter().for_each(|w| w.join().unwrap());
+
+        assert_eq!(ledger.balances().values().sum::<Units>(), 16 * 1000);
+        assert_eq!(ledger.supply(), 16 * 1000);
+        let totals = ledger.totals();
+        assert_eq!(totals.sent, totals.received);
+    }
+}
@@ -0,0 +1,642 @@
+use crate::{
+    accounts::Accounts,
+    clock::Timestamp,
+    dryrun::{BatchSimulation, Op, SimulationResult},
+    errors::ApplicationError,
+    events::LedgerEvent,
+    goals::Goal,
+    multisig::PendingTransfer,
+    plugins::LedgerApi,
+    storage::TxStore,
+    tx::{Tx, Units},
+};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::task::{Context, Poll, Waker};
+
+/// A thread-safe handle to an [`Accounts`] ledger. Clones share the same ledger.
+///
+/// Every method takes the lock for the duration of a single operation, so e.g. a `send`
+/// is never observed half-applied. The `_async` ones keep other changes out until their
+/// transactions are persisted, so nothing builds on a change that may still be undone; a
+/// blocking method called meanwhile waits for that.
+#[derive(Debug, Clone, Default)]
+pub struct SharedAccounts {
+    inner: Arc<RwLock<Accounts>>,
+    writer: Arc<WriterTurn>,
+    journal: Option<Arc<Journal>>,
+    /// Who operations through this handle are performed as, see [`SharedAccounts::acting_as`]
+    acting_as: Option<String>,
+}
+
+impl From<Accounts> for SharedAccounts {
+    fn from(accounts: Accounts) -> Self {
+        SharedAccounts::new(accounts)
+    }
+}
+
+impl SharedAccounts {
+    /// Wraps `accounts` for shared use
+    pub fn new(accounts: Accounts) -> Self {
+        SharedAccounts {
+            inner: Arc::new(RwLock::new(accounts)),
+            writer: Default::default(),
+            journal: None,
+            acting_as: None,
+        }
+    }
+
+    /// Like [`SharedAccounts::new`], handing `persist` the transactions each operation
+    /// committed, all of them in one call, before any other operation starts. A send is then
+    /// persisted with both of its transactions or not at all.
+    pub fn journaled(
+        mut accounts: Accounts,
+        persist: impl Fn(&[Tx]) + Send + Sync + 'static,
+    ) -> Self {
+        let journal = Arc::new(Journal {
+            committed: Default::default(),
+            persist: Box::new(persist),
+        });
+        let committed = journal.clone();
+        accounts.subscribe(move |event| {
+            if let LedgerEvent::TxCommitted(tx) = event {
+                committed.committed.lock().unwrap().push(tx.clone());
+            }
+        });
+        SharedAccounts {
+            journal: Some(journal),
+            ..SharedAccounts::new(accounts)
+        }
+    }
+
+    /// A handle to the same ledger that performs every operation as `principal` rather than the
+    /// ledger's own, see [`Accounts::set_principal`], e.g. for a server request
+    pub fn acting_as(&self, principal: &str) -> Self {
+        SharedAccounts {
+            acting_as: Some(principal.to_string()),
+            ..self.clone()
+        }
+    }
+
+    /// Runs `f` with shared read access to the ledger
+    pub fn read<R>(&self, f: impl FnOnce(&Accounts) -> R) -> R {
+        f(&self.inner.read().unwrap())
+    }
+
+    /// Runs `f` with exclusive write access to the ledger, e.g. to apply several operations atomically
+    pub fn write<R>(&self, f: impl FnOnce(&mut Accounts) -> R) -> R {
+        let _turn = self.writer.wait();
+        let result = {
+            let mut accounts = self.inner.write().unwrap();
+            let own = accounts.principal().map(str::to_string);
+            if let Some(principal) = &self.acting_as {
+                accounts.set_principal(Some(principal.clone()));
+            }
+            let result = f(&mut accounts);
+            accounts.set_principal(own);
+            result
+        };
+        self.persist_journal();
+        result
+    }
+
+    /// Hands what the last operation committed to the [`Journal`], if there is one
+    fn persist_journal(&self) {
+        let Some(journal) = &self.journal else {
+            return;
+        };
+        let committed = std::mem::take(&mut *journal.committed.lock().unwrap());
+        if !committed.is_empty() {
+            (journal.persist)(&committed);
+        }
+    }
+
+    /// See [`Accounts::balance_of`]
+    pub fn balance_of(&self, signer: &str) -> Result<Units, ApplicationError> {
+        self.read(|accounts| accounts.balance_of(signer).copied())
+    }
+
+    /// See [`Accounts::len`]
+    pub fn len(&self) -> usize {
+        self.read(Accounts::len)
+    }
+
+    /// See [`Accounts::is_empty`]
+    pub fn is_empty(&self) -> bool {
+        self.read(Accounts::is_empty)
+    }
+
+    /// A copy of all accounts and their balances
+    pub fn balances(&self) -> BTreeMap<String, Units> {
+        self.read(|accounts| accounts.iter().map(|(k, v)| (k.to_string(), *v)).collect())
+    }
+
+    /// See [`Accounts::simulate`]
+    pub fn simulate(&self, op: &Op) -> SimulationResult {
+        self.read(|accounts| accounts.simulate(op))
+    }
+
+    /// See [`Accounts::simulate_batch`]
+    pub fn simulate_batch(&self, ops: &[Op]) -> BatchSimulation {
+        self.read(|accounts| accounts.simulate_batch(ops))
+    }
+
+    /// See [`Accounts::subscribe`]
+    pub fn subscribe(&self, listener: impl Fn(&LedgerEvent) + Send + Sync + 'static) {
+        self.write(|accounts| accounts.subscribe(listener))
+    }
+
+    /// See [`Accounts::deposit`]
+    pub fn deposit(&self, signer: &str, amount: Units) -> Result<Tx, ApplicationError> {
+        self.write(|accounts| accounts.deposit(signer, amount))
+    }
+
+    /// See [`Accounts::withdraw`]
+    pub fn withdraw(&self, signer: &str, amount: Units) -> Result<Tx, ApplicationError> {
+        self.write(|accounts| accounts.withdraw(signer, amount))
+    }
+
+    /// See [`Accounts::mint`]
+    pub fn mint(&self, signer: &str, amount: Units) -> Result<Tx, ApplicationError> {
+        self.write(|accounts| accounts.mint(signer, amount))
+    }
+
+    /// See [`Accounts::burn`]
+    pub fn burn(&self, signer: &str, amount: Units) -> Result<Tx, ApplicationError> {
+        self.write(|accounts| accounts.burn(signer, amount))
+    }
+
+    /// See [`Accounts::grant_promo`]
+    pub fn grant_promo(
+        &self,
+        signer: &str,
+        amount: Units,
+        expires: Timestamp,
+    ) -> Result<Tx, ApplicationError> {
+        self.write(|accounts| accounts.grant_promo(signer, amount, expires))
+    }
+
+    /// See [`crate::metadata::LedgerMetadata::goals`]
+    pub fn goals(&self) -> BTreeMap<String, BTreeMap<String, Goal>> {
+        self.read(|accounts| accounts.metadata().goals())
+    }
+
+    /// See [`Accounts::send`]
+    pub fn send(
+        &self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx), ApplicationError> {
+        self.write(|accounts| accounts.send(sender, recipient, amount))
+    }
+
+    /// See [`Accounts::send_confirmed`]
+    pub fn send_confirmed(
+        &self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx), ApplicationError> {
+        self.write(|accounts| accounts.send_confirmed(sender, recipient, amount))
+    }
+
+    /// See [`Accounts::pay`]
+    pub fn pay(
+        &self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<Vec<Tx>, ApplicationError> {
+        self.write(|accounts| accounts.pay(sender, recipient, amount))
+    }
+
+    /// See [`Accounts::pay_confirmed`]
+    pub fn pay_confirmed(
+        &self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<Vec<Tx>, ApplicationError> {
+        self.write(|accounts| accounts.pay_confirmed(sender, recipient, amount))
+    }
+
+    /// See [`Accounts::send_multi`]; no other operation sees some of the legs without the rest
+    pub fn send_multi(&self, legs: &[(&str, &str, Units)]) -> Result<Vec<Tx>, ApplicationError> {
+        self.write(|accounts| accounts.send_multi(legs))
+    }
+
+    /// See [`Accounts::approve`]
+    pub fn approve(&self, id: u64) -> Result<Option<Vec<Tx>>, ApplicationError> {
+        self.write(|accounts| accounts.approve(id))
+    }
+
+    /// See [`Accounts::pending_transfers`]
+    pub fn pending_transfers(&self) -> Vec<PendingTransfer> {
+        self.read(|accounts| accounts.pending_transfers().cloned().collect())
+    }
+
+    /// See [`Accounts::import_accounts`]
+    pub fn import_accounts(
+        &self,
+        accounts: impl IntoIterator<Item = (String, Units)>,
+    ) -> Result<Vec<Tx>, ApplicationError> {
+        self.write(|ledger| ledger.import_accounts(accounts))
+    }
+
+    /// Like [`SharedAccounts::deposit`], but also awaits persisting the transaction to `store`.
+    /// # Errors
+    /// See [`SharedAccounts::deposit`], or [`ApplicationError::Storage`] if persisting failed
+    pub async fn deposit_async<S: TxStore>(
+        &self,
+        store: &S,
+        signer: &str,
+        amount: Units,
+    ) -> Result<Tx, ApplicationError> {
+        let txs = self
+            .commit_and_persist(store, |ledger| Ok(vec![ledger.deposit(signer, amount)?]))
+            .await?;
+        Ok(txs[0].clone())
+    }
+
+    /// Like [`SharedAccounts::withdraw`], but also awaits persisting the transaction to `store`.
+    /// # Errors
+    /// See [`SharedAccounts::withdraw`], or [`ApplicationError::Storage`] if persisting failed
+    pub async fn withdraw_async<S: TxStore>(
+        &self,
+        store: &S,
+        signer: &str,
+        amount: Units,
+    ) -> Result<Tx, ApplicationError> {
+        let txs = self
+            .commit_and_persist(store, |ledger| Ok(vec![ledger.withdraw(signer, amount)?]))
+            .await?;
+        Ok(txs[0].clone())
+    }
+
+    /// Like [`SharedAccounts::send`], but also awaits persisting both transactions to `store`.
+    /// # Errors
+    /// See [`SharedAccounts::send`], or [`ApplicationError::Storage`] if persisting failed
+    pub async fn send_async<S: TxStore>(
+        &self,
+        store: &S,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx), ApplicationError> {
+        let txs = self
+            .commit_and_persist(store, |ledger| {
+                let (withdrawal, deposit) = ledger.send(sender, recipient, amount)?;
+                Ok(vec![withdrawal, deposit])
+            })
+            .await?;
+        Ok((txs[0].clone(), txs[1].clone()))
+    }
+
+    /// Commits what `commit` does to the ledger and appends its transactions to `store`, with
+    /// no other change in between. The operation is staged on a fork of the ledger, see
+    /// [`Accounts::stage`], which takes the ledger's place once the store has its transactions,
+    /// so readers see the ledger as it was meanwhile and don't wait for the store, but other
+    /// writers do. If appending fails, the fork is dropped: nothing it did is published or
+    /// persisted, and the ledger looks as if nothing happened.
+    async fn commit_and_persist<S: TxStore>(
+        &self,
+        store: &S,
+        commit: impl FnOnce(&mut Accounts) -> Result<Vec<Tx>, ApplicationError>,
+    ) -> Result<Vec<Tx>, ApplicationError> {
+        let _turn = self.writer.take().await;
+        let (txs, staged) = self.read(|accounts| {
+            accounts.stage(|fork| {
+                if let Some(principal) = &self.acting_as {
+                    fork.set_principal(Some(principal.clone()));
+                }
+                commit(fork)
+            })
+        });
+        let persisted = match &txs {
+            Ok(txs) => store
+                .append(txs)
+                .await
+                .map_err(|e| ApplicationError::Storage(e.to_string())),
+            Err(e) => Err(e.clone()),
+        };
+        {
+            let mut accounts = self.inner.write().unwrap();
+            let own = accounts.principal().map(str::to_string);
+            accounts.settle(staged, persisted.is_ok());
+            accounts.set_principal(own);
+        }
+        self.persist_journal();
+        persisted.and(txs)
+    }
+}
+
+type Persist = Box<dyn Fn(&[Tx]) + Send + Sync>;
+
+/// What [`SharedAccounts::journaled`] persists, collected from the ledger's events while an
+/// operation runs
+struct Journal {
+    committed: Mutex<Vec<Tx>>,
+    persist: Persist,
+}
+
+impl fmt::Debug for Journal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Journal")
+            .field("committed", &self.committed)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Who may change the ledger: one writer at a time, which the `_async` methods of
+/// [`SharedAccounts`] stay through awaiting their store. Blocking writers wait on the condvar,
+/// async ones are woken.
+#[derive(Debug, Default)]
+struct WriterTurn {
+    state: Mutex<TurnState>,
+    freed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct TurnState {
+    taken: bool,
+    waiting: Vec<Waker>,
+}
+
+impl WriterTurn {
+    /// Blocks until the turn is free, and takes it
+    fn wait(&self) -> Turn<'_> {
+        let mut state = self.state.lock().unwrap();
+        while state.taken {
+            state = self.freed.wait(state).unwrap();
+        }
+        state.taken = true;
+        Turn(self)
+    }
+
+    /// Completes once the turn is free, having taken it
+    fn take(&self) -> TakeTurn<'_> {
+        TakeTurn(self)
+    }
+}
+
+/// Holds the [`WriterTurn`] until dropped
+struct Turn<'a>(&'a WriterTurn);
+
+impl Drop for Turn<'_> {
+    fn drop(&mut self) {
+        let waiting = {
+            let mut state = self.0.state.lock().unwrap();
+            state.taken = false;
+            std::mem::take(&mut state.waiting)
+        };
+        self.0.freed.notify_one();
+        waiting.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// See [`WriterTurn::take`]
+struct TakeTurn<'a>(&'a WriterTurn);
+
+impl<'a> Future for TakeTurn<'a> {
+    type Output = Turn<'a>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Turn<'a>> {
+        let mut state = self.0.state.lock().unwrap();
+        if state.taken {
+            state.waiting.push(cx.waker().clone());
+            return Poll::Pending;
+        }
+        state.taken = true;
+        Poll::Ready(Turn(self.0))
+    }
+}
+
+impl LedgerApi for SharedAccounts {
+    fn balance_of(&mut self, signer: &str) -> Result<Units, Box<dyn Error>> {
+        Ok(SharedAccounts::balance_of(self, signer)?)
+    }
+
+    fn accounts(&mut self) -> Result<BTreeMap<String, Units>, Box<dyn Error>> {
+        Ok(self.balances())
+    }
+
+    fn deposit(&mut self, signer: &str, amount: Units) -> Result<Tx, Box<dyn Error>> {
+        Ok(SharedAccounts::deposit(self, signer, amount)?)
+    }
+
+    fn withdraw(&mut self, signer: &str, amount: Units) -> Result<Tx, Box<dyn Error>> {
+        Ok(SharedAccounts::withdraw(self, signer, amount)?)
+    }
+
+    fn send(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx), Box<dyn Error>> {
+        Ok(SharedAccounts::send(self, sender, recipient, amount)?)
+    }
+
+    fn send_confirmed(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<(Tx, Tx), Box<dyn Error>> {
+        Ok(SharedAccounts::send_confirmed(
+            self, sender, recipient, amount,
+        )?)
+    }
+
+    fn pay(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<Vec<Tx>, Box<dyn Error>> {
+        Ok(SharedAccounts::pay(self, sender, recipient, amount)?)
+    }
+
+    fn pay_confirmed(
+        &mut self,
+        sender: &str,
+        recipient: &str,
+        amount: Units,
+    ) -> Result<Vec<Tx>, Box<dyn Error>> {
+        Ok(SharedAccounts::pay_confirmed(
+            self, sender, recipient, amount,
+        )?)
+    }
+
+    fn approve(&mut self, id: u64) -> Result<Vec<Tx>, Box<dyn Error>> {
+        Ok(SharedAccounts::approve(self, id)?.unwrap_or_default())
+    }
+
+    fn mint(&mut self, signer: &str, amount: Units) -> Result<Tx, Box<dyn Error>> {
+        Ok(SharedAccounts::mint(self, signer, amount)?)
+    }
+
+    fn burn(&mut self, signer: &str, amount: Units) -> Result<Tx, Box<dyn Error>> {
+        Ok(SharedAccounts::burn(self, signer, amount)?)
+    }
+
+    fn grant_promo(
+        &mut self,
+        signer: &str,
+        amount: Units,
+        expires: Timestamp,
+    ) -> Result<Tx, Box<dyn Error>> {
+        Ok(SharedAccounts::grant_promo(self, signer, amount, expires)?)
+    }
+
+    fn goals(&mut self) -> Result<BTreeMap<String, BTreeMap<String, Goal>>, Box<dyn Error>> {
+        Ok(SharedAccounts::goals(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStore;
+    use std::future::Future;
+    use std::io;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+    use std::thread;
+
+    /// Polls a future that never has to wait until it completes
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    struct FailingStore;
+
+    impl TxStore for FailingStore {
+        async fn append(&self, _txs: &[Tx]) -> io::Result<()> {
+            Err(io::Error::other("disk full"))
+        }
+    }
+
+    #[test]
+    fn test_shared_accounts_async_ops_persist() {
+        let ledger = SharedAccounts::new(Accounts::new());
+        let store = MemoryStore::new();
+
+        //act
+        let future = ledger.deposit_async(&store, "ALICE", 100);
+        fn assert_send<T: Send>(_: &T) {}
+        assert_send(&future);
+        block_on(future).unwrap();
+        block_on(ledger.withdraw_async(&store, "ALICE", 10)).unwrap();
+        block_on(ledger.send_async(&store, "ALICE", "BOB", 20)).unwrap();
+
+        assert_eq!(store.txs().len(), 4);
+        assert_eq!(ledger.balance_of("ALICE"), Ok(70));
+        assert_eq!(ledger.balance_of("BOB"), Ok(20));
+    }
+
+    #[test]
+    fn test_shared_accounts_journal_persists_each_operation_at_once() {
+        let appends: Arc<Mutex<Vec<Vec<Tx>>>> = Default::default();
+        let persisted = appends.clone();
+        let ledger = SharedAccounts::journaled(Accounts::new(), move |txs| {
+            persisted.lock().unwrap().push(txs.to_vec())
+        });
+
+        //act
+        ledger.deposit("ALICE", 100).unwrap();
+        ledger.send("ALICE", "BOB", 30).unwrap();
+        ledger.send("ALICE", "BOB", 300).unwrap_err();
+        ledger
+            .send_multi(&[("ALICE", "BOB", 10), ("BOB", "CAROL", 5)])
+            .unwrap();
+
+        let sizes: Vec<_> = appends.lock().unwrap().iter().map(Vec::len).collect();
+        assert_eq!(sizes, vec![1, 2, 4]);
+    }
+
+    #[test]
+    fn test_shared_accounts_async_storage_failure_rolls_back() {
+        let persisted: Arc<Mutex<Vec<Tx>>> = Default::default();
+        let journal = persisted.clone();
+        let ledger = SharedAccounts::journaled(Accounts::new(), move |txs| {
+            journal.lock().unwrap().extend_from_slice(txs)
+        });
+        ledger.deposit("ALICE", 100).unwrap();
+        ledger
+            .write(|accounts| accounts.grant_mandate("ALICE", "BOB", "50/day".parse().unwrap()))
+            .unwrap();
+        let committed: Arc<Mutex<Vec<Tx>>> = Default::default();
+        let published = committed.clone();
+        ledger.subscribe(move |event| {
+            if let LedgerEvent::TxCommitted(tx) = event {
+                published.lock().unwrap().push(tx.clone());
+            }
+        });
+        let before = persisted.lock().unwrap().len();
+
+        //act
+        let result = block_on(ledger.send_async(&FailingStore, "ALICE", "CAROL", 30));
+        let collected = block_on(ledger.commit_and_persist(&FailingStore, |accounts| {
+            accounts.collect("BOB", "ALICE", 20)
+        }));
+
+        assert_eq!(
+            result,
+            Err(ApplicationError::Storage("disk full".to_string()))
+        );
+        assert_eq!(
+            collected,
+            Err(ApplicationError::Storage("disk full".to_string()))
+        );
+        assert_eq!(ledger.balance_of("ALICE"), Ok(100));
+        assert!(ledger.balance_of("CAROL").is_err());
+        assert_eq!(ledger.len(), 1);
+        assert!(committed.lock().unwrap().is_empty());
+        assert_eq!(persisted.lock().unwrap().len(), before);
+        // The allowance of the mandate is left as it was
+        ledger
+            .write(|accounts| accounts.collect("BOB", "ALICE", 50))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_shared_accounts_concurrent_sends_conserve_funds() {
+        let ledger = SharedAccounts::new(Accounts::new());
+        ledger.deposit("ALICE", 1000).unwrap();
+        ledger.deposit("BOB", 1000).unwrap();
+
+        //act
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let ledger = ledger.clone();
+                thread::spawn(move || {
+                    let (from, to) = if i % 2 == 0 {
+                        ("ALICE", "BOB")
+                    } else {
+                        ("BOB", "ALICE")
+                    };
+                    for _ in 0..100 {
+                        ledger.send(from, to, 1).unwrap();
+                    }
+                })
+            })
+            .collect();
+        handles.into_iter().for_each(|h| h.join().unwrap());
+
+        assert_eq!(ledger.balance_of("ALICE"), Ok(1000));
+        assert_eq!(ledger.balance_of("BOB"), Ok(1000));
+        assert_eq!(ledger.len(), 2);
+    }
+}
@@ -0,0 +1,171 @@
+//! Shutting down without losing state, whether the user quits, the process is told to
+//! terminate or the server stops.
+//!
+//! Whatever persists the ledger brackets each write with [`Shutdown::begin_write`], and
+//! registers what has to happen on the way out, like writing a final snapshot, with
+//! [`Shutdown::on_shutdown`]. [`Shutdown::complete`] then waits for the writes in flight,
+//! refuses new ones and runs the hooks.
+
+use std::io;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+use tracing::{error, info};
+
+type Hook = Box<dyn FnOnce() -> io::Result<()> + Send>;
+
+#[derive(Default)]
+struct State {
+    requested: bool,
+    /// No writes are allowed anymore
+    closed: bool,
+    /// The hooks ran
+    done: bool,
+    writes: usize,
+    hooks: Vec<(String, Hook)>,
+}
+
+/// Coordinates shutting down between the threads of the CLI and the server.
+#[derive(Default)]
+pub struct Shutdown {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+/// Keeps [`Shutdown::complete`] waiting until dropped
+#[must_use = "the write is only covered while the guard lives"]
+pub struct WriteGuard<'a> {
+    shutdown: &'a Shutdown,
+}
+
+impl Drop for WriteGuard<'_> {
+    fn drop(&mut self) {
+        self.shutdown.lock().writes -= 1;
+        self.shutdown.changed.notify_all();
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Shutdown::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// Runs `hook` when completing the shutdown. Hooks run last registered first, so
+    /// whatever was set up later is torn down earlier; a failing hook doesn't stop the others.
+    pub fn on_shutdown(
+        &self,
+        name: impl Into<String>,
+        hook: impl FnOnce() -> io::Result<()> + Send + 'static,
+    ) {
+        self.lock().hooks.push((name.into(), Box::new(hook)));
+    }
+
+    /// Asks whoever waits for it, e.g. the server loop, to stop
+    pub fn request(&self) {
+        self.lock().requested = true;
+        self.changed.notify_all();
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.lock().requested
+    }
+
+    /// Blocks until a shutdown is requested, for at most `timeout`.
+    /// Returns whether it was requested.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let state = self.lock();
+        let (state, _) = self
+            .changed
+            .wait_timeout_while(state, timeout, |state| !state.requested)
+            .unwrap();
+        state.requested
+    }
+
+    /// Marks a write as in flight until the guard is dropped, or returns `None` once the
+    /// shutdown completed and nothing may be written anymore.
+    pub fn begin_write(&self) -> Option<WriteGuard<'_>> {
+        let mut state = self.lock();
+        if state.closed {
+            return None;
+        }
+        state.writes += 1;
+        Some(WriteGuard { shutdown: self })
+    }
+
+    /// Requests the shutdown, waits for the writes in flight, refuses further writes and runs
+    /// the hooks. Returns how many hooks failed; each failure is logged.
+    ///
+    /// Only the first call runs the hooks, later ones return 0 once it finished.
+    pub fn complete(&self) -> usize {
+        let mut state = self.lock();
+        if state.closed {
+            drop(self.changed.wait_while(state, |state| !state.done).unwrap());
+            return 0;
+        }
+        state.requested = true;
+        state.closed = true;
+        self.changed.notify_all();
+        let mut state = self
+            .changed
+            .wait_while(state, |state| state.writes > 0)
+            .unwrap();
+        let hooks = std::mem::take(&mut state.hooks);
+        // Hooks may take a while, and must not deadlock registering or requesting again
+        drop(state);
+
+        let mut failed = 0;
+        for (name, hook) in hooks.into_iter().rev() {
+            match hook() {
+                Ok(()) => info!(hook = %name, "shut down"),
+                Err(e) => {
+                    error!(hook = %name, error = %e, "shutting down failed");
+                    failed += 1;
+                }
+            }
+        }
+        self.lock().done = true;
+        self.changed.notify_all();
+        failed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_shutdown_waits_for_writes_then_runs_hooks_in_reverse() {
+        let shutdown = Arc::new(Shutdown::new());
+        let ran = Arc::new(Mutex::new(vec![]));
+        for name in ["storage", "snapshot"] {
+            let ran = ran.clone();
+            shutdown.on_shutdown(name, move || {
+                ran.lock().unwrap().push(name);
+                Ok(())
+            });
+        }
+        shutdown.on_shutdown("broken", || Err(io::Error::other("disk full")));
+        let write = shutdown.begin_write().unwrap();
+
+        //act
+        let completing = {
+            let shutdown = shutdown.clone();
+            thread::spawn(move || shutdown.complete())
+        };
+        assert!(shutdown.wait_timeout(Duration::from_secs(5)));
+        thread::sleep(Duration::from_millis(50));
+        assert!(ran.lock().unwrap().is_empty());
+        drop(write);
+        let failed = completing.join().unwrap();
+
+        assert_eq!(failed, 1);
+        assert_eq!(*ran.lock().unwrap(), vec!["snapshot", "storage"]);
+        assert!(shutdown.begin_write().is_none());
+        assert_eq!(shutdown.complete(), 0);
+    }
+}
@@ -0,0 +1,310 @@
+//! Point-in-time copies of the ledger state and checking them against the transaction log.
+
+use crate::{
+    accounts::Accounts,
+    checksum,
+    errors::ApplicationError,
+    migrations,
+    storage::LogEntry,
+    tx::{Tx, Units},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fmt::{self, Write as _};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The balances of a ledger after applying the first `entries` entries of its log,
+/// stored as JSON alongside a hash of the state.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// How many log entries the snapshot covers
+    pub entries: usize,
+    /// See [`state_hash`]
+    pub hash: String,
+    pub balances: BTreeMap<String, Units>,
+}
+
+impl Snapshot {
+    /// Captures `ledger`, which has had `entries` log entries applied
+    pub fn of(ledger: &Accounts, entries: usize) -> Self {
+        let balances: BTreeMap<_, _> = ledger.iter().map(|(k, v)| (k.to_string(), *v)).collect();
+        Snapshot {
+            entries,
+            hash: hash_balances(&balances),
+            balances,
+        }
+    }
+
+    /// Reads the snapshot at `path`
+    /// # Errors
+    /// Reading failed, the snapshot doesn't match its checksum or its own hash, see
+    /// [`checksum::read`], or a newer crabbux wrote it, see [`migrations::upgrade`]
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let snapshot: Snapshot = migrations::load(path, migrations::SNAPSHOT)?;
+        if hash_balances(&snapshot.balances) != snapshot.hash {
+            return Err(checksum::corrupt(path, Divergence::CorruptSnapshot));
+        }
+        Ok(snapshot)
+    }
+
+    /// Writes the snapshot and its checksum to `path`, replacing it atomically
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        checksum::write(path, &migrations::to_json(self, migrations::SNAPSHOT)?)
+    }
+}
+
+/// Where the final snapshot of the ledger persisted to `log` is saved when shutting down:
+/// `<log>.snapshot.json`, see [`crate::shutdown`]
+pub fn path_for(log: impl AsRef<Path>) -> PathBuf {
+    let mut path = OsString::from(log.as_ref());
+    path.push(".snapshot.json");
+    path.into()
+}
+
+/// A hex encoded SHA-256 over every account and balance in name order,
+/// so equal states hash equally regardless of how they were built
+pub fn state_hash(ledger: &Accounts) -> String {
+    Snapshot::of(ledger, 0).hash
+}
+
+fn hash_balances(balances: &BTreeMap<String, Units>) -> String {
+    let mut hasher = Sha256::new();
+    for (account, balance) in balances {
+        hasher.update((account.len() as u64).to_le_bytes());
+        hasher.update(account.as_bytes());
+        hasher.update(balance.to_le_bytes());
+    }
+    hasher.finalize().iter().fold(String::new(), |mut hex, b| {
+        let _ = write!(hex, "{:02x}", b);
+        hex
+    })
+}
+
+/// Why a replayed log doesn't reproduce a [`Snapshot`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence {
+    /// The snapshot's hash doesn't match its own balances, so the snapshot itself is damaged
+    CorruptSnapshot,
+    /// Log entry number `entry` (1-based) couldn't be applied
+    Rejected {
+        entry: usize,
+        tx: Tx,
+        error: ApplicationError,
+    },
+    /// The log ends after `entries` entries, before the point the snapshot covers
+    Truncated { entries: usize },
+    /// `account` ends up with a different balance (`None` if it doesn't exist).
+    /// `entry` is the first log entry affecting the account, where the divergence can start.
+    Balance {
+        account: String,
+        expected: Option<Units>,
+        actual: Option<Units>,
+        entry: Option<(usize, Tx)>,
+    },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Divergence::CorruptSnapshot => write!(f, "the snapshot doesn't match its hash"),
+            Divergence::Rejected { entry, tx, error } => {
+                write!(f, "entry {} ({:?}) was rejected: {}", entry, tx, error)
+            }
+            Divergence::Truncated { entries } => {
+                write!(f, "the log ends after {} entries", entries)
+            }
+            Divergence::Balance {
+                account,
+                expected,
+                actual,
+                entry,
+            } => {
+                write!(
+                    f,
+                    "{} should be {:?} but is {:?}",
+                    account, expected, actual
+                )?;
+                match entry {
+                    Some((entry, tx)) => {
+                        write!(f, ", first affected by entry {} ({:?})", entry, tx)
+                    }
+                    None => write!(f, ", but no entry affects it"),
+                }
+            }
+        }
+    }
+}
+
+/// Replays `entries` into `ledger`, which is empty but follows the rules of the log like its
+/// credit limits, and compares the state after `snapshot.entries` entries with `snapshot`,
+/// returning the first divergence found, if any.
+/// Entries after the ones the snapshot covers are ignored.
+/// # Errors
+/// Reading an entry failed
+pub fn verify(
+    snapshot: &Snapshot,
+    mut ledger: Accounts,
+    entries: impl IntoIterator<Item = io::Result<LogEntry>>,
+) -> io::Result<Option<Divergence>> {
+    if hash_balances(&snapshot.balances) != snapshot.hash {
+        return Ok(Some(Divergence::CorruptSnapshot));
+    }
+    let mut first_entries: HashMap<String, (usize, Tx)> = HashMap::new();
+    let mut applied = 0;
+    for entry in entries.into_iter().take(snapshot.entries) {
+        let LogEntry { timestamp, tx, .. } = entry?;
+        applied += 1;
+        // Replayed like any log, see `crate::storage::replay`, so escrows and disputes pass
+        if let Err(error) = ledger.apply(&tx) {
+            return Ok(Some(Divergence::Rejected {
+                entry: applied,
+                tx,
+                error,
+            }));
+        }
+        ledger.backdate(timestamp);
+        if !first_entries.contains_key(tx.account()) {
+            first_entries.insert(tx.account().to_string(), (applied, tx));
+        }
+    }
+    if applied < snapshot.entries {
+        return Ok(Some(Divergence::Truncated { entries: applied }));
+    }
+
+    let replayed = Snapshot::of(&ledger, applied);
+    if replayed.hash == snapshot.hash {
+        return Ok(None);
+    }
+    let mut accounts: Vec<&String> = snapshot.balances.keys().collect();
+    accounts.extend(replayed.balances.keys());
+    accounts.sort();
+    let divergence = accounts
+        .into_iter()
+        .find(|account| snapshot.balances.get(*account) != replayed.balances.get(*account))
+        .map(|account| Divergence::Balance {
+            account: account.clone(),
+            expected: snapshot.balances.get(account).copied(),
+            actual: replayed.balances.get(account).copied(),
+            entry: first_entries.remove(account),
+        });
+    Ok(divergence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Timestamp;
+
+    fn log(txs: Vec<Tx>) -> impl Iterator<Item = io::Result<LogEntry>> {
+        txs.into_iter().map(|tx| {
+            Ok(LogEntry {
+                timestamp: Timestamp(0),
+                tx,
+                actor: None,
+            })
+        })
+    }
+
+    #[test]
+    fn test_verify_accepts_matching_log() {
+        let mut ledger = Accounts::new();
+        let mut txs = vec![ledger.deposit("ALICE", 100).unwrap()];
+        let (withdrawal, deposit) = ledger.send("ALICE", "BOB", 30).unwrap();
+        txs.extend([withdrawal, deposit]);
+        let snapshot = Snapshot::of(&ledger, txs.len());
+        txs.push(ledger.deposit("CAROL", 1).unwrap());
+
+        //act
+        let divergence = verify(&snapshot, Accounts::new(), log(txs)).unwrap();
+
+        assert_eq!(divergence, None);
+        assert_eq!(
+            snapshot.hash,
+            state_hash(&{
+                let mut other = Accounts::new();
+                other.deposit("BOB", 30).unwrap();
+                other.deposit("ALICE", 70).unwrap();
+                other
+            })
+        );
+    }
+
+    #[test]
+    fn test_verify_reports_divergence() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("ALICE", 100).unwrap();
+        ledger.deposit("BOB", 5).unwrap();
+        let snapshot = Snapshot::of(&ledger, 2);
+        let bob = Tx::Deposit {
+            account: "BOB".into(),
+            amount: 6,
+        };
+        let alice = Tx::Deposit {
+            account: "ALICE".into(),
+            amount: 100,
+        };
+
+        //act
+        let divergence = verify(
+            &snapshot,
+            Accounts::new(),
+            log(vec![alice.clone(), bob.clone()]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            divergence,
+            Some(Divergence::Balance {
+                account: "BOB".to_string(),
+                expected: Some(5),
+                actual: Some(6),
+                entry: Some((2, bob)),
+            })
+        );
+        assert_eq!(
+            verify(&snapshot, Accounts::new(), log(vec![alice])).unwrap(),
+            Some(Divergence::Truncated { entries: 1 })
+        );
+        let mut corrupt = snapshot.clone();
+        corrupt.balances.insert("BOB".to_string(), 6);
+        assert_eq!(
+            verify(&corrupt, Accounts::new(), log(vec![])).unwrap(),
+            Some(Divergence::CorruptSnapshot)
+        );
+    }
+
+    #[test]
+    fn test_verify_accepts_escrows_and_overdrafts() {
+        let rules = || {
+            let mut ledger = Accounts::new();
+            ledger.set_credit_limit("ALICE", 50);
+            ledger
+        };
+        let mut ledger = rules();
+        let mut txs = vec![ledger.deposit("ALICE", 100).unwrap()];
+        let (released, held) = ledger.hold_in_escrow("ALICE", "BOB", 40).unwrap();
+        let (refunded, held_again) = ledger.hold_in_escrow("ALICE", "BOB", 20).unwrap();
+        let release = ledger.release_escrow(released.id).unwrap();
+        let refund = ledger.refund_escrow(refunded.id).unwrap();
+        for (withdrawal, deposit) in [held, held_again, release, refund] {
+            txs.extend([withdrawal, deposit]);
+        }
+        txs.push(ledger.withdraw("ALICE", 80).unwrap());
+        let snapshot = Snapshot::of(&ledger, txs.len());
+
+        //act
+        let divergence = verify(&snapshot, rules(), log(txs.clone())).unwrap();
+        let without_credit = verify(&snapshot, Accounts::new(), log(txs)).unwrap();
+
+        assert_eq!(divergence, None);
+        assert_eq!(snapshot.balances.get("BOB"), Some(&40));
+        assert!(matches!(
+            without_credit,
+            Some(Divergence::Rejected { entry: 10, .. })
+        ));
+    }
+}
@@ -0,0 +1,282 @@
+//! Standing orders: sending the same amount between two accounts on the same day every month,
+//! like "transfer 200 from checking to savings on the 1st".
+//!
+//! Orders aren't derived from the tx log, so they are kept next to it in `<log>.orders`, see
+//! [`path_for`], and managed with `crabbux orders`. The server modes run the orders that are
+//! due every `orders.run_every = <n><m|h|d>` (default `1h`) on a thread of their own, see
+//! [`spawn_runner`], and `crabbux orders run` runs them once, e.g. from cron. Each run is a
+//! normal [`crate::accounts::Accounts::pay`], so it is logged, charged and checked like any
+//! other send.
+
+use crate::accounts::Accounts;
+use crate::date::Date;
+use crate::errors::ApplicationError;
+use crate::tx::{Tx, Units};
+use crate::{checksum, migrations};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "native")]
+use {
+    crate::{shared::SharedAccounts, shutdown::Shutdown},
+    std::sync::Arc,
+    std::thread::{self, JoinHandle},
+    std::time::Duration,
+    tracing::{error, info},
+};
+
+/// Sends `amount` from `from` to `to` on `day` of every month, or on its last day in months
+/// that are shorter
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StandingOrder {
+    pub from: String,
+    pub to: String,
+    pub amount: Units,
+    /// The day of the month, 1 to 31
+    pub day: u32,
+    /// The date of the next run
+    pub next: Date,
+}
+
+impl StandingOrder {
+    /// An order first running on `day` of the month on or after `today`
+    /// # Errors
+    /// `day` isn't a day of the month
+    pub fn new(from: &str, to: &str, amount: Units, day: u32, today: Date) -> Result<Self, String> {
+        if !(1..=31).contains(&day) {
+            return Err(format!("expected a day of the month, got {}", day));
+        }
+        let this_month = on_day(today.year, today.month, day);
+        let next = match this_month >= today {
+            true => this_month,
+            false => following(this_month, day),
+        };
+        Ok(StandingOrder {
+            from: from.to_string(),
+            to: to.to_string(),
+            amount,
+            day,
+            next,
+        })
+    }
+
+    /// Returns `true` if the next run is on or before `today`
+    pub fn is_due(&self, today: Date) -> bool {
+        self.next <= today
+    }
+
+    /// Moves the next run to the month after it
+    pub fn skip(&mut self) {
+        self.next = following(self.next, self.day);
+    }
+}
+
+/// `day` of the month, or the last day of the month if it has fewer days
+fn on_day(year: i32, month: u32, day: u32) -> Date {
+    (1..=day)
+        .rev()
+        .find_map(|day| Date::new(year, month, day))
+        .expect("every month has a first day")
+}
+
+/// `day` of the month after `date`
+fn following(date: Date, day: u32) -> Date {
+    match date.month {
+        12 => on_day(date.year + 1, 1, day),
+        month => on_day(date.year, month + 1, day),
+    }
+}
+
+/// What running a due order did, see [`StandingOrders::run_due`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    pub id: u64,
+    /// The date the order was due on
+    pub due: Date,
+    /// The committed transactions, see [`Accounts::pay`]
+    pub result: Result<Vec<Tx>, ApplicationError>,
+}
+
+/// The standing orders of a ledger by id, stored as JSON
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StandingOrders {
+    next_id: u64,
+    orders: BTreeMap<u64, StandingOrder>,
+}
+
+impl StandingOrders {
+    /// Reads the orders at `path`; a missing file has none
+    /// # Errors
+    /// Reading failed, the file doesn't match its checksum, see [`checksum::read`], or a newer
+    /// crabbux wrote it, see [`migrations::upgrade`]
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        match migrations::load(path.as_ref(), migrations::ORDERS) {
+            Ok(orders) => Ok(orders),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(StandingOrders::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the orders and their checksum to `path`, replacing it atomically
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        checksum::write(path, &migrations::to_json(self, migrations::ORDERS)?)
+    }
+
+    /// Adds `order` and returns its id
+    pub fn add(&mut self, order: StandingOrder) -> u64 {
+        self.next_id += 1;
+        self.orders.insert(self.next_id, order);
+        self.next_id
+    }
+
+    /// Removes order `id` and returns it, if there is one
+    pub fn cancel(&mut self, id: u64) -> Option<StandingOrder> {
+        self.orders.remove(&id)
+    }
+
+    pub fn get(&self, id: u64) -> Option<&StandingOrder> {
+        self.orders.get(&id)
+    }
+
+    /// Order `id`, to change it
+    pub fn get_mut(&mut self, id: u64) -> Option<&mut StandingOrder> {
+        self.orders.get_mut(&id)
+    }
+
+    /// Iterates over the orders in id order
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &StandingOrder)> {
+        self.orders.iter().map(|(id, order)| (*id, order))
+    }
+
+    /// Pays every order due on `today` on `ledger`, confirmed as they were when they were
+    /// added, see [`Accounts::pay_confirmed`], and moves it to its next month. An order that
+    /// was due more than once, e.g. because nothing ran it for a while, is paid once, and a
+    /// failed payment isn't retried.
+    pub fn run_due(&mut self, ledger: &mut Accounts, today: Date) -> Vec<Run> {
+        let mut runs = vec![];
+        for (id, order) in self.orders.iter_mut() {
+            if !order.is_due(today) {
+                continue;
+            }
+            let due = order.next;
+            while order.is_due(today) {
+                order.skip();
+            }
+            runs.push(Run {
+                id: *id,
+                due,
+                result: ledger.pay_confirmed(&order.from, &order.to, order.amount),
+            });
+        }
+        runs
+    }
+}
+
+/// Where the standing orders of the ledger persisted to `log` are kept: `<log>.orders`
+pub fn path_for(log: impl AsRef<Path>) -> PathBuf {
+    let mut path = OsString::from(log.as_ref());
+    path.push(".orders");
+    path.into()
+}
+
+/// Runs the orders at `path` that are due on `ledger` every `every` on a thread of its own, see
+/// [`StandingOrders::run_due`], until `shutdown` is requested. The orders are read anew each
+/// time, so changes made with `crabbux orders` in the meantime count.
+#[cfg(feature = "native")]
+pub fn spawn_runner(
+    ledger: SharedAccounts,
+    path: PathBuf,
+    every: Duration,
+    shutdown: Arc<Shutdown>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        while !shutdown.wait_timeout(every) {
+            let mut orders = match StandingOrders::load(&path) {
+                Ok(orders) => orders,
+                Err(e) => {
+                    error!(error = %e, "reading standing orders failed");
+                    continue;
+                }
+            };
+            let today = crate::date::today();
+            let runs = ledger.write(|accounts| orders.run_due(accounts, today));
+            if runs.is_empty() {
+                continue;
+            }
+            for run in &runs {
+                match &run.result {
+                    Ok(_) => info!(order = run.id, due = %run.due.iso(), "ran standing order"),
+                    Err(e) => error!(order = run.id, error = %e, "standing order failed"),
+                }
+            }
+            if let Err(e) = orders.save(&path) {
+                error!(error = %e, "saving standing orders failed");
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_standing_order_runs_monthly() {
+        let date = |year, month, day| Date::new(year, month, day).unwrap();
+
+        //act
+        let mut order = StandingOrder::new("A", "B", 1, 31, date(2026, 10, 14)).unwrap();
+        let first = order.next;
+        order.skip();
+        let shortened = order.next;
+        order.skip();
+        let next_year = order.next;
+        let before_today = StandingOrder::new("A", "B", 1, 1, date(2026, 12, 14)).unwrap();
+        let today = StandingOrder::new("A", "B", 1, 14, date(2026, 10, 14)).unwrap();
+
+        assert_eq!(first, date(2026, 10, 31));
+        assert_eq!(shortened, date(2026, 11, 30));
+        assert_eq!(next_year, date(2026, 12, 31));
+        assert_eq!(before_today.next, date(2027, 1, 1));
+        assert!(today.is_due(date(2026, 10, 14)));
+        assert!(StandingOrder::new("A", "B", 1, 32, date(2026, 10, 14)).is_err());
+        assert_eq!(path_for("l.jsonl"), PathBuf::from("l.jsonl.orders"));
+    }
+
+    #[test]
+    fn test_standing_orders_run_due_pays_once() {
+        let mut ledger = Accounts::new();
+        ledger.deposit("checking", 500).unwrap();
+        let mut orders = StandingOrders::default();
+        let start = Date::new(2026, 8, 20).unwrap();
+        let savings = StandingOrder::new("checking", "savings", 200, 1, start).unwrap();
+        let rent = StandingOrder::new("checking", "landlord", 900, 15, start).unwrap();
+        let savings = orders.add(savings);
+        let rent = orders.add(rent);
+
+        //act
+        let runs = orders.run_due(&mut ledger, Date::new(2026, 10, 14).unwrap());
+        let again = orders.run_due(&mut ledger, Date::new(2026, 10, 14).unwrap());
+
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].id, savings);
+        assert_eq!(runs[0].due, Date::new(2026, 9, 1).unwrap());
+        assert_eq!(runs[0].result.as_ref().unwrap().len(), 2);
+        assert!(matches!(
+            runs[1].result,
+            Err(ApplicationError::UnderFunded(_, _))
+        ));
+        assert!(again.is_empty());
+        assert_eq!(*ledger.balance_of("savings").unwrap(), 200);
+        assert_eq!(
+            orders.get(savings).unwrap().next,
+            Date::new(2026, 11, 1).unwrap()
+        );
+        assert_eq!(
+            orders.get(rent).unwrap().next,
+            Date::new(2026, 10, 15).unwrap()
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use crate::clock::Timestamp;
+use crate::overlay::Overlay;
+use crate::tx::Units;
+use std::sync::Arc;
+
+/// Activity of one account, or of a whole ledger in [`LedgerStats::totals`].
+///
+/// The sums can only wrap around with the `u128` feature, for amounts as wide as they are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AccountStats {
+    /// Everything deposited, including opening balances from imports and merges
+    pub deposited: u128,
+    pub withdrawn: u128,
+    /// Everything sent to other accounts
+    pub sent: u128,
+    /// Everything received from other accounts
+    pub received: u128,
+    /// New money issued into the account, see [`crate::accounts::Accounts::mint`]
+    pub minted: u128,
+    /// Money taken out of circulation from the account
+    pub burned: u128,
+    /// The number of committed transactions touching the account
+    pub txs: u64,
+}
+
+impl AccountStats {
+    /// The value moved by all transactions
+    pub fn volume(&self) -> u128 {
+        [
+            self.withdrawn,
+            self.sent,
+            self.received,
+            self.minted,
+            self.burned,
+        ]
+        .into_iter()
+        .fold(self.deposited, u128::wrapping_add)
+    }
+
+    /// The average value moved per transaction, or `None` without any
+    pub fn average(&self) -> Option<u128> {
+        (self.txs > 0).then(|| self.volume() / self.txs as u128)
+    }
+}
+
+/// Aggregate statistics of a ledger, updated as transactions commit.
+///
+/// Transfers are counted as sent and received, also when replayed from a log storing the
+/// withdrawal side with its recipient. Older logs only store a withdrawal and a deposit, so a
+/// ledger replaying them counts their transfers that way.
+#[derive(Debug, Clone, Default)]
+pub struct LedgerStats {
+    accounts: Overlay<Arc<str>, AccountStats>,
+    totals: AccountStats,
+    busiest: Option<Arc<str>>,
+    last_activity: Option<Timestamp>,
+}
+
+impl LedgerStats {
+    /// The statistics of `account`, or `None` if it had no transactions
+    pub fn account(&self, account: &str) -> Option<&AccountStats> {
+        self.accounts.get(account)
+    }
+
+    /// Iterates over all accounts and their statistics in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &AccountStats)> {
+        self.accounts
+            .iter()
+            .map(|(account, stats)| (&**account, stats))
+    }
+
+    /// The statistics of all accounts summed up, so a transfer counts once for each side
+    pub fn totals(&self) -> &AccountStats {
+        &self.totals
+    }
+
+    /// The account with the most transactions and its statistics, the one that got there first
+    /// on a tie, or `None` without any transactions
+    pub fn busiest(&self) -> Option<(&str, &AccountStats)> {
+        let account = self.busiest.as_ref()?;
+        Some((account, self.accounts.get(account)?))
+    }
+
+    /// When the latest transaction was committed, or `None` without any
+    pub fn last_activity(&self) -> Option<Timestamp> {
+        self.last_activity
+    }
+
+    /// See [`Overlay::compact`]
+    pub(crate) fn compact(&mut self) {
+        self.accounts.compact();
+    }
+
+    pub(crate) fn record_deposit(&mut self, account: &Arc<str>, amount: Units, at: Timestamp) {
+        self.record(account, at, |stats| {
+            stats.deposited = stats.deposited.wrapping_add(amount as u128)
+        });
+    }
+
+    pub(crate) fn record_withdrawal(&mut self, account: &Arc<str>, amount: Units, at: Timestamp) {
+        self.record(account, at, |stats| {
+            stats.withdrawn = stats.withdrawn.wrapping_add(amount as u128)
+        });
+    }
+
+    pub(crate) fn record_mint(&mut self, account: &Arc<str>, amount: Units, at: Timestamp) {
+        self.record(account, at, |stats| {
+            stats.minted = stats.minted.wrapping_add(amount as u128)
+        });
+    }
+
+    pub(crate) fn record_burn(&mut self, account: &Arc<str>, amount: Units, at: Timestamp) {
+        self.record(account, at, |stats| {
+            stats.burned = stats.burned.wrapping_add(amount as u128)
+        });
+    }
+
+    pub(crate) fn record_transfer(
+        &mut self,
+        sender: &Arc<str>,
+        recipient: &Arc<str>,
+        amount: Units,
+        at: Timestamp,
+    ) {
+        self.record_sent(sender, amount, at);
+        self.record_received(recipient, amount, at);
+    }
+
+    /// Records one side of a transfer, for replays applying them one side at a time
+    pub(crate) fn record_sent(&mut self, sender: &Arc<str>, amount: Units, at: Timestamp) {
+        self.record(sender, at, |stats| {
+            stats.sent = stats.sent.wrapping_add(amount as u128)
+        });
+    }
+
+    /// See [`LedgerStats::record_sent`]
+    pub(crate) fn record_received(&mut self, recipient: &Arc<str>, amount: Units, at: Timestamp) {
+        self.record(recipient, at, |stats| {
+            stats.received = stats.received.wrapping_add(amount as u128)
+        });
+    }
+
+    /// Dates the latest transaction `at` instead, for replays committing logged transactions
+    /// long after they were first committed. Entries of logs from before timestamps carry
+    /// `Timestamp(0)`, which leaves the time unknown.
+    pub(crate) fn backdate(&mut self, at: Timestamp) {
+        if self.last_activity.is_some() {
+            self.last_activity = (at > Timestamp(0)).then_some(at);
+        }
+    }
+
+    fn record(&mut self, account: &Arc<str>, at: Timestamp, update: impl Fn(&mut AccountStats)) {
+        let stats = self.accounts.get_or_insert_default(account);
+        update(stats);
+        stats.txs += 1;
+        let txs = stats.txs;
+        update(&mut self.totals);
+        self.totals.txs += 1;
+        // Counts only grow, so the busiest account only changes to the one just recorded
+        let most = self.busiest().map_or(0, |(_, stats)| stats.txs);
+        if txs > most {
+            self.busiest = Some(account.clone());
+        }
+        self.last_activity = Some(at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ledger_stats_works() {
+        let (alice, bob): (Arc<str>, Arc<str>) = ("ALICE".into(), "BOB".into());
+        let mut stats = LedgerStats::default();
+
+        //act
+        stats.record_deposit(&alice, 100, Timestamp(1_000));
+        stats.record_transfer(&alice, &bob, 30, Timestamp(2_000));
+        stats.record_withdrawal(&bob, 10, Timestamp(3_000));
+        let busiest = stats
+            .busiest()
+            .map(|(account, stats)| (account.to_string(), stats.txs));
+        stats.record_withdrawal(&bob, 10, Timestamp(4_000));
+
+        let alice = stats.account("ALICE").unwrap();
+        assert_eq!((alice.deposited, alice.sent, alice.txs), (100, 30, 2));
+        assert_eq!(alice.average(), Some(65));
+        let bob = stats.account("BOB").unwrap();
+        assert_eq!((bob.received, bob.withdrawn, bob.txs), (30, 20, 3));
+        assert_eq!(stats.totals().volume(), 180);
+        assert_eq!(stats.totals().txs, 5);
+        assert_eq!(busiest, Some(("ALICE".to_string(), 2)));
+        assert_eq!(stats.busiest().map(|(account, _)| account), Some("BOB"));
+        assert_eq!(stats.last_activity(), Some(Timestamp(4_000)));
+        assert_eq!(LedgerStats::default().busiest(), None);
+        assert_eq!(stats.account("CAROL"), None);
+        assert_eq!(AccountStats::default().average(), None);
+    }
+}
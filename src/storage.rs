@@ -0,0 +1,483 @@
+use crate::{
+    accounts::Accounts,
+    clock::{Clock, SystemClock, Timestamp},
+    tx::Tx,
+};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::fs::{File, OpenOptions};
+use std::future::Future;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+/// Durable storage for committed transactions, e.g. a write-ahead log or a database table.
+///
+/// Appending is async so that slow disk or network I/O doesn't block the threads of an async runtime.
+pub trait TxStore: Send + Sync {
+    /// Persists `txs`, in order, as one unit
+    fn append(&self, txs: &[Tx]) -> impl Future<Output = io::Result<()>> + Send;
+}
+
+/// Runs the blocking `write` on a thread of its own and completes with its result once it's
+/// done, like `tokio::task::spawn_blocking` does without tying the stores to a runtime, so
+/// waiting for a disk doesn't hold up the thread polling.
+pub(crate) fn unblock(write: impl FnOnce() -> io::Result<()> + Send + 'static) -> Unblock {
+    let done: Arc<Mutex<Completion>> = Default::default();
+    let completes = done.clone();
+    let spawned = thread::Builder::new()
+        .name("crabbux-write".to_string())
+        .spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(write))
+                .unwrap_or_else(|_| Err(io::Error::other("writing panicked")));
+            let waker = {
+                let mut completion = completes.lock().unwrap();
+                completion.result = Some(result);
+                completion.waker.take()
+            };
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        });
+    if let Err(e) = spawned {
+        done.lock().unwrap().result = Some(Err(e));
+    }
+    Unblock(done)
+}
+
+#[derive(Default)]
+struct Completion {
+    result: Option<io::Result<()>>,
+    waker: Option<Waker>,
+}
+
+/// The result of a write run by [`unblock`]
+pub(crate) struct Unblock(Arc<Mutex<Completion>>);
+
+impl Future for Unblock {
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let mut completion = self.0.lock().unwrap();
+        match completion.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                completion.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// A committed [`Tx`] as it is persisted, stamped with the time it was stored
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub timestamp: Timestamp,
+    pub tx: Tx,
+    /// Who was logged in when the transaction was committed, see
+    /// [`crate::accounts::Accounts::principal`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+}
+
+/// The version of the format [`FileStore`] writes, stated in the first line of the log.
+/// Logs without that line are version 1.
+pub const LOG_VERSION: u32 = 2;
+
+/// What identifies the first line of a log as its header
+const LOG_FORMAT: &str = "crabbux-log";
+
+/// The first line of a log: `{"format":"crabbux-log","version":<n>}`
+#[derive(Serialize, Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+}
+
+/// The header line of a log in the current format, see [`LOG_VERSION`]
+pub fn header() -> String {
+    let header = Header {
+        format: LOG_FORMAT.to_string(),
+        version: LOG_VERSION,
+    };
+    format!("{}\n", serde_json::to_string(&header).unwrap())
+}
+
+/// Replaces the file at `path` with `contents` through a temporary file, synced to disk first
+pub fn replace_file(path: impl AsRef<Path>, contents: &[u8]) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let file = File::create(&tmp)?;
+    (&file).write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(tmp, path)
+}
+
+/// A whole log in the current format holding `entries`, e.g. to replace a log with
+pub fn encode_log(entries: &[LogEntry]) -> io::Result<Vec<u8>> {
+    let mut log = header().into_bytes();
+    for entry in entries {
+        serde_json::to_writer(&mut log, entry)?;
+        log.push(b'\n');
+    }
+    Ok(log)
+}
+
+/// The format version of the log at `path`, see [`LOG_VERSION`]
+pub fn log_version(path: impl AsRef<Path>) -> io::Result<u32> {
+    let mut line = String::new();
+    BufReader::new(File::open(path)?).read_line(&mut line)?;
+    Ok(match serde_json::from_str(&line) {
+        Ok(Header { format, version }) if format == LOG_FORMAT => version,
+        _ => 1,
+    })
+}
+
+/// A line of a log: its header, an entry, or one written before entries were timestamped,
+/// which was a bare [`Tx`]
+enum StoredLine {
+    Header(Header),
+    Entry(LogEntry),
+    Legacy(Tx),
+}
+
+/// Told apart by their fields rather than with `#[serde(untagged)]`, which can't buffer the
+/// numbers of the `u128` feature
+impl<'de> Deserialize<'de> for StoredLine {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let line = if value.get("format").is_some() {
+            serde_json::from_value(value).map(StoredLine::Header)
+        } else if value.get("tx").is_some() {
+            serde_json::from_value(value).map(StoredLine::Entry)
+        } else {
+            serde_json::from_value(value).map(StoredLine::Legacy)
+        };
+        line.map_err(de::Error::custom)
+    }
+}
+
+/// A [`TxStore`] keeping everything in memory, useful for tests and ephemeral ledgers.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    txs: Mutex<Vec<Tx>>,
+}
+
+impl MemoryStore {
+    /// Creates an empty store
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Everything appended so far
+    pub fn txs(&self) -> Vec<Tx> {
+        self.txs.lock().unwrap().clone()
+    }
+}
+
+impl TxStore for MemoryStore {
+    async fn append(&self, txs: &[Tx]) -> io::Result<()> {
+        self.txs.lock().unwrap().extend_from_slice(txs);
+        Ok(())
+    }
+}
+
+/// A [`TxStore`] appending to a file with one JSON encoded [`LogEntry`] per line.
+///
+/// Every append is synced to disk before it completes, on a thread of its own for
+/// [`TxStore::append`]. Read it back with [`LogReader`].
+#[derive(Debug)]
+pub struct FileStore {
+    file: Arc<Mutex<BufWriter<File>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl FileStore {
+    /// Opens the log at `path` for appending, creating it with a header if needed.
+    /// Entries are stamped by the [`SystemClock`].
+    ///
+    /// A log in an older format is appended to as it is, see
+    /// [`crate::migrations::migrate_log`] to upgrade it first.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if file.metadata()?.len() == 0 {
+            file.write_all(header().as_bytes())?;
+            file.sync_data()?;
+        }
+        Ok(FileStore {
+            file: Arc::new(Mutex::new(BufWriter::new(file))),
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    /// Stamps entries using `clock` instead
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Appends `txs` from synchronous code, see [`TxStore::append`].
+    /// They all get the same timestamp.
+    pub fn write(&self, txs: &[Tx]) -> io::Result<()> {
+        self.write_as(txs, None)
+    }
+
+    /// Like [`FileStore::write`], recording `actor` as the one who committed `txs`
+    pub fn write_as(&self, txs: &[Tx], actor: Option<&str>) -> io::Result<()> {
+        sync_append(&self.file, &self.encode(txs, actor)?)
+    }
+
+    /// The lines of `txs` stamped with the current time
+    fn encode(&self, txs: &[Tx], actor: Option<&str>) -> io::Result<Vec<u8>> {
+        let timestamp = self.clock.now();
+        let mut lines = vec![];
+        for tx in txs {
+            let entry = LogEntry {
+                timestamp,
+                tx: tx.clone(),
+                actor: actor.map(str::to_string),
+            };
+            serde_json::to_writer(&mut lines, &entry)?;
+            lines.push(b'\n');
+        }
+        Ok(lines)
+    }
+}
+
+impl TxStore for FileStore {
+    async fn append(&self, txs: &[Tx]) -> io::Result<()> {
+        let lines = self.encode(txs, None)?;
+        let file = self.file.clone();
+        unblock(move || sync_append(&file, &lines)).await
+    }
+}
+
+/// Writes `bytes` to `file` and syncs it to disk
+pub(crate) fn sync_append(file: &Mutex<BufWriter<File>>, bytes: &[u8]) -> io::Result<()> {
+    let mut file = file.lock().unwrap();
+    file.write_all(bytes)?;
+    file.flush()?;
+    file.get_ref().sync_data()
+}
+
+/// Reads a log written by [`FileStore`] one entry at a time, so only the current line is held in memory.
+///
+/// Lines from before entries were timestamped are read with a zero timestamp, and the header
+/// line is skipped. Yields an [`io::ErrorKind::InvalidData`] error for a line that isn't a
+/// valid [`LogEntry`], and an [`io::ErrorKind::Unsupported`] one for a log in a newer format.
+#[derive(Debug)]
+pub struct LogReader<R> {
+    reader: R,
+    line: String,
+    line_number: usize,
+}
+
+impl LogReader<BufReader<File>> {
+    /// Opens the log at `path`
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(LogReader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> LogReader<R> {
+    /// Reads entries from `reader`
+    pub fn new(reader: R) -> Self {
+        LogReader {
+            reader,
+            line: String::new(),
+            line_number: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for LogReader<R> {
+    type Item = io::Result<LogEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            self.line_number += 1;
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) if self.line.trim().is_empty() => continue,
+                Ok(_) => {
+                    let line = serde_json::from_str(&self.line).map_err(|e| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("line {}: {}", self.line_number, e),
+                        )
+                    });
+                    return Some(match line {
+                        Ok(StoredLine::Header(header)) if header.format == LOG_FORMAT => {
+                            match crate::migrations::check_supported(header.version, LOG_VERSION) {
+                                Ok(()) => continue,
+                                Err(e) => Err(e),
+                            }
+                        }
+                        Ok(StoredLine::Header(header)) => Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("line {}: not a {} header", self.line_number, header.format),
+                        )),
+                        Ok(StoredLine::Entry(entry)) => Ok(entry),
+                        Ok(StoredLine::Legacy(tx)) => Ok(LogEntry {
+                            timestamp: Timestamp::default(),
+                            tx,
+                            actor: None,
+                        }),
+                        Err(e) => Err(e),
+                    });
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// The entries of the log at `path` after its first `offset` bytes, and the offset after them,
+/// so following a log only reads what was appended since. A line cut off at the end, e.g.
+/// because it is being appended, is left for the next read.
+/// # Errors
+/// The log can't be read, is shorter than `offset`, e.g. because it was replaced, or has an
+/// invalid line, see [`LogReader`]
+pub fn read_appended(path: impl AsRef<Path>, offset: u64) -> io::Result<(Vec<LogEntry>, u64)> {
+    let mut file = File::open(path)?;
+    if file.metadata()?.len() < offset {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("the log is shorter than the {} bytes read before", offset),
+        ));
+    }
+    file.seek(SeekFrom::Start(offset))?;
+    let mut appended = vec![];
+    file.read_to_end(&mut appended)?;
+    let complete = appended
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    let entries = LogReader::new(&appended[..complete]).collect::<io::Result<_>>()?;
+    Ok((entries, offset + complete as u64))
+}
+
+/// Applies every transaction from `entries` to `ledger`, in order, and returns how many were applied.
+///
+/// Entries are consumed as they are applied, so replaying from a [`LogReader`] never holds the
+/// whole log in memory.
+/// # Errors
+/// Reading an entry failed, or an entry couldn't be applied, e.g. because the log doesn't
+/// start from an empty ledger. Entries before the failing one stay applied.
+pub fn replay(
+    ledger: &mut Accounts,
+    entries: impl IntoIterator<Item = io::Result<LogEntry>>,
+) -> io::Result<usize> {
+    let mut applied = 0;
+    for entry in entries {
+        let entry = entry?;
+        ledger.apply(&entry.tx).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("entry {}: {}", applied + 1, e),
+            )
+        })?;
+        ledger.backdate(entry.timestamp);
+        applied += 1;
+    }
+    debug_assert_eq!(ledger.check_invariants(), Ok(()));
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use std::env;
+    use std::time::Duration;
+
+    #[test]
+    fn test_file_store_replays_into_same_state() {
+        let path = env::temp_dir().join(format!("crabbux-log-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let clock = Arc::new(ManualClock::new(Timestamp(1_000)));
+        let store = FileStore::open(&path).unwrap().with_clock(clock.clone());
+        let mut ledger = Accounts::new();
+        store
+            .write(&[ledger.deposit("ALICE", 100).unwrap()])
+            .unwrap();
+        clock.advance(Duration::from_secs(1));
+        let (withdrawal, deposit) = ledger.send("ALICE", "BOB", 30).unwrap();
+        store
+            .write_as(&[withdrawal, deposit], Some("ALICE"))
+            .unwrap();
+
+        //act
+        let (timestamps, actors): (Vec<_>, Vec<_>) = LogReader::open(&path)
+            .unwrap()
+            .map(|entry| entry.unwrap())
+            .map(|entry| (entry.timestamp, entry.actor))
+            .unzip();
+        let mut replayed = Accounts::new();
+        let applied = replay(&mut replayed, LogReader::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            timestamps,
+            vec![Timestamp(1_000), Timestamp(2_000), Timestamp(2_000)]
+        );
+        let alice = Some("ALICE".to_string());
+        assert_eq!(actors, vec![None, alice.clone(), alice]);
+        assert_eq!(applied, 3);
+        assert_eq!(replayed.balance_of("ALICE"), Ok(&70));
+        assert_eq!(replayed.balance_of("BOB"), Ok(&30));
+        assert_eq!(replayed.stats().last_activity(), Some(Timestamp(2_000)));
+        let alice = replayed.stats().account("ALICE").unwrap();
+        assert_eq!((alice.deposited, alice.sent, alice.txs), (100, 30, 2));
+        assert_eq!(replayed.stats().account("BOB").unwrap().received, 30);
+        assert_eq!(replayed.check_conservation(), Ok(()));
+    }
+
+    #[test]
+    fn test_replay_reports_invalid_entries() {
+        // The first line predates timestamps
+        let log = "{\"Deposit\":{\"account\":\"ALICE\",\"amount\":5}}\n\nnot json\n";
+        let mut ledger = Accounts::new();
+
+        //act
+        let error = replay(&mut ledger, LogReader::new(log.as_bytes())).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("line 3"));
+        assert_eq!(ledger.balance_of("ALICE"), Ok(&5));
+    }
+
+    #[test]
+    fn test_read_appended_reads_only_complete_new_lines() {
+        let path = env::temp_dir().join(format!("crabbux-tail-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let store = FileStore::open(&path).unwrap();
+        let deposit = |amount| Tx::Deposit {
+            account: "ALICE".into(),
+            amount,
+        };
+        store.write(&[deposit(1), deposit(2)]).unwrap();
+        let (first, read) = read_appended(&path, 0).unwrap();
+        store.write(&[deposit(3)]).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"timestamp\":4,\"tx\":").unwrap();
+
+        //act
+        let (appended, end) = read_appended(&path, read).unwrap();
+        let (unchanged, _) = read_appended(&path, end).unwrap();
+        let truncated = read_appended(&path, u64::MAX);
+        std::fs::remove_file(&path).unwrap();
+
+        let amounts =
+            |entries: &[LogEntry]| entries.iter().map(|e| e.tx.amount()).collect::<Vec<_>>();
+        assert_eq!(amounts(&first), vec![1, 2]);
+        assert_eq!(amounts(&appended), vec![3]);
+        assert!(unchanged.is_empty());
+        assert_eq!(truncated.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
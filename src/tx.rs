@@ -1,8 +1,216 @@
+use crate::clock::Timestamp;
+use crate::limits::Period;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Amounts and balances, in the smallest unit of the currency. With the `u128` feature they
+/// are `u128`, for ledgers of micro-units that would overflow a `u64`.
+#[cfg(not(feature = "u128"))]
+pub type Units = u64;
+/// Amounts and balances, in the smallest unit of the currency. With the `u128` feature they
+/// are `u128`, for ledgers of micro-units that would overflow a `u64`.
+#[cfg(feature = "u128")]
+pub type Units = u128;
+
+/// `amount` as a `u64`, or `None` if it doesn't fit, for formats and interfaces that only hold
+/// 64 bits like the WAL and the C API
+#[allow(clippy::useless_conversion)]
+pub fn to_u64(amount: Units) -> Option<u64> {
+    u64::try_from(amount).ok()
+}
+
 /// A transaction type. Transaction replay should be able to rebuild a ledger's state
 /// when they are applied in the same sequence to an empty state.
-#[derive(Debug)]
+///
+/// Account names are shared (see [`crate::accounts::Accounts`]), so cloning a `Tx` or keeping
+/// millions of them for the same accounts doesn't copy the names.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Tx {
     // Add variants for storing withdraw/deposit transactions
-    Deposit { account: String, amount: u64 },
-    Withdraw { account: String, amount: u64 },
+    Deposit {
+        account: Arc<str>,
+        amount: Units,
+    },
+    /// Money taken out of the account, the withdrawal side of a transfer to `recipient` if
+    /// it has one, see [`crate::accounts::Accounts::send`]
+    Withdraw {
+        account: Arc<str>,
+        amount: Units,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        recipient: Option<Arc<str>>,
+    },
+    /// New money issued into the account by an admin, see [`crate::accounts::Accounts::mint`]
+    Mint {
+        account: Arc<str>,
+        amount: Units,
+    },
+    /// Money taken out of circulation from the account by an admin, see
+    /// [`crate::accounts::Accounts::burn`]
+    Burn {
+        account: Arc<str>,
+        amount: Units,
+    },
+    /// Promotional credit granted to the account, kept apart from its balance until it's
+    /// spent or `expires`, see [`crate::accounts::Accounts::grant_promo`]
+    PromoGrant {
+        account: Arc<str>,
+        amount: Units,
+        expires: Timestamp,
+    },
+    /// Promotional credit, of the grant expiring at `expires`, added to the balance of the
+    /// account as it's spent, see [`crate::accounts::Accounts::pay`]
+    PromoSpend {
+        account: Arc<str>,
+        amount: Units,
+        expires: Timestamp,
+    },
+    /// Promotional credit of the grant expiring at `expires` written off unspent, see
+    /// [`crate::accounts::Accounts::expire_promos`]
+    PromoExpire {
+        account: Arc<str>,
+        amount: Units,
+        expires: Timestamp,
+    },
+    /// The account authorized `payee` to collect up to `max` from it per `period`, replacing
+    /// the mandate `payee` had, see [`crate::accounts::Accounts::grant_mandate`]
+    MandateGrant {
+        account: Arc<str>,
+        payee: Arc<str>,
+        max: Units,
+        period: Period,
+    },
+    /// The account revoked the mandate of `payee`, see
+    /// [`crate::accounts::Accounts::revoke_mandate`]
+    MandateRevoke {
+        account: Arc<str>,
+        payee: Arc<str>,
+    },
+    /// `payee` collected `amount` from the account under its mandate, the withdrawal side of
+    /// a transfer like [`Tx::Withdraw`], see [`crate::accounts::Accounts::collect`]
+    Collect {
+        account: Arc<str>,
+        payee: Arc<str>,
+        amount: Units,
+    },
+}
+
+impl Tx {
+    /// The account affected by this transaction
+    pub fn account(&self) -> &str {
+        match self {
+            Tx::Deposit { account, .. }
+            | Tx::Withdraw { account, .. }
+            | Tx::Mint { account, .. }
+            | Tx::Burn { account, .. }
+            | Tx::PromoGrant { account, .. }
+            | Tx::PromoSpend { account, .. }
+            | Tx::PromoExpire { account, .. }
+            | Tx::MandateGrant { account, .. }
+            | Tx::MandateRevoke { account, .. }
+            | Tx::Collect { account, .. } => account,
+        }
+    }
+
+    /// The shared name of the account affected by this transaction
+    pub fn account_name(&self) -> &Arc<str> {
+        match self {
+            Tx::Deposit { account, .. }
+            | Tx::Withdraw { account, .. }
+            | Tx::Mint { account, .. }
+            | Tx::Burn { account, .. }
+            | Tx::PromoGrant { account, .. }
+            | Tx::PromoSpend { account, .. }
+            | Tx::PromoExpire { account, .. }
+            | Tx::MandateGrant { account, .. }
+            | Tx::MandateRevoke { account, .. }
+            | Tx::Collect { account, .. } => account,
+        }
+    }
+
+    /// The amount moved by this transaction, 0 for mandates, which don't move money
+    pub fn amount(&self) -> Units {
+        match self {
+            Tx::Deposit { amount, .. }
+            | Tx::Withdraw { amount, .. }
+            | Tx::Mint { amount, .. }
+            | Tx::Burn { amount, .. }
+            | Tx::PromoGrant { amount, .. }
+            | Tx::PromoSpend { amount, .. }
+            | Tx::PromoExpire { amount, .. }
+            | Tx::Collect { amount, .. } => *amount,
+            Tx::MandateGrant { .. } | Tx::MandateRevoke { .. } => 0,
+        }
+    }
+
+    /// A short, lowercase name of the transaction type
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Tx::Deposit { .. } => "deposit",
+            Tx::Withdraw { .. } => "withdraw",
+            Tx::Mint { .. } => "mint",
+            Tx::Burn { .. } => "burn",
+            Tx::PromoGrant { .. } => "promo-grant",
+            Tx::PromoSpend { .. } => "promo-spend",
+            Tx::PromoExpire { .. } => "promo-expire",
+            Tx::MandateGrant { .. } => "mandate-grant",
+            Tx::MandateRevoke { .. } => "mandate-revoke",
+            Tx::Collect { .. } => "collect",
+        }
+    }
+
+    /// Returns `true` if this transaction adds to the balance of its account, i.e. it's a
+    /// deposit, a mint or spent promotional credit
+    pub fn is_credit(&self) -> bool {
+        matches!(
+            self,
+            Tx::Deposit { .. } | Tx::Mint { .. } | Tx::PromoSpend { .. }
+        )
+    }
+
+    /// How much this transaction adds to the balance of its account, negative if it takes
+    /// from it. Promotional credit is only part of the balance once spent, so granting it and
+    /// writing it off don't change the balance, and neither do mandates.
+    pub fn balance_change(&self) -> i128 {
+        match self {
+            Tx::PromoGrant { .. }
+            | Tx::PromoExpire { .. }
+            | Tx::MandateGrant { .. }
+            | Tx::MandateRevoke { .. } => 0,
+            tx if tx.is_credit() => tx.amount() as i128,
+            tx => -(tx.amount() as i128),
+        }
+    }
+
+    /// When the promotional credit this transaction grants, spends or writes off expires,
+    /// `None` for other transactions
+    pub fn expires(&self) -> Option<Timestamp> {
+        match self {
+            Tx::PromoGrant { expires, .. }
+            | Tx::PromoSpend { expires, .. }
+            | Tx::PromoExpire { expires, .. } => Some(*expires),
+            _ => None,
+        }
+    }
+
+    /// The payee of the mandate this transaction grants, revokes or collects under, `None` for
+    /// other transactions
+    pub fn payee(&self) -> Option<&str> {
+        match self {
+            Tx::MandateGrant { payee, .. }
+            | Tx::MandateRevoke { payee, .. }
+            | Tx::Collect { payee, .. } => Some(payee),
+            _ => None,
+        }
+    }
+
+    /// The account the withdrawal side of a transfer sent to, the payee of collections, `None`
+    /// for other transactions
+    pub fn recipient(&self) -> Option<&str> {
+        match self {
+            Tx::Withdraw { recipient, .. } => recipient.as_deref(),
+            Tx::Collect { payee, .. } => Some(payee),
+            _ => None,
+        }
+    }
 }
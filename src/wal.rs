@@ -0,0 +1,786 @@
+use crate::{
+    accounts::Accounts,
+    checksum,
+    clock::{Clock, SystemClock, Timestamp},
+    limits::Period,
+    storage::{self, LogEntry, TxStore},
+    tx::{self, Tx, Units},
+};
+use memmap2::Mmap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// The first bytes of every WAL file [`WalWriter`] creates
+pub const MAGIC: &[u8; 8] = b"CRABWAL3";
+/// The first bytes of WALs written before entries carried a checksum
+pub const MAGIC_V2: &[u8; 8] = b"CRABWAL2";
+
+const DEPOSIT: u8 = 0;
+const WITHDRAW: u8 = 1;
+const MINT: u8 = 2;
+const BURN: u8 = 3;
+const PROMO_GRANT: u8 = 4;
+const PROMO_SPEND: u8 = 5;
+const PROMO_EXPIRE: u8 = 6;
+const MANDATE_GRANT: u8 = 7;
+const MANDATE_REVOKE: u8 = 8;
+const COLLECT: u8 = 9;
+/// A withdrawal that is the withdrawal side of a transfer, followed by the recipient
+const SEND: u8 = 10;
+/// Kind, account length, amount and timestamp
+const ENTRY_HEADER_LEN: usize = 1 + 2 + 8 + 8;
+
+/// The length of what follows the account name of an entry of `kind`, starting with
+/// `trailer`: when the promotional credit of promotional entries expires, or the period of
+/// mandate grants and the payee of mandates and collections or the recipient of sends. `None`
+/// if `trailer` is too short to tell.
+fn trailer_len(kind: u8, trailer: &[u8]) -> Option<usize> {
+    let payee_len = |at: usize| {
+        let len = trailer.get(at..at + 2)?;
+        Some(at + 2 + u16::from_le_bytes([len[0], len[1]]) as usize)
+    };
+    match kind {
+        PROMO_GRANT | PROMO_SPEND | PROMO_EXPIRE => Some(8),
+        MANDATE_GRANT => payee_len(1),
+        MANDATE_REVOKE | COLLECT | SEND => payee_len(0),
+        _ => Some(0),
+    }
+}
+
+fn period_code(period: Period) -> u8 {
+    match period {
+        Period::Hour => 0,
+        Period::Day => 1,
+        Period::Week => 2,
+    }
+}
+
+fn period_of(code: u8) -> io::Result<Period> {
+    match code {
+        0 => Ok(Period::Hour),
+        1 => Ok(Period::Day),
+        2 => Ok(Period::Week),
+        code => Err(invalid(format!("unknown mandate period {}", code))),
+    }
+}
+
+/// The length of account name `name` as a `u16`
+fn name_len(name: &str) -> io::Result<u16> {
+    u16::try_from(name.len())
+        .map_err(|_| invalid(format!("account name of {} bytes is too long", name.len())))
+}
+
+/// How the entries of a WAL are laid out, told apart by the first bytes of the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Entries without a checksum, after [`MAGIC_V2`]
+    V2,
+    /// Entries ending in a CRC32 of the entry, after [`MAGIC`]
+    V3,
+}
+
+impl Format {
+    /// The format of the WAL starting with `bytes`
+    /// # Errors
+    /// `bytes` doesn't start with the header of any format
+    pub fn of(bytes: &[u8]) -> io::Result<Format> {
+        if bytes.starts_with(MAGIC) {
+            Ok(Format::V3)
+        } else if bytes.starts_with(MAGIC_V2) {
+            Ok(Format::V2)
+        } else {
+            Err(invalid("not a crabbux WAL".to_string()))
+        }
+    }
+
+    /// The first bytes of a WAL in this format
+    pub fn magic(self) -> &'static [u8; 8] {
+        match self {
+            Format::V2 => MAGIC_V2,
+            Format::V3 => MAGIC,
+        }
+    }
+
+    /// The number of the format, counting on from the first WAL format
+    pub fn version(self) -> u32 {
+        match self {
+            Format::V2 => 2,
+            Format::V3 => 3,
+        }
+    }
+
+    fn checksum_len(self) -> usize {
+        match self {
+            Format::V2 => 0,
+            Format::V3 => 4,
+        }
+    }
+
+    /// Appends the encoding of `tx` stored at `timestamp` to `buffer`, see [`encode`]
+    pub fn encode(self, tx: &Tx, timestamp: Timestamp, buffer: &mut Vec<u8>) -> io::Result<()> {
+        let kind = match tx {
+            Tx::Deposit { .. } => DEPOSIT,
+            Tx::Withdraw {
+                recipient: None, ..
+            } => WITHDRAW,
+            Tx::Withdraw { .. } => SEND,
+            Tx::Mint { .. } => MINT,
+            Tx::Burn { .. } => BURN,
+            Tx::PromoGrant { .. } => PROMO_GRANT,
+            Tx::PromoSpend { .. } => PROMO_SPEND,
+            Tx::PromoExpire { .. } => PROMO_EXPIRE,
+            Tx::MandateGrant { .. } => MANDATE_GRANT,
+            Tx::MandateRevoke { .. } => MANDATE_REVOKE,
+            Tx::Collect { .. } => COLLECT,
+        };
+        let account = tx.account().as_bytes();
+        let len = name_len(tx.account())?;
+        // Mandates move nothing, so grants keep their maximum where the amount goes
+        let amount = match tx {
+            Tx::MandateGrant { max, .. } => *max,
+            tx => tx.amount(),
+        };
+        let amount = tx::to_u64(amount)
+            .ok_or_else(|| invalid(format!("amount {} is too large for a WAL", amount)))?;
+        let start = buffer.len();
+        buffer.push(kind);
+        buffer.extend_from_slice(&len.to_le_bytes());
+        buffer.extend_from_slice(&amount.to_le_bytes());
+        buffer.extend_from_slice(&timestamp.0.to_le_bytes());
+        buffer.extend_from_slice(account);
+        if let Some(expires) = tx.expires() {
+            buffer.extend_from_slice(&expires.0.to_le_bytes());
+        }
+        if let Tx::MandateGrant { period, .. } = tx {
+            buffer.push(period_code(*period));
+        }
+        if let Some(payee) = tx.payee().or(tx.recipient()) {
+            buffer.extend_from_slice(&name_len(payee)?.to_le_bytes());
+            buffer.extend_from_slice(payee.as_bytes());
+        }
+        if self == Format::V3 {
+            let crc = crc32fast::hash(&buffer[start..]);
+            buffer.extend_from_slice(&crc.to_le_bytes());
+        }
+        Ok(())
+    }
+
+    /// Decodes the entry at the start of `bytes`, see [`decode`]
+    pub fn decode(self, bytes: &[u8]) -> io::Result<(TxRef<'_>, usize)> {
+        let header = bytes
+            .get(..ENTRY_HEADER_LEN)
+            .ok_or_else(|| invalid("truncated entry header".to_string()))?;
+        let len = u16::from_le_bytes([header[1], header[2]]) as usize;
+        let account_end = ENTRY_HEADER_LEN + len;
+        let account = bytes
+            .get(ENTRY_HEADER_LEN..account_end)
+            .ok_or_else(|| invalid("truncated account name".to_string()))?;
+        let trailer_len = trailer_len(header[0], &bytes[account_end..])
+            .ok_or_else(|| invalid("truncated payee".to_string()))?;
+        let entry_len = account_end + trailer_len;
+        let trailer = bytes
+            .get(account_end..entry_len)
+            .ok_or_else(|| invalid("truncated entry".to_string()))?;
+        if self == Format::V3 {
+            let crc = bytes
+                .get(entry_len..entry_len + 4)
+                .ok_or_else(|| invalid("truncated checksum".to_string()))?;
+            if u32::from_le_bytes(crc.try_into().unwrap()) != crc32fast::hash(&bytes[..entry_len]) {
+                return Err(invalid("the entry doesn't match its checksum".to_string()));
+            }
+        }
+        let kind = match header[0] {
+            DEPOSIT => EntryKind::Deposit,
+            WITHDRAW | SEND => EntryKind::Withdraw,
+            MINT => EntryKind::Mint,
+            BURN => EntryKind::Burn,
+            PROMO_GRANT => EntryKind::PromoGrant,
+            PROMO_SPEND => EntryKind::PromoSpend,
+            PROMO_EXPIRE => EntryKind::PromoExpire,
+            MANDATE_GRANT => EntryKind::MandateGrant,
+            MANDATE_REVOKE => EntryKind::MandateRevoke,
+            COLLECT => EntryKind::Collect,
+            kind => return Err(invalid(format!("unknown entry kind {}", kind))),
+        };
+        let amount = u64::from_le_bytes(header[3..11].try_into().unwrap()) as Units;
+        let timestamp = Timestamp(u64::from_le_bytes(header[11..].try_into().unwrap()));
+        let account = std::str::from_utf8(account).map_err(|e| invalid(e.to_string()))?;
+        let (mut expires, mut period, mut payee) = (None, None, None);
+        match kind {
+            EntryKind::PromoGrant | EntryKind::PromoSpend | EntryKind::PromoExpire => {
+                expires = Some(Timestamp(u64::from_le_bytes(trailer.try_into().unwrap())));
+            }
+            EntryKind::Withdraw if trailer.is_empty() => {}
+            EntryKind::Withdraw
+            | EntryKind::MandateGrant
+            | EntryKind::MandateRevoke
+            | EntryKind::Collect => {
+                let name = match kind {
+                    EntryKind::MandateGrant => {
+                        period = Some(period_of(trailer[0])?);
+                        &trailer[3..]
+                    }
+                    _ => &trailer[2..],
+                };
+                payee = Some(std::str::from_utf8(name).map_err(|e| invalid(e.to_string()))?);
+            }
+            _ => {}
+        }
+        let entry = TxRef {
+            kind,
+            account,
+            amount,
+            timestamp,
+            expires,
+            period,
+            payee,
+        };
+        Ok((entry, entry_len + self.checksum_len()))
+    }
+
+    /// The length of the complete entries at the start of `bytes`, see [`complete_len`]
+    pub fn complete_len(self, bytes: &[u8]) -> io::Result<usize> {
+        let mut len = 0;
+        while len < bytes.len() {
+            let rest = &bytes[len..];
+            match self.decode(rest) {
+                Ok((_, entry_len)) => len += entry_len,
+                Err(e) => {
+                    let account_len = rest
+                        .get(1..3)
+                        .map_or(0, |len| u16::from_le_bytes([len[0], len[1]]) as usize);
+                    let account_end = ENTRY_HEADER_LEN + account_len;
+                    let trailer = rest.get(account_end..).unwrap_or_default();
+                    let Some(trailer_len) = trailer_len(rest[0], trailer) else {
+                        break;
+                    };
+                    let entry_len = account_end + trailer_len;
+                    if rest.len() < entry_len + self.checksum_len() {
+                        break;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(len)
+    }
+}
+
+/// A [`TxStore`] appending to a binary write-ahead log.
+///
+/// After the [`MAGIC`] header, every entry is laid out as
+/// `kind: u8 | account length: u16 LE | amount: u64 LE | timestamp: u64 LE | account: UTF-8 bytes | CRC32: u32 LE`,
+/// with the kind being 0 for deposits, 1 for withdrawals, 2 for mints and 3 for burns, the timestamp in milliseconds and
+/// the CRC32 over the rest of the entry. Promotional credit, of kind 4 for grants, 5 for spends
+/// and 6 for expirations, has `expiry: u64 LE` in milliseconds between the account and the CRC32.
+/// Mandates, of kind 7 for grants and 8 for revocations, have `payee length: u16 LE | payee`
+/// there, after `period: u8` (0 hourly, 1 daily, 2 weekly) for grants, whose amount is the
+/// maximum. Collections, of kind 9, have the payee there too. WALs in an older [`Format`] are
+/// appended to in their format. Every append is synced to disk before it completes, on a
+/// thread of its own for [`TxStore::append`]. Read it back with [`MmapWal`].
+#[derive(Debug)]
+pub struct WalWriter {
+    file: Arc<Mutex<BufWriter<File>>>,
+    format: Format,
+    clock: Arc<dyn Clock>,
+}
+
+impl WalWriter {
+    /// Opens the WAL at `path` for appending, creating it if needed.
+    /// Entries are stamped by the [`SystemClock`].
+    /// # Errors
+    /// Opening failed, or the file isn't a WAL
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let empty = file.metadata()?.len() == 0;
+        let mut file = BufWriter::new(file);
+        let format = if empty {
+            // Right away, so a WAL nothing was appended to still opens
+            file.write_all(MAGIC)?;
+            file.flush()?;
+            file.get_ref().sync_data()?;
+            Format::V3
+        } else {
+            let mut magic = [0; MAGIC.len()];
+            File::open(path)?.read_exact(&mut magic)?;
+            Format::of(&magic)?
+        };
+        Ok(WalWriter {
+            file: Arc::new(Mutex::new(file)),
+            format,
+            clock: Arc::new(SystemClock),
+        })
+    }
+
+    /// Stamps entries using `clock` instead
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Appends `txs` from synchronous code, see [`TxStore::append`].
+    /// They all get the same timestamp.
+    pub fn write(&self, txs: &[Tx]) -> io::Result<()> {
+        storage::sync_append(&self.file, &self.encode(txs)?)
+    }
+
+    /// The entries of `txs` stamped with the current time
+    fn encode(&self, txs: &[Tx]) -> io::Result<Vec<u8>> {
+        let timestamp = self.clock.now();
+        let mut buffer = vec![];
+        for tx in txs {
+            self.format.encode(tx, timestamp, &mut buffer)?;
+        }
+        Ok(buffer)
+    }
+}
+
+impl TxStore for WalWriter {
+    async fn append(&self, txs: &[Tx]) -> io::Result<()> {
+        let entries = self.encode(txs)?;
+        let file = self.file.clone();
+        storage::unblock(move || storage::sync_append(&file, &entries)).await
+    }
+}
+
+/// Appends the binary encoding of `tx` stored at `timestamp` to `buffer`, in the current
+/// format with a checksum
+/// # Errors
+/// The account name is longer than 65535 bytes, or the amount doesn't fit a `u64` with the
+/// `u128` feature
+pub fn encode(tx: &Tx, timestamp: Timestamp, buffer: &mut Vec<u8>) -> io::Result<()> {
+    Format::V3.encode(tx, timestamp, buffer)
+}
+
+/// A whole WAL in the current format holding `entries`, e.g. to replace a WAL with.
+/// Actors aren't kept, the format has no room for them.
+pub fn encode_wal(entries: &[LogEntry]) -> io::Result<Vec<u8>> {
+    let mut wal = MAGIC.to_vec();
+    for entry in entries {
+        encode(&entry.tx, entry.timestamp, &mut wal)?;
+    }
+    Ok(wal)
+}
+
+/// The length of the complete entries at the start of `bytes`, a WAL after its [`MAGIC`].
+///
+/// An entry cut off at the end, e.g. because it is being appended while the WAL is read, is
+/// left out.
+/// # Errors
+/// An entry before the end is invalid or doesn't match its checksum
+pub fn complete_len(bytes: &[u8]) -> io::Result<usize> {
+    Format::V3.complete_len(bytes)
+}
+
+/// Which [`Tx`] a WAL entry holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    Deposit,
+    Withdraw,
+    Mint,
+    Burn,
+    PromoGrant,
+    PromoSpend,
+    PromoExpire,
+    MandateGrant,
+    MandateRevoke,
+    Collect,
+}
+
+/// A WAL entry borrowing its account name from the underlying bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxRef<'a> {
+    pub kind: EntryKind,
+    pub account: &'a str,
+    pub amount: Units,
+    pub timestamp: Timestamp,
+    /// When the promotional credit of promotional entries expires
+    pub expires: Option<Timestamp>,
+    /// The period of mandate grants
+    pub period: Option<Period>,
+    /// The payee of mandates and collections, or the recipient of withdrawals sending to one
+    pub payee: Option<&'a str>,
+}
+
+impl TxRef<'_> {
+    /// An owned copy of the transaction
+    pub fn to_tx(&self) -> Tx {
+        let (account, amount) = (self.account.into(), self.amount);
+        match self.kind {
+            EntryKind::Deposit => Tx::Deposit { account, amount },
+            EntryKind::Withdraw => Tx::Withdraw {
+                account,
+                amount,
+                recipient: self.payee.map(Into::into),
+            },
+            EntryKind::Mint => Tx::Mint { account, amount },
+            EntryKind::Burn => Tx::Burn { account, amount },
+            EntryKind::PromoGrant => Tx::PromoGrant {
+                account,
+                amount,
+                expires: self.expires.unwrap_or_default(),
+            },
+            EntryKind::PromoSpend => Tx::PromoSpend {
+                account,
+                amount,
+                expires: self.expires.unwrap_or_default(),
+            },
+            EntryKind::PromoExpire => Tx::PromoExpire {
+                account,
+                amount,
+                expires: self.expires.unwrap_or_default(),
+            },
+            EntryKind::MandateGrant => Tx::MandateGrant {
+                account,
+                payee: self.payee.unwrap_or_default().into(),
+                max: amount,
+                period: self.period.unwrap_or(Period::Day),
+            },
+            EntryKind::MandateRevoke => Tx::MandateRevoke {
+                account,
+                payee: self.payee.unwrap_or_default().into(),
+            },
+            EntryKind::Collect => Tx::Collect {
+                account,
+                payee: self.payee.unwrap_or_default().into(),
+                amount,
+            },
+        }
+    }
+
+    /// An owned copy of the entry
+    pub fn to_entry(&self) -> LogEntry {
+        LogEntry {
+            timestamp: self.timestamp,
+            tx: self.to_tx(),
+            actor: None,
+        }
+    }
+}
+
+/// Decodes the entry at the start of `bytes` without copying and returns it with its encoded length.
+/// # Errors
+/// `bytes` is truncated, doesn't start with a valid entry or the entry doesn't match its checksum
+pub fn decode(bytes: &[u8]) -> io::Result<(TxRef<'_>, usize)> {
+    Format::V3.decode(bytes)
+}
+
+/// A read-only, memory-mapped view of a WAL written by [`WalWriter`].
+///
+/// Entries are decoded in place as [`TxRef`]s, so reading the log neither copies it into
+/// memory nor allocates per entry; the OS pages it in as it is read.
+#[derive(Debug)]
+pub struct MmapWal {
+    map: Mmap,
+    format: Format,
+    path: PathBuf,
+}
+
+impl MmapWal {
+    /// Maps the WAL at `path`
+    /// # Errors
+    /// The file can't be mapped or doesn't start with the header of a [`Format`]
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        // SAFETY: the map is read-only, and the WAL is only ever appended to, so bytes that
+        // are visible through the map aren't modified while it exists
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(MmapWal {
+            format: Format::of(&map)?,
+            map,
+            path: path.to_path_buf(),
+        })
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Iterates over the entries in order. Stops after the first error, which is
+    /// [`checksum::corrupt`] as the writer only ever appends whole entries.
+    pub fn iter(&self) -> impl Iterator<Item = io::Result<TxRef<'_>>> {
+        let mut rest = &self.map[MAGIC.len()..];
+        let mut entries = 0;
+        std::iter::from_fn(move || {
+            if rest.is_empty() {
+                return None;
+            }
+            match self.format.decode(rest) {
+                Ok((entry, len)) => {
+                    rest = &rest[len..];
+                    entries += 1;
+                    Some(Ok(entry))
+                }
+                Err(e) => {
+                    rest = &[];
+                    let what = format!("entry {}: {}", entries + 1, e);
+                    Some(Err(checksum::corrupt(&self.path, what)))
+                }
+            }
+        })
+    }
+
+    /// The complete entries after the first `offset` bytes of the WAL, and the offset after
+    /// them, like [`crate::storage::read_appended`] does for a tx log. An entry cut off at the
+    /// end is left out, see [`complete_len`].
+    /// # Errors
+    /// The WAL is shorter than `offset`, or an entry before the end is invalid
+    pub fn entries_after(&self, offset: usize) -> io::Result<(Vec<LogEntry>, usize)> {
+        let start = offset.max(MAGIC.len());
+        let Some(appended) = self.map.get(start..) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} is shorter than the {} bytes read before",
+                    self.path.display(),
+                    offset
+                ),
+            ));
+        };
+        let mut rest = &appended[..self.format.complete_len(appended)?];
+        let end = start + rest.len();
+        let mut entries = vec![];
+        while !rest.is_empty() {
+            let (entry, len) = self.format.decode(rest)?;
+            entries.push(entry.to_entry());
+            rest = &rest[len..];
+        }
+        Ok((entries, end))
+    }
+
+    /// Applies every entry to `ledger`, in order, and returns how many were applied.
+    /// See [`crate::storage::replay`] for the error cases.
+    pub fn replay(&self, ledger: &mut Accounts) -> io::Result<usize> {
+        let mut applied = 0;
+        for entry in self.iter() {
+            let entry = entry?;
+            let result = match entry.kind {
+                EntryKind::Deposit => ledger.apply_deposit(entry.account, entry.amount),
+                EntryKind::Withdraw if entry.payee.is_none() => {
+                    ledger.apply_withdrawal(entry.account, entry.amount)
+                }
+                _ => ledger.apply(&entry.to_tx()),
+            };
+            result.map_err(|e| invalid(format!("entry {}: {}", applied + 1, e)))?;
+            ledger.backdate(entry.timestamp);
+            applied += 1;
+        }
+        debug_assert_eq!(ledger.check_invariants(), Ok(()));
+        Ok(applied)
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use std::env;
+
+    #[test]
+    fn test_wal_round_trip_works() {
+        let path = env::temp_dir().join(format!("crabbux-{}.wal", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let clock = Arc::new(ManualClock::new(Timestamp(42)));
+        let mut ledger = Accounts::new();
+        let deposit = ledger.deposit("ALICE", 100).unwrap();
+        let (withdrawal, deposit2) = ledger.send("ALICE", "BOB", 30).unwrap();
+        let promo = ledger
+            .grant_promo("ALICE", 20, Timestamp(u64::MAX))
+            .unwrap();
+        let paid = ledger.pay("ALICE", "BOB", 25).unwrap();
+        WalWriter::open(&path)
+            .unwrap()
+            .with_clock(clock)
+            .write(std::slice::from_ref(&deposit))
+            .unwrap();
+        WalWriter::open(&path)
+            .unwrap()
+            .write(&[withdrawal.clone(), deposit2, promo])
+            .unwrap();
+        WalWriter::open(&path).unwrap().write(&paid).unwrap();
+        let mandate = ledger
+            .grant_mandate("ALICE", "BOB", "10/week".parse().unwrap())
+            .unwrap();
+        let collected = ledger.collect("BOB", "ALICE", 5).unwrap();
+        let revoked = ledger.revoke_mandate("ALICE", "BOB").unwrap().unwrap();
+        WalWriter::open(&path)
+            .unwrap()
+            .write(&[vec![mandate.clone()], collected, vec![revoked.clone()]].concat())
+            .unwrap();
+
+        //act
+        let wal = MmapWal::open(&path).unwrap();
+        let first = wal.iter().next().unwrap().unwrap().to_entry();
+        let txs: Vec<Tx> = wal.iter().map(|entry| entry.unwrap().to_tx()).collect();
+        let mut replayed = Accounts::new();
+        let applied = wal.replay(&mut replayed).unwrap();
+        drop(wal);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            first,
+            LogEntry {
+                timestamp: Timestamp(42),
+                tx: deposit,
+                actor: None,
+            }
+        );
+        assert_eq!(applied, 11);
+        assert_eq!(
+            (&txs[1], &txs[7], &txs[10]),
+            (&withdrawal, &mandate, &revoked)
+        );
+        assert_eq!(withdrawal.recipient(), Some("BOB"));
+        for account in ["ALICE", "BOB"] {
+            assert_eq!(
+                replayed.stats().account(account),
+                ledger.stats().account(account)
+            );
+        }
+        assert_eq!(mandate.payee(), Some("BOB"));
+        assert_eq!(replayed.balance_of("ALICE"), Ok(&60));
+        assert_eq!(replayed.balance_of("BOB"), Ok(&60));
+        assert_eq!(replayed.mandate("ALICE", "BOB"), None);
+        assert_eq!(replayed.promo_credits("ALICE"), None);
+    }
+
+    #[cfg(feature = "u128")]
+    #[test]
+    fn test_encode_refuses_amounts_wider_than_u64() {
+        let mut ledger = Accounts::new();
+        let wide = Units::from(u64::MAX) + 1;
+        let deposit = ledger.deposit("ALICE", wide).unwrap();
+        let line = serde_json::to_string(&deposit).unwrap();
+
+        //act
+        let encoded = encode(&deposit, Timestamp(0), &mut vec![]);
+
+        assert_eq!(ledger.balance_of("ALICE"), Ok(&wide));
+        assert_eq!(serde_json::from_str::<Tx>(&line).unwrap(), deposit);
+        assert!(encoded
+            .unwrap_err()
+            .to_string()
+            .contains("too large for a WAL"));
+    }
+
+    #[test]
+    fn test_decode_rejects_truncated_entries() {
+        let mut buffer = vec![];
+        let tx = Tx::Withdraw {
+            account: "ALICE".into(),
+            amount: 7,
+            recipient: None,
+        };
+        encode(&tx, Timestamp(9), &mut buffer).unwrap();
+
+        assert_eq!(
+            decode(&buffer).unwrap(),
+            (
+                TxRef {
+                    kind: EntryKind::Withdraw,
+                    account: "ALICE",
+                    amount: 7,
+                    timestamp: Timestamp(9),
+                    expires: None,
+                    period: None,
+                    payee: None,
+                },
+                buffer.len()
+            )
+        );
+        assert!(decode(&buffer[..buffer.len() - 1]).is_err());
+        assert!(decode(&buffer[..ENTRY_HEADER_LEN - 1]).is_err());
+        let mut two = buffer.repeat(2);
+        assert_eq!(complete_len(&two[..two.len() - 1]).unwrap(), buffer.len());
+        two[0] = 0xff;
+        assert!(complete_len(&two).is_err());
+    }
+
+    #[test]
+    fn test_mmap_wal_refuses_damaged_entries_and_reads_v2() {
+        let path = |name: &str| {
+            env::temp_dir().join(format!("crabbux-{}-{}.wal", std::process::id(), name))
+        };
+        let deposit = Tx::Deposit {
+            account: "ALICE".into(),
+            amount: 100,
+        };
+        let mut legacy = MAGIC_V2.to_vec();
+        Format::V2
+            .encode(&deposit, Timestamp(1), &mut legacy)
+            .unwrap();
+        std::fs::write(path("v2"), &legacy).unwrap();
+        WalWriter::open(path("v2"))
+            .unwrap()
+            .write(std::slice::from_ref(&deposit))
+            .unwrap();
+        let writer = WalWriter::open(path("v3")).unwrap();
+        writer.write(&[deposit.clone(), deposit]).unwrap();
+        let mut damaged = std::fs::read(path("v3")).unwrap();
+        // The amount of the second entry
+        damaged[MAGIC.len() + ENTRY_HEADER_LEN + 5 + 4 + 3] ^= 1;
+        std::fs::write(path("v3"), &damaged).unwrap();
+
+        //act
+        let v2 = MmapWal::open(path("v2")).unwrap();
+        let mut replayed = Accounts::new();
+        let applied = v2.replay(&mut replayed);
+        let v3 = MmapWal::open(path("v3")).unwrap();
+        let entries: Vec<_> = v3.iter().map(|entry| entry.map(|e| e.to_entry())).collect();
+        drop((v2, v3));
+        std::fs::remove_file(path("v2")).unwrap();
+        std::fs::remove_file(path("v3")).unwrap();
+
+        assert_eq!(applied.unwrap(), 2);
+        assert_eq!(replayed.balance_of("ALICE"), Ok(&200));
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].is_ok());
+        let error = entries[1].as_ref().unwrap_err();
+        assert!(checksum::is_corrupt(error));
+        assert!(error
+            .to_string()
+            .contains("entry 2: the entry doesn't match its checksum"));
+        assert!(complete_len(&damaged[MAGIC.len()..]).is_err());
+    }
+
+    #[test]
+    fn test_mmap_wal_entries_after_reads_only_complete_new_entries() {
+        let path = env::temp_dir().join(format!("crabbux-{}-tail.wal", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let deposit = |amount| Tx::Deposit {
+            account: "ALICE".into(),
+            amount,
+        };
+        let writer = WalWriter::open(&path).unwrap();
+        writer.write(&[deposit(1), deposit(2)]).unwrap();
+        let (first, read) = MmapWal::open(&path).unwrap().entries_after(0).unwrap();
+        writer.write(&[deposit(3)]).unwrap();
+        let mut cut_off = vec![];
+        encode(&deposit(4), Timestamp(4), &mut cut_off).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&cut_off[..cut_off.len() - 1]).unwrap();
+
+        //act
+        let wal = MmapWal::open(&path).unwrap();
+        let (appended, end) = wal.entries_after(read).unwrap();
+        let (unchanged, _) = wal.entries_after(end).unwrap();
+        let truncated = wal.entries_after(usize::MAX);
+        drop(wal);
+        std::fs::remove_file(&path).unwrap();
+
+        let amounts =
+            |entries: &[LogEntry]| entries.iter().map(|e| e.tx.amount()).collect::<Vec<_>>();
+        assert_eq!(amounts(&first), vec![1, 2]);
+        assert_eq!(amounts(&appended), vec![3]);
+        assert!(unchanged.is_empty());
+        assert!(truncated.is_err());
+    }
+}
@@ -0,0 +1,79 @@
+//! JavaScript bindings for the ledger, for browsers and Node.js.
+//!
+//! Build them with `wasm-pack build --target web -- --no-default-features --features wasm`
+//! (or `--target nodejs`). Amounts are `BigInt`s on the JavaScript side, and failing
+//! operations throw an `Error` with the ledger's message.
+
+use crate::{
+    accounts::Accounts,
+    tx::{Tx, Units},
+};
+use wasm_bindgen::prelude::*;
+
+/// An in-memory ledger that remembers every committed transaction
+#[wasm_bindgen]
+#[derive(Debug, Default)]
+pub struct Ledger {
+    accounts: Accounts,
+    history: Vec<Tx>,
+}
+
+#[wasm_bindgen]
+impl Ledger {
+    /// Creates an empty ledger
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Deposits `amount` into `account`, creating it if needed
+    pub fn deposit(&mut self, account: &str, amount: Units) -> Result<(), JsError> {
+        let tx = self.accounts.deposit(account, amount)?;
+        self.history.push(tx);
+        Ok(())
+    }
+
+    /// Withdraws `amount` from `account`
+    pub fn withdraw(&mut self, account: &str, amount: Units) -> Result<(), JsError> {
+        let tx = self.accounts.withdraw(account, amount)?;
+        self.history.push(tx);
+        Ok(())
+    }
+
+    /// Transfers `amount` from `sender` to `recipient`
+    pub fn send(&mut self, sender: &str, recipient: &str, amount: Units) -> Result<(), JsError> {
+        let (withdrawal, deposit) = self.accounts.send(sender, recipient, amount)?;
+        self.history.extend([withdrawal, deposit]);
+        Ok(())
+    }
+
+    /// The balance of `account`
+    pub fn balance(&self, account: &str) -> Result<Units, JsError> {
+        Ok(*self.accounts.balance_of(account)?)
+    }
+
+    /// The committed transactions as a JSON array, oldest first, in the tx log's encoding
+    pub fn history(&self) -> String {
+        serde_json::to_string(&self.history).expect("transactions are always serializable")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ledger_keeps_history() {
+        let mut ledger = Ledger::new();
+
+        //act
+        ledger.deposit("ALICE", 10).unwrap();
+        ledger.send("ALICE", "BOB", 4).unwrap();
+
+        assert_eq!(ledger.balance("BOB").unwrap(), 4);
+        assert_eq!(
+            ledger.history(),
+            r#"[{"Deposit":{"account":"ALICE","amount":10}},{"Withdraw":{"account":"ALICE","amount":4,"recipient":"BOB"}},{"Deposit":{"account":"BOB","amount":4}}]"#
+        );
+    }
+}
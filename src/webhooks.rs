@@ -0,0 +1,152 @@
+use crate::tx::Tx;
+use hmac::{Hmac, KeyInit, Mac};
+use serde_json::json;
+use sha2::Sha256;
+use std::fmt::Write;
+use std::sync::mpsc::Receiver;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// The header carrying the payload signature, `sha256=<hex encoded HMAC>`
+pub const SIGNATURE_HEADER: &str = "X-Crabbux-Signature";
+
+/// Where and how to deliver webhook notifications
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// Every URL receives every notification
+    pub urls: Vec<String>,
+    /// Shared secret used to sign the payloads
+    pub secret: String,
+    /// Number of delivery attempts per URL before giving up
+    pub max_attempts: u32,
+    /// Wait time before the first retry, doubled after every failed attempt
+    pub initial_backoff: Duration,
+}
+
+impl WebhookConfig {
+    /// Creates a config for `urls` with 5 attempts starting at a 500ms backoff
+    pub fn new(urls: Vec<String>, secret: &str) -> Self {
+        WebhookConfig {
+            urls,
+            secret: secret.to_string(),
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Delivers a notification for every [`Tx`] received on `txs` on a background thread until the sender hangs up.
+///
+/// Deliveries happen in order, so a URL that is retrying delays the notifications after it.
+pub fn spawn(config: WebhookConfig, txs: Receiver<Tx>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        for tx in txs {
+            let body = payload(&tx);
+            let signature = sign(&config.secret, &body);
+            for url in &config.urls {
+                if let Err(e) = deliver(&config, url, &body, &signature) {
+                    warn!(url, error = %e, "webhook delivery failed");
+                }
+            }
+        }
+    })
+}
+
+/// The JSON body sent for a committed [`Tx`]
+pub fn payload(tx: &Tx) -> String {
+    json!({"event": "tx.committed", "tx": tx}).to_string()
+}
+
+/// Signs `body` with `secret` using HMAC-SHA256 and returns the [`SIGNATURE_HEADER`] value
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key size");
+    mac.update(body.as_bytes());
+    let hex = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .fold(String::new(), |mut hex, b| {
+            let _ = write!(hex, "{:02x}", b);
+            hex
+        });
+    format!("sha256={}", hex)
+}
+
+fn deliver(
+    config: &WebhookConfig,
+    url: &str,
+    body: &str,
+    signature: &str,
+) -> Result<(), ureq::Error> {
+    let mut backoff = config.initial_backoff;
+    let mut attempt = 1;
+    loop {
+        let result = ureq::post(url)
+            .header("Content-Type", "application/json")
+            .header(SIGNATURE_HEADER, signature)
+            .send(body);
+        match result {
+            Ok(_) => return Ok(()),
+            Err(e) if attempt >= config.max_attempts => return Err(e),
+            Err(e) => {
+                debug!(url, attempt, error = %e, "webhook delivery attempt failed, retrying");
+                thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use tiny_http::{Response, Server};
+
+    #[test]
+    fn test_sign_works() {
+        // Reference value computed with `openssl dgst -sha256 -hmac secret`
+        assert_eq!(
+            sign("secret", "hello"),
+            "sha256=88aab3ede8d3adf94d26ab90d3bafd4a2083070c3bcce9c014ee04a443847c0b"
+        );
+    }
+
+    #[test]
+    fn test_webhooks_retry_until_delivered() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", server.server_addr().to_ip().unwrap());
+        let mut config = WebhookConfig::new(vec![url], "secret");
+        config.initial_backoff = Duration::from_millis(1);
+
+        let (sender, receiver) = mpsc::channel();
+        let worker = spawn(config, receiver);
+        let tx = Tx::Deposit {
+            account: "ALICE".into(),
+            amount: 10,
+        };
+        sender.send(tx.clone()).unwrap();
+        drop(sender);
+
+        // Fail the first attempt
+        let request = server.recv().unwrap();
+        request.respond(Response::empty(500)).unwrap();
+
+        let mut request = server.recv().unwrap();
+        let mut body = String::new();
+        request.as_reader().read_to_string(&mut body).unwrap();
+        let signature = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv(SIGNATURE_HEADER))
+            .map(|h| h.value.to_string());
+        request.respond(Response::empty(200)).unwrap();
+
+        assert_eq!(body, payload(&tx));
+        assert_eq!(signature, Some(sign("secret", &body)));
+        worker.join().unwrap();
+    }
+}